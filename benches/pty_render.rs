@@ -0,0 +1,193 @@
+//! Terminal pane throughput: PTY output in, frames out.
+//!
+//! Feeds 10MB of colored `ls`-style output through the terminal pane and
+//! renders 100 frames along the way. Before timing, the run checks the last
+//! frame against `benches/golden/pty_render.txt` (text and styles, recorded
+//! with the renderer before the allocation work) and prints how many heap
+//! allocations one pass makes.
+//!
+//! Measured on the same machine, before and after the allocation work
+//! (criterion mean, runs alternated):
+//!
+//! | renderer | allocations | time          |
+//! |----------|-------------|---------------|
+//! | before   | 320,040     | 303 - 316 ms  |
+//! | after    | 12,261      | 228 - 230 ms  |
+//!
+//! Usage: cargo bench --bench pty_render
+//! (`UPDATE_GOLDEN=1` rewrites the snapshot after an intended change)
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier};
+use ratatui::widgets::Widget;
+use rusty_term::init_app_eventsource;
+use rusty_term::ui::terminal::TuiTerminal;
+use tokio::sync::mpsc;
+
+const OUTPUT_BYTES: usize = 10 * 1024 * 1024;
+const CHUNK_BYTES: usize = 16 * 1024;
+const FRAMES: usize = 100;
+
+/// Ends the output, so the last frame also shows wide and multi-byte
+/// characters, reverse video and 256-color and truecolor runs.
+const TAIL: &str = "\x1b[7m reversed \x1b[0m 日本語 café \x1b[38;5;208m256\x1b[0m \x1b[38;2;10;20;30;48;5;17mrgb\x1b[0m \
+                    \x1b[1;3;4mbold italic underlined\x1b[0m\r\n$ ";
+
+/// System allocator that counts allocations.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Colored output split into PTY-sized chunks.
+fn colored_output() -> Vec<Vec<u8>> {
+    let mut output = Vec::with_capacity(OUTPUT_BYTES + 256);
+    let mut line = 0usize;
+    while output.len() < OUTPUT_BYTES {
+        let row = format!(
+            "\x1b[1;34mdrwxr-xr-x\x1b[0m  2 user staff {:>8} \x1b[33mJan {:>2}\x1b[0m \x1b[32mfile_{:06}.rs\x1b[0m\r\n",
+            line * 37 % 100_000,
+            line % 28 + 1,
+            line
+        );
+        output.extend_from_slice(row.as_bytes());
+        line += 1;
+    }
+    output.extend_from_slice(TAIL.as_bytes());
+    output.chunks(CHUNK_BYTES).map(<[u8]>::to_vec).collect()
+}
+
+/// Process every chunk and draw `FRAMES` frames, evenly spaced. Returns
+/// the last frame.
+fn run(chunks: Vec<Vec<u8>>) -> Buffer {
+    let (sink, mut events) = init_app_eventsource();
+    let (tx, rx) = mpsc::channel(chunks.len());
+    let mut terminal = TuiTerminal::new(rx, sink);
+    let area = Rect::new(0, 0, 120, 40);
+    terminal.resize(area.width, area.height);
+    let mut buf = Buffer::empty(area);
+
+    let per_frame = chunks.len().div_ceil(FRAMES);
+    let mut chunks = chunks.into_iter();
+    for _ in 0..FRAMES {
+        for chunk in chunks.by_ref().take(per_frame) {
+            if tx.try_send(chunk).is_err() {
+                return buf;
+            }
+        }
+        while terminal.poll_pty_output() {}
+        while events.try_recv().is_ok() {}
+        // Every frame starts from a blank buffer, as in ratatui's draw
+        buf.reset();
+        (&terminal).render(area, &mut buf);
+    }
+    buf
+}
+
+/// The frame's text, one line per row with trailing blanks trimmed, then
+/// every run of cells that is not default-styled.
+fn snapshot(buf: &Buffer) -> String {
+    let area = buf.area;
+    let mut text = format!("{}x{}\n", area.width, area.height);
+    let mut styles = String::new();
+    for y in area.top()..area.bottom() {
+        let mut line = String::new();
+        let mut run: Option<(u16, u16, (Color, Color, Modifier))> = None;
+        for x in area.left()..area.right() {
+            let Some(cell) = buf.cell((x, y)) else {
+                continue;
+            };
+            line.push_str(cell.symbol());
+            let style = (cell.fg, cell.bg, cell.modifier);
+            match &mut run {
+                Some((_, end, current)) if *current == style => *end = x,
+                _ => {
+                    if let Some(done) = run.take() {
+                        push_run(&mut styles, y, done);
+                    }
+                    run = Some((x, x, style));
+                }
+            }
+        }
+        if let Some(done) = run {
+            push_run(&mut styles, y, done);
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text.push_str("--- styles ---\n");
+    text.push_str(&styles);
+    text
+}
+
+fn push_run(out: &mut String, y: u16, (start, end, (fg, bg, modifier)): (u16, u16, (Color, Color, Modifier))) {
+    if (fg, bg, modifier) != (Color::Reset, Color::Reset, Modifier::empty()) {
+        out.push_str(&format!("{:>3} {:>3}..={:<3} fg={:?} bg={:?} {:?}\n", y, start, end, fg, bg, modifier));
+    }
+}
+
+/// Compare the last frame with the one the renderer drew before the
+/// allocation work, so a faster render path can't change what is shown.
+fn check_snapshot(buf: &Buffer) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("benches/golden/pty_render.txt");
+    let actual = snapshot(buf);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        if let Err(e) = std::fs::write(&path, &actual) {
+            panic!("writing {}: {}", path.display(), e);
+        }
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        expected == actual,
+        "pty_render: last frame differs from {}\n--- expected\n{}\n--- actual\n{}",
+        path.display(),
+        expected,
+        actual
+    );
+}
+
+fn bench_pty_render(c: &mut Criterion) {
+    let chunks = colored_output();
+
+    let input = chunks.clone();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let last_frame = run(input);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    check_snapshot(&last_frame);
+    eprintln!("pty_render: {} allocations for 10MB and {} frames", allocations, FRAMES);
+
+    let mut group = c.benchmark_group("terminal");
+    group.sample_size(10);
+    group.bench_function("process 10MB of colored output + render 100 frames", |b| {
+        b.iter_batched(|| chunks.clone(), run, BatchSize::PerIteration)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_pty_render);
+criterion_main!(benches);
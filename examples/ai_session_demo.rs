@@ -61,6 +61,9 @@ async fn main() -> Result<()> {
         ],
         recent_output: vec![],
         recent_commands: vec![], // Empty for demo, in real app this comes from ShellManager
+        project: None,
+        files: vec![],
+        screen: None,
     };
 
     println!("Context:");
@@ -92,8 +95,8 @@ async fn main() -> Result<()> {
             continue;
         }
 
-        if input.starts_with("switch ") {
-            if let Ok(id) = input[7..].parse::<u64>() {
+        if let Some(id) = input.strip_prefix("switch ") {
+            if let Ok(id) = id.parse::<u64>() {
                 if session_manager.switch_session(id) {
                     println!("✓ Switched to session: {}\n", id);
                 } else {
@@ -122,7 +125,7 @@ async fn main() -> Result<()> {
                 // This is the new pattern: AiSessionManager owns the stream and
                 // stores data internally, then returns AiUiUpdate for display
                 Some(update) = session_manager.recv_ai_stream() => {
-                    match update {
+                    match update.event {
                         AiUiUpdate::Chunk { text, .. } => {
                             print!("{}", text);
                             io::stdout().flush()?;
@@ -135,11 +138,45 @@ async fn main() -> Result<()> {
                             println!("\n✗ Error: {}\n", error);
                             stream_ended = true;
                         }
-                        AiUiUpdate::CommandSuggestion { commands, session_id: sid } => {
+                        AiUiUpdate::Truncated { partial, .. } => {
+                            println!("\n({})\n", if partial { "response interrupted" } else { "response reached the length limit" });
+                            stream_ended = true;
+                        }
+                        AiUiUpdate::ModelNotFound { message, .. } => {
+                            println!("\n✗ {}\n", message);
+                            stream_ended = true;
+                        }
+                        // Reasoning, timings, quotes and card details aren't shown here
+                        AiUiUpdate::Reasoning { .. }
+                        | AiUiUpdate::Timing { .. }
+                        | AiUiUpdate::Quotes { .. }
+                        | AiUiUpdate::Rationale { .. }
+                        | AiUiUpdate::SaferAlternative { .. }
+                        | AiUiUpdate::ContextPart { .. } => {}
+                        AiUiUpdate::Warning { message, .. } => {
+                            println!("(! {})", message);
+                        }
+                        AiUiUpdate::Status { text, .. } => {
+                            println!("({})", text);
+                        }
+                        AiUiUpdate::CommandSuggestionDraft { .. } => {}
+                        AiUiUpdate::Explanation { explanation, .. } => {
+                            println!("\n--- Explanation ---");
+                            println!("  {}", explanation.summary);
+                            println!("  Cause: {}", explanation.probable_cause);
+                            println!("  Next: {}", explanation.suggested_next_step);
+                        }
+                        AiUiUpdate::Summary { text, .. } => {
+                            println!("(earlier conversation summarized: {})", text);
+                        }
+                        AiUiUpdate::CommandSuggestion { commands, session_id: sid, .. } => {
                             println!("\n");
                             println!("--- Command Suggestions ({}) ---", commands.len());
-                            for (i, (cmd, exp)) in commands.iter().enumerate() {
+                            for (i, (_, cmd, exp, cwd)) in commands.iter().enumerate() {
                                 println!("  [{}] Command: {}", i + 1, cmd);
+                                if let Some(cwd) = cwd {
+                                    println!("      In: {}", cwd);
+                                }
                                 println!("      Explanation: {}", exp);
                             }
                             println!();
@@ -152,26 +189,28 @@ async fn main() -> Result<()> {
                                 if io::stdin().read_line(&mut response).is_ok() {
                                     let response = response.trim().to_lowercase();
 
+                                    // Decisions name a suggestion by its tool call
+                                    let first_id = &commands[0].0;
                                     if response == "n" || response == "no" {
-                                        session_manager.reject_suggestion(sid);
+                                        session_manager.reject_suggestion(sid, first_id).ok();
                                         println!("✗ Rejected all suggestions");
                                     } else if let Ok(choice) = response.parse::<usize>() {
                                         if choice >= 1 && choice <= commands.len() {
-                                            let pending_idx = choice - 1;
-                                            if let Some(cmd) = session_manager.accept_suggestion(sid, pending_idx) {
+                                            let (tool_call_id, _, _, cwd) = &commands[choice - 1];
+                                            if let Ok(cmd) = session_manager.accept_suggestion(sid, tool_call_id) {
                                                 println!("✓ Accepted command: {}", cmd);
                                                 // In real app, would execute the command here
-                                                if let Err(e) = session_manager.execute_suggestion(sid, cmd) {
+                                                if let Err(e) = session_manager.execute_suggestion(sid, tool_call_id, cmd, cwd.clone(), false) {
                                                     println!("✗ Execute error: {}", e);
                                                 }
                                             }
                                         } else {
                                             println!("✗ Invalid choice, rejecting all");
-                                            session_manager.reject_suggestion(sid);
+                                            session_manager.reject_suggestion(sid, first_id).ok();
                                         }
                                     } else {
                                         println!("✗ Invalid input, rejecting all");
-                                        session_manager.reject_suggestion(sid);
+                                        session_manager.reject_suggestion(sid, first_id).ok();
                                     }
                                 }
                                 println!();
@@ -184,14 +223,14 @@ async fn main() -> Result<()> {
 
                 // Handle app events
                 Some(app_event) = app_event_rx.recv() => {
-                    match app_event {
-                        AppEvent::ExecuteAiCommand { command, .. } => {
-                            println!("--- Executing Command ---");
-                            println!("  {}", command);
-                            println!("  (In a real app, this would execute the command)");
-                            println!();
+                    if let AppEvent::ExecuteAiCommand { command, cwd, .. } = app_event.event {
+                        println!("--- Executing Command ---");
+                        println!("  {}", command);
+                        if let Some(cwd) = cwd {
+                            println!("  in {}", cwd);
                         }
-                        _ => {}
+                        println!("  (In a real app, this would execute the command)");
+                        println!();
                     }
                 }
 
@@ -30,15 +30,15 @@ async fn chat_with_gpt_streaming(
     while let Some(result) = stream.next().await {
         match result {
             Ok(response) => {
-                response.choices.iter().for_each(|chat_choice| {
+                for chat_choice in &response.choices {
                     if let Some(ref content) = chat_choice.delta.content {
-                        write!(lock, "{}", content).unwrap();
-                        ai_response.push_str(&content);
+                        write!(lock, "{}", content)?;
+                        ai_response.push_str(content);
                     }
-                });
+                }
             }
             Err(err) => {
-                writeln!(lock, "error: {err}").unwrap();
+                writeln!(lock, "error: {err}")?;
                 break;
             }
         }
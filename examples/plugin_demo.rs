@@ -0,0 +1,111 @@
+//! Demonstration of a compiled-in plugin.
+//!
+//! This example runs RustyTerm with a plugin that uses all three hooks:
+//! - `on_event` looks up the git branch whenever the shell changes directory
+//! - `on_pre_execute` asks for confirmation before a suggested `git push`
+//!   while on `main` or `master`
+//! - `status_segment` shows the branch in the terminal pane title
+//!
+//! The lookup runs `git`, which can take a while, so it happens on a thread
+//! of the plugin's own and the answer comes back through a channel that
+//! `on_event` drains: hooks must never block the event loop.
+//!
+//! Run with: cargo run --example plugin_demo
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use anyhow::Result;
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::execute;
+use rusty_term::app::App;
+use rusty_term::event::AppEvent;
+use rusty_term::plugin::{Plugin, PluginCtx, PluginVerdict};
+use rusty_term::utils;
+use rusty_term::utils::context::Context;
+
+/// Shows the git branch of the shell's directory and guards pushes from
+/// the main branches.
+struct BranchGuard {
+    branch: Option<String>,
+    tx: Sender<Option<String>>,
+    rx: Receiver<Option<String>>,
+}
+
+impl BranchGuard {
+    fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        let guard = Self { branch: None, tx, rx };
+        if let Ok(cwd) = std::env::current_dir() {
+            guard.look_up(cwd);
+        }
+        guard
+    }
+
+    /// Ask git for the branch of `dir` on another thread.
+    fn look_up(&self, dir: PathBuf) {
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            let branch = Command::new("git")
+                .args(["rev-parse", "--abbrev-ref", "HEAD"])
+                .current_dir(dir)
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+            // The plugin is gone if the app has quit
+            tx.send(branch).ok();
+        });
+    }
+}
+
+impl Plugin for BranchGuard {
+    fn on_event(&mut self, event: &AppEvent, _ctx: &PluginCtx<'_>) {
+        // Pick up answers from earlier lookups without waiting
+        while let Ok(branch) = self.rx.try_recv() {
+            self.branch = branch;
+        }
+        if let AppEvent::ShellCwdChanged { payload } = event {
+            self.look_up(PathBuf::from(payload));
+        }
+    }
+
+    fn on_pre_execute(&mut self, cmd: &str, _ctx: &PluginCtx<'_>) -> PluginVerdict {
+        let pushes = cmd.split_whitespace().take(2).eq(["git", "push"]);
+        match self.branch.as_deref() {
+            Some(branch @ ("main" | "master")) if pushes => {
+                PluginVerdict::Confirm(format!("Pushes from {}", branch))
+            }
+            _ => PluginVerdict::Allow,
+        }
+    }
+
+    fn status_segment(&self) -> Option<String> {
+        self.branch.as_ref().map(|branch| format!("git: {}", branch))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    utils::logger::init_logging();
+
+    let mut terminal = ratatui::init();
+    execute!(std::io::stdout(), EnableMouseCapture)?;
+    let _ctx = Context::with(|| {
+        if let Err(e) = execute!(std::io::stdout(), DisableMouseCapture) {
+            tracing::error!("Failed to disable mouse capture: {}", e);
+        }
+        ratatui::restore();
+    });
+
+    let mut app = App::new()?;
+    // Plugins are registered before the first frame and the event loop
+    app.register_plugin(BranchGuard::new());
+    app.draw(&mut terminal)?;
+    let result = app.run(&mut terminal).await;
+    app.save_drafts();
+    app.stop_recording();
+    result
+}
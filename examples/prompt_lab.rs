@@ -0,0 +1,407 @@
+//! Prompt lab: score the assistant's suggestions over a set of scenarios
+//!
+//! Each scenario file (JSON) holds a fake shell context, the user's question
+//! and, optionally, a regex the suggested command must match. Every scenario
+//! goes through the real prompt builder and request builder, then either the
+//! live API or the reply scripted in the file, and the result is shown as a
+//! scoreboard: was a command suggested, did it match the expectation, what
+//! verdict the security checks give it, and roughly how many tokens the
+//! request costs.
+//!
+//! Run with: cargo run --example prompt_lab -- [OPTIONS] [SCENARIO_DIR]
+//!
+//! Options:
+//!   --live          ask the API (needs OPENAI_API_KEY) instead of using the
+//!                   scripted replies
+//!   --model NAME    model for --live (default gpt-4o-mini)
+//!   --prompt FILE   use FILE as the system prompt instead of SYSTEM_PROMPT
+//!   --watch         re-run whenever the prompt file or a scenario changes
+//!
+//! The scenarios default to examples/prompt_scenarios. Without --watch the lab
+//! is a small REPL: Enter re-runs everything, `run NAME` one scenario, `show
+//! NAME` prints the request, `quit` leaves.
+//!
+//! Scenario format:
+//!
+//! ```json
+//! {
+//!   "query": "delete the build directory",
+//!   "context": { "cwd": "/home/user/app", "recent_history": ["make"] },
+//!   "expect_command": "^rm -r",
+//!   "fake_reply": { "text": "...", "command": "rm -rf build", "risk_level": "medium" }
+//! }
+//! ```
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+    CreateChatCompletionRequest,
+};
+use async_openai::Client;
+use regex::Regex;
+use rusty_term::ai::prompt;
+use rusty_term::ai::sampling::{Profiles, Settings, Task};
+use rusty_term::ai::session::{self, SuggestCommandArgs, TOOL_SUGGEST_COMMAND};
+use rusty_term::context::ContextSnapshot;
+use rusty_term::security::{self, Verdict};
+use rusty_term::shell::ShellKind;
+use serde::Deserialize;
+
+const DEFAULT_SCENARIOS: &str = "examples/prompt_scenarios";
+
+/// How often --watch looks at the files
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// One scenario file.
+#[derive(Debug, Deserialize)]
+struct Scenario {
+    /// Shown on the scoreboard (default: the file name)
+    #[serde(default)]
+    name: String,
+    /// What the user asks
+    query: String,
+    /// The shell context sent with the question
+    #[serde(default = "ContextSnapshot::empty")]
+    context: ContextSnapshot,
+    /// Regex the suggested command must match (None = any outcome passes)
+    #[serde(default)]
+    expect_command: Option<String>,
+    /// Reply used instead of the API
+    #[serde(default)]
+    fake_reply: Option<FakeReply>,
+}
+
+/// A reply as the API would give it: some text and at most one suggestion.
+#[derive(Debug, Deserialize)]
+struct FakeReply {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    command: Option<String>,
+    #[serde(default)]
+    explanation: String,
+    #[serde(default = "default_risk")]
+    risk_level: String,
+}
+
+fn default_risk() -> String {
+    "low".to_string()
+}
+
+struct Options {
+    live: bool,
+    model: String,
+    prompt_file: Option<PathBuf>,
+    watch: bool,
+    scenarios: PathBuf,
+}
+
+impl Options {
+    fn parse() -> Result<Self> {
+        let mut options = Options {
+            live: false,
+            model: "gpt-4o-mini".to_string(),
+            prompt_file: None,
+            watch: false,
+            scenarios: PathBuf::from(DEFAULT_SCENARIOS),
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--live" => options.live = true,
+                "--watch" => options.watch = true,
+                "--model" => options.model = args.next().ok_or_else(|| anyhow!("--model needs a name"))?,
+                "--prompt" => {
+                    options.prompt_file = Some(args.next().ok_or_else(|| anyhow!("--prompt needs a file"))?.into())
+                }
+                flag if flag.starts_with("--") => bail!("unknown option {}", flag),
+                dir => options.scenarios = PathBuf::from(dir),
+            }
+        }
+        Ok(options)
+    }
+
+    fn system_prompt(&self) -> Result<String> {
+        match &self.prompt_file {
+            Some(path) => fs::read_to_string(path).with_context(|| format!("reading {}", path.display())),
+            None => Ok(prompt::SYSTEM_PROMPT.to_string()),
+        }
+    }
+}
+
+/// What came back for one scenario.
+struct Outcome {
+    name: String,
+    /// Text of the reply
+    text: String,
+    suggestion: Option<SuggestCommandArgs>,
+    /// None when the scenario has no expectation
+    matched: Option<bool>,
+    verdict: Option<Verdict>,
+    tokens: usize,
+    error: Option<String>,
+}
+
+fn load_scenarios(dir: &Path) -> Result<Vec<Scenario>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("reading {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    let mut scenarios = Vec::new();
+    for path in paths {
+        let text = fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        let mut scenario: Scenario =
+            serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+        if scenario.name.is_empty() {
+            scenario.name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        }
+        scenarios.push(scenario);
+    }
+    Ok(scenarios)
+}
+
+/// The request the assistant would send for the scenario's question.
+fn scenario_request(scenario: &Scenario, system_prompt: &str, model: &str) -> Result<CreateChatCompletionRequest> {
+    let user_prompt = prompt::build_prompt(&scenario.query, scenario.context.clone())?;
+    let history: Vec<ChatCompletionRequestMessage> = vec![
+        ChatCompletionRequestSystemMessageArgs::default().content(system_prompt).build()?.into(),
+        ChatCompletionRequestUserMessageArgs::default().content(user_prompt).build()?.into(),
+    ];
+    let sampling = Profiles::default().pick(Task::of(&scenario.query), model, Settings::default());
+    Ok(session::build_request(model, sampling, history, vec![ShellKind::default().prompt_note()], "", true)?)
+}
+
+/// Text and tool calls of the reply, the calls as (name, arguments) pairs.
+async fn reply(
+    scenario: &Scenario,
+    request: CreateChatCompletionRequest,
+    client: Option<&Client<async_openai::config::OpenAIConfig>>,
+) -> Result<(String, Vec<(String, String)>)> {
+    let Some(client) = client else {
+        let text = scenario.fake_reply.as_ref().map(|reply| reply.text.clone()).unwrap_or_default();
+        let calls = scenario
+            .fake_reply
+            .iter()
+            .filter_map(|reply| {
+                let command = reply.command.as_ref()?;
+                let args = serde_json::json!({
+                    "command": command,
+                    "explanation": reply.explanation,
+                    "risk_level": reply.risk_level,
+                });
+                Some((TOOL_SUGGEST_COMMAND.to_string(), args.to_string()))
+            })
+            .collect();
+        return Ok((text, calls));
+    };
+
+    let response = client.chat().create(request).await?;
+    let mut text = String::new();
+    let mut calls = Vec::new();
+    for choice in response.choices {
+        text.push_str(&choice.message.content.unwrap_or_default());
+        for call in choice.message.tool_calls.unwrap_or_default() {
+            calls.push((call.function.name, call.function.arguments));
+        }
+    }
+    Ok((text, calls))
+}
+
+async fn run_scenario(
+    scenario: &Scenario,
+    system_prompt: &str,
+    options: &Options,
+    client: Option<&Client<async_openai::config::OpenAIConfig>>,
+) -> Outcome {
+    let mut outcome = Outcome {
+        name: scenario.name.clone(),
+        text: String::new(),
+        suggestion: None,
+        matched: None,
+        verdict: None,
+        tokens: 0,
+        error: None,
+    };
+    let expectation = match scenario.expect_command.as_deref().map(Regex::new).transpose() {
+        Ok(expectation) => expectation,
+        Err(e) => {
+            outcome.error = Some(format!("bad expect_command: {}", e));
+            return outcome;
+        }
+    };
+    let request = match scenario_request(scenario, system_prompt, &options.model) {
+        Ok(request) => request,
+        Err(e) => {
+            outcome.error = Some(format!("building request: {}", e));
+            return outcome;
+        }
+    };
+    outcome.tokens = serde_json::to_string(&request.messages).map(|json| prompt::estimate_tokens(&json)).unwrap_or(0);
+
+    match reply(scenario, request, client).await {
+        Ok((text, calls)) => {
+            outcome.text = text;
+            outcome.suggestion = calls.iter().find_map(|(name, args)| session::parse_suggestion(name, args));
+        }
+        Err(e) => outcome.error = Some(e.to_string()),
+    }
+    if let Some(suggestion) = &outcome.suggestion {
+        outcome.verdict = Some(security::evaluate(&suggestion.command));
+    }
+    outcome.matched = expectation.map(|re| outcome.suggestion.as_ref().is_some_and(|s| re.is_match(&s.command)));
+    outcome
+}
+
+fn verdict_label(verdict: Option<&Verdict>) -> &'static str {
+    match verdict {
+        None => "-",
+        Some(Verdict::Allow) => "allow",
+        Some(Verdict::RequireConfirmation(_)) => "confirm",
+        Some(Verdict::Deny(_)) => "deny",
+    }
+}
+
+fn print_scoreboard(outcomes: &[Outcome]) {
+    let width = outcomes.iter().map(|o| o.name.len()).max().unwrap_or(0).max("scenario".len());
+    println!("{:<width$}  {:<5}  {:<7}  {:>6}  suggestion", "scenario", "match", "verdict", "tokens");
+    for outcome in outcomes {
+        let matched = match outcome.matched {
+            None => "-",
+            Some(true) => "ok",
+            Some(false) => "FAIL",
+        };
+        let detail = match (&outcome.error, &outcome.suggestion) {
+            (Some(error), _) => format!("error: {}", error),
+            (None, Some(suggestion)) => suggestion.command.clone(),
+            (None, None) => {
+                let text: String = outcome.text.split_whitespace().collect::<Vec<_>>().join(" ");
+                let text: String = text.chars().take(60).collect();
+                format!("(no suggestion) {}", text)
+            }
+        };
+        println!(
+            "{:<width$}  {:<5}  {:<7}  {:>6}  {}",
+            outcome.name,
+            matched,
+            verdict_label(outcome.verdict.as_ref()),
+            outcome.tokens,
+            detail
+        );
+    }
+
+    let suggested = outcomes.iter().filter(|o| o.suggestion.is_some()).count();
+    let expected = outcomes.iter().filter(|o| o.matched.is_some()).count();
+    let matched = outcomes.iter().filter(|o| o.matched == Some(true)).count();
+    let errors = outcomes.iter().filter(|o| o.error.is_some()).count();
+    println!(
+        "\n{} scenarios, {} with a suggestion, {}/{} matched, {} errors",
+        outcomes.len(),
+        suggested,
+        matched,
+        expected,
+        errors
+    );
+}
+
+/// Run the scenarios whose name contains `filter` (all when empty).
+async fn run_all(
+    options: &Options,
+    client: Option<&Client<async_openai::config::OpenAIConfig>>,
+    filter: &str,
+) -> Result<()> {
+    let system_prompt = options.system_prompt()?;
+    let scenarios = load_scenarios(&options.scenarios)?;
+    let mut outcomes = Vec::new();
+    for scenario in scenarios.iter().filter(|s| s.name.contains(filter)) {
+        outcomes.push(run_scenario(scenario, &system_prompt, options, client).await);
+    }
+    print_scoreboard(&outcomes);
+    Ok(())
+}
+
+/// Print the request a scenario sends.
+fn show(options: &Options, name: &str) -> Result<()> {
+    let system_prompt = options.system_prompt()?;
+    let scenarios = load_scenarios(&options.scenarios)?;
+    let scenario = scenarios
+        .iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| anyhow!("no scenario named {}", name))?;
+    let request = scenario_request(scenario, &system_prompt, &options.model)?;
+    println!("{}", serde_json::to_string_pretty(&request)?);
+    Ok(())
+}
+
+/// Latest modification time of the watched files.
+fn last_change(options: &Options) -> Option<SystemTime> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(&options.scenarios)
+        .map(|entries| entries.filter_map(|entry| entry.ok().map(|e| e.path())).collect())
+        .unwrap_or_default();
+    paths.extend(options.prompt_file.clone());
+    paths.iter().filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok()).max()
+}
+
+async fn watch(options: &Options, client: Option<&Client<async_openai::config::OpenAIConfig>>) -> Result<()> {
+    let mut seen = None;
+    loop {
+        let changed = last_change(options);
+        if changed != seen {
+            seen = changed;
+            println!("\n=== {} ===", chrono::Local::now().format("%H:%M:%S"));
+            if let Err(e) = run_all(options, client, "").await {
+                eprintln!("error: {:#}", e);
+            }
+        }
+        tokio::time::sleep(WATCH_INTERVAL).await;
+    }
+}
+
+async fn repl(options: &Options, client: Option<&Client<async_openai::config::OpenAIConfig>>) -> Result<()> {
+    let stdin = io::stdin();
+    loop {
+        print!("lab> ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let line = line.trim();
+        let result = match line.split_once(' ').unwrap_or((line, "")) {
+            ("" | "run", filter) => run_all(options, client, filter.trim()).await,
+            ("show", name) => show(options, name.trim()),
+            ("quit" | "q", _) => return Ok(()),
+            _ => {
+                println!("commands: run [NAME], show NAME, quit");
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("error: {:#}", e);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let options = Options::parse()?;
+    if options.live && std::env::var("OPENAI_API_KEY").is_err() {
+        bail!("--live needs OPENAI_API_KEY");
+    }
+    let client = options.live.then(Client::new);
+    let source = if options.live { options.model.as_str() } else { "scripted replies" };
+    println!("Prompt lab: {} ({})", options.scenarios.display(), source);
+
+    if options.watch {
+        return watch(&options, client.as_ref()).await;
+    }
+    run_all(&options, client.as_ref(), "").await?;
+    repl(&options, client.as_ref()).await
+}
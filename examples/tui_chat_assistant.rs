@@ -72,10 +72,10 @@ impl App {
 
             if event::poll(Duration::from_millis(50))? {
                 match event::read()? {
-                    Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                        if self.handle_key_event(key_event).await? {
-                            break; // Exit requested
-                        }
+                    Event::Key(key_event)
+                        if key_event.kind == KeyEventKind::Press && self.handle_key_event(key_event).await? =>
+                    {
+                        break; // Exit requested
                     }
                     Event::Resize(..) => {
                         // Terminal was resized, will be handled in next draw
@@ -103,41 +103,32 @@ impl App {
             KeyCode::Enter => {
                 self.send_message().await;
             }
-            KeyCode::Backspace => {
-                if self.input_cursor > 0 {
-                    // Find the char boundary before cursor
-                    let char_start = self.input.char_indices()
-                        .nth(self.input_cursor - 1)
-                        .map(|(i, _)| i)
-                        .unwrap_or(0);
-                    self.input.drain(char_start..);
-                    self.input_cursor -= 1;
-                }
+            KeyCode::Backspace if self.input_cursor > 0 => {
+                // Find the char boundary before cursor
+                let char_start = self.input.char_indices()
+                    .nth(self.input_cursor - 1)
+                    .map(|(i, _)| i)
+                    .unwrap_or(0);
+                self.input.drain(char_start..);
+                self.input_cursor -= 1;
             }
-            KeyCode::Delete => {
-                if self.input_cursor < self.input.chars().count() {
-                    // Find the char boundary at cursor
-                    if let Some((char_start, _)) = self.input.char_indices().nth(self.input_cursor) {
-                        // Find the end of this character
-                        let char_end = self.input[char_start..]
-                            .char_indices()
-                            .nth(1)
-                            .map(|(i, _)| char_start + i)
-                            .unwrap_or(self.input.len());
-                        self.input.drain(char_start..char_end);
-                    }
+            KeyCode::Delete if self.input_cursor < self.input.chars().count() => {
+                // Find the char boundary at cursor
+                if let Some((char_start, _)) = self.input.char_indices().nth(self.input_cursor) {
+                    // Find the end of this character
+                    let char_end = self.input[char_start..]
+                        .char_indices()
+                        .nth(1)
+                        .map(|(i, _)| char_start + i)
+                        .unwrap_or(self.input.len());
+                    self.input.drain(char_start..char_end);
                 }
             }
-            KeyCode::Left => {
-                if self.input_cursor > 0 {
-                    self.input_cursor -= 1;
-                }
+            KeyCode::Left if self.input_cursor > 0 => {
+                self.input_cursor -= 1;
             }
-            KeyCode::Right => {
-                let char_count = self.input.chars().count();
-                if self.input_cursor < char_count {
-                    self.input_cursor += 1;
-                }
+            KeyCode::Right if self.input_cursor < self.input.chars().count() => {
+                self.input_cursor += 1;
             }
             KeyCode::Home => {
                 self.input_cursor = 0;
@@ -149,11 +140,9 @@ impl App {
                 // Scroll chat history up (show earlier content)
                 self.scroll_offset = self.scroll_offset.saturating_add(1);
             }
-            KeyCode::Down => {
+            KeyCode::Down if self.scroll_offset > 0 => {
                 // Scroll chat history down (show later content, towards bottom)
-                if self.scroll_offset > 0 {
-                    self.scroll_offset -= 1;
-                }
+                self.scroll_offset -= 1;
             }
             KeyCode::PageUp => {
                 // Scroll up by 5 (show earlier content)
@@ -163,18 +152,17 @@ impl App {
                 // Scroll down by 5 (show later content, towards bottom)
                 self.scroll_offset = self.scroll_offset.saturating_sub(5);
             }
-            KeyCode::Char(c) => {
+            KeyCode::Char(c)
                 if !key_event.modifiers.contains(KeyModifiers::CONTROL)
-                    && !key_event.modifiers.contains(KeyModifiers::ALT)
-                {
-                    // Find the byte position for character insertion
-                    let insert_pos = self.input.char_indices()
-                        .nth(self.input_cursor)
-                        .map(|(i, _)| i)
-                        .unwrap_or(self.input.len());
-                    self.input.insert(insert_pos, c);
-                    self.input_cursor += 1;
-                }
+                    && !key_event.modifiers.contains(KeyModifiers::ALT) =>
+            {
+                // Find the byte position for character insertion
+                let insert_pos = self.input.char_indices()
+                    .nth(self.input_cursor)
+                    .map(|(i, _)| i)
+                    .unwrap_or(self.input.len());
+                self.input.insert(insert_pos, c);
+                self.input_cursor += 1;
             }
             _ => {}
         }
@@ -255,17 +243,17 @@ impl App {
                             }
                         });
                         if !chunk.is_empty() {
-                            let _ = tx.send(StreamUpdate::Chunk(chunk));
+                            tx.send(StreamUpdate::Chunk(chunk)).ok();
                         }
                     }
                     Err(err) => {
-                        let _ = tx.send(StreamUpdate::Error(format!("Error: {}", err)));
+                        tx.send(StreamUpdate::Error(format!("Error: {}", err))).ok();
                         break;
                     }
                 }
             }
             // Send completion marker with final response
-            let _ = tx.send(StreamUpdate::Complete(ai_response));
+            tx.send(StreamUpdate::Complete(ai_response)).ok();
         });
 
         Ok(())
@@ -279,10 +267,10 @@ impl App {
             while let Ok(update) = rx.try_recv() {
                 match update {
                     StreamUpdate::Chunk(chunk) => {
-                        if let Some(idx) = self.current_assistant_msg_index {
-                            if let Some(msg) = self.messages.get_mut(idx) {
-                                msg.content.push_str(&chunk);
-                            }
+                        if let Some(idx) = self.current_assistant_msg_index
+                            && let Some(msg) = self.messages.get_mut(idx)
+                        {
+                            msg.content.push_str(&chunk);
                         }
                     }
                     StreamUpdate::Error(err) => {
@@ -309,15 +297,13 @@ impl App {
             self.scroll_offset = 0;
             
             // Add AI response to conversation history if we have one
-            if let Some(response) = final_response {
-                if !response.trim().is_empty() {
-                    if let Ok(assistant_msg) = ChatCompletionRequestAssistantMessageArgs::default()
-                        .content(response.trim())
-                        .build()
-                    {
-                        self.conversation_history.push(assistant_msg.into());
-                    }
-                }
+            if let Some(response) = final_response
+                && !response.trim().is_empty()
+                && let Ok(assistant_msg) = ChatCompletionRequestAssistantMessageArgs::default()
+                    .content(response.trim())
+                    .build()
+            {
+                self.conversation_history.push(assistant_msg.into());
             }
         }
     }
@@ -334,7 +320,7 @@ impl App {
         } else if effective_width > 0 {
             let input_width = self.input.width();
             let max_width = effective_width as usize;
-            (input_width + max_width - 1) / max_width
+            input_width.div_ceil(max_width)
         } else {
             1
         };
@@ -552,8 +538,7 @@ impl App {
                     is_first_wrap = false;
                     
                     // Skip leading spaces on next line
-                    while current_char_pos < full_text.len() {
-                        let ch = full_text[current_char_pos..].chars().next().unwrap();
+                    while let Some(ch) = full_text[current_char_pos..].chars().next() {
                         if ch == ' ' || ch == '\t' {
                             current_char_pos += ch.len_utf8();
                         } else {
@@ -176,7 +176,7 @@ impl App {
 
             // Check if shell has exited
             if self.shell.is_shell_exited() {
-                self.debug_panel.add_log("Shell exited".to_string());
+                self.debug_panel.add_log("Shell exited");
                 self.exit = true;
                 break;
             }
@@ -227,15 +227,15 @@ impl App {
         let area = frame.area();
 
         // Render the widget
-        frame.render_widget(&*self, area);
+        frame.render_widget(self, area);
 
         // Set cursor position and make it visible
         if let Some((cursor_x, cursor_y)) = self.get_cursor_position(area) {
             frame.set_cursor_position((cursor_x, cursor_y));
             // Ensure cursor is shown
-            let _ = std::io::stdout().execute(cursor::Show);
+            std::io::stdout().execute(cursor::Show).ok();
             // Optionally set cursor style to blinking block (default for most shells)
-            let _ = std::io::stdout().execute(cursor::SetCursorStyle::BlinkingBlock);
+            std::io::stdout().execute(cursor::SetCursorStyle::BlinkingBlock).ok();
         }
     }
 
@@ -315,7 +315,7 @@ impl App {
                 if should_forward {
                     // Forward mouse event to shell (for programs like vim, tmux, etc.)
                     self.shell.send_mouse(mouse_event)?;
-                    self.debug_panel.add_log("→ Mouse forwarded to shell".to_string());
+                    self.debug_panel.add_log("→ Mouse forwarded to shell");
 
                     // Update active panel if needed
                     if self.active_panel != ActivePanel::Terminal {
@@ -350,7 +350,7 @@ impl App {
                             } else {
                                 // Scrolled to bottom, return to normal mode
                                 self.input_mode = InputMode::Normal;
-                                self.debug_panel.add_log("→ Scroll to bottom, exit browsing mode".to_string());
+                                self.debug_panel.add_log("→ Scroll to bottom, exit browsing mode");
                             }
                         }
                         MouseEventKind::Down(_button) => {
@@ -383,7 +383,7 @@ impl App {
                 // and crossterm reports it as Ctrl+5. We accept both to handle this correctly.
                 if key_event.modifiers.contains(KeyModifiers::CONTROL)
                     && matches!(key_event.code, KeyCode::Char(']') | KeyCode::Char('5')) {
-                    self.debug_panel.add_log("Action: Enter leader mode".to_string());
+                    self.debug_panel.add_log("Action: Enter leader mode");
                     self.input_mode = InputMode::LeaderCommand;
                 } else {
                     // Normal mode: only send keys to shell if Terminal is active
@@ -395,13 +395,13 @@ impl App {
                         ActivePanel::DebugPanel => {
                             // DebugPanel active: ignore input (it's read-only)
                             // User can use Ctrl+] to switch panels or enter browsing mode
-                            self.debug_panel.add_log("Key ignored (DebugPanel is read-only)".to_string());
+                            self.debug_panel.add_log("Key ignored (DebugPanel is read-only)");
                         }
                     }
                 }
             }
             InputMode::LeaderCommand => {
-                self.debug_panel.add_log("Action: Leader command".to_string());
+                self.debug_panel.add_log("Action: Leader command");
                 self.input_mode = InputMode::Normal;  // Reset to normal by default
                 self.handle_leader_command(key_event)?;
             }
@@ -433,12 +433,12 @@ impl App {
             KeyCode::Left => {
                 // Ctrl+] Left: Switch to Terminal panel
                 self.active_panel = ActivePanel::Terminal;
-                self.debug_panel.add_log("Switch to Terminal panel".to_string());
+                self.debug_panel.add_log("Switch to Terminal panel");
             }
             KeyCode::Right => {
                 // Ctrl+] Right: Switch to Debug panel
                 self.active_panel = ActivePanel::DebugPanel;
-                self.debug_panel.add_log("Switch to Debug panel".to_string());
+                self.debug_panel.add_log("Switch to Debug panel");
             }
             KeyCode::Up => {
                 // Ctrl+] Up: Scroll up in active panel and enter browsing mode
@@ -473,7 +473,7 @@ impl App {
             KeyCode::End => {
                 // Ctrl+] End: Scroll to bottom of active panel
                 self.scroll_active_panel_to_bottom();
-                self.debug_panel.add_log("Scroll to bottom".to_string());
+                self.debug_panel.add_log("Scroll to bottom");
             }
             KeyCode::Esc => {
                 // ESC: Cancel leader mode (already reset in handle_key_event)
@@ -521,24 +521,24 @@ impl App {
                 // Scroll to bottom of active panel and exit browsing mode
                 self.scroll_active_panel_to_bottom();
                 self.input_mode = InputMode::Normal;
-                self.debug_panel.add_log("Exit browsing mode, scroll to bottom".to_string());
+                self.debug_panel.add_log("Exit browsing mode, scroll to bottom");
             }
             KeyCode::Left => {
                 // Switch to Terminal panel (stay in browsing mode)
                 self.active_panel = ActivePanel::Terminal;
-                self.debug_panel.add_log("Switch to Terminal panel".to_string());
+                self.debug_panel.add_log("Switch to Terminal panel");
             }
             KeyCode::Right => {
                 // Switch to Debug panel (stay in browsing mode)
                 self.active_panel = ActivePanel::DebugPanel;
-                self.debug_panel.add_log("Switch to Debug panel".to_string());
+                self.debug_panel.add_log("Switch to Debug panel");
             }
             KeyCode::Esc => {
                 // ESC: Exit browsing mode and return to bottom
                 self.scroll_active_panel_to_bottom();
                 self.active_panel = ActivePanel::Terminal;
                 self.input_mode = InputMode::Normal;
-                self.debug_panel.add_log("Exit browsing mode (ESC)".to_string());
+                self.debug_panel.add_log("Exit browsing mode (ESC)");
             }
             _ => {
                 // Any other key: exit browsing mode, switch to terminal, return to bottom, and send key to shell
@@ -546,7 +546,7 @@ impl App {
                 self.debug_panel.scroll_to_bottom();
                 self.active_panel = ActivePanel::Terminal;
                 self.input_mode = InputMode::Normal;
-                self.debug_panel.add_log("Exit browsing mode (input)".to_string());
+                self.debug_panel.add_log("Exit browsing mode (input)");
                 // Send the key to shell
                 self.shell.send_key(key_event)?;
             }
@@ -69,7 +69,7 @@ impl ShellState {
                 match reader.read(&mut buf) {
                     Ok(0) => {
                         // EOF - PTY closed, send empty vec as EOF marker
-                        let _ = output_tx.send(vec![]);
+                        output_tx.send(vec![]).ok();
                         break;
                     }
                     Ok(n) => {
@@ -184,8 +184,8 @@ impl ShellState {
         if data_str.contains("\x1b[6n") {
             let cursor = get_cursor();
             let response = format!("\x1b[{};{}R", cursor.0 + 1, cursor.1 + 1);
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some(format!("Query: CPR -> resp[{},{}]", cursor.0 + 1, cursor.1 + 1));
             data_str = data_str.replace("\x1b[6n", "");
         }
@@ -193,14 +193,14 @@ impl ShellState {
         // ESC[c - Primary Device Attributes query (be careful with order!)
         if data_str.contains("\x1b[0c") {
             let response = "\x1b[?1;2c";
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some("Query: DA (primary)".to_string());
             data_str = data_str.replace("\x1b[0c", "");
         } else if data_str.contains("\x1b[c") && !data_str.contains("\x1b[>") {
             let response = "\x1b[?1;2c";
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some("Query: DA (primary)".to_string());
             data_str = data_str.replace("\x1b[c", "");
         }
@@ -208,14 +208,14 @@ impl ShellState {
         // ESC[>c - Secondary Device Attributes query
         if data_str.contains("\x1b[>0c") {
             let response = "\x1b[>0;276;0c";
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some("Query: DA (secondary)".to_string());
             data_str = data_str.replace("\x1b[>0c", "");
         } else if data_str.contains("\x1b[>c") {
             let response = "\x1b[>0;276;0c";
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some("Query: DA (secondary)".to_string());
             data_str = data_str.replace("\x1b[>c", "");
         }
@@ -398,7 +398,7 @@ fn key_to_bytes(key_event: KeyEvent) -> Vec<u8> {
         KeyCode::PageDown => vec![0x1b, b'[', b'6', b'~'],
         KeyCode::Delete => vec![0x1b, b'[', b'3', b'~'],
         KeyCode::Insert => vec![0x1b, b'[', b'2', b'~'],
-        KeyCode::F(n) if n >= 1 && n <= 12 => {
+        KeyCode::F(n) if (1..=12).contains(&n) => {
             match n {
                 1 => vec![0x1b, b'O', b'P'],
                 2 => vec![0x1b, b'O', b'Q'],
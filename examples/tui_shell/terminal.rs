@@ -161,7 +161,7 @@ impl TerminalDisplay {
     /// Unlike vt100, this preserves scrollback history via intelligent reflow!
     pub fn resize(&mut self, cols: u16, rows: u16) {
         let size = TermSize::new(cols, rows);
-        self.term.resize(&size);
+        self.term.resize(size);
     }
 
     /// Get rendered lines for display.
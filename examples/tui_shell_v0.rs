@@ -144,9 +144,9 @@ impl App {
         if let Some((cursor_x, cursor_y)) = self.get_cursor_position(area) {
             frame.set_cursor_position((cursor_x, cursor_y));
             // Ensure cursor is shown
-            let _ = std::io::stdout().execute(cursor::Show);
+            std::io::stdout().execute(cursor::Show).ok();
             // Optionally set cursor style to blinking block (default for most shells)
-            let _ = std::io::stdout().execute(cursor::SetCursorStyle::BlinkingBlock);
+            std::io::stdout().execute(cursor::SetCursorStyle::BlinkingBlock).ok();
         }
     }
 
@@ -168,8 +168,8 @@ impl App {
         let shell_width = inner_area.width.saturating_sub(SIDEBAR_WIDTH);
 
         // Convert parser coordinates to screen coordinates
-        let cursor_x = inner_area.x + cursor.1 as u16;
-        let cursor_y = inner_area.y + cursor.0 as u16;
+        let cursor_x = inner_area.x + cursor.1;
+        let cursor_y = inner_area.y + cursor.0;
 
         // Make sure cursor is within the shell area
         if cursor_x < inner_area.x + shell_width && cursor_y < inner_area.y + inner_area.height {
@@ -433,7 +433,7 @@ impl ShellState {
                 match reader.read(&mut buf) {
                     Ok(0) => {
                         // EOF - PTY closed, send empty vec as EOF marker
-                        let _ = output_tx.send(vec![]);
+                        output_tx.send(vec![]).ok();
                         break;
                     }
                     Ok(n) => {
@@ -521,8 +521,8 @@ impl ShellState {
             let screen = self.parser.screen();
             let cursor = screen.cursor_position();
             let response = format!("\x1b[{};{}R", cursor.0 + 1, cursor.1 + 1);
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some(format!("Query: CPR -> resp[{},{}]", cursor.0 + 1, cursor.1 + 1));
             data_str = data_str.replace("\x1b[6n", "");
         }
@@ -530,14 +530,14 @@ impl ShellState {
         // ESC[c - Primary Device Attributes query (be careful with order!)
         if data_str.contains("\x1b[0c") {
             let response = "\x1b[?1;2c";
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some("Query: DA (primary)".to_string());
             data_str = data_str.replace("\x1b[0c", "");
         } else if data_str.contains("\x1b[c") && !data_str.contains("\x1b[>") {
             let response = "\x1b[?1;2c";
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some("Query: DA (primary)".to_string());
             data_str = data_str.replace("\x1b[c", "");
         }
@@ -545,14 +545,14 @@ impl ShellState {
         // ESC[>c - Secondary Device Attributes query
         if data_str.contains("\x1b[>0c") {
             let response = "\x1b[>0;276;0c";
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some("Query: DA (secondary)".to_string());
             data_str = data_str.replace("\x1b[>0c", "");
         } else if data_str.contains("\x1b[>c") {
             let response = "\x1b[>0;276;0c";
-            let _ = self.writer.write_all(response.as_bytes());
-            let _ = self.writer.flush();
+            self.writer.write_all(response.as_bytes()).ok();
+            self.writer.flush().ok();
             log_msg = Some("Query: DA (secondary)".to_string());
             data_str = data_str.replace("\x1b[>c", "");
         }
@@ -608,7 +608,9 @@ impl ShellState {
             let mut current_style = Style::default();
 
             for col in 0..cols {
-                let cell = screen.cell(row, col).unwrap();
+                let Some(cell) = screen.cell(row, col) else {
+                    continue;
+                };
                 let contents = cell.contents();
 
                 // Determine what to display:
@@ -619,23 +621,23 @@ impl ShellState {
 
                 let display_contents = if contents.is_empty() {
                     // Check if previous cell has wide character
-                    if col > 0 {
-                        if let Some(prev_cell) = screen.cell(row, col - 1) {
-                            let prev_contents = prev_cell.contents();
-                            if !prev_contents.is_empty() {
-                                // Check if previous character is wide (CJK, emoji, etc.)
-                                let is_wide = prev_contents.chars().next()
-                                    .map(|c| {
-                                        // Use unicode-width crate to determine width
-                                        use unicode_width::UnicodeWidthChar;
-                                        c.width().unwrap_or(1) > 1
-                                    })
-                                    .unwrap_or(false);
-
-                                if is_wide {
-                                    // This is a wide character placeholder, skip it
-                                    continue;
-                                }
+                    if col > 0
+                        && let Some(prev_cell) = screen.cell(row, col - 1)
+                    {
+                        let prev_contents = prev_cell.contents();
+                        if !prev_contents.is_empty() {
+                            // Check if previous character is wide (CJK, emoji, etc.)
+                            let is_wide = prev_contents.chars().next()
+                                .map(|c| {
+                                    // Use unicode-width crate to determine width
+                                    use unicode_width::UnicodeWidthChar;
+                                    c.width().unwrap_or(1) > 1
+                                })
+                                .unwrap_or(false);
+
+                            if is_wide {
+                                // This is a wide character placeholder, skip it
+                                continue;
                             }
                         }
                     }
@@ -780,12 +782,12 @@ fn key_to_bytes(key_event: KeyEvent) -> Vec<u8> {
     let alt = modifiers.contains(KeyModifiers::ALT);
 
     // eprintln!("DEBUG key_to_bytes: code={:?}, ctrl={}, alt={}", code, ctrl, alt);
-    let _ = stderr().flush();
+    stderr().flush().ok();
 
     match code {
         KeyCode::Char(c) => {
             // eprintln!("DEBUG: Char '{}' (0x{:02x})", c, c as u8);
-            let _ = stderr().flush();
+            stderr().flush().ok();
             if ctrl {
                 // Handle Ctrl+letter: Ctrl+A = 0x01, Ctrl+B = 0x02, etc.
                 if c.is_ascii_lowercase() || c.is_ascii_uppercase() {
@@ -838,7 +840,7 @@ fn key_to_bytes(key_event: KeyEvent) -> Vec<u8> {
         KeyCode::PageDown => vec![0x1b, b'[', b'6', b'~'],
         KeyCode::Delete => vec![0x1b, b'[', b'3', b'~'],
         KeyCode::Insert => vec![0x1b, b'[', b'2', b'~'],
-        KeyCode::F(n) if n >= 1 && n <= 12 => {
+        KeyCode::F(n) if (1..=12).contains(&n) => {
             match n {
                 1 => vec![0x1b, b'O', b'P'],
                 2 => vec![0x1b, b'O', b'Q'],
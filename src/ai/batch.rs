@@ -0,0 +1,178 @@
+//! Several accepted suggestions run one after another.
+//!
+//! When the AI suggests complementary commands ("prune containers, prune
+//! images, prune volumes") the user can pick more than one of them and put
+//! them in order. The app runs them through the security gate one at a
+//! time, each once the one before it is done, and stops at the first that
+//! fails or is denied. The session is told how far the batch got, so the
+//! tool responses say which commands ran.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::session::SessionId;
+
+/// One accepted suggestion of a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchStep {
+    pub tool_call_id: String,
+    pub command: String,
+    /// Directory to run it in (None = the shell's own)
+    pub cwd: Option<String>,
+}
+
+/// Why a batch stopped before its last step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StepFailure {
+    /// The command ran and exited non-zero
+    Exit(i32),
+    /// The security gate would not run it
+    Denied(String),
+    /// Whether it finished couldn't be told, so the next one can't follow
+    Lost,
+}
+
+impl StepFailure {
+    /// What happened to the step, for the tool response and the notice.
+    pub fn describe(&self) -> String {
+        match self {
+            StepFailure::Exit(code) => format!("it failed (exit {})", code),
+            StepFailure::Denied(reason) => format!("the safety check denied running it ({})", reason),
+            StepFailure::Lost => "it could not be told when it finished".to_string(),
+        }
+    }
+}
+
+/// What to do next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchProgress {
+    /// Run this step
+    Run(BatchStep),
+    /// Every step ran
+    Done,
+    /// `failed` stopped the batch; the `skipped` tool calls never ran
+    Stopped { failed: BatchStep, failure: StepFailure, skipped: Vec<String> },
+}
+
+/// A batch on its way: the steps still to run and the one running.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    session_id: SessionId,
+    steps: VecDeque<BatchStep>,
+    /// The running step and the text typed for it
+    running: Option<(BatchStep, String)>,
+}
+
+impl Batch {
+    pub fn new(session_id: SessionId, steps: Vec<BatchStep>) -> Self {
+        Self { session_id, steps: steps.into(), running: None }
+    }
+
+    pub fn session_id(&self) -> SessionId {
+        self.session_id
+    }
+
+    /// The next step to run (none once the batch is over).
+    pub fn next_step(&mut self) -> BatchProgress {
+        match self.steps.pop_front() {
+            Some(step) => BatchProgress::Run(step),
+            None => BatchProgress::Done,
+        }
+    }
+
+    /// `step` was typed into the shell as `typed`.
+    pub fn started(&mut self, step: BatchStep, typed: String) {
+        self.running = Some((step, typed));
+    }
+
+    /// Text typed for the running step.
+    pub fn running(&self) -> Option<&str> {
+        self.running.as_ref().map(|(_, typed)| typed.as_str())
+    }
+
+    /// The running step is done, with `exit_code` if the shell reported
+    /// one: on to the next step, or stop if it failed.
+    pub fn finished(&mut self, exit_code: Option<i32>) -> BatchProgress {
+        let Some((step, _)) = self.running.take() else {
+            return self.next_step();
+        };
+        match exit_code {
+            Some(code) if code != 0 => self.stop(step, StepFailure::Exit(code)),
+            _ => self.next_step(),
+        }
+    }
+
+    /// `step`, about to run, failed before it started.
+    pub fn failed(&mut self, step: BatchStep, failure: StepFailure) -> BatchProgress {
+        self.running = None;
+        self.stop(step, failure)
+    }
+
+    /// The running step can't be followed any further.
+    pub fn lost(&mut self) -> BatchProgress {
+        match self.running.take() {
+            Some((step, _)) => self.stop(step, StepFailure::Lost),
+            None => self.next_step(),
+        }
+    }
+
+    fn stop(&mut self, failed: BatchStep, failure: StepFailure) -> BatchProgress {
+        let skipped = self.steps.drain(..).map(|step| step.tool_call_id).collect();
+        BatchProgress::Stopped { failed, failure, skipped }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(id: &str, command: &str) -> BatchStep {
+        BatchStep { tool_call_id: id.to_string(), command: command.to_string(), cwd: None }
+    }
+
+    /// Run the batch to its end, each step exiting with the code given.
+    fn run(batch: &mut Batch, exit_codes: &[Option<i32>]) -> (Vec<String>, BatchProgress) {
+        let mut ran = Vec::new();
+        let mut progress = batch.next_step();
+        let mut codes = exit_codes.iter();
+        while let BatchProgress::Run(step) = progress {
+            ran.push(step.command.clone());
+            let typed = step.command.clone();
+            batch.started(step, typed);
+            assert_eq!(batch.running(), ran.last().map(String::as_str));
+            progress = batch.finished(codes.next().copied().flatten());
+        }
+        (ran, progress)
+    }
+
+    #[test]
+    fn test_batch_runs_every_step_in_order() {
+        let mut batch = Batch::new(1, vec![step("c", "docker volume prune"), step("a", "docker container prune")]);
+        let (ran, progress) = run(&mut batch, &[Some(0), None]);
+        assert_eq!(ran, ["docker volume prune", "docker container prune"]);
+        assert_eq!(progress, BatchProgress::Done);
+        assert_eq!(batch.running(), None);
+    }
+
+    #[test]
+    fn test_batch_stops_at_the_first_failure() {
+        let mut batch = Batch::new(1, vec![step("a", "make"), step("b", "make test"), step("c", "make install")]);
+        let (ran, progress) = run(&mut batch, &[Some(0), Some(2)]);
+        assert_eq!(ran, ["make", "make test"]);
+        assert_eq!(
+            progress,
+            BatchProgress::Stopped { failed: step("b", "make test"), failure: StepFailure::Exit(2), skipped: vec!["c".to_string()] }
+        );
+        assert_eq!(batch.next_step(), BatchProgress::Done);
+
+        // A step the gate denies stops it before it runs
+        let mut batch = Batch::new(1, vec![step("a", "ls"), step("b", "rm -rf /")]);
+        assert_eq!(batch.next_step(), BatchProgress::Run(step("a", "ls")));
+        assert_eq!(batch.failed(step("a", "ls"), StepFailure::Denied("no".to_string())), BatchProgress::Stopped {
+            failed: step("a", "ls"),
+            failure: StepFailure::Denied("no".to_string()),
+            skipped: vec!["b".to_string()],
+        });
+    }
+}
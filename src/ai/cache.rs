@@ -0,0 +1,358 @@
+//! Opt-in on-disk cache of answers to context-free questions.
+//!
+//! Quick questions ("tar extract flags?") get asked over and over, and each
+//! one is a paid request. A question sent without shell context as the first
+//! turn of a session depends only on the model, the system prompt and its own
+//! text, so its reply (text plus command suggestions) can be stored under
+//! that key and replayed instead of calling the API again.
+//!
+//! The cache file is shared by all instances: every change takes the file
+//! lock, merges with what is on disk and replaces the file atomically.
+//! Entries expire a fixed time after they were stored, and the least
+//! recently used ones are evicted beyond [`MAX_ENTRIES`].
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::utils::persist::{self, FileLock, LOCK_TIMEOUT};
+
+/// Maximum number of cached replies kept.
+pub const MAX_ENTRIES: usize = 200;
+
+/// Replies larger than this are not cached.
+const MAX_REPLY_BYTES: usize = 32 * 1024;
+
+const FILE_NAME: &str = "response_cache.json";
+
+/// Normalize a question for cache lookup.
+///
+/// Case and runs of whitespace are ignored, as is trailing punctuation, so
+/// "Tar extract flags?" and "tar  extract flags" share an entry.
+pub fn normalize_question(text: &str) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    collapsed.trim_end_matches(['?', '.', '!', ' ']).to_string()
+}
+
+/// FNV-1a, used instead of std's hasher because the value is stored on disk
+/// and must not change between builds.
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// What a cached reply is looked up by.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKey {
+    pub model: String,
+    pub system_prompt_hash: u64,
+    /// The question, normalized with [`normalize_question`]
+    pub question: String,
+}
+
+impl CacheKey {
+    pub fn new(model: &str, system_prompt: &str, question: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            system_prompt_hash: fnv1a(system_prompt),
+            question: normalize_question(question),
+        }
+    }
+}
+
+/// A complete assistant reply.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedReply {
+    pub text: String,
+    /// Tool calls as (tool_call_id, function_name, arguments_json)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<(String, String, String)>,
+}
+
+impl CachedReply {
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.tool_calls.is_empty()
+    }
+
+    fn size(&self) -> usize {
+        self.text.len()
+            + self
+                .tool_calls
+                .iter()
+                .map(|(id, name, args)| id.len() + name.len() + args.len())
+                .sum::<usize>()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    key: CacheKey,
+    reply: CachedReply,
+    /// Unix time the reply was stored (expiry is measured from here)
+    stored_at: u64,
+    /// Unix time of the last hit (eviction order)
+    last_used: u64,
+}
+
+/// Replies to context-free questions, least recently used first.
+#[derive(Debug)]
+pub struct ResponseCache {
+    /// Backing file (None keeps the cache in memory only)
+    path: Option<PathBuf>,
+    entries: Vec<CacheEntry>,
+    max_age: Duration,
+    max_entries: usize,
+}
+
+impl ResponseCache {
+    /// Cache that is never written to disk.
+    pub fn in_memory(max_age: Duration) -> Self {
+        Self {
+            path: None,
+            entries: Vec::new(),
+            max_age,
+            max_entries: MAX_ENTRIES,
+        }
+    }
+
+    /// Cache backed by `path`, loading whatever is stored there.
+    pub fn at(path: impl Into<PathBuf>, max_age: Duration) -> Self {
+        let path = path.into();
+        let entries = match read_entries(&path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Ignoring unreadable response cache {}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+        Self {
+            path: Some(path),
+            entries,
+            max_age,
+            max_entries: MAX_ENTRIES,
+        }
+    }
+
+    /// Cache in the data directory (in memory only if there is none).
+    pub fn open(max_age: Duration) -> Self {
+        match persist::data_dir() {
+            Some(dir) => Self::at(dir.join(FILE_NAME), max_age),
+            None => Self::in_memory(max_age),
+        }
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Look up a reply that has not expired, marking it as recently used.
+    pub fn lookup(&mut self, key: &CacheKey, now: u64) -> Option<CachedReply> {
+        let max_age = self.max_age.as_secs();
+        let reply = self
+            .entries
+            .iter()
+            .find(|e| e.key == *key && now.saturating_sub(e.stored_at) <= max_age)?
+            .reply
+            .clone();
+
+        let key = key.clone();
+        self.update(now, move |entries| {
+            if let Some(pos) = entries.iter().position(|e| e.key == key) {
+                let mut entry = entries.remove(pos);
+                entry.last_used = now;
+                entries.push(entry);
+            }
+        });
+        Some(reply)
+    }
+
+    /// Store a reply, replacing any earlier one for the same key.
+    pub fn insert(&mut self, key: CacheKey, reply: CachedReply, now: u64) {
+        if reply.is_empty() || reply.size() > MAX_REPLY_BYTES {
+            return;
+        }
+        self.update(now, move |entries| {
+            entries.retain(|e| e.key != key);
+            entries.push(CacheEntry { key, reply, stored_at: now, last_used: now });
+        });
+    }
+
+    /// Remove every entry. Returns how many were removed.
+    pub fn clear(&mut self) -> usize {
+        let mut removed = 0;
+        self.update(unix_now(), |entries| {
+            removed = entries.len();
+            entries.clear();
+        });
+        removed
+    }
+
+    /// Apply a change, merged with the file on disk when there is one.
+    fn update<F>(&mut self, now: u64, change: F)
+    where
+        F: FnOnce(&mut Vec<CacheEntry>),
+    {
+        let Some(path) = self.path.clone() else {
+            change(&mut self.entries);
+            self.prune(now);
+            return;
+        };
+
+        // Another instance may have written since we last looked
+        let lock = FileLock::acquire(&path, LOCK_TIMEOUT);
+        if lock.is_ok()
+            && let Ok(entries) = read_entries(&path)
+        {
+            self.entries = entries;
+        }
+        change(&mut self.entries);
+        self.prune(now);
+
+        let Ok(_lock) = lock.inspect_err(|e| warn!("Response cache not saved: {}", e)) else {
+            return;
+        };
+        let result = serde_json::to_vec(&self.entries)
+            .map_err(io::Error::from)
+            .and_then(|bytes| persist::write_atomic(&path, &bytes));
+        if let Err(e) = result {
+            warn!("Failed to save response cache {}: {}", path.display(), e);
+        }
+    }
+
+    /// Drop expired entries, then the least recently used beyond the cap.
+    fn prune(&mut self, now: u64) {
+        let max_age = self.max_age.as_secs();
+        self.entries.retain(|e| now.saturating_sub(e.stored_at) <= max_age);
+        self.entries.sort_by_key(|e| e.last_used);
+        let excess = self.entries.len().saturating_sub(self.max_entries);
+        self.entries.drain(..excess);
+    }
+}
+
+fn read_entries(path: &Path) -> io::Result<Vec<CacheEntry>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86_400;
+
+    fn reply(text: &str) -> CachedReply {
+        CachedReply { text: text.to_string(), tool_calls: Vec::new() }
+    }
+
+    fn key(question: &str) -> CacheKey {
+        CacheKey::new("gpt-4o-mini", "You are helpful.", question)
+    }
+
+    #[test]
+    fn test_normalize_question() {
+        assert_eq!(normalize_question("  Tar   extract flags? "), "tar extract flags");
+        assert_eq!(normalize_question("undo last commit, keep changes."), "undo last commit, keep changes");
+        assert_eq!(normalize_question("why?!"), "why");
+    }
+
+    #[test]
+    fn test_key_covers_model_prompt_and_question() {
+        assert_eq!(key("Tar extract flags?"), key("tar extract flags"));
+        assert_ne!(key("tar extract flags"), key("tar create flags"));
+        assert_ne!(
+            CacheKey::new("gpt-4o", "You are helpful.", "q"),
+            CacheKey::new("gpt-4o-mini", "You are helpful.", "q")
+        );
+        assert_ne!(
+            CacheKey::new("gpt-4o", "You are helpful.", "q"),
+            CacheKey::new("gpt-4o", "You review diffs.", "q")
+        );
+        // The hash is stored on disk, so it must never change
+        assert_eq!(fnv1a(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a("a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_lookup_respects_max_age() {
+        let mut cache = ResponseCache::in_memory(Duration::from_secs(7 * DAY));
+        cache.insert(key("q"), reply("a"), 1_000);
+        assert_eq!(cache.lookup(&key("Q?"), 1_000 + DAY), Some(reply("a")));
+        // Hits do not extend the lifetime
+        assert_eq!(cache.lookup(&key("q"), 1_000 + 8 * DAY), None);
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let mut cache = ResponseCache::in_memory(Duration::from_secs(DAY)).with_max_entries(2);
+        cache.insert(key("one"), reply("1"), 10);
+        cache.insert(key("two"), reply("2"), 20);
+        // Using "one" makes "two" the eviction candidate
+        assert!(cache.lookup(&key("one"), 30).is_some());
+        cache.insert(key("three"), reply("3"), 40);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.lookup(&key("two"), 50).is_none());
+        assert!(cache.lookup(&key("one"), 50).is_some());
+        assert!(cache.lookup(&key("three"), 50).is_some());
+    }
+
+    #[test]
+    fn test_insert_skips_empty_and_oversized_replies() {
+        let mut cache = ResponseCache::in_memory(Duration::from_secs(DAY));
+        cache.insert(key("empty"), CachedReply::default(), 1);
+        cache.insert(key("huge"), reply(&"x".repeat(MAX_REPLY_BYTES + 1)), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_cache_file_is_shared() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-cache-test-{}", std::process::id()));
+        let path = dir.join(FILE_NAME);
+        let max_age = Duration::from_secs(DAY);
+        let now = unix_now();
+
+        let mut first = ResponseCache::at(&path, max_age);
+        let mut second = ResponseCache::at(&path, max_age);
+        let suggestion = (
+            "call_1".to_string(),
+            "suggest_command".to_string(),
+            r#"{"command":"git reset --soft HEAD~1"}"#.to_string(),
+        );
+        let stored = CachedReply { text: String::new(), tool_calls: vec![suggestion] };
+        first.insert(key("undo"), stored.clone(), now);
+        // The second instance merges instead of overwriting
+        second.insert(key("tar"), reply("tar -xf"), now);
+
+        let mut reopened = ResponseCache::at(&path, max_age);
+        assert_eq!(reopened.len(), 2);
+        assert_eq!(reopened.lookup(&key("undo"), now), Some(stored));
+        assert_eq!(reopened.clear(), 2);
+        assert!(ResponseCache::at(&path, max_age).is_empty());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
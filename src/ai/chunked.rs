@@ -0,0 +1,345 @@
+//! Sending the whole output of a command, in parts.
+//!
+//! A message carries the tail of each command's output, `OUTPUT_BUDGET`
+//! bytes at most. When that cuts off what matters (a long stack trace),
+//! the user can attach one command from the picker (`/attach`) and its
+//! whole output goes instead, split into parts sent one request at a time.
+//! Every part but the last asks the model to reply only READY; the last
+//! carries the question. The session manager drives the rounds.
+//!
+//! At most `MAX_PARTS` parts are sent: the start of longer output is left
+//! out, and the model is told so.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ContextSection;
+use crate::context::{CommandRecord, ContextSnapshot};
+
+use super::prompt;
+
+/// Bytes of output in one part (parts end at a line end when they can)
+pub const PART_BYTES: usize = 12 * 1024;
+
+/// Most parts sent for one output
+pub const MAX_PARTS: usize = 8;
+
+/// What the model replies to a part that is not the last
+pub const READY: &str = "READY";
+
+/// Tokens each request spends on its envelope, in a rough estimate
+const ENVELOPE_TOKENS: usize = 60;
+
+/// Which part of a command's output a message carries, sent along with it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputPart {
+    /// 1-based
+    pub part: usize,
+    pub of: usize,
+    pub command: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub note: Option<String>,
+}
+
+impl OutputPart {
+    /// More parts follow: the model only acknowledges this one.
+    pub fn is_intermediate(&self) -> bool {
+        self.part < self.of
+    }
+}
+
+/// The output of a command, split into the parts to send.
+#[derive(Debug, Clone)]
+pub struct OutputParts {
+    command: String,
+    parts: Vec<String>,
+    /// Bytes at the start of the output left out (over `MAX_PARTS`)
+    left_out: usize,
+}
+
+impl OutputParts {
+    /// Split `output` into parts of at most `PART_BYTES`, keeping the last
+    /// `MAX_PARTS` of them.
+    pub fn split(command: &str, output: &str) -> Self {
+        // Nothing before this much of the tail can be kept
+        let mut start = output.len().saturating_sub(PART_BYTES * MAX_PARTS);
+        while !output.is_char_boundary(start) {
+            start += 1;
+        }
+        // Start on a line of its own if the cut fell inside one
+        if start > 0
+            && !output[..start].ends_with('\n')
+            && let Some(newline) = output[start..].find('\n')
+        {
+            start += newline + 1;
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = &output[start..];
+        while !rest.is_empty() {
+            let end = part_end(rest);
+            parts.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+        // Parts cut at line ends are shorter, so there can be a few too many
+        let extra = parts.len().saturating_sub(MAX_PARTS);
+        let left_out = start + parts.drain(..extra).map(|part| part.len()).sum::<usize>();
+        Self { command: command.to_string(), parts, left_out }
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn count(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Bytes of output sent
+    pub fn total_bytes(&self) -> usize {
+        self.parts.iter().map(String::len).sum()
+    }
+
+    pub fn left_out(&self) -> usize {
+        self.left_out
+    }
+
+    /// Rough number of input tokens the parts cost: every request sends
+    /// the parts before it again, as part of the conversation.
+    pub fn estimate_tokens(&self) -> usize {
+        let count = self.parts.len();
+        self.parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| (prompt::estimate_tokens(part) + ENVELOPE_TOKENS) * (count - i))
+            .sum()
+    }
+
+    /// One line on what sending the parts takes, shown before starting.
+    pub fn describe(&self) -> String {
+        let mut text = format!(
+            "{} KB in {} part{}, about {} input tokens",
+            self.total_bytes().div_ceil(1024),
+            self.count(),
+            if self.count() == 1 { "" } else { "s" },
+            self.estimate_tokens()
+        );
+        if self.left_out > 0 {
+            text.push_str(&format!(" (first {} KB left out)", self.left_out.div_ceil(1024)));
+        }
+        text
+    }
+}
+
+/// Where the first part of `text` ends: after the last line end within
+/// `PART_BYTES`, or at a character boundary if the line is longer.
+fn part_end(text: &str) -> usize {
+    if text.len() <= PART_BYTES {
+        return text.len();
+    }
+    let mut end = PART_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    match text[..end].rfind('\n') {
+        Some(newline) => newline + 1,
+        None => end,
+    }
+}
+
+/// The next request of a transfer.
+#[derive(Debug)]
+pub struct PartRequest {
+    /// The envelope for a part before the last, otherwise the question
+    pub user_input: String,
+    pub context: ContextSnapshot,
+    pub part: OutputPart,
+}
+
+/// A full output on its way, one part a request.
+#[derive(Debug)]
+pub struct Transfer {
+    parts: OutputParts,
+    /// Started at, to find the command among the records of the context
+    record: CommandRecord,
+    question: String,
+    /// Context of the question, sent with the last part
+    context: ContextSnapshot,
+    /// Shell2 sections left out of the question
+    excluded: Vec<ContextSection>,
+    /// Parts sent so far
+    sent: usize,
+}
+
+impl Transfer {
+    /// Send `output`, the whole output of `record`, before `question`
+    /// asked with `context` (leaving out the Shell2 sections `excluded`).
+    pub fn new(
+        record: CommandRecord,
+        output: &str,
+        question: String,
+        context: ContextSnapshot,
+        excluded: Vec<ContextSection>,
+    ) -> Self {
+        let parts = OutputParts::split(&record.command_line, output);
+        Self { parts, record, question, context, excluded, sent: 0 }
+    }
+
+    pub fn question(&self) -> &str {
+        &self.question
+    }
+
+    pub fn excluded(&self) -> &[ContextSection] {
+        &self.excluded
+    }
+
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    pub fn count(&self) -> usize {
+        self.parts.count()
+    }
+
+    /// The last part is sent (with the question).
+    pub fn is_done(&self) -> bool {
+        self.sent >= self.parts.count()
+    }
+
+    /// The request for the next part, or None once they are all sent.
+    ///
+    /// A part before the last goes alone, as the output of the command in
+    /// an otherwise empty context. The last one replaces the truncated
+    /// output of the command in the question's context.
+    pub fn next_request(&mut self) -> Option<PartRequest> {
+        let text = self.parts.parts.get(self.sent)?.clone();
+        self.sent += 1;
+        let (part, of) = (self.sent, self.parts.count());
+
+        let mut notes = Vec::new();
+        if part == 1 && self.parts.left_out > 0 {
+            notes.push(format!(
+                "The first {} bytes of the output were left out; this part starts after them.",
+                self.parts.left_out
+            ));
+        }
+        if part == of && of > 1 {
+            notes.push(format!("Parts 1 to {} are in the previous messages.", of - 1));
+        }
+        let part = OutputPart {
+            part,
+            of,
+            command: self.record.command_line.clone(),
+            note: (!notes.is_empty()).then(|| notes.join(" ")),
+        };
+
+        let record = CommandRecord { output: text, ..self.record.clone() };
+        let (user_input, context) = if part.is_intermediate() {
+            let user_input = format!(
+                "Part {}/{} of the full output of `{}` is in the context. More parts follow: do not answer yet, reply only {}.",
+                part.part, part.of, part.command, READY
+            );
+            (user_input, ContextSnapshot { recent_commands: vec![record], ..ContextSnapshot::empty() })
+        } else {
+            let mut context = self.context.clone();
+            replace_record(&mut context, &self.record, record);
+            (self.question.clone(), context)
+        };
+        Some(PartRequest { user_input, context, part })
+    }
+
+    /// The question's context with the whole output sent in place of the
+    /// truncated one (to tell which suggestions were copied from it).
+    pub fn full_context(&self) -> ContextSnapshot {
+        let mut context = self.context.clone();
+        let output = self.parts.parts.concat();
+        replace_record(&mut context, &self.record, CommandRecord { output, ..self.record.clone() });
+        context
+    }
+}
+
+/// Put `record` in place of `original` among the commands of `context`
+/// (the latest run with the same command line and start), or add it.
+fn replace_record(context: &mut ContextSnapshot, original: &CommandRecord, record: CommandRecord) {
+    let same = |other: &CommandRecord| {
+        other.command_line == original.command_line && other.started_at == original.started_at
+    };
+    match context.recent_commands.iter().rposition(same) {
+        Some(i) => context.recent_commands[i] = record,
+        None => context.recent_commands.push(record),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(count: usize, width: usize) -> String {
+        (0..count).map(|i| format!("{:0width$}\n", i, width = width - 1)).collect()
+    }
+
+    #[test]
+    fn test_parts_end_at_line_ends_and_keep_everything() {
+        let output = lines(1000, 40);
+        let parts = OutputParts::split("cargo test", &output);
+        assert_eq!(parts.count(), output.len().div_ceil(PART_BYTES - PART_BYTES % 40));
+        assert!(parts.parts.iter().all(|part| part.len() <= PART_BYTES && part.ends_with('\n')));
+        assert_eq!(parts.parts.concat(), output);
+        assert_eq!(parts.left_out(), 0);
+
+        // A line longer than a part is cut anyway, on a character boundary
+        let output = "é".repeat(PART_BYTES);
+        let parts = OutputParts::split("cat", &output);
+        assert_eq!(parts.parts.concat(), output);
+        assert!(parts.parts.iter().all(|part| part.len() <= PART_BYTES));
+    }
+
+    #[test]
+    fn test_parts_are_capped_keeping_the_tail() {
+        let output = lines(10_000, 50);
+        let parts = OutputParts::split("make", &output);
+        assert_eq!(parts.count(), MAX_PARTS);
+        assert!(output.ends_with(&parts.parts.concat()));
+        assert!(parts.parts[0].starts_with(|c: char| c.is_ascii_digit()));
+        assert_eq!(parts.left_out() + parts.total_bytes(), output.len());
+        assert!(parts.describe().contains("left out"));
+    }
+
+    #[test]
+    fn test_estimate_counts_parts_sent_again() {
+        let parts = OutputParts::split("make", &lines(2 * PART_BYTES / 40, 40));
+        assert_eq!(parts.count(), 2);
+        let tokens: Vec<usize> = parts.parts.iter().map(|part| prompt::estimate_tokens(part) + ENVELOPE_TOKENS).collect();
+        assert_eq!(parts.estimate_tokens(), 2 * tokens[0] + tokens[1]);
+    }
+
+    #[test]
+    fn test_requests_envelope_all_but_the_last_part() -> Result<(), Box<dyn std::error::Error>> {
+        let record = CommandRecord::new("cargo test".to_string(), "tail only".to_string());
+        let context = ContextSnapshot {
+            cwd: "/src".to_string(),
+            recent_commands: vec![CommandRecord::new("ls".to_string(), String::new()), record.clone()],
+            ..ContextSnapshot::empty()
+        };
+        let output = lines(PART_BYTES / 20 + 10, 20);
+        let mut transfer = Transfer::new(record, &output, "why?".to_string(), context, Vec::new());
+        assert_eq!(transfer.count(), 2);
+
+        let first = transfer.next_request().ok_or("no first part")?;
+        assert!(first.part.is_intermediate());
+        assert!(first.user_input.contains("reply only READY"));
+        assert!(first.context.cwd.is_empty());
+        assert_eq!(first.context.recent_commands.len(), 1);
+        assert!(!transfer.is_done());
+
+        let last = transfer.next_request().ok_or("no last part")?;
+        assert_eq!(last.user_input, "why?");
+        assert_eq!((last.part.part, last.part.of), (2, 2));
+        assert_eq!(last.context.cwd, "/src");
+        assert_eq!(last.context.recent_commands.len(), 2);
+        assert_eq!(format!("{}{}", first.context.recent_commands[0].output, last.context.recent_commands[1].output), output);
+        assert!(transfer.is_done());
+        assert!(transfer.next_request().is_none());
+        assert_eq!(transfer.full_context().recent_commands[1].output, output);
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+//! Provisional command suggestions read from tool-call arguments mid-stream.
+//!
+//! `suggest_command` arguments arrive as JSON deltas and only parse once the
+//! stream is over. To show the card earlier, the arguments received so far
+//! are scanned for the `command` and `explanation` fields whose string values
+//! are already complete. The scanner works on any prefix of the JSON (cut
+//! mid-key, mid-escape, mid-number) and gives up quietly at the first token
+//! it cannot finish; the real card still comes from the full parse.
+
+/// Fields of a `suggest_command` call known so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SuggestionDraft {
+    pub command: Option<String>,
+    pub explanation: Option<String>,
+}
+
+impl SuggestionDraft {
+    /// Read the complete `command`/`explanation` values from `args`, a prefix
+    /// of a JSON object.
+    pub fn scan(args: &str) -> Self {
+        let mut draft = Self::default();
+        let mut scanner = Scanner { s: args, pos: 0 };
+        if scanner.eat(b'{').is_none() {
+            return draft;
+        }
+        loop {
+            scanner.skip_ws();
+            if scanner.peek() == Some(b'}') {
+                break;
+            }
+            let Some(key) = scanner.string() else {
+                break;
+            };
+            if scanner.eat(b':').is_none() {
+                break;
+            }
+            scanner.skip_ws();
+            let field = match key.as_str() {
+                "command" => &mut draft.command,
+                "explanation" => &mut draft.explanation,
+                _ => {
+                    if scanner.skip_value().is_none() {
+                        break;
+                    }
+                    if scanner.eat(b',').is_none() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let Some(value) = scanner.string() else {
+                break;
+            };
+            *field = Some(value);
+            if scanner.eat(b',').is_none() {
+                break;
+            }
+        }
+        draft
+    }
+}
+
+/// Follows the first suggestion of a stream as its arguments grow.
+#[derive(Debug, Default)]
+pub struct DraftTracker {
+    index: Option<u32>,
+    last: Option<SuggestionDraft>,
+}
+
+impl DraftTracker {
+    /// Note the arguments of suggestion `index` so far. Returns the draft
+    /// when its known fields changed; later suggestions are ignored.
+    pub fn update(&mut self, index: u32, args: &str) -> Option<SuggestionDraft> {
+        if *self.index.get_or_insert(index) != index {
+            return None;
+        }
+        let draft = SuggestionDraft::scan(args);
+        if self.last.as_ref() == Some(&draft) {
+            return None;
+        }
+        self.last = Some(draft.clone());
+        Some(draft)
+    }
+}
+
+/// Cursor over a JSON prefix. Every method returns None when the token at
+/// the cursor is malformed or cut off.
+struct Scanner<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip whitespace, then consume `byte`.
+    fn eat(&mut self, byte: u8) -> Option<()> {
+        self.skip_ws();
+        (self.peek()? == byte).then(|| self.pos += 1)
+    }
+
+    /// Index of the quote closing the string that opens at the cursor.
+    fn string_end(&self) -> Option<usize> {
+        let bytes = self.s.as_bytes();
+        if bytes.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        let mut i = self.pos + 1;
+        while let Some(&b) = bytes.get(i) {
+            match b {
+                b'\\' => i += 2,
+                b'"' => return Some(i),
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    /// Consume and decode a complete string literal.
+    fn string(&mut self) -> Option<String> {
+        self.skip_ws();
+        let end = self.string_end()?;
+        let value = serde_json::from_str(&self.s[self.pos..=end]).ok()?;
+        self.pos = end + 1;
+        Some(value)
+    }
+
+    /// Consume a complete value of any type without decoding it.
+    fn skip_value(&mut self) -> Option<()> {
+        match self.peek()? {
+            b'"' => {
+                self.pos = self.string_end()? + 1;
+                Some(())
+            }
+            b'{' | b'[' => self.skip_nested(),
+            _ => self.skip_scalar(),
+        }
+    }
+
+    /// Consume an object or array, including the strings inside it.
+    fn skip_nested(&mut self) -> Option<()> {
+        let mut depth = 0usize;
+        loop {
+            match self.peek()? {
+                b'"' => self.pos = self.string_end()? + 1,
+                b'{' | b'[' => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                b'}' | b']' => {
+                    depth = depth.checked_sub(1)?;
+                    self.pos += 1;
+                    if depth == 0 {
+                        return Some(());
+                    }
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    /// Consume a number, `true`, `false` or `null`. It only counts as
+    /// complete once the delimiter after it has arrived.
+    fn skip_scalar(&mut self) -> Option<()> {
+        let start = self.pos;
+        while let Some(b) = self.peek() {
+            if matches!(b, b',' | b'}' | b']') || b.is_ascii_whitespace() {
+                return (self.pos > start).then_some(());
+            }
+            self.pos += 1;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARGS: &str = r#"{"risk_level": {"level": "low", "tags": ["a\"]"]}, "count": 12, "command": "grep -rn \"TODO\\n\" src | head é🚀", "explanation":"Finds \"TODO\" markers","extra":null}"#;
+
+    fn full() -> SuggestionDraft {
+        SuggestionDraft {
+            command: Some("grep -rn \"TODO\\n\" src | head é🚀".to_string()),
+            explanation: Some("Finds \"TODO\" markers".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_scan_complete_arguments() {
+        assert_eq!(SuggestionDraft::scan(ARGS), full());
+        assert_eq!(SuggestionDraft::scan(""), SuggestionDraft::default());
+        assert_eq!(SuggestionDraft::scan("[1, 2]"), SuggestionDraft::default());
+    }
+
+    #[test]
+    fn test_scan_every_prefix() {
+        let full = full();
+        for (end, _) in ARGS.char_indices() {
+            let draft = SuggestionDraft::scan(&ARGS[..end]);
+            // Fields only ever appear with their final value
+            assert!(draft.command.is_none() || draft.command == full.command, "prefix {end}");
+            assert!(draft.explanation.is_none() || draft.explanation == full.explanation, "prefix {end}");
+        }
+    }
+
+    #[test]
+    fn test_scan_waits_for_value_to_close() {
+        let mid_escape = r#"{"command": "echo \"#;
+        assert_eq!(SuggestionDraft::scan(mid_escape).command, None);
+        let mid_unicode = r#"{"command": "echo \u00"#;
+        assert_eq!(SuggestionDraft::scan(mid_unicode).command, None);
+        let mid_key = r#"{"command": "ls", "expla"#;
+        assert_eq!(SuggestionDraft::scan(mid_key).command.as_deref(), Some("ls"));
+        assert_eq!(SuggestionDraft::scan(mid_key).explanation, None);
+        // A number may still be growing
+        let mid_number = r#"{"n": 12"#;
+        assert_eq!(SuggestionDraft::scan(mid_number), SuggestionDraft::default());
+    }
+
+    #[test]
+    fn test_scan_rejects_malformed_input() {
+        assert_eq!(SuggestionDraft::scan(r#"{"a": ]]]], "command": "ls"}"#), SuggestionDraft::default());
+        assert_eq!(SuggestionDraft::scan(r#"{"command": 5, "explanation": "x"}"#), SuggestionDraft::default());
+        assert_eq!(SuggestionDraft::scan(r#"{"command": "\x"}"#), SuggestionDraft::default());
+    }
+
+    #[test]
+    fn test_tracker_reports_changes_of_first_suggestion() {
+        let mut tracker = DraftTracker::default();
+        assert_eq!(tracker.update(0, ""), Some(SuggestionDraft::default()));
+        assert_eq!(tracker.update(0, r#"{"comm"#), None);
+        let draft = tracker.update(0, r#"{"command": "ls""#);
+        assert_eq!(draft.and_then(|d| d.command).as_deref(), Some("ls"));
+        assert_eq!(tracker.update(0, r#"{"command": "ls", "#), None);
+        assert_eq!(tracker.update(1, r#"{"command": "pwd"}"#), None);
+    }
+}
@@ -0,0 +1,252 @@
+//! What an API error means for the request that got it.
+//!
+//! Providers report a missing model or a model without function calling in
+//! their own words: an error code from OpenAI, a message only from Ollama
+//! and vLLM, and the body folded into a stream error when it arrives after
+//! streaming started. [`classify`] maps all of them to an [`ErrorCause`], so
+//! the session code switches on that and never on the text.
+//!
+//! A [`ErrorCause::Transient`] failure (the rate limit, an overloaded
+//! server, a dropped connection) is sent again after [`retry_delay`].
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use async_openai::error::OpenAIError;
+use tokio::time::Duration;
+
+/// Why a request failed, as far as the session can do something about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCause {
+    /// The model does not exist, or the key has no access to it
+    ModelNotFound,
+    /// The model cannot call tools: the request has to go without them
+    ToolsUnsupported,
+    /// Rate limited, a server error or the connection dropped: the same
+    /// request may well go through in a moment
+    Transient,
+    /// Anything else; the error is shown as it is
+    Other,
+}
+
+/// Suggestions listed at most when the model was not found.
+pub const MAX_CLOSE_MATCHES: usize = 3;
+
+/// Times a transient failure is retried, unless configured.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Wait before the first retry; each one after waits twice as long
+const RETRY_BASE: Duration = Duration::from_millis(500);
+
+/// Longest wait before a retry
+const RETRY_CAP: Duration = Duration::from_secs(8);
+
+/// Error codes of failures worth retrying; anything else with a code
+/// (`insufficient_quota`, `invalid_api_key`) is not
+const TRANSIENT_CODES: &[&str] = &["rate_limit_exceeded", "server_error", "overloaded_error"];
+
+// Lowercase; checked against the lowercased message
+const TRANSIENT: &[&str] = &[
+    "rate limit reached",
+    "overloaded",
+    "connection reset",
+    "connection closed",
+    "broken pipe",
+    "error sending request",
+    "operation timed out",
+];
+
+// Lowercase; checked against the lowercased message
+const TOOLS_UNSUPPORTED: &[&str] = &[
+    "does not support tools",
+    "tools is not supported",
+    "'tools' is not supported",
+    "unrecognized request argument supplied: tools",
+    "function calling is not supported",
+    "tool choice requires --enable-auto-tool-choice",
+];
+
+/// The cause of `error`.
+pub fn classify(error: &OpenAIError) -> ErrorCause {
+    match error {
+        OpenAIError::ApiError(api) if api.code.as_deref() == Some("model_not_found") => ErrorCause::ModelNotFound,
+        OpenAIError::ApiError(api)
+            if [&api.code, &api.r#type].into_iter().flatten().any(|code| TRANSIENT_CODES.contains(&code.as_str())) =>
+        {
+            ErrorCause::Transient
+        }
+        OpenAIError::ApiError(api) => classify_text(&api.message),
+        other => classify_text(&other.to_string()),
+    }
+}
+
+/// The cause of an error known only by its text (a message, or a body
+/// carried in a stream error).
+pub fn classify_text(text: &str) -> ErrorCause {
+    let text = text.to_lowercase();
+    if TOOLS_UNSUPPORTED.iter().any(|pattern| text.contains(pattern)) {
+        return ErrorCause::ToolsUnsupported;
+    }
+    let model_missing = text.contains("model")
+        && (text.contains("does not exist") || text.contains("not found") || text.contains("model_not_found"));
+    if model_missing || text.contains("invalid status code: 404") {
+        return ErrorCause::ModelNotFound;
+    }
+    // 429 and 5xx, in the text of a stream error
+    let status = text.split_once("invalid status code: ").and_then(|(_, rest)| rest.get(..3));
+    if status.is_some_and(|code| code == "429" || (code.starts_with('5') && code.bytes().all(|b| b.is_ascii_digit())))
+        || TRANSIENT.iter().any(|pattern| text.contains(pattern))
+    {
+        return ErrorCause::Transient;
+    }
+    ErrorCause::Other
+}
+
+/// How long to wait before retry number `retry` (from 1): doubling from
+/// [`RETRY_BASE`] up to [`RETRY_CAP`], less up to half of it at random so
+/// clients that failed together don't all retry together.
+pub fn retry_delay(retry: u32) -> Duration {
+    let jitter = RandomState::new().build_hasher().finish();
+    jittered_delay(retry, jitter as f64 / u64::MAX as f64)
+}
+
+/// [`retry_delay`] with the random part given, from 0 to 1.
+fn jittered_delay(retry: u32, jitter: f64) -> Duration {
+    let full = RETRY_BASE.saturating_mul(1 << retry.saturating_sub(1).min(16)).min(RETRY_CAP);
+    full.mul_f64(1.0 - jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+/// Models in `available` that `model` is likely a typo of, closest first
+/// (at most [`MAX_CLOSE_MATCHES`]).
+pub fn close_matches<'a>(model: &str, available: &'a [String]) -> Vec<&'a str> {
+    let model = model.to_lowercase();
+    let limit = (model.chars().count() / 4).max(2);
+    let mut matches: Vec<(usize, &str)> = available
+        .iter()
+        .map(|name| (edit_distance(&model, &name.to_lowercase()), name.as_str()))
+        .filter(|&(distance, _)| distance <= limit)
+        .collect();
+    matches.sort();
+    matches.into_iter().take(MAX_CLOSE_MATCHES).map(|(_, name)| name).collect()
+}
+
+/// The chat message for a model that was not found.
+pub fn model_not_found_message(model: &str, suggestions: &[String]) -> String {
+    let hint = match suggestions {
+        [] => String::new(),
+        [only] => format!(" Did you mean {}?", only),
+        [rest @ .., last] => format!(" Did you mean {} or {}?", rest.join(", "), last),
+    };
+    format!(
+        "Model `{}` was not found.{} Fix `model` in the config and send your message again: it is back in the input box.",
+        model, hint
+    )
+}
+
+/// Levenshtein distance, by characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::error::ApiError;
+
+    // Error bodies as the providers sent them
+    const OPENAI_MODEL_NOT_FOUND: &str = r#"{"error":{"message":"The model `gpt-4o-mimi` does not exist or you do not have access to it.","type":"invalid_request_error","param":null,"code":"model_not_found"}}"#;
+    const OPENAI_TOOLS_UNSUPPORTED: &str = r#"{"error":{"message":"tools is not supported in this model. For a list of supported models, refer to https://platform.openai.com/docs/guides/function-calling#models-supporting-function-calling.","type":"invalid_request_error","param":null,"code":null}}"#;
+    const OLLAMA_MODEL_NOT_FOUND: &str = r#"{"error":{"message":"model \"llama3.3:8b\" not found, try pulling it first","type":"api_error","param":null,"code":null}}"#;
+    const OLLAMA_TOOLS_UNSUPPORTED: &str = r#"{"error":{"message":"registry.ollama.ai/library/gemma:2b does not support tools","type":"api_error","param":null,"code":null}}"#;
+    const OPENAI_RATE_LIMITED: &str = r#"{"error":{"message":"Rate limit reached for gpt-4o-mini in organization org-abc on requests per min (RPM): Limit 3, Used 3, Requested 1.","type":"requests","param":null,"code":"rate_limit_exceeded"}}"#;
+    const OPENAI_NO_QUOTA: &str = r#"{"error":{"message":"You exceeded your current quota, please check your plan and billing details.","type":"insufficient_quota","param":null,"code":"insufficient_quota"}}"#;
+    const OPENAI_BAD_KEY: &str = r#"{"error":{"message":"Incorrect API key provided: sk-abc. You can find your API key at https://platform.openai.com/account/api-keys.","type":"invalid_request_error","param":null,"code":"invalid_api_key"}}"#;
+
+    fn api_error(body: &str) -> Result<OpenAIError, serde_json::Error> {
+        let body: serde_json::Value = serde_json::from_str(body)?;
+        let api: ApiError = serde_json::from_value(body["error"].clone())?;
+        Ok(OpenAIError::ApiError(api))
+    }
+
+    #[test]
+    fn test_api_errors_are_classified_by_code_then_message() -> Result<(), serde_json::Error> {
+        assert_eq!(classify(&api_error(OPENAI_MODEL_NOT_FOUND)?), ErrorCause::ModelNotFound);
+        assert_eq!(classify(&api_error(OPENAI_TOOLS_UNSUPPORTED)?), ErrorCause::ToolsUnsupported);
+        assert_eq!(classify(&api_error(OLLAMA_MODEL_NOT_FOUND)?), ErrorCause::ModelNotFound);
+        assert_eq!(classify(&api_error(OLLAMA_TOOLS_UNSUPPORTED)?), ErrorCause::ToolsUnsupported);
+        assert_eq!(classify(&api_error(OPENAI_RATE_LIMITED)?), ErrorCause::Transient);
+        assert_eq!(classify(&api_error(OPENAI_NO_QUOTA)?), ErrorCause::Other);
+        assert_eq!(classify(&api_error(OPENAI_BAD_KEY)?), ErrorCause::Other);
+        Ok(())
+    }
+
+    #[test]
+    fn test_busy_servers_and_dropped_connections_are_transient() {
+        let stream = classify_text;
+        assert_eq!(stream("Invalid status code: 429 Too Many Requests"), ErrorCause::Transient);
+        assert_eq!(stream("Invalid status code: 503 Service Unavailable"), ErrorCause::Transient);
+        assert_eq!(stream("Transport error: error sending request for url (https://api.openai.com/v1/chat/completions)"), ErrorCause::Transient);
+        assert_eq!(stream("error decoding response body: connection reset by peer"), ErrorCause::Transient);
+        // The key or the request is wrong: asking again changes nothing
+        assert_eq!(stream("Invalid status code: 401 Unauthorized"), ErrorCause::Other);
+        assert_eq!(stream("Invalid status code: 400 Bad Request"), ErrorCause::Other);
+    }
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_the_cap() {
+        let delays: Vec<Duration> = (1..=6).map(|retry| jittered_delay(retry, 0.0)).collect();
+        let ms = |ms| Duration::from_millis(ms);
+        assert_eq!(delays, [ms(500), ms(1000), ms(2000), ms(4000), ms(8000), ms(8000)]);
+        assert_eq!(jittered_delay(2, 1.0), ms(500));
+        assert_eq!(jittered_delay(u32::MAX, 0.0), RETRY_CAP);
+        assert!((1..=3).all(|retry| (jittered_delay(retry, 1.0)..=jittered_delay(retry, 0.0)).contains(&retry_delay(retry))));
+    }
+
+    #[test]
+    fn test_stream_errors_carry_the_cause_in_their_text() {
+        // A stream error is classified by its text
+        let stream = classify_text;
+        assert_eq!(
+            stream(&format!("Invalid status code: 400 Bad Request: {}", OLLAMA_TOOLS_UNSUPPORTED)),
+            ErrorCause::ToolsUnsupported
+        );
+        assert_eq!(stream("Invalid status code: 404 Not Found"), ErrorCause::ModelNotFound);
+        assert_eq!(
+            stream(r#"{"object":"error","message":"\"auto\" tool choice requires --enable-auto-tool-choice and --tool-call-parser to be set","type":"BadRequestError","param":null,"code":400}"#),
+            ErrorCause::ToolsUnsupported
+        );
+        assert_eq!(stream("error decoding response body"), ErrorCause::Other);
+    }
+
+    #[test]
+    fn test_close_matches_are_nearest_first() {
+        let available: Vec<String> = ["gpt-4o", "gpt-4o-mini", "gpt-4.1-mini", "o3-mini", "text-embedding-3-small"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(close_matches("gpt-4o-mimi", &available), ["gpt-4o-mini"]);
+        assert_eq!(close_matches("gpt-4-mini", &available), ["gpt-4o-mini", "gpt-4.1-mini"]);
+        assert_eq!(close_matches("GPT-4o", &available), ["gpt-4o"]);
+        assert!(close_matches("claude", &available).is_empty());
+    }
+
+    #[test]
+    fn test_model_not_found_message_lists_the_suggestions() {
+        let message = model_not_found_message("gpt-4o-mimi", &["gpt-4o-mini".to_string()]);
+        assert!(message.starts_with("Model `gpt-4o-mimi` was not found. Did you mean gpt-4o-mini? "));
+        let message = model_not_found_message("gpt4", &["gpt-4".to_string(), "gpt-4o".to_string(), "gpt-4.1".to_string()]);
+        assert!(message.contains("Did you mean gpt-4, gpt-4o or gpt-4.1?"));
+        assert!(!model_not_found_message("x", &[]).contains("Did you mean"));
+    }
+}
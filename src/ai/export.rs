@@ -0,0 +1,266 @@
+//! A session written out to a file.
+//!
+//! The Markdown transcript is for reading and sharing: the questions and
+//! replies as the pane shows them, each suggested command in a fenced
+//! block with what became of it. The JSON document is for loading the
+//! session again: the history exactly as the model is sent it, system
+//! prompt and tool messages included, and the suggestion records next to
+//! it. It carries [`EXPORT_VERSION`], so an import can tell which layout it
+//! reads.
+//!
+//! An imported document is checked before it becomes a session: the API
+//! refuses a history where a tool call goes without its response, so each
+//! call left unanswered before the next message gets a response saying
+//! none was recorded, and a response to no call fails the import.
+
+use std::fmt;
+
+use async_openai::error::OpenAIError;
+use async_openai::types::{
+    ChatCompletionRequestMessage, ChatCompletionRequestToolMessage, ChatCompletionRequestToolMessageContent,
+    ChatCompletionRequestUserMessageContent,
+};
+use serde::{Deserialize, Serialize};
+
+use super::prompt;
+use super::session::{
+    CommandSuggestionRecord, CommandSuggestionStatus, ExplainOutputArgs, Linkage, TOOL_EXPLAIN_OUTPUT,
+};
+
+/// Layout of the JSON export; bumped when it changes.
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Response given on import to a tool call exported without one
+pub const MISSING_RESPONSE: &str = "No response to this tool call was recorded.";
+
+/// What a session is exported as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    /// The format named `name` ("md", "markdown" or "json").
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "json" => Some(ExportFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// File extension of an export in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Everything exported from a session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub version: u32,
+    pub name: String,
+    pub model: String,
+    /// Seconds since the epoch
+    pub exported_at: u64,
+    /// Summary of the turns folded out of the history, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// The messages sent to the model, system prompt first
+    pub conversation_history: Vec<ChatCompletionRequestMessage>,
+    pub command_suggestions: Vec<CommandSuggestionRecord>,
+}
+
+/// Why an export can't be imported.
+#[derive(Debug)]
+pub enum ImportError {
+    /// Not JSON, or not laid out as an export
+    Parse(serde_json::Error),
+    /// Written in a layout this version doesn't know
+    Version(u32),
+    /// A tool message answers no call made before it
+    StrayResponse(String),
+    /// The session couldn't be made
+    Build(Box<OpenAIError>),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Parse(e) => write!(f, "Not a session export: {}", e),
+            ImportError::Version(version) => write!(
+                f,
+                "Can't read session export version {} (this version reads up to {})",
+                version, EXPORT_VERSION
+            ),
+            ImportError::StrayResponse(id) => {
+                write!(f, "Session export has a tool response to no tool call ({})", id)
+            }
+            ImportError::Build(e) => write!(f, "Cannot create the session: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl SessionExport {
+    /// Read a JSON export, with every tool call answered but those of the
+    /// last message, which are answered when the next question is sent.
+    pub fn from_json(json: &str) -> Result<Self, ImportError> {
+        // The version first, so a newer layout says so rather than failing
+        // on a field it moved
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        let Version { version } = serde_json::from_str(json).map_err(ImportError::Parse)?;
+        if version == 0 || version > EXPORT_VERSION {
+            return Err(ImportError::Version(version));
+        }
+        let mut export: SessionExport = serde_json::from_str(json).map_err(ImportError::Parse)?;
+        export.conversation_history = answer_tool_calls(std::mem::take(&mut export.conversation_history))?;
+        Ok(export)
+    }
+
+    pub fn render(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Markdown => self.to_markdown(),
+            ExportFormat::Json => self.to_json(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// The transcript: "You:" and "AI:" turns, with the suggested commands
+    /// fenced. System prompts, tool messages and the parts of a full output
+    /// sent ahead of a question are left out.
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n\n", self.name);
+        let exported = chrono::DateTime::from_timestamp(i64::try_from(self.exported_at).unwrap_or(0), 0)
+            .map(|at| at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        out.push_str(&format!("Model `{}`, exported {}\n", self.model, exported));
+        if let Some(summary) = &self.summary {
+            out.push_str("\n> Earlier turns, summarized:\n");
+            for line in summary.lines() {
+                out.push_str(format!("> {}", line).trim_end());
+                out.push('\n');
+            }
+        }
+
+        let mut suggestions = Linkage::new(&self.command_suggestions);
+        // The acknowledgement of a part before the last is left out too
+        let mut acknowledgement = false;
+        for msg in &self.conversation_history {
+            match msg {
+                ChatCompletionRequestMessage::User(user_msg) => {
+                    let text = match &user_msg.content {
+                        ChatCompletionRequestUserMessageContent::Text(t) => t,
+                        ChatCompletionRequestUserMessageContent::Array(_) => continue,
+                    };
+                    let parsed = prompt::parse_user_prompt(text);
+                    acknowledgement = parsed.as_ref().and_then(|p| p.output_part.as_ref()).is_some_and(|part| part.is_intermediate());
+                    if !acknowledgement {
+                        let request = parsed.map_or_else(|| text.clone(), |p| p.user_request);
+                        out.push_str(&format!("\n**You:** {}\n", request));
+                    }
+                }
+                ChatCompletionRequestMessage::Assistant(asst_msg) => {
+                    if std::mem::take(&mut acknowledgement) {
+                        for call in asst_msg.tool_calls.iter().flatten() {
+                            suggestions.take(call);
+                        }
+                        continue;
+                    }
+                    if let Some(async_openai::types::ChatCompletionRequestAssistantMessageContent::Text(text)) = &asst_msg.content
+                        && !text.trim().is_empty()
+                    {
+                        out.push_str(&format!("\n**AI:** {}\n", text.trim_end()));
+                    }
+                    for call in asst_msg.tool_calls.iter().flatten() {
+                        if let Some(record) = suggestions.take(call) {
+                            out.push_str(&command_block(record));
+                        } else if call.function.name == TOOL_EXPLAIN_OUTPUT
+                            && let Ok(args) = serde_json::from_str::<ExplainOutputArgs>(&call.function.arguments)
+                        {
+                            out.push_str(&format!(
+                                "\n**Explanation:** {}\n\n- Probable cause: {}\n- Next step: {}\n",
+                                args.summary, args.probable_cause, args.suggested_next_step
+                            ));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+}
+
+/// `history` with a response after each tool call left without one before
+/// the next message. Calls of the last message may stay unanswered.
+fn answer_tool_calls(
+    history: Vec<ChatCompletionRequestMessage>,
+) -> Result<Vec<ChatCompletionRequestMessage>, ImportError> {
+    let mut answered = Vec::with_capacity(history.len());
+    // Calls of the latest assistant message still to be answered
+    let mut open: Vec<String> = Vec::new();
+    for msg in history {
+        match &msg {
+            ChatCompletionRequestMessage::Tool(tool_msg) => {
+                let Some(at) = open.iter().position(|id| *id == tool_msg.tool_call_id) else {
+                    return Err(ImportError::StrayResponse(tool_msg.tool_call_id.clone()));
+                };
+                open.remove(at);
+            }
+            _ => {
+                answered.extend(open.drain(..).map(|tool_call_id| {
+                    ChatCompletionRequestMessage::Tool(ChatCompletionRequestToolMessage {
+                        content: ChatCompletionRequestToolMessageContent::Text(MISSING_RESPONSE.to_string()),
+                        tool_call_id,
+                    })
+                }));
+                if let ChatCompletionRequestMessage::Assistant(asst_msg) = &msg {
+                    open = asst_msg.tool_calls.iter().flatten().map(|call| call.id.clone()).collect();
+                }
+            }
+        }
+        answered.push(msg);
+    }
+    Ok(answered)
+}
+
+/// A suggested command fenced, with what became of it and why it was
+/// suggested.
+fn command_block(record: &CommandSuggestionRecord) -> String {
+    // Longer than any run of backticks in the command
+    let mut fence = "```".to_string();
+    while record.command.contains(&fence) {
+        fence.push('`');
+    }
+    let mut block = format!("\n{}sh\n{}\n{}\n", fence, record.command, fence);
+    let cwd = record.cwd.as_deref().map(|cwd| format!(" (in `{}`)", cwd)).unwrap_or_default();
+    block.push_str(&format!("*{}*{}", status_label(record.status), cwd));
+    if !record.explanation.is_empty() {
+        block.push_str(&format!(": {}", record.explanation));
+    }
+    block.push('\n');
+    block
+}
+
+fn status_label(status: CommandSuggestionStatus) -> &'static str {
+    match status {
+        CommandSuggestionStatus::Pending => "Pending",
+        CommandSuggestionStatus::Accepted => "Accepted",
+        CommandSuggestionStatus::Rejected => "Rejected",
+        CommandSuggestionStatus::Ignored => "Ignored",
+        CommandSuggestionStatus::Superseded => "Superseded",
+        CommandSuggestionStatus::Skipped => "Skipped",
+    }
+}
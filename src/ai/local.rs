@@ -0,0 +1,871 @@
+//! Local answers for trivial questions, evaluated without an API call.
+//!
+//! A tiny expression language covering what people tend to ask an assistant
+//! out of laziness:
+//!
+//! - arithmetic: `+ - * / % ^` (or `**`), parentheses, unary minus,
+//!   `0x`/`0b`/`0o` literals (`2^10 * 3`)
+//! - base conversion: `0x1F4 in decimal`, `255 to hex`, `10 in binary`
+//! - unit conversion for time, length, mass, data sizes and temperature:
+//!   `3 weeks in hours`, `100 c to f`, `1.5 GiB in MB`
+//! - timestamps: `unix 1700000000`, `1700000000 in utc`, `2024-01-02 to unix`
+//!
+//! Integer arithmetic is exact (`i128`) and reports overflow instead of
+//! wrapping; anything with a fractional part is done in `f64`.
+//!
+//! [`try_answer`] only answers inputs it understands with high confidence and
+//! returns `None` for everything else so the question reaches the AI as usual.
+//! [`evaluate`] is used when the user forces the local path with `=`.
+
+use std::fmt;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Utc};
+
+/// Leading words ignored before the query ("what's 2+2?").
+const FILLER_WORDS: &[&str] = &[
+    "what's", "whats", "what", "is", "how", "many", "much", "convert", "calculate", "calc",
+    "compute", "eval", "evaluate", "parse", "this", "please",
+];
+
+/// Words that introduce a timestamp ("unix timestamp 1700000000").
+const TIMESTAMP_WORDS: &[&str] = &["unix", "timestamp", "epoch", "date"];
+
+/// Conversion targets that format a timestamp as a date.
+const DATE_TARGETS: &[&str] = &["date", "utc", "iso", "datetime", "time"];
+
+/// Conversion targets that turn a date into a unix timestamp.
+const UNIX_TARGETS: &[&str] = &["unix", "timestamp", "epoch"];
+
+/// Timestamps at or above this magnitude are taken as milliseconds.
+const MILLIS_THRESHOLD: i128 = 100_000_000_000;
+
+/// Why an input could not be answered locally.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocalError {
+    /// The input is not something the evaluator understands
+    Syntax(String),
+    /// A word in unit position is not a known unit
+    UnknownUnit(String),
+    /// Units of different kinds (e.g. hours to meters)
+    IncompatibleUnits(String, String),
+    /// Integer result does not fit, or a float result is infinite
+    Overflow,
+    DivisionByZero,
+    /// Base conversion of a value with a fractional part
+    NotAnInteger,
+    /// Timestamp outside the representable date range
+    InvalidTimestamp,
+}
+
+impl fmt::Display for LocalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(msg) => write!(f, "cannot evaluate: {}", msg),
+            Self::UnknownUnit(unit) => write!(f, "unknown unit '{}'", unit),
+            Self::IncompatibleUnits(from, to) => write!(f, "cannot convert {} to {}", from, to),
+            Self::Overflow => write!(f, "result overflows"),
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::NotAnInteger => write!(f, "base conversion needs an integer"),
+            Self::InvalidTimestamp => write!(f, "timestamp out of range"),
+        }
+    }
+}
+
+impl std::error::Error for LocalError {}
+
+impl LocalError {
+    /// Whether the error means "not a local query" rather than a failed one.
+    fn is_unrecognized(&self) -> bool {
+        matches!(self, Self::Syntax(_) | Self::UnknownUnit(_))
+    }
+}
+
+/// Answer `input` locally if it is confidently a calculator-style query.
+///
+/// Returns `None` when the input should go to the AI. Errors are only
+/// returned for queries that were understood but cannot be computed
+/// (overflow, division by zero, mismatched units, ...).
+pub fn try_answer(input: &str) -> Option<Result<String, LocalError>> {
+    let query = parse_query(input).ok()?;
+    if !query.is_confident() {
+        return None;
+    }
+    match query.run() {
+        Err(e) if e.is_unrecognized() => None,
+        result => Some(result),
+    }
+}
+
+/// Evaluate `input` unconditionally (the user asked for a local answer).
+pub fn evaluate(input: &str) -> Result<String, LocalError> {
+    parse_query(input)?.run()
+}
+
+// ============================================================================
+// Queries
+// ============================================================================
+
+/// A recognized query shape, not evaluated yet.
+#[derive(Debug)]
+enum Query {
+    /// Plain arithmetic expression
+    Arithmetic(Vec<Token>),
+    /// Expression followed by `in hex|decimal|binary|octal`
+    Base(Vec<Token>, Base),
+    /// `<expr> <unit> in <unit>`
+    Units(Vec<Token>, &'static Unit, &'static Unit),
+    /// Unix timestamp (seconds or milliseconds) to date
+    FromTimestamp(Vec<Token>),
+    /// Date text (or "now") to unix timestamp
+    ToTimestamp(String),
+}
+
+impl Query {
+    /// Whether the query clearly asks for a computation (a bare number does not).
+    fn is_confident(&self) -> bool {
+        match self {
+            Self::Arithmetic(tokens) => tokens.iter().any(Token::is_operator),
+            _ => true,
+        }
+    }
+
+    fn run(&self) -> Result<String, LocalError> {
+        match self {
+            Self::Arithmetic(tokens) => Ok(format_number(eval_tokens(tokens)?)),
+            Self::Base(tokens, base) => format_base(eval_tokens(tokens)?, *base),
+            Self::Units(tokens, from, to) => {
+                let value = convert(eval_tokens(tokens)?.as_f64(), from, to)?;
+                Ok(format!("{} {}", format_number(Number::Float(value)), to.symbol))
+            }
+            Self::FromTimestamp(tokens) => match eval_tokens(tokens)? {
+                Number::Int(n) => format_timestamp(n),
+                Number::Float(_) => Err(LocalError::NotAnInteger),
+            },
+            Self::ToTimestamp(text) => parse_date(text).map(|secs| secs.to_string()),
+        }
+    }
+}
+
+/// Recognize the shape of `input`.
+fn parse_query(input: &str) -> Result<Query, LocalError> {
+    let text = input.trim().trim_end_matches('?').trim();
+    let mut words: Vec<&str> = text.split_whitespace().collect();
+    while words.len() > 1 && FILLER_WORDS.contains(&words[0].to_lowercase().as_str()) {
+        words.remove(0);
+    }
+    if words.is_empty() {
+        return Err(LocalError::Syntax("empty input".to_string()));
+    }
+
+    // "<lhs> in|to|as <rhs>", split on the last keyword
+    let split = words
+        .iter()
+        .rposition(|w| matches!(w.to_lowercase().as_str(), "in" | "to" | "as"));
+    if let Some(pos) = split
+        && pos > 0
+        && pos + 1 < words.len()
+    {
+        let lhs = words[..pos].join(" ");
+        let rhs = words[pos + 1..].join(" ");
+        return parse_conversion(&lhs, &rhs);
+    }
+
+    // "unix timestamp 1700000000", "@1700000000"
+    let mut rest = words.as_slice();
+    while let Some((first, tail)) = rest.split_first()
+        && TIMESTAMP_WORDS.contains(&first.to_lowercase().as_str())
+    {
+        rest = tail;
+    }
+    if rest.len() < words.len() {
+        return Ok(Query::FromTimestamp(tokenize(&rest.join(" "))?));
+    }
+    let joined = words.join(" ");
+    if let Some(stamp) = joined.strip_prefix('@') {
+        return Ok(Query::FromTimestamp(tokenize(stamp)?));
+    }
+
+    let tokens = tokenize(&joined)?;
+    if tokens.iter().any(|t| matches!(t, Token::Word(_))) {
+        return Err(LocalError::Syntax(joined));
+    }
+    Ok(Query::Arithmetic(tokens))
+}
+
+/// Recognize `<lhs> in <rhs>`.
+fn parse_conversion(lhs: &str, rhs: &str) -> Result<Query, LocalError> {
+    let target = rhs.to_lowercase();
+    if let Some(base) = Base::from_name(&target) {
+        return Ok(Query::Base(expression_tokens(lhs)?, base));
+    }
+    if DATE_TARGETS.contains(&target.as_str()) {
+        return Ok(Query::FromTimestamp(expression_tokens(lhs)?));
+    }
+    if UNIX_TARGETS.contains(&target.as_str()) {
+        return Ok(Query::ToTimestamp(lhs.to_string()));
+    }
+
+    // "hours in 3 weeks" (from "how many hours in 3 weeks")
+    let (lhs, rhs) = if lookup_unit(lhs).is_some() && lookup_unit(rhs).is_none() {
+        (rhs, lhs)
+    } else {
+        (lhs, rhs)
+    };
+
+    let mut tokens = tokenize(lhs)?;
+    let Some(Token::Word(from_name)) = tokens.pop() else {
+        return Err(LocalError::Syntax(format!("missing unit in '{}'", lhs)));
+    };
+    if tokens.is_empty() || tokens.iter().any(|t| matches!(t, Token::Word(_))) {
+        return Err(LocalError::Syntax(lhs.to_string()));
+    }
+    let from = lookup_unit(&from_name).ok_or(LocalError::UnknownUnit(from_name))?;
+    let to = lookup_unit(rhs).ok_or_else(|| LocalError::UnknownUnit(rhs.to_string()))?;
+    Ok(Query::Units(tokens, from, to))
+}
+
+/// Tokenize text that must be a pure expression (no words).
+fn expression_tokens(text: &str) -> Result<Vec<Token>, LocalError> {
+    let tokens = tokenize(text)?;
+    if tokens.iter().any(|t| matches!(t, Token::Word(_))) {
+        return Err(LocalError::Syntax(text.to_string()));
+    }
+    Ok(tokens)
+}
+
+// ============================================================================
+// Tokens and expression evaluation
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+impl Number {
+    fn as_f64(self) -> f64 {
+        match self {
+            Self::Int(n) => n as f64,
+            Self::Float(x) => x,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(Number),
+    Word(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+    LParen,
+    RParen,
+}
+
+impl Token {
+    fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            Self::Plus | Self::Minus | Self::Star | Self::Slash | Self::Percent | Self::Caret
+        )
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Num(n) => write!(f, "{}", format_number(*n)),
+            Self::Word(w) => write!(f, "'{}'", w),
+            Self::Plus => write!(f, "'+'"),
+            Self::Minus => write!(f, "'-'"),
+            Self::Star => write!(f, "'*'"),
+            Self::Slash => write!(f, "'/'"),
+            Self::Percent => write!(f, "'%'"),
+            Self::Caret => write!(f, "'^'"),
+            Self::LParen => write!(f, "'('"),
+            Self::RParen => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, LocalError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '+' => Token::Plus,
+            '-' | '−' => Token::Minus,
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 1;
+                Token::Caret
+            }
+            '*' | '×' => Token::Star,
+            '/' | '÷' => Token::Slash,
+            '%' => Token::Percent,
+            '^' => Token::Caret,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = i;
+                let (number, end) = lex_number(&chars, start)?;
+                i = end;
+                tokens.push(Token::Num(number));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '°' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphabetic() || chars[i] == '°') {
+                    i += 1;
+                }
+                tokens.push(Token::Word(chars[start..i].iter().collect()));
+                continue;
+            }
+            other => return Err(LocalError::Syntax(format!("unexpected '{}'", other))),
+        };
+        tokens.push(token);
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// Lex a number literal starting at `start`; returns the value and end index.
+fn lex_number(chars: &[char], start: usize) -> Result<(Number, usize), LocalError> {
+    // Prefixed integer literals
+    if chars[start] == '0'
+        && let Some(radix) = chars.get(start + 1).and_then(|c| match c.to_ascii_lowercase() {
+            'x' => Some(16),
+            'b' => Some(2),
+            'o' => Some(8),
+            _ => None,
+        })
+    {
+        let mut end = start + 2;
+        while end < chars.len() && (chars[end].is_digit(radix) || chars[end] == '_') {
+            end += 1;
+        }
+        let digits: String = chars[start + 2..end].iter().filter(|c| **c != '_').collect();
+        if digits.is_empty() {
+            return Err(LocalError::Syntax("incomplete literal".to_string()));
+        }
+        return parse_int(&digits, radix).map(|n| (Number::Int(n), end));
+    }
+
+    let mut end = start;
+    let mut is_float = false;
+    while end < chars.len() {
+        let c = chars[end];
+        if c.is_ascii_digit() || c == '_' {
+            end += 1;
+        } else if c == '.' && !is_float {
+            is_float = true;
+            end += 1;
+        } else if (c == 'e' || c == 'E') && {
+            let sign = usize::from(matches!(chars.get(end + 1), Some('+' | '-')));
+            chars.get(end + 1 + sign).is_some_and(|n| n.is_ascii_digit())
+        } {
+            is_float = true;
+            end += if matches!(chars.get(end + 1), Some('+' | '-')) { 2 } else { 1 };
+            while end < chars.len() && chars[end].is_ascii_digit() {
+                end += 1;
+            }
+            break;
+        } else {
+            break;
+        }
+    }
+
+    let literal: String = chars[start..end].iter().filter(|c| **c != '_').collect();
+    if is_float {
+        let value: f64 = literal
+            .parse()
+            .map_err(|_| LocalError::Syntax(format!("invalid number '{}'", literal)))?;
+        if !value.is_finite() {
+            return Err(LocalError::Overflow);
+        }
+        Ok((Number::Float(value), end))
+    } else {
+        parse_int(&literal, 10).map(|n| (Number::Int(n), end))
+    }
+}
+
+fn parse_int(digits: &str, radix: u32) -> Result<i128, LocalError> {
+    i128::from_str_radix(digits, radix).map_err(|e| match e.kind() {
+        std::num::IntErrorKind::PosOverflow | std::num::IntErrorKind::NegOverflow => {
+            LocalError::Overflow
+        }
+        _ => LocalError::Syntax(format!("invalid number '{}'", digits)),
+    })
+}
+
+/// Evaluate a full token list as one expression.
+fn eval_tokens(tokens: &[Token]) -> Result<Number, LocalError> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let value = parser.expr()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(token) => Err(LocalError::Syntax(format!("unexpected {}", token))),
+    }
+}
+
+/// Recursive-descent parser that evaluates as it goes.
+///
+/// ```text
+/// expr  := term (('+' | '-') term)*
+/// term  := unary (('*' | '/' | '%') unary)*
+/// unary := ('-' | '+') unary | power
+/// power := atom ('^' unary)?        (right associative, binds tighter than unary minus)
+/// atom  := number | '(' expr ')'
+/// ```
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn advance(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn expr(&mut self) -> Result<Number, LocalError> {
+        let mut value = self.term()?;
+        while let Some(op) = self.peek().filter(|t| matches!(t, Token::Plus | Token::Minus)).cloned() {
+            self.pos += 1;
+            let rhs = self.term()?;
+            value = apply(&op, value, rhs)?;
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<Number, LocalError> {
+        let mut value = self.unary()?;
+        while let Some(op) = self
+            .peek()
+            .filter(|t| matches!(t, Token::Star | Token::Slash | Token::Percent))
+            .cloned()
+        {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            value = apply(&op, value, rhs)?;
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<Number, LocalError> {
+        match self.peek() {
+            Some(Token::Minus) => {
+                self.pos += 1;
+                match self.unary()? {
+                    Number::Int(n) => n.checked_neg().map(Number::Int).ok_or(LocalError::Overflow),
+                    Number::Float(x) => Ok(Number::Float(-x)),
+                }
+            }
+            Some(Token::Plus) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Result<Number, LocalError> {
+        let base = self.atom()?;
+        if self.peek() == Some(&Token::Caret) {
+            self.pos += 1;
+            let exponent = self.unary()?;
+            return apply(&Token::Caret, base, exponent);
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Number, LocalError> {
+        match self.advance() {
+            Some(Token::Num(n)) => Ok(*n),
+            Some(Token::LParen) => {
+                let value = self.expr()?;
+                match self.advance() {
+                    Some(Token::RParen) => Ok(value),
+                    _ => Err(LocalError::Syntax("missing ')'".to_string())),
+                }
+            }
+            Some(token) => Err(LocalError::Syntax(format!("unexpected {}", token))),
+            None => Err(LocalError::Syntax("unexpected end of input".to_string())),
+        }
+    }
+}
+
+/// Apply a binary operator, keeping integers exact where possible.
+fn apply(op: &Token, lhs: Number, rhs: Number) -> Result<Number, LocalError> {
+    if let (Number::Int(a), Number::Int(b)) = (lhs, rhs) {
+        let result = match op {
+            Token::Plus => a.checked_add(b),
+            Token::Minus => a.checked_sub(b),
+            Token::Star => a.checked_mul(b),
+            Token::Slash if b == 0 => return Err(LocalError::DivisionByZero),
+            Token::Slash if a.checked_rem(b).is_some_and(|r| r != 0) => return apply(op, Number::Float(a as f64), rhs),
+            Token::Slash => a.checked_div(b),
+            Token::Percent if b == 0 => return Err(LocalError::DivisionByZero),
+            Token::Percent => a.checked_rem(b),
+            Token::Caret if b < 0 => return apply(op, Number::Float(a as f64), rhs),
+            Token::Caret => u32::try_from(b).ok().and_then(|exp| a.checked_pow(exp)),
+            _ => return Err(LocalError::Syntax(format!("unexpected {}", op))),
+        };
+        return result.map(Number::Int).ok_or(LocalError::Overflow);
+    }
+
+    let (a, b) = (lhs.as_f64(), rhs.as_f64());
+    let result = match op {
+        Token::Plus => a + b,
+        Token::Minus => a - b,
+        Token::Star => a * b,
+        Token::Slash | Token::Percent if b == 0.0 => return Err(LocalError::DivisionByZero),
+        Token::Slash => a / b,
+        Token::Percent => a % b,
+        Token::Caret => a.powf(b),
+        _ => return Err(LocalError::Syntax(format!("unexpected {}", op))),
+    };
+    if result.is_nan() {
+        return Err(LocalError::Syntax("result is not a number".to_string()));
+    }
+    if !result.is_finite() {
+        return Err(LocalError::Overflow);
+    }
+    Ok(Number::Float(result))
+}
+
+// ============================================================================
+// Formatting and conversions
+// ============================================================================
+
+/// Format a number without float noise (`0.1 + 0.2` prints `0.3`).
+fn format_number(value: Number) -> String {
+    match value {
+        Number::Int(n) => n.to_string(),
+        Number::Float(x) => {
+            let magnitude = x.abs();
+            if magnitude != 0.0 && !(1e-9..1e15).contains(&magnitude) {
+                return format!("{:e}", x);
+            }
+            let text = format!("{:.10}", x);
+            let text = text.trim_end_matches('0').trim_end_matches('.');
+            if text == "-0" { "0".to_string() } else { text.to_string() }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Base {
+    Binary,
+    Octal,
+    Decimal,
+    Hex,
+}
+
+impl Base {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bin" | "binary" => Some(Self::Binary),
+            "oct" | "octal" => Some(Self::Octal),
+            "dec" | "decimal" => Some(Self::Decimal),
+            "hex" | "hexadecimal" => Some(Self::Hex),
+            _ => None,
+        }
+    }
+}
+
+fn format_base(value: Number, base: Base) -> Result<String, LocalError> {
+    let n = match value {
+        Number::Int(n) => n,
+        Number::Float(x) if x.fract() == 0.0 && x.abs() < i128::MAX as f64 => x as i128,
+        Number::Float(_) => return Err(LocalError::NotAnInteger),
+    };
+    let sign = if n < 0 { "-" } else { "" };
+    let abs = n.unsigned_abs();
+    Ok(match base {
+        Base::Binary => format!("{}0b{:b}", sign, abs),
+        Base::Octal => format!("{}0o{:o}", sign, abs),
+        Base::Decimal => n.to_string(),
+        Base::Hex => format!("{}0x{:x}", sign, abs),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Time,
+    Length,
+    Mass,
+    Data,
+    Temperature,
+}
+
+/// A unit: `value * factor + offset` gives the value in the dimension's base unit.
+#[derive(Debug)]
+struct Unit {
+    names: &'static [&'static str],
+    symbol: &'static str,
+    dimension: Dimension,
+    factor: f64,
+    offset: f64,
+}
+
+const fn unit(names: &'static [&'static str], symbol: &'static str, dimension: Dimension, factor: f64) -> Unit {
+    Unit { names, symbol, dimension, factor, offset: 0.0 }
+}
+
+/// Known units; names are matched case-insensitively.
+/// Base units: seconds, meters, grams, bytes, kelvin.
+static UNITS: &[Unit] = &[
+    unit(&["ns", "nanosecond", "nanoseconds"], "ns", Dimension::Time, 1e-9),
+    unit(&["us", "µs", "microsecond", "microseconds"], "µs", Dimension::Time, 1e-6),
+    unit(&["ms", "millisecond", "milliseconds"], "ms", Dimension::Time, 1e-3),
+    unit(&["s", "sec", "secs", "second", "seconds"], "s", Dimension::Time, 1.0),
+    unit(&["min", "mins", "minute", "minutes"], "min", Dimension::Time, 60.0),
+    unit(&["h", "hr", "hrs", "hour", "hours"], "h", Dimension::Time, 3600.0),
+    unit(&["d", "day", "days"], "d", Dimension::Time, 86_400.0),
+    unit(&["w", "wk", "wks", "week", "weeks"], "wk", Dimension::Time, 604_800.0),
+    unit(&["mm", "millimeter", "millimeters", "millimetre", "millimetres"], "mm", Dimension::Length, 1e-3),
+    unit(&["cm", "centimeter", "centimeters", "centimetre", "centimetres"], "cm", Dimension::Length, 1e-2),
+    unit(&["m", "meter", "meters", "metre", "metres"], "m", Dimension::Length, 1.0),
+    unit(&["km", "kilometer", "kilometers", "kilometre", "kilometres"], "km", Dimension::Length, 1e3),
+    unit(&["inch", "inches"], "in", Dimension::Length, 0.0254),
+    unit(&["ft", "foot", "feet"], "ft", Dimension::Length, 0.3048),
+    unit(&["yd", "yard", "yards"], "yd", Dimension::Length, 0.9144),
+    unit(&["mi", "mile", "miles"], "mi", Dimension::Length, 1609.344),
+    unit(&["mg", "milligram", "milligrams"], "mg", Dimension::Mass, 1e-3),
+    unit(&["g", "gram", "grams"], "g", Dimension::Mass, 1.0),
+    unit(&["kg", "kilogram", "kilograms"], "kg", Dimension::Mass, 1e3),
+    unit(&["oz", "ounce", "ounces"], "oz", Dimension::Mass, 28.349_523_125),
+    unit(&["lb", "lbs", "pound", "pounds"], "lb", Dimension::Mass, 453.592_37),
+    unit(&["b", "byte", "bytes"], "B", Dimension::Data, 1.0),
+    unit(&["kb", "kilobyte", "kilobytes"], "KB", Dimension::Data, 1e3),
+    unit(&["mb", "megabyte", "megabytes"], "MB", Dimension::Data, 1e6),
+    unit(&["gb", "gigabyte", "gigabytes"], "GB", Dimension::Data, 1e9),
+    unit(&["tb", "terabyte", "terabytes"], "TB", Dimension::Data, 1e12),
+    unit(&["kib", "kibibyte", "kibibytes"], "KiB", Dimension::Data, 1024.0),
+    unit(&["mib", "mebibyte", "mebibytes"], "MiB", Dimension::Data, 1_048_576.0),
+    unit(&["gib", "gibibyte", "gibibytes"], "GiB", Dimension::Data, 1_073_741_824.0),
+    unit(&["tib", "tebibyte", "tebibytes"], "TiB", Dimension::Data, 1_099_511_627_776.0),
+    unit(&["k", "kelvin"], "K", Dimension::Temperature, 1.0),
+    Unit {
+        names: &["c", "°c", "celsius"],
+        symbol: "°C",
+        dimension: Dimension::Temperature,
+        factor: 1.0,
+        offset: 273.15,
+    },
+    Unit {
+        names: &["f", "°f", "fahrenheit"],
+        symbol: "°F",
+        dimension: Dimension::Temperature,
+        factor: 5.0 / 9.0,
+        offset: 459.67 * 5.0 / 9.0,
+    },
+];
+
+fn lookup_unit(name: &str) -> Option<&'static Unit> {
+    let name = name.trim().to_lowercase();
+    UNITS.iter().find(|u| u.names.contains(&name.as_str()))
+}
+
+fn convert(value: f64, from: &Unit, to: &Unit) -> Result<f64, LocalError> {
+    if from.dimension != to.dimension {
+        return Err(LocalError::IncompatibleUnits(from.symbol.to_string(), to.symbol.to_string()));
+    }
+    let base = value * from.factor + from.offset;
+    let result = (base - to.offset) / to.factor;
+    if !result.is_finite() {
+        return Err(LocalError::Overflow);
+    }
+    // Offsets cancelling out (32 °F in °C) leave rounding noise instead of 0
+    let scale = (value * from.factor).abs().max(from.offset.abs());
+    if (result * to.factor).abs() < scale * 1e-12 {
+        return Ok(0.0);
+    }
+    Ok(result)
+}
+
+/// Format a unix timestamp (seconds, or milliseconds when very large).
+fn format_timestamp(stamp: i128) -> Result<String, LocalError> {
+    let (millis, precise) = if stamp.abs() >= MILLIS_THRESHOLD {
+        (stamp, true)
+    } else {
+        (stamp.checked_mul(1000).ok_or(LocalError::InvalidTimestamp)?, false)
+    };
+    let millis = i64::try_from(millis).map_err(|_| LocalError::InvalidTimestamp)?;
+    let utc = DateTime::<Utc>::from_timestamp_millis(millis).ok_or(LocalError::InvalidTimestamp)?;
+    let pattern = if precise { "%Y-%m-%d %H:%M:%S%.3f" } else { "%Y-%m-%d %H:%M:%S" };
+    let local = utc.with_timezone(&Local);
+    Ok(format!(
+        "{} UTC ({} {})",
+        utc.format(pattern),
+        local.format(pattern),
+        local.format("%Z")
+    ))
+}
+
+/// Parse "now", RFC 3339, "YYYY-MM-DD HH:MM:SS" or "YYYY-MM-DD" (UTC) to unix seconds.
+fn parse_date(text: &str) -> Result<i64, LocalError> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("now") {
+        return Ok(Utc::now().timestamp());
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Ok(date.timestamp());
+    }
+    for pattern in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(text, pattern) {
+            return Ok(date.and_utc().timestamp());
+        }
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc().timestamp())
+        .ok_or_else(|| LocalError::Syntax(format!("not a date: '{}'", text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(input: &str) -> Result<String, LocalError> {
+        evaluate(input)
+    }
+
+    #[test]
+    fn test_operator_precedence() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(eval("2 + 3 * 4")?, "14");
+        assert_eq!(eval("(2 + 3) * 4")?, "20");
+        assert_eq!(eval("10 - 4 - 3")?, "3");
+        assert_eq!(eval("2 ^ 3 ^ 2")?, "512");
+        assert_eq!(eval("-2 ^ 2")?, "-4");
+        assert_eq!(eval("2 ^ -1")?, "0.5");
+        assert_eq!(eval("2 ** 10")?, "1024");
+        assert_eq!(eval("17 % 5 * 2")?, "4");
+        assert_eq!(eval("-(3 - 5)")?, "2");
+        Ok(())
+    }
+
+    #[test]
+    fn test_integer_and_float_results() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(eval("10 / 2")?, "5");
+        assert_eq!(eval("7 / 2")?, "3.5");
+        assert_eq!(eval("0.1 + 0.2")?, "0.3");
+        assert_eq!(eval("1e3 * 2")?, "2000");
+        assert_eq!(eval("1_000_000 + 1")?, "1000001");
+        assert_eq!(eval("0x1F4 + 0b11 + 0o10")?, "511");
+        Ok(())
+    }
+
+    #[test]
+    fn test_overflow_and_division_by_zero() {
+        assert_eq!(eval("2 ^ 200"), Err(LocalError::Overflow));
+        assert_eq!(eval("170141183460469231731687303715884105727 + 1"), Err(LocalError::Overflow));
+        assert_eq!(eval("999999999999999999999999999999999999999999"), Err(LocalError::Overflow));
+        assert_eq!(eval("10.0 ^ 400"), Err(LocalError::Overflow));
+        assert_eq!(eval("1 / 0"), Err(LocalError::DivisionByZero));
+        assert_eq!(eval("5 % 0"), Err(LocalError::DivisionByZero));
+        assert_eq!(eval("1.5 / 0"), Err(LocalError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        for input in ["2 +", "(1 + 2", "1 2", "* 3", "0x", "1 $ 2", ""] {
+            assert!(matches!(eval(input), Err(LocalError::Syntax(_))), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_base_conversion() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(eval("0x1F4 in decimal")?, "500");
+        assert_eq!(eval("255 to hex")?, "0xff");
+        assert_eq!(eval("10 in binary")?, "0b1010");
+        assert_eq!(eval("0b1010 as octal")?, "0o12");
+        assert_eq!(eval("-255 in hex")?, "-0xff");
+        assert_eq!(eval("2^16 - 1 in hex")?, "0xffff");
+        assert_eq!(eval("1.5 in hex"), Err(LocalError::NotAnInteger));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unit_conversion() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(eval("what's 3 weeks in hours?")?, "504 h");
+        assert_eq!(eval("how many hours in 3 weeks")?, "504 h");
+        assert_eq!(eval("90 min to h")?, "1.5 h");
+        assert_eq!(eval("5km in miles")?, "3.1068559612 mi");
+        assert_eq!(eval("1 GiB in MB")?, "1073.741824 MB");
+        assert_eq!(eval("2 * 512 KiB in MiB")?, "1 MiB");
+        assert_eq!(eval("100 c to f")?, "212 °F");
+        assert_eq!(eval("32 °F in celsius")?, "0 °C");
+        assert_eq!(eval("0 kelvin in c")?, "-273.15 °C");
+        assert_eq!(eval("1 lb in g")?, "453.59237 g");
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_units() {
+        assert_eq!(eval("3 parsecs in m"), Err(LocalError::UnknownUnit("parsecs".to_string())));
+        assert_eq!(eval("3 m in furlongs"), Err(LocalError::UnknownUnit("furlongs".to_string())));
+        assert_eq!(
+            eval("3 hours in meters"),
+            Err(LocalError::IncompatibleUnits("h".to_string(), "m".to_string()))
+        );
+        assert!(matches!(eval("hours in meters"), Err(LocalError::Syntax(_))));
+    }
+
+    #[test]
+    fn test_timestamps() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(eval("unix timestamp 1700000000")?.starts_with("2023-11-14 22:13:20 UTC"));
+        assert!(eval("parse this unix timestamp 1700000000")?.starts_with("2023-11-14 22:13:20 UTC"));
+        assert!(eval("@0")?.starts_with("1970-01-01 00:00:00 UTC"));
+        assert!(eval("1700000000123 in utc")?.starts_with("2023-11-14 22:13:20.123 UTC"));
+        assert_eq!(eval("2023-11-14 22:13:20 to unix")?, "1700000000");
+        assert_eq!(eval("2023-11-14T22:13:20Z in unix")?, "1700000000");
+        assert_eq!(eval("1970-01-02 in epoch")?, "86400");
+        assert_eq!(eval("unix 99999999999999999999"), Err(LocalError::InvalidTimestamp));
+        Ok(())
+    }
+
+    #[test]
+    fn test_confident_inputs_are_answered() {
+        assert_eq!(try_answer("2+2"), Some(Ok("4".to_string())));
+        assert_eq!(try_answer("0x1F4 in decimal"), Some(Ok("500".to_string())));
+        assert_eq!(try_answer("what's 3 weeks in hours"), Some(Ok("504 h".to_string())));
+        assert_eq!(try_answer("1 / 0"), Some(Err(LocalError::DivisionByZero)));
+        assert!(try_answer("unix 1700000000").is_some());
+    }
+
+    #[test]
+    fn test_ambiguous_inputs_fall_through() {
+        for input in [
+            "42",
+            "0x1F4",
+            "how do I list files in a directory?",
+            "convert this video to mp4",
+            "what is a monad",
+            "5 files in bash",
+            "how to exit vim in unix",
+            "translate hello to french",
+            "",
+        ] {
+            assert_eq!(try_answer(input), None, "{input}");
+        }
+    }
+}
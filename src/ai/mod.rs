@@ -3,7 +3,20 @@
 //! This module provides functionality for communicating with AI services,
 //! managing chat sessions, parsing AI responses, and building prompts.
 
+pub mod batch;
+pub mod cache;
+pub mod chunked;
+pub mod draft;
+pub mod errors;
+pub mod export;
+pub mod local;
 pub mod prompt;
+pub mod provider;
+pub mod quotes;
+pub mod reasoning;
+pub mod sampling;
 pub mod session;
+pub mod timing;
+pub mod usage;
 
 pub use session::AiSessionManager;
@@ -0,0 +1,1041 @@
+//! Where the requests of the assistant go.
+//!
+//! A [`Provider`] takes a chat request as the session code builds it, in
+//! OpenAI's types (model, messages, tools and sampling), and streams the
+//! reply back as [`ProviderEvent`]s: text, pieces of tool calls, why the
+//! reply ended and the tokens it used. Replies read the same whichever
+//! backend wrote them, so suggestion cards, retries and the fallback for
+//! models without tools work alike for all.
+//!
+//! [`OpenAiProvider`] is the OpenAI API, or a server that speaks it (vLLM,
+//! LM Studio, a proxy) at another URL. [`OllamaProvider`] speaks Ollama's
+//! own `/api/chat`: the request is translated to its layout, and the lines
+//! of its reply (a JSON object each) back into events. Ollama sends a tool
+//! call whole, its arguments as an object and without an id, so each call
+//! gets an id here and its arguments as JSON text.
+//!
+//! [`AnthropicProvider`] speaks Claude's Messages API. The system prompts
+//! go in its top-level `system`, a tool call is a `tool_use` block of the
+//! assistant turn, and its result a `tool_result` block of the user turn
+//! after it: the tool messages answering one reply (accepted, rejected or
+//! ignored suggestions alike) become a single user turn, which the next
+//! question joins, since turns have to alternate. The streamed
+//! `text_delta` and `input_json_delta` parts become events like OpenAI's.
+//!
+//! Errors of all of them come as an [`OpenAIError`], which
+//! [`classify`](super::errors::classify) reads.
+
+// `?` turns reqwest and serde errors into an OpenAIError, so this module keeps
+// it unboxed; it is made at most once per request
+#![allow(clippy::result_large_err, reason = "OpenAIError is async-openai's error, returned as is")]
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_openai::Client;
+use async_openai::config::OpenAIConfig;
+use async_openai::error::{ApiError, OpenAIError};
+use async_openai::types::{
+    ChatChoice, ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk, ChatCompletionRequestAssistantMessageContent,
+    ChatCompletionRequestAssistantMessageContentPart, ChatCompletionRequestDeveloperMessageContent, ChatCompletionRequestMessage,
+    ChatCompletionRequestSystemMessageContent, ChatCompletionRequestSystemMessageContentPart, ChatCompletionRequestToolMessageContent,
+    ChatCompletionRequestToolMessageContentPart, ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart,
+    ChatCompletionResponseMessage, ChatCompletionStreamOptions, ChatCompletionToolType, CompletionUsage, CreateChatCompletionRequest,
+    CreateChatCompletionResponse, CreateChatCompletionStreamResponse, FinishReason, FunctionCall, FunctionCallStream, Role,
+};
+use futures::future::BoxFuture;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+/// Where Ollama listens, unless configured.
+pub const OLLAMA_URL: &str = "http://localhost:11434";
+
+/// Where Claude's API is, unless configured.
+pub const ANTHROPIC_URL: &str = "https://api.anthropic.com";
+
+/// Longest reply asked of Claude, which needs a limit, unless the request
+/// sets one
+pub const ANTHROPIC_MAX_TOKENS: u32 = 4096;
+
+/// Version of the Messages API the requests are written for
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Tool calls of Ollama replies so far, for their ids
+static OLLAMA_CALLS: AtomicU64 = AtomicU64::new(1);
+
+/// The backend requests go to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// The OpenAI API, or a server compatible with it
+    #[default]
+    OpenAI,
+    /// Ollama's own chat API
+    Ollama,
+    /// Claude, through Anthropic's Messages API
+    Anthropic,
+}
+
+/// One part of a streamed reply, as it came.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderEvent {
+    /// Text of the reply
+    pub content: Option<String>,
+    /// Pieces of tool calls, put together by their index
+    pub tool_calls: Vec<ChatCompletionMessageToolCallChunk>,
+    /// Why the reply ended, on its last part
+    pub finish_reason: Option<FinishReason>,
+    /// Tokens of the request and reply, if reported
+    pub usage: Option<CompletionUsage>,
+}
+
+/// The parts of a reply until it ends.
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<ProviderEvent, OpenAIError>> + Send>>;
+
+/// A backend that answers chat requests.
+pub trait Provider: Send + Sync {
+    /// Send `request` and stream its reply. Fails if the reply doesn't
+    /// start; an error after that comes in the stream.
+    fn stream_chat(&self, request: CreateChatCompletionRequest) -> BoxFuture<'static, Result<EventStream, OpenAIError>>;
+
+    /// Send `request` and take its reply whole.
+    fn chat(&self, request: CreateChatCompletionRequest) -> BoxFuture<'static, Result<CreateChatCompletionResponse, OpenAIError>>;
+
+    /// Names of the models the backend has.
+    fn list_models(&self) -> BoxFuture<'static, Result<Vec<String>, OpenAIError>>;
+
+    /// The environment variable the API key would come from, when the
+    /// backend needs one and has none (every request would fail).
+    fn missing_key(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+/// The provider of `kind`, at `url` and with `api_key` if given.
+pub fn from_config(kind: ProviderKind, url: Option<&str>, api_key: Option<&str>) -> Arc<dyn Provider> {
+    match kind {
+        ProviderKind::OpenAI => Arc::new(OpenAiProvider::new(url, api_key)),
+        ProviderKind::Ollama => Arc::new(OllamaProvider::new(url.unwrap_or(OLLAMA_URL))),
+        ProviderKind::Anthropic => Arc::new(AnthropicProvider::new(url.unwrap_or(ANTHROPIC_URL), api_key)),
+    }
+}
+
+impl From<CreateChatCompletionStreamResponse> for ProviderEvent {
+    fn from(part: CreateChatCompletionStreamResponse) -> Self {
+        let mut event = ProviderEvent { usage: part.usage, ..ProviderEvent::default() };
+        for choice in part.choices {
+            if let Some(content) = choice.delta.content {
+                event.content.get_or_insert_default().push_str(&content);
+            }
+            event.tool_calls.extend(choice.delta.tool_calls.unwrap_or_default());
+            event.finish_reason = choice.finish_reason.or(event.finish_reason);
+        }
+        event
+    }
+}
+
+/// The OpenAI API.
+#[derive(Debug, Clone)]
+pub struct OpenAiProvider {
+    client: Client<OpenAIConfig>,
+    /// OpenAI's own API, without a key
+    keyless: bool,
+}
+
+impl OpenAiProvider {
+    /// The API at `url` (its base, such as `http://localhost:8000/v1`), or
+    /// OpenAI's; with `api_key`, or the one in `OPENAI_API_KEY`.
+    pub fn new(url: Option<&str>, api_key: Option<&str>) -> Self {
+        let mut config = OpenAIConfig::new();
+        if let Some(url) = url {
+            config = config.with_api_base(url.trim_end_matches('/'));
+        }
+        if let Some(key) = api_key {
+            config = config.with_api_key(key);
+        }
+        // A server of our own may well take requests without one
+        let key = api_key.map(str::to_string).or_else(|| std::env::var("OPENAI_API_KEY").ok()).unwrap_or_default();
+        Self { client: Client::with_config(config), keyless: url.is_none() && key.trim().is_empty() }
+    }
+}
+
+impl Provider for OpenAiProvider {
+    fn stream_chat(&self, mut request: CreateChatCompletionRequest) -> BoxFuture<'static, Result<EventStream, OpenAIError>> {
+        let client = self.client.clone();
+        // The last part then says how many tokens the reply used
+        request.stream_options = Some(ChatCompletionStreamOptions { include_usage: true });
+        Box::pin(async move {
+            let stream = client.chat().create_stream(request).await?;
+            Ok(stream.map(|part| part.map(ProviderEvent::from)).boxed())
+        })
+    }
+
+    fn chat(&self, request: CreateChatCompletionRequest) -> BoxFuture<'static, Result<CreateChatCompletionResponse, OpenAIError>> {
+        let client = self.client.clone();
+        Box::pin(async move { client.chat().create(request).await })
+    }
+
+    fn list_models(&self) -> BoxFuture<'static, Result<Vec<String>, OpenAIError>> {
+        let client = self.client.clone();
+        Box::pin(async move { Ok(client.models().list().await?.data.into_iter().map(|m| m.id).collect()) })
+    }
+
+    fn missing_key(&self) -> Option<&'static str> {
+        self.keyless.then_some("OPENAI_API_KEY")
+    }
+}
+
+/// An Ollama server, through `/api/chat`.
+#[derive(Debug, Clone)]
+pub struct OllamaProvider {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl OllamaProvider {
+    /// The server at `url`, such as [`OLLAMA_URL`].
+    pub fn new(url: &str) -> Self {
+        Self { http: reqwest::Client::new(), url: url.trim_end_matches('/').to_string() }
+    }
+
+    /// Send `request` to `/api/chat`; fails unless the server took it.
+    fn post_chat(&self, request: &CreateChatCompletionRequest, stream: bool) -> BoxFuture<'static, Result<reqwest::Response, OpenAIError>> {
+        let sent = self.http.post(format!("{}/api/chat", self.url)).json(&ollama_request(request, stream)).send();
+        Box::pin(async move { accepted(sent.await?).await })
+    }
+}
+
+impl Provider for OllamaProvider {
+    fn stream_chat(&self, request: CreateChatCompletionRequest) -> BoxFuture<'static, Result<EventStream, OpenAIError>> {
+        let response = self.post_chat(&request, true);
+        Box::pin(async move { Ok(line_events(response.await?, OllamaReader::default(), |reader, line| Some(reader.event(line)))) })
+    }
+
+    fn chat(&self, request: CreateChatCompletionRequest) -> BoxFuture<'static, Result<CreateChatCompletionResponse, OpenAIError>> {
+        let response = self.post_chat(&request, false);
+        let model = request.model;
+        Box::pin(async move {
+            let text = response.await?.text().await?;
+            let event = OllamaReader::default().event(text.trim())?;
+            Ok(whole_response(model, event))
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'static, Result<Vec<String>, OpenAIError>> {
+        #[derive(Deserialize)]
+        struct Tags {
+            models: Vec<Tag>,
+        }
+        #[derive(Deserialize)]
+        struct Tag {
+            name: String,
+        }
+        let sent = self.http.get(format!("{}/api/tags", self.url)).send();
+        Box::pin(async move {
+            let text = accepted(sent.await?).await?.text().await?;
+            let tags: Tags = serde_json::from_str(&text).map_err(|e| OpenAIError::JSONDeserialize(e, text))?;
+            Ok(tags.models.into_iter().map(|tag| tag.name).collect())
+        })
+    }
+}
+
+/// `response`, if its status is a success; otherwise the error its body
+/// gives: `{"error": "..."}` from Ollama, `{"error": {"type": ..., "message":
+/// ...}}` from Claude.
+async fn accepted(response: reqwest::Response) -> Result<reqwest::Response, OpenAIError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let error = serde_json::from_str::<Value>(&body).ok().and_then(|value| value.get("error").cloned());
+    let (message, kind) = match &error {
+        Some(Value::String(message)) => (message.clone(), None),
+        Some(error) => (
+            error.get("message").and_then(Value::as_str).map_or_else(|| error.to_string(), str::to_string),
+            error.get("type").and_then(Value::as_str),
+        ),
+        None => (format!("{} {}", status, body.trim()), None),
+    };
+    // Codes the OpenAI API would give, so the same failures are retried,
+    // and a missing model offers the ones there are
+    let code = match status.as_u16() {
+        404 => Some("model_not_found"),
+        429 => Some("rate_limit_exceeded"),
+        500..=599 => Some("server_error"),
+        _ => None,
+    };
+    Err(api_error(message, kind, code))
+}
+
+fn api_error(message: String, kind: Option<&str>, code: Option<&str>) -> OpenAIError {
+    OpenAIError::ApiError(ApiError { message, r#type: kind.map(str::to_string), param: None, code: code.map(str::to_string) })
+}
+
+/// The events of a reply that comes a line at a time, each line read by
+/// `read` (None = the line says nothing).
+fn line_events<R: Send + 'static>(
+    response: reqwest::Response,
+    reader: R,
+    read: fn(&mut R, &str) -> Option<Result<ProviderEvent, OpenAIError>>,
+) -> EventStream {
+    response
+        .bytes_stream()
+        .scan((LineBuffer::default(), reader), move |(lines, reader), bytes| {
+            let events: Vec<_> = match bytes {
+                Ok(bytes) => lines.push(&bytes).iter().filter_map(|line| read(reader, line)).collect(),
+                Err(e) => vec![Err(OpenAIError::Reqwest(e))],
+            };
+            futures::future::ready(Some(futures::stream::iter(events)))
+        })
+        .flatten()
+        .boxed()
+}
+
+/// Cuts a body into lines as its bytes come.
+#[derive(Debug, Default)]
+struct LineBuffer {
+    /// Start of a line still to come whole
+    partial: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// The lines `bytes` completes, trimmed, blank ones left out; the rest
+    /// waits for the next bytes.
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.partial.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                lines.push(line.trim().to_string());
+            }
+        }
+        lines
+    }
+}
+
+/// The text of `msg`, its parts one per line; the refusal of an assistant
+/// message counts, images and audio don't.
+fn message_text(msg: &ChatCompletionRequestMessage) -> String {
+    match msg {
+        ChatCompletionRequestMessage::Developer(msg) => match &msg.content {
+            ChatCompletionRequestDeveloperMessageContent::Text(text) => text.clone(),
+            ChatCompletionRequestDeveloperMessageContent::Array(parts) => joined(parts.iter().map(|part| part.text.as_str())),
+        },
+        ChatCompletionRequestMessage::System(msg) => match &msg.content {
+            ChatCompletionRequestSystemMessageContent::Text(text) => text.clone(),
+            ChatCompletionRequestSystemMessageContent::Array(parts) => joined(parts.iter().map(|part| match part {
+                ChatCompletionRequestSystemMessageContentPart::Text(part) => part.text.as_str(),
+            })),
+        },
+        ChatCompletionRequestMessage::User(msg) => match &msg.content {
+            ChatCompletionRequestUserMessageContent::Text(text) => text.clone(),
+            ChatCompletionRequestUserMessageContent::Array(parts) => joined(parts.iter().filter_map(|part| match part {
+                ChatCompletionRequestUserMessageContentPart::Text(part) => Some(part.text.as_str()),
+                _ => None,
+            })),
+        },
+        ChatCompletionRequestMessage::Assistant(msg) => match &msg.content {
+            Some(ChatCompletionRequestAssistantMessageContent::Text(text)) => text.clone(),
+            Some(ChatCompletionRequestAssistantMessageContent::Array(parts)) => joined(parts.iter().map(|part| match part {
+                ChatCompletionRequestAssistantMessageContentPart::Text(part) => part.text.as_str(),
+                ChatCompletionRequestAssistantMessageContentPart::Refusal(part) => part.refusal.as_str(),
+            })),
+            None => String::new(),
+        },
+        ChatCompletionRequestMessage::Tool(msg) => match &msg.content {
+            ChatCompletionRequestToolMessageContent::Text(text) => text.clone(),
+            ChatCompletionRequestToolMessageContent::Array(parts) => joined(parts.iter().map(|part| match part {
+                ChatCompletionRequestToolMessageContentPart::Text(part) => part.text.as_str(),
+            })),
+        },
+        ChatCompletionRequestMessage::Function(msg) => msg.content.clone().unwrap_or_default(),
+    }
+}
+
+/// The arguments of `call` as an object, where OpenAI takes the JSON text
+/// of one.
+fn call_arguments(call: &ChatCompletionMessageToolCall) -> Value {
+    serde_json::from_str::<Value>(&call.function.arguments).unwrap_or_else(|_| json!({}))
+}
+
+/// The body of an `/api/chat` request asking what `request` does.
+fn ollama_request(request: &CreateChatCompletionRequest, stream: bool) -> Value {
+    // Tools called so far by id, named in the messages with their results
+    let mut called: HashMap<&str, &str> = HashMap::new();
+    let mut messages = Vec::with_capacity(request.messages.len());
+    for msg in &request.messages {
+        let text = message_text(msg);
+        let message = match msg {
+            ChatCompletionRequestMessage::Developer(_) | ChatCompletionRequestMessage::System(_) => {
+                json!({"role": "system", "content": text})
+            }
+            ChatCompletionRequestMessage::User(_) => json!({"role": "user", "content": text}),
+            ChatCompletionRequestMessage::Assistant(msg) => {
+                let mut message = json!({"role": "assistant", "content": text});
+                let calls: Vec<Value> = msg
+                    .tool_calls
+                    .iter()
+                    .flatten()
+                    .map(|call| {
+                        called.insert(&call.id, &call.function.name);
+                        json!({"function": {"name": call.function.name, "arguments": call_arguments(call)}})
+                    })
+                    .collect();
+                if !calls.is_empty() {
+                    message["tool_calls"] = Value::Array(calls);
+                }
+                message
+            }
+            ChatCompletionRequestMessage::Tool(msg) => {
+                json!({"role": "tool", "content": text, "tool_name": called.get(msg.tool_call_id.as_str())})
+            }
+            ChatCompletionRequestMessage::Function(msg) => json!({"role": "tool", "content": text, "tool_name": msg.name}),
+        };
+        messages.push(message);
+    }
+
+    let mut body = json!({"model": request.model, "messages": messages, "stream": stream});
+    if let Some(tools) = &request.tools {
+        body["tools"] = json!(tools);
+    }
+    if let Some(temperature) = request.temperature {
+        body["options"] = json!({"temperature": temperature});
+    }
+    body
+}
+
+fn joined<'a>(texts: impl Iterator<Item = &'a str>) -> String {
+    texts.collect::<Vec<_>>().join("\n")
+}
+
+/// One line of an Ollama reply.
+#[derive(Debug, Deserialize)]
+struct OllamaReply {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+    #[serde(default)]
+    eval_count: Option<u32>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaMessage {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunction,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaFunction {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Reads the lines of an Ollama reply into events.
+#[derive(Debug, Default)]
+struct OllamaReader {
+    /// Tool calls read so far, for the index of the next
+    calls: u32,
+}
+
+impl OllamaReader {
+    /// The event of one line.
+    fn event(&mut self, line: &str) -> Result<ProviderEvent, OpenAIError> {
+        let reply: OllamaReply =
+            serde_json::from_str(line).map_err(|e| OpenAIError::JSONDeserialize(e, line.to_string()))?;
+        if let Some(error) = reply.error {
+            return Err(api_error(error, None, None));
+        }
+        let mut event = ProviderEvent::default();
+        if let Some(message) = reply.message {
+            event.content = Some(message.content).filter(|text| !text.is_empty());
+            for call in message.tool_calls {
+                let id = format!("ollama_{}", OLLAMA_CALLS.fetch_add(1, Ordering::Relaxed));
+                let arguments = match call.function.arguments {
+                    Value::String(text) => text,
+                    arguments => arguments.to_string(),
+                };
+                event.tool_calls.push(tool_call_chunk(self.calls, Some(id), Some(call.function.name), Some(arguments)));
+                self.calls += 1;
+            }
+        }
+        if reply.done {
+            event.finish_reason = Some(match reply.done_reason.as_deref() {
+                Some("length") => FinishReason::Length,
+                _ if self.calls > 0 => FinishReason::ToolCalls,
+                _ => FinishReason::Stop,
+            });
+            if reply.prompt_eval_count.is_some() || reply.eval_count.is_some() {
+                event.usage = Some(usage(reply.prompt_eval_count.unwrap_or(0), reply.eval_count.unwrap_or(0)));
+            }
+        }
+        Ok(event)
+    }
+}
+
+fn usage(prompt_tokens: u32, completion_tokens: u32) -> CompletionUsage {
+    CompletionUsage {
+        prompt_tokens,
+        completion_tokens,
+        total_tokens: prompt_tokens + completion_tokens,
+        prompt_tokens_details: None,
+        completion_tokens_details: None,
+    }
+}
+
+/// A reply that came whole as one event, in the shape of OpenAI's.
+fn whole_response(model: String, event: ProviderEvent) -> CreateChatCompletionResponse {
+    let tool_calls: Vec<ChatCompletionMessageToolCall> = event
+        .tool_calls
+        .into_iter()
+        .filter_map(|chunk| {
+            let function = chunk.function?;
+            Some(ChatCompletionMessageToolCall {
+                id: chunk.id?,
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionCall { name: function.name?, arguments: function.arguments.unwrap_or_default() },
+            })
+        })
+        .collect();
+    #[allow(deprecated)]
+    let message = ChatCompletionResponseMessage {
+        content: event.content,
+        refusal: None,
+        tool_calls: Some(tool_calls).filter(|calls| !calls.is_empty()),
+        role: Role::Assistant,
+        function_call: None,
+        audio: None,
+    };
+    CreateChatCompletionResponse {
+        id: String::new(),
+        choices: vec![ChatChoice { index: 0, message, finish_reason: event.finish_reason, logprobs: None }],
+        created: 0,
+        model,
+        service_tier: None,
+        system_fingerprint: None,
+        object: "chat.completion".to_string(),
+        usage: event.usage,
+    }
+}
+
+/// Claude, through the Messages API.
+#[derive(Clone)]
+pub struct AnthropicProvider {
+    http: reqwest::Client,
+    url: String,
+    api_key: String,
+}
+
+impl AnthropicProvider {
+    /// The API at `url`, such as [`ANTHROPIC_URL`]; with `api_key`, or the
+    /// one in `ANTHROPIC_API_KEY`.
+    pub fn new(url: &str, api_key: Option<&str>) -> Self {
+        let api_key = api_key.map(str::to_string).or_else(|| std::env::var("ANTHROPIC_API_KEY").ok()).unwrap_or_default();
+        Self { http: reqwest::Client::new(), url: url.trim_end_matches('/').to_string(), api_key }
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.http
+            .get(format!("{}{}", self.url, path))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    /// Send `request` to `/v1/messages`; fails unless the API took it.
+    fn post_messages(&self, request: &CreateChatCompletionRequest, stream: bool) -> BoxFuture<'static, Result<reqwest::Response, OpenAIError>> {
+        let sent = self
+            .http
+            .post(format!("{}/v1/messages", self.url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&anthropic_request(request, stream))
+            .send();
+        Box::pin(async move { accepted(sent.await?).await })
+    }
+}
+
+impl Provider for AnthropicProvider {
+    fn stream_chat(&self, request: CreateChatCompletionRequest) -> BoxFuture<'static, Result<EventStream, OpenAIError>> {
+        let response = self.post_messages(&request, true);
+        Box::pin(async move { Ok(line_events(response.await?, AnthropicReader::default(), AnthropicReader::line)) })
+    }
+
+    fn chat(&self, request: CreateChatCompletionRequest) -> BoxFuture<'static, Result<CreateChatCompletionResponse, OpenAIError>> {
+        let response = self.post_messages(&request, false);
+        let model = request.model;
+        Box::pin(async move {
+            let text = response.await?.text().await?;
+            let message: ClaudeMessage = serde_json::from_str(&text).map_err(|e| OpenAIError::JSONDeserialize(e, text))?;
+            Ok(whole_response(model, message.into_event()))
+        })
+    }
+
+    fn list_models(&self) -> BoxFuture<'static, Result<Vec<String>, OpenAIError>> {
+        #[derive(Deserialize)]
+        struct Models {
+            data: Vec<Model>,
+        }
+        #[derive(Deserialize)]
+        struct Model {
+            id: String,
+        }
+        let sent = self.get("/v1/models").send();
+        Box::pin(async move {
+            let text = accepted(sent.await?).await?.text().await?;
+            let models: Models = serde_json::from_str(&text).map_err(|e| OpenAIError::JSONDeserialize(e, text))?;
+            Ok(models.data.into_iter().map(|model| model.id).collect())
+        })
+    }
+
+    fn missing_key(&self) -> Option<&'static str> {
+        self.api_key.trim().is_empty().then_some("ANTHROPIC_API_KEY")
+    }
+}
+
+/// The body of a `/v1/messages` request asking what `request` does. The
+/// system messages, wherever they are, make up `system`; each tool message
+/// becomes a `tool_result` block, and turns of the same role one turn.
+pub(crate) fn anthropic_request(request: &CreateChatCompletionRequest, stream: bool) -> Value {
+    let mut system = Vec::new();
+    let mut turns: Vec<(&str, Vec<Value>)> = Vec::new();
+    for msg in &request.messages {
+        let text = message_text(msg);
+        let (role, blocks) = match msg {
+            ChatCompletionRequestMessage::Developer(_) | ChatCompletionRequestMessage::System(_) => {
+                system.push(text);
+                continue;
+            }
+            ChatCompletionRequestMessage::User(_) | ChatCompletionRequestMessage::Function(_) => ("user", text_block(text)),
+            ChatCompletionRequestMessage::Assistant(msg) => {
+                let mut blocks = text_block(text);
+                blocks.extend(msg.tool_calls.iter().flatten().map(|call| {
+                    json!({"type": "tool_use", "id": call.id, "name": call.function.name, "input": call_arguments(call)})
+                }));
+                ("assistant", blocks)
+            }
+            ChatCompletionRequestMessage::Tool(msg) => {
+                ("user", vec![json!({"type": "tool_result", "tool_use_id": msg.tool_call_id, "content": text})])
+            }
+        };
+        if blocks.is_empty() {
+            continue;
+        }
+        match turns.last_mut() {
+            Some((last, content)) if *last == role => content.extend(blocks),
+            _ => turns.push((role, blocks)),
+        }
+    }
+
+    let messages: Vec<Value> = turns.into_iter().map(|(role, content)| json!({"role": role, "content": content})).collect();
+    #[allow(deprecated)]
+    let max_tokens = request.max_completion_tokens.or(request.max_tokens).unwrap_or(ANTHROPIC_MAX_TOKENS);
+    let mut body = json!({"model": request.model, "max_tokens": max_tokens, "messages": messages, "stream": stream});
+    if !system.is_empty() {
+        body["system"] = json!(system.join("\n\n"));
+    }
+    if let Some(tools) = &request.tools {
+        let tools: Vec<Value> = tools
+            .iter()
+            .map(|tool| {
+                let schema = tool.function.parameters.clone().unwrap_or_else(|| json!({"type": "object"}));
+                json!({"name": tool.function.name, "description": tool.function.description, "input_schema": schema})
+            })
+            .collect();
+        body["tools"] = json!(tools);
+    }
+    // Claude takes temperatures up to 1
+    if let Some(temperature) = request.temperature {
+        body["temperature"] = json!(temperature.min(1.0));
+    }
+    body
+}
+
+/// `text` as a text block, none if empty (the API refuses those).
+fn text_block(text: String) -> Vec<Value> {
+    if text.trim().is_empty() { Vec::new() } else { vec![json!({"type": "text", "text": text})] }
+}
+
+/// A reply of the Messages API that came whole.
+#[derive(Debug, Deserialize)]
+struct ClaudeMessage {
+    content: Vec<ClaudeBlock>,
+    #[serde(default)]
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: ClaudeUsage,
+}
+
+impl ClaudeMessage {
+    fn into_event(self) -> ProviderEvent {
+        let mut event = ProviderEvent {
+            finish_reason: Some(claude_finish(self.stop_reason.as_deref())),
+            usage: Some(usage(self.usage.input_tokens, self.usage.output_tokens)),
+            ..ProviderEvent::default()
+        };
+        for (index, block) in self.content.into_iter().enumerate() {
+            match block {
+                ClaudeBlock::Text { text } => event.content.get_or_insert_default().push_str(&text),
+                ClaudeBlock::ToolUse { id, name, input } => {
+                    event.tool_calls.push(tool_call_chunk(index as u32, Some(id), Some(name), Some(input.to_string())))
+                }
+                ClaudeBlock::Other => {}
+            }
+        }
+        event
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        #[serde(default)]
+        input: Value,
+    },
+    /// Thinking, and blocks of later versions
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ClaudeUsage {
+    #[serde(default)]
+    input_tokens: u32,
+    #[serde(default)]
+    output_tokens: u32,
+}
+
+/// One event of a streamed reply (the `data:` of a server-sent event).
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeEvent {
+    MessageStart {
+        message: ClaudeStart,
+    },
+    ContentBlockStart {
+        index: u32,
+        content_block: ClaudeBlock,
+    },
+    ContentBlockDelta {
+        index: u32,
+        delta: ClaudeDelta,
+    },
+    MessageDelta {
+        delta: ClaudeStop,
+        #[serde(default)]
+        usage: ClaudeUsage,
+    },
+    Error {
+        error: ClaudeError,
+    },
+    /// Pings, block and message ends
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeStart {
+    #[serde(default)]
+    usage: ClaudeUsage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeDelta {
+    TextDelta {
+        text: String,
+    },
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeStop {
+    #[serde(default)]
+    stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaudeError {
+    #[serde(rename = "type")]
+    kind: String,
+    message: String,
+}
+
+/// Reads the server-sent events of a Claude reply into events.
+#[derive(Debug, Default)]
+struct AnthropicReader {
+    /// Tokens of the request, told at the start and counted at the end
+    input_tokens: u32,
+}
+
+impl AnthropicReader {
+    /// The event of one line, if it is data that says something.
+    fn line(&mut self, line: &str) -> Option<Result<ProviderEvent, OpenAIError>> {
+        let data = line.strip_prefix("data:")?.trim();
+        let event = match serde_json::from_str::<ClaudeEvent>(data) {
+            Ok(event) => event,
+            Err(e) => return Some(Err(OpenAIError::JSONDeserialize(e, data.to_string()))),
+        };
+        let mut part = ProviderEvent::default();
+        match event {
+            ClaudeEvent::MessageStart { message } => self.input_tokens = message.usage.input_tokens,
+            ClaudeEvent::ContentBlockStart { index, content_block } => match content_block {
+                ClaudeBlock::Text { text } => part.content = Some(text).filter(|text| !text.is_empty()),
+                // Its input comes in the deltas after it
+                ClaudeBlock::ToolUse { id, name, .. } => part.tool_calls.push(tool_call_chunk(index, Some(id), Some(name), None)),
+                ClaudeBlock::Other => return None,
+            },
+            ClaudeEvent::ContentBlockDelta { index, delta } => match delta {
+                ClaudeDelta::TextDelta { text } => part.content = Some(text),
+                ClaudeDelta::InputJsonDelta { partial_json } => {
+                    part.tool_calls.push(tool_call_chunk(index, None, None, Some(partial_json)))
+                }
+                ClaudeDelta::Other => return None,
+            },
+            ClaudeEvent::MessageDelta { delta, usage: used } => {
+                part.finish_reason = Some(claude_finish(delta.stop_reason.as_deref()));
+                part.usage = Some(usage(self.input_tokens.max(used.input_tokens), used.output_tokens));
+            }
+            ClaudeEvent::Error { error } => {
+                let code = (error.kind == "rate_limit_error").then_some("rate_limit_exceeded");
+                return Some(Err(api_error(error.message, Some(&error.kind), code)));
+            }
+            ClaudeEvent::Other => return None,
+        }
+        Some(Ok(part))
+    }
+}
+
+fn claude_finish(stop_reason: Option<&str>) -> FinishReason {
+    match stop_reason {
+        Some("max_tokens") => FinishReason::Length,
+        Some("tool_use") => FinishReason::ToolCalls,
+        _ => FinishReason::Stop,
+    }
+}
+
+fn tool_call_chunk(index: u32, id: Option<String>, name: Option<String>, arguments: Option<String>) -> ChatCompletionMessageToolCallChunk {
+    ChatCompletionMessageToolCallChunk {
+        index,
+        id,
+        r#type: Some(ChatCompletionToolType::Function),
+        function: Some(FunctionCallStream { name, arguments }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_openai::types::{
+        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs,
+        CreateChatCompletionRequestArgs,
+    };
+
+    fn ollama_events(lines: &mut LineBuffer, reader: &mut OllamaReader, bytes: &[u8]) -> Vec<Result<ProviderEvent, OpenAIError>> {
+        lines.push(bytes).iter().map(|line| reader.event(line)).collect()
+    }
+
+    fn claude_events(reader: &mut AnthropicReader, body: &str) -> Result<Vec<ProviderEvent>, OpenAIError> {
+        let lines = LineBuffer::default().push(body.as_bytes());
+        lines.iter().filter_map(|line| reader.line(line)).collect()
+    }
+
+    #[test]
+    fn test_ollama_lines_become_events() -> Result<(), OpenAIError> {
+        let (mut lines, mut reader) = (LineBuffer::default(), OllamaReader::default());
+        // A line split across reads waits for its end
+        let events = ollama_events(&mut lines, &mut reader, br#"{"model":"qwen2.5","message":{"role":"assistant","content":"Run "},"done":false}
+{"model":"qwen2.5","message":{"role":"assistant","con"#);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].as_ref().ok().and_then(|e| e.content.as_deref()), Some("Run "));
+        let events = ollama_events(&mut lines, &mut reader, br#"tent":"this:"},"done":false}
+{"model":"qwen2.5","message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"suggest_command","arguments":{"command":"df -h","risk_level":"low"}}}]},"done":false}
+{"model":"qwen2.5","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop","prompt_eval_count":120,"eval_count":31}
+"#);
+        let events: Vec<ProviderEvent> = events.into_iter().collect::<Result<_, _>>()?;
+        assert_eq!(events[0].content.as_deref(), Some("this:"));
+
+        let call = &events[1].tool_calls[0];
+        assert_eq!((call.index, call.id.as_ref().map(|id| id.starts_with("ollama_"))), (0, Some(true)));
+        let function = call.function.as_ref().ok_or(OpenAIError::InvalidArgument("no function".to_string()))?;
+        assert_eq!(function.name.as_deref(), Some("suggest_command"));
+        let arguments: Value = serde_json::from_str(function.arguments.as_deref().unwrap_or_default())
+            .map_err(|e| OpenAIError::JSONDeserialize(e, String::new()))?;
+        assert_eq!(arguments, json!({"command": "df -h", "risk_level": "low"}));
+
+        // A reply with calls finished with them
+        assert_eq!(events[2].content, None);
+        assert_eq!(events[2].finish_reason, Some(FinishReason::ToolCalls));
+        assert_eq!(events[2].usage.as_ref().map(|u| (u.prompt_tokens, u.completion_tokens)), Some((120, 31)));
+
+        let mut reader = OllamaReader::default();
+        let event = reader.event(r#"{"message":{"content":"tar packs"},"done":true,"done_reason":"length"}"#)?;
+        assert_eq!(event.finish_reason, Some(FinishReason::Length));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ollama_errors_are_api_errors() {
+        let failure = OllamaReader::default().event(r#"{"error":"model \"qwen9\" not found, try pulling it first"}"#).err();
+        assert!(matches!(failure, Some(OpenAIError::ApiError(api)) if api.message.starts_with("model \"qwen9\" not found")));
+        assert!(matches!(OllamaReader::default().event("<html>"), Err(OpenAIError::JSONDeserialize(..))));
+    }
+
+    #[test]
+    fn test_ollama_request_names_the_tools_called() -> Result<(), OpenAIError> {
+        let call = ChatCompletionMessageToolCall {
+            id: "call_1".to_string(),
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionCall { name: "suggest_command".to_string(), arguments: r#"{"command":"ls"}"#.to_string() },
+        };
+        let request = CreateChatCompletionRequestArgs::default()
+            .model("qwen2.5")
+            .messages(vec![
+                ChatCompletionRequestUserMessageArgs::default().content("list files").build()?.into(),
+                ChatCompletionRequestAssistantMessageArgs::default().tool_calls(vec![call]).build()?.into(),
+                ChatCompletionRequestToolMessageArgs::default().tool_call_id("call_1").content("Accepted").build()?.into(),
+            ])
+            .temperature(0.2)
+            .build()?;
+        let body = ollama_request(&request, true);
+        assert_eq!(body["model"], "qwen2.5");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["messages"][1]["tool_calls"][0]["function"]["arguments"], json!({"command": "ls"}));
+        assert_eq!(body["messages"][2], json!({"role": "tool", "content": "Accepted", "tool_name": "suggest_command"}));
+        assert_eq!(body["options"]["temperature"].as_f64().map(|t| (t * 10.0).round()), Some(2.0));
+        assert!(body.get("tools").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_whole_ollama_reply_reads_like_openai() -> Result<(), OpenAIError> {
+        let event = OllamaReader::default().event(
+            r#"{"message":{"content":"Run this:","tool_calls":[{"function":{"name":"suggest_command","arguments":{"command":"df -h"}}}]},"done":true,"done_reason":"stop"}"#,
+        )?;
+        let response = whole_response("qwen2.5".to_string(), event);
+        let choice = &response.choices[0];
+        assert_eq!(choice.message.content.as_deref(), Some("Run this:"));
+        assert_eq!(choice.finish_reason, Some(FinishReason::ToolCalls));
+        let calls = choice.message.tool_calls.as_deref().unwrap_or_default();
+        assert_eq!(calls.iter().map(|c| c.function.arguments.as_str()).collect::<Vec<_>>(), vec![r#"{"command":"df -h"}"#]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_claude_stream_becomes_events() -> Result<(), OpenAIError> {
+        let body = concat!(
+            "event: message_start\n",
+            r#"data: {"type":"message_start","message":{"id":"msg_1","role":"assistant","content":[],"usage":{"input_tokens":410,"output_tokens":1}}}"#,
+            "\n\nevent: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            "\n\nevent: ping\ndata: {\"type\": \"ping\"}\n\nevent: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Run this:"}}"#,
+            "\n\nevent: content_block_start\n",
+            r#"data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_01","name":"suggest_command","input":{}}}"#,
+            "\n\nevent: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"command\": \"df"}}"#,
+            "\n\nevent: content_block_delta\n",
+            r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":" -h\"}"}}"#,
+            "\n\nevent: content_block_stop\n",
+            r#"data: {"type":"content_block_stop","index":1}"#,
+            "\n\nevent: message_delta\n",
+            r#"data: {"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"output_tokens":42}}"#,
+            "\n\nevent: message_stop\n",
+            r#"data: {"type":"message_stop"}"#,
+            "\n",
+        );
+        let events = claude_events(&mut AnthropicReader::default(), body)?;
+        let text: String = events.iter().filter_map(|e| e.content.as_deref()).collect();
+        assert_eq!(text, "Run this:");
+
+        let chunks: Vec<_> = events.iter().flat_map(|e| &e.tool_calls).collect();
+        assert_eq!(chunks.iter().map(|c| c.index).collect::<Vec<_>>(), vec![1, 1, 1]);
+        assert_eq!(chunks[0].id.as_deref(), Some("toolu_01"));
+        let arguments: String = chunks.iter().filter_map(|c| c.function.as_ref()?.arguments.as_deref()).collect();
+        assert_eq!(arguments, r#"{"command": "df -h"}"#);
+
+        let last = events.last().ok_or(OpenAIError::InvalidArgument("no events".to_string()))?;
+        assert_eq!(last.finish_reason, Some(FinishReason::ToolCalls));
+        assert_eq!(last.usage.as_ref().map(|u| (u.prompt_tokens, u.completion_tokens)), Some((410, 42)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_key_names_its_variable() {
+        assert_eq!(AnthropicProvider::new(ANTHROPIC_URL, Some(" ")).missing_key(), Some("ANTHROPIC_API_KEY"));
+        assert_eq!(AnthropicProvider::new(ANTHROPIC_URL, Some("sk-ant-1")).missing_key(), None);
+        assert_eq!(OpenAiProvider::new(None, Some("")).missing_key(), Some("OPENAI_API_KEY"));
+        assert_eq!(OpenAiProvider::new(None, Some("sk-1")).missing_key(), None);
+        // Servers of one's own may not check keys at all
+        assert_eq!(OpenAiProvider::new(Some("http://localhost:8000/v1"), Some("")).missing_key(), None);
+        assert_eq!(OllamaProvider::new(OLLAMA_URL).missing_key(), None);
+    }
+
+    #[test]
+    fn test_claude_errors_are_api_errors() {
+        let body = "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n";
+        let failure = claude_events(&mut AnthropicReader::default(), body).err();
+        assert!(matches!(&failure, Some(OpenAIError::ApiError(api)) if api.message == "Overloaded"));
+        assert_eq!(failure.map(|e| crate::ai::errors::classify(&e)), Some(crate::ai::errors::ErrorCause::Transient));
+
+        let body = "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"max_tokens\"},\"usage\":{\"output_tokens\":4096}}\n";
+        let events = claude_events(&mut AnthropicReader::default(), body).unwrap_or_default();
+        assert_eq!(events.first().and_then(|e| e.finish_reason), Some(FinishReason::Length));
+    }
+
+    #[test]
+    fn test_whole_claude_reply_reads_like_openai() -> Result<(), serde_json::Error> {
+        let message: ClaudeMessage = serde_json::from_value(json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "content": [
+                {"type": "thinking", "thinking": "Disk usage.", "signature": "x"},
+                {"type": "text", "text": "Run this:"},
+                {"type": "tool_use", "id": "toolu_01", "name": "suggest_command", "input": {"command": "df -h"}},
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 90, "output_tokens": 30},
+        }))?;
+        let response = whole_response("claude-sonnet-4-5".to_string(), message.into_event());
+        let choice = &response.choices[0];
+        assert_eq!(choice.message.content.as_deref(), Some("Run this:"));
+        assert_eq!(choice.finish_reason, Some(FinishReason::ToolCalls));
+        let calls = choice.message.tool_calls.as_deref().unwrap_or_default();
+        assert_eq!(calls.iter().map(|c| (c.id.as_str(), c.function.arguments.as_str())).collect::<Vec<_>>(), vec![(
+            "toolu_01",
+            r#"{"command":"df -h"}"#
+        )]);
+        assert_eq!(response.usage.map(|u| u.total_tokens), Some(120));
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! Parts of a reply quoted verbatim from the context sent with its question.
+//!
+//! When a reply ends, [`find_quotes`] looks for the stretches of it that
+//! also appear byte for byte in the context its question went with (see
+//! [`sources`]): a path, a command or a line of output echoed back. The
+//! pane underlines them, so what was copied from the user's own terminal
+//! stands apart from what the model wrote. They are display only: the
+//! reply's text is unchanged, and so is everything copied from it.
+//!
+//! Every window of `min_len` bytes of the context goes in a table by its
+//! rolling hash; each window of the reply is looked up there, and a hit is
+//! extended as far as both texts agree. The context is capped at
+//! [`MAX_SOURCE_BYTES`] and the places tried per window at
+//! [`MAX_CANDIDATES`], so the work grows with the size of both texts and
+//! no more: a reply takes a few milliseconds.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::context::ContextSnapshot;
+
+/// Shortest stretch of a reply taken for a quote, unless configured.
+pub const DEFAULT_MIN_LEN: usize = 20;
+
+/// Most context looked at; the latest part is kept.
+pub const MAX_SOURCE_BYTES: usize = 256 * 1024;
+
+/// Places in the context tried for one window of the reply (repeated text
+/// repeats its windows)
+const MAX_CANDIDATES: usize = 8;
+
+/// Multiplier of the rolling hash
+const BASE: u64 = 0x0000_0100_0000_01b3;
+
+/// Goes between the pieces of context, so a match never runs from one into
+/// the next
+const SEPARATOR: char = '\0';
+
+/// The text of the context a reply can quote, as one string: the
+/// directory, recent commands and outputs, attached files and the screen.
+pub fn sources(ctx: &ContextSnapshot) -> String {
+    let pieces = std::iter::once(ctx.cwd.as_str())
+        .chain(ctx.recent_history.iter().map(String::as_str))
+        .chain(ctx.recent_output.iter().map(String::as_str))
+        .chain(ctx.recent_commands.iter().flat_map(|record| [record.command_line.as_str(), record.output.as_str()]))
+        .chain(ctx.files.iter().map(|file| file.content.as_str()))
+        .chain(ctx.screen.as_deref())
+        .filter(|piece| !piece.is_empty());
+    let mut joined = String::new();
+    for piece in pieces {
+        if !joined.is_empty() {
+            joined.push(SEPARATOR);
+        }
+        joined.push_str(piece);
+    }
+    joined
+}
+
+/// Byte ranges of `reply` that appear verbatim in `sources`, each at least
+/// `min_len` bytes long, in order. Overlapping quotes are merged into one
+/// range; quotes that merely touch stay apart. Ranges start and end on
+/// character boundaries, without whitespace at either end, and have a
+/// letter or digit in them. None when `min_len` is 0.
+pub fn find_quotes(reply: &str, sources: &str, min_len: usize) -> Vec<Range<usize>> {
+    let text = reply.as_bytes();
+    let source = sources.as_bytes();
+    let source = &source[source.len().saturating_sub(MAX_SOURCE_BYTES)..];
+    if min_len == 0 || text.len() < min_len || source.len() < min_len {
+        return Vec::new();
+    }
+
+    // Start of the latest window with each hash, and for each window the
+    // one before it with the same hash
+    let mut latest: HashMap<u64, u32> = HashMap::with_capacity(source.len() - min_len + 1);
+    let mut earlier: Vec<Option<u32>> = Vec::with_capacity(source.len() - min_len + 1);
+    for (at, hash) in window_hashes(source, min_len).enumerate() {
+        earlier.push(latest.insert(hash, at as u32));
+    }
+
+    let mut quotes: Vec<Range<usize>> = Vec::new();
+    for (at, hash) in window_hashes(text, min_len).enumerate() {
+        // A quote starting inside the last one and going on past its end
+        // covers the first window that does too: nothing is lost by
+        // looking only from there
+        if quotes.last().is_some_and(|last| at + min_len <= last.end) {
+            continue;
+        }
+        let mut candidate = latest.get(&hash).copied();
+        let mut longest = 0;
+        for _ in 0..MAX_CANDIDATES {
+            let Some(from) = candidate else {
+                break;
+            };
+            let from = from as usize;
+            let agree = text[at..].iter().zip(&source[from..]).take_while(|(a, b)| a == b).count();
+            longest = longest.max(agree);
+            candidate = earlier[from];
+        }
+        if longest < min_len {
+            continue;
+        }
+        let end = at + longest;
+        match quotes.last_mut() {
+            Some(last) if at < last.end => last.end = last.end.max(end),
+            _ => quotes.push(at..end),
+        }
+    }
+
+    quotes.into_iter().filter_map(|quote| tidy(reply, quote, min_len)).collect()
+}
+
+/// Hashes of the windows of `len` bytes of `bytes`, by where they start.
+fn window_hashes(bytes: &[u8], len: usize) -> impl Iterator<Item = u64> + '_ {
+    let step = |hash: u64, byte: u8| hash.wrapping_mul(BASE).wrapping_add(u64::from(byte));
+    // Weight of the byte that leaves the window
+    let outgoing = BASE.wrapping_pow((len - 1) as u32);
+    let first = bytes[..len].iter().fold(0, |hash, &byte| step(hash, byte));
+    (0..=bytes.len() - len).scan(None, move |hash: &mut Option<u64>, at| {
+        let next = match *hash {
+            None => first,
+            Some(prev) => step(prev.wrapping_sub(u64::from(bytes[at - 1]).wrapping_mul(outgoing)), bytes[at + len - 1]),
+        };
+        *hash = Some(next);
+        Some(next)
+    })
+}
+
+/// `quote` cut down to whole characters and trimmed; None if too little
+/// of it is left, or nothing but punctuation.
+fn tidy(reply: &str, quote: Range<usize>, min_len: usize) -> Option<Range<usize>> {
+    let mut start = quote.start;
+    while !reply.is_char_boundary(start) {
+        start += 1;
+    }
+    let mut end = quote.end;
+    while !reply.is_char_boundary(end) {
+        end -= 1;
+    }
+    let text = reply.get(start..end)?;
+    let trimmed = text.trim_start();
+    let start = start + (text.len() - trimmed.len());
+    let trimmed = trimmed.trim_end();
+    (trimmed.len() >= min_len && trimmed.chars().any(char::is_alphanumeric)).then(|| start..start + trimmed.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::CommandRecord;
+    use std::time::{Duration, Instant};
+
+    fn quoted<'a>(reply: &'a str, sources: &str, min_len: usize) -> Vec<&'a str> {
+        find_quotes(reply, sources, min_len).into_iter().map(|quote| &reply[quote]).collect()
+    }
+
+    #[test]
+    fn test_echoed_paths_and_lines_are_found() {
+        let sources = "error[E0425]: cannot find value `x` in this scope\n --> /home/me/app/src/main.rs:4:5\0cargo build";
+        let reply = "The compiler says cannot find value `x` in this scope, at /home/me/app/src/main.rs line 4.";
+        assert_eq!(
+            quoted(reply, sources, 12),
+            vec!["cannot find value `x` in this scope", "/home/me/app/src/main.rs"]
+        );
+        // Too short to count
+        assert_eq!(quoted("Run cargo build again.", sources, 12), Vec::<&str>::new());
+        assert_eq!(quoted("Run cargo build again.", sources, 0), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_overlapping_quotes_merge() {
+        // The reply runs from one piece of context on into another that
+        // shares some of its text
+        let sources = "alpha beta gamma delta\0gamma delta epsilon zeta";
+        let reply = "alpha beta gamma delta epsilon zeta";
+        assert_eq!(find_quotes(reply, sources, 10), vec![0..reply.len()]);
+    }
+
+    #[test]
+    fn test_adjacent_quotes_stay_apart() {
+        let sources = "abcdefghij\0klmnopqrst";
+        let reply = "abcdefghijklmnopqrst";
+        assert_eq!(find_quotes(reply, sources, 5), vec![0..10, 10..20]);
+        // Nor does a quote run across the pieces of context
+        assert_eq!(find_quotes("fghij klmno", sources, 8), Vec::<Range<usize>>::new());
+    }
+
+    #[test]
+    fn test_quotes_are_trimmed_whole_characters_with_words() {
+        let sources = "ls: cannot access '/tmp/日本語/ログ.txt': No such file\0------------------------";
+        let reply = "The file '/tmp/日本語/ログ.txt' is missing.\n------------------------";
+        let quotes = find_quotes(reply, sources, 10);
+        assert!(quotes.iter().all(|quote| reply.get(quote.clone()).is_some()));
+        assert_eq!(quoted(reply, sources, 10), vec!["'/tmp/日本語/ログ.txt'"]);
+        // Ending inside a character
+        assert_eq!(quoted("日本語ファイル", "日本語ファイレ", 15), vec!["日本語ファイ"]);
+    }
+
+    #[test]
+    fn test_sources_keep_the_pieces_apart() {
+        let mut ctx = ContextSnapshot::empty();
+        ctx.cwd = "/home/me/app".to_string();
+        ctx.recent_history = vec!["make".to_string()];
+        ctx.recent_commands = vec![CommandRecord {
+            command_line: "make test".to_string(),
+            output: "3 passed".to_string(),
+            exit_code: Some(0),
+            started_at: None,
+            position: None,
+        }];
+        ctx.screen = Some("$ ".to_string());
+        assert_eq!(sources(&ctx), "/home/me/app\0make\0make test\u{0}3 passed\0$ ");
+        assert_eq!(sources(&ContextSnapshot::empty()), "");
+    }
+
+    #[test]
+    fn test_large_inputs_stay_fast() {
+        // Past the cap, with runs of repeated text to crowd the table
+        let mut sources = String::new();
+        let mut line = 0u64;
+        while sources.len() < 2 * MAX_SOURCE_BYTES {
+            sources.push_str(&format!("line {} value {} {}\n", line, line.wrapping_mul(7919) % 10007, "=".repeat(40)));
+            line += 1;
+        }
+        let quoted_line = sources.lines().nth_back(10).unwrap_or_default().to_string();
+        let mut reply = String::new();
+        while reply.len() < 64 * 1024 {
+            reply.push_str(&format!("A new sentence {} the model wrote. {}\n", reply.len(), "=".repeat(30)));
+            reply.push_str(&quoted_line);
+            reply.push('\n');
+        }
+
+        let start = Instant::now();
+        let quotes = find_quotes(&reply, &sources, 16);
+        let elapsed = start.elapsed();
+        assert!(quotes.iter().any(|quote| reply[quote.clone()].contains(&quoted_line)));
+        // Generous for a debug build; a release build takes milliseconds
+        assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+
+        let repeated = "a".repeat(MAX_SOURCE_BYTES);
+        let start = Instant::now();
+        assert_eq!(find_quotes(&"a".repeat(32 * 1024), &repeated, 16), vec![0..32 * 1024]);
+        assert!(start.elapsed() < Duration::from_secs(2), "took {:?}", start.elapsed());
+    }
+}
@@ -0,0 +1,206 @@
+//! Reasoning ("thinking") streamed by some models before their answer.
+//!
+//! Reasoning reaches the session as `AiStreamData::Reasoning`, apart from
+//! the answer's chunks, whether a backend streams it on a channel of its own
+//! or inline, as a `<think>…</think>` block at the start of the text. The
+//! [`ThinkSplitter`] takes such a block out of the streamed text, whatever
+//! chunks the tags are cut across, so only the answer reaches the reply
+//! (and the suggestion parser). Reasoning is shown apart and never sent
+//! back to the API with the history.
+
+/// Opens an inline reasoning block
+pub const OPEN_TAG: &str = "<think>";
+
+/// Closes it
+pub const CLOSE_TAG: &str = "</think>";
+
+/// What a chunk of reply text holds, once the reasoning is taken out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Split {
+    pub reasoning: String,
+    pub answer: String,
+}
+
+/// Splits streamed reply text into reasoning and answer.
+///
+/// A `<think>` block is only recognized before the answer starts (leading
+/// whitespace aside), so a tag quoted further down stays in the answer.
+/// Text that may be the start of a tag is held back until the next chunk
+/// tells.
+#[derive(Debug, Clone, Default)]
+pub struct ThinkSplitter {
+    /// Inside a `<think>` block
+    thinking: bool,
+    /// Answer text came, so no block opens anymore
+    answering: bool,
+    /// A block just closed: whitespace before the answer is dropped
+    after_block: bool,
+    /// Text held back, maybe the start of a tag
+    pending: String,
+}
+
+impl ThinkSplitter {
+    /// Split the next chunk of reply text.
+    pub fn push(&mut self, chunk: &str) -> Split {
+        let mut split = Split::default();
+        let mut text = std::mem::take(&mut self.pending);
+        text.push_str(chunk);
+        let mut rest = text.as_str();
+
+        while !rest.is_empty() {
+            if self.answering {
+                split.answer.push_str(rest);
+                break;
+            }
+            if self.thinking {
+                match rest.find(CLOSE_TAG) {
+                    Some(end) => {
+                        split.reasoning.push_str(&rest[..end]);
+                        rest = &rest[end + CLOSE_TAG.len()..];
+                        self.thinking = false;
+                        self.after_block = true;
+                    }
+                    None => {
+                        let keep = partial_tag_len(rest, CLOSE_TAG);
+                        split.reasoning.push_str(&rest[..rest.len() - keep]);
+                        self.pending = rest[rest.len() - keep..].to_string();
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            let trimmed = rest.trim_start();
+            if let Some(after) = trimmed.strip_prefix(OPEN_TAG) {
+                self.thinking = true;
+                rest = after;
+            } else if OPEN_TAG.starts_with(trimmed) {
+                // Whitespace, or the start of a tag: wait for more
+                self.pending = rest.to_string();
+                break;
+            } else {
+                self.answering = true;
+                if self.after_block {
+                    rest = trimmed;
+                }
+            }
+        }
+        split
+    }
+
+    /// The reply ended: whatever was held back goes where it was headed
+    /// (an unclosed block is all reasoning).
+    pub fn finish(&mut self) -> Split {
+        let pending = std::mem::take(&mut self.pending);
+        let mut split = Split::default();
+        if self.thinking {
+            split.reasoning = pending;
+        } else if !self.after_block || !pending.trim().is_empty() {
+            split.answer = pending;
+        }
+        *self = Self::default();
+        split
+    }
+}
+
+/// Length of the longest end of `text` that `tag` starts with.
+fn partial_tag_len(text: &str, tag: &str) -> usize {
+    (1..tag.len().min(text.len() + 1))
+        .rev()
+        .find(|&len| text.is_char_boundary(text.len() - len) && tag.starts_with(&text[text.len() - len..]))
+        .unwrap_or(0)
+}
+
+/// Reply tokens as (answer, reasoning), from the counts the provider
+/// reported (completion tokens, and the reasoning among them) or, where it
+/// didn't, estimated from the text streamed.
+pub fn reply_tokens(reported: Option<(u32, Option<u32>)>, streamed: &Split) -> (u64, u64) {
+    let estimated_reasoning = super::prompt::estimate_tokens(&streamed.reasoning) as u64;
+    match reported {
+        Some((completion, Some(reasoning))) => (u64::from(completion.saturating_sub(reasoning)), u64::from(reasoning)),
+        Some((completion, None)) => (u64::from(completion).saturating_sub(estimated_reasoning), estimated_reasoning),
+        None => (super::prompt::estimate_tokens(&streamed.answer) as u64, estimated_reasoning),
+    }
+}
+
+/// Reasoning length for the collapsed block: "850 chars", "1.2k chars".
+pub fn describe_length(text: &str) -> String {
+    let chars = text.chars().count();
+    if chars < 1000 {
+        format!("{} chars", chars)
+    } else {
+        format!("{:.1}k chars", chars as f64 / 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `chunks` one at a time and join what comes out.
+    fn split_all(chunks: &[&str]) -> Split {
+        let mut splitter = ThinkSplitter::default();
+        let mut total = Split::default();
+        for chunk in chunks.iter().copied() {
+            let split = splitter.push(chunk);
+            total.reasoning.push_str(&split.reasoning);
+            total.answer.push_str(&split.answer);
+        }
+        let split = splitter.finish();
+        total.reasoning.push_str(&split.reasoning);
+        total.answer.push_str(&split.answer);
+        total
+    }
+
+    #[test]
+    fn test_think_block_is_taken_out_of_the_answer() {
+        let split = split_all(&["<think>The user wants disk usage.</think>\n\nRun `du -sh`."]);
+        assert_eq!(split.reasoning, "The user wants disk usage.");
+        assert_eq!(split.answer, "Run `du -sh`.");
+    }
+
+    #[test]
+    fn test_tags_cut_across_chunks() {
+        let reply = "\n<think>Check the flags.\nThen answer.</think>\nUse `ls -la`.";
+        // Every way of cutting the reply in two, and one character a chunk
+        for cut in 0..=reply.len() {
+            let split = split_all(&[&reply[..cut], &reply[cut..]]);
+            assert_eq!(split.reasoning, "Check the flags.\nThen answer.", "cut at {}", cut);
+            assert_eq!(split.answer, "Use `ls -la`.", "cut at {}", cut);
+        }
+        let chars: Vec<String> = reply.chars().map(String::from).collect();
+        let split = split_all(&chars.iter().map(String::as_str).collect::<Vec<_>>());
+        assert_eq!(split.answer, "Use `ls -la`.");
+
+        // Held back while it may be a tag, let go as soon as it isn't
+        let mut splitter = ThinkSplitter::default();
+        assert_eq!(splitter.push("<thi"), Split::default());
+        assert_eq!(splitter.push("s> is a tag").answer, "<this> is a tag");
+    }
+
+    #[test]
+    fn test_tags_only_count_before_the_answer() {
+        let split = split_all(&["Wrap it in ", "<think></think> tags."]);
+        assert_eq!(split.reasoning, "");
+        assert_eq!(split.answer, "Wrap it in <think></think> tags.");
+
+        // An unclosed block is all reasoning
+        let split = split_all(&["<think>Still going </th"]);
+        assert_eq!(split.reasoning, "Still going </th");
+        assert_eq!(split.answer, "");
+    }
+
+    #[test]
+    fn test_reported_reasoning_tokens_are_taken_from_the_completion() {
+        let streamed = Split { reasoning: "x".repeat(40), answer: "y".repeat(8) };
+        assert_eq!(reply_tokens(Some((120, Some(100))), &streamed), (20, 100));
+        let (answer, reasoning) = reply_tokens(None, &streamed);
+        assert!(answer > 0 && reasoning > answer);
+    }
+
+    #[test]
+    fn test_length_is_described_in_thousands() {
+        assert_eq!(describe_length("abc"), "3 chars");
+        assert_eq!(describe_length(&"x".repeat(1234)), "1.2k chars");
+    }
+}
@@ -0,0 +1,344 @@
+//! How a request asks the model to sample its reply.
+//!
+//! A question that seeks a command is sent with the low temperature of the
+//! command profile, so the flags it gets back are the likely ones; anything
+//! else goes with the conversation profile, the backend's own default
+//! unless configured. Reasoning models take no temperature, and get the
+//! configured reasoning effort instead. A session can set its own
+//! temperature and effort (`/set temperature 0.2`), which win over both
+//! profiles. The [`Sampling`] picked for a request is shown with its timing.
+
+use std::fmt;
+use std::str::FromStr;
+
+use async_openai::types::ReasoningEffort;
+use serde::{Deserialize, Serialize};
+
+/// A sampling temperature, from 0 to 2 in hundredths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct Temperature(u16);
+
+impl Temperature {
+    /// Highest temperature the API takes.
+    pub const MAX: f64 = 2.0;
+
+    /// Temperature of the command profile, unless configured.
+    pub const COMMAND: Temperature = Temperature(20);
+
+    pub fn as_f32(self) -> f32 {
+        f32::from(self.0) / 100.0
+    }
+}
+
+impl TryFrom<f64> for Temperature {
+    type Error = String;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if !(0.0..=Self::MAX).contains(&value) {
+            return Err(format!("temperature must be from 0 to {}, not {}", Self::MAX, value));
+        }
+        Ok(Self((value * 100.0).round() as u16))
+    }
+}
+
+impl From<Temperature> for f64 {
+    fn from(temperature: Temperature) -> Self {
+        f64::from(temperature.0) / 100.0
+    }
+}
+
+impl FromStr for Temperature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: f64 = s
+            .trim()
+            .parse()
+            .map_err(|_| format!("temperature must be a number from 0 to {}, not \"{}\"", Self::MAX, s.trim()))?;
+        Self::try_from(value)
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", f64::from(*self))
+    }
+}
+
+/// How hard a reasoning model thinks before it answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Effort {
+    Minimal,
+    Low,
+    Medium,
+    High,
+}
+
+impl Effort {
+    pub const ALL: [Effort; 4] = [Effort::Minimal, Effort::Low, Effort::Medium, Effort::High];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Effort::Minimal => "minimal",
+            Effort::Low => "low",
+            Effort::Medium => "medium",
+            Effort::High => "high",
+        }
+    }
+}
+
+impl FromStr for Effort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Self::ALL.into_iter().find(|effort| effort.name() == s).ok_or_else(|| {
+            let names: Vec<_> = Self::ALL.iter().map(|effort| effort.name()).collect();
+            format!("effort must be one of {}, not \"{}\"", names.join(", "), s)
+        })
+    }
+}
+
+impl From<Effort> for ReasoningEffort {
+    fn from(effort: Effort) -> Self {
+        match effort {
+            Effort::Minimal => ReasoningEffort::Minimal,
+            Effort::Low => ReasoningEffort::Low,
+            Effort::Medium => ReasoningEffort::Medium,
+            Effort::High => ReasoningEffort::High,
+        }
+    }
+}
+
+/// What a request is for, and so which profile it is sent with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Task {
+    /// Asks for a command to run
+    Command,
+    /// Anything else: explanations, follow-ups, brainstorming
+    #[default]
+    Conversation,
+}
+
+impl Task {
+    /// The task of a question, going by its wording: asking how to do
+    /// something, or telling the assistant to do it, seeks a command.
+    pub fn of(question: &str) -> Self {
+        let q = question.trim().to_lowercase();
+        let asks_how = ["how do i", "how can i", "how to", "how would i", "what's the command", "what is the command"]
+            .iter()
+            .any(|phrase| q.contains(phrase));
+        let mentions = ["command", "one-liner", "oneliner", "script"].iter().any(|word| q.contains(word));
+        let first = q.split_whitespace().next().unwrap_or_default();
+        let imperative = [
+            "find", "list", "show", "delete", "remove", "kill", "install", "uninstall", "create", "make", "run",
+            "start", "stop", "restart", "copy", "move", "rename", "compress", "extract", "count", "search", "grep",
+            "check", "undo", "revert", "give", "get", "set", "change", "clean", "free", "build", "update", "upgrade",
+        ]
+        .contains(&first);
+        match asks_how || mentions || imperative {
+            true => Task::Command,
+            false => Task::Conversation,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Task::Command => "command",
+            Task::Conversation => "chat",
+        }
+    }
+}
+
+/// Sampling settings: the configured profiles, or a session's own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Settings {
+    /// Temperature of every request (None = the profile's)
+    pub temperature: Option<Temperature>,
+    /// Effort of reasoning models (None = the backend's default)
+    pub effort: Option<Effort>,
+}
+
+impl Settings {
+    /// `settings` changed by the arguments of `/set` (`temperature 0.2`,
+    /// `effort low`, `default` for either to unset it), with a line saying
+    /// what changed.
+    pub fn set(self, args: &str) -> Result<(Self, String), String> {
+        let usage = || "Usage: /set temperature <0-2|default> or /set effort <minimal|low|medium|high|default>".to_string();
+        let mut words = args.split_whitespace();
+        let (Some(name), Some(value), None) = (words.next(), words.next(), words.next()) else {
+            return Err(usage());
+        };
+        let value = value.to_lowercase();
+        let reset = value == "default";
+        match name {
+            "temperature" | "temp" => {
+                let temperature = if reset { None } else { Some(value.parse::<Temperature>()?) };
+                let said = match temperature {
+                    Some(temperature) => format!("Temperature of this session set to {}", temperature),
+                    None => "Temperature of this session back to the default".to_string(),
+                };
+                Ok((Self { temperature, ..self }, said))
+            }
+            "effort" => {
+                let effort = if reset { None } else { Some(value.parse::<Effort>()?) };
+                let said = match effort {
+                    Some(effort) => format!("Reasoning effort of this session set to {}", effort.name()),
+                    None => "Reasoning effort of this session back to the default".to_string(),
+                };
+                Ok((Self { effort, ..self }, said))
+            }
+            _ => Err(usage()),
+        }
+    }
+}
+
+/// The configured profiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Profiles {
+    /// Temperature of command-seeking requests
+    pub command: Temperature,
+    /// Temperature of conversation (None = the backend's default)
+    pub conversation: Option<Temperature>,
+    /// Effort of reasoning models (None = the backend's default)
+    pub effort: Option<Effort>,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self { command: Temperature::COMMAND, conversation: None, effort: None }
+    }
+}
+
+impl Profiles {
+    /// Sampling of a request for `task` to `model`, with the session's
+    /// own `settings` first.
+    pub fn pick(&self, task: Task, model: &str, settings: Settings) -> Sampling {
+        if is_reasoning_model(model) {
+            return Sampling { task, temperature: None, effort: settings.effort.or(self.effort) };
+        }
+        let profile = match task {
+            Task::Command => Some(self.command),
+            Task::Conversation => self.conversation,
+        };
+        Sampling { task, temperature: settings.temperature.or(profile), effort: None }
+    }
+}
+
+/// Sampling parameters of one request (None = the backend's default).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sampling {
+    pub task: Task,
+    pub temperature: Option<Temperature>,
+    pub effort: Option<Effort>,
+}
+
+/// "command · temp 0.2", "chat · effort low", or just the profile.
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.task.name())?;
+        if let Some(temperature) = self.temperature {
+            write!(f, " · temp {}", temperature)?;
+        }
+        if let Some(effort) = self.effort {
+            write!(f, " · effort {}", effort.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `model` reasons before it answers: such models take a
+/// reasoning effort, and no temperature.
+pub fn is_reasoning_model(model: &str) -> bool {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    ["o1", "o3", "o4", "gpt-5"].iter().any(|family| {
+        name.strip_prefix(family).is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_out_of_range_are_rejected_with_the_range() {
+        assert_eq!("0.2".parse::<Temperature>(), Ok(Temperature(20)));
+        assert_eq!("2".parse::<Temperature>().map(f64::from), Ok(2.0));
+        assert_eq!(
+            "2.5".parse::<Temperature>(),
+            Err("temperature must be from 0 to 2, not 2.5".to_string())
+        );
+        assert_eq!(
+            "warm".parse::<Temperature>(),
+            Err("temperature must be a number from 0 to 2, not \"warm\"".to_string())
+        );
+        assert!("-0.1".parse::<Temperature>().is_err());
+        assert!("NaN".parse::<Temperature>().is_err());
+        assert_eq!("low".parse::<Effort>(), Ok(Effort::Low));
+        assert_eq!(
+            "max".parse::<Effort>(),
+            Err("effort must be one of minimal, low, medium, high, not \"max\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_set_changes_one_setting_at_a_time() {
+        let settings = Settings::default();
+        let (settings, said) = settings.set(" temperature 0.2").unwrap_or_default();
+        assert_eq!(settings.temperature, Some(Temperature(20)));
+        assert_eq!(said, "Temperature of this session set to 0.2");
+        let (settings, _) = settings.set("effort HIGH").unwrap_or_default();
+        assert_eq!(settings, Settings { temperature: Some(Temperature(20)), effort: Some(Effort::High) });
+        let (settings, said) = settings.set("temperature default").unwrap_or_default();
+        assert_eq!(settings, Settings { temperature: None, effort: Some(Effort::High) });
+        assert_eq!(said, "Temperature of this session back to the default");
+
+        assert_eq!(settings.set("temperature 7"), Err("temperature must be from 0 to 2, not 7".to_string()));
+        assert!(settings.set("").is_err_and(|e| e.starts_with("Usage: /set")));
+        assert!(settings.set("top_p 0.5").is_err_and(|e| e.starts_with("Usage: /set")));
+        assert!(settings.set("temperature 0.2 0.3").is_err());
+    }
+
+    #[test]
+    fn test_questions_seeking_commands_are_told_apart() {
+        for question in ["how do I free disk space?", "find files over 1GB", "Kill whatever holds port 8080", "give me a one-liner to count lines"] {
+            assert_eq!(Task::of(question), Task::Command, "{}", question);
+        }
+        for question in ["why is my build slow?", "what does this error mean", "explain the difference between rebase and merge"] {
+            assert_eq!(Task::of(question), Task::Conversation, "{}", question);
+        }
+    }
+
+    #[test]
+    fn test_profiles_pick_by_task_and_model() {
+        let profiles = Profiles { conversation: Some(Temperature(90)), ..Profiles::default() };
+        let none = Settings::default();
+        assert_eq!(profiles.pick(Task::Command, "gpt-4o-mini", none).temperature, Some(Temperature(20)));
+        assert_eq!(profiles.pick(Task::Conversation, "gpt-4o-mini", none).temperature, Some(Temperature(90)));
+
+        // The session's own temperature wins
+        let own = Settings { temperature: Some(Temperature(50)), effort: None };
+        assert_eq!(profiles.pick(Task::Command, "gpt-4o-mini", own).temperature, Some(Temperature(50)));
+
+        // Reasoning models get an effort instead
+        let profiles = Profiles { effort: Some(Effort::Low), ..profiles };
+        let sampling = profiles.pick(Task::Command, "o3-mini", own);
+        assert_eq!((sampling.temperature, sampling.effort), (None, Some(Effort::Low)));
+        assert_eq!(profiles.pick(Task::Command, "gpt-4o", own).effort, None);
+        assert_eq!(sampling.to_string(), "command · effort low");
+        assert_eq!(profiles.pick(Task::Command, "gpt-4o", own).to_string(), "command · temp 0.5");
+    }
+
+    #[test]
+    fn test_reasoning_models_are_known_by_name() {
+        assert!(is_reasoning_model("o3-mini"));
+        assert!(is_reasoning_model("o1"));
+        assert!(is_reasoning_model("gpt-5-mini"));
+        assert!(is_reasoning_model("openai/o4-mini"));
+        assert!(!is_reasoning_model("gpt-4o"));
+        assert!(!is_reasoning_model("llama3.1"));
+        assert!(!is_reasoning_model("o1337"));
+    }
+}
@@ -0,0 +1,199 @@
+//! Where the time of one AI request went.
+//!
+//! A [`Timeline`] goes with the request from the manager into the task that
+//! streams it and only takes timestamps on the way (nothing is shared, and
+//! the chunk loop checks a single `Option`). When the stream ends it is
+//! turned into a [`RequestTiming`], which is sent back ahead of the end of
+//! the stream and kept with the reply: one line of it is shown on demand,
+//! and the time to the first byte feeds the metrics registry. The sampling
+//! the request went with is shown on the same line.
+
+use std::fmt;
+
+use tokio::time::{Duration, Instant};
+
+use super::sampling::Sampling;
+
+/// Timestamps taken while a request is under way.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    /// Building the context snapshot, before the request was handed over
+    snapshot: Duration,
+    /// The manager took the request
+    started: Instant,
+    /// Extended context (Shell2) collected, or not waited for
+    context_ready: Option<Instant>,
+    /// Request built and sent
+    sent: Option<Instant>,
+    /// First part of the reply arrived
+    first_byte: Option<Instant>,
+    /// Last part of the reply arrived
+    stream_end: Option<Instant>,
+    /// Tool calls assembled from their parts
+    tool_calls: Option<Instant>,
+    /// Temperature and effort the request went with
+    sampling: Option<Sampling>,
+}
+
+impl Timeline {
+    /// A request taken now, after `snapshot` was spent building its context.
+    pub fn start(snapshot: Duration) -> Self {
+        Self {
+            snapshot,
+            started: Instant::now(),
+            context_ready: None,
+            sent: None,
+            first_byte: None,
+            stream_end: None,
+            tool_calls: None,
+            sampling: None,
+        }
+    }
+
+    pub fn sampled(&mut self, sampling: Sampling) {
+        self.sampling = Some(sampling);
+    }
+
+    pub fn context_ready(&mut self) {
+        self.context_ready = Some(Instant::now());
+    }
+
+    pub fn sent(&mut self) {
+        self.sent = Some(Instant::now());
+    }
+
+    /// Part of the reply arrived; only the first one is kept.
+    pub fn received(&mut self) {
+        if self.first_byte.is_none() {
+            self.first_byte = Some(Instant::now());
+        }
+    }
+
+    pub fn stream_ended(&mut self) {
+        self.stream_end = Some(Instant::now());
+    }
+
+    pub fn tool_calls_assembled(&mut self) {
+        self.tool_calls = Some(Instant::now());
+    }
+
+    /// Time of each phase, for a reply of `tokens` tokens. A phase that was
+    /// never reached takes no time; the next one counts from the last mark.
+    pub fn finish(&self, tokens: u64) -> RequestTiming {
+        let context_ready = self.context_ready.unwrap_or(self.started);
+        let sent = self.sent.unwrap_or(context_ready);
+        let first_byte = self.first_byte.unwrap_or(sent);
+        let stream_end = self.stream_end.unwrap_or(first_byte);
+        let tool_calls = self.tool_calls.unwrap_or(stream_end);
+        RequestTiming {
+            snapshot: self.snapshot,
+            shell2: context_ready.saturating_duration_since(self.started),
+            request: sent.saturating_duration_since(context_ready),
+            first_byte: first_byte.saturating_duration_since(sent),
+            stream: stream_end.saturating_duration_since(first_byte),
+            tool_calls: tool_calls.saturating_duration_since(stream_end),
+            tokens,
+            sampling: self.sampling,
+        }
+    }
+}
+
+/// How long each phase of a request took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequestTiming {
+    /// Building the context snapshot (directory, history, output)
+    pub snapshot: Duration,
+    /// Waiting for the extended context (git, files, tools)
+    pub shell2: Duration,
+    /// Building the request
+    pub request: Duration,
+    /// From sending the request to the first part of the reply
+    pub first_byte: Duration,
+    /// From the first part of the reply to the last
+    pub stream: Duration,
+    /// Assembling the tool calls after the stream ended
+    pub tool_calls: Duration,
+    /// Tokens of the reply (answer and reasoning)
+    pub tokens: u64,
+    /// Temperature and effort the request went with
+    pub sampling: Option<Sampling>,
+}
+
+impl RequestTiming {
+    /// Everything before the request went out.
+    pub fn context(&self) -> Duration {
+        self.snapshot + self.shell2 + self.request
+    }
+
+    /// From taking the question to the end of the reply.
+    pub fn total(&self) -> Duration {
+        self.context() + self.first_byte + self.stream + self.tool_calls
+    }
+
+    /// Tokens streamed per second (None if the reply came in one piece).
+    pub fn tokens_per_second(&self) -> Option<u64> {
+        let secs = self.stream.as_secs_f64();
+        (secs > 0.0 && self.tokens > 0).then(|| (self.tokens as f64 / secs).round() as u64)
+    }
+}
+
+/// The one-line breakdown: "ctx 0.2s · ttfb 1.8s · stream 6.4s · 42 tok/s",
+/// then the sampling: "· command · temp 0.2".
+impl fmt::Display for RequestTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = |d: Duration| format!("{:.1}s", d.as_secs_f64());
+        write!(
+            f,
+            "ctx {} · ttfb {} · stream {}",
+            secs(self.context()),
+            secs(self.first_byte),
+            secs(self.stream)
+        )?;
+        if self.tool_calls >= Duration::from_millis(50) {
+            write!(f, " · tools {}", secs(self.tool_calls))?;
+        }
+        if let Some(rate) = self.tokens_per_second() {
+            write!(f, " · {} tok/s", rate)?;
+        }
+        if let Some(sampling) = self.sampling {
+            write!(f, " · {}", sampling)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breakdown_reads_in_one_line() {
+        let timing = RequestTiming {
+            snapshot: Duration::from_millis(50),
+            shell2: Duration::from_millis(120),
+            request: Duration::from_millis(30),
+            first_byte: Duration::from_millis(1800),
+            stream: Duration::from_millis(6400),
+            tool_calls: Duration::ZERO,
+            tokens: 269,
+            sampling: None,
+        };
+        assert_eq!(timing.to_string(), "ctx 0.2s · ttfb 1.8s · stream 6.4s · 42 tok/s");
+        assert_eq!(timing.total(), Duration::from_millis(8400));
+
+        let instant = RequestTiming { stream: Duration::ZERO, ..timing };
+        assert_eq!(instant.to_string(), "ctx 0.2s · ttfb 1.8s · stream 0.0s");
+
+        let sampling = Sampling { temperature: "0.2".parse().ok(), ..Sampling::default() };
+        let sampled = RequestTiming { sampling: Some(sampling), ..instant };
+        assert_eq!(sampled.to_string(), "ctx 0.2s · ttfb 1.8s · stream 0.0s · chat · temp 0.2");
+    }
+
+    #[test]
+    fn test_phases_never_reached_take_no_time() {
+        let timeline = Timeline::start(Duration::from_millis(5));
+        let timing = timeline.finish(0);
+        assert_eq!(timing.snapshot, Duration::from_millis(5));
+        assert_eq!(timing.first_byte + timing.stream + timing.tool_calls, Duration::ZERO);
+    }
+}
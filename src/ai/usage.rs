@@ -0,0 +1,143 @@
+//! Tokens the replies of a session used, and roughly what they cost.
+//!
+//! Each reply adds the prompt and completion tokens the API reported for
+//! it, or where it didn't, an estimate from the text sent and received.
+//! The pane shows the total of the active session next to its status
+//! ("12.3k tok"), with a dollar figure when prices are configured.
+
+use serde::{Deserialize, Serialize};
+
+/// Tokens used by the replies of a session, added up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsageStats {
+    /// Tokens of the requests: the history, context and tools sent
+    pub prompt_tokens: u64,
+    /// Tokens of the replies, reasoning included
+    pub completion_tokens: u64,
+    /// Replies counted
+    pub replies: u32,
+}
+
+impl UsageStats {
+    /// The usage of one reply.
+    pub fn reply(prompt_tokens: u64, completion_tokens: u64) -> Self {
+        Self { prompt_tokens, completion_tokens, replies: 1 }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn add(&mut self, other: UsageStats) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.replies += other.replies;
+    }
+
+    /// Dollars spent at `prices`.
+    pub fn cost(&self, prices: Prices) -> f64 {
+        (self.prompt_tokens as f64 * f64::from(prices.prompt_per_1k)
+            + self.completion_tokens as f64 * f64::from(prices.completion_per_1k))
+            / 1000.0
+    }
+
+    /// "12.3k tok", and "12.3k tok $0.04" with prices; None before the first
+    /// reply.
+    pub fn label(&self, prices: Option<Prices>) -> Option<String> {
+        if self.replies == 0 {
+            return None;
+        }
+        let tokens = format!("{} tok", format_count(self.total()));
+        Some(match prices {
+            Some(prices) => format!("{} {}", tokens, format_cost(self.cost(prices))),
+            None => tokens,
+        })
+    }
+}
+
+/// Dollars per thousand tokens, sent and received.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Prices {
+    pub prompt_per_1k: Price,
+    pub completion_per_1k: Price,
+}
+
+/// A price in dollars, in billionths, so a config holding it stays `Eq`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct Price(u64);
+
+impl TryFrom<f64> for Price {
+    type Error = String;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if !value.is_finite() || value < 0.0 {
+            return Err(format!("price must be 0 dollars or more, not {}", value));
+        }
+        Ok(Self((value * 1e9).round() as u64))
+    }
+}
+
+impl From<Price> for f64 {
+    fn from(price: Price) -> Self {
+        price.0 as f64 / 1e9
+    }
+}
+
+/// "850", "12.3k", "1.2M".
+pub fn format_count(count: u64) -> String {
+    match count {
+        0..1_000 => count.to_string(),
+        1_000..1_000_000 => format!("{:.1}k", count as f64 / 1_000.0),
+        _ => format!("{:.1}M", count as f64 / 1_000_000.0),
+    }
+}
+
+/// "$0.04", and "<$0.01" for less than a cent.
+fn format_cost(dollars: f64) -> String {
+    if dollars > 0.0 && dollars < 0.01 {
+        "<$0.01".to_string()
+    } else {
+        format!("${:.2}", dollars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_adds_up_and_labels() -> Result<(), String> {
+        let mut usage = UsageStats::default();
+        assert_eq!(usage.label(None), None);
+        usage.add(UsageStats::reply(11_000, 800));
+        usage.add(UsageStats::reply(400, 100));
+        assert_eq!(usage, UsageStats { prompt_tokens: 11_400, completion_tokens: 900, replies: 2 });
+        assert_eq!(usage.label(None).as_deref(), Some("12.3k tok"));
+
+        let prices = Prices { prompt_per_1k: Price::try_from(0.0025)?, completion_per_1k: Price::try_from(0.01)? };
+        assert!((usage.cost(prices) - 0.0375).abs() < 1e-9);
+        assert_eq!(usage.label(Some(prices)).as_deref(), Some("12.3k tok $0.04"));
+        let small = UsageStats::reply(100, 10);
+        assert_eq!(small.label(Some(prices)).as_deref(), Some("110 tok <$0.01"));
+        assert_eq!(small.label(Some(Prices::default())).as_deref(), Some("110 tok $0.00"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_prices_are_dollar_amounts() -> Result<(), String> {
+        assert_eq!(f64::from(Price::try_from(0.00015)?), 0.00015);
+        assert!(Price::try_from(-0.01).is_err());
+        assert!(Price::try_from(f64::NAN).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1_000), "1.0k");
+        assert_eq!(format_count(12_345), "12.3k");
+        assert_eq!(format_count(1_250_000), "1.2M");
+    }
+}
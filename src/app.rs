@@ -5,23 +5,63 @@
 //! It provides methods for pane switching and state initialization.
 
 
-use crate::event::{AppEvent, init_app_eventsource, init_user_event};
-use crate::event::{assistant as assistant_event, mouse as mouse_event, terminal as terminal_event, UserEvent};
-use crate::ai::session::AiSessionManager;
-use crate::context::ContextManager;
-use crate::shell::ShellManager;
+use crate::event::{AiUiUpdate, AppEvent, Stamped, init_app_eventsource, init_user_event};
+use crate::event::trace::{user_event_kind, EventOrigin, EventTrace};
+use crate::event::actions::Category;
+use crate::event::{assistant as assistant_event, mouse as mouse_event, terminal as terminal_event, UserEvent, UserEvents};
+use crate::ai::batch::{Batch, BatchProgress, BatchStep, StepFailure};
+use crate::ai::cache::ResponseCache;
+use crate::ai::prompt::{self, TemplateStore};
+use crate::ai::provider;
+use crate::ai::sampling::Profiles;
+use crate::ai::session::{AiSessionManager, ScriptedReplies, SessionId};
+use crate::context::{ContextManager, OUTPUT_BUDGET, SCREEN_BUDGET, truncate_output};
+use crate::shell::{CastRecorder, CommandOrigin, Composed, Execution, SIGINT, ScriptedShell, ShellManager, Submit};
 use crate::ui::assistant::TuiAssistant;
+use crate::ui::context_chips::ContextSummary;
 use crate::ui::terminal::TuiTerminal;
-use crate::ui::layout::{AppLayout, LayoutBuilder};
-use crate::security::{evaluate, ExecutionDecision, gate_command};
+use crate::ui::visual::{CopyOutcome, PaneStatus, copy_to_clipboard};
+use crate::ui::layout::{AppLayout, LayoutBuilder, LayoutMode, plausible_size};
+use crate::ui::drafts::DraftStore;
+use crate::ui::layout_state::LayoutStore;
+use crate::ui::switcher::{SwitchEntry, SwitchTarget, Switcher};
+use crate::ui::mini_assistant::{MiniAssistant, MAX_ANSWER_ROWS};
+use crate::ui::help::Help;
+use crate::ui::cues::{CueEvent, Cues};
+use crate::ui::pacing::{DECORATION_FRAME, FramePacer, FRAME};
+use crate::ui::performance::{self, PerformanceMode, SLOW_DECORATION_FRAME};
+use crate::ui::long_lines::{LongLine, LongLineView};
+use crate::ui::macros::MacroStep;
+use crate::ui::repeats::RepeatedLines;
+use crate::security::{
+    AllowlistStore, AuditLog, ExecutionDecision, Policy, ProjectRules, composed_findings, gate_command, verdict_from,
+};
+use crate::plugin::{Plugin, PluginCtx, PluginRegistry};
+use crate::config::{
+    Config, DumpConfig, InputEditing, KeymapConfig, LeaderProgress, Placeholders, ProjectTracker, QuickAction, QuickActionKind,
+    RecordingConfig,
+};
+use crate::config::project::{Project, ProjectChange, TrustStore};
+use crate::utils::metrics::{self, DrawTrigger, MetricsHistory, metrics};
+use crate::utils::persist;
+use crate::utils::shell2::Shell2Config;
+use crate::utils::state_dir::{self, Handshake};
+use crate::utils::suspend::{self, JobSignal, JobSignals, Suspension, TerminalModes};
 
 
 use anyhow::{Context, Result};
-use ratatui::DefaultTerminal;
-use tokio::sync::mpsc::{Receiver, UnboundedReceiver};
+use futures::FutureExt;
+use ratatui::backend::Backend;
+use ratatui::{DefaultTerminal, Terminal};
+use tokio::sync::mpsc::{Receiver, UnboundedReceiver, UnboundedSender};
 use tokio::time::{Duration, Instant};
 
-use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEventKind};
+
+/// How long after start a too-small screen is waited out before the
+/// placeholder is drawn: some emulators report a zero or tiny size until the
+/// window is mapped.
+const SIZE_SETTLE: Duration = Duration::from_millis(300);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ActivePane {
@@ -42,6 +82,17 @@ pub enum MouseTarget {
     Outside,
 }
 
+/// A quick action's command, shown for a yes before it is typed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickRun {
+    /// Name of the quick action
+    pub name: String,
+    /// The text typed into the shell
+    pub command: String,
+    /// Why the security gate wants a confirmation, if it does
+    pub reason: Option<String>,
+}
+
 pub struct App {
     // backend
     shell_manager: ShellManager,
@@ -59,8 +110,12 @@ pub struct App {
 
     exit: bool,  // Should the app exit?
     command_mode: bool,  // Is the app in the command mode?
+    command_mode_deadline: Option<Instant>,  // When command mode auto-exits (if a timeout is configured)
     force_redraw_flag: bool,  // Should force a full screen clear and redraw?
-    next_frame_deadline: Option<Instant>,
+    suspension: Suspension,  // Host terminal modes released while stopped (SIGTSTP)
+    frames: FramePacer,  // When the next frame is due, and whether for content or decorations
+    drawn_status: Option<(PaneStatus, PaneStatus)>,  // Pane statuses of the last frame (terminal, assistant)
+    size_settling_until: Option<Instant>,  // Until when a too-small screen at start is not drawn yet
 
     // Mouse drag state for visual selection
     mouse_drag_state: Option<mouse_event::MouseDragState>,
@@ -70,9 +125,73 @@ pub struct App {
 
     // Multi-click detection state (double/triple-click)
     last_click: Option<mouse_event::LastClickState>,
+
+    // Panes drawn the cheap way for slow terminals (no hover while any is)
+    performance: PerformanceMode,
+
+    // Hover highlighting: last pointer position and what it is over
+    hover_enabled: bool,
+    pointer: Option<(u16, u16)>,
+    hover: mouse_event::HoverState,
+    // Show what the next AI message carries above the assistant input
+    // (off in scripted apps, whose screens must not depend on the host)
+    context_chips: bool,
+    // Sends events back to the loop from tasks the app starts
+    app_event_tx: UnboundedSender<Stamped<AppEvent>>,
+    // Put the AI marker variable on suggested commands
+    mark_ai_commands: bool,
+    // Suggested command sent last, while it seems to still be running
+    running_suggestion: Option<String>,
+    // The last command and its exit code, if it failed (command mode `D`)
+    failed_command: Option<(String, i32)>,
+    // Accepted suggestions run one after another, while some are left
+    batch: Option<Batch>,
     // Shell input tracking
     shell_input_buffer: String,  // Track user input in shell panel
 
+    // Keymap preferences (leader chord, command mode timeout)
+    keymap: KeymapConfig,
+    // Where recordings go and whether they include input
+    recording: RecordingConfig,
+    // Where scrollback dumps go and whether they keep colors
+    dump: DumpConfig,
+    // Bell and notification cues waiting for the next frame to be drawn
+    cues: Cues,
+    // Project file for the shell's directory (overlay and trust prompt)
+    project: ProjectTracker,
+    // Keys typed so far towards a multi-chord leader
+    leader_pending: Vec<KeyEvent>,
+    // Writes unsent assistant input to disk (None = drafts are not saved)
+    draft_store: Option<DraftStore>,
+    // Writes the split ratio to disk (None = not kept across restarts)
+    layout_store: Option<LayoutStore>,
+    // Quick switcher overlay (None = closed); keys go to it while open
+    switcher: Option<Switcher>,
+    // Mini assistant over the terminal (None = closed); keys go to it while open
+    mini: Option<MiniAssistant>,
+    // Help overlay listing the keys (None = closed); keys go to it while open
+    help: Option<Help>,
+    // Long line viewer (None = closed); keys go to it while open
+    long_line_view: Option<LongLineView>,
+    // The user's own command mode keys
+    quick_actions: Vec<QuickAction>,
+    // Quick action command waiting for a yes (None = no prompt); modal
+    quick_run: Option<QuickRun>,
+    // Why persisted state is read-only this run (None = it isn't, or the
+    // banner was dismissed); modal
+    state_banner: Option<String>,
+    // Compiled-in plugins, called in registration order
+    plugins: PluginRegistry,
+
+    // Ring of recently processed events, for debugging ordering bugs
+    event_trace: EventTrace,
+    show_event_trace: bool,  // Is the event trace overlay visible?
+
+    // Samples of the metrics registry, for the overlay and the log
+    metrics_history: MetricsHistory,
+    next_metrics_sample: Instant,
+    show_metrics: bool,  // Is the metrics overlay visible?
+
     // Layout builder - holds user preferences/constraints for layout
     layout_builder: LayoutBuilder,
 
@@ -80,12 +199,19 @@ pub struct App {
     layout: AppLayout,
 
     // events sources
-    user_events: Receiver<std::io::Result<UserEvent>>,  // User input
-    app_events: UnboundedReceiver<AppEvent>,  // App Events
+    user_events: UserEvents,  // User input
+    app_events: UnboundedReceiver<Stamped<AppEvent>>,  // App Events
 }
 
 impl App {
     pub fn new() -> Result<Self> {
+        Self::with_performance(None)
+    }
+
+    /// Like [`App::new`], with performance mode forced on or off by the
+    /// command line (None = as the config says, probing the terminal if
+    /// it says `auto`).
+    pub fn with_performance(flag: Option<bool>) -> Result<Self> {
         let (event_sink, app_events) = init_app_eventsource();
 
         // Start with reasonable default size (will be resized on first draw)
@@ -93,9 +219,94 @@ impl App {
         let rows = 24;
 
         let (shell, pty_rx) = ShellManager::new(event_sink.clone(), cols, rows)?;
+        let config = Config::load();
+        crate::utils::logger::log_startup_header(&config);
+        // The probe reads its answer from stdin: before the user event reader
+        // starts, which would take it
+        let decision = performance::decide(config.display.performance, flag, || {
+            performance::probe_terminal(performance::PROBE_TIMEOUT)
+        });
+        tracing::info!("performance mode: {}", decision);
+        // Before anything in the data directory is read or written
+        let handshake = persist::data_dir().map(|dir| {
+            let handshake = state_dir::handshake(&dir);
+            (state_dir::read_only_reason(&dir, &handshake), handshake)
+        });
+        let save_drafts = config.assistant.save_drafts;
+        let project_context = config.assistant.project_context;
+        let mut app = Self::assemble(config, shell, pty_rx, event_sink, app_events, cols, rows)?;
+        app.set_performance(PerformanceMode::both(decision.on));
+        app.user_events = init_user_event();
+        app.size_settling_until = Some(Instant::now() + SIZE_SETTLE);
+        match handshake {
+            Some((Some(reason), _)) => {
+                tracing::warn!("{}", reason);
+                app.state_banner = Some(reason);
+            }
+            Some((None, Ok(Handshake::Migrated { from, backups }))) => app.tui_terminal.show_notice(format!(
+                "Saved state upgraded from schema {} (originals in {})",
+                from,
+                backups.display()
+            )),
+            _ => {}
+        }
+        app.ai_sessions.set_audit_log(AuditLog::open());
+        // The user's allowlist, owned by the first instance to open it
+        let allowlist = AllowlistStore::open().inspect_err(|e| tracing::warn!("No allowlist: {}", e)).ok();
+        app.ai_sessions.set_allowlist_store(allowlist);
+        app.tui_assistant.set_allowlist(app.ai_sessions.allowlist());
+        // Unsent input from the last run comes back in the first session
+        if save_drafts {
+            app.draft_store = DraftStore::open();
+            if let Some(draft) = app.draft_store.as_ref().and_then(|store| store.saved().to_restore()) {
+                app.tui_assistant.restore_draft(draft.clone());
+            }
+        }
+        // So does the split the separator was left at
+        app.layout_store = LayoutStore::open();
+        if let Some(ratio) = app.layout_store.as_ref().and_then(|store| store.saved().split_ratio) {
+            app.layout_builder = app.layout_builder.with_split_ratio(ratio);
+            app.rebuild_layout(app.layout.full_area);
+        }
+        // The shell starts in our directory, which may be in a project
+        app.project = ProjectTracker::open();
+        app.refresh_project();
+        app.context_manager.set_project_context(project_context);
+        app.refresh_project_context();
+        app.context_chips = true;
+        app.refresh_context_chips();
+        Ok(app)
+    }
+
+    /// An app on a scripted shell and scripted AI replies, for driving the
+    /// whole UI without a terminal, a PTY or the network. User events are
+    /// fed through [`App::on_user_event`] and queued output is handled by
+    /// [`App::process_pending`]. Project trust decisions are kept in memory.
+    pub fn scripted(config: Config, cols: u16, rows: u16) -> Result<(Self, ScriptedShell, ScriptedReplies)> {
+        let (event_sink, app_events) = init_app_eventsource();
+        let (shell, pty_rx, scripted_shell) = ScriptedShell::new(event_sink.clone());
+        let mut app = Self::assemble(config, shell, pty_rx, event_sink, app_events, cols, rows)?;
+        app.refresh_project();
+        let replies = app.ai_sessions.script_replies();
+        app.tui_assistant.set_setup_hint(app.ai_sessions.setup_hint());
+        Ok((app, scripted_shell, replies))
+    }
+
+    /// Wire up the app around a shell. User events start disconnected and
+    /// project trust decisions are kept in memory.
+    fn assemble(
+        config: Config,
+        shell: ShellManager,
+        pty_rx: Receiver<Vec<u8>>,
+        event_sink: UnboundedSender<Stamped<AppEvent>>,
+        app_events: UnboundedReceiver<Stamped<AppEvent>>,
+        cols: u16,
+        rows: u16,
+    ) -> Result<Self> {
 
         // Create layout builder with default preferences
-        let layout_builder = LayoutBuilder::new();
+        let layout_builder = LayoutBuilder::new()
+            .with_min_split_size(config.layout.split_min_width, config.layout.split_min_height);
 
         // Build initial layout from builder
         let initial_area = ratatui::layout::Rect {
@@ -106,27 +317,126 @@ impl App {
         };
         let initial_layout = layout_builder.build(initial_area);
 
-        Ok(Self {
+        // AiSessionManager now owns its own stream channel internally
+        let mut ai_sessions = AiSessionManager::new(event_sink.clone(), config.assistant.model.clone())?;
+        ai_sessions.set_provider(provider::from_config(
+            config.assistant.provider,
+            config.assistant.provider_url.as_deref(),
+            config.assistant.api_key.as_deref(),
+        ));
+        ai_sessions.set_templates(config.templates);
+        if let Some(dir) = prompt::templates_dir() {
+            ai_sessions.set_prompt_templates(TemplateStore::load(&dir));
+        }
+        ai_sessions.set_shell(shell.kind());
+        ai_sessions.set_mark_ai_commands(config.assistant.mark_ai_commands);
+        ai_sessions.set_safer_alternatives(config.assistant.safer_alternatives);
+        ai_sessions.set_quote_min_len(config.assistant.quote_min_len);
+        ai_sessions.set_sampling(Profiles {
+            command: config.assistant.command_temperature,
+            conversation: config.assistant.temperature,
+            effort: config.assistant.reasoning_effort,
+        });
+        if config.assistant.response_cache {
+            let max_age = Duration::from_secs(config.assistant.cache_max_age_days.saturating_mul(86_400));
+            ai_sessions.set_response_cache(Some(ResponseCache::open(max_age)));
+        }
+        ai_sessions.set_history_trimming(config.assistant.trim_strategy, config.assistant.summary_model.clone());
+        ai_sessions.set_history_budget(config.assistant.history_budget);
+        let request_timeout = Some(Duration::from_secs(config.assistant.request_timeout_secs)).filter(|t| !t.is_zero());
+        ai_sessions.set_request_mode(config.assistant.request_mode, request_timeout);
+        ai_sessions.set_max_retries(config.assistant.max_retries);
+        ai_sessions.set_session_names(config.assistant.session_names);
+        ai_sessions.set_extended_context(config.assistant.extended_context.then(|| Shell2Config {
+            timeout: Duration::from_millis(config.assistant.extended_context_timeout_ms),
+            max_context_bytes: config.assistant.extended_context_max_kb.saturating_mul(1024),
+        }));
+
+        let mut tui_assistant = TuiAssistant::new();
+        tui_assistant.set_local_answers(config.assistant.local_answers);
+        tui_assistant.set_prices(config.assistant.prices());
+        tui_assistant.set_vi_editing(config.assistant.input_editing == InputEditing::Vi);
+        tui_assistant.set_shell(shell.kind());
+        tui_assistant.set_mark_ai_commands(config.assistant.mark_ai_commands);
+        tui_assistant.set_copy_reasoning(config.assistant.copy_reasoning);
+        tui_assistant.set_enter_rejects_pending(config.assistant.enter_rejects_pending);
+        tui_assistant.set_file_drop(config.assistant.file_drop);
+        tui_assistant.set_setup_hint(ai_sessions.setup_hint());
+        crate::ui::visual::configure_clipboard(config.clipboard.osc52);
+
+        let mut context_manager = ContextManager::new();
+        context_manager.set_prompt_history(config.assistant.prompt_history);
+
+        let mut app = Self {
             shell_manager: shell,
-            // AiSessionManager now owns its own stream channel internally
-            ai_sessions: AiSessionManager::new(event_sink.clone(), "gpt-4o-mini")?,
+            ai_sessions,
             tui_terminal: TuiTerminal::new(pty_rx, event_sink.clone()),
-            tui_assistant: TuiAssistant::new(),
+            tui_assistant,
             active_pane: ActivePane::Terminal,
-            context_manager: ContextManager::new(),
+            context_manager,
             exit: false,
             command_mode: false,
+            command_mode_deadline: None,
             force_redraw_flag: false,
+            suspension: Suspension::default(),
             mouse_drag_state: None,
             separator_drag_state: None,
             last_click: None,
+            performance: PerformanceMode::default(),
+            hover_enabled: config.mouse.hover,
+            pointer: None,
+            hover: mouse_event::HoverState::default(),
+            context_chips: false,
+            app_event_tx: event_sink.clone(),
+            mark_ai_commands: config.assistant.mark_ai_commands,
+            running_suggestion: None,
+            failed_command: None,
+            batch: None,
             shell_input_buffer: String::new(),
-            next_frame_deadline: None,
+            frames: FramePacer::new(),
+            drawn_status: None,
+            size_settling_until: None,
+            keymap: config.keymap,
+            recording: config.recording,
+            dump: config.dump,
+            cues: Cues::new(config.cues),
+            project: ProjectTracker::new(TrustStore::in_memory(), std::env::var_os("HOME").map(Into::into)),
+            leader_pending: Vec::new(),
+            draft_store: None,
+            layout_store: None,
+            switcher: None,
+            mini: None,
+            help: None,
+            long_line_view: None,
+            quick_actions: config.quick_actions,
+            quick_run: None,
+            state_banner: None,
+            plugins: PluginRegistry::new(),
+            event_trace: EventTrace::new(),
+            show_event_trace: false,
+            metrics_history: MetricsHistory::new(
+                metrics().snapshot(),
+                config.metrics.log_interval(),
+                std::time::Instant::now(),
+            ),
+            next_metrics_sample: Instant::now() + metrics::SAMPLE_INTERVAL,
+            show_metrics: false,
             layout_builder,
             layout: initial_layout,
-            user_events: init_user_event(),
+            user_events: UserEvents::detached(),
             app_events,
-        })
+        };
+        if let Some(spare) = app.shell_manager.output_buffer_recycler() {
+            app.tui_terminal.recycle_output_buffers(spare);
+        }
+        let terminal = &config.terminal;
+        app.tui_terminal.set_repeated_lines(
+            terminal.collapse_repeats.then(|| RepeatedLines::new(terminal.repeat_threshold, terminal.max_repeats)),
+        );
+        app.tui_terminal.macros_mut().set_key_delay(Duration::from_millis(app.keymap.macro_delay_ms));
+        // Give the terminal and the shell the size of their pane
+        app.rebuild_layout(initial_area);
+        Ok(app)
     }
 
     pub fn get_active_pane(&self) -> ActivePane {
@@ -135,6 +445,12 @@ impl App {
 
     pub fn switch_pane(&mut self, pane: ActivePane) {
         self.active_pane = pane;
+        self.sync_layout_focus();
+    }
+
+    /// Keep the pane shown on small screens in step with the active one.
+    fn sync_layout_focus(&mut self) {
+        self.set_layout_builder(self.layout_builder.with_focus(self.active_pane));
     }
     pub fn toggle_pane(&mut self) {
         let next = match self.active_pane {
@@ -148,6 +464,108 @@ impl App {
         self.command_mode
     }
 
+    /// Check freshly suggested commands against what already ran in the shell.
+    fn note_prior_runs(&mut self, session_id: SessionId) {
+        let records = self.shell_manager.recent_command_records(usize::MAX);
+        let prior_runs = self.context_manager.prior_runs(&records);
+        self.ai_sessions.note_prior_runs(session_id, &prior_runs);
+        self.tui_assistant.set_prior_runs(prior_runs);
+    }
+
+    /// Mark and log a suggested command about to be sent to the shell. It
+    /// counts as running until the shell looks back at its prompt.
+    fn start_suggested_command(&mut self, cmd: &str, origin: Option<CommandOrigin>) {
+        self.start_command(cmd, origin);
+        self.running_suggestion = Some(cmd.to_string());
+        self.refresh_running_suggestion();
+    }
+
+    /// Mark and log a command we are about to type into the shell.
+    fn start_command(&mut self, cmd: &str, origin: Option<CommandOrigin>) {
+        let position = self.tui_terminal.mark_command_start(cmd);
+        self.shell_manager.start_new_command(cmd.to_string(), origin);
+        if let Some(position) = position {
+            self.shell_manager.set_command_position(position);
+        }
+    }
+
+    /// Forget the suggested command sent last once it no longer seems to
+    /// run, and tell the assistant whether it does.
+    fn refresh_running_suggestion(&mut self) {
+        if let Some(command) = &self.running_suggestion
+            && self.tui_terminal.command_running(command) != Some(true)
+        {
+            self.running_suggestion = None;
+        }
+        self.tui_assistant.set_running_ai_command(self.running_suggestion.clone());
+        self.poll_batch();
+    }
+
+    /// Run the steps of a batch accepted in `session_id`, from the first.
+    fn start_batch(&mut self, session_id: SessionId, steps: Vec<BatchStep>) -> Result<()> {
+        let mut batch = Batch::new(session_id, steps);
+        let progress = batch.next_step();
+        self.batch = Some(batch);
+        self.advance_batch(progress)
+    }
+
+    /// Stop the batch if its running step can't be followed. The step ends
+    /// with its ShellCommandCompleted, which without shell integration
+    /// comes once the step is back at the prompt; that takes the prompt
+    /// the step was typed at to be known.
+    fn poll_batch(&mut self) {
+        let Some(typed) = self.batch.as_ref().and_then(Batch::running).map(str::to_string) else {
+            return;
+        };
+        if self.shell_manager.has_shell_integration() || self.tui_terminal.command_running(&typed).is_some() {
+            return;
+        }
+        // The next step must not be typed into a command still running
+        let progress = self.batch.as_mut().map(Batch::lost);
+        if let Some(progress) = progress
+            && let Err(e) = self.advance_batch(progress)
+        {
+            tracing::error!("Batch step failed to start: {:?}", e);
+        }
+    }
+
+    /// Act on what the batch does next: send a step through the security
+    /// gate (a denied step stops the batch), or end it.
+    fn advance_batch(&mut self, mut progress: BatchProgress) -> Result<()> {
+        let Some(mut batch) = self.batch.take() else {
+            return Ok(());
+        };
+        loop {
+            match progress {
+                BatchProgress::Run(step) => {
+                    let execution =
+                        Execution { command: &step.command, cwd: step.cwd.as_deref(), mark: self.mark_ai_commands };
+                    let (composed, decision) = self.gate(execution);
+                    if let ExecutionDecision::Deny { reason } = decision {
+                        progress = batch.failed(step, StepFailure::Denied(reason));
+                        continue;
+                    }
+                    let origin = CommandOrigin { session_id: batch.session_id(), tool_call_id: step.tool_call_id.clone() };
+                    self.try_execute_suggested(&step.command, step.cwd.as_deref(), false, Some(origin))?;
+                    batch.started(step, composed.text);
+                    self.batch = Some(batch);
+                    return Ok(());
+                }
+                BatchProgress::Done => return Ok(()),
+                BatchProgress::Stopped { failed, failure, skipped } => {
+                    let mut notice = format!("Stopped at `{}`: {}", failed.command, failure.describe());
+                    if !skipped.is_empty() {
+                        notice.push_str(&format!("; {} more not run", skipped.len()));
+                    }
+                    self.tui_assistant.show_notice(notice);
+                    self.ai_sessions.batch_stopped(batch.session_id(), &failed.tool_call_id, failure, &skipped);
+                    self.sync_sessions();
+                    return Ok(());
+                }
+            }
+        }
+    }
+
     /// Single execution entrypoint that enforces security verdict gating.
     ///
     /// This method is the ONLY way commands should be executed from AI suggestions.
@@ -158,13 +576,22 @@ impl App {
     ///
     /// # Arguments
     /// * `cmd` - The command string to execute
+    /// * `cwd` - Directory to run it in (None = the shell's own)
+    /// * `interrupt_first` - Send Ctrl+C before it, to stop the command
+    ///   running in the foreground (only if the command itself may run)
+    /// * `origin` - The tool call that suggested it, reported back with
+    ///   the command once it finishes
+    ///
+    /// The text sent to the shell is composed here: a quoted `cd` into
+    /// `cwd`, then the AI marker if enabled, then the command, as shown on
+    /// its card. The directory is checked against the scope policy too.
     ///
     /// # Returns
     /// * `Ok(())` if the command was handled appropriately
     /// * `Err(_)` if execution failed
     ///
     /// # Behavior by Verdict
-    /// - `Allow`: Executes immediately via `execute_visible()`
+    /// - `Allow`: Executes immediately via `ShellManager::submit_command()`
     /// - `RequireConfirmation`: Returns Ok without executing (UI handles confirmation)
     /// - `Deny`: Returns Ok without executing and surfaces error to UI
     ///
@@ -173,36 +600,46 @@ impl App {
     /// # use rusty_term::app::App;
     /// # fn example(app: &mut App) -> anyhow::Result<()> {
     /// // Safe command - executes immediately
-    /// app.try_execute_suggested("ls -la")?;
+    /// app.try_execute_suggested("ls -la", None, false, None)?;
     ///
     /// // Dangerous command - denied, error shown to user
-    /// app.try_execute_suggested("rm -rf /")?;
+    /// app.try_execute_suggested("rm -rf /", None, false, None)?;
     /// # Ok(())
     /// # }
     /// ```
-    pub fn try_execute_suggested(&mut self, cmd: &str) -> Result<()> {
-        // Evaluate the command to get its security verdict
-        let verdict = evaluate(cmd);
-
-        // Gate the command based on its verdict
-        let decision = gate_command(cmd, &verdict);
+    pub fn try_execute_suggested(
+        &mut self,
+        cmd: &str,
+        cwd: Option<&str>,
+        interrupt_first: bool,
+        origin: Option<CommandOrigin>,
+    ) -> Result<()> {
+        let (composed, decision) = self.gate(Execution { command: cmd, cwd, mark: self.mark_ai_commands });
 
         match decision {
             ExecutionDecision::Execute => {
                 // Allow verdict: execute immediately
+                if interrupt_first {
+                    self.shell_manager.handle_user_input(b"\x03")?;
+                }
+                self.start_suggested_command(&composed.text, origin);
                 self.shell_manager
-                    .execute_visible(cmd)
+                    .submit_command(&composed.text, Submit::Execute)
                     .context("Failed to execute allowed command")?;
             }
             ExecutionDecision::RequireConfirmation { reason } => {
                 // User already confirmed via Ctrl+Y, execute the command
                 let _ = reason;
+                if interrupt_first {
+                    self.shell_manager.handle_user_input(b"\x03")?;
+                }
+                self.start_suggested_command(&composed.text, origin);
                 self.shell_manager
-                    .execute_visible(cmd)
+                    .submit_command(&composed.text, Submit::Execute)
                     .context("Failed to execute confirmed command")?;
             }
             ExecutionDecision::Deny { reason } => {
-                // Deny verdict: do not execute, surface error to UI
+                // Deny verdict: do not execute (nor interrupt), surface error to UI
                 self.tui_terminal.show_error(&format!("Command denied: {}", reason));
             }
         }
@@ -210,12 +647,294 @@ impl App {
         Ok(())
     }
 
+    /// The text typed for `execution` and what the security gate decides
+    /// about it. Its verdict comes from the command's findings (project
+    /// rules and plugins can only make it stricter, the allowlist only
+    /// lifts confirmations) and the directory it runs in.
+    fn gate(&mut self, execution: Execution<'_>) -> (Composed, ExecutionDecision) {
+        let composed = execution.compose(self.shell_manager.kind());
+        let policy = Policy { rules: self.project_rules(), allowlist: self.ai_sessions.allowlist() };
+        let ctx = PluginCtx { cwd: &self.context_manager.cwd.path, project: self.project.active() };
+        let mut findings = policy.findings(execution.command);
+        findings.extend(self.plugins.findings(execution.command, &ctx));
+        let verdict = verdict_from(&composed_findings(findings, &composed, execution.cwd));
+        let decision = gate_command(&composed.text, &verdict);
+        (composed, decision)
+    }
+
     pub fn set_command_mode(&mut self, flag: bool) {
         self.command_mode = flag;
+        if flag {
+            self.arm_command_timeout();
+        } else {
+            self.command_mode_deadline = None;
+            self.tui_terminal.macros_mut().clear_pending();
+        }
     }
 
     pub fn toggle_command_mode(&mut self) {
-        self.command_mode = !self.command_mode;
+        self.set_command_mode(!self.command_mode);
+    }
+
+    /// Trusted project file whose settings are in effect.
+    pub fn active_project(&self) -> Option<&Project> {
+        self.project.active()
+    }
+
+    /// Project file waiting for the user to trust or ignore it.
+    pub fn pending_project(&self) -> Option<&Project> {
+        self.project.pending()
+    }
+
+    /// Add a compiled-in plugin; its hooks run after those of the plugins
+    /// registered before it. See [`crate::plugin`] for what hooks may do.
+    pub fn register_plugin(&mut self, plugin: impl Plugin + 'static) {
+        self.plugins.register(Box::new(plugin));
+    }
+
+    /// Status texts of the plugins, in registration order.
+    pub fn plugin_status(&self) -> Vec<String> {
+        self.plugins.status_segments()
+    }
+
+    fn project_rules(&self) -> ProjectRules {
+        self.project.active().map(|p| p.config.security.clone()).unwrap_or_default()
+    }
+
+    /// Look for a project file again after the shell changed directory.
+    fn refresh_project(&mut self) {
+        let cwd = std::path::PathBuf::from(&self.context_manager.cwd.path);
+        if cwd.is_absolute() {
+            let change = self.project.update_cwd(&cwd);
+            self.on_project_change(change);
+        }
+    }
+
+    /// Look at the README and manifests of the shell's directory again, off
+    /// the UI thread; a [`AppEvent::ProjectRead`] comes back if they changed.
+    fn refresh_project_context(&mut self) {
+        let Some((dir, known)) = self.context_manager.project_refresh() else {
+            return;
+        };
+        let tx = self.app_event_tx.clone();
+        tokio::spawn(async move {
+            if let Some((stamp, project)) = crate::context::project::load(dir, known).await
+                && let Err(e) = tx.send(Stamped::new(EventOrigin::Shell, AppEvent::ProjectRead { stamp, project }))
+            {
+                tracing::warn!("Failed to send the project files: {}", e);
+            }
+        });
+    }
+
+    /// Update the chips above the assistant input from the current context.
+    fn refresh_context_chips(&mut self) {
+        if !self.context_chips {
+            return;
+        }
+        let snapshot = self.context_manager.snapshot_with_commands(self.shell_manager.recent_command_records(10));
+        let summary = ContextSummary::from_snapshot(&snapshot, self.context_manager.cwd.git_branch());
+        self.tui_assistant.set_context_summary(summary);
+    }
+
+    /// Answer the trust prompt: `y` trusts the file, `n` or Esc ignores it
+    /// (both remembered for this version of the file). Other keys are
+    /// swallowed while the prompt is open.
+    fn handle_trust_key(&mut self, key: KeyEvent) {
+        if !matches!(key.kind, KeyEventKind::Press) {
+            return;
+        }
+        let trusted = match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => true,
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => false,
+            _ => return,
+        };
+        let change = self.project.answer(trusted);
+        self.on_project_change(change);
+    }
+
+    /// Why persisted state is read-only this run, until dismissed.
+    pub fn state_banner(&self) -> Option<&str> {
+        self.state_banner.as_deref()
+    }
+
+    /// Enter or Esc dismisses the read-only banner (for this run only: it
+    /// comes back on every start until the data directory is fixed). Other
+    /// keys are swallowed while it is up.
+    fn handle_state_banner_key(&mut self, key: KeyEvent) {
+        if matches!(key.kind, KeyEventKind::Press) && matches!(key.code, KeyCode::Enter | KeyCode::Esc) {
+            self.state_banner = None;
+        }
+    }
+
+    /// The user's own command mode keys, in the order configured.
+    pub fn quick_actions(&self) -> &[QuickAction] {
+        &self.quick_actions
+    }
+
+    /// Quick action command waiting for a yes.
+    pub fn quick_run(&self) -> Option<&QuickRun> {
+        self.quick_run.as_ref()
+    }
+
+    /// Do what quick action `index` says.
+    fn run_quick_action(&mut self, index: usize) -> Result<()> {
+        let Some(action) = self.quick_actions.get(index).cloned() else {
+            return Ok(());
+        };
+        match action.kind {
+            QuickActionKind::InsertText(text) => {
+                self.switch_pane(ActivePane::Assistant);
+                self.tui_assistant.delete_input_selection();
+                for c in text.chars() {
+                    self.tui_assistant.insert_char(c);
+                }
+            }
+            QuickActionKind::RunShell { command, confirm } => self.start_quick_run(action.name, &command, confirm)?,
+            QuickActionKind::AskAi(template) => {
+                let question = self.quick_action_placeholders().expand(&template);
+                self.switch_pane(ActivePane::Assistant);
+                if self.tui_assistant.is_streaming() {
+                    self.tui_assistant.show_notice(format!("{}: wait for the reply to finish", action.name));
+                } else if question.trim().is_empty() {
+                    self.tui_assistant.show_notice(format!("{}: nothing to ask", action.name));
+                } else {
+                    assistant_event::send_question(
+                        &mut self.tui_assistant,
+                        &mut self.ai_sessions,
+                        &self.context_manager,
+                        &self.shell_manager,
+                        question,
+                        false,
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Values of the placeholders in a quick action's question, now: the
+    /// last command run and the tail of its output, the active pane's
+    /// selection and the shell's directory.
+    fn quick_action_placeholders(&self) -> Placeholders {
+        let last = self.shell_manager.recent_command_records(1).pop();
+        let selection = match self.active_pane {
+            ActivePane::Terminal => self.tui_terminal.selected_text(),
+            ActivePane::Assistant => {
+                self.tui_assistant.selected_text().or_else(|| self.tui_assistant.get_input_selected_text())
+            }
+        };
+        Placeholders {
+            last_command: last.as_ref().map(|record| record.command_line.clone()).unwrap_or_default(),
+            last_output: last
+                .map(|record| truncate_output(&self.context_manager.full_output(&record), OUTPUT_BUDGET))
+                .unwrap_or_default(),
+            selection: selection.unwrap_or_default(),
+            cwd: self.context_manager.cwd.path.clone(),
+        }
+    }
+
+    /// Type a quick action's command into the shell, through the security
+    /// gate: right away if the gate allows it and the action doesn't ask
+    /// for a yes, after one otherwise. A denied command only shows why.
+    fn start_quick_run(&mut self, name: String, command: &str, confirm: bool) -> Result<()> {
+        let (composed, decision) = self.gate(Execution { command, cwd: None, mark: false });
+        let reason = match decision {
+            ExecutionDecision::Deny { reason } => {
+                self.tui_terminal.show_error(&format!("Command denied: {}", reason));
+                return Ok(());
+            }
+            ExecutionDecision::Execute if !confirm => return self.run_quick_command(&composed.text),
+            ExecutionDecision::Execute => None,
+            ExecutionDecision::RequireConfirmation { reason } => Some(reason),
+        };
+        self.quick_run = Some(QuickRun { name, command: composed.text, reason });
+        Ok(())
+    }
+
+    /// Answer the quick action prompt: `y` runs the command, `n` or Esc
+    /// drops it. Other keys are swallowed while the prompt is open.
+    fn handle_quick_run_key(&mut self, key: KeyEvent) -> Result<()> {
+        if !matches!(key.kind, KeyEventKind::Press) {
+            return Ok(());
+        }
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => match self.quick_run.take() {
+                Some(run) => self.run_quick_command(&run.command),
+                None => Ok(()),
+            },
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                self.quick_run = None;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Type a quick action's command into the shell, which comes to the front.
+    fn run_quick_command(&mut self, text: &str) -> Result<()> {
+        self.switch_pane(ActivePane::Terminal);
+        self.start_command(text, None);
+        self.shell_manager
+            .submit_command(text, Submit::Execute)
+            .context("Failed to run quick action")?;
+        Ok(())
+    }
+
+    /// Lay the active project's settings over the global ones (or remove them).
+    fn on_project_change(&mut self, change: ProjectChange) {
+        if let ProjectChange::Invalid(message) = &change {
+            self.tui_terminal.show_error(message);
+        }
+        if change == ProjectChange::Unchanged {
+            return;
+        }
+        let config = self.project.active().map(|p| p.config.clone());
+        self.tui_assistant.set_project_rules(self.project_rules());
+        self.ai_sessions.set_project(config);
+    }
+
+    /// Keymap preferences in effect (leader, command mode timeout).
+    pub fn keymap(&self) -> &KeymapConfig {
+        &self.keymap
+    }
+
+    /// Time left before command mode auto-exits, if a timeout is armed.
+    pub fn command_mode_remaining(&self) -> Option<Duration> {
+        self.command_mode_deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// (Re)start the command mode timeout, if one is configured.
+    ///
+    /// Sub-prompts opened from command mode should clear `command_mode_deadline`
+    /// so the timeout cannot fire while the user is typing into them.
+    fn arm_command_timeout(&mut self) {
+        self.command_mode_deadline = self
+            .keymap
+            .command_timeout_ms
+            .map(|ms| Instant::now() + Duration::from_millis(ms));
+    }
+
+    /// Next wake-up for the command mode countdown: the deadline itself, or
+    /// sooner so the countdown in the title bar keeps ticking.
+    fn command_mode_tick(&self) -> Instant {
+        const TICK: Duration = Duration::from_millis(250);
+        let now = Instant::now();
+        match self.command_mode_deadline {
+            Some(deadline) => deadline.min(now + TICK),
+            None => now + TICK,
+        }
+    }
+
+    /// Called from the main loop while a command mode timeout is armed.
+    fn on_command_mode_tick(&mut self) {
+        if let Some(deadline) = self.command_mode_deadline {
+            if Instant::now() >= deadline {
+                self.set_command_mode(false);
+            }
+            // Redraw to update the countdown (or remove the popup)
+            self.request_decoration_draw();
+        }
     }
 
     /// Check if the active pane is in visual mode.
@@ -235,6 +954,575 @@ impl App {
         }
     }
 
+    /// Ring of the most recently processed events.
+    pub fn event_trace(&self) -> &EventTrace {
+        &self.event_trace
+    }
+
+    /// Is the pane separator under the mouse pointer?
+    pub fn is_separator_hovered(&self) -> bool {
+        self.hover.separator
+    }
+
+    /// Re-run hover hit-testing for the last pointer position.
+    ///
+    /// Call after each frame (hit areas are rebuilt during render) and after
+    /// pointer motion. Returns true if the hovered element changed.
+    fn refresh_hover(&mut self) -> bool {
+        let hover = match self.pointer {
+            Some((col, row)) if self.hover_enabled && !self.performance.any() => {
+                mouse_event::hover_at(&self.layout, &self.tui_assistant, col, row)
+            }
+            _ => mouse_event::HoverState::default(),
+        };
+        if hover == self.hover {
+            return false;
+        }
+        self.hover = hover;
+        // The assistant applies its part of the hover while rendering
+        self.tui_assistant.set_hover(hover.tab, hover.card_button);
+        true
+    }
+
+    /// The quick switcher, if it is open.
+    pub fn switcher(&self) -> Option<&Switcher> {
+        self.switcher.as_ref()
+    }
+
+    /// Session shown in the assistant pane.
+    pub fn active_session(&self) -> SessionId {
+        self.ai_sessions.current_session_id()
+    }
+
+    /// Open the quick switcher over the sessions and the shell.
+    pub fn open_switcher(&mut self) {
+        let now = Instant::now();
+        let mut entries: Vec<SwitchEntry> = self
+            .ai_sessions
+            .get_session_tabs()
+            .iter()
+            .map(|tab| SwitchEntry::session(tab, now))
+            .collect();
+        let last_command = self.shell_manager.recent_command_records(1).pop().map(|record| record.command_line);
+        entries.push(SwitchEntry::shell(self.shell_manager.kind().label(), last_command.as_deref()));
+        self.switcher = Some(Switcher::new(entries));
+    }
+
+    /// Jump to a switcher entry: activate its pane and, for a session, show it.
+    pub fn switch_to(&mut self, target: SwitchTarget) {
+        match target {
+            SwitchTarget::Session(session_id) => {
+                self.ai_sessions.switch_session(session_id);
+                self.switch_pane(ActivePane::Assistant);
+            }
+            SwitchTarget::Shell => self.switch_pane(ActivePane::Terminal),
+        }
+    }
+
+    /// Keys while the switcher is open: typing filters, arrows or
+    /// Ctrl+N/Ctrl+P move, Enter switches, Esc cancels.
+    fn handle_switcher_key(&mut self, key: KeyEvent) {
+        if !matches!(key.kind, KeyEventKind::Press) {
+            return;
+        }
+        let Some(switcher) = &mut self.switcher else {
+            return;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.switcher = None,
+            KeyCode::Enter => {
+                let target = switcher.selected_target();
+                self.switcher = None;
+                if let Some(target) = target {
+                    self.switch_to(target);
+                }
+            }
+            KeyCode::Up | KeyCode::BackTab => switcher.move_selection(-1),
+            KeyCode::Down | KeyCode::Tab => switcher.move_selection(1),
+            KeyCode::Char('p') if ctrl => switcher.move_selection(-1),
+            KeyCode::Char('n') if ctrl => switcher.move_selection(1),
+            KeyCode::Backspace => switcher.pop_char(),
+            KeyCode::Char(c) if !ctrl => switcher.push_char(c),
+            _ => {}
+        }
+    }
+
+    /// The mini assistant, if it is open.
+    pub fn mini_assistant(&self) -> Option<&MiniAssistant> {
+        self.mini.as_ref()
+    }
+
+    /// Open the mini assistant over the terminal, on a session of its own.
+    pub fn open_mini_assistant(&mut self) {
+        match self.ai_sessions.new_ephemeral_session() {
+            Ok(session_id) => self.mini = Some(MiniAssistant::new(session_id)),
+            Err(e) => self.tui_terminal.show_error(&format!("Failed to open the mini assistant: {}", e)),
+        }
+    }
+
+    /// Where the mini assistant is drawn: over the terminal pane, or the
+    /// whole screen when the pane is too narrow for it.
+    pub fn mini_assistant_area(&self) -> ratatui::layout::Rect {
+        let pane = self.layout.terminal_area;
+        if pane.width >= 30 && pane.height >= 5 { pane } else { self.layout.full_area }
+    }
+
+    /// Keys while the mini assistant is open: typing edits the question,
+    /// Enter asks it, arrows scroll the reply, Ctrl+Y/Ctrl+N decide on a
+    /// suggested command, Ctrl+T keeps the session as a tab and Esc drops it.
+    fn handle_mini_key(&mut self, key: KeyEvent) -> Result<()> {
+        if !matches!(key.kind, KeyEventKind::Press) {
+            return Ok(());
+        }
+        let Some(mini) = &mut self.mini else {
+            return Ok(());
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let page = MAX_ANSWER_ROWS as isize;
+        match key.code {
+            KeyCode::Esc => self.close_mini_assistant(),
+            KeyCode::Enter => self.ask_mini_assistant(),
+            KeyCode::Char('t') if ctrl => self.promote_mini_assistant(),
+            KeyCode::Char('y') if ctrl => self.run_mini_suggestion()?,
+            KeyCode::Char('n') if ctrl => self.reject_mini_suggestion(),
+            KeyCode::Up => mini.scroll(-1),
+            KeyCode::Down => mini.scroll(1),
+            KeyCode::PageUp => mini.scroll(-page),
+            KeyCode::PageDown => mini.scroll(page),
+            KeyCode::Backspace => mini.pop_char(),
+            KeyCode::Char(c) if !ctrl => mini.push_char(c),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Close the mini assistant and drop its session.
+    fn close_mini_assistant(&mut self) {
+        if let Some(mini) = self.mini.take() {
+            self.ai_sessions.discard_session(mini.session_id());
+        }
+    }
+
+    /// Keep the mini assistant's session as a tab and show it in the
+    /// assistant pane, the reply still streaming if it is.
+    fn promote_mini_assistant(&mut self) {
+        if let Some(mini) = self.mini.take()
+            && self.ai_sessions.promote_session(mini.session_id())
+        {
+            self.switch_pane(ActivePane::Assistant);
+        }
+    }
+
+    /// Send the question typed in the mini assistant, with the same shell
+    /// context as one from the assistant pane. One reply at a time.
+    fn ask_mini_assistant(&mut self) {
+        let Some(mini) = &mut self.mini else {
+            return;
+        };
+        if mini.is_replying() {
+            return;
+        }
+        let Some(question) = mini.take_question() else {
+            return;
+        };
+        let session_id = mini.session_id();
+        // A card still waiting is rejected first, as in the assistant pane
+        if let Some((tool_call_id, _)) = mini.pending_suggestion()
+            && let Err(e) = self.ai_sessions.reject_suggestion(session_id, &tool_call_id)
+        {
+            tracing::debug!("Mini assistant card out of date: {}", e);
+        }
+        let context = self.context_manager.snapshot_with_commands(self.shell_manager.recent_command_records(10));
+        self.ai_sessions.send_message(session_id, &question, context);
+    }
+
+    /// Ctrl+Y in the mini assistant: have the app run the waiting card's
+    /// command through the security gate, like Ctrl+Y in the assistant
+    /// pane. A command the verdict denies is not run.
+    fn run_mini_suggestion(&mut self) -> Result<()> {
+        let Some(mini) = &mut self.mini else {
+            return Ok(());
+        };
+        let Some((tool_call_id, denied)) = mini.pending_suggestion() else {
+            return Ok(());
+        };
+        if denied {
+            mini.show_notice("Not run: the safety check denies it (Ctrl+T opens it in a tab to copy)".to_string());
+            return Ok(());
+        }
+        let session_id = mini.session_id();
+        match self.ai_sessions.accept_suggestion(session_id, &tool_call_id) {
+            Ok(command) => {
+                let cwd = self.ai_sessions.suggestion_cwd(session_id, &tool_call_id);
+                self.ai_sessions.execute_suggestion(session_id, &tool_call_id, command, cwd, false)?;
+            }
+            Err(e) => mini.show_notice(e.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Ctrl+N in the mini assistant: reject the suggestions waiting.
+    fn reject_mini_suggestion(&mut self) {
+        let Some(mini) = &mut self.mini else {
+            return;
+        };
+        let Some((tool_call_id, _)) = mini.pending_suggestion() else {
+            return;
+        };
+        if let Err(e) = self.ai_sessions.reject_suggestion(mini.session_id(), &tool_call_id) {
+            mini.show_notice(e.to_string());
+        }
+    }
+
+    /// The help overlay, if it is open.
+    pub fn help(&self) -> Option<&Help> {
+        self.help.as_ref()
+    }
+
+    /// Open the help overlay for the active pane, the keys of the mode it
+    /// is in first.
+    pub fn open_help(&mut self) {
+        let browsing = match self.active_pane {
+            ActivePane::Terminal => self.tui_terminal.is_scrolled(),
+            ActivePane::Assistant => self.tui_assistant.is_scrolled(),
+        };
+        let current = if self.is_visual_mode() {
+            Category::Visual
+        } else if browsing {
+            Category::Browsing
+        } else {
+            Category::Command
+        };
+        self.help = Some(Help::new(self.keymap.leader.clone(), self.active_pane, current));
+    }
+
+    /// Keys while the help is open: typing filters, arrows and PgUp/PgDn
+    /// scroll, Esc or `?` closes.
+    fn handle_help_key(&mut self, key: KeyEvent) {
+        if !matches!(key.kind, KeyEventKind::Press) {
+            return;
+        }
+        let Some(help) = &mut self.help else {
+            return;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('?') => self.help = None,
+            KeyCode::Up => help.scroll(-1),
+            KeyCode::Down => help.scroll(1),
+            KeyCode::PageUp => help.scroll(-10),
+            KeyCode::PageDown => help.scroll(10),
+            KeyCode::Backspace => help.pop_char(),
+            KeyCode::Char(c) if !ctrl => help.push_char(c),
+            _ => {}
+        }
+    }
+
+    /// The long line viewer and its line, if it is open.
+    pub fn long_line_view(&self) -> Option<(&LongLineView, &LongLine)> {
+        let view = self.long_line_view.as_ref()?;
+        Some((view, self.tui_terminal.long_line(view.id)?))
+    }
+
+    /// Open the viewer on the lowest long line marked on screen, or the
+    /// most recent one.
+    pub fn open_long_line(&mut self) {
+        match self.tui_terminal.shown_long_line() {
+            Some(id) => self.long_line_view = Some(LongLineView::new(id)),
+            None => self.tui_terminal.show_notice("No long line to open".to_string()),
+        }
+    }
+
+    /// Keys while the long line viewer is open: j/k and the arrows scroll
+    /// a row, PgUp/PgDn/Space a page, g/G to either end, y copies the rows
+    /// shown, q or Esc closes.
+    fn handle_long_line_key(&mut self, key: KeyEvent) {
+        if !matches!(key.kind, KeyEventKind::Press) {
+            return;
+        }
+        let (_, inner) = LongLineView::areas(self.layout.full_area);
+        let Some(view) = &mut self.long_line_view else {
+            return;
+        };
+        // The line may have been dropped for newer ones
+        let Some(line) = self.tui_terminal.long_line(view.id) else {
+            self.long_line_view = None;
+            return;
+        };
+        let page = inner.height.max(1) as isize;
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => self.long_line_view = None,
+            KeyCode::Down | KeyCode::Char('j') => view.scroll(line, inner, 1),
+            KeyCode::Up | KeyCode::Char('k') => view.scroll(line, inner, -1),
+            KeyCode::PageDown | KeyCode::Char(' ') => view.scroll(line, inner, page),
+            KeyCode::PageUp => view.scroll(line, inner, -page),
+            KeyCode::Home | KeyCode::Char('g') => view.scroll_to_start(),
+            KeyCode::End | KeyCode::Char('G') => view.scroll_to_end(),
+            KeyCode::Char('y') => {
+                let text = view.visible_text(line, inner);
+                let rows = text.lines().count();
+                let notice = match copy_to_clipboard(&text) {
+                    CopyOutcome::Clipboard => format!("Copied {} rows of the long line", rows),
+                    CopyOutcome::Osc52 => format!("Copied {} rows of the long line (OSC 52)", rows),
+                    CopyOutcome::Unavailable => "Cannot copy the long line: clipboard unavailable".to_string(),
+                };
+                self.tui_terminal.show_notice(notice);
+            }
+            _ => {}
+        }
+    }
+
+    /// Start recording the terminal pane to a cast file, or stop and
+    /// finish the running recording. The outcome is shown in the pane title.
+    pub fn toggle_recording(&mut self) {
+        if self.tui_terminal.is_recording() {
+            self.stop_recording();
+            return;
+        }
+        let Some(dir) = self.recording.dir() else {
+            self.tui_terminal.show_error("Cannot record: no directory for recordings (set [recording] dir)");
+            return;
+        };
+        let (cols, rows) = self.tui_terminal.size();
+        match CastRecorder::start(&dir, cols, rows, self.recording.record_input) {
+            Ok(recorder) => self.tui_terminal.start_recording(recorder),
+            Err(e) => self.tui_terminal.show_error(&format!("Cannot record to {}: {}", dir.display(), e)),
+        }
+    }
+
+    /// Finish the running recording, if any (also called on exit).
+    pub fn stop_recording(&mut self) {
+        let Some(recorder) = self.tui_terminal.stop_recording() else {
+            return;
+        };
+        let path = recorder.path().to_path_buf();
+        match recorder.stop() {
+            Ok(path) => self.tui_terminal.show_notice(format!("Saved {}", path.display())),
+            Err(e) => self.tui_terminal.show_error(&format!("Recording to {} failed: {}", path.display(), e)),
+        }
+    }
+
+    /// Read the assistant's backend from the config file again (`provider`,
+    /// `provider_url`, `api_key` and `model`), so that a key added after
+    /// starting unlocks the assistant. The rest of the config still takes a
+    /// restart, and so does a change to the environment we started in.
+    pub fn reload_config(&mut self) {
+        let assistant = Config::load().assistant;
+        self.ai_sessions.set_model(assistant.model);
+        self.ai_sessions.set_provider(provider::from_config(
+            assistant.provider,
+            assistant.provider_url.as_deref(),
+            assistant.api_key.as_deref(),
+        ));
+        let hint = self.ai_sessions.setup_hint();
+        let said = if hint.is_some() { "Config reloaded: still no API key" } else { "Config reloaded" };
+        self.tui_assistant.show_notice(said.to_string());
+        self.tui_assistant.set_setup_hint(hint);
+    }
+
+    /// A key typed in command mode, for the keyboard macros: `q` stops a
+    /// recording from either pane; in the terminal pane `q`, `@`, a count
+    /// and the keys after them are macro keys. Returns whether it was one.
+    fn macro_command_key(&mut self, key: &KeyEvent) -> bool {
+        let macros = self.tui_terminal.macros();
+        let KeyCode::Char(c) = key.code else {
+            // Not a register: drop the q, @ or count before it
+            let pending = macros.is_pending();
+            if pending {
+                self.set_command_mode(false);
+            }
+            return pending;
+        };
+        let stops = c == 'q' && macros.recording().is_some();
+        if self.active_pane != ActivePane::Terminal && !stops {
+            return false;
+        }
+        // A key of the user's own wins over starting a count
+        if !macros.is_pending() && c.is_ascii_digit() && self.quick_actions.iter().any(|action| action.key.matches(key)) {
+            return false;
+        }
+        match self.tui_terminal.macros_mut().command_key(c) {
+            MacroStep::Ignored => return false,
+            MacroStep::Pending => {
+                self.arm_command_timeout();
+                return true;
+            }
+            MacroStep::Recording(_) => {}
+            MacroStep::Recorded(register, keys) => {
+                self.tui_terminal.show_notice(format!("Recorded {} keys in @{}", keys, register));
+            }
+            MacroStep::Play { register, count, force } => self.play_macro(register, count, force),
+            MacroStep::NoRegister => self.tui_terminal.show_notice("Registers are a to z".to_string()),
+        }
+        self.set_command_mode(false);
+        true
+    }
+
+    /// Start replaying the keys of `register` `count` times. Not while a
+    /// full-screen program has the terminal, unless `force`d: the keys were
+    /// most likely recorded at the prompt.
+    fn play_macro(&mut self, register: char, count: u32, force: bool) {
+        if self.tui_terminal.is_alt_screen() && !force {
+            self.tui_terminal.show_notice(format!("Full screen: @{} to replay anyway", register.to_ascii_uppercase()));
+            return;
+        }
+        if let Err(e) = self.tui_terminal.macros_mut().play(register, count, Instant::now()) {
+            self.tui_terminal.show_notice(e);
+            return;
+        }
+        if let Err(e) = self.replay_due_keys() {
+            tracing::warn!("Failed to replay @{}: {}", register, e);
+        }
+    }
+
+    /// Send the keys of the macro being replayed that are due. Returns
+    /// whether there were any.
+    fn replay_due_keys(&mut self) -> Result<bool> {
+        let keys = self.tui_terminal.macros_mut().take_due(Instant::now());
+        for key in &keys {
+            terminal_event::replay_key(&mut self.tui_terminal, &mut self.shell_manager, *key, &mut self.shell_input_buffer)?;
+        }
+        Ok(!keys.is_empty())
+    }
+
+    /// Copy the text on the terminal screen to the clipboard, reporting the
+    /// line count in the pane title.
+    pub fn copy_screen(&mut self) {
+        let (text, lines) = self.tui_terminal.visible_text();
+        let notice = match copy_to_clipboard(&text) {
+            CopyOutcome::Clipboard => format!("Copied {} lines to the clipboard", lines),
+            CopyOutcome::Osc52 => format!("Copied {} lines to the clipboard (OSC 52)", lines),
+            CopyOutcome::Unavailable => "Cannot copy the screen: clipboard unavailable".to_string(),
+        };
+        self.tui_terminal.show_notice(notice);
+    }
+
+    /// Ask the active session what the terminal screen shows: the live
+    /// screen, or the part of the scrollback in view when scrolled back.
+    /// Not while a full-screen program has the screen, nor while a reply is
+    /// coming in.
+    pub fn describe_screen(&mut self) {
+        let scrolled_back = self.tui_terminal.is_scrolled();
+        if self.tui_terminal.is_alt_screen() && !scrolled_back {
+            self.tui_terminal.show_notice("Cannot describe the screen of a full-screen program".to_string());
+            return;
+        }
+        let (text, _) = self.tui_terminal.visible_text();
+        if text.trim().is_empty() {
+            self.tui_terminal.show_notice("Nothing on the screen to describe".to_string());
+            return;
+        }
+        self.switch_pane(ActivePane::Assistant);
+        if self.tui_assistant.is_streaming() {
+            self.tui_assistant.show_notice("describe screen: wait for the reply to finish".to_string());
+            return;
+        }
+        assistant_event::describe_screen(
+            &mut self.tui_assistant,
+            &mut self.ai_sessions,
+            &self.context_manager,
+            &self.shell_manager,
+            truncate_output(&text, SCREEN_BUDGET),
+            scrolled_back,
+        );
+    }
+
+    /// Ask the active session how to fix the last command, which failed,
+    /// with its exit code and the tail of its output. The question shows in
+    /// the chat as the user's, and waits like one while a reply streams in.
+    pub fn fix_failed_command(&mut self) {
+        let Some((command, exit_code)) = self.failed_command.clone() else {
+            self.tui_terminal.show_notice("Nothing to fix: the last command did not fail".to_string());
+            return;
+        };
+        let output = self
+            .shell_manager
+            .recent_command_records(10)
+            .into_iter()
+            .rev()
+            .find(|record| record.command_line == command)
+            .map(|record| self.context_manager.full_output(&record))
+            .unwrap_or_default();
+        self.switch_pane(ActivePane::Assistant);
+        assistant_event::send_question(
+            &mut self.tui_assistant,
+            &mut self.ai_sessions,
+            &self.context_manager,
+            &self.shell_manager,
+            prompt::fix_request(&command, exit_code, &output),
+            false,
+        );
+    }
+
+    /// Write the terminal scrollback and screen to a new file in the dump
+    /// directory. The outcome is shown in the pane title.
+    pub fn dump_scrollback(&mut self) {
+        let Some(dir) = self.dump.dir() else {
+            self.tui_terminal.show_error("Cannot dump: no directory for dumps (set [dump] dir)");
+            return;
+        };
+        match self.tui_terminal.dump_scrollback(&dir, self.dump.colors) {
+            Ok((path, lines)) => self.tui_terminal.show_notice(format!("Wrote {} lines to {}", lines, path.display())),
+            Err(e) => self.tui_terminal.show_error(&format!("Cannot dump to {}: {}", dir.display(), e)),
+        }
+    }
+
+    /// Is the event trace debug overlay visible?
+    pub fn is_event_trace_visible(&self) -> bool {
+        self.show_event_trace
+    }
+
+    /// Samples of the metrics registry, if the metrics overlay is visible.
+    pub fn metrics_overlay(&self) -> Option<&MetricsHistory> {
+        self.show_metrics.then_some(&self.metrics_history)
+    }
+
+    /// Show or hide the metrics overlay.
+    pub fn toggle_metrics(&mut self) {
+        self.show_metrics = !self.show_metrics;
+    }
+
+    /// Are the bell and notification cues muted?
+    pub fn cues_muted(&self) -> bool {
+        self.cues.is_muted()
+    }
+
+    /// Which panes are in performance mode.
+    pub fn performance(&self) -> PerformanceMode {
+        self.performance
+    }
+
+    /// Put the panes in or out of performance mode: cheaper drawing, no
+    /// hover and decoration frames at most every [`SLOW_DECORATION_FRAME`]
+    /// while any pane is in it.
+    pub fn set_performance(&mut self, mode: PerformanceMode) {
+        self.performance = mode;
+        self.tui_terminal.set_performance(mode.terminal);
+        self.tui_assistant.set_performance(mode.assistant);
+        self.frames.set_decoration_interval(if mode.any() { SLOW_DECORATION_FRAME } else { DECORATION_FRAME });
+        self.refresh_hover();
+        self.request_draw(true);
+    }
+
+    /// Toggle performance mode for the focused pane.
+    pub fn toggle_performance(&mut self) {
+        let mut mode = self.performance;
+        mode.toggle(self.active_pane);
+        self.set_performance(mode);
+    }
+
+    /// Sample the metrics registry, and write a summary to the log when one is due.
+    fn sample_metrics(&mut self) {
+        self.next_metrics_sample = Instant::now() + metrics::SAMPLE_INTERVAL;
+        if let Some(summary) = self.metrics_history.sample(metrics().snapshot(), std::time::Instant::now()) {
+            tracing::info!(target: "metrics", "{}", summary);
+        }
+        if self.show_metrics {
+            self.request_decoration_draw();
+        }
+    }
+
     /// Get current layout
     pub fn layout(&self) -> &AppLayout {
         &self.layout
@@ -269,7 +1557,7 @@ impl App {
     ///
     /// This ratio persists across window resizes. For example, if user drags
     /// the separator to 70/30, this ratio will be maintained even when the
-    /// window is resized.
+    /// window is resized. It is also saved for the next start.
     ///
     /// # Arguments
     /// * `ratio` - Percentage (0-100) for terminal pane width
@@ -280,62 +1568,161 @@ impl App {
         // Only rebuild if ratio actually changed
         if self.layout_builder.split_ratio() != old_ratio {
             self.rebuild_layout(self.layout.full_area);
+            self.tui_terminal.on_resize();
+            self.tui_assistant.on_resize(self.layout.assistant_inner);
+            if let Some(store) = &mut self.layout_store {
+                store.save_split_ratio(self.layout_builder.split_ratio());
+            }
         }
     }
 
+    /// Split ratio under the pointer while the separator is dragged.
+    pub fn separator_drag_ratio(&self) -> Option<u16> {
+        self.separator_drag_state.map(|drag| drag.ratio)
+    }
+
+    /// Drop a separator drag, back to the split it started from.
+    fn cancel_separator_drag(&mut self) {
+        if self.separator_drag_state.take().is_some() {
+            self.rebuild_layout(self.layout.full_area);
+            self.tui_assistant.on_resize(self.layout.assistant_inner);
+        }
+    }
+
+
+
+    /// Show or hide the terminal line-number gutter.
+    ///
+    /// While it is shown, command output sent to the AI is numbered the same way.
+    pub fn toggle_line_numbers(&mut self) {
+        self.tui_terminal.toggle_line_numbers();
+        self.context_manager
+            .set_number_command_output(self.tui_terminal.line_numbers_enabled());
+        self.sync_gutter();
+    }
+
+    /// Resize the gutter when the terminal needs a different width for it.
+    fn sync_gutter(&mut self) {
+        let width = self.tui_terminal.gutter_width();
+        if width != self.layout_builder.gutter_width() {
+            self.set_layout_builder(self.layout_builder.with_gutter_width(width));
+        }
+    }
+
+    fn request_draw(&mut self, asap: bool) {
+        // Cap redraw rate for performance.
+        // Terminal rendering is relatively expensive, and we can easily receive bursts
+        // of PTY output / AI stream chunks.
+        self.frames.content(asap, Instant::now());
+    }
+
+    /// Ask for a frame for streamed text: drawn at the stream rate, so a
+    /// reply coming in a chunk every few milliseconds isn't drawn per chunk.
+    fn request_stream_draw(&mut self) {
+        self.frames.stream(Instant::now());
+    }
 
+    /// Ask for a frame for something only the decorations show (a
+    /// countdown, a title notice, the metrics overlay). These are drawn at
+    /// the decoration rate, so they can't keep the loop drawing at full rate.
+    fn request_decoration_draw(&mut self) {
+        self.frames.decoration(Instant::now());
+    }
 
-    fn request_draw(&mut self, asap: bool) {
-        // Cap redraw rate for performance.
-        // Terminal rendering is relatively expensive, and we can easily receive bursts
-        // of PTY output / AI stream chunks.
-        const FRAME: Duration = Duration::from_millis(16);
-        let now = Instant::now();
-        let requested = if asap { now } else { now + FRAME };
+    /// The pane statuses as they would be drawn now.
+    fn pane_status(&self) -> (PaneStatus, PaneStatus) {
+        (self.tui_terminal.get_pane_status(), self.tui_assistant.get_pane_status())
+    }
 
-        // Important: don't keep pushing the deadline forward when we keep receiving events.
-        // We want the earliest scheduled draw to win.
-        match self.next_frame_deadline {
-            None => self.next_frame_deadline = Some(requested),
-            Some(existing) => {
-                if requested < existing {
-                    self.next_frame_deadline = Some(requested);
-                }
-            }
+    /// Ask for a decoration frame if the pane statuses differ from the ones
+    /// last drawn; nothing is drawn for an update that changes neither.
+    fn request_status_draw(&mut self) {
+        if self.drawn_status.as_ref() != Some(&self.pane_status()) {
+            self.request_decoration_draw();
         }
     }
 
     pub async fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        // Without the handlers SIGTSTP stops the process in raw mode
+        let mut job_signals = match JobSignals::install() {
+            Ok(signals) => Some(signals),
+            Err(e) => {
+                tracing::warn!("Job control signals not handled: {}", e);
+                None
+            }
+        };
         // Ensure we draw at least once when entering the loop.
         self.request_draw(true);
         loop {
             if self.exit {
                 break Ok(());
             }
+            if self.tui_assistant.take_suspend_request() {
+                self.suspend(terminal)?;
+            }
+            let command_tick = self.command_mode_tick();
+            let drafts_due = self.draft_store.as_ref().and_then(DraftStore::due);
+            let file_drop_due = self.tui_assistant.file_drop_due();
+            let macro_due = self.tui_terminal.macros().due();
+            // Every branch times its handling (the busy timer records on drop),
+            // so a handler that stalls the loop shows up in the metrics
             tokio::select! {
                 res = self.user_events.recv() => {
-                    let usr_evt = res.with_context(|| anyhow::anyhow!("User event stream is ended."))?;
-                    self.handle_user_event(usr_evt?)?;
-                    self.request_draw(false);
+                    let _busy = metrics().loop_timer();
+                    let usr_evt = res.with_context(|| anyhow::anyhow!("User event stream is ended."))??;
+                    self.on_user_event(usr_evt)?;
                 }
                 res = self.app_events.recv() => {
+                    let _busy = metrics().loop_timer();
                     let app_evt = res.with_context(|| anyhow::anyhow!("App event stream is ended"))?;
-                    self.handle_app_event(app_evt)?;
-                    self.request_draw(false);
+                    self.on_app_event(app_evt)?;
                 }
                 // AiSessionManager receives stream data, stores it, and returns UI updates
                 update = self.ai_sessions.recv_ai_stream() => {
-                    if let Some(update) = update {
-                        // Forward UI update to TuiAssistant for display
-                        self.tui_assistant.handle_ai_update(update);
-                        self.request_draw(false);
+                    let _busy = metrics().loop_timer();
+                    match update {
+                        Some(update) => self.on_ai_update(update),
+                        None => self.on_ai_data(),
                     }
                 }
                 _ = self.tui_terminal.recv_pty_output() => {
-                    // PTY output is handled internally by TuiTerminal
-                    self.request_draw(false);
+                    let _busy = metrics().loop_timer();
+                    self.on_pty_output();
+                }
+                Some(signal) = async { job_signals.as_mut()?.recv().await }, if job_signals.is_some() => {
+                    let _busy = metrics().loop_timer();
+                    match signal {
+                        JobSignal::Stop => self.suspend(terminal)?,
+                        JobSignal::Continue => self.resume()?,
+                    }
+                }
+                _ = tokio::time::sleep_until(command_tick), if self.command_mode_deadline.is_some() => {
+                    let _busy = metrics().loop_timer();
+                    self.on_command_mode_tick();
                 }
-                _ = tokio::time::sleep_until(self.next_frame_deadline.unwrap_or_else(Instant::now)), if self.next_frame_deadline.is_some() => {
+                _ = tokio::time::sleep_until(drafts_due.unwrap_or_else(Instant::now)), if drafts_due.is_some() => {
+                    let _busy = metrics().loop_timer();
+                    self.save_drafts();
+                }
+                _ = tokio::time::sleep_until(file_drop_due.unwrap_or_else(Instant::now)), if file_drop_due.is_some() => {
+                    let _busy = metrics().loop_timer();
+                    self.check_file_drop();
+                }
+                _ = tokio::time::sleep_until(macro_due.unwrap_or_else(Instant::now)), if macro_due.is_some() => {
+                    let _busy = metrics().loop_timer();
+                    self.replay_due_keys()?;
+                }
+                _ = tokio::time::sleep_until(self.next_metrics_sample) => {
+                    let _busy = metrics().loop_timer();
+                    self.sample_metrics();
+                }
+                _ = tokio::time::sleep_until(self.frames.due().unwrap_or_else(Instant::now)), if self.frames.due().is_some() => {
+                    let _busy = metrics().loop_timer();
+                    // Cleared first: a held frame schedules itself again
+                    let now = Instant::now();
+                    if let Some((due, trigger)) = self.frames.take(now) {
+                        metrics().record_frame(trigger, now.saturating_duration_since(due), FRAME);
+                    }
                     // Check if force redraw is needed (e.g., after stderr pollution)
                     if self.force_redraw_flag {
                         self.force_redraw_flag = false;
@@ -343,13 +1730,225 @@ impl App {
                     } else {
                         self.draw(terminal)?;
                     }
-                    self.next_frame_deadline = None;
+                    // Between frames, so a bell or notification never lands in one
+                    if let Err(e) = self.cues.flush(&mut std::io::stdout()) {
+                        tracing::warn!("Failed to write cues: {}", e);
+                    }
+                    // Hit areas were rebuilt; the hovered element may have moved or gone
+                    if self.refresh_hover() {
+                        self.request_draw(false);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Give the host terminal back and stop, as SIGTSTP would, then pick up
+    /// again once continued (`fg`).
+    fn suspend<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        let Some(modes) = self.suspension.suspend(TerminalModes::APP) else {
+            return Ok(());
+        };
+        terminal.show_cursor()?;
+        modes.disable(&mut std::io::stdout())?;
+        if let Err(e) = suspend::stop_self() {
+            tracing::error!("Failed to stop: {}", e);
+        }
+        self.resume()
+    }
+
+    /// Take the host terminal again after a stop. The window may have been
+    /// resized meanwhile, and whatever ran in the foreground drew over the
+    /// screen, so the PTY is resized and everything is drawn again.
+    fn resume(&mut self) -> Result<()> {
+        if let Some(modes) = self.suspension.resume() {
+            modes.enable(&mut std::io::stdout())?;
+        }
+        let (cols, rows) = crossterm::terminal::size()?;
+        self.handle_resize(cols, rows);
+        self.force_redraw_flag = true;
+        self.request_draw(true);
+        Ok(())
+    }
+
+    /// Handle one event from the user (keys, mouse, resize).
+    pub fn on_user_event(&mut self, usr_evt: UserEvent) -> Result<()> {
+        if let Some(kind) = user_event_kind(&usr_evt) {
+            self.event_trace.record_unstamped(EventOrigin::User, kind);
+        }
+        if let UserEvent::Mouse(mouse) = usr_evt {
+            self.pointer = Some((mouse.column, mouse.row));
+        }
+        if let UserEvent::Key(_) = usr_evt {
+            self.cues.note_input(std::time::Instant::now());
+        }
+        if let UserEvent::Mouse(mouse) = usr_evt
+            && matches!(mouse.kind, MouseEventKind::Moved)
+        {
+            // Plain motion only matters if it changes what is hovered
+            if self.refresh_hover() {
+                self.request_draw(false);
+            }
+        } else {
+            self.handle_user_event(usr_evt)?;
+            self.sync_sessions();
+            self.request_draw(false);
+            if let Some(store) = &mut self.draft_store {
+                store.note(&self.tui_assistant.saved_drafts(), Instant::now());
+            }
+        }
+        Ok(())
+    }
+
+    /// Offer to attach the file whose path was just typed into the
+    /// assistant input in a burst, if it was one.
+    fn check_file_drop(&mut self) {
+        let home = std::env::var("HOME").ok();
+        if self.tui_assistant.check_file_drop(home.as_deref(), Instant::now()) {
+            self.request_draw(true);
+            // The path left the input
+            if let Some(store) = &mut self.draft_store {
+                store.note(&self.tui_assistant.saved_drafts(), Instant::now());
+            }
+        }
+    }
+
+    /// Write unsent assistant input to disk now (if drafts are saved and
+    /// anything changed). Called on a timer after edits and on exit.
+    pub fn save_drafts(&mut self) {
+        if let Some(store) = &mut self.draft_store {
+            store.save(self.tui_assistant.saved_drafts());
+        }
+    }
+
+    fn on_app_event(&mut self, app_evt: Stamped<AppEvent>) -> Result<()> {
+        self.event_trace.record(&app_evt);
+        let ctx = PluginCtx { cwd: &self.context_manager.cwd.path, project: self.project.active() };
+        self.plugins.on_event(&app_evt.event, &ctx);
+        self.handle_app_event(app_evt.event)?;
+        self.sync_sessions();
+        self.request_draw(false);
+        Ok(())
+    }
+
+    fn on_ai_update(&mut self, update: Stamped<AiUiUpdate>) {
+        self.event_trace.record(&update);
+        if let AiUiUpdate::CommandSuggestion { session_id, .. } = &update.event {
+            self.note_prior_runs(*session_id);
+        }
+        match &update.event {
+            AiUiUpdate::End { .. } | AiUiUpdate::Truncated { .. } => {
+                self.cues.fire(CueEvent::AiFinished, std::time::Instant::now())
+            }
+            AiUiUpdate::CommandSuggestion { .. } => self.cues.fire(CueEvent::Suggestion, std::time::Instant::now()),
+            _ => {}
+        }
+        // Forward UI update to TuiAssistant for display
+        if let Some(mini) = &mut self.mini
+            && let AiUiUpdate::Error { session_id, error } | AiUiUpdate::ModelNotFound { session_id, message: error, .. } =
+                &update.event
+            && *session_id == mini.session_id()
+        {
+            mini.show_notice(error.clone());
+        }
+        // A warning only shows next to the pane status
+        let status_only = matches!(update.event, AiUiUpdate::Warning { .. });
+        let streamed = matches!(update.event, AiUiUpdate::Chunk { .. } | AiUiUpdate::Reasoning { .. });
+        self.tui_assistant.handle_ai_update(update.event);
+        self.sync_sessions();
+        if status_only {
+            self.request_status_draw();
+        } else if streamed {
+            self.request_stream_draw();
+        } else {
+            self.request_draw(false);
+        }
+    }
+
+    /// Stream data that needs no UI update was handled: it may still have
+    /// changed the sessions (a name came back).
+    fn on_ai_data(&mut self) {
+        if self.ai_sessions.has_events() {
+            self.sync_sessions();
+            self.request_draw(false);
+        }
+    }
+
+    /// Bring the assistant pane up to date with whatever the handlers
+    /// changed in the sessions (see [`assistant_event::sync_sessions`]).
+    fn sync_sessions(&mut self) {
+        assistant_event::sync_sessions(&mut self.tui_assistant, &mut self.ai_sessions);
+        // The mini assistant's session has no tab: it is shown as it is
+        if let Some(mini) = &mut self.mini {
+            let session_id = mini.session_id();
+            let replying = self.ai_sessions.is_replying(session_id);
+            mini.set_messages(self.ai_sessions.get_session_messages(session_id), replying);
+        }
+    }
+
+    /// PTY output was handled internally by TuiTerminal.
+    fn on_pty_output(&mut self) {
+        self.shell_manager
+            .set_bracketed_paste(self.tui_terminal.is_bracketed_paste());
+        self.track_commands();
+        // New lines may add a digit to the line numbers
+        self.sync_gutter();
+        self.refresh_running_suggestion();
+        self.request_draw(false);
+    }
+
+    /// Follow where shell commands end: by the OSC 133 marks the shell
+    /// printed, or without them by the prompt coming back on screen.
+    fn track_commands(&mut self) {
+        for mark in self.tui_terminal.take_shell_marks() {
+            self.shell_manager.shell_mark(mark);
+        }
+        if let Some(command) = self.shell_manager.unmarked_command()
+            && self.tui_terminal.command_running(&command) == Some(false)
+        {
+            self.shell_manager.prompt_back();
+        }
+    }
+
+    /// Handle everything already queued (PTY output, app events, AI stream
+    /// data) without waiting, the way the main loop would.
+    /// Returns whether anything was handled.
+    pub fn process_pending(&mut self) -> Result<bool> {
+        let mut handled = false;
+        loop {
+            if self.tui_terminal.poll_pty_output() {
+                self.on_pty_output();
+            } else if let Ok(app_evt) = self.app_events.try_recv() {
+                self.on_app_event(app_evt)?;
+            } else if let Some(update) = self.ai_sessions.recv_ai_stream().now_or_never() {
+                // Stream data that needs no UI update yields None
+                match update {
+                    Some(update) => self.on_ai_update(update),
+                    None => self.on_ai_data(),
                 }
+            } else if !self.replay_due_keys()? {
+                return Ok(handled);
             }
+            handled = true;
         }
     }
 
-    pub fn draw(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+    pub fn draw<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
+        // A bogus size keeps the last frame up; so does a too-small one right
+        // after start, until the emulator reports the real size
+        let size = terminal.size()?;
+        if !plausible_size(size.width, size.height) {
+            return Ok(());
+        }
+        if let Some(until) = self.size_settling_until {
+            let area = ratatui::layout::Rect::new(0, 0, size.width, size.height);
+            if self.layout_builder.build(area).mode == LayoutMode::TooSmall && Instant::now() < until {
+                self.frames.at(until, DrawTrigger::Content);
+                return Ok(());
+            }
+            self.size_settling_until = None;
+        }
+
         // Render the UI
         terminal.draw(|frame| {
             let area = frame.area();
@@ -367,12 +1966,19 @@ impl App {
         // Set cursor based on current layout
         self.update_cursor_position(terminal)?;
 
+        self.drawn_status = Some(self.pane_status());
+
+        // A flashing prompt is drawn again when the flash is over
+        if let Some(until) = self.tui_assistant.prompt_flash_until() {
+            self.frames.at(until, DrawTrigger::Content);
+        }
+
         Ok(())
     }
 
     /// Force a full screen clear and redraw.
     /// This is useful when stderr output has polluted the screen.
-    pub fn force_redraw(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+    pub fn force_redraw<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
         // eprintln!("force_redraw");
         terminal.clear()?;
         self.draw(terminal)?;
@@ -383,28 +1989,64 @@ impl App {
     ///
     /// This method also handles terminal resize when the terminal pane size changes.
     fn rebuild_layout(&mut self, area: ratatui::layout::Rect) {
-        let old_layout = self.layout;
-        self.layout = self.layout_builder.build(area);
-
-        // Resize terminal and PTY if terminal pane size changed
-        let new_term_area = self.layout.terminal_inner;
-        let old_term_area = old_layout.terminal_inner;
-        if new_term_area.width != old_term_area.width || new_term_area.height != old_term_area.height {
-            self.tui_terminal.resize(new_term_area.width, new_term_area.height);
-            if let Err(e) = self.shell_manager.resize(new_term_area.width, new_term_area.height) {
+        let committed = self.layout_builder.build(area);
+        // While the separator is dragged the panes are drawn at the ratio
+        // under the pointer, but the terminal keeps the committed size
+        // (clipped or with blank columns) so the shell isn't resized on
+        // every column moved
+        self.layout = match self.separator_drag_state {
+            Some(drag) => self.layout_builder.with_split_ratio(drag.ratio).build(area),
+            None => committed,
+        };
+
+        // Resize terminal and PTY if terminal pane size changed. While the
+        // pane is hidden they keep their last size, so showing it again at
+        // that size leaves the shell undisturbed.
+        let term_area = committed.terminal_inner;
+        if !term_area.is_empty() && self.tui_terminal.size() != (term_area.width, term_area.height) {
+            self.tui_terminal.resize(term_area.width, term_area.height);
+            if let Err(e) = self.shell_manager.resize(term_area.width, term_area.height) {
                 eprintln!("Failed to resize PTY: {}", e);
             }
         }
     }
 
-    fn update_cursor_position(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+    /// Apply a host terminal resize (window resize or font zoom) right away.
+    ///
+    /// The layout, the PTY size and every width-dependent cache in both panes
+    /// are updated before the next event is handled, so a click or key that
+    /// arrives before the next frame is matched against the new layout.
+    pub fn handle_resize(&mut self, cols: u16, rows: u16) {
+        if !plausible_size(cols, rows) {
+            tracing::warn!("Ignoring implausible screen size {}x{}", cols, rows);
+            return;
+        }
+        self.rebuild_layout(ratatui::layout::Rect::new(0, 0, cols, rows));
+        self.tui_terminal.on_resize();
+        self.tui_assistant.on_resize(self.layout.assistant_inner);
+        self.request_draw(true);
+    }
+
+    fn update_cursor_position<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<()> {
         // In visual mode or command mode, hide the hardware cursor
         // (visual mode cursor is rendered as a highlighted cell)
-        if self.get_command_mode() || self.is_visual_mode() {
+        // Nor is there anywhere to put it on the too-small placeholder
+        if self.get_command_mode() || self.is_visual_mode() || self.switcher.is_some() || self.help.is_some()
+            || self.quick_run.is_some() || self.state_banner.is_some() || self.layout.mode == LayoutMode::TooSmall
+        {
             terminal.hide_cursor()?;
             return Ok(());
         }
 
+        // The mini assistant types at the end of its one line
+        if let Some(mini) = &self.mini
+            && self.project.pending().is_none()
+        {
+            terminal.show_cursor()?;
+            terminal.set_cursor_position(mini.cursor_position(self.mini_assistant_area()))?;
+            return Ok(());
+        }
+
         // Directly match on app state and use layout areas
         match self.get_active_pane() {
             // Terminal pane active: show cursor at terminal position
@@ -450,31 +2092,160 @@ impl App {
 impl App {
 
     fn handle_user_event(&mut self, event: UserEvent) -> Result<()>  {
+        if let UserEvent::Resize(cols, rows) = event {
+            self.handle_resize(cols, rows);
+            return Ok(());
+        }
+
+        // So is the read-only banner, so it isn't missed
+        if self.state_banner.is_some() {
+            if let UserEvent::Key(key) = event {
+                self.handle_state_banner_key(key);
+            }
+            return Ok(());
+        }
+
+        // The trust prompt is modal so typing can't answer it by accident
+        if self.project.pending().is_some() {
+            if let UserEvent::Key(key) = event {
+                self.handle_trust_key(key);
+            }
+            return Ok(());
+        }
+
+        // And the quick action prompt
+        if self.quick_run.is_some() {
+            if let UserEvent::Key(key) = event {
+                self.handle_quick_run_key(key)?;
+            }
+            return Ok(());
+        }
+
+        // So is the switcher: typing filters its list instead of reaching a pane
+        if self.switcher.is_some() {
+            if let UserEvent::Key(key) = event {
+                self.handle_switcher_key(key);
+            }
+            return Ok(());
+        }
+
+        // And the mini assistant, which takes a question
+        if self.mini.is_some() {
+            match event {
+                UserEvent::Key(key) => self.handle_mini_key(key)?,
+                UserEvent::Paste(text) => {
+                    if let Some(mini) = &mut self.mini {
+                        mini.paste(&text);
+                    }
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        // The help filters as you type too
+        if self.help.is_some() {
+            if let UserEvent::Key(key) = event {
+                self.handle_help_key(key);
+            }
+            return Ok(());
+        }
+
+        // And the long line viewer, which scrolls with the keys
+        if self.long_line_view.is_some() {
+            if let UserEvent::Key(key) = event {
+                self.handle_long_line_key(key);
+            }
+            return Ok(());
+        }
+
         // Handle mouse events first (they work in all modes)
         if let UserEvent::Mouse(mouse) = event {
             let current_ratio = self.split_ratio();
-            let result = mouse_event::handle_mouse_event(
-                mouse,
-                &self.layout,
-                &mut self.tui_terminal,
-                &mut self.tui_assistant,
-                &mut self.shell_manager,
-                &mut self.ai_sessions,
-                &mut self.active_pane,
-                &mut self.mouse_drag_state,
-                &mut self.separator_drag_state,
-                &mut self.last_click,
-                current_ratio,
-            )?;
-
-            // Apply any deferred actions from mouse event
-            if let Some(new_ratio) = result.new_split_ratio {
-                self.set_split_ratio(new_ratio);
+            let result = mouse_event::handle_mouse_event(mouse, mouse_event::MouseCtx {
+                layout: &self.layout,
+                terminal: &mut self.tui_terminal,
+                assistant: &mut self.tui_assistant,
+                shell: &mut self.shell_manager,
+                ai_sessions: &mut self.ai_sessions,
+                active_pane: &mut self.active_pane,
+                drag_state: &mut self.mouse_drag_state,
+                separator_drag: &mut self.separator_drag_state,
+                last_click: &mut self.last_click,
+                current_split_ratio: current_ratio,
+            })?;
+
+            // Apply any deferred actions from mouse event: the panes follow
+            // the separator, the shell is resized once it is released
+            if result.separator_moved {
+                self.rebuild_layout(self.layout.full_area);
+            }
+            if let Some(ratio) = result.committed_ratio {
+                self.set_split_ratio(ratio);
             }
+            self.sync_layout_focus();
+
+            return Ok(());
+        }
 
+        // Esc while dragging the separator puts it back where it was
+        if let UserEvent::Key(key) = event
+            && matches!(key.kind, KeyEventKind::Press)
+            && key.code == KeyCode::Esc
+            && self.separator_drag_state.is_some()
+        {
+            self.cancel_separator_drag();
             return Ok(());
         }
 
+        // The leader is checked before any mode so it works everywhere
+        if let UserEvent::Key(key) = event
+            && matches!(key.kind, KeyEventKind::Press)
+        {
+            match self.keymap.leader.step(self.leader_pending.len(), &key) {
+                LeaderProgress::Partial => {
+                    self.leader_pending.push(key);
+                    return Ok(());
+                }
+                LeaderProgress::Complete => {
+                    self.leader_pending.clear();
+                    if self.command_mode {
+                        // Leader twice => send the literal leader to the shell
+                        self.set_command_mode(false);
+                        self.send_literal_leader()?;
+                    } else {
+                        self.set_command_mode(true);
+                    }
+                    return Ok(());
+                }
+                LeaderProgress::Mismatch => {
+                    // Replay keys that looked like the start of the leader
+                    for pending in std::mem::take(&mut self.leader_pending) {
+                        self.dispatch_user_event(UserEvent::Key(pending))?;
+                    }
+                }
+            }
+        }
+
+        self.dispatch_user_event(event)
+    }
+
+    /// Send the configured leader keys to the PTY as if typed.
+    fn send_literal_leader(&mut self) -> Result<()> {
+        let keys: Vec<KeyEvent> = self.keymap.leader.key_events().collect();
+        for key in keys {
+            terminal_event::handle_key_event(
+                &mut self.tui_terminal,
+                &mut self.shell_manager,
+                key,
+                &mut self.shell_input_buffer,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Route a non-mouse user event according to the current mode.
+    fn dispatch_user_event(&mut self, event: UserEvent) -> Result<()> {
         if self.command_mode {
             self.handle_command_mode_events(event)?;
             return Ok(());
@@ -482,29 +2253,41 @@ impl App {
 
         // Handle visual mode events (delegated to component)
         if self.is_visual_mode() {
-            if let UserEvent::Key(key) = event {
-                let result = match self.active_pane {
+            if let UserEvent::Key(key) = event
+                && self.active_pane == ActivePane::Assistant
+                && matches!(key.kind, KeyEventKind::Press)
+                && matches!(key.code, KeyCode::Char('c') | KeyCode::Char('C'))
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+            {
+                assistant_event::interrupt(&mut self.tui_assistant, &mut self.ai_sessions);
+            } else if let UserEvent::Key(key) = event
+                && self.active_pane == ActivePane::Assistant
+                && matches!(key.kind, KeyEventKind::Press)
+                && key.code == KeyCode::Char('r')
+                && self.tui_assistant.visual_cursor_on_regenerable_reply()
+            {
+                // r on the latest reply: pick how to ask the question again
+                self.tui_assistant.exit_visual_mode();
+                self.tui_assistant.open_regen_menu();
+            } else if let UserEvent::Key(key) = event
+                && self.active_pane == ActivePane::Assistant
+                && matches!(key.kind, KeyEventKind::Press)
+                && key.code == KeyCode::Char('e')
+                && self.tui_assistant.question_at_visual_cursor().is_some()
+            {
+                // e on a question: edit it in the input and ask it again
+                assistant_event::edit_question(&mut self.tui_assistant, &self.ai_sessions);
+            } else if let UserEvent::Key(key) = event {
+                match self.active_pane {
                     ActivePane::Terminal => self.tui_terminal.handle_visual_key(key),
                     ActivePane::Assistant => self.tui_assistant.handle_visual_key(key),
                 };
-                match result {
-                    crate::ui::visual::KeyHandleResult::RequestCommandMode => {
-                        self.set_command_mode(true);
-                    }
-                    _ => {}
-                }
             }
             return Ok(());
         }
 
         match event {
             UserEvent::Key(key_evt) if matches!(key_evt.kind, KeyEventKind::Press) => {
-                // Ctrl + B => Command Mode
-                if key_evt.modifiers.contains(KeyModifiers::CONTROL) && matches!(key_evt.code, KeyCode::Char('b') | KeyCode::Char('B')) {
-                    self.set_command_mode(true);
-                    return Ok(());
-                }
-
                 // Pane-specific event handling (delegated to event module)
                 match self.active_pane {
                     ActivePane::Terminal => {
@@ -535,15 +2318,72 @@ impl App {
     fn handle_command_mode_events(&mut self, event: UserEvent) -> Result<()> {
         assert!(self.command_mode);
 
+        // Keyboard macros: their keys after q, @ or a count too
+        if let UserEvent::Key(e) = &event
+            && matches!(e.kind, KeyEventKind::Press)
+            && self.macro_command_key(e)
+        {
+            return Ok(());
+        }
+
         // Common commands (available in both panes)
         match &event {
             // n => toggle pane (switch between Terminal and Assistant)
-            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('n') | KeyCode::Char('N')) => {
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('n')) => {
                 self.toggle_pane();
                 self.set_command_mode(false);
                 return Ok(());
             }
 
+            // N => toggle the terminal line-number gutter
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('N')) => {
+                self.toggle_line_numbers();
+                self.set_command_mode(false);
+                return Ok(());
+            }
+
+            // . => read the assistant's backend from the config file again
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('.')) => {
+                self.reload_config();
+                self.set_command_mode(false);
+                return Ok(());
+            }
+
+            // c => copy the terminal screen to the clipboard
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('c')) => {
+                self.copy_screen();
+                self.set_command_mode(false);
+                return Ok(());
+            }
+
+            // d => ask the AI what the terminal screen shows
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('d')) => {
+                self.set_command_mode(false);
+                self.describe_screen();
+                return Ok(());
+            }
+
+            // D => ask the AI to fix the last command, which failed (diagnose)
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('D')) => {
+                self.set_command_mode(false);
+                self.fix_failed_command();
+                return Ok(());
+            }
+
+            // C => write the terminal scrollback to a file
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('C')) => {
+                self.dump_scrollback();
+                self.set_command_mode(false);
+                return Ok(());
+            }
+
+            // p => open the long line viewer
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('p') | KeyCode::Char('P')) => {
+                self.set_command_mode(false);
+                self.open_long_line();
+                return Ok(());
+            }
+
             // q => exit application
             UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('q') | KeyCode::Char('Q')) => {
                 self.exit = true;
@@ -558,6 +2398,67 @@ impl App {
                 return Ok(());
             }
 
+            // e => toggle event trace overlay (debugging)
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('e') | KeyCode::Char('E')) => {
+                self.show_event_trace = !self.show_event_trace;
+                self.set_command_mode(false);
+                return Ok(());
+            }
+
+            // b => mute or unmute the bell and notification cues
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('b') | KeyCode::Char('B')) => {
+                self.cues.toggle_mute();
+                self.set_command_mode(false);
+                return Ok(());
+            }
+
+            // z => toggle performance mode for the focused pane
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('z') | KeyCode::Char('Z')) => {
+                self.toggle_performance();
+                self.set_command_mode(false);
+                return Ok(());
+            }
+
+            // m => toggle metrics overlay
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('m') | KeyCode::Char('M')) => {
+                self.toggle_metrics();
+                self.set_command_mode(false);
+                return Ok(());
+            }
+
+            // r => start or stop recording the terminal pane (in the
+            // assistant pane r asks again, so only R records there)
+            UserEvent::Key(e)
+                if matches!(e.kind, KeyEventKind::Press)
+                    && (matches!(e.code, KeyCode::Char('R'))
+                        || matches!(e.code, KeyCode::Char('r')) && self.active_pane == ActivePane::Terminal) =>
+            {
+                self.toggle_recording();
+                self.set_command_mode(false);
+                return Ok(());
+            }
+
+            // s => open the quick switcher (sessions and shell)
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('s') | KeyCode::Char('S')) => {
+                self.set_command_mode(false);
+                self.open_switcher();
+                return Ok(());
+            }
+
+            // Space => ask the mini assistant over the terminal
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char(' ')) => {
+                self.set_command_mode(false);
+                self.open_mini_assistant();
+                return Ok(());
+            }
+
+            // ? => list every key that works here
+            UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('?')) => {
+                self.set_command_mode(false);
+                self.open_help();
+                return Ok(());
+            }
+
             // v => enter visual mode
             UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('v') | KeyCode::Char('V')) => {
                 self.set_command_mode(false);
@@ -570,6 +2471,7 @@ impl App {
             UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Left) => {
                 let current = self.split_ratio();
                 self.set_split_ratio(current.saturating_sub(2));
+                self.arm_command_timeout();
                 return Ok(());
             }
 
@@ -578,26 +2480,32 @@ impl App {
             UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Right) => {
                 let current = self.split_ratio();
                 self.set_split_ratio(current.saturating_add(2));
+                self.arm_command_timeout();
                 return Ok(());
             }
 
             _ => {}
         }
 
+        // The user's own keys (see config::quick_actions)
+        if let UserEvent::Key(e) = &event
+            && matches!(e.kind, KeyEventKind::Press)
+            && let Some(index) = self.quick_actions.iter().position(|action| action.key.matches(e))
+        {
+            self.set_command_mode(false);
+            return self.run_quick_action(index);
+        }
+
         // Pane-specific commands
         match self.active_pane {
             ActivePane::Terminal => {
-                crate::event::terminal::handle_command_mode(
-                    &mut self.tui_terminal,
-                    &mut self.shell_manager,
-                    &mut self.shell_input_buffer,
-                    event,
-                )?;
+                terminal_event::handle_command_mode(&mut self.tui_terminal, event)?;
             }
             ActivePane::Assistant => {
                 crate::event::assistant::handle_command_mode(
                     &mut self.tui_assistant,
                     &mut self.ai_sessions,
+                    &self.context_manager,
                     event,
                 )?;
             }
@@ -617,9 +2525,14 @@ impl App {
             }
 
             // AI Events
-            AppEvent::ExecuteAiCommand { session_id: _, command } => {
+            AppEvent::ExecuteAiCommand { session_id, tool_call_id, command, cwd, interrupt_first } => {
                 // Execute through the security gate (single entrypoint)
-                self.try_execute_suggested(&command)?;
+                let origin = CommandOrigin { session_id, tool_call_id };
+                self.try_execute_suggested(&command, cwd.as_deref(), interrupt_first, Some(origin))?;
+            }
+            AppEvent::ExecuteAiBatch { session_id, steps } => {
+                // Each step goes through the same gate, once the last is done
+                self.start_batch(session_id, steps)?;
             }
 
             // Shell Events
@@ -629,18 +2542,70 @@ impl App {
 
                 // If shell exited, mark app for exit
                 if message.contains("exited") {
+                    self.shell_manager.shell_exited();
                     self.exit = true;
                 }
             }
 
-            AppEvent::ShellCommandCompleted { command, exit_code } => {
-                self.context_manager.history.push(command);
-                let _ = exit_code; // Suppress unused warnings for now
+            AppEvent::ShellCommandCompleted { command, exit_code, signal, duration, origin } => {
+                if let Some(exit_code) = exit_code {
+                    self.shell_manager.record_exit_code(&command, exit_code);
+                }
+                // Stopped with Ctrl+C: no news to the user
+                if signal != Some(SIGINT) {
+                    self.cues.command_finished(exit_code.unwrap_or(0), Some(duration), std::time::Instant::now());
+                }
+                if self.batch.as_ref().and_then(Batch::running) == Some(command.as_str()) {
+                    let progress = self.batch.as_mut().map(|batch| batch.finished(exit_code));
+                    if let Some(progress) = progress {
+                        self.advance_batch(progress)?;
+                    }
+                }
+                // A failure can be handed to the AI to fix, until the next command
+                self.failed_command =
+                    exit_code.filter(|&code| code != 0 && signal != Some(SIGINT)).map(|code| (command.clone(), code));
+                if let Some((_, code)) = &self.failed_command {
+                    self.tui_terminal.show_notice(format!("Exit {}: command mode Shift+D asks the AI to fix it", code));
+                }
+                if self.running_suggestion.as_ref() == Some(&command) {
+                    self.running_suggestion = None;
+                    self.refresh_running_suggestion();
+                }
+                // The AI hears how a command it suggested went with the next question
+                if let Some(origin) = origin {
+                    let record = self
+                        .shell_manager
+                        .recent_command_records(10)
+                        .into_iter()
+                        .rev()
+                        .find(|record| record.command_line == command);
+                    let output = record.map(|record| self.context_manager.full_output(&record)).unwrap_or_default();
+                    self.ai_sessions.report_command_result(origin.session_id, &origin.tool_call_id, exit_code, &output);
+                }
+                self.context_manager.history.push(command.clone());
+                if let Some(exit_code) = exit_code {
+                    self.context_manager.history.set_exit_code(&command, exit_code);
+                }
+                self.refresh_context_chips();
             }
 
             AppEvent::ShellOutput { data } => {
                 self.context_manager.push_output(data);
             }
+
+            AppEvent::ShellCwdChanged { payload } => {
+                if self.context_manager.update_cwd_from_osc7(&payload) {
+                    self.refresh_project();
+                    self.refresh_project_context();
+                    self.refresh_context_chips();
+                }
+            }
+
+            AppEvent::ProjectRead { stamp, project } => {
+                if self.context_manager.set_project(stamp, project) {
+                    self.refresh_context_chips();
+                }
+            }
         }
         Ok(())
     }
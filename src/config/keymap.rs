@@ -0,0 +1,338 @@
+//! Keymap configuration: the command-mode leader and its timeout.
+//!
+//! The leader is written as one or two space-separated key chords, e.g.
+//! `"ctrl+b"` (the default), `"ctrl+a"` for screen users, or `"ctrl+a r"`
+//! for a two-key leader.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+/// A single key press with modifiers, e.g. `Ctrl+B`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub const fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    /// Shorthand for `Ctrl+<c>`.
+    pub const fn ctrl(c: char) -> Self {
+        Self::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    /// Check whether a key event is this chord.
+    ///
+    /// Letters compare case-insensitively and Shift is ignored for characters,
+    /// since terminals report `Ctrl+B` as either `b` or `B`.
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        let relevant = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        match (self.code, key.code) {
+            (KeyCode::Char(a), KeyCode::Char(b)) => {
+                if (self.modifiers & relevant) != (key.modifiers & relevant) {
+                    return false;
+                }
+                // crossterm reports Ctrl+] as Ctrl+5 on some terminals (both are 0x1D)
+                let ctrl = self.modifiers.contains(KeyModifiers::CONTROL);
+                a.eq_ignore_ascii_case(&b) || (ctrl && matches!((a, b), (']', '5') | ('5', ']')))
+            }
+            (a, b) => a == b && self.modifiers == key.modifiers,
+        }
+    }
+
+    /// Build the key event this chord represents (used to send it literally).
+    pub fn to_key_event(self) -> KeyEvent {
+        KeyEvent {
+            code: self.code,
+            modifiers: self.modifiers,
+            kind: KeyEventKind::Press,
+            state: KeyEventState::NONE,
+        }
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("empty key chord".to_string());
+        }
+
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts: Vec<&str> = s.split('+').collect();
+        // A trailing "+" means the key itself is '+', e.g. "ctrl++"
+        let key = if s.ends_with("++") {
+            parts.truncate(parts.len().saturating_sub(2));
+            "+"
+        } else {
+            parts.pop().unwrap_or_default()
+        };
+
+        for m in parts {
+            match m.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" | "c" => modifiers |= KeyModifiers::CONTROL,
+                "alt" | "meta" | "m" => modifiers |= KeyModifiers::ALT,
+                "shift" | "s" => modifiers |= KeyModifiers::SHIFT,
+                other => return Err(format!("unknown modifier '{}' in '{}'", other, s)),
+            }
+        }
+
+        let lower = key.to_ascii_lowercase();
+        let code = match lower.as_str() {
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "enter" | "return" => KeyCode::Enter,
+            "space" => KeyCode::Char(' '),
+            "backspace" => KeyCode::Backspace,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            _ if lower.len() > 1 && lower.starts_with('f') => {
+                let n: u8 = lower[1..]
+                    .parse()
+                    .map_err(|_| format!("unknown key '{}' in '{}'", key, s))?;
+                KeyCode::F(n)
+            }
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c.to_ascii_lowercase()),
+                    _ => return Err(format!("unknown key '{}' in '{}'", key, s)),
+                }
+            }
+        };
+
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl TryFrom<String> for KeyChord {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<KeyChord> for String {
+    fn from(chord: KeyChord) -> Self {
+        chord.to_string()
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            f.write_str("Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            f.write_str("Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            f.write_str("Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::Esc => f.write_str("Esc"),
+            KeyCode::Tab => f.write_str("Tab"),
+            KeyCode::Enter => f.write_str("Enter"),
+            KeyCode::Backspace => f.write_str("Backspace"),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// Progress of a key sequence towards the leader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaderProgress {
+    /// The whole leader has been typed
+    Complete,
+    /// The key continues the leader but more keys are needed
+    Partial,
+    /// The key does not continue the leader
+    Mismatch,
+}
+
+/// The key sequence that enters command mode (one or two chords).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Leader {
+    chords: Vec<KeyChord>,
+}
+
+impl Default for Leader {
+    fn default() -> Self {
+        Self { chords: vec![KeyChord::ctrl('b')] }
+    }
+}
+
+impl Leader {
+    /// Maximum number of chords in a leader sequence.
+    pub const MAX_CHORDS: usize = 2;
+
+    pub fn chords(&self) -> &[KeyChord] {
+        &self.chords
+    }
+
+    /// Feed a key, given how many chords of the leader were already matched.
+    pub fn step(&self, matched: usize, key: &KeyEvent) -> LeaderProgress {
+        match self.chords.get(matched) {
+            Some(chord) if chord.matches(key) => {
+                if matched + 1 == self.chords.len() {
+                    LeaderProgress::Complete
+                } else {
+                    LeaderProgress::Partial
+                }
+            }
+            _ => LeaderProgress::Mismatch,
+        }
+    }
+
+    /// Key events that reproduce the leader literally.
+    pub fn key_events(&self) -> impl Iterator<Item = KeyEvent> + '_ {
+        self.chords.iter().map(|c| c.to_key_event())
+    }
+}
+
+impl FromStr for Leader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chords = s
+            .split_whitespace()
+            .map(KeyChord::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        if chords.is_empty() || chords.len() > Self::MAX_CHORDS {
+            return Err(format!(
+                "leader must be 1 to {} key chords, got '{}'",
+                Self::MAX_CHORDS,
+                s
+            ));
+        }
+        Ok(Self { chords })
+    }
+}
+
+impl TryFrom<String> for Leader {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Leader> for String {
+    fn from(leader: Leader) -> Self {
+        leader.to_string()
+    }
+}
+
+impl fmt::Display for Leader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, chord) in self.chords.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", chord)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keymap section of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeymapConfig {
+    /// Key sequence that enters command mode
+    pub leader: Leader,
+    /// Auto-exit command mode after this many milliseconds (None = never)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_timeout_ms: Option<u64>,
+    /// Time between two keys of a replayed keyboard macro (0 = all at once)
+    pub macro_delay_ms: u64,
+}
+
+impl Default for KeymapConfig {
+    fn default() -> Self {
+        Self {
+            leader: Leader::default(),
+            command_timeout_ms: None,
+            macro_delay_ms: crate::ui::macros::DEFAULT_KEY_DELAY.as_millis() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyChord::new(code, modifiers).to_key_event()
+    }
+
+    #[test]
+    fn test_parse_chords() -> Result<(), String> {
+        assert_eq!("ctrl+b".parse::<KeyChord>()?, KeyChord::ctrl('b'));
+        assert_eq!("Ctrl+]".parse::<KeyChord>()?, KeyChord::ctrl(']'));
+        assert_eq!(
+            "alt+f2".parse::<KeyChord>()?,
+            KeyChord::new(KeyCode::F(2), KeyModifiers::ALT)
+        );
+        assert_eq!("r".parse::<KeyChord>()?, KeyChord::new(KeyCode::Char('r'), KeyModifiers::NONE));
+        assert!("hyper+b".parse::<KeyChord>().is_err());
+        assert!("ctrl+bb".parse::<KeyChord>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_leader_display_roundtrip() -> Result<(), String> {
+        let leader: Leader = "ctrl+a r".parse()?;
+        assert_eq!(leader.to_string(), "Ctrl+A R");
+        assert_eq!(leader.to_string().parse::<Leader>()?, leader);
+        assert_eq!(Leader::default().to_string(), "Ctrl+B");
+        Ok(())
+    }
+
+    #[test]
+    fn test_leader_length_is_limited() {
+        assert!("".parse::<Leader>().is_err());
+        assert!("ctrl+a b c".parse::<Leader>().is_err());
+    }
+
+    #[test]
+    fn test_chord_matching_ignores_case() {
+        let chord = KeyChord::ctrl('b');
+        assert!(chord.matches(&key(KeyCode::Char('b'), KeyModifiers::CONTROL)));
+        assert!(chord.matches(&key(KeyCode::Char('B'), KeyModifiers::CONTROL | KeyModifiers::SHIFT)));
+        assert!(!chord.matches(&key(KeyCode::Char('b'), KeyModifiers::NONE)));
+        assert!(!chord.matches(&key(KeyCode::Char('b'), KeyModifiers::CONTROL | KeyModifiers::ALT)));
+    }
+
+    #[test]
+    fn test_two_key_leader_steps() -> Result<(), String> {
+        let leader: Leader = "ctrl+a r".parse()?;
+        let ctrl_a = key(KeyCode::Char('a'), KeyModifiers::CONTROL);
+        let r = key(KeyCode::Char('r'), KeyModifiers::NONE);
+        assert_eq!(leader.step(0, &ctrl_a), LeaderProgress::Partial);
+        assert_eq!(leader.step(1, &r), LeaderProgress::Complete);
+        assert_eq!(leader.step(0, &r), LeaderProgress::Mismatch);
+        assert_eq!(leader.step(1, &ctrl_a), LeaderProgress::Mismatch);
+        Ok(())
+    }
+}
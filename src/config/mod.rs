@@ -0,0 +1,927 @@
+//! User configuration loaded from a TOML file.
+//!
+//! The config file is looked up at `$RUSTY_TERM_CONFIG`, then
+//! `$XDG_CONFIG_HOME/rusty-term/config.toml`, then
+//! `~/.config/rusty-term/config.toml`. A missing file means defaults; a file
+//! that fails to parse is logged and ignored so a typo never blocks startup.
+//!
+//! ```toml
+//! [keymap]
+//! leader = "ctrl+a"
+//! command_timeout_ms = 3000
+//! macro_delay_ms = 20
+//!
+//! [[templates]]
+//! name = "code review"
+//! context = ["git"]
+//!
+//! [[quick_actions]]
+//! name = "git status"
+//! key = "g"
+//! run_shell = { command = "git status", confirm = false }
+//!
+//! [mouse]
+//! hover = false
+//!
+//! [assistant]
+//! provider = "ollama"
+//! provider_url = "http://localhost:11434"
+//! model = "qwen2.5"
+//! local_answers = false
+//! response_cache = true
+//! cache_max_age_days = 3
+//! extended_context = true
+//! extended_context_timeout_ms = 1500
+//! extended_context_max_kb = 8
+//! project_context = true
+//! save_drafts = true
+//! trim_strategy = "summarize"
+//! summary_model = "gpt-4o-mini"
+//! history_budget = 8000
+//! input_editing = "vi"
+//! mark_ai_commands = true
+//! copy_reasoning = false
+//! enter_rejects_pending = false
+//! safer_alternatives = false
+//! prompt_history = 20
+//! file_drop = false
+//! quote_min_len = 32
+//! temperature = 0.7
+//! command_temperature = 0.1
+//! reasoning_effort = "low"
+//! request_mode = "blocking"
+//! request_timeout_secs = 120
+//! max_retries = 5
+//! session_names = "model"
+//! prompt_price_per_1k = 0.0025
+//! completion_price_per_1k = 0.01
+//!
+//! [clipboard]
+//! osc52 = false
+//!
+//! [layout]
+//! split_min_width = 70
+//! split_min_height = 18
+//!
+//! [recording]
+//! dir = "~/casts"
+//! record_input = false
+//!
+//! [dump]
+//! dir = "~/dumps"
+//! colors = false
+//!
+//! [metrics]
+//! log_interval_minutes = 15
+//!
+//! [terminal]
+//! collapse_repeats = true
+//! repeat_threshold = 5
+//! max_repeats = 1000000
+//!
+//! [cues]
+//! ai_finished = "bell"
+//! suggestion = "off"
+//! command_failed = "both"
+//! long_command = "notify"
+//! long_command_secs = 30
+//! muted = false
+//!
+//! [display]
+//! performance = "on"
+//! ```
+//!
+//! A project can override a few of these for its own tree with a
+//! `.rustyterm.toml`; see [`project`].
+
+pub mod keymap;
+pub mod project;
+pub mod quick_actions;
+pub mod templates;
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::ai::provider::ProviderKind;
+use crate::ai::sampling::{Effort, Temperature};
+use crate::ai::usage::{Price, Prices};
+
+pub use keymap::{KeyChord, KeymapConfig, Leader, LeaderProgress};
+pub use project::{ProjectConfig, ProjectTracker};
+pub use quick_actions::{Placeholders, QuickAction, QuickActionKind};
+pub use templates::{ContextSection, Template};
+
+/// Top-level configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub keymap: KeymapConfig,
+    pub mouse: MouseConfig,
+    pub assistant: AssistantConfig,
+    pub clipboard: ClipboardConfig,
+    pub layout: LayoutConfig,
+    pub recording: RecordingConfig,
+    pub dump: DumpConfig,
+    pub metrics: MetricsConfig,
+    pub terminal: TerminalConfig,
+    pub cues: CuesConfig,
+    pub display: DisplayConfig,
+    /// Presets offered when creating a new AI session
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<Template>,
+    /// The user's own command mode keys
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub quick_actions: Vec<QuickAction>,
+}
+
+/// Mouse section of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MouseConfig {
+    /// Highlight clickable elements under the pointer (tracks motion events)
+    pub hover: bool,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self { hover: true }
+    }
+}
+
+/// Assistant section of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssistantConfig {
+    /// Backend the requests go to
+    pub provider: ProviderKind,
+    /// Where the backend listens (None = its usual place: OpenAI's API, or
+    /// Ollama on this machine)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider_url: Option<String>,
+    /// Key of the backend (None = from the environment: `OPENAI_API_KEY`
+    /// or `ANTHROPIC_API_KEY`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Model of sessions that don't name their own
+    pub model: String,
+    /// Answer calculator-style questions locally instead of asking the AI
+    /// (a leading `=` always evaluates locally)
+    pub local_answers: bool,
+    /// Reuse stored replies to questions asked without shell context
+    /// (a leading `?` strips the context)
+    pub response_cache: bool,
+    /// Age after which a cached reply is asked again
+    pub cache_max_age_days: u64,
+    /// Probe the system (uname, git status, tool versions) in a separate
+    /// shell and attach the result to requests
+    pub extended_context: bool,
+    /// Budget for the probe; whatever it printed by then is sent
+    pub extended_context_timeout_ms: u64,
+    /// Cap on the attached probe output, in KiB
+    pub extended_context_max_kb: usize,
+    /// Attach the start of the directory's README and the gist of its
+    /// manifests (Cargo.toml, package.json, ...) to requests
+    pub project_context: bool,
+    /// Keep unsent input on disk so it survives a restart
+    pub save_drafts: bool,
+    /// What happens to the oldest messages of a session that outgrew its
+    /// history budget
+    pub trim_strategy: TrimStrategy,
+    /// Model that writes the summary of folded messages
+    pub summary_model: String,
+    /// Key bindings of the input box
+    pub input_editing: InputEditing,
+    /// Run suggested commands with `RUSTYTERM_AI=1` in their environment,
+    /// so shell history shows which ones came from the assistant
+    pub mark_ai_commands: bool,
+    /// Visual mode copies include the model's reasoning, not just its answers
+    pub copy_reasoning: bool,
+    /// Enter with a suggested command still waiting rejects it and sends
+    /// the input, instead of asking to answer the card first
+    pub enter_rejects_pending: bool,
+    /// When the only suggestion of a reply needs confirmation or is
+    /// denied, ask once more for a safer command and offer it first
+    pub safer_alternatives: bool,
+    /// Distinct recent shell commands sent with each message (the history
+    /// itself keeps many more)
+    pub prompt_history: usize,
+    /// A file dropped on the assistant input (its path typed by the
+    /// terminal all at once) is offered for attaching to the next message
+    pub file_drop: bool,
+    /// Shortest stretch of a reply, in bytes, underlined as quoted verbatim
+    /// from the context sent with the question (0 = none are)
+    pub quote_min_len: usize,
+    /// Sampling temperature of conversational questions, from 0 to 2
+    /// (None = the backend's default)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<Temperature>,
+    /// Sampling temperature of questions that ask for a command
+    pub command_temperature: Temperature,
+    /// Reasoning effort of reasoning models, which take no temperature
+    /// (None = the backend's default)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<Effort>,
+    /// Whether replies are streamed or come whole, for proxies and servers
+    /// that don't pass streamed replies on
+    pub request_mode: RequestMode,
+    /// Give up on a reply after this long without any of it: the whole
+    /// reply when blocking, the next part when streaming (0 = never)
+    pub request_timeout_secs: u64,
+    /// Times a request is sent again after the rate limit, a server error
+    /// or a dropped connection, while none of its reply came
+    pub max_retries: u32,
+    /// Where a session's tab name comes from after its first question
+    pub session_names: SessionNames,
+    /// Estimated tokens of history sent with a question (None = by the
+    /// model's context window)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_budget: Option<usize>,
+    /// Dollars per thousand tokens sent, for the cost shown with a
+    /// session's token count (None = no cost shown)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_price_per_1k: Option<Price>,
+    /// Dollars per thousand tokens received, reasoning included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_price_per_1k: Option<Price>,
+}
+
+impl AssistantConfig {
+    /// The prices set for tokens, a missing one counting as free (None =
+    /// neither set).
+    pub fn prices(&self) -> Option<Prices> {
+        if self.prompt_price_per_1k.is_none() && self.completion_price_per_1k.is_none() {
+            return None;
+        }
+        Some(Prices {
+            prompt_per_1k: self.prompt_price_per_1k.unwrap_or_default(),
+            completion_per_1k: self.completion_price_per_1k.unwrap_or_default(),
+        })
+    }
+}
+
+/// How a session's history is brought back within its budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrimStrategy {
+    /// Forget the oldest messages
+    Drop,
+    /// Fold the oldest messages into a summary that is sent instead
+    #[default]
+    Summarize,
+}
+
+/// How a reply comes back from the API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestMode {
+    /// Part by part as it is written (server-sent events)
+    #[default]
+    Streaming,
+    /// All at once when it is done
+    Blocking,
+}
+
+/// How a session is named after its first question, unless renamed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionNames {
+    /// The start of the question
+    #[default]
+    Question,
+    /// A few words from the summary model, the start of the question until
+    /// they come
+    Model,
+}
+
+/// How keys edit the assistant input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputEditing {
+    /// Always inserting, with the usual readline-like keys
+    #[default]
+    Emacs,
+    /// Vi insert and normal modes, Esc switching to normal
+    Vi,
+}
+
+impl Default for AssistantConfig {
+    fn default() -> Self {
+        Self {
+            provider: ProviderKind::default(),
+            provider_url: None,
+            api_key: None,
+            model: "gpt-4o-mini".to_string(),
+            local_answers: true,
+            response_cache: false,
+            cache_max_age_days: 7,
+            extended_context: true,
+            extended_context_timeout_ms: 1500,
+            extended_context_max_kb: 8,
+            project_context: true,
+            save_drafts: true,
+            trim_strategy: TrimStrategy::default(),
+            summary_model: "gpt-4o-mini".to_string(),
+            input_editing: InputEditing::default(),
+            mark_ai_commands: false,
+            copy_reasoning: false,
+            enter_rejects_pending: false,
+            safer_alternatives: false,
+            prompt_history: crate::context::DEFAULT_PROMPT_HISTORY,
+            file_drop: true,
+            quote_min_len: crate::ai::quotes::DEFAULT_MIN_LEN,
+            temperature: None,
+            command_temperature: Temperature::COMMAND,
+            reasoning_effort: None,
+            request_mode: RequestMode::default(),
+            request_timeout_secs: 60,
+            max_retries: crate::ai::errors::DEFAULT_MAX_RETRIES,
+            session_names: SessionNames::default(),
+            history_budget: None,
+            prompt_price_per_1k: None,
+            completion_price_per_1k: None,
+        }
+    }
+}
+
+/// Clipboard section of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    /// When the system clipboard is unavailable (SSH, headless), send copies
+    /// to the host terminal as OSC 52 escape sequences
+    pub osc52: bool,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self { osc52: true }
+    }
+}
+
+/// Layout section of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// Below this many columns only the active pane is shown
+    pub split_min_width: u16,
+    /// Below this many rows only the active pane is shown
+    pub split_min_height: u16,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self { split_min_width: 70, split_min_height: 18 }
+    }
+}
+
+/// Recording section of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    /// Where cast files go (None = `recordings` in the data directory); a
+    /// leading `~/` is the home directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// Also record what is typed into the shell (never while it has echo off)
+    pub record_input: bool,
+}
+
+impl RecordingConfig {
+    /// Directory for new recordings, if one can be determined.
+    pub fn dir(&self) -> Option<PathBuf> {
+        match &self.dir {
+            Some(dir) => expand_home(dir),
+            None => crate::utils::persist::data_dir().map(|data| data.join("recordings")),
+        }
+    }
+}
+
+/// Scrollback dump section of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DumpConfig {
+    /// Where scrollback dumps go (None = `dumps` in the data directory); a
+    /// leading `~/` is the home directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// Keep colors and text attributes as ANSI sequences (for `less -R`)
+    pub colors: bool,
+}
+
+impl DumpConfig {
+    /// Directory for new dumps, if one can be determined.
+    pub fn dir(&self) -> Option<PathBuf> {
+        match &self.dir {
+            Some(dir) => expand_home(dir),
+            None => crate::utils::persist::data_dir().map(|data| data.join("dumps")),
+        }
+    }
+}
+
+/// `dir` with a leading `~` replaced by the home directory.
+pub(crate) fn expand_home(dir: &std::path::Path) -> Option<PathBuf> {
+    match dir.strip_prefix("~") {
+        Ok(rest) => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(rest)),
+        Err(_) => Some(dir.to_path_buf()),
+    }
+}
+
+/// Metrics section of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Write a metrics summary to the log this often (0 = never)
+    pub log_interval_minutes: u64,
+}
+
+impl MetricsConfig {
+    /// Time between two summaries in the log, if they are written.
+    pub fn log_interval(&self) -> Option<std::time::Duration> {
+        let secs = self.log_interval_minutes.saturating_mul(60);
+        (secs > 0).then(|| std::time::Duration::from_secs(secs))
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { log_interval_minutes: 15 }
+    }
+}
+
+/// Terminal pane section of the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerminalConfig {
+    /// Collapse runs of identical output lines into one marker row
+    pub collapse_repeats: bool,
+    /// Identical lines in a row that collapse, the first one (which stays)
+    /// included
+    pub repeat_threshold: usize,
+    /// Copies one marker row stands for at most; the next copy is shown
+    /// again and starts a new run
+    pub max_repeats: u64,
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            collapse_repeats: true,
+            repeat_threshold: 5,
+            max_repeats: 1_000_000,
+        }
+    }
+}
+
+/// What a cue does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cue {
+    /// Nothing
+    #[default]
+    Off,
+    /// Ring the terminal bell
+    Bell,
+    /// Send a desktop notification (OSC 9)
+    Notify,
+    /// Both
+    Both,
+}
+
+/// Cues section of the config file: what happens when something the user
+/// may be waiting on finishes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CuesConfig {
+    /// An AI reply finished streaming
+    pub ai_finished: Cue,
+    /// The AI suggested a command
+    pub suggestion: Cue,
+    /// A shell command exited with a nonzero status
+    pub command_failed: Cue,
+    /// A shell command ran at least `long_command_secs`
+    pub long_command: Cue,
+    pub long_command_secs: u64,
+    /// Start muted (command mode `b` toggles)
+    pub muted: bool,
+}
+
+impl Default for CuesConfig {
+    fn default() -> Self {
+        Self {
+            ai_finished: Cue::Off,
+            suggestion: Cue::Off,
+            command_failed: Cue::Off,
+            long_command: Cue::Off,
+            long_command_secs: 10,
+            muted: false,
+        }
+    }
+}
+
+/// Whether performance mode is used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PerformanceSetting {
+    /// On when the terminal answers a startup probe slowly
+    #[default]
+    Auto,
+    /// Always
+    On,
+    /// Never
+    Off,
+}
+
+/// Display section of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// Strip expensive decorations (RGB colors, hover, scrollbars) for slow
+    /// terminals; `--performance` and `--no-performance` override it
+    pub performance: PerformanceSetting,
+}
+
+impl Config {
+    /// Parse a config from TOML text. Quick actions are checked against
+    /// the built-in keys and the leader too.
+    pub fn from_toml_str(text: &str) -> Result<Self, toml::de::Error> {
+        let config: Self = toml::from_str(text)?;
+        quick_actions::check(&config.quick_actions, &config.keymap.leader)
+            .map_err(<toml::de::Error as serde::de::Error>::custom)?;
+        Ok(config)
+    }
+
+    /// Serialize the config to TOML text.
+    pub fn to_toml_string(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string_pretty(self)
+    }
+
+    /// Load the config from the default location, falling back to defaults.
+    pub fn load() -> Self {
+        let Some(path) = config_path() else {
+            return Self::default();
+        };
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                warn!("Failed to read config {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+
+        match Self::from_toml_str(&text) {
+            Ok(config) => {
+                info!("Loaded config from {}", path.display());
+                config
+            }
+            Err(e) => {
+                warn!("Ignoring invalid config {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Location of the config file, if one can be determined.
+pub fn config_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("RUSTY_TERM_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Directory of the config file and the files that go with it
+/// (`~/.config/rusty-term`), whatever `RUSTY_TERM_CONFIG` says.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("rusty-term"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_config_is_default() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::from_toml_str("")?, Config::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_keymap_config_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::from_toml_str(
+            "[keymap]\nleader = \"ctrl+a r\"\ncommand_timeout_ms = 2500\nmacro_delay_ms = 0\n",
+        )?;
+        assert_eq!(config.keymap.leader.to_string(), "Ctrl+A R");
+        assert_eq!(config.keymap.command_timeout_ms, Some(2500));
+        assert_eq!(config.keymap.macro_delay_ms, 0);
+        assert_eq!(Config::default().keymap.macro_delay_ms, 5);
+
+        let text = config.to_toml_string()?;
+        assert_eq!(Config::from_toml_str(&text)?, config);
+        Ok(())
+    }
+
+    #[test]
+    fn test_templates_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::from_toml_str(
+            r#"
+[[templates]]
+name = "code review"
+context = ["git", "files"]
+
+[[templates]]
+name = "k8s ops"
+system_prompt = "You are a Kubernetes expert."
+model = "gpt-4o"
+greeting = "Which cluster?"
+"#,
+        )?;
+        assert_eq!(config.templates.len(), 2);
+
+        let review = &config.templates[0];
+        assert_eq!(review.name, "code review");
+        assert!(review.attaches(ContextSection::Git));
+        assert!(review.attaches(ContextSection::Files));
+        assert!(!review.attaches(ContextSection::Tools));
+        assert_eq!(review.system_prompt, None);
+
+        let k8s = &config.templates[1];
+        assert_eq!(k8s.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(k8s.greeting.as_deref(), Some("Which cluster?"));
+
+        let text = config.to_toml_string()?;
+        assert_eq!(Config::from_toml_str(&text)?, config);
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_requires_name() {
+        assert!(Config::from_toml_str("[[templates]]\nmodel = \"gpt-4o\"\n").is_err());
+        assert!(Config::from_toml_str("[[templates]]\nname = \"x\"\ncontext = [\"disk\"]\n").is_err());
+    }
+
+    #[test]
+    fn test_recording_input_is_off_by_default() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(!Config::default().recording.record_input);
+        let config = Config::from_toml_str("[recording]\ndir = \"/tmp/casts\"\nrecord_input = true\n")?;
+        assert!(config.recording.record_input);
+        assert_eq!(config.recording.dir(), Some(PathBuf::from("/tmp/casts")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_colors_are_off_by_default() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(!Config::default().dump.colors);
+        let config = Config::from_toml_str("[dump]\ndir = \"/tmp/dumps\"\ncolors = true\n")?;
+        assert!(config.dump.colors);
+        assert_eq!(config.dump.dir(), Some(PathBuf::from("/tmp/dumps")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_metrics_log_can_be_turned_off() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::default().metrics.log_interval(), Some(std::time::Duration::from_secs(15 * 60)));
+        let config = Config::from_toml_str("[metrics]\nlog_interval_minutes = 0\n")?;
+        assert_eq!(config.metrics.log_interval(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mouse_hover_can_be_disabled() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(Config::default().mouse.hover);
+        let config = Config::from_toml_str("[mouse]\nhover = false\n")?;
+        assert!(!config.mouse.hover);
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_answers_can_be_disabled() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(Config::default().assistant.local_answers);
+        let config = Config::from_toml_str("[assistant]\nlocal_answers = false\n")?;
+        assert!(!config.assistant.local_answers);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mark_ai_commands_is_opt_in() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(!Config::default().assistant.mark_ai_commands);
+        let config = Config::from_toml_str("[assistant]\nmark_ai_commands = true\n")?;
+        assert!(config.assistant.mark_ai_commands);
+        Ok(())
+    }
+
+    #[test]
+    fn test_safer_alternatives_are_opt_in() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(!Config::default().assistant.safer_alternatives);
+        let config = Config::from_toml_str("[assistant]\nsafer_alternatives = true\n")?;
+        assert!(config.assistant.safer_alternatives);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prompt_history_is_configurable() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::default().assistant.prompt_history, 20);
+        let config = Config::from_toml_str("[assistant]\nprompt_history = 5\n")?;
+        assert_eq!(config.assistant.prompt_history, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_drop_can_be_turned_off() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(Config::default().assistant.file_drop);
+        let config = Config::from_toml_str("[assistant]\nfile_drop = false\n")?;
+        assert!(!config.assistant.file_drop);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sampling_defaults_and_range() -> Result<(), Box<dyn std::error::Error>> {
+        let default = Config::default().assistant;
+        assert_eq!((default.temperature, default.reasoning_effort), (None, None));
+        assert_eq!(default.command_temperature, Temperature::COMMAND);
+        let config = Config::from_toml_str(
+            "[assistant]\ntemperature = 0.7\ncommand_temperature = 0\nreasoning_effort = \"high\"\n",
+        )?;
+        assert_eq!(config.assistant.temperature, Some("0.7".parse()?));
+        assert_eq!(config.assistant.command_temperature, "0".parse()?);
+        assert_eq!(config.assistant.reasoning_effort, Some(Effort::High));
+        assert_eq!(Config::from_toml_str(&config.to_toml_string()?)?, config);
+
+        let error = Config::from_toml_str("[assistant]\ntemperature = 3.5\n").err().map(|e| e.to_string());
+        assert!(error.is_some_and(|e| e.contains("temperature must be from 0 to 2")));
+        assert!(Config::from_toml_str("[assistant]\nreasoning_effort = \"max\"\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_mode_and_timeout() -> Result<(), Box<dyn std::error::Error>> {
+        let default = Config::default().assistant;
+        assert_eq!((default.request_mode, default.request_timeout_secs), (RequestMode::Streaming, 60));
+        assert_eq!(default.max_retries, 3);
+        let config = Config::from_toml_str("[assistant]\nrequest_mode = \"blocking\"\nrequest_timeout_secs = 0\nmax_retries = 0\n")?;
+        assert_eq!((config.assistant.request_mode, config.assistant.request_timeout_secs), (RequestMode::Blocking, 0));
+        assert_eq!(config.assistant.max_retries, 0);
+        assert_eq!(Config::from_toml_str(&config.to_toml_string()?)?, config);
+        assert!(Config::from_toml_str("[assistant]\nrequest_mode = \"sse\"\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_provider_and_model() -> Result<(), Box<dyn std::error::Error>> {
+        let default = Config::default().assistant;
+        assert_eq!((default.provider, default.provider_url, default.model.as_str()), (ProviderKind::OpenAI, None, "gpt-4o-mini"));
+        let config = Config::from_toml_str(
+            "[assistant]\nprovider = \"ollama\"\nprovider_url = \"http://gpu-box:11434\"\nmodel = \"qwen2.5\"\n",
+        )?;
+        assert_eq!(config.assistant.provider, ProviderKind::Ollama);
+        assert_eq!(config.assistant.provider_url.as_deref(), Some("http://gpu-box:11434"));
+        assert_eq!(config.assistant.model, "qwen2.5");
+        assert_eq!(Config::from_toml_str(&config.to_toml_string()?)?, config);
+        assert!(Config::from_toml_str("[assistant]\nprovider = \"bard\"\n").is_err());
+        let config = Config::from_toml_str("[assistant]\nprovider = \"anthropic\"\napi_key = \"sk-ant-test\"\n")?;
+        assert_eq!((config.assistant.provider, config.assistant.api_key.as_deref()), (ProviderKind::Anthropic, Some("sk-ant-test")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_names() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::default().assistant.session_names, SessionNames::Question);
+        let config = Config::from_toml_str("[assistant]\nsession_names = \"model\"\n")?;
+        assert_eq!(config.assistant.session_names, SessionNames::Model);
+        assert!(Config::from_toml_str("[assistant]\nsession_names = \"random\"\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_prices() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::default().assistant.prices(), None);
+        let config = Config::from_toml_str("[assistant]\ncompletion_price_per_1k = 0.01\n")?;
+        let prices = config.assistant.prices().ok_or("no prices")?;
+        assert_eq!((f64::from(prices.prompt_per_1k), f64::from(prices.completion_per_1k)), (0.0, 0.01));
+        assert!(Config::from_toml_str("[assistant]\nprompt_price_per_1k = -1\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_cache_is_opt_in() -> Result<(), Box<dyn std::error::Error>> {
+        let default = Config::default().assistant;
+        assert!(!default.response_cache);
+        assert_eq!(default.cache_max_age_days, 7);
+        let config = Config::from_toml_str("[assistant]\nresponse_cache = true\ncache_max_age_days = 1\n")?;
+        assert!(config.assistant.response_cache);
+        assert_eq!(config.assistant.cache_max_age_days, 1);
+        assert!(config.assistant.local_answers);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extended_context_can_be_disabled() -> Result<(), Box<dyn std::error::Error>> {
+        let default = Config::default().assistant;
+        assert!(default.extended_context);
+        assert_eq!(default.extended_context_timeout_ms, 1500);
+        assert_eq!(default.extended_context_max_kb, 8);
+        let config = Config::from_toml_str(
+            "[assistant]\nextended_context = false\nextended_context_timeout_ms = 500\n",
+        )?;
+        assert!(!config.assistant.extended_context);
+        assert_eq!(config.assistant.extended_context_timeout_ms, 500);
+        assert_eq!(config.assistant.extended_context_max_kb, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_summarizing_can_be_skipped() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::default().assistant.trim_strategy, TrimStrategy::Summarize);
+        let config = Config::from_toml_str("[assistant]\ntrim_strategy = \"drop\"\n")?;
+        assert_eq!(config.assistant.trim_strategy, TrimStrategy::Drop);
+        assert_eq!(config.assistant.summary_model, "gpt-4o-mini");
+        assert!(Config::from_toml_str("[assistant]\ntrim_strategy = \"truncate\"\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_budget_is_by_model_unless_set() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::default().assistant.history_budget, None);
+        let config = Config::from_toml_str("[assistant]\nhistory_budget = 8000\n")?;
+        assert_eq!(config.assistant.history_budget, Some(8000));
+        assert!(Config::from_toml_str("[assistant]\nhistory_budget = -1\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeat_collapsing_can_be_disabled() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(Config::default().terminal.collapse_repeats);
+        let config = Config::from_toml_str("[terminal]\nrepeat_threshold = 10\n")?;
+        assert_eq!(config.terminal.repeat_threshold, 10);
+        assert_eq!(config.terminal.max_repeats, 1_000_000);
+        let config = Config::from_toml_str("[terminal]\ncollapse_repeats = false\n")?;
+        assert!(!config.terminal.collapse_repeats);
+        Ok(())
+    }
+
+    #[test]
+    fn test_vi_editing_is_opt_in() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::default().assistant.input_editing, InputEditing::Emacs);
+        let config = Config::from_toml_str("[assistant]\ninput_editing = \"vi\"\n")?;
+        assert_eq!(config.assistant.input_editing, InputEditing::Vi);
+        assert!(Config::from_toml_str("[assistant]\ninput_editing = \"vim\"\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_osc52_can_be_disabled() -> Result<(), Box<dyn std::error::Error>> {
+        assert!(Config::default().clipboard.osc52);
+        let config = Config::from_toml_str("[clipboard]\nosc52 = false\n")?;
+        assert!(!config.clipboard.osc52);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_thresholds_are_configurable() -> Result<(), Box<dyn std::error::Error>> {
+        let default = Config::default().layout;
+        assert_eq!((default.split_min_width, default.split_min_height), (70, 18));
+        let config = Config::from_toml_str("[layout]\nsplit_min_width = 100\n")?;
+        assert_eq!(config.layout.split_min_width, 100);
+        assert_eq!(config.layout.split_min_height, 18);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cues_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::default().cues.ai_finished, Cue::Off);
+        let config = Config::from_toml_str(
+            "[cues]\nai_finished = \"bell\"\ncommand_failed = \"both\"\nlong_command = \"notify\"\nlong_command_secs = 30\n",
+        )?;
+        assert_eq!(config.cues.ai_finished, Cue::Bell);
+        assert_eq!(config.cues.suggestion, Cue::Off);
+        assert_eq!(config.cues.command_failed, Cue::Both);
+        assert_eq!(config.cues.long_command, Cue::Notify);
+        assert_eq!(config.cues.long_command_secs, 30);
+        assert!(!config.cues.muted);
+
+        let text = config.to_toml_string()?;
+        assert_eq!(Config::from_toml_str(&text)?, config);
+        assert!(Config::from_toml_str("[cues]\nai_finished = \"beep\"\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_performance_mode_defaults_to_auto() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(Config::default().display.performance, PerformanceSetting::Auto);
+        let config = Config::from_toml_str("[display]\nperformance = \"off\"\n")?;
+        assert_eq!(config.display.performance, PerformanceSetting::Off);
+        assert!(Config::from_toml_str("[display]\nperformance = \"fast\"\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_leader_is_rejected() {
+        assert!(Config::from_toml_str("[keymap]\nleader = \"hyper+x\"\n").is_err());
+    }
+}
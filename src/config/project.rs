@@ -0,0 +1,453 @@
+//! Per-project settings from a `.rustyterm.toml` next to the code.
+//!
+//! When the shell's working directory is inside a project that has one (the
+//! file is looked up from the directory upwards, up to the git root and never
+//! in `$HOME` or above), its settings are laid over the global config until
+//! the shell leaves that tree:
+//!
+//! ```toml
+//! # Added to the system prompt
+//! system_prompt = "This repo manages our Kubernetes clusters."
+//! # Used unless the session picked a model itself
+//! model = "gpt-4o"
+//! # Attached to every request
+//! context = ["git"]
+//!
+//! [security]
+//! deny = ["kubectl delete", "terraform apply"]
+//! confirm = ["kubectl"]
+//! ```
+//!
+//! Only these settings exist; any other key makes the file invalid. Since
+//! the file comes with the repository, its security rules can only make the
+//! built-in policy stricter, and a file is not used until the user trusted
+//! it. Trust is recorded per file path and content, so an edited file is
+//! asked about again.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::ContextSection;
+use crate::ai::cache::fnv1a;
+use crate::security::ProjectRules;
+use crate::utils::persist::{self, FileLock, LOCK_TIMEOUT};
+
+/// Name of the project config file.
+pub const PROJECT_FILE: &str = ".rustyterm.toml";
+
+/// Where trust decisions are stored, in the data directory.
+const TRUST_FILE_NAME: &str = "trusted_projects.json";
+
+/// Settings a project file may override.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Appended to the system prompt of every request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Model used by sessions that did not pick one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Context sections attached to every request
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<ContextSection>,
+    /// Extra deny/confirm rules for suggested commands
+    #[serde(skip_serializing_if = "ProjectRules::is_empty")]
+    pub security: ProjectRules,
+}
+
+impl ProjectConfig {
+    /// Parse a project config from TOML text.
+    pub fn from_toml_str(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    /// One line per setting, for the trust prompt.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(prompt) = &self.system_prompt {
+            lines.push(format!("system prompt: +{} chars", prompt.chars().count()));
+        }
+        if let Some(model) = &self.model {
+            lines.push(format!("model: {}", model));
+        }
+        if !self.context.is_empty() {
+            let sections: Vec<String> = self.context.iter().map(|s| format!("{:?}", s).to_lowercase()).collect();
+            lines.push(format!("context: {}", sections.join(", ")));
+        }
+        if !self.security.deny.is_empty() {
+            lines.push(format!("deny: {}", self.security.deny.join(", ")));
+        }
+        if !self.security.confirm.is_empty() {
+            lines.push(format!("confirm: {}", self.security.confirm.join(", ")));
+        }
+        lines
+    }
+}
+
+/// A project file that was found and parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    /// Path of the `.rustyterm.toml`
+    pub file: PathBuf,
+    /// Hash of the file contents, what trust is given to
+    pub hash: u64,
+    pub config: ProjectConfig,
+}
+
+impl Project {
+    /// Name shown in the status indicator (the project directory).
+    pub fn name(&self) -> String {
+        self.file
+            .parent()
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "/".to_string())
+    }
+}
+
+/// Find the project file that applies to `cwd`.
+///
+/// Directories are checked from `cwd` upwards; the search ends after the git
+/// root (a directory with `.git`) and before `home`.
+pub fn find_project_file(cwd: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    for dir in cwd.ancestors() {
+        if Some(dir) == home {
+            return None;
+        }
+        let file = dir.join(PROJECT_FILE);
+        if file.is_file() {
+            return Some(file);
+        }
+        if dir.join(".git").exists() {
+            return None;
+        }
+    }
+    None
+}
+
+/// A stored answer to "trust this project file?".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct TrustEntry {
+    file: PathBuf,
+    hash: u64,
+    trusted: bool,
+}
+
+/// Trust decisions for project files, shared by all instances.
+#[derive(Debug)]
+pub struct TrustStore {
+    /// Backing file (None keeps decisions in memory only)
+    path: Option<PathBuf>,
+    entries: Vec<TrustEntry>,
+}
+
+impl TrustStore {
+    /// Store that is never written to disk.
+    pub fn in_memory() -> Self {
+        Self { path: None, entries: Vec::new() }
+    }
+
+    /// Store backed by `path`, loading whatever is stored there.
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let entries = match read_entries(&path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Ignoring unreadable project trust file {}: {}", path.display(), e);
+                Vec::new()
+            }
+        };
+        Self { path: Some(path), entries }
+    }
+
+    /// Store in the data directory (in memory only if there is none).
+    pub fn open() -> Self {
+        match persist::data_dir() {
+            Some(dir) => Self::at(dir.join(TRUST_FILE_NAME)),
+            None => Self::in_memory(),
+        }
+    }
+
+    /// The decision made for this version of the file, if any.
+    pub fn decision(&self, file: &Path, hash: u64) -> Option<bool> {
+        self.entries
+            .iter()
+            .find(|e| e.file == file && e.hash == hash)
+            .map(|e| e.trusted)
+    }
+
+    /// Record a decision, replacing any earlier one for the same file.
+    pub fn record(&mut self, file: &Path, hash: u64, trusted: bool) {
+        let entry = TrustEntry { file: file.to_path_buf(), hash, trusted };
+        let Some(path) = self.path.clone() else {
+            self.entries.retain(|e| e.file != entry.file);
+            self.entries.push(entry);
+            return;
+        };
+
+        // Another instance may have recorded decisions since we loaded
+        let lock = FileLock::acquire(&path, LOCK_TIMEOUT);
+        if lock.is_ok()
+            && let Ok(entries) = read_entries(&path)
+        {
+            self.entries = entries;
+        }
+        self.entries.retain(|e| e.file != entry.file);
+        self.entries.push(entry);
+
+        let Ok(_lock) = lock.inspect_err(|e| warn!("Project trust not saved: {}", e)) else {
+            return;
+        };
+        let result = serde_json::to_vec(&self.entries)
+            .map_err(io::Error::from)
+            .and_then(|bytes| persist::write_atomic(&path, &bytes));
+        if let Err(e) = result {
+            warn!("Failed to save project trust {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn read_entries(path: &Path) -> io::Result<Vec<TrustEntry>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// What changed after the working directory moved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectChange {
+    Unchanged,
+    /// A trusted project file now applies
+    Entered,
+    /// The project overlay was removed
+    Left,
+    /// A project file applies but the user has not decided whether to trust
+    /// it yet; see [`ProjectTracker::pending`]
+    NeedsTrust,
+    /// The project file could not be used (the overlay, if any, was removed)
+    Invalid(String),
+}
+
+/// Follows the working directory and decides which project file applies.
+#[derive(Debug)]
+pub struct ProjectTracker {
+    trust: TrustStore,
+    home: Option<PathBuf>,
+    /// Trusted project in effect
+    active: Option<Project>,
+    /// Project waiting for a trust decision
+    pending: Option<Project>,
+    /// Last file version reported as invalid, so it is reported once
+    invalid: Option<(PathBuf, u64)>,
+}
+
+impl ProjectTracker {
+    /// Tracker that stops searching at `home`.
+    pub fn new(trust: TrustStore, home: Option<PathBuf>) -> Self {
+        Self { trust, home, active: None, pending: None, invalid: None }
+    }
+
+    /// Tracker using `$HOME` and the persisted trust decisions.
+    pub fn open() -> Self {
+        Self::new(TrustStore::open(), std::env::var_os("HOME").map(PathBuf::from))
+    }
+
+    /// Trusted project in effect.
+    pub fn active(&self) -> Option<&Project> {
+        self.active.as_ref()
+    }
+
+    /// Project waiting for a trust decision.
+    pub fn pending(&self) -> Option<&Project> {
+        self.pending.as_ref()
+    }
+
+    /// The shell moved to `cwd`. The project file is read again every time,
+    /// so edits are picked up on the next directory change.
+    pub fn update_cwd(&mut self, cwd: &Path) -> ProjectChange {
+        let Some(file) = find_project_file(cwd, self.home.as_deref()) else {
+            self.pending = None;
+            return self.leave();
+        };
+
+        let text = match std::fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("Failed to read {}: {}", file.display(), e);
+                self.pending = None;
+                return self.leave();
+            }
+        };
+        let hash = fnv1a(&text);
+        let config = match ProjectConfig::from_toml_str(&text) {
+            Ok(config) => config,
+            Err(e) => {
+                self.pending = None;
+                let left = self.leave();
+                if self.invalid.as_ref() == Some(&(file.clone(), hash)) {
+                    return left;
+                }
+                warn!("Ignoring invalid project config {}: {}", file.display(), e);
+                self.invalid = Some((file.clone(), hash));
+                return ProjectChange::Invalid(format!("Ignoring {}: {}", file.display(), e.message()));
+            }
+        };
+
+        let project = Project { file, hash, config };
+        if self.active.as_ref() == Some(&project) || self.pending.as_ref() == Some(&project) {
+            return ProjectChange::Unchanged;
+        }
+        match self.trust.decision(&project.file, project.hash) {
+            Some(true) => {
+                self.pending = None;
+                self.active = Some(project);
+                ProjectChange::Entered
+            }
+            Some(false) => {
+                self.pending = None;
+                self.leave()
+            }
+            None => {
+                self.active = None;
+                self.pending = Some(project);
+                ProjectChange::NeedsTrust
+            }
+        }
+    }
+
+    /// Answer the trust prompt for the pending project.
+    pub fn answer(&mut self, trusted: bool) -> ProjectChange {
+        let Some(project) = self.pending.take() else {
+            return ProjectChange::Unchanged;
+        };
+        self.trust.record(&project.file, project.hash, trusted);
+        if trusted {
+            self.active = Some(project);
+            ProjectChange::Entered
+        } else {
+            ProjectChange::Unchanged
+        }
+    }
+
+    fn leave(&mut self) -> ProjectChange {
+        match self.active.take() {
+            Some(_) => ProjectChange::Left,
+            None => ProjectChange::Unchanged,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory tree: `home/repo/.git`, `home/repo/sub/deeper`.
+    fn scratch(name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let root = std::env::temp_dir().join(format!("rusty-term-project-{}-{}", name, std::process::id()));
+        if root.exists() {
+            std::fs::remove_dir_all(&root)?;
+        }
+        std::fs::create_dir_all(root.join("home/repo/.git"))?;
+        std::fs::create_dir_all(root.join("home/repo/sub/deeper"))?;
+        Ok(root)
+    }
+
+    #[test]
+    fn test_search_goes_up_to_git_root() -> Result<(), Box<dyn std::error::Error>> {
+        let root = scratch("search")?;
+        let home = root.join("home");
+        let repo = home.join("repo");
+        let deeper = repo.join("sub/deeper");
+
+        assert_eq!(find_project_file(&deeper, Some(&home)), None);
+
+        std::fs::write(repo.join(PROJECT_FILE), "")?;
+        assert_eq!(find_project_file(&deeper, Some(&home)), Some(repo.join(PROJECT_FILE)));
+        assert_eq!(find_project_file(&repo, Some(&home)), Some(repo.join(PROJECT_FILE)));
+
+        // The nearest file wins
+        std::fs::write(repo.join("sub").join(PROJECT_FILE), "")?;
+        assert_eq!(find_project_file(&deeper, Some(&home)), Some(repo.join("sub").join(PROJECT_FILE)));
+
+        // Nothing above the git root or in $HOME is used
+        std::fs::remove_file(repo.join(PROJECT_FILE))?;
+        std::fs::remove_file(repo.join("sub").join(PROJECT_FILE))?;
+        std::fs::write(home.join(PROJECT_FILE), "")?;
+        std::fs::write(root.join(PROJECT_FILE), "")?;
+        assert_eq!(find_project_file(&deeper, Some(&home)), None);
+        assert_eq!(find_project_file(&home, Some(&home)), None);
+        // Outside a repository the search stops at $HOME
+        std::fs::create_dir_all(home.join("notes"))?;
+        assert_eq!(find_project_file(&home.join("notes"), Some(&home)), None);
+
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_known_settings_are_accepted() -> Result<(), Box<dyn std::error::Error>> {
+        let config = ProjectConfig::from_toml_str(
+            "system_prompt = \"k8s\"\nmodel = \"gpt-4o\"\ncontext = [\"git\"]\n\n[security]\ndeny = [\"kubectl delete\"]\n",
+        )?;
+        assert_eq!(config.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(config.context, vec![ContextSection::Git]);
+        assert_eq!(config.security.deny, vec!["kubectl delete".to_string()]);
+        assert_eq!(config.summary().len(), 4);
+
+        // Global settings and loosening rules are not available to projects
+        assert!(ProjectConfig::from_toml_str("[keymap]\nleader = \"ctrl+a\"\n").is_err());
+        assert!(ProjectConfig::from_toml_str("[security]\nallow = [\"rm\"]\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trust_prompt_flow() -> Result<(), Box<dyn std::error::Error>> {
+        let root = scratch("trust")?;
+        let home = root.join("home");
+        let repo = home.join("repo");
+        let file = repo.join(PROJECT_FILE);
+        std::fs::write(&file, "model = \"gpt-4o\"\n")?;
+        let store = root.join(TRUST_FILE_NAME);
+
+        // First visit asks, and nothing applies until answered
+        let mut tracker = ProjectTracker::new(TrustStore::at(&store), Some(home.clone()));
+        assert_eq!(tracker.update_cwd(&repo.join("sub")), ProjectChange::NeedsTrust);
+        assert!(tracker.active().is_none());
+        assert_eq!(tracker.update_cwd(&repo), ProjectChange::Unchanged);
+        assert_eq!(tracker.answer(true), ProjectChange::Entered);
+        assert_eq!(tracker.active().map(Project::name).as_deref(), Some("repo"));
+
+        // Moving within the tree keeps it, leaving removes it
+        assert_eq!(tracker.update_cwd(&repo.join("sub/deeper")), ProjectChange::Unchanged);
+        assert_eq!(tracker.update_cwd(&home), ProjectChange::Left);
+        assert!(tracker.active().is_none());
+
+        // The decision is remembered by another instance
+        let mut other = ProjectTracker::new(TrustStore::at(&store), Some(home.clone()));
+        assert_eq!(other.update_cwd(&repo), ProjectChange::Entered);
+
+        // An edited file is asked about again; declining leaves it unused
+        std::fs::write(&file, "model = \"gpt-4o\"\n[security]\nconfirm = [\"ls\"]\n")?;
+        assert_eq!(other.update_cwd(&repo.join("sub")), ProjectChange::NeedsTrust);
+        assert!(other.active().is_none());
+        assert_eq!(other.answer(false), ProjectChange::Unchanged);
+        assert_eq!(other.update_cwd(&repo), ProjectChange::Unchanged);
+        assert!(other.active().is_none() && other.pending().is_none());
+        let mut third = ProjectTracker::new(TrustStore::at(&store), Some(home.clone()));
+        assert_eq!(third.update_cwd(&repo), ProjectChange::Unchanged);
+
+        // A broken file is reported once and not used
+        std::fs::write(&file, "[keymap]\n")?;
+        assert!(matches!(third.update_cwd(&repo), ProjectChange::Invalid(_)));
+        assert_eq!(third.update_cwd(&repo), ProjectChange::Unchanged);
+
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
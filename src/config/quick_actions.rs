@@ -0,0 +1,246 @@
+//! Quick actions: the user's own command mode keys.
+//!
+//! ```toml
+//! [[quick_actions]]
+//! name = "debug preamble"
+//! key = "i"
+//! insert_text = "I'm debugging a flaky test. Keep answers short."
+//!
+//! [[quick_actions]]
+//! name = "git status"
+//! key = "g"
+//! run_shell = { command = "git status", confirm = false }
+//!
+//! [[quick_actions]]
+//! name = "why did it fail"
+//! key = "f"
+//! ask_ai = "Why did `{last_command}` fail?\n\n{last_output}"
+//! ```
+//!
+//! An `ask_ai` question may use the [`PLACEHOLDERS`], filled in when the
+//! key is pressed. A key the built-in command mode already uses (or the
+//! leader) makes the config invalid, like any other mistake in it.
+
+use crossterm::event::KeyCode;
+use serde::{Deserialize, Serialize};
+
+use super::keymap::{KeyChord, Leader};
+use crate::event::actions::command_key_owner;
+
+/// Names an `ask_ai` question can put in braces.
+pub const PLACEHOLDERS: [&str; 4] = ["last_command", "last_output", "selection", "cwd"];
+
+/// A command mode key of the user's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuickAction {
+    /// Shown in the command mode popup
+    pub name: String,
+    /// Pressed after the leader
+    pub key: KeyChord,
+    #[serde(flatten)]
+    pub kind: QuickActionKind,
+}
+
+/// What a quick action does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuickActionKind {
+    /// Put the text into the assistant input, at the cursor
+    InsertText(String),
+    /// Type the command into the shell. It goes through the security gate
+    /// either way; with `confirm` it also waits for a yes first.
+    RunShell {
+        command: String,
+        #[serde(default = "confirm_by_default")]
+        confirm: bool,
+    },
+    /// Ask the active session this question, placeholders filled in
+    AskAi(String),
+}
+
+fn confirm_by_default() -> bool {
+    true
+}
+
+/// Check `actions` against the built-in command mode keys, the leader and
+/// each other, and their questions for placeholders that don't exist.
+pub fn check(actions: &[QuickAction], leader: &Leader) -> Result<(), String> {
+    for (i, action) in actions.iter().enumerate() {
+        let key = action.key;
+        if let Some(builtin) = command_key_owner(key) {
+            return Err(format!(
+                "quick action '{}': {} is taken by '{}' ({})",
+                action.name, key, builtin.name, builtin.description
+            ));
+        }
+        if leader.chords().first().is_some_and(|chord| chord.matches(&key.to_key_event())) {
+            return Err(format!("quick action '{}': {} starts the leader", action.name, key));
+        }
+        if let Some(other) = actions[..i].iter().find(|other| other.key.matches(&key.to_key_event())) {
+            return Err(format!("quick action '{}': {} is already bound to '{}'", action.name, key, other.name));
+        }
+        if let QuickActionKind::AskAi(question) = &action.kind
+            && let Some(name) = unknown_placeholder(question)
+        {
+            return Err(format!(
+                "quick action '{}': unknown placeholder {{{}}} (known: {})",
+                action.name,
+                name,
+                PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// First `{word}` in `text` that isn't one of the [`PLACEHOLDERS`]. Braces
+/// around anything but a plain word (JSON, shell `${VAR}`) don't count.
+fn unknown_placeholder(text: &str) -> Option<&str> {
+    placeholder_names(text).find(|name| !PLACEHOLDERS.contains(name))
+}
+
+fn placeholder_names(text: &str) -> impl Iterator<Item = &str> {
+    text.split('{').skip(1).filter_map(|after| {
+        let name = &after[..after.find('}')?];
+        let word = !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_');
+        word.then_some(name)
+    })
+}
+
+/// Values of the placeholders when a quick action runs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Placeholders {
+    pub last_command: String,
+    pub last_output: String,
+    pub selection: String,
+    pub cwd: String,
+}
+
+impl Placeholders {
+    fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "last_command" => Some(&self.last_command),
+            "last_output" => Some(&self.last_output),
+            "selection" => Some(&self.selection),
+            "cwd" => Some(&self.cwd),
+            _ => None,
+        }
+    }
+
+    /// `template` with every placeholder replaced by its value. Other text
+    /// in braces stays as written, and values are not expanded again.
+    pub fn expand(&self, template: &str) -> String {
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            out.push_str(&rest[..open]);
+            let after = &rest[open + 1..];
+            let value = after.find('}').and_then(|close| Some((close, self.get(&after[..close])?)));
+            match value {
+                Some((close, value)) => {
+                    out.push_str(value);
+                    rest = &after[close + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+impl QuickAction {
+    /// The key as the command mode popup shows it.
+    pub fn key_text(&self) -> String {
+        match self.key.code {
+            KeyCode::Char(c) if self.key.modifiers.is_empty() => c.to_string(),
+            _ => self.key.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_placeholders_are_filled_in_once() {
+        let values = Placeholders {
+            last_command: "cargo test".to_string(),
+            last_output: "error[E0308]: mismatched types {cwd}".to_string(),
+            selection: String::new(),
+            cwd: "/src/app".to_string(),
+        };
+        assert_eq!(
+            values.expand("Why did `{last_command}` fail in {cwd}?\n{last_output}"),
+            "Why did `cargo test` fail in /src/app?\nerror[E0308]: mismatched types {cwd}"
+        );
+        assert_eq!(values.expand("[{selection}]"), "[]");
+        assert_eq!(values.expand("echo ${HOME} {\"a\": 1} {cwd"), "echo ${HOME} {\"a\": 1} {cwd");
+    }
+
+    #[test]
+    fn test_quick_actions_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::from_toml_str(
+            r#"
+[[quick_actions]]
+name = "preamble"
+key = "i"
+insert_text = "I'm debugging."
+
+[[quick_actions]]
+name = "git status"
+key = "alt+g"
+run_shell = { command = "git status", confirm = false }
+
+[[quick_actions]]
+name = "fail"
+key = "f"
+ask_ai = "Why did {last_command} fail?"
+"#,
+        )?;
+        let actions = &config.quick_actions;
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0].kind, QuickActionKind::InsertText("I'm debugging.".to_string()));
+        assert_eq!(actions[1].kind, QuickActionKind::RunShell { command: "git status".to_string(), confirm: false });
+        assert_eq!(actions[1].key_text(), "Alt+G");
+        assert_eq!(actions[2].key_text(), "f");
+
+        let text = config.to_toml_string()?;
+        assert_eq!(Config::from_toml_str(&text)?, config);
+
+        // Commands are confirmed unless said otherwise
+        let config = Config::from_toml_str("[[quick_actions]]\nname = \"x\"\nkey = \"x\"\nrun_shell = { command = \"make\" }\n")?;
+        assert_eq!(config.quick_actions[0].kind, QuickActionKind::RunShell { command: "make".to_string(), confirm: true });
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflicting_keys_are_rejected() {
+        let error = |toml: &str| Config::from_toml_str(toml).err().map(|e| e.message().to_string());
+
+        // Built-in keys, in either case and with any modifier
+        let taken = error("[[quick_actions]]\nname = \"git\"\nkey = \"Q\"\ninsert_text = \"x\"\n");
+        assert_eq!(taken.as_deref(), Some("quick action 'git': Q is taken by 'quit' (Exit RustyTerm)"));
+        assert!(error("[[quick_actions]]\nname = \"a\"\nkey = \"ctrl+o\"\ninsert_text = \"x\"\n").is_some());
+        assert!(error("[[quick_actions]]\nname = \"a\"\nkey = \"left\"\ninsert_text = \"x\"\n").is_some());
+
+        // The leader, and each other
+        let leader = error("[keymap]\nleader = \"ctrl+g\"\n[[quick_actions]]\nname = \"a\"\nkey = \"ctrl+g\"\ninsert_text = \"x\"\n");
+        assert_eq!(leader.as_deref(), Some("quick action 'a': Ctrl+G starts the leader"));
+        let twice = error(
+            "[[quick_actions]]\nname = \"a\"\nkey = \"g\"\ninsert_text = \"x\"\n\
+             [[quick_actions]]\nname = \"b\"\nkey = \"G\"\ninsert_text = \"y\"\n",
+        );
+        assert_eq!(twice.as_deref(), Some("quick action 'b': G is already bound to 'a'"));
+
+        // Placeholders that don't exist
+        let unknown = error("[[quick_actions]]\nname = \"a\"\nkey = \"g\"\nask_ai = \"in {dir}\"\n");
+        assert!(unknown.is_some_and(|e| e.contains("unknown placeholder {dir}")));
+        assert!(error("[[quick_actions]]\nname = \"a\"\nkey = \"g\"\nask_ai = \"echo ${HOME} in {cwd}\"\n").is_none());
+    }
+}
@@ -0,0 +1,61 @@
+//! Session templates: named presets for new AI sessions.
+//!
+//! ```toml
+//! [[templates]]
+//! name = "k8s ops"
+//! system_prompt = "You are a Kubernetes expert. Prefer kubectl commands."
+//! context = ["tools"]
+//! model = "gpt-4o"
+//! greeting = "Which cluster are we looking at?"
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// Context section that a template attaches to every request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextSection {
+    /// Git branch and working tree status
+    Git,
+    /// Listing of the current directory
+    Files,
+    /// Versions of common developer tools
+    Tools,
+}
+
+/// A preset used to create a new AI session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Template {
+    /// Template name, also used as the session tab name
+    pub name: String,
+    /// Replaces the built-in system prompt when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Context sections always attached, regardless of what the user asks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<ContextSection>,
+    /// Model used instead of the default one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Assistant message shown when the session opens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub greeting: Option<String>,
+}
+
+impl Template {
+    /// A template with only a name (behaves like a blank session).
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            system_prompt: None,
+            context: Vec::new(),
+            model: None,
+            greeting: None,
+        }
+    }
+
+    /// Check whether the template attaches the given context section.
+    pub fn attaches(&self, section: ContextSection) -> bool {
+        self.context.contains(&section)
+    }
+}
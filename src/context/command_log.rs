@@ -3,14 +3,37 @@
 //! This module records user-executed commands and their outputs,
 //! providing structured context to improve AI suggestions.
 
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
 
+use crate::utils::utf8::Utf8Decoder;
+
 /// A single command execution record with its output.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CommandRecord {
     #[serde(rename = "command")]
     pub command_line: String,
     pub output: String,
+    /// Exit status, when shell integration reports it
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub exit_code: Option<i32>,
+    /// When the command was started
+    #[serde(skip)]
+    pub started_at: Option<Instant>,
+    /// Where the command line sits in the terminal scrollback
+    #[serde(skip)]
+    pub position: Option<ScrollbackPosition>,
+}
+
+/// Location of a command line in the terminal pane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScrollbackPosition {
+    /// Absolute line number of the command line (1-based)
+    pub line: usize,
+    /// Terminal width when the command ran, to account for wrapped output
+    pub columns: usize,
 }
 
 impl CommandRecord {
@@ -19,14 +42,83 @@ impl CommandRecord {
         Self {
             command_line,
             output,
+            exit_code: None,
+            started_at: None,
+            position: None,
         }
     }
 }
 
+/// Lines of output longer than this many bytes keep only their head and
+/// tail, with a note on how much was left out (minified JSON, long logs).
+pub const LONG_LINE_BYTES: usize = 8 * 1024;
+/// Bytes kept at each end of a long line.
+const LONG_LINE_KEEP: usize = 512;
+
+/// The unfinished last line of the newest record's output.
+#[derive(Debug, Default)]
+struct OpenLine {
+    /// Where it starts in the output
+    start: usize,
+    /// Where the note on the cut-out middle sits, and the bytes cut so far
+    cut: Option<(Range<usize>, usize)>,
+}
+
+impl OpenLine {
+    fn at(start: usize) -> Self {
+        Self { start, cut: None }
+    }
+
+    /// Cut the middle out of the line at the end of `output` once it is too
+    /// long. The tail keeps moving while the line grows, so the middle is
+    /// cut again whenever the tail has doubled, and once the line is done.
+    fn shorten(&mut self, output: &mut String, done: bool) {
+        let (kept_from, limit) = match &self.cut {
+            Some((note, _)) if done => (note.end, LONG_LINE_KEEP),
+            Some((note, _)) => (note.end, 2 * LONG_LINE_KEEP),
+            None => (self.start, LONG_LINE_BYTES),
+        };
+        if output.len() - kept_from <= limit {
+            return;
+        }
+        let tail = ceil_char_boundary(output, output.len() - LONG_LINE_KEEP);
+        let (head_end, omitted) = match self.cut.take() {
+            Some((note, omitted)) => (note.start, omitted + tail - note.end),
+            None => {
+                let head_end = floor_char_boundary(output, self.start + LONG_LINE_KEEP);
+                (head_end, tail - head_end)
+            }
+        };
+        let note = format!(" …[{} bytes omitted]… ", omitted);
+        output.replace_range(head_end..tail, &note);
+        self.cut = Some((head_end..head_end + note.len(), omitted));
+    }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+fn ceil_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
 /// Maintains a bounded log of recent command executions.
 pub struct CommandLog {
     entries: Vec<CommandRecord>,
     max_len: usize,
+    /// Last line of the newest output, which may still grow too long
+    open_line: OpenLine,
+    /// Output being appended, without escape codes
+    scratch: String,
+    /// Output bytes as text, a read at a time
+    decoder: Utf8Decoder,
 }
 
 impl CommandLog {
@@ -35,6 +127,9 @@ impl CommandLog {
         Self {
             entries: Vec::with_capacity(max_len),
             max_len,
+            open_line: OpenLine::default(),
+            scratch: String::new(),
+            decoder: Utf8Decoder::default(),
         }
     }
 
@@ -50,18 +145,53 @@ impl CommandLog {
         self.entries.push(CommandRecord {
             command_line,
             output: String::new(),
+            exit_code: None,
+            started_at: Some(Instant::now()),
+            position: None,
         });
+        self.open_line = OpenLine::default();
+    }
+
+    /// Record where the most recent command sits in the terminal scrollback.
+    pub fn set_position(&mut self, position: ScrollbackPosition) {
+        if let Some(last) = self.entries.last_mut() {
+            last.position = Some(position);
+        }
+    }
+
+    /// Record the exit status of the most recent unfinished run of
+    /// `command_line`. Returns how long that run took, if it is known.
+    pub fn set_exit_code(&mut self, command_line: &str, exit_code: i32) -> Option<Duration> {
+        let command_line = command_line.trim();
+        let record = self
+            .entries
+            .iter_mut()
+            .rev()
+            .find(|r| r.exit_code.is_none() && r.command_line.trim() == command_line)?;
+        record.exit_code = Some(exit_code);
+        record.started_at.map(|started| started.elapsed())
     }
 
     /// Append output data to the most recent command.
-    /// Uses lossy UTF-8 conversion and strips ANSI escape codes.
+    /// Bytes go through the stream's [`Utf8Decoder`] (a character cut
+    /// across reads is kept whole, control characters dropped) and ANSI
+    /// escape codes are stripped. Lines longer than [`LONG_LINE_BYTES`]
+    /// keep only their head and tail.
     pub fn append_output(&mut self, bytes: &[u8]) {
         if let Some(last) = self.entries.last_mut() {
-            // Convert bytes to string, replacing invalid UTF-8 sequences
-            let text = String::from_utf8_lossy(bytes);
-            // Strip ANSI codes immediately so stored data is clean
-            let clean_text = strip_ansi_codes(&text);
-            last.output.push_str(&clean_text);
+            let text = self.decoder.decode_clean(bytes);
+            self.scratch.clear();
+            strip_ansi_codes_into(&text, &mut self.scratch);
+            for piece in self.scratch.split_inclusive('\n') {
+                last.output.push_str(piece);
+                let done = piece.ends_with('\n');
+                self.open_line.shorten(&mut last.output, done);
+                if done {
+                    self.open_line = OpenLine::at(last.output.len());
+                }
+            }
+            // Don't hold on to the room a huge chunk needed
+            self.scratch.shrink_to(LONG_LINE_BYTES);
         }
     }
 
@@ -71,6 +201,7 @@ impl CommandLog {
         if self.entries.len() >= self.max_len {
             self.entries.remove(0);
         }
+        self.open_line = OpenLine::at(record.output.rfind('\n').map_or(0, |i| i + 1));
         self.entries.push(record);
     }
 
@@ -99,6 +230,7 @@ impl CommandLog {
     /// Clear all entries.
     pub fn clear(&mut self) {
         self.entries.clear();
+        self.open_line = OpenLine::default();
     }
 }
 
@@ -108,10 +240,54 @@ impl Default for CommandLog {
     }
 }
 
+/// Append `text` to `result` with ANSI escape codes removed.
+/// Removes color codes, cursor movements, and other terminal control sequences.
+fn strip_ansi_codes_into(text: &str, result: &mut String) {
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' {
+            // ESC sequence started
+            if chars.peek() == Some(&'[') {
+                chars.next(); // consume '['
+                // Skip until we hit a letter (CSI sequence terminator)
+                while let Some(&next_ch) = chars.peek() {
+                    chars.next();
+                    if next_ch.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            } else if chars.peek() == Some(&']') {
+                // OSC sequence (e.g., OSC 7 for directory)
+                chars.next(); // consume ']'
+                // Skip until we hit BEL (\x07) or ST (\x1b\\)
+                while let Some(&next_ch) = chars.peek() {
+                    chars.next();
+                    if next_ch == '\x07' {
+                        break;
+                    }
+                    if next_ch == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next(); // consume '\\'
+                        break;
+                    }
+                }
+            }
+        } else {
+            result.push(ch);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn strip_ansi_codes(text: &str) -> String {
+        let mut result = String::new();
+        strip_ansi_codes_into(text, &mut result);
+        result
+    }
+
     #[test]
     fn test_strip_ansi_codes() {
         // Test basic color codes
@@ -170,6 +346,19 @@ mod tests {
         assert_eq!(log.entries()[0].output, "hello\nworld\n");
     }
 
+    #[test]
+    fn test_append_output_survives_binary_and_split_characters() {
+        let mut log = CommandLog::new(10);
+        log.start_new_command("cat archive.tar".to_string());
+        let accent = "é".as_bytes();
+        log.append_output(&[b'c', b'a', b'f', accent[0]]);
+        log.append_output(&[accent[1], b'\n']);
+        log.append_output(b"\x00\x00ustar\x00\xff\xfe\x07\x1b[1m!\x1b[0m\n");
+        log.append_output("done ✓\n".as_bytes());
+
+        assert_eq!(log.entries()[0].output, "café\nustar\u{fffd}\u{fffd}!\ndone ✓\n");
+    }
+
     #[test]
     fn test_bounded_log() {
         let mut log = CommandLog::new(3);
@@ -212,6 +401,50 @@ mod tests {
         assert_eq!(recent.len(), 2);
     }
 
+    #[test]
+    fn test_set_exit_code_targets_latest_unfinished_run() {
+        let mut log = CommandLog::new(10);
+
+        log.start_new_command("make".to_string());
+        log.set_exit_code("make", 2);
+        log.start_new_command("make".to_string());
+        log.start_new_command("ls".to_string());
+        log.set_exit_code("make", 0);
+        log.set_exit_code("unknown", 1);
+
+        assert_eq!(log.entries()[0].exit_code, Some(2));
+        assert_eq!(log.entries()[1].exit_code, Some(0));
+        assert_eq!(log.entries()[2].exit_code, None);
+        assert!(log.entries()[2].started_at.is_some());
+    }
+
+    #[test]
+    fn test_long_line_keeps_head_and_tail() {
+        let mut log = CommandLog::new(10);
+        log.start_new_command("curl api".to_string());
+        log.append_output(b"short\n");
+        let line: String = (0..500_000).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+        for chunk in format!("\x1b[32m{}\x1b[0m\nnext\n", line).as_bytes().chunks(4096) {
+            log.append_output(chunk);
+        }
+
+        let line = format!("{}\n", line);
+        let omitted = line.len() - 2 * LONG_LINE_KEEP;
+        let expected = format!(
+            "short\n{} …[{} bytes omitted]… {}next\n",
+            &line[..LONG_LINE_KEEP],
+            omitted,
+            &line[line.len() - LONG_LINE_KEEP..]
+        );
+        assert_eq!(log.entries()[0].output, expected);
+
+        // Lines under the limit are kept whole
+        log.start_new_command("cat".to_string());
+        let fits = "x".repeat(LONG_LINE_BYTES);
+        log.append_output(fits.as_bytes());
+        assert_eq!(log.entries()[1].output, fits);
+    }
+
     #[test]
     fn test_append_output_invalid_utf8() {
         let mut log = CommandLog::new(10);
@@ -242,44 +475,3 @@ mod tests {
         assert_eq!(log.entries()[1].output, "/home/user\n");
     }
 }
-
-/// Strip ANSI escape codes from text.
-/// Removes color codes, cursor movements, and other terminal control sequences.
-fn strip_ansi_codes(text: &str) -> String {
-    let mut result = String::with_capacity(text.len());
-    let mut chars = text.chars().peekable();
-
-    while let Some(ch) = chars.next() {
-        if ch == '\x1b' {
-            // ESC sequence started
-            if chars.peek() == Some(&'[') {
-                chars.next(); // consume '['
-                // Skip until we hit a letter (CSI sequence terminator)
-                while let Some(&next_ch) = chars.peek() {
-                    chars.next();
-                    if next_ch.is_ascii_alphabetic() {
-                        break;
-                    }
-                }
-            } else if chars.peek() == Some(&']') {
-                // OSC sequence (e.g., OSC 7 for directory)
-                chars.next(); // consume ']'
-                // Skip until we hit BEL (\x07) or ST (\x1b\\)
-                while let Some(&next_ch) = chars.peek() {
-                    chars.next();
-                    if next_ch == '\x07' {
-                        break;
-                    }
-                    if next_ch == '\x1b' && chars.peek() == Some(&'\\') {
-                        chars.next(); // consume '\\'
-                        break;
-                    }
-                }
-            }
-        } else {
-            result.push(ch);
-        }
-    }
-
-    result
-}
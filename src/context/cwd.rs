@@ -4,6 +4,8 @@
 //! of the shell session, providing context for AI command suggestions.
 
 use std::env;
+use std::fs;
+use std::path::Path;
 
 #[derive(Clone, Default, Debug)]
 pub struct CurrentDir {
@@ -39,6 +41,117 @@ impl CurrentDir {
         }
         false
     }
+
+    /// Branch checked out in the git repository containing the directory,
+    /// read from its `HEAD` file (no git process is run). A detached HEAD
+    /// gives the short commit id; None outside a repository.
+    pub fn git_branch(&self) -> Option<String> {
+        let head = Path::new(&self.path).ancestors().find_map(|dir| {
+            let dot_git = dir.join(".git");
+            if dot_git.is_dir() {
+                return Some(dot_git.join("HEAD"));
+            }
+            // Worktrees and submodules: `.git` is a file naming the git dir
+            let link = fs::read_to_string(&dot_git).ok()?;
+            let git_dir = Path::new(link.strip_prefix("gitdir:")?.trim());
+            Some(dir.join(git_dir).join("HEAD"))
+        })?;
+        let head = fs::read_to_string(head).ok()?;
+        let head = head.trim();
+        match head.strip_prefix("ref:") {
+            Some(reference) => {
+                let reference = reference.trim();
+                Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string())
+            }
+            None => Some(head.chars().take(7).collect()),
+        }
+    }
+}
+
+/// Longest OSC payload kept while scanning; longer sequences are skipped.
+const MAX_OSC_LEN: usize = 4096;
+
+/// Picks OSC payloads (`ESC ] payload`, ended by BEL or `ESC \`) out of
+/// raw PTY output: the OSC 7 working directory reports (`7;file://host/path`)
+/// and OSC 133 command marks that shells with integration print around
+/// every prompt. A sequence may be split across reads.
+#[derive(Debug, Default)]
+pub struct OscScanner {
+    state: ScanState,
+    payload: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    Osc,
+    /// ESC inside an OSC: either the `ESC \` terminator or a new sequence
+    OscEscape,
+}
+
+impl OscScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan the next chunk of output. Returns the payload of the last
+    /// complete OSC 7 sequence in it, if any.
+    pub fn feed(&mut self, bytes: &[u8]) -> Option<String> {
+        let mut found = None;
+        self.scan(bytes, |payload| {
+            if let Some(payload) = payload.strip_prefix(b"7;") {
+                found = Some(String::from_utf8_lossy(payload).into_owned());
+            }
+        });
+        found
+    }
+
+    /// Scan the next chunk of output, calling `found` with the payload of
+    /// every OSC sequence it completes, in order.
+    pub fn scan(&mut self, bytes: &[u8], mut found: impl FnMut(&[u8])) {
+        let mut rest = bytes;
+        loop {
+            if self.state == ScanState::Ground {
+                // Plain output: skip straight to the next escape
+                match rest.iter().position(|&b| b == 0x1b) {
+                    Some(offset) => rest = &rest[offset..],
+                    None => break,
+                }
+            }
+            let Some((&byte, tail)) = rest.split_first() else {
+                break;
+            };
+            rest = tail;
+            self.state = match (self.state, byte) {
+                (ScanState::Ground, 0x1b) => ScanState::Escape,
+                (ScanState::Ground, _) => ScanState::Ground,
+                (ScanState::Escape, b']') => {
+                    self.payload.clear();
+                    ScanState::Osc
+                }
+                (ScanState::Escape, 0x1b) => ScanState::Escape,
+                (ScanState::Escape, _) => ScanState::Ground,
+                (ScanState::Osc, 0x07) | (ScanState::OscEscape, b'\\') => {
+                    found(&self.payload);
+                    ScanState::Ground
+                }
+                (ScanState::Osc, 0x1b) => ScanState::OscEscape,
+                (ScanState::Osc, _) if self.payload.len() >= MAX_OSC_LEN => ScanState::Ground,
+                (ScanState::Osc, _) => {
+                    self.payload.push(byte);
+                    ScanState::Osc
+                }
+                (ScanState::OscEscape, b']') => {
+                    self.payload.clear();
+                    ScanState::Osc
+                }
+                (ScanState::OscEscape, 0x1b) => ScanState::Escape,
+                (ScanState::OscEscape, _) => ScanState::Ground,
+            };
+        }
+    }
 }
 
 /// Simple URL decoding for path strings.
@@ -49,11 +162,11 @@ fn urlencoding_decode(s: &str) -> Result<String, ()> {
     while let Some(c) = chars.next() {
         if c == '%' {
             let hex: String = chars.by_ref().take(2).collect();
-            if hex.len() == 2 {
-                if let Ok(byte) = u8::from_str_radix(&hex, 16) {
-                    result.push(byte as char);
-                    continue;
-                }
+            if hex.len() == 2
+                && let Ok(byte) = u8::from_str_radix(&hex, 16)
+            {
+                result.push(byte as char);
+                continue;
             }
             return Err(());
         } else {
@@ -62,3 +175,34 @@ fn urlencoding_decode(s: &str) -> Result<String, ()> {
     }
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_branch_from_head() -> std::io::Result<()> {
+        let root = env::temp_dir().join(format!("rusty-term-git-head-{}", std::process::id()));
+        let nested = root.join("src").join("app");
+        fs::create_dir_all(root.join(".git"))?;
+        fs::create_dir_all(&nested)?;
+
+        let dir = CurrentDir { path: nested.to_string_lossy().into_owned() };
+        fs::write(root.join(".git").join("HEAD"), "ref: refs/heads/feature/chips\n")?;
+        assert_eq!(dir.git_branch().as_deref(), Some("feature/chips"));
+        fs::write(root.join(".git").join("HEAD"), "3f2a9c41d0e4b6a7c8d9e0f1a2b3c4d5e6f7a8b9\n")?;
+        assert_eq!(dir.git_branch().as_deref(), Some("3f2a9c4"));
+
+        // A worktree's `.git` file points at its git dir
+        let worktree = root.join("worktree");
+        fs::create_dir_all(root.join("wt-git"))?;
+        fs::create_dir_all(&worktree)?;
+        fs::write(worktree.join(".git"), "gitdir: ../wt-git\n")?;
+        fs::write(root.join("wt-git").join("HEAD"), "ref: refs/heads/other\n")?;
+        let dir = CurrentDir { path: worktree.to_string_lossy().into_owned() };
+        assert_eq!(dir.git_branch().as_deref(), Some("other"));
+
+        fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+}
@@ -36,8 +36,12 @@ impl Environment {
 
     /// Get a filtered list of environment variables that are relevant for AI context.
     /// This avoids sending sensitive or irrelevant data to the AI.
+    ///
+    /// Sorted by name, so the same environment always makes the same prompt
+    /// (providers only reuse a cached prompt prefix that matches exactly).
     pub fn filtered_vars(&self) -> Vec<(String, String)> {
-        self.vars
+        let mut vars: Vec<(String, String)> = self
+            .vars
             .iter()
             .filter(|(key, _)| {
                 RELEVANT_ENV_VARS.contains(&key.as_str())
@@ -54,7 +58,9 @@ impl Environment {
                 };
                 (k.clone(), truncated)
             })
-            .collect()
+            .collect();
+        vars.sort();
+        vars
     }
 
     /// Get a specific environment variable.
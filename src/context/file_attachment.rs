@@ -0,0 +1,169 @@
+//! Files attached to the next message, dropped on the assistant pane (see
+//! [`crate::ui::file_drop`]).
+//!
+//! A file goes along whole up to [`FILE_BUDGET`] bytes. A longer one keeps
+//! its start and its end, where logs and tracebacks say the most, with a
+//! line in between saying how much was left out; only those two pieces are
+//! read, however big the file is. Binary files (NUL bytes, or not UTF-8)
+//! are refused: the model can't make anything of them.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::metrics::format_bytes;
+
+/// Bytes of a file a message carries at most.
+pub const FILE_BUDGET: usize = 16 * 1024;
+
+/// A file's text as it goes with a message.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileAttachment {
+    /// Path as it was dropped
+    pub path: String,
+    /// Size of the whole file, in bytes
+    pub size: u64,
+    /// The text, or its start and end with a note on what is left out
+    pub content: String,
+    /// Whether the middle of the file is left out
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub truncated: bool,
+}
+
+impl FileAttachment {
+    /// The file name, for the chip.
+    pub fn name(&self) -> &str {
+        Path::new(&self.path).file_name().and_then(|name| name.to_str()).unwrap_or(&self.path)
+    }
+}
+
+/// Why a file can't be attached.
+#[derive(Debug)]
+pub enum AttachError {
+    /// It holds NUL bytes or isn't UTF-8
+    Binary,
+    Io(io::Error),
+}
+
+impl fmt::Display for AttachError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttachError::Binary => write!(f, "is not a text file"),
+            AttachError::Io(error) => write!(f, "can't be read: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for AttachError {}
+
+impl From<io::Error> for AttachError {
+    fn from(error: io::Error) -> Self {
+        AttachError::Io(error)
+    }
+}
+
+/// Read `path` for attaching, keeping at most `budget` bytes of it: half
+/// from its start and half from its end when it is longer.
+pub fn read_attachment(path: &Path, budget: usize) -> Result<FileAttachment, AttachError> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut head = Vec::new();
+    (&mut file).take(budget as u64).read_to_end(&mut head)?;
+
+    let (content, truncated) = if size <= budget as u64 {
+        (text(&head, false, false)?.to_string(), false)
+    } else {
+        let tail_len = budget - budget / 2;
+        head.truncate(budget / 2);
+        file.seek(SeekFrom::End(-(tail_len as i64)))?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail)?;
+
+        let head = text(&head, false, true)?;
+        let tail = text(&tail, true, false)?;
+        let left_out = size.saturating_sub((head.len() + tail.len()) as u64);
+        (format!("{}\n[… {} left out …]\n{}", head, format_bytes(left_out), tail), true)
+    };
+    Ok(FileAttachment { path: path.display().to_string(), size, content, truncated })
+}
+
+/// `bytes` as text. A piece cut from the start (`cut_start`) or the end
+/// (`cut_end`) of a file may start or end in the middle of a character,
+/// which is dropped.
+fn text(bytes: &[u8], cut_start: bool, cut_end: bool) -> Result<&str, AttachError> {
+    if bytes.contains(&0) {
+        return Err(AttachError::Binary);
+    }
+    let start = if cut_start { bytes.iter().take(3).take_while(|&&b| (b & 0xC0) == 0x80).count() } else { 0 };
+    let bytes = &bytes[start..];
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        // Only the last character is incomplete
+        Err(error) if cut_end && error.error_len().is_none() => {
+            std::str::from_utf8(&bytes[..error.valid_up_to()]).map_err(|_| AttachError::Binary)
+        }
+        Err(_) => Err(AttachError::Binary),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A file of its own under the temp dir holding `bytes`.
+    fn fixture(name: &str, bytes: &[u8]) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-attach-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(name);
+        fs::write(&path, bytes)?;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_small_files_go_whole() -> Result<(), Box<dyn std::error::Error>> {
+        let text = "line 1\nérreur: ünïcode\n";
+        let path = fixture("error log.txt", text.as_bytes())?;
+        let file = read_attachment(&path, FILE_BUDGET)?;
+        assert_eq!(file.content, text);
+        assert_eq!(file.size, text.len() as u64);
+        assert!(!file.truncated);
+        assert_eq!(file.name(), "error log.txt");
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_files_keep_their_start_and_end() -> Result<(), Box<dyn std::error::Error>> {
+        let text: String = (0..10_000).map(|i| format!("line {} ✓\n", i)).collect();
+        let path = fixture("large.log", text.as_bytes())?;
+        let file = read_attachment(&path, 1000)?;
+
+        assert!(file.truncated);
+        assert_eq!(file.size, text.len() as u64);
+        assert!(file.content.starts_with("line 0 ✓\nline 1 ✓\n"));
+        assert!(file.content.ends_with("line 9998 ✓\nline 9999 ✓\n"));
+        assert!(file.content.contains("KiB left out …]\n"));
+        // The pieces cut mid-character lose that character only
+        assert!(file.content.len() <= 1000 + 40);
+        assert!(file.content.len() >= 1000 - 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_files_are_refused() -> Result<(), Box<dyn std::error::Error>> {
+        let nul = fixture("image.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR")?;
+        assert!(matches!(read_attachment(&nul, FILE_BUDGET), Err(AttachError::Binary)));
+        let latin1 = fixture("latin1.txt", b"caf\xe9 cr\xe8me")?;
+        assert!(matches!(read_attachment(&latin1, FILE_BUDGET), Err(AttachError::Binary)));
+        // Far into a large file counts too
+        let mut bytes = vec![b'a'; 5000];
+        bytes.push(0);
+        let late = fixture("late.bin", &bytes)?;
+        assert!(matches!(read_attachment(&late, 1000), Err(AttachError::Binary)));
+        Ok(())
+    }
+}
@@ -1,14 +1,35 @@
 //! Command history tracking and storage.
 //!
-//! This module maintains a history of executed commands, allowing the AI
-//! to provide suggestions based on recent command patterns and context.
+//! This module keeps a large history of executed commands (up to
+//! [`MAX_HISTORY_SIZE`]), with when each ran and, once the shell reports it,
+//! its exit status. The AI prompt only gets a small slice of it (see
+//! [`History::recent_unique`]); duplicate detection indexes all of it.
+
+use std::time::Instant;
 
 /// Maximum number of commands to keep in history.
-const MAX_HISTORY_SIZE: usize = 1000;
+pub const MAX_HISTORY_SIZE: usize = 5000;
+
+/// One command in the history.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub command: String,
+    /// When the command was added
+    pub ran_at: Instant,
+    /// Exit status, once the shell reported it
+    pub exit_code: Option<i32>,
+}
+
+impl HistoryEntry {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self { command: command.into(), ran_at: Instant::now(), exit_code: None }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct History {
-    commands: Vec<String>,
+    entries: Vec<HistoryEntry>,
+    max_len: usize,
 }
 
 impl Default for History {
@@ -19,46 +40,171 @@ impl Default for History {
 
 impl History {
     pub fn new() -> Self {
+        Self::with_capacity(MAX_HISTORY_SIZE)
+    }
+
+    /// A history keeping at most `max_len` commands.
+    pub fn with_capacity(max_len: usize) -> Self {
         Self {
-            commands: Vec::new(),
+            entries: Vec::new(),
+            max_len: max_len.max(1),
         }
     }
 
     /// Add a command to history.
-    /// Skips empty commands and duplicates of the last command.
+    /// Skips empty commands and duplicates of the last command; a command
+    /// repeated later, with others in between, is kept again.
     pub fn push(&mut self, cmd: String) {
         let trimmed = cmd.trim();
         if trimmed.is_empty() {
             return;
         }
-        
-        // Skip if it's a duplicate of the last command
-        if self.commands.last().map(|s| s.as_str()) == Some(trimmed) {
+
+        // A repeat of the last command only refreshes it
+        if let Some(last) = self.entries.last_mut()
+            && last.command == trimmed
+        {
+            last.ran_at = Instant::now();
+            last.exit_code = None;
             return;
         }
-        
-        self.commands.push(trimmed.to_string());
-        
+
+        self.entries.push(HistoryEntry::new(trimmed));
+
         // Trim history if it exceeds max size
-        if self.commands.len() > MAX_HISTORY_SIZE {
-            self.commands.remove(0);
+        if self.entries.len() > self.max_len {
+            let excess = self.entries.len() - self.max_len;
+            self.entries.drain(..excess);
         }
     }
 
-    /// Get the most recent n commands.
-    pub fn recent(&self, n: usize) -> Vec<String> {
-        let len = self.commands.len();
-        let start = len.saturating_sub(n);
-        self.commands[start..].to_vec()
+    /// Note the exit status of the latest run of `command` that has none yet.
+    pub fn set_exit_code(&mut self, command: &str, exit_code: i32) {
+        let command = command.trim();
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.exit_code.is_none() && entry.command == command)
+        {
+            entry.exit_code = Some(exit_code);
+        }
+    }
+
+    /// The most recent n commands, oldest first.
+    pub fn recent(&self, n: usize) -> &[HistoryEntry] {
+        let start = self.entries.len().saturating_sub(n);
+        &self.entries[start..]
+    }
+
+    /// Every command, newest first.
+    pub fn iter_rev(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter().rev()
+    }
+
+    /// Commands containing `needle`, newest first. Matching ignores case;
+    /// an empty needle matches everything.
+    pub fn search(&self, needle: &str) -> Vec<&HistoryEntry> {
+        let needle = needle.to_lowercase();
+        self.iter_rev().filter(|entry| entry.command.to_lowercase().contains(&needle)).collect()
+    }
+
+    /// Get the most recent n distinct commands, oldest first. A command run
+    /// more than once is placed at its latest run.
+    pub fn recent_unique(&self, n: usize) -> Vec<String> {
+        let mut unique: Vec<String> = Vec::new();
+        for entry in self.iter_rev() {
+            if unique.len() == n {
+                break;
+            }
+            if !unique.contains(&entry.command) {
+                unique.push(entry.command.clone());
+            }
+        }
+        unique.reverse();
+        unique
     }
 
     /// Get total number of commands in history.
     pub fn len(&self) -> usize {
-        self.commands.len()
+        self.entries.len()
     }
 
     /// Check if history is empty.
     pub fn is_empty(&self) -> bool {
-        self.commands.is_empty()
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(commands: &[&str]) -> History {
+        let mut history = History::new();
+        for command in commands {
+            history.push(command.to_string());
+        }
+        history
+    }
+
+    fn commands<'a>(entries: impl IntoIterator<Item = &'a HistoryEntry>) -> Vec<&'a str> {
+        entries.into_iter().map(|entry| entry.command.as_str()).collect()
+    }
+
+    #[test]
+    fn test_capped_to_the_newest_commands() {
+        let mut history = History::with_capacity(3);
+        for i in 0..5 {
+            history.push(format!("echo {}", i));
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(commands(history.recent(10)), ["echo 2", "echo 3", "echo 4"]);
+        assert_eq!(commands(history.recent(1)), ["echo 4"]);
+        assert_eq!(commands(history.iter_rev()), ["echo 4", "echo 3", "echo 2"]);
+    }
+
+    #[test]
+    fn test_only_consecutive_repeats_are_merged() {
+        let history = history(&["make", " make ", "make", "ls", "make", "", "  "]);
+        assert_eq!(commands(history.recent(10)), ["make", "ls", "make"]);
+        assert_eq!(history.recent_unique(10), ["ls", "make"]);
+    }
+
+    #[test]
+    fn test_exit_code_goes_to_the_latest_unannotated_run() {
+        let mut history = history(&["make", "ls", "make"]);
+        history.set_exit_code("make", 2);
+        history.set_exit_code("make ", 0);
+        history.set_exit_code("unknown", 1);
+        let codes: Vec<_> = history.recent(3).iter().map(|entry| entry.exit_code).collect();
+        assert_eq!(codes, [Some(0), None, Some(2)]);
+
+        // Running it again right away starts a fresh, unannotated entry
+        history.push("make".to_string());
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.recent(1)[0].exit_code, None);
+    }
+
+    #[test]
+    fn test_search_is_newest_first_and_handles_multibyte_text() {
+        let mut history = History::new();
+        for i in 0..MAX_HISTORY_SIZE {
+            history.push(format!("cargo test case_{}", i));
+        }
+        history.push("echo 'héllo wörld' > ünïcode.txt".to_string());
+        history.push("grep 日本語 notes.md".to_string());
+        history.push("git commit -m 'fix'".to_string());
+
+        assert_eq!(history.len(), MAX_HISTORY_SIZE);
+        assert_eq!(commands(history.search("日本")), ["grep 日本語 notes.md"]);
+        assert_eq!(commands(history.search("WÖRLD")), ["echo 'héllo wörld' > ünïcode.txt"]);
+        assert!(history.search("zzz").is_empty());
+
+        let cargo = history.search("case_4");
+        assert_eq!(cargo.first().map(|entry| entry.command.as_str()), Some("cargo test case_4999"));
+        // The oldest three fell off the end
+        assert!(history.search("case_0").iter().all(|entry| entry.command != "cargo test case_0"));
+        assert_eq!(history.search("").len(), MAX_HISTORY_SIZE);
     }
 }
@@ -7,14 +7,34 @@
 mod command_log;
 mod cwd;
 mod env;
+mod file_attachment;
 mod history;
+mod prior_runs;
+pub mod project;
 
 use serde::{Deserialize, Serialize};
 
-pub use command_log::{CommandLog, CommandRecord};
-pub use cwd::CurrentDir;
+use crate::ui::line_numbers::number_output;
+use crate::utils::utf8::sanitize;
+
+pub use command_log::{CommandLog, CommandRecord, ScrollbackPosition};
+pub use cwd::{CurrentDir, OscScanner};
 pub use env::Environment;
-pub use history::History;
+pub use file_attachment::{read_attachment, AttachError, FileAttachment, FILE_BUDGET};
+pub use history::{History, HistoryEntry, MAX_HISTORY_SIZE};
+pub use prior_runs::{normalize_command, PriorRun, PriorRuns};
+pub use project::{ProjectContext, ProjectStamp};
+
+/// Distinct recent commands an AI message carries by default.
+pub const DEFAULT_PROMPT_HISTORY: usize = 20;
+
+/// Bytes of each command's output a message carries (the tail). Longer
+/// output can go whole in parts, see `ai::chunked`.
+pub const OUTPUT_BUDGET: usize = 2048;
+
+/// Bytes of the terminal screen a question about it carries (the bottom).
+/// A screen is short of it unless the window is very large.
+pub const SCREEN_BUDGET: usize = 8 * 1024;
 
 /// Manages all context information for AI suggestions.
 #[derive(Debug)]
@@ -22,7 +42,15 @@ pub struct ContextManager {
     pub env: Environment,
     pub cwd: CurrentDir,
     pub history: History,
+    /// Distinct recent commands each AI message carries
+    prompt_history: usize,
     recent_output: std::collections::VecDeque<String>,
+    /// Prefix command output with terminal line numbers (gutter is shown)
+    number_command_output: bool,
+    /// Attach an excerpt of the directory's README and manifests
+    project_context: bool,
+    /// The excerpt last read, for the directory and files in the stamp
+    project: Option<(ProjectStamp, Option<ProjectContext>)>,
 }
 
 impl Default for ContextManager {
@@ -37,7 +65,11 @@ impl ContextManager {
             env: Environment::capture(),
             cwd: CurrentDir::capture().unwrap_or_default(),
             history: History::new(),
+            prompt_history: DEFAULT_PROMPT_HISTORY,
             recent_output: std::collections::VecDeque::new(),
+            number_command_output: false,
+            project_context: false,
+            project: None,
         }
     }
 
@@ -46,22 +78,38 @@ impl ContextManager {
         ContextSnapshot {
             cwd: self.cwd.path.clone(),
             env_vars: self.env.filtered_vars(),
-            recent_history: self.history.recent(20),
+            recent_history: self.history.recent_unique(self.prompt_history),
             // Only take last 6 lines for AI prompt
             recent_output: self.recent_output.iter().rev().take(6).rev().cloned().collect(),
             recent_commands: Vec::new(), // Filled by caller with ShellManager data
+            project: self.project(),
+            files: Vec::new(),
+            screen: None,
         }
     }
 
+    /// How many distinct recent commands an AI message carries. The
+    /// history itself keeps many more.
+    pub fn set_prompt_history(&mut self, commands: usize) {
+        self.prompt_history = commands;
+    }
+
+    /// Number command output like the terminal's line-number gutter, so the
+    /// AI can refer to the same line numbers the user sees.
+    pub fn set_number_command_output(&mut self, enabled: bool) {
+        self.number_command_output = enabled;
+    }
+
     /// Create a snapshot with command records from ShellManager.
-    /// Truncates command outputs to 2KB for AI prompt efficiency.
+    /// Truncates command outputs to `OUTPUT_BUDGET` for AI prompt efficiency.
     pub fn snapshot_with_commands(&self, command_records: Vec<CommandRecord>) -> ContextSnapshot {
         // Truncate command outputs to reasonable size
         let truncated_commands: Vec<CommandRecord> = command_records
             .into_iter()
             .map(|mut record| {
-                if record.output.len() > 2048 {
-                    record.output = truncate_output(&record.output, 2048);
+                record.output = self.full_output(&record);
+                if record.output.len() > OUTPUT_BUDGET {
+                    record.output = truncate_output(&record.output, OUTPUT_BUDGET);
                 }
                 record
             })
@@ -70,18 +118,73 @@ impl ContextManager {
         ContextSnapshot {
             cwd: self.cwd.path.clone(),
             env_vars: self.env.filtered_vars(),
-            recent_history: self.history.recent(20),
+            recent_history: self.history.recent_unique(self.prompt_history),
             // Only take last 6 lines for AI prompt
             recent_output: self.recent_output.iter().rev().take(6).rev().cloned().collect(),
             recent_commands: truncated_commands,
+            project: self.project(),
+            files: Vec::new(), // Filled by caller with the files dropped
+            screen: None,
+        }
+    }
+
+    /// Attach an excerpt of the README and manifests of the directory (see
+    /// [`project`]).
+    pub fn set_project_context(&mut self, enabled: bool) {
+        self.project_context = enabled;
+        if !enabled {
+            self.project = None;
         }
     }
 
+    /// The directory whose project files to look at, and what was last read
+    /// there (None = nothing to look at).
+    pub fn project_refresh(&self) -> Option<(std::path::PathBuf, Option<ProjectStamp>)> {
+        let dir = std::path::PathBuf::from(&self.cwd.path);
+        if !self.project_context || !dir.is_absolute() {
+            return None;
+        }
+        let known = self.project.as_ref().map(|(stamp, _)| stamp).filter(|stamp| stamp.dir() == dir).cloned();
+        Some((dir, known))
+    }
+
+    /// Keep `project`, read as `stamp` says, unless the shell has moved on
+    /// to another directory since. Returns whether it was kept.
+    pub fn set_project(&mut self, stamp: ProjectStamp, project: Option<ProjectContext>) -> bool {
+        if !self.project_context || stamp.dir() != std::path::Path::new(&self.cwd.path) {
+            return false;
+        }
+        self.project = Some((stamp, project));
+        true
+    }
+
+    /// The project of the current directory, if it was read.
+    fn project(&self) -> Option<ProjectContext> {
+        match &self.project {
+            Some((stamp, project)) if stamp.dir() == std::path::Path::new(&self.cwd.path) => project.clone(),
+            _ => None,
+        }
+    }
+
+    /// The whole output of `record` as the AI gets it (numbered like the
+    /// terminal's gutter if that is on), before any truncation.
+    pub fn full_output(&self, record: &CommandRecord) -> String {
+        match record.position {
+            Some(position) if self.number_command_output => {
+                number_output(&record.output, position.line, position.columns)
+            }
+            _ => record.output.clone(),
+        }
+    }
+
+    /// Keep the non-blank lines of `chunk` as recent output, without
+    /// control characters (see [`sanitize`]).
     pub fn push_output(&mut self, chunk: String) {
         if chunk.trim().is_empty() {
             return;
         }
         const MAX_OUTPUT_LINES: usize = 20;
+        let chunk = sanitize(&chunk);
         for line in chunk.lines() {
             if line.trim().is_empty() {
                 continue;
@@ -108,6 +211,11 @@ impl ContextManager {
         self.history.push(command);
     }
 
+    /// Index commands already run (history plus `command_records`) for duplicate detection.
+    pub fn prior_runs(&self, command_records: &[CommandRecord]) -> PriorRuns {
+        PriorRuns::build(self.history.recent(MAX_HISTORY_SIZE), command_records)
+    }
+
     /// Refresh environment variables from the current process.
     pub fn refresh_env(&mut self) {
         self.env = Environment::capture();
@@ -115,23 +223,69 @@ impl ContextManager {
 }
 
 /// A snapshot of context information for AI prompt building.
+///
+/// The same shell state always serializes to the same bytes: fields go in
+/// declaration order and every list is in a fixed order, so prompts keep
+/// hitting the provider's prompt cache and debug logs diff cleanly.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ContextSnapshot {
     pub cwd: String,
+    /// Relevant environment variables, sorted by name
     #[serde(rename = "env", skip_serializing_if = "Vec::is_empty", default)]
     pub env_vars: Vec<(String, String)>,
+    /// Distinct recent commands, oldest first (see [`History::recent_unique`])
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub recent_history: Vec<String>,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub recent_output: Vec<String>,
-    /// Recent commands with their outputs (command_line, output)
+    /// Recent commands with their outputs (command_line, output), in the
+    /// order they ran
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub recent_commands: Vec<CommandRecord>,
+    /// README and manifest excerpt of the directory's project
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub project: Option<ProjectContext>,
+    /// Files the user attached to this message, in the order they were dropped
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub files: Vec<FileAttachment>,
+    /// Text on the terminal screen, or the part of the scrollback in view,
+    /// when the question is about it (command mode `d`)
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub screen: Option<String>,
+}
+
+impl ContextSnapshot {
+    /// A snapshot with nothing attached, for questions asked without shell context.
+    pub fn empty() -> Self {
+        Self {
+            cwd: String::new(),
+            env_vars: Vec::new(),
+            recent_history: Vec::new(),
+            recent_output: Vec::new(),
+            recent_commands: Vec::new(),
+            project: None,
+            files: Vec::new(),
+            screen: None,
+        }
+    }
+
+    /// True when no shell state (directory, environment, history, output,
+    /// screen) and no file is attached.
+    pub fn is_context_free(&self) -> bool {
+        self.cwd.is_empty()
+            && self.env_vars.is_empty()
+            && self.recent_history.is_empty()
+            && self.recent_output.is_empty()
+            && self.recent_commands.is_empty()
+            && self.project.is_none()
+            && self.files.is_empty()
+            && self.screen.is_none()
+    }
 }
 
 /// Truncate output to a maximum size, keeping the last N bytes.
 /// Preserves UTF-8 boundaries and adds ellipsis if truncated.
-fn truncate_output(output: &str, max_bytes: usize) -> String {
+pub fn truncate_output(output: &str, max_bytes: usize) -> String {
     if output.len() <= max_bytes {
         return output.to_string();
     }
@@ -149,3 +303,47 @@ fn truncate_output(output: &str, max_bytes: usize) -> String {
 
     format!("...[truncated]\n{}", truncated)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A context manager with `vars` as its environment and a fixed
+    /// directory, history and output.
+    fn manager(vars: &[(&str, &str)]) -> ContextManager {
+        let mut ctx = ContextManager::new();
+        ctx.env = Environment { vars: vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect() };
+        ctx.update_cwd("/home/dev/app".to_string());
+        for cmd in ["git status", "cargo build", "git status", "cargo test"] {
+            ctx.add_to_history(cmd.to_string());
+        }
+        ctx.push_output("test result: ok\n".to_string());
+        ctx
+    }
+
+    #[test]
+    fn test_snapshots_of_the_same_state_serialize_identically() -> Result<(), Box<dyn std::error::Error>> {
+        let vars = [("SHELL", "/bin/zsh"), ("SECRET_TOKEN", "x"), ("HOME", "/home/dev"), ("CARGO_HOME", "/c")];
+        let mut reversed = vars;
+        reversed.reverse();
+        let records = || vec![CommandRecord::new("cargo build".to_string(), "ok".to_string())];
+
+        let first = serde_json::to_string(&manager(&vars).snapshot_with_commands(records()))?;
+        let second = serde_json::to_string(&manager(&reversed).snapshot_with_commands(records()))?;
+        assert_eq!(first, second);
+
+        let snapshot = manager(&reversed).snapshot();
+        let names: Vec<_> = snapshot.env_vars.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["CARGO_HOME", "HOME", "SHELL"]);
+        assert_eq!(snapshot.recent_history, ["cargo build", "git status", "cargo test"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prompt_gets_a_slice_of_the_history() {
+        let mut ctx = manager(&[]);
+        ctx.set_prompt_history(2);
+        assert_eq!(ctx.snapshot().recent_history, ["git status", "cargo test"]);
+        assert_eq!(ctx.history.len(), 4);
+    }
+}
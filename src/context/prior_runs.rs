@@ -0,0 +1,193 @@
+//! Detection of suggested commands that were already run.
+//!
+//! The AI sometimes re-suggests a command the user ran minutes ago, sometimes
+//! the exact one that failed. [`PriorRuns`] indexes recently-run commands by
+//! their normalized text so a suggestion can be checked with one hash lookup.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::{CommandRecord, HistoryEntry};
+
+/// Maximum number of distinct commands indexed.
+const MAX_TRACKED: usize = 500;
+
+/// Normalize a command line for duplicate detection.
+///
+/// Runs of whitespace collapse to one space, and leading/trailing whitespace
+/// and trailing `;` separators are dropped.
+pub fn normalize_command(command: &str) -> String {
+    let collapsed = command.split_whitespace().collect::<Vec<_>>().join(" ");
+    collapsed.trim_end_matches([';', ' ']).to_string()
+}
+
+/// What is known about an earlier run of a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorRun {
+    /// When the command was started (None if unknown)
+    pub ran_at: Option<Instant>,
+    /// Exit status, when shell integration reported it
+    pub exit_code: Option<i32>,
+}
+
+impl PriorRun {
+    /// Whether the earlier run is known to have failed.
+    pub fn failed(&self) -> bool {
+        self.exit_code.is_some_and(|code| code != 0)
+    }
+
+    /// Card annotation, e.g. "↺ ran 5m ago — exit 0" or "⚠ ran 5m ago and failed (exit 127)".
+    pub fn annotation(&self, now: Instant) -> String {
+        let when = match self.ran_at {
+            Some(at) => format!("ran {} ago", format_elapsed(now.saturating_duration_since(at))),
+            None => "ran earlier".to_string(),
+        };
+        match self.exit_code {
+            Some(code) if code != 0 => format!("⚠ {} and failed (exit {})", when, code),
+            Some(code) => format!("↺ {} — exit {}", when, code),
+            None => format!("↺ {}", when),
+        }
+    }
+}
+
+/// Compact elapsed time: "42s", "5m", "3h", "2d".
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86_400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86_400),
+    }
+}
+
+/// Index of recently-run commands, keyed by normalized command line.
+#[derive(Debug, Clone, Default)]
+pub struct PriorRuns {
+    runs: HashMap<String, PriorRun>,
+}
+
+impl PriorRuns {
+    /// Build the index from shell history and in-session command records.
+    ///
+    /// Both are ordered oldest first. Records carry the start time and the
+    /// output's exit code, so they override history entries for the same
+    /// command; for repeated commands the most recent run wins.
+    pub fn build(history: &[HistoryEntry], records: &[CommandRecord]) -> Self {
+        let mut runs = HashMap::new();
+        let history_start = history.len().saturating_sub(MAX_TRACKED);
+        for entry in &history[history_start..] {
+            runs.insert(
+                normalize_command(&entry.command),
+                PriorRun { ran_at: Some(entry.ran_at), exit_code: entry.exit_code },
+            );
+        }
+        let records_start = records.len().saturating_sub(MAX_TRACKED);
+        for record in &records[records_start..] {
+            runs.insert(
+                normalize_command(&record.command_line),
+                PriorRun {
+                    ran_at: record.started_at,
+                    exit_code: record.exit_code,
+                },
+            );
+        }
+        runs.remove("");
+        Self { runs }
+    }
+
+    /// Look up an earlier run of `command`.
+    pub fn lookup(&self, command: &str) -> Option<PriorRun> {
+        self.runs.get(&normalize_command(command)).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(command: &str, exit_code: Option<i32>, ran_at: Instant) -> CommandRecord {
+        let mut record = CommandRecord::new(command.to_string(), String::new());
+        record.exit_code = exit_code;
+        record.started_at = Some(ran_at);
+        record
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert_eq!(normalize_command("  ls   -la  "), "ls -la");
+        assert_eq!(normalize_command("git\tstatus"), "git status");
+        assert_eq!(normalize_command("echo a\n"), "echo a");
+    }
+
+    #[test]
+    fn test_normalize_trailing_semicolons() {
+        assert_eq!(normalize_command("make;"), "make");
+        assert_eq!(normalize_command("make ; ;"), "make");
+        assert_eq!(normalize_command("cd src; ls;"), "cd src; ls");
+        assert_eq!(normalize_command(";"), "");
+        assert_eq!(normalize_command(""), "");
+    }
+
+    #[test]
+    fn test_lookup_matches_near_exact_commands() {
+        let now = Instant::now();
+        let runs = PriorRuns::build(&[], &[record("cargo  build;", Some(0), now)]);
+        assert!(runs.lookup("cargo build").is_some());
+        assert!(runs.lookup(" cargo build ").is_some());
+        assert!(runs.lookup("cargo build --release").is_none());
+        assert!(runs.lookup("Cargo build").is_none());
+    }
+
+    #[test]
+    fn test_records_override_history_and_latest_run_wins() {
+        let now = Instant::now();
+        let history = vec![HistoryEntry::new("make")];
+        let records = vec![record("make", Some(2), now), record("make", Some(0), now)];
+        let runs = PriorRuns::build(&history, &records);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs.lookup("make").map(|r| r.exit_code), Some(Some(0)));
+    }
+
+    #[test]
+    fn test_empty_commands_are_not_indexed() {
+        let runs = PriorRuns::build(&[HistoryEntry::new(" ; ")], &[]);
+        assert!(runs.is_empty());
+    }
+
+    #[test]
+    fn test_annotation() {
+        let now = Instant::now();
+        let five_minutes = Duration::from_secs(300);
+        let Some(ran_at) = now.checked_sub(five_minutes) else {
+            return;
+        };
+
+        let ok = PriorRun { ran_at: Some(ran_at), exit_code: Some(0) };
+        assert!(!ok.failed());
+        assert_eq!(ok.annotation(now), "↺ ran 5m ago — exit 0");
+
+        let failed = PriorRun { ran_at: Some(ran_at), exit_code: Some(127) };
+        assert!(failed.failed());
+        assert_eq!(failed.annotation(now), "⚠ ran 5m ago and failed (exit 127)");
+
+        let unknown = PriorRun { ran_at: None, exit_code: None };
+        assert_eq!(unknown.annotation(now), "↺ ran earlier");
+    }
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(Duration::from_secs(42)), "42s");
+        assert_eq!(format_elapsed(Duration::from_secs(3599)), "59m");
+        assert_eq!(format_elapsed(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_elapsed(Duration::from_secs(3 * 86_400)), "3d");
+    }
+}
@@ -0,0 +1,505 @@
+//! What the project in the working directory is.
+//!
+//! When the shell is in a directory with a README or a manifest
+//! (`Cargo.toml`, `package.json`, `pyproject.toml`, `go.mod`, `Makefile`),
+//! a short excerpt of them goes with each message, so "what does this do"
+//! and "how do I build it" can be answered: the first lines of the README
+//! and the parts of the manifests that name the project and say how it is
+//! built.
+//!
+//! The files are read off the UI thread under [`READ_TIMEOUT`] (see
+//! [`load`]), and read again only when the directory or one of their
+//! modification times changes (see [`ProjectStamp`]). Only regular files of
+//! the directory itself are read; a symlink is followed only when it
+//! resolves to a file inside the directory.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+/// Lines of the README kept.
+pub const README_LINES: usize = 80;
+
+/// Bytes of excerpt a message carries at most.
+pub const PROJECT_BUDGET: usize = 4096;
+
+/// Budget for looking at and reading the files; a directory that takes
+/// longer (a stalled network mount) gets no excerpt.
+pub const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Bytes read of any one file.
+const MAX_FILE_BYTES: u64 = 64 * 1024;
+
+/// Dependencies and targets listed per manifest.
+const MAX_LISTED: usize = 20;
+
+/// Manifests recognized, in the order their excerpts go.
+const MANIFESTS: [&str; 5] = ["Cargo.toml", "package.json", "pyproject.toml", "go.mod", "Makefile"];
+
+/// The project the shell is in, as the AI gets it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectContext {
+    /// Name given by a manifest, or else the directory's
+    pub name: String,
+    /// Excerpts of the files, each under a `== file ==` header
+    pub excerpt: String,
+}
+
+/// The project files of a directory and when each was last modified: the
+/// excerpt is read again only when this changes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProjectStamp {
+    dir: PathBuf,
+    /// File names, manifests first, then the README
+    files: Vec<(String, Option<SystemTime>)>,
+}
+
+impl ProjectStamp {
+    /// Look at `dir` (a directory listing and a few `stat`s, no reads).
+    pub fn take(dir: &Path) -> Self {
+        let mut names: Vec<String> = MANIFESTS.iter().map(|name| name.to_string()).collect();
+        if let Some(readme) = find_readme(dir) {
+            names.push(readme);
+        }
+        let files = names
+            .into_iter()
+            .filter_map(|name| {
+                let path = inside(dir, &name)?;
+                let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+                Some((name, modified))
+            })
+            .collect();
+        Self { dir: dir.to_path_buf(), files }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// The README of `dir` (`README`, `README.md`, `readme.rst`, ...): the
+/// first in name order if there are several.
+fn find_readme(dir: &Path) -> Option<String> {
+    let mut readmes: Vec<String> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.get(..6).is_some_and(|prefix| prefix.eq_ignore_ascii_case("readme")))
+        .collect();
+    readmes.sort();
+    readmes.into_iter().next()
+}
+
+/// `dir/name` if it is a regular file, or a symlink to a regular file,
+/// that is inside `dir`.
+fn inside(dir: &Path, name: &str) -> Option<PathBuf> {
+    let path = dir.join(name);
+    let meta = fs::symlink_metadata(&path).ok()?;
+    if meta.is_file() {
+        return Some(path);
+    }
+    if !meta.file_type().is_symlink() {
+        return None;
+    }
+    let target = fs::canonicalize(&path).ok()?;
+    let root = fs::canonicalize(dir).ok()?;
+    (target.starts_with(&root) && target.is_file()).then_some(target)
+}
+
+/// At most [`MAX_FILE_BYTES`] of `dir/name`, if it is a file inside `dir`.
+fn read_file(dir: &Path, name: &str) -> Option<String> {
+    let path = inside(dir, name)?;
+    let mut bytes = Vec::new();
+    File::open(path).ok()?.take(MAX_FILE_BYTES).read_to_end(&mut bytes).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Read the excerpt of the files in `stamp` (blocking). None if the
+/// directory has none of them, or none could be read.
+pub fn read_project(stamp: &ProjectStamp) -> Option<ProjectContext> {
+    let mut name = None;
+    let mut sections = Vec::new();
+    for (file, _) in &stamp.files {
+        let Some(text) = read_file(&stamp.dir, file) else {
+            continue;
+        };
+        let (excerpt, named) = match file.as_str() {
+            "Cargo.toml" => cargo_excerpt(&text),
+            "package.json" => package_json_excerpt(&text),
+            "pyproject.toml" => pyproject_excerpt(&text),
+            "go.mod" => go_mod_excerpt(&text),
+            "Makefile" => (makefile_excerpt(&text), None),
+            _ => (text.lines().take(README_LINES).collect::<Vec<_>>().join("\n"), None),
+        };
+        name = name.or(named);
+        if !excerpt.trim().is_empty() {
+            sections.push(format!("== {} ==\n{}", file, excerpt.trim_end()));
+        }
+    }
+    if sections.is_empty() {
+        return None;
+    }
+    let name = name.or_else(|| Some(stamp.dir.file_name()?.to_string_lossy().into_owned())).unwrap_or_default();
+    Some(ProjectContext { name, excerpt: cap(&sections.join("\n\n"), PROJECT_BUDGET) })
+}
+
+/// Look at `dir` and, unless it is what `known` says, read its excerpt off
+/// the UI thread. None when nothing changed or it took too long; otherwise
+/// the new stamp and the excerpt (None = no project files).
+pub async fn load(dir: PathBuf, known: Option<ProjectStamp>) -> Option<(ProjectStamp, Option<ProjectContext>)> {
+    let task = tokio::task::spawn_blocking(move || {
+        let stamp = ProjectStamp::take(&dir);
+        if known.as_ref() == Some(&stamp) {
+            return None;
+        }
+        let project = read_project(&stamp);
+        Some((stamp, project))
+    });
+    match tokio::time::timeout(READ_TIMEOUT, task).await {
+        Ok(Ok(loaded)) => loaded,
+        Ok(Err(e)) => {
+            tracing::warn!("Reading the project files failed: {}", e);
+            None
+        }
+        Err(_) => {
+            tracing::warn!("Reading the project files took over {:?}, skipped", READ_TIMEOUT);
+            None
+        }
+    }
+}
+
+/// The first `max_bytes` of `text`, cut at a line if possible.
+fn cap(text: &str, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text.to_string();
+    }
+    let end = (0..=max_bytes).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0);
+    let end = text[..end].rfind('\n').unwrap_or(end);
+    format!("{}\n...[truncated]", &text[..end])
+}
+
+/// Value of a `key = "value"` line of a TOML file.
+fn toml_string<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let (k, value) = line.split_once('=')?;
+    (k.trim() == key).then_some(value.trim().trim_matches('"'))
+}
+
+/// Lines of the `[package]` table (name, version, edition, description)
+/// and the names of the dependencies.
+fn cargo_excerpt(text: &str) -> (String, Option<String>) {
+    let mut name = None;
+    let mut lines = Vec::new();
+    let mut dependencies = Vec::new();
+    let mut table = "";
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            table = line;
+            if line == "[package]" || line == "[workspace]" {
+                lines.push(line.to_string());
+            }
+            continue;
+        }
+        match table {
+            "[package]" => {
+                if let Some(value) = toml_string(line, "name") {
+                    name = Some(value.to_string());
+                }
+                if ["name", "version", "edition", "description"].iter().any(|key| toml_string(line, key).is_some()) {
+                    lines.push(line.to_string());
+                }
+            }
+            "[workspace]" if line.starts_with("members") => lines.push(line.to_string()),
+            "[dependencies]" => {
+                if let Some((dependency, _)) = line.split_once('=') {
+                    dependencies.push(dependency.trim().to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    if !dependencies.is_empty() {
+        lines.push(format!("[dependencies] {}", listed(&dependencies)));
+    }
+    (lines.join("\n"), name)
+}
+
+/// Name, description and scripts of a `package.json`.
+fn package_json_excerpt(text: &str) -> (String, Option<String>) {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(text) else {
+        return (String::new(), None);
+    };
+    let name = json.get("name").and_then(|v| v.as_str()).map(str::to_string);
+    let mut lines = Vec::new();
+    for key in ["name", "version", "description"] {
+        if let Some(value) = json.get(key).and_then(|v| v.as_str()) {
+            lines.push(format!("{}: {}", key, value));
+        }
+    }
+    if let Some(scripts) = json.get("scripts").and_then(|v| v.as_object()) {
+        lines.push("scripts:".to_string());
+        for (script, command) in scripts.iter().take(MAX_LISTED) {
+            lines.push(format!("  {}: {}", script, command.as_str().unwrap_or_default()));
+        }
+    }
+    (lines.join("\n"), name)
+}
+
+/// Name, version and description of a `pyproject.toml` (PEP 621 or
+/// Poetry), its scripts, and which tools it configures.
+fn pyproject_excerpt(text: &str) -> (String, Option<String>) {
+    let mut name = None;
+    let mut lines = Vec::new();
+    let mut tables = Vec::new();
+    let mut table = "";
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            table = line;
+            tables.push(line.to_string());
+            continue;
+        }
+        match table {
+            "[project]" | "[tool.poetry]" => {
+                if let Some(value) = toml_string(line, "name") {
+                    name = Some(value.to_string());
+                }
+                if ["name", "version", "description", "requires-python"].iter().any(|key| toml_string(line, key).is_some())
+                {
+                    lines.push(line.to_string());
+                }
+            }
+            "[project.scripts]" | "[tool.poetry.scripts]" if line.contains('=') => lines.push(line.to_string()),
+            _ => {}
+        }
+    }
+    if !tables.is_empty() {
+        lines.push(format!("tables: {}", listed(&tables)));
+    }
+    (lines.join("\n"), name)
+}
+
+/// Module path, Go version and required modules of a `go.mod`; the name is
+/// the last part of the module path.
+fn go_mod_excerpt(text: &str) -> (String, Option<String>) {
+    let mut module = None;
+    let mut lines = Vec::new();
+    let mut required = Vec::new();
+    let mut in_require = false;
+    for line in text.lines().map(str::trim) {
+        if in_require {
+            if line == ")" {
+                in_require = false;
+            } else if let Some(path) = line.split_whitespace().next().filter(|path| !path.starts_with("//")) {
+                required.push(path.to_string());
+            }
+        } else if let Some(path) = line.strip_prefix("module ") {
+            module = path.trim().rsplit('/').next().map(str::to_string);
+            lines.push(line.to_string());
+        } else if line.starts_with("go ") {
+            lines.push(line.to_string());
+        } else if line == "require (" {
+            in_require = true;
+        } else if let Some(path) = line.strip_prefix("require ").and_then(|rest| rest.split_whitespace().next()) {
+            required.push(path.to_string());
+        }
+    }
+    if !required.is_empty() {
+        lines.push(format!("require {}", listed(&required)));
+    }
+    (lines.join("\n"), module)
+}
+
+/// The targets of a Makefile, without pattern and special (`.PHONY`) ones.
+fn makefile_excerpt(text: &str) -> String {
+    let targets: Vec<String> = text
+        .lines()
+        .filter(|line| !line.starts_with(['\t', ' ', '#', '.']))
+        .filter_map(|line| {
+            let (target, rest) = line.split_once(':')?;
+            // `VAR := value` and `VAR ::= value` assign
+            if rest.starts_with('=') || rest.starts_with(":=") || target.contains(['=', '%', '$']) {
+                return None;
+            }
+            Some(target.split_whitespace().map(str::to_string).collect::<Vec<_>>())
+        })
+        .flatten()
+        .collect();
+    if targets.is_empty() { String::new() } else { format!("targets: {}", listed(&targets)) }
+}
+
+/// Up to [`MAX_LISTED`] of `items`, comma-separated, with how many more.
+fn listed(items: &[String]) -> String {
+    let shown = items.iter().take(MAX_LISTED).cloned().collect::<Vec<_>>().join(", ");
+    match items.len().saturating_sub(MAX_LISTED) {
+        0 => shown,
+        more => format!("{} (+{} more)", shown, more),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory of its own under the temp dir.
+    fn fixture_dir(name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-project-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// A directory holding `files`, and its excerpt.
+    fn project(name: &str, files: &[(&str, &str)]) -> Result<(PathBuf, Option<ProjectContext>), Box<dyn std::error::Error>> {
+        let dir = fixture_dir(name)?;
+        for (file, text) in files {
+            fs::write(dir.join(file), text)?;
+        }
+        let project = read_project(&ProjectStamp::take(&dir));
+        Ok((dir, project))
+    }
+
+    #[test]
+    fn test_each_manifest_names_the_project() -> Result<(), Box<dyn std::error::Error>> {
+        let cargo = "[package]\nname = \"rusty-term\"\nversion = \"0.1.0\"\nedition = \"2024\"\nauthors = [\"x\"]\n\n\
+                     [dependencies]\ntokio = { version = \"1\" }\nserde = \"1\"\n\n[dev-dependencies]\ntempfile = \"3\"\n";
+        let (dir, found) = project("cargo", &[("Cargo.toml", cargo)])?;
+        let found = found.ok_or("no Cargo.toml excerpt")?;
+        assert_eq!(found.name, "rusty-term");
+        assert_eq!(
+            found.excerpt,
+            "== Cargo.toml ==\n[package]\nname = \"rusty-term\"\nversion = \"0.1.0\"\nedition = \"2024\"\n[dependencies] tokio, serde"
+        );
+        fs::remove_dir_all(dir)?;
+
+        let package = r#"{"name": "web", "version": "2.0.0", "description": "The site", "scripts": {"build": "vite build", "test": "vitest"}, "dependencies": {"vue": "3"}}"#;
+        let (dir, found) = project("npm", &[("package.json", package)])?;
+        let found = found.ok_or("no package.json excerpt")?;
+        assert_eq!(found.name, "web");
+        assert_eq!(
+            found.excerpt,
+            "== package.json ==\nname: web\nversion: 2.0.0\ndescription: The site\nscripts:\n  build: vite build\n  test: vitest"
+        );
+        fs::remove_dir_all(dir)?;
+
+        let pyproject = "[project]\nname = \"tool\"\nrequires-python = \">=3.10\"\ndependencies = [\"click\"]\n\n\
+                         [project.scripts]\ntool = \"tool.cli:main\"\n\n[tool.pytest.ini_options]\naddopts = \"-q\"\n";
+        let (dir, found) = project("python", &[("pyproject.toml", pyproject)])?;
+        let found = found.ok_or("no pyproject.toml excerpt")?;
+        assert_eq!(found.name, "tool");
+        assert_eq!(
+            found.excerpt,
+            "== pyproject.toml ==\nname = \"tool\"\nrequires-python = \">=3.10\"\ntool = \"tool.cli:main\"\n\
+             tables: [project], [project.scripts], [tool.pytest.ini_options]"
+        );
+        fs::remove_dir_all(dir)?;
+
+        let go_mod = "module github.com/acme/server\n\ngo 1.22\n\nrequire (\n\tgithub.com/gin-gonic/gin v1.9.1\n\tgolang.org/x/sync v0.6.0 // indirect\n)\n";
+        let (dir, found) = project("go", &[("go.mod", go_mod)])?;
+        let found = found.ok_or("no go.mod excerpt")?;
+        assert_eq!(found.name, "server");
+        assert_eq!(
+            found.excerpt,
+            "== go.mod ==\nmodule github.com/acme/server\ngo 1.22\nrequire github.com/gin-gonic/gin, golang.org/x/sync"
+        );
+        fs::remove_dir_all(dir)?;
+
+        // No name in a Makefile or README: the directory's
+        let makefile = "CC := gcc\n.PHONY: all clean\nall: app\n\napp: main.o\n\t$(CC) -o app main.o\n%.o: %.c\n\t$(CC) -c $<\nclean:\n\trm -f app\n";
+        let readme = (1..=100).map(|n| format!("line {}", n)).collect::<Vec<_>>().join("\n");
+        let (dir, found) = project("make", &[("Makefile", makefile), ("README.md", &readme)])?;
+        let found = found.ok_or("no Makefile excerpt")?;
+        assert_eq!(found.name, format!("rusty-term-project-make-{}", std::process::id()));
+        assert!(found.excerpt.starts_with("== Makefile ==\ntargets: all, app, clean\n\n== README.md ==\nline 1\n"));
+        assert!(found.excerpt.ends_with("\nline 80"));
+        fs::remove_dir_all(dir)?;
+
+        // Nothing to go by
+        let (dir, found) = project("none", &[("notes.txt", "hello")])?;
+        assert_eq!(found, None);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_excerpt_is_capped() -> Result<(), Box<dyn std::error::Error>> {
+        let readme = format!("{}\n", "x".repeat(200));
+        let (dir, found) = project("big", &[("README", &readme.repeat(README_LINES))])?;
+        let excerpt = found.ok_or("no README excerpt")?.excerpt;
+        assert!(excerpt.len() <= PROJECT_BUDGET + "\n...[truncated]".len());
+        assert!(excerpt.ends_with("x\n...[truncated]"));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_out_of_the_directory_are_not_followed() -> Result<(), Box<dyn std::error::Error>> {
+        let outside = fixture_dir("outside")?;
+        fs::write(outside.join("secret"), "[package]\nname = \"secret\"\n")?;
+        let dir = fixture_dir("links")?;
+        std::os::unix::fs::symlink(outside.join("secret"), dir.join("Cargo.toml"))?;
+        assert_eq!(read_project(&ProjectStamp::take(&dir)), None);
+
+        // One to a file inside is fine
+        fs::create_dir(dir.join("docs"))?;
+        fs::write(dir.join("docs").join("intro.md"), "# Inside")?;
+        std::os::unix::fs::symlink(dir.join("docs").join("intro.md"), dir.join("README.md"))?;
+        let found = read_project(&ProjectStamp::take(&dir)).ok_or("no README excerpt")?;
+        assert_eq!(found.excerpt, "== README.md ==\n# Inside");
+        fs::remove_dir_all(dir)?;
+        fs::remove_dir_all(outside)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_a_read_for_a_directory_left_behind_is_dropped() -> Result<(), Box<dyn std::error::Error>> {
+        let (dir, found) = project("left", &[("go.mod", "module example.com/left\n")])?;
+        let mut context = crate::context::ContextManager::new();
+        context.update_cwd(dir.to_string_lossy().into_owned());
+        // Off unless asked for
+        assert_eq!(context.project_refresh(), None);
+        context.set_project_context(true);
+        assert_eq!(context.project_refresh(), Some((dir.clone(), None)));
+
+        let stamp = ProjectStamp::take(&dir);
+        context.update_cwd("/".to_string());
+        assert!(!context.set_project(stamp.clone(), found.clone()));
+        assert_eq!(context.snapshot().project, None);
+
+        context.update_cwd(dir.to_string_lossy().into_owned());
+        assert!(context.set_project(stamp.clone(), found.clone()));
+        assert_eq!(context.snapshot().project, found);
+        assert_eq!(context.project_refresh(), Some((dir.clone(), Some(stamp))));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_files_are_read_again_only_when_they_change() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = fixture_dir("mtime")?;
+        fs::write(dir.join("README"), "first")?;
+        let (stamp, found) = load(dir.clone(), None).await.ok_or("not read")?;
+        assert_eq!(found.map(|p| p.excerpt), Some("== README ==\nfirst".to_string()));
+
+        // Same files, same times: nothing to read
+        assert_eq!(load(dir.clone(), Some(stamp.clone())).await, None);
+
+        // A newer README is read again
+        let file = File::options().write(true).open(dir.join("README"))?;
+        fs::write(dir.join("README"), "second")?;
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))?;
+        let (newer, found) = load(dir.clone(), Some(stamp.clone())).await.ok_or("not read again")?;
+        assert_ne!(newer, stamp);
+        assert_eq!(found.map(|p| p.excerpt), Some("== README ==\nsecond".to_string()));
+
+        // So is a manifest that appears
+        fs::write(dir.join("go.mod"), "module example.com/m\n")?;
+        let (_, found) = load(dir.clone(), Some(newer)).await.ok_or("new file not read")?;
+        assert_eq!(found.map(|p| p.name), Some("m".to_string()));
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@
 
 #[cfg(test)]
 mod tests {
-    use crate::context::{CommandContext, ContextManager};
+    use crate::context::{CommandContext, ContextManager, OscScanner};
 
     #[test]
     fn test_baseline_mode() {
@@ -90,6 +90,18 @@ mod tests {
         assert_eq!(ctx.cwd.path, "/home/user/projects");
     }
 
+    #[test]
+    fn test_osc7_scanner_handles_split_sequences() {
+        let mut scanner = OscScanner::new();
+        assert_eq!(scanner.feed(b"ls\r\n\x1b]0;title\x07\x1b]7;file://host/tm"), None);
+        assert_eq!(scanner.feed(b"p/a%20b\x1b"), None);
+        assert_eq!(scanner.feed(b"\\$ "), Some("file://host/tmp/a%20b".to_string()));
+
+        // The last of several sequences wins; other OSCs are ignored
+        let out = b"\x1b]7;file://h/one\x07\x1b[1m\x1b]7;file://h/two\x07\x1b]2;x\x07";
+        assert_eq!(scanner.feed(out), Some("file://h/two".to_string()));
+    }
+
     #[test]
     fn test_format_for_prompt_with_commands() {
         let mut ctx = ContextManager::new();
@@ -109,6 +121,15 @@ mod tests {
         assert!(formatted.contains("file1"));
     }
 
+    #[test]
+    fn test_recent_output_keeps_no_control_characters() {
+        let mut ctx = ContextManager::new();
+        ctx.push_output("\0\0\x07\n\u{fffd}ustar\0data\x7f\nok\n".to_string());
+
+        let snapshot = ctx.snapshot();
+        assert_eq!(snapshot.recent_output, ["\u{fffd}ustardata", "ok"]);
+    }
+
     #[test]
     fn test_format_for_prompt_baseline() {
         let mut ctx = ContextManager::new();
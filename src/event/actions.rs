@@ -0,0 +1,261 @@
+//! Registry of the key bindings and what each one does.
+//!
+//! The handlers in this module match their keys themselves; this table
+//! describes them, grouped the way the help overlay (command mode `?`)
+//! lists them. A key added to a handler gets an entry here as well.
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::app::ActivePane;
+use crate::config::keymap::{KeyChord, Leader};
+
+/// Where a binding works, and the heading it is listed under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    /// Anywhere
+    Global,
+    /// After the leader
+    Command,
+    /// Typing in the pane
+    Pane,
+    /// While a suggested command waits on its card
+    Card,
+    /// Visual mode
+    Visual,
+    /// Looking back through the scrollback or the conversation
+    Browsing,
+}
+
+impl Category {
+    pub const ALL: [Category; 6] =
+        [Category::Global, Category::Command, Category::Pane, Category::Card, Category::Visual, Category::Browsing];
+
+    pub fn title(self, pane: ActivePane) -> &'static str {
+        match (self, pane) {
+            (Category::Global, _) => "Global",
+            (Category::Command, _) => "Command mode",
+            (Category::Pane, ActivePane::Terminal) => "Terminal",
+            (Category::Pane, ActivePane::Assistant) => "Assistant",
+            (Category::Card, _) => "Suggestion card",
+            (Category::Visual, _) => "Visual mode",
+            (Category::Browsing, _) => "Browsing",
+        }
+    }
+}
+
+/// The keys bound to an action.
+#[derive(Debug, Clone, Copy)]
+pub enum Keys {
+    /// The configured leader
+    Leader,
+    /// Any of these chords
+    Chords(&'static [KeyChord]),
+}
+
+/// One registered action.
+#[derive(Debug)]
+pub struct Action {
+    pub name: &'static str,
+    /// One line, shown next to the keys
+    pub description: &'static str,
+    pub category: Category,
+    /// Pane the binding works in (None = both)
+    pub pane: Option<ActivePane>,
+    pub keys: Keys,
+}
+
+impl Action {
+    pub fn applies_to(&self, pane: ActivePane) -> bool {
+        self.pane.is_none_or(|only| only == pane)
+    }
+
+    /// Whether the keys start with the leader (command mode keys, and the
+    /// leader itself).
+    pub fn starts_with_leader(&self) -> bool {
+        self.category == Category::Command || matches!(self.keys, Keys::Leader)
+    }
+
+    /// The keys as shown to the user, formatted like the leader in the pane
+    /// hints; command mode keys come after the leader.
+    pub fn chord_text(&self, leader: &Leader) -> String {
+        let chords = match self.keys {
+            Keys::Leader => leader.to_string(),
+            Keys::Chords(chords) => chords.iter().map(ToString::to_string).collect::<Vec<_>>().join(" / "),
+        };
+        match self.category {
+            Category::Command => format!("{} {}", leader, chords),
+            _ => chords,
+        }
+    }
+}
+
+const fn key(c: char) -> KeyChord {
+    KeyChord::new(KeyCode::Char(c), KeyModifiers::NONE)
+}
+
+const fn shift(code: KeyCode) -> KeyChord {
+    KeyChord::new(code, KeyModifiers::SHIFT)
+}
+
+const fn plain(code: KeyCode) -> KeyChord {
+    KeyChord::new(code, KeyModifiers::NONE)
+}
+
+const fn ctrl(c: char) -> KeyChord {
+    KeyChord::ctrl(c)
+}
+
+const fn ctrl_end() -> KeyChord {
+    KeyChord::new(KeyCode::End, KeyModifiers::CONTROL)
+}
+
+const fn action(
+    name: &'static str,
+    description: &'static str,
+    category: Category,
+    pane: Option<ActivePane>,
+    keys: Keys,
+) -> Action {
+    Action { name, description, category, pane, keys }
+}
+
+const TERMINAL: Option<ActivePane> = Some(ActivePane::Terminal);
+const ASSISTANT: Option<ActivePane> = Some(ActivePane::Assistant);
+
+/// Every key binding, in the order the help lists them.
+pub const ACTIONS: &[Action] = &[
+    action("command-mode", "Enter command mode", Category::Global, None, Keys::Leader),
+    action("cancel-resize", "Put the separator back while dragging it", Category::Global, None, Keys::Chords(&[plain(KeyCode::Esc)])),
+    // Command mode
+    action("toggle-pane", "Switch between the terminal and the assistant", Category::Command, None, Keys::Chords(&[key('n')])),
+    action("line-numbers", "Show or hide the terminal line numbers", Category::Command, None, Keys::Chords(&[shift(KeyCode::Char('n'))])),
+    action("copy-screen", "Copy the terminal screen to the clipboard", Category::Command, None, Keys::Chords(&[key('c')])),
+    action("describe-screen", "Ask the AI what the terminal shows, live or scrolled back", Category::Command, None, Keys::Chords(&[key('d')])),
+    action("fix-command", "Ask the AI to fix the last command, which failed", Category::Command, None, Keys::Chords(&[shift(KeyCode::Char('d'))])),
+    action("dump-scrollback", "Write the terminal scrollback to a file", Category::Command, None, Keys::Chords(&[shift(KeyCode::Char('c'))])),
+    action("long-line", "Open the long line viewer", Category::Command, None, Keys::Chords(&[key('p')])),
+    action("switcher", "Switch to a session or the shell", Category::Command, None, Keys::Chords(&[key('s')])),
+    action("mini-assistant", "Ask the AI in a popup over the terminal", Category::Command, None, Keys::Chords(&[key(' ')])),
+    action("visual-mode", "Enter visual mode", Category::Command, None, Keys::Chords(&[key('v')])),
+    action("select-output", "Select the output of the last command", Category::Command, TERMINAL, Keys::Chords(&[key('o')])),
+    action("new-session", "New AI session (or pick a template)", Category::Command, ASSISTANT, Keys::Chords(&[key('t')])),
+    action("close-session", "Close the current session", Category::Command, ASSISTANT, Keys::Chords(&[key('w')])),
+    action("regenerate", "Ask the latest question again (Shift+R records here)", Category::Command, ASSISTANT, Keys::Chords(&[key('r')])),
+    action("rename-session", "Rename the current session (/rename <name>)", Category::Command, ASSISTANT, Keys::Chords(&[key('h')])),
+    action("system-prompt", "Edit the current session's system prompt or pick a prompt template (/prompt <text>, /prompt alone for the default)", Category::Command, ASSISTANT, Keys::Chords(&[key('!')])),
+    action("export-session", "Write the session to a Markdown file in the shell's directory (/export json for JSON)", Category::Command, ASSISTANT, Keys::Chords(&[key('>')])),
+    action("import-session", "Open a session exported as JSON in a new tab (/import <file>)", Category::Command, ASSISTANT, Keys::Chords(&[key('<')])),
+    action("quote-legend", "Explain the underline on reply text quoted from your context", Category::Command, ASSISTANT, Keys::Chords(&[key('u')])),
+    action("next-session", "Next session", Category::Command, ASSISTANT, Keys::Chords(&[key(']')])),
+    action("previous-session", "Previous session", Category::Command, ASSISTANT, Keys::Chords(&[key('[')])),
+    action("shrink-terminal", "Move the separator left (stays in command mode)", Category::Command, None, Keys::Chords(&[plain(KeyCode::Left)])),
+    action("grow-terminal", "Move the separator right (stays in command mode)", Category::Command, None, Keys::Chords(&[plain(KeyCode::Right)])),
+    action("record", "Start or stop recording the terminal pane", Category::Command, None, Keys::Chords(&[key('r')])),
+    action("mute", "Mute or unmute the bell and notification cues", Category::Command, None, Keys::Chords(&[key('b')])),
+    action("performance", "Turn performance mode on or off for this pane", Category::Command, None, Keys::Chords(&[key('z')])),
+    action("metrics", "Show or hide the metrics overlay", Category::Command, None, Keys::Chords(&[key('m')])),
+    action("event-trace", "Show or hide the event trace", Category::Command, None, Keys::Chords(&[key('e')])),
+    action("redraw", "Redraw the whole screen", Category::Command, None, Keys::Chords(&[key('l')])),
+    action("reload-config", "Read the assistant's provider, model and API key from the config file again", Category::Command, None, Keys::Chords(&[key('.')])),
+    action("send-leader", "Send the leader to the shell", Category::Command, None, Keys::Leader),
+    action("help", "List the keys that work here", Category::Command, None, Keys::Chords(&[key('?')])),
+    action("quit", "Exit RustyTerm", Category::Command, None, Keys::Chords(&[key('q')])),
+    action("record-macro", "Record the keys sent to the shell into register a-z, then q stops (Shift+Q quits here)", Category::Command, TERMINAL, Keys::Chords(&[key('q')])),
+    action("play-macro", "Send the keys of a register again (5@a five times, @A over a full-screen program)", Category::Command, TERMINAL, Keys::Chords(&[key('@')])),
+    // Assistant input
+    action("send", "Send the message (= calculates, ? leaves out the shell context)", Category::Pane, ASSISTANT, Keys::Chords(&[plain(KeyCode::Enter)])),
+    action("newline", "Insert a newline", Category::Pane, ASSISTANT, Keys::Chords(&[ctrl('o')])),
+    action("select-all", "Select all of the input", Category::Pane, ASSISTANT, Keys::Chords(&[ctrl('a')])),
+    action("cut", "Cut the selection", Category::Pane, ASSISTANT, Keys::Chords(&[ctrl('x')])),
+    action("paste", "Paste from the clipboard", Category::Pane, ASSISTANT, Keys::Chords(&[ctrl('v')])),
+    action("interrupt", "Copy the selection, clear the input or stop the reply", Category::Pane, ASSISTANT, Keys::Chords(&[ctrl('c')])),
+    action("restore-input", "Bring back the cleared input, or suspend RustyTerm", Category::Pane, ASSISTANT, Keys::Chords(&[ctrl('z')])),
+    action("reasoning", "Show or hide the model's reasoning above its latest reply", Category::Pane, ASSISTANT, Keys::Chords(&[ctrl('e')])),
+    action("ask-again", "Ask a question answered from the cache again", Category::Pane, ASSISTANT, Keys::Chords(&[ctrl('r')])),
+    action("continue", "Continue a reply that was cut off", Category::Pane, ASSISTANT, Keys::Chords(&[ctrl('g')])),
+    action("stop-parts", "Stop sending a full output (/attach), or drop the one attached", Category::Pane, ASSISTANT, Keys::Chords(&[plain(KeyCode::Esc)])),
+    action("cycle-session", "Next session", Category::Pane, ASSISTANT, Keys::Chords(&[plain(KeyCode::Tab)])),
+    action("cycle-session-back", "Previous session", Category::Pane, ASSISTANT, Keys::Chords(&[shift(KeyCode::Tab)])),
+    // Suggestion card
+    action("run-suggestion", "Run the suggested command (or copy it)", Category::Card, ASSISTANT, Keys::Chords(&[ctrl('y')])),
+    action("reject-suggestions", "Reject the suggestions", Category::Card, ASSISTANT, Keys::Chords(&[ctrl('n')])),
+    action("next-suggestion", "Show the next suggestion", Category::Card, ASSISTANT, Keys::Chords(&[ctrl('a')])),
+    action("interrupt-then-run", "Stop the previous AI command, then run this one", Category::Card, ASSISTANT, Keys::Chords(&[ctrl('k')])),
+    action("rewrite-for-shell", "Ask for the command again, written for this shell", Category::Card, ASSISTANT, Keys::Chords(&[ctrl('t')])),
+    action("findings", "Show or hide the card's findings (input empty)", Category::Card, ASSISTANT, Keys::Chords(&[key('i')])),
+    action("why", "Ask the AI why the card got its verdict, or hide the answer (input empty)", Category::Card, ASSISTANT, Keys::Chords(&[key('?')])),
+    action("multi", "Pick several suggestions to run in order, or one at a time again (input empty)", Category::Card, ASSISTANT, Keys::Chords(&[key('m')])),
+    action("multi-select", "Checklist: select the command under the cursor", Category::Card, ASSISTANT, Keys::Chords(&[plain(KeyCode::Char(' '))])),
+    action("multi-reorder", "Checklist: move the command under the cursor down or up", Category::Card, ASSISTANT, Keys::Chords(&[key('j'), key('k')])),
+    action("multi-cursor", "Checklist: move the cursor", Category::Card, ASSISTANT, Keys::Chords(&[plain(KeyCode::Up), plain(KeyCode::Down)])),
+    action("compare", "List all suggestions to compare them, or one at a time again (input empty)", Category::Card, ASSISTANT, Keys::Chords(&[key('c')])),
+    action("compare-move", "Comparison: highlight the next or previous suggestion", Category::Card, ASSISTANT, Keys::Chords(&[key('j'), key('k'), plain(KeyCode::Down), plain(KeyCode::Up)])),
+    // Visual mode
+    action("visual-move", "Move the cursor (arrows too; a count first repeats)", Category::Visual, None, Keys::Chords(&[key('h'), key('j'), key('k'), key('l')])),
+    action("visual-select", "Start a line selection, then switch line and block", Category::Visual, None, Keys::Chords(&[key(' ')])),
+    action("visual-copy", "Copy the selection", Category::Visual, None, Keys::Chords(&[key('y')])),
+    action("visual-edit", "Edit the question under the cursor and ask it again in its place", Category::Visual, ASSISTANT, Keys::Chords(&[key('e')])),
+    action("visual-timing", "Show where the time of the reply under the cursor went", Category::Visual, ASSISTANT, Keys::Chords(&[key('i')])),
+    action("visual-output", "Select the last command's output (again for earlier ones)", Category::Visual, TERMINAL, Keys::Chords(&[key('o')])),
+    action("visual-scroll", "Scroll a line without moving the cursor", Category::Visual, None, Keys::Chords(&[shift(KeyCode::Up), shift(KeyCode::Down)])),
+    action("visual-page", "Scroll a page", Category::Visual, None, Keys::Chords(&[plain(KeyCode::PageUp), plain(KeyCode::PageDown)])),
+    action("visual-exit", "Drop the selection, or leave visual mode", Category::Visual, None, Keys::Chords(&[plain(KeyCode::Esc)])),
+    // Browsing
+    action("scroll-line", "Scroll the scrollback a line", Category::Browsing, TERMINAL, Keys::Chords(&[shift(KeyCode::Up), shift(KeyCode::Down)])),
+    action("scroll-page", "Scroll the scrollback a page", Category::Browsing, TERMINAL, Keys::Chords(&[shift(KeyCode::PageUp), shift(KeyCode::PageDown)])),
+    action("scroll-bottom", "Back to the live screen (so does any other key)", Category::Browsing, TERMINAL, Keys::Chords(&[shift(KeyCode::End)])),
+    action("chat-scroll-line", "Scroll the conversation a line", Category::Browsing, ASSISTANT, Keys::Chords(&[shift(KeyCode::Up), shift(KeyCode::Down)])),
+    action("chat-scroll-page", "Scroll the conversation a page", Category::Browsing, ASSISTANT, Keys::Chords(&[shift(KeyCode::PageUp), shift(KeyCode::PageDown)])),
+    action("chat-bottom", "Back to the latest message", Category::Browsing, ASSISTANT, Keys::Chords(&[ctrl_end(), plain(KeyCode::Esc)])),
+];
+
+/// The built-in command mode action on `chord`'s key, if any. Command mode
+/// matches keys without their modifiers, and most letters in either case,
+/// so only the key itself is compared, letters ignoring case.
+pub fn command_key_owner(chord: KeyChord) -> Option<&'static Action> {
+    let same_key = |builtin: &KeyChord| match (builtin.code, chord.code) {
+        (KeyCode::Char(a), KeyCode::Char(b)) => a.eq_ignore_ascii_case(&b),
+        (a, b) => a == b,
+    };
+    ACTIONS.iter().filter(|action| action.category == Category::Command).find(|action| match action.keys {
+        Keys::Chords(chords) => chords.iter().any(same_key),
+        Keys::Leader => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_names_are_unique() {
+        let mut names: Vec<_> = ACTIONS.iter().map(|action| action.name).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), ACTIONS.len());
+    }
+
+    #[test]
+    fn test_chord_text_puts_command_keys_after_the_leader() -> Result<(), String> {
+        let leader: Leader = "ctrl+a r".parse()?;
+        let chord_text = |name: &str| ACTIONS.iter().find(|a| a.name == name).map(|a| a.chord_text(&leader));
+        assert_eq!(chord_text("command-mode").as_deref(), Some("Ctrl+A R"));
+        assert_eq!(chord_text("line-numbers").as_deref(), Some("Ctrl+A R Shift+N"));
+        assert_eq!(chord_text("send-leader").as_deref(), Some("Ctrl+A R Ctrl+A R"));
+        assert_eq!(chord_text("run-suggestion").as_deref(), Some("Ctrl+Y"));
+        assert_eq!(chord_text("visual-page").as_deref(), Some("PageUp / PageDown"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_keys_are_owned_whatever_their_case() {
+        let owner = |chord: KeyChord| command_key_owner(chord).map(|action| action.name);
+        assert_eq!(owner(key('q')), Some("quit"));
+        assert_eq!(owner(shift(KeyCode::Char('q'))), Some("quit"));
+        assert_eq!(owner(ctrl('w')), Some("close-session"));
+        assert_eq!(owner(plain(KeyCode::Left)), Some("shrink-terminal"));
+        assert_eq!(owner(key('g')), None);
+        // Keys of other modes don't count
+        assert_eq!(owner(key('y')), None);
+    }
+}
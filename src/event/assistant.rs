@@ -1,13 +1,74 @@
 //! Key event handling for the AI Assistant pane.
 
+use std::io::Write;
+use std::path::Path;
+
 use anyhow::Result;
 use arboard::Clipboard;
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use tracing::warn;
 
 use super::UserEvent;
-use crate::ai::session::AiSessionManager;
+use crate::ai::chunked::OutputParts;
+use crate::ai::export::ExportFormat;
+use crate::ai::local;
+use crate::ai::prompt;
+use crate::ai::session::{AiSessionManager, EditError, QueuedOnCancel, SessionEvent, Steer, SuggestionError};
+use crate::context::{ContextSnapshot, OUTPUT_BUDGET};
 use crate::shell::ShellManager;
-use crate::ui::assistant::TuiAssistant;
+use crate::ui::assistant::{EnterAction, TuiAssistant, WhyVerdict};
+use crate::ui::visual::KeyHandleResult;
+use crate::utils::persist;
+
+/// What Ctrl+C did in the Assistant pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// Copied the selection in the input box
+    CopiedInput,
+    /// Copied the visual-mode selection in the message area
+    CopiedMessages,
+    /// Cleared the input (Ctrl+Z restores it)
+    ClearedInput,
+    /// Cancelled the reply being streamed
+    CancelledStream,
+    /// Nothing to act on
+    Nothing,
+}
+
+/// Handle Ctrl+C in the Assistant pane, in any mode.
+///
+/// The first of these that applies wins: copy the input selection, copy the
+/// visual-mode selection, clear the input, cancel the streaming reply. The
+/// key never reaches the shell and never quits the app.
+pub fn interrupt(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager) -> Interrupt {
+    if assistant.has_input_selection() {
+        assistant.copy_input_selection();
+        Interrupt::CopiedInput
+    } else if assistant.is_visual_selecting() {
+        assistant.yank_visual_selection();
+        Interrupt::CopiedMessages
+    } else if assistant.clear_input() {
+        Interrupt::ClearedInput
+    } else if assistant.is_streaming() && cancel_with_queue(assistant, ai_sessions) {
+        Interrupt::CancelledStream
+    } else {
+        Interrupt::Nothing
+    }
+}
+
+/// Cancel the reply streaming into the active session, and the messages
+/// queued behind it: those go back to the (empty) input box, unsent.
+fn cancel_with_queue(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager) -> bool {
+    let session_id = assistant.active_session_id();
+    let queued = ai_sessions.queued_messages(session_id);
+    if !ai_sessions.cancel_stream(session_id, QueuedOnCancel::Drop) {
+        return false;
+    }
+    if !queued.is_empty() {
+        assistant.put_back_input(queued.join("\n\n"));
+    }
+    true
+}
 
 /// Handle key events when the Assistant pane is active.
 ///
@@ -27,11 +88,88 @@ pub fn handle_key_event(
     shell_manager: &ShellManager,
     key_evt: KeyEvent,
 ) -> Result<()> {
+    // New-session picker is modal: it takes all keys while open
+    if assistant.is_template_picker_open() {
+        return handle_template_picker_key(assistant, ai_sessions, key_evt);
+    }
+    // So is the system prompt picker
+    if assistant.is_prompt_picker_open() {
+        handle_prompt_picker_key(assistant, ai_sessions, context_manager, key_evt);
+        return Ok(());
+    }
+    // And the regenerate menu
+    if assistant.is_regen_menu_open() {
+        return handle_regen_menu_key(assistant, ai_sessions, key_evt);
+    }
+    // And the attachment picker
+    if assistant.is_attach_picker_open() {
+        return handle_attach_picker_key(assistant, key_evt);
+    }
+    // And the offer to attach a file just dropped
+    if assistant.has_drop_offer() {
+        handle_drop_offer_key(assistant, key_evt);
+        return Ok(());
+    }
+    // Vi normal mode takes the plain keys before the card shortcuts and
+    // the input do; Esc in insert mode switches to it
+    if assistant.handle_vi_key(key_evt) == KeyHandleResult::Consumed {
+        return Ok(());
+    }
+
     let session_id = assistant.active_session_id();
 
-    // Check for pending command confirmation first (Ctrl+Y / Ctrl+N / Ctrl+/ shortcuts)
-    // Use ai_sessions as the authoritative source for pending suggestions
-    if ai_sessions.has_pending_suggestion(session_id) {
+    // Check for pending command confirmation first (Ctrl+Y / Ctrl+N / Ctrl+/ shortcuts).
+    // Only the card on display in the active session is decided on; the
+    // session manager checks it is still pending there
+    if assistant.pending_tool_call_id().is_some() {
+        // The checklist takes the plain keys it uses before the input does
+        if key_evt.modifiers.is_empty()
+            && assistant.get_input().is_empty()
+            && let Some(checklist) = assistant.checklist_mut()
+        {
+            let handled = match key_evt.code {
+                KeyCode::Char(' ') => {
+                    checklist.toggle();
+                    true
+                }
+                KeyCode::Char('j') => {
+                    checklist.move_item(1);
+                    true
+                }
+                KeyCode::Char('k') => {
+                    checklist.move_item(-1);
+                    true
+                }
+                KeyCode::Down => {
+                    checklist.move_cursor(1);
+                    true
+                }
+                KeyCode::Up => {
+                    checklist.move_cursor(-1);
+                    true
+                }
+                _ => false,
+            };
+            if handled {
+                return Ok(());
+            }
+        }
+
+        // So does the comparison, to move its highlight
+        if key_evt.modifiers.is_empty() && assistant.get_input().is_empty() && assistant.is_comparing() {
+            match key_evt.code {
+                KeyCode::Char('j') | KeyCode::Down => {
+                    assistant.move_compare(1);
+                    return Ok(());
+                }
+                KeyCode::Char('k') | KeyCode::Up => {
+                    assistant.move_compare(-1);
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
         match key_evt.code {
             // Ctrl+A => Cycle to next command suggestion
             KeyCode::Char('a') | KeyCode::Char('A')
@@ -45,29 +183,67 @@ pub fn handle_key_event(
             KeyCode::Char('y') | KeyCode::Char('Y')
             if key_evt.modifiers.contains(KeyModifiers::CONTROL) =>
             {
-                // Get the index of the currently displayed suggestion
-                let pending_idx = assistant.current_suggestion_index();
-
-                // Check if the command is denied (should copy instead of execute)
-                if assistant.is_pending_command_denied() {
-                    // Deny verdict: copy to clipboard instead of executing
-                    if assistant.copy_pending_command().is_some() {
-                        // Update backend state
-                        ai_sessions.reject_suggestion(session_id);
-                    }
-                } else {
-                    // Allow/RequireConfirmation verdict: execute the command
-                    if let Some(command) = ai_sessions.accept_suggestion(session_id, pending_idx) {
-                        // Update UI to show command as executed
-                        assistant.confirm_command();
-
-                        // Tell the session manager to execute the suggested command
-                        // It will send the ExecuteAiCommand event to the app layer
-                        // Security gating happens in app.rs try_execute_suggested()
-                        ai_sessions.execute_suggestion(session_id, command)?;
-                    }
+                return confirm_pending(assistant, ai_sessions);
+            }
+
+            // Ctrl+K => Stop the previous AI command still running, then run this one
+            KeyCode::Char('k') | KeyCode::Char('K')
+                if key_evt.modifiers.contains(KeyModifiers::CONTROL) && assistant.running_ai_command().is_some() =>
+            {
+                return interrupt_and_confirm_pending(assistant, ai_sessions);
+            }
+
+            // Ctrl+T => Ask for the command again, written for the target shell
+            KeyCode::Char('t') | KeyCode::Char('T')
+                if key_evt.modifiers.contains(KeyModifiers::CONTROL) =>
+            {
+                if !assistant.is_streaming()
+                    && let Some((command, shell)) = assistant.pending_incompatible_command()
+                {
+                    let question = format!("Rewrite this command for {}: `{}`", shell.label(), command);
+                    send_question(assistant, ai_sessions, context_manager, shell_manager, question, false);
                 }
+                return Ok(());
+            }
 
+            // i (with nothing typed yet) => Show or hide the card's findings;
+            // a card without findings leaves the key to the input
+            KeyCode::Char('i')
+                if key_evt.modifiers.is_empty()
+                    && assistant.get_input().is_empty()
+                    && assistant.toggle_pending_findings() =>
+            {
+                return Ok(());
+            }
+
+            // m (with nothing typed yet) => Pick several of the suggestions
+            // to run in order, or go back to one at a time
+            KeyCode::Char('m')
+                if key_evt.modifiers.is_empty()
+                    && assistant.get_input().is_empty()
+                    && assistant.toggle_checklist() =>
+            {
+                return Ok(());
+            }
+
+            // c (with nothing typed yet) => List all the suggestions to
+            // compare them, or go back to one at a time
+            KeyCode::Char('c')
+                if key_evt.modifiers.is_empty()
+                    && assistant.get_input().is_empty()
+                    && assistant.toggle_compare() =>
+            {
+                return Ok(());
+            }
+
+            // ? (with nothing typed yet) => Ask the AI why the pending card
+            // got its verdict, or show or hide the answer; without a flagged
+            // card the key goes to the input
+            KeyCode::Char('?')
+                if !key_evt.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                    && assistant.get_input().is_empty()
+                    && explain_pending_verdict(assistant, ai_sessions) =>
+            {
                 return Ok(());
             }
 
@@ -75,14 +251,19 @@ pub fn handle_key_event(
             KeyCode::Char('n') | KeyCode::Char('N')
                 if key_evt.modifiers.contains(KeyModifiers::CONTROL) =>
             {
-                // Update backend state first (marks all suggestions as Rejected)
-                ai_sessions.reject_suggestion(session_id);
-                // Update UI
-                assistant.reject_command();
+                reject_pending(assistant, ai_sessions);
                 return Ok(());
             }
             _ => {}
         }
+    } else if let Some(tab) = assistant.pending_elsewhere()
+        && key_evt.modifiers.contains(KeyModifiers::CONTROL)
+        && matches!(key_evt.code, KeyCode::Char('y' | 'Y' | 'n' | 'N'))
+    {
+        // Never let the keys reach another session's card
+        let notice = format!("no card here, see {}", tab.name);
+        assistant.show_notice(notice);
+        return Ok(());
     }
     let ctrl = key_evt.modifiers.contains(KeyModifiers::CONTROL);
     let shift = key_evt.modifiers.contains(KeyModifiers::SHIFT);
@@ -100,9 +281,15 @@ pub fn handle_key_event(
             assistant.select_all_input();
         }
 
-        // Ctrl+C: Copy selected text (or do nothing if no selection)
+        // Ctrl+C: Copy, clear or cancel depending on context (never quits)
         KeyCode::Char('c') | KeyCode::Char('C') if ctrl => {
-            assistant.copy_input_selection();
+            interrupt(assistant, ai_sessions);
+        }
+
+        // Ctrl+Z: Bring back the input cleared with Ctrl+C; with nothing to
+        // bring back, suspend RustyTerm as Ctrl+Z in a shell would
+        KeyCode::Char('z') | KeyCode::Char('Z') if ctrl && !assistant.restore_cleared_input() => {
+            assistant.request_suspend();
         }
 
         // Ctrl+X: Cut selected text
@@ -110,44 +297,228 @@ pub fn handle_key_event(
             assistant.cut_input_selection();
         }
 
+        // Ctrl+R: Ask a question answered from the response cache again
+        KeyCode::Char('r') | KeyCode::Char('R') if ctrl => {
+            if assistant.is_streaming() {
+                return Ok(());
+            }
+            ai_sessions.refresh_cached_reply(session_id);
+        }
+
+        // Ctrl+G: Continue a reply that ended before it was complete
+        KeyCode::Char('g') | KeyCode::Char('G') if ctrl && assistant.can_continue_reply() => {
+            let question = prompt::CONTINUE_PROMPT.to_string();
+            send_question(assistant, ai_sessions, context_manager, shell_manager, question, true);
+        }
+
+        // Ctrl+E: Show or hide the reasoning above the latest reply
+        KeyCode::Char('e') | KeyCode::Char('E') if ctrl => {
+            assistant.toggle_reasoning();
+        }
+
         // Ctrl+V: Paste from clipboard (replaces selection if any)
         KeyCode::Char('v') | KeyCode::Char('V') if ctrl => {
             // Delete selection first if present
             assistant.delete_input_selection();
 
             // Paste from clipboard
-            if let Ok(mut clipboard) = Clipboard::new() {
-                if let Ok(text) = clipboard.get_text() {
-                    for c in text.chars() {
-                        assistant.insert_char(c);
-                    }
+            if let Ok(mut clipboard) = Clipboard::new()
+                && let Ok(text) = clipboard.get_text()
+            {
+                for c in text.chars() {
+                    assistant.insert_char(c);
                 }
             }
         }
 
-        // Plain Enter: Submit the message
+        // Plain Enter: Submit the message, unless there is nothing to
+        // send or it has to wait (see `EnterAction`); the input stays then
         KeyCode::Enter => {
-            // Don't allow sending new messages while AI is still streaming a response
-            if assistant.is_streaming() {
-                return Ok(());
+            match assistant.enter_action() {
+                // A waiting card keeps Enter quiet: its keys are Ctrl+Y/N
+                EnterAction::Nothing if assistant.pending_tool_call_id().is_some() => return Ok(()),
+                EnterAction::Nothing => {
+                    assistant.flash_prompt();
+                    return Ok(());
+                }
+                EnterAction::Wait => {
+                    assistant.show_notice("reply still streaming: Enter again once it is done (Ctrl+C stops it)".to_string());
+                    return Ok(());
+                }
+                EnterAction::Queue => {}
+                EnterAction::AnswerCard => {
+                    assistant.show_notice("answer the card first: Ctrl+Y runs it, Ctrl+N rejects it".to_string());
+                    return Ok(());
+                }
+                EnterAction::RejectAndSend | EnterAction::Send => {}
             }
 
             let input = assistant.take_input();
-            if !input.trim().is_empty() {
-                // If there's a pending command, auto-reject it before sending new message
-                if ai_sessions.has_pending_suggestion(session_id) {
-                    ai_sessions.reject_suggestion(session_id);
-                    assistant.reject_command();
+            // An edited question is asked in place of the old one; sent as
+            // anything else, the edit is given up
+            let editing = assistant.take_editing();
+
+            // Calculator-style questions are answered locally ("=" forces it)
+            let local = match input.trim_start().strip_prefix('=') {
+                Some(expr) => Some(local::evaluate(expr)),
+                None if assistant.local_answers_enabled() => local::try_answer(&input),
+                None => None,
+            };
+            if let Some(result) = local {
+                assistant.push_local_question(input);
+                match result {
+                    Ok(answer) => assistant.push_local_answer(answer),
+                    Err(e) => assistant.push_error_message(e.to_string()),
+                }
+                return Ok(());
+            }
+
+            // "/regen" opens the regenerate menu; "/regen <steer>" skips it
+            if let Some(arg) = input.trim().strip_prefix("/regen")
+                && (arg.is_empty() || arg.starts_with(' '))
+            {
+                match arg.trim() {
+                    "" => assistant.open_regen_menu(),
+                    name => match Steer::from_name(name) {
+                        Some(steer) => regenerate(assistant, ai_sessions, Some(steer)),
+                        None => {
+                            let names: Vec<_> = Steer::ALL.iter().map(|steer| steer.name()).collect();
+                            assistant.push_error_message(format!(
+                                "Unknown steer \"{}\" (try {})",
+                                name,
+                                names.join(", ")
+                            ));
+                        }
+                    },
+                }
+                return Ok(());
+            }
+
+            // "/attach" picks a command whose whole output goes with the next message
+            if input.trim() == "/attach" {
+                open_attach_picker(assistant, context_manager, shell_manager);
+                return Ok(());
+            }
+
+            if input.trim() == "/cache clear" {
+                assistant.push_local_question(input);
+                match ai_sessions.clear_response_cache() {
+                    Some(removed) => assistant.push_local_answer(format!("Cleared {} cached replies", removed)),
+                    None => assistant.push_error_message("Response cache is disabled".to_string()),
+                }
+                return Ok(());
+            }
+
+            // "/set temperature 0.2" or "/set effort low" for this session ("default" unsets)
+            if let Some(args) = input.trim().strip_prefix("/set")
+                && (args.is_empty() || args.starts_with(' '))
+            {
+                let args = args.to_string();
+                let session_id = assistant.active_session_id();
+                assistant.push_local_question(input);
+                let result = ai_sessions
+                    .session_sampling(session_id)
+                    .ok_or_else(|| "Session not found".to_string())
+                    .and_then(|settings| settings.set(&args));
+                match result {
+                    Ok((settings, said)) => {
+                        ai_sessions.set_session_sampling(session_id, settings);
+                        assistant.push_local_answer(said);
+                    }
+                    Err(e) => assistant.push_error_message(e),
+                }
+                return Ok(());
+            }
+
+            // "/rename <name>" names this session ("/rename" alone takes the name away)
+            if let Some(name) = input.trim().strip_prefix("/rename")
+                && (name.is_empty() || name.starts_with(' '))
+            {
+                ai_sessions.rename_session(assistant.active_session_id(), name);
+                return Ok(());
+            }
+
+            // "/prompt <text>" gives this session its own system prompt ("/prompt" alone the default one)
+            if let Some(text) = input.trim().strip_prefix("/prompt")
+                && (text.is_empty() || text.starts_with(char::is_whitespace))
+            {
+                let session_id = assistant.active_session_id();
+                let (system_prompt, said) = match text.trim() {
+                    "" => (prompt::SYSTEM_PROMPT, "System prompt of this session back to the default"),
+                    text => (text, "System prompt of this session changed"),
+                };
+                if ai_sessions.set_system_prompt(session_id, system_prompt.to_string()) {
+                    assistant.show_notice(said.to_string());
+                }
+                return Ok(());
+            }
+
+            // "/export" writes the session to a file in the shell's directory ("/export json" for JSON)
+            if let Some(name) = input.trim().strip_prefix("/export")
+                && (name.is_empty() || name.starts_with(' '))
+            {
+                match name.trim() {
+                    "" => export_session(assistant, ai_sessions, Path::new(&context_manager.cwd.path), ExportFormat::Markdown),
+                    name => match ExportFormat::from_name(name) {
+                        Some(format) => export_session(assistant, ai_sessions, Path::new(&context_manager.cwd.path), format),
+                        None => assistant.show_notice(format!("Unknown export format \"{}\" (try md, json)", name)),
+                    },
+                }
+                return Ok(());
+            }
+
+            // "/import <file>" opens an exported session in a new tab
+            if let Some(file) = input.trim().strip_prefix("/import")
+                && (file.is_empty() || file.starts_with(' '))
+            {
+                match file.trim() {
+                    "" => assistant.show_notice("type /import <file> to open an exported session".to_string()),
+                    file => import_session(assistant, ai_sessions, Path::new(&context_manager.cwd.path), file),
+                }
+                return Ok(());
+            }
+
+            // "/security export <file>" or "/security import <file> [--replace | --dry-run] [--force]"
+            if let Some(args) = input.trim().strip_prefix("/security")
+                && (args.is_empty() || args.starts_with(' '))
+            {
+                let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+                assistant.push_local_question(input);
+                match ai_sessions.security_command(&args) {
+                    Ok(summary) => assistant.push_local_answer(summary),
+                    Err(e) => assistant.push_error_message(e),
                 }
+                // Pending cards may no longer need confirming
+                assistant.set_allowlist(ai_sessions.allowlist());
+                return Ok(());
+            }
 
-                assistant.push_user_message(input.clone());
-                assistant.start_assistant_message();
-                // Send to AI backend - response will come through ai_stream channel
-                // Include recent command records from ShellManager (max 10)
-                let command_records = shell_manager.recent_command_records(10);
-                let context = context_manager.snapshot_with_commands(command_records);
-                ai_sessions.send_message(session_id, &input, context);
+            // Without an API key the request could only fail: the message
+            // stays in the input until the config is reloaded
+            if let Some(hint) = ai_sessions.setup_hint() {
+                assistant.put_back_input(input);
+                assistant.push_error_message(not_sent(&hint));
+                return Ok(());
             }
+
+            // A leading "?" asks without shell context (and can be answered from the cache)
+            let (question, context_free) = match input.trim_start().strip_prefix('?') {
+                Some(question) => (question.trim_start().to_string(), true),
+                None => (input, false),
+            };
+
+            if question.trim().is_empty() {
+                assistant.flash_prompt();
+                return Ok(());
+            }
+            if let Some(index) = editing {
+                if let Err(e) = ai_sessions.truncate_after_user_message(assistant.active_session_id(), index) {
+                    assistant.push_error_message(e.to_string());
+                    return Ok(());
+                }
+                sync_sessions(assistant, ai_sessions);
+            }
+            send_question(assistant, ai_sessions, context_manager, shell_manager, question, context_free);
         }
 
         // Text input (with selection replacement)
@@ -194,6 +565,10 @@ pub fn handle_key_event(
         KeyCode::End if ctrl => {
             assistant.scroll_to_bottom();
         }
+        // While scrolled back, End jumps to the latest and follows it again
+        KeyCode::End if !shift && assistant.is_scrolled() => {
+            assistant.scroll_to_bottom();
+        }
 
         KeyCode::End if shift => {
             assistant.start_input_selection();
@@ -204,12 +579,17 @@ pub fn handle_key_event(
             assistant.move_cursor_to_end();
         }
 
-        // Escape: Clear selection or exit scroll mode
+        // Escape: Clear selection, exit scroll mode, stop sending a full
+        // output or drop the one attached, then the dropped files
         KeyCode::Esc => {
             if assistant.has_input_selection() {
                 assistant.clear_input_selection();
             } else if assistant.is_scrolled() {
                 assistant.scroll_to_bottom();
+            } else if ai_sessions.is_sending_parts(session_id) {
+                ai_sessions.cancel_stream(session_id, QueuedOnCancel::Keep);
+            } else if !assistant.drop_full_output() {
+                assistant.drop_dropped_files();
             }
         }
 
@@ -249,14 +629,7 @@ pub fn handle_key_event(
             };
 
             if let Some(id) = new_id {
-                // Switch backend session
                 ai_sessions.switch_session(id);
-                // Switch frontend session and load messages
-                assistant.switch_session(id);
-                let messages = ai_sessions.get_session_messages(id);
-                assistant.load_messages(messages);
-                // Sync tab list (in case it changed)
-                assistant.sync_session_tabs(ai_sessions.get_session_tabs());
             }
         }
 
@@ -265,45 +638,397 @@ pub fn handle_key_event(
     Ok(())
 }
 
+/// Decide on the pending card on display: run its command, or copy it if
+/// the verdict denies running it. While the previous AI command seems to
+/// still be running, the first call only asks to press again, since the
+/// command would be typed into it.
+///
+/// The session manager is told which card the user saw (session and tool
+/// call). If it is no longer pending there, nothing is decided and the
+/// session's messages are loaded again to show their current state.
+pub fn confirm_pending(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager) -> Result<()> {
+    let session_id = assistant.active_session_id();
+    let Some(tool_call_id) = assistant.pending_tool_call_id() else {
+        return Ok(());
+    };
+    if assistant.checklist().is_some() {
+        return confirm_checklist(assistant, ai_sessions);
+    }
+
+    // Deny verdict: copy to clipboard instead of executing
+    if assistant.is_pending_command_denied() {
+        if assistant.copy_pending_command().is_some()
+            && let Err(e) = ai_sessions.reject_suggestion(session_id, &tool_call_id)
+        {
+            resync_stale_card(assistant, e);
+        }
+        return Ok(());
+    }
+
+    // Allow/RequireConfirmation verdict: execute the command, on the second
+    // press if the previous AI command would get it as input
+    if !assistant.confirm_send_while_running() {
+        return Ok(());
+    }
+    execute_pending(assistant, ai_sessions, &tool_call_id, false)
+}
+
+/// Accept the suggestions selected in the checklist and have the app run
+/// them in their order, each through the security gate once the one
+/// before it is done.
+fn confirm_checklist(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager) -> Result<()> {
+    let session_id = assistant.active_session_id();
+    let selected = assistant.selected_tool_call_ids();
+    if selected.is_empty() {
+        assistant.show_notice("Nothing selected: Space selects the command under ▸".to_string());
+        return Ok(());
+    }
+    if !assistant.confirm_send_while_running() {
+        return Ok(());
+    }
+    match ai_sessions.accept_suggestions(session_id, &selected) {
+        Ok(steps) => {
+            assistant.confirm_checklist();
+            ai_sessions.execute_batch(session_id, steps)?;
+        }
+        Err(e) => resync_stale_card(assistant, e),
+    }
+    Ok(())
+}
+
+/// Run the pending card's command after stopping the previous AI command,
+/// which seems to still be running, with Ctrl+C. A card whose verdict
+/// denies running it is left alone; the app sends the Ctrl+C only if it
+/// lets the command run.
+pub fn interrupt_and_confirm_pending(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager) -> Result<()> {
+    let Some(tool_call_id) = assistant.pending_tool_call_id() else {
+        return Ok(());
+    };
+    if assistant.running_ai_command().is_none() || assistant.is_pending_command_denied() || assistant.checklist().is_some()
+    {
+        return Ok(());
+    }
+    execute_pending(assistant, ai_sessions, &tool_call_id, true)
+}
+
+/// Accept the card made by `tool_call_id` and have the app run it
+/// (`interrupt_first` = after a Ctrl+C).
+fn execute_pending(
+    assistant: &mut TuiAssistant,
+    ai_sessions: &mut AiSessionManager,
+    tool_call_id: &str,
+    interrupt_first: bool,
+) -> Result<()> {
+    let session_id = assistant.active_session_id();
+    match ai_sessions.accept_suggestion(session_id, tool_call_id) {
+        Ok(command) => {
+            // Update UI to show command as executed
+            assistant.confirm_command();
+            // The session manager sends the ExecuteAiCommand event to the app layer;
+            // security gating happens in app.rs try_execute_suggested()
+            let cwd = ai_sessions.suggestion_cwd(session_id, tool_call_id);
+            ai_sessions.execute_suggestion(session_id, tool_call_id, command, cwd, interrupt_first)?;
+        }
+        Err(e) => resync_stale_card(assistant, e),
+    }
+    Ok(())
+}
+
+/// Reject the pending card on display, along with the other suggestions of
+/// the same reply.
+pub fn reject_pending(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager) {
+    let session_id = assistant.active_session_id();
+    let Some(tool_call_id) = assistant.pending_tool_call_id() else {
+        return;
+    };
+    match ai_sessions.reject_suggestion(session_id, &tool_call_id) {
+        Ok(()) => assistant.reject_command(),
+        Err(e) => resync_stale_card(assistant, e),
+    }
+}
+
+/// '?' on the pending card. False if it has no verdict to explain.
+fn explain_pending_verdict(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager) -> bool {
+    let why = assistant.why_pending_verdict();
+    let applies = why != WhyVerdict::NotApplicable;
+    explain_verdict(assistant, ai_sessions, why);
+    applies
+}
+
+/// Send the question of a card's "why" to the AI, if there is one to send.
+/// The answer comes back as an `AiUiUpdate::Rationale`.
+pub fn explain_verdict(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager, why: WhyVerdict) {
+    let WhyVerdict::Ask(question) = why else {
+        return;
+    };
+    let request =
+        prompt::verdict_request(&question.command, question.cwd.as_deref(), question.shell.label(), &question.findings);
+    if !ai_sessions.explain_verdict(assistant.active_session_id(), &question.tool_call_id, request) {
+        assistant.set_rationale(&question.tool_call_id, Err("no session to ask".to_string()));
+    }
+}
+
+/// The card on display was out of date: say so. The session manager has
+/// the session shown again as it is now.
+fn resync_stale_card(assistant: &mut TuiAssistant, error: SuggestionError) {
+    warn!("Decision on a stale suggestion card ignored: {}", error);
+    assistant.show_notice(error.to_string());
+}
+
+/// Why a question went nowhere, and how to change that.
+fn not_sent(hint: &str) -> String {
+    format!("Not sent: {} — then command mode . reloads the config", hint)
+}
+
+/// Send a question to the active session, rejecting any pending command first.
+pub fn send_question(
+    assistant: &mut TuiAssistant,
+    ai_sessions: &mut AiSessionManager,
+    context_manager: &crate::context::ContextManager,
+    shell_manager: &ShellManager,
+    question: String,
+    context_free: bool,
+) {
+    if let Some(hint) = ai_sessions.setup_hint() {
+        assistant.push_error_message(not_sent(&hint));
+        return;
+    }
+    let session_id = assistant.active_session_id();
+    // While a reply streams in, the question waits for it in the session's
+    // queue (shown from there); an attached output waits for the next one
+    let queued = ai_sessions.is_busy(session_id);
+    // If there's a pending command, auto-reject it before sending new message
+    if !queued {
+        reject_pending(assistant, ai_sessions);
+    }
+    // A question without context goes without an attached output too, and
+    // leaves the dropped files for the next one
+    let full_output = if queued { None } else { assistant.take_full_output().filter(|_| !context_free) };
+
+    // Send to AI backend - response will come through ai_stream channel
+    // Include recent command records from ShellManager (max 10)
+    if context_free {
+        if !queued {
+            assistant.push_user_message(question.clone());
+            assistant.start_assistant_message();
+        }
+        ai_sessions.send_message(session_id, &question, ContextSnapshot::empty());
+    } else {
+        // Sections clicked off in the chips stay out of this message only
+        let attachments = assistant.attachments();
+        let snapshot_started = tokio::time::Instant::now();
+        let command_records = shell_manager.recent_command_records(10);
+        let mut context = attachments.apply(context_manager.snapshot_with_commands(command_records));
+        context.files = assistant.take_dropped_files();
+        ai_sessions.note_snapshot_time(snapshot_started.elapsed());
+        let excluded = attachments.excluded_sections();
+        assistant.reset_attachments();
+        match full_output {
+            // Its whole output goes first, in parts; the question goes with the last
+            Some(record) => {
+                let output = context_manager.full_output(&record);
+                let command = record.command_line.clone();
+                let parts = ai_sessions.send_full_output(session_id, &question, context, record, &output, &excluded);
+                assistant.begin_context_parts(command, parts);
+                assistant.show_context_part(1, parts, (parts == 1).then_some(question));
+            }
+            None => {
+                if !queued {
+                    assistant.push_user_message(question.clone());
+                    assistant.start_assistant_message();
+                }
+                ai_sessions.send_message_excluding(session_id, &question, context, &excluded);
+            }
+        }
+    }
+}
+
+/// Ask the active session about `screen`, the text on the terminal screen
+/// or the part of the scrollback in view (`scrolled_back`), with the last
+/// few commands for context. The reply is a normal assistant message.
+pub fn describe_screen(
+    assistant: &mut TuiAssistant,
+    ai_sessions: &mut AiSessionManager,
+    context_manager: &crate::context::ContextManager,
+    shell_manager: &ShellManager,
+    screen: String,
+    scrolled_back: bool,
+) {
+    let session_id = assistant.active_session_id();
+    reject_pending(assistant, ai_sessions);
+    let question = prompt::describe_screen_request(scrolled_back).to_string();
+    let mut context = context_manager.snapshot_with_commands(shell_manager.recent_command_records(3));
+    context.screen = Some(screen);
+    assistant.push_user_message(question.clone());
+    assistant.start_assistant_message();
+    ai_sessions.send_message(session_id, &question, context);
+}
+
+/// Open the attachment picker on the recent commands whose output is over
+/// the message budget, newest first, each with what sending it whole takes.
+fn open_attach_picker(
+    assistant: &mut TuiAssistant,
+    context_manager: &crate::context::ContextManager,
+    shell_manager: &ShellManager,
+) {
+    let records: Vec<_> = shell_manager
+        .recent_command_records(10)
+        .into_iter()
+        .rev()
+        .filter_map(|record| {
+            let output = context_manager.full_output(&record);
+            if output.len() <= OUTPUT_BUDGET {
+                return None;
+            }
+            let description = OutputParts::split(&record.command_line, &output).describe();
+            Some((record, description))
+        })
+        .collect();
+    if records.is_empty() {
+        assistant.show_notice(format!("no recent command has more than {} KB of output", OUTPUT_BUDGET / 1024));
+    } else {
+        assistant.open_attach_picker(records);
+    }
+}
+
+/// Ask the active session's latest question again, with `steer` appended.
+///
+/// The old question and reply stay on display, greyed out; if the reply
+/// can't be regenerated the reason is shown instead.
+pub fn regenerate(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager, steer: Option<Steer>) {
+    let session_id = assistant.active_session_id();
+    if let Err(e) = ai_sessions.regenerate(session_id, steer) {
+        assistant.push_error_message(e.to_string());
+    }
+}
+
+/// Load the question under the visual cursor into the input, to be edited
+/// and asked in its place. Does nothing if the cursor is not on a question.
+pub fn edit_question(assistant: &mut TuiAssistant, ai_sessions: &AiSessionManager) {
+    let Some((index, shown)) = assistant.question_at_visual_cursor() else {
+        return;
+    };
+    // The view can be behind the session: the question has to still be there
+    let question = ai_sessions
+        .user_message(assistant.active_session_id(), index)
+        .filter(|question| question == shown);
+    match question {
+        Some(question) => assistant.start_editing(index, question),
+        None => assistant.show_notice(EditError::NoQuestion.to_string()),
+    }
+}
+
+/// Write the active session to a new file in `dir`, named after the time,
+/// and say where in the pane status.
+pub fn export_session(assistant: &mut TuiAssistant, ai_sessions: &AiSessionManager, dir: &Path, format: ExportFormat) {
+    let session_id = assistant.active_session_id();
+    if ai_sessions.user_message(session_id, 0).is_none() {
+        assistant.show_notice("nothing to export yet".to_string());
+        return;
+    }
+    let Some(export) = ai_sessions.export_session(session_id, format) else {
+        return;
+    };
+    let stem = format!("rusty-term-session-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+    let written = persist::create_unique(dir, &stem, format.extension())
+        .and_then(|(path, mut file)| file.write_all(export.as_bytes()).map(|()| path));
+    match written {
+        Ok(path) => assistant.show_notice(format!("Exported to {}", path.display())),
+        Err(e) => assistant.push_error_message(format!("Cannot export to {}: {}", dir.display(), e)),
+    }
+}
+
+/// Open the JSON export at `file` (relative to `dir`, the shell's directory)
+/// in a new session. A file that can't be read or imported leaves an error
+/// card in the session shown.
+pub fn import_session(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager, dir: &Path, file: &str) {
+    let path = dir.join(crate::config::expand_home(Path::new(file)).unwrap_or_else(|| file.into()));
+    let imported = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))
+        .and_then(|json| ai_sessions.import_session(&json).map_err(|e| e.to_string()));
+    match imported {
+        Ok(_) => assistant.show_notice(format!("Imported {}", path.display())),
+        Err(e) => assistant.push_error_message(e),
+    }
+}
+
 /// Handle command mode keys specific to Assistant pane.
 ///
 /// Returns true if the event was handled.
 pub fn handle_command_mode(
     assistant: &mut TuiAssistant,
     ai_sessions: &mut AiSessionManager,
+    context_manager: &crate::context::ContextManager,
     event: UserEvent,
 ) -> Result<bool> {
     match event {
-        // t => create new AI session
+        // t => create new AI session (or pick a template if any are configured)
         UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('t') | KeyCode::Char('T')) => {
-            if let Ok(new_id) = ai_sessions.new_session() {
-                assistant.switch_session(new_id);
-                assistant.load_messages(vec![]);
-                assistant.sync_session_tabs(ai_sessions.get_session_tabs());
-            }
+            request_new_session(assistant, ai_sessions);
+            Ok(true)
+        }
+
+        // r => ask the latest question again (R records, as in the terminal)
+        UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('r')) => {
+            regenerate(assistant, ai_sessions, None);
             Ok(true)
         }
 
         // w => close current AI session
         UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('w') | KeyCode::Char('W')) => {
-            let current_id = assistant.active_session_id();
-            if let Some(new_id) = ai_sessions.close_session(current_id) {
-                assistant.switch_session(new_id);
-                let messages = ai_sessions.get_session_messages(new_id);
-                assistant.load_messages(messages);
-                assistant.sync_session_tabs(ai_sessions.get_session_tabs());
+            ai_sessions.close_session(assistant.active_session_id());
+            Ok(true)
+        }
+
+        // h => rename the current session: "/rename <its name>" to edit
+        UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('h') | KeyCode::Char('H')) => {
+            if assistant.get_input().is_empty() {
+                let session_id = assistant.active_session_id();
+                let name = ai_sessions.get_session_tabs().into_iter().find(|tab| tab.id == session_id).map(|tab| tab.name);
+                assistant.restore_unsent_input(format!("/rename {}", name.unwrap_or_default()));
+            } else {
+                assistant.show_notice("type /rename <name> to rename the session".to_string());
+            }
+            Ok(true)
+        }
+
+        // ! => edit the session's system prompt, or pick a prompt template
+        UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('!')) => {
+            let templates = ai_sessions.prompt_templates();
+            if templates.is_empty() {
+                edit_system_prompt(assistant, ai_sessions);
+            } else {
+                assistant.open_prompt_picker(templates.names());
+            }
+            Ok(true)
+        }
+
+        // > => write the session to a Markdown file in the shell's directory
+        UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('>')) => {
+            export_session(assistant, ai_sessions, Path::new(&context_manager.cwd.path), ExportFormat::Markdown);
+            Ok(true)
+        }
+
+        // < => open an exported session: "/import " to type its file after
+        UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('<')) => {
+            if assistant.get_input().is_empty() {
+                assistant.restore_unsent_input("/import ".to_string());
+            } else {
+                assistant.show_notice("type /import <file> to open an exported session".to_string());
             }
             Ok(true)
         }
 
+        // u => say what the underline under quoted context means, or stop
+        UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('u') | KeyCode::Char('U')) => {
+            assistant.toggle_quote_legend();
+            Ok(true)
+        }
+
         // ] => next AI session
         UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char(']')) => {
             if let Some(new_id) = ai_sessions.next_session_id() {
                 ai_sessions.switch_session(new_id);
-                assistant.switch_session(new_id);
-                let messages = ai_sessions.get_session_messages(new_id);
-                assistant.load_messages(messages);
-                assistant.sync_session_tabs(ai_sessions.get_session_tabs());
             }
             Ok(true)
         }
@@ -312,10 +1037,6 @@ pub fn handle_command_mode(
         UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('[')) => {
             if let Some(new_id) = ai_sessions.prev_session_id() {
                 ai_sessions.switch_session(new_id);
-                assistant.switch_session(new_id);
-                let messages = ai_sessions.get_session_messages(new_id);
-                assistant.load_messages(messages);
-                assistant.sync_session_tabs(ai_sessions.get_session_tabs());
             }
             Ok(true)
         }
@@ -323,3 +1044,1202 @@ pub fn handle_command_mode(
         _ => Ok(false),
     }
 }
+
+/// Bring the pane up to date with the changes the session manager queued
+/// (see [`SessionEvent`]): the session shown, its conversation and the tabs.
+///
+/// Handlers only change the sessions; the app calls this after each event,
+/// so every way of changing them ends with the pane showing the same thing.
+pub fn sync_sessions(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager) {
+    let events = ai_sessions.take_events();
+    if events.is_empty() {
+        return;
+    }
+    let active = ai_sessions.current_session_id();
+    let switched = assistant.active_session_id() != active;
+    if switched {
+        assistant.switch_session(active);
+    } else if events.contains(&SessionEvent::SessionCleared { session_id: active }) {
+        assistant.clear_session();
+    }
+    if switched || events.contains(&SessionEvent::MessagesChanged { session_id: active }) {
+        assistant.load_messages(ai_sessions.get_session_messages(active));
+        // A question was just sent: its reply is on the way
+        if ai_sessions.is_replying(active) && !assistant.is_streaming() {
+            assistant.start_assistant_message();
+        }
+    }
+    if switched || events.contains(&SessionEvent::QueueChanged { session_id: active }) {
+        assistant.set_queued(ai_sessions.queued_messages(active));
+    }
+    assistant.sync_session_tabs(ai_sessions.get_session_tabs());
+}
+
+/// Start creating a new session.
+///
+/// With no templates configured this creates a blank session right away;
+/// otherwise it opens the picker listing "Blank" plus the templates.
+pub fn request_new_session(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager) {
+    if ai_sessions.templates().is_empty() {
+        create_session(assistant, ai_sessions, 0);
+        return;
+    }
+    let names = ai_sessions.templates().iter().map(|t| t.name.clone()).collect();
+    assistant.open_template_picker(names);
+}
+
+/// Create a session from a picker entry (0 = Blank, i = template i - 1) and show it.
+fn create_session(assistant: &mut TuiAssistant, ai_sessions: &mut AiSessionManager, entry: usize) {
+    let result = match entry.checked_sub(1).and_then(|i| ai_sessions.templates().get(i)) {
+        Some(template) => {
+            let template = template.clone();
+            ai_sessions.new_session_from_template(&template)
+        }
+        None => ai_sessions.new_session(),
+    };
+
+    if let Err(e) = result {
+        assistant.push_error_message(format!("Failed to create session: {}", e));
+    }
+}
+
+/// Handle a key while the new-session picker is open.
+fn handle_template_picker_key(
+    assistant: &mut TuiAssistant,
+    ai_sessions: &mut AiSessionManager,
+    key_evt: KeyEvent,
+) -> Result<()> {
+    match key_evt.code {
+        KeyCode::Up | KeyCode::Char('k') => assistant.move_template_selection(-1),
+        KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => assistant.move_template_selection(1),
+        KeyCode::Enter => {
+            if let Some(entry) = assistant.take_template_selection() {
+                create_session(assistant, ai_sessions, entry);
+            }
+        }
+        // 1-9 => choose an entry directly
+        KeyCode::Char(c @ '1'..='9') => {
+            let index = c as usize - '1' as usize;
+            if let Some(entry) = assistant.take_template_entry(index) {
+                create_session(assistant, ai_sessions, entry);
+            }
+        }
+        KeyCode::Esc | KeyCode::Char('q') => assistant.close_template_picker(),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Fill the input with "/prompt" and the session's system prompt, to edit.
+fn edit_system_prompt(assistant: &mut TuiAssistant, ai_sessions: &AiSessionManager) {
+    if assistant.get_input().is_empty() {
+        let system_prompt = ai_sessions.session_system_prompt(assistant.active_session_id()).unwrap_or_default();
+        assistant.restore_unsent_input(format!("/prompt {}", system_prompt));
+    } else {
+        assistant.show_notice("type /prompt <text> to change the session's system prompt".to_string());
+    }
+}
+
+/// Handle a key while the system prompt picker is open.
+fn handle_prompt_picker_key(
+    assistant: &mut TuiAssistant,
+    ai_sessions: &mut AiSessionManager,
+    context_manager: &crate::context::ContextManager,
+    key_evt: KeyEvent,
+) {
+    let entry = match key_evt.code {
+        KeyCode::Up | KeyCode::Char('k') => {
+            assistant.move_prompt_selection(-1);
+            None
+        }
+        KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => {
+            assistant.move_prompt_selection(1);
+            None
+        }
+        KeyCode::Enter => assistant.take_prompt_entry(None),
+        // 1-9 => choose an entry directly
+        KeyCode::Char(c @ '1'..='9') => assistant.take_prompt_entry(Some(c as usize - '1' as usize)),
+        KeyCode::Esc | KeyCode::Char('q') => {
+            assistant.close_prompt_picker();
+            None
+        }
+        _ => None,
+    };
+    match entry {
+        Some(None) => edit_system_prompt(assistant, ai_sessions),
+        Some(Some(name)) if ai_sessions.apply_template(assistant.active_session_id(), &name, &context_manager.snapshot()) => {
+            assistant.show_notice(format!("System prompt of this session set from \"{}\"", name));
+        }
+        _ => {}
+    }
+}
+
+/// Handle a key while the regenerate menu is open.
+fn handle_regen_menu_key(
+    assistant: &mut TuiAssistant,
+    ai_sessions: &mut AiSessionManager,
+    key_evt: KeyEvent,
+) -> Result<()> {
+    let entry = match key_evt.code {
+        KeyCode::Up | KeyCode::Char('k') => {
+            assistant.move_regen_selection(-1);
+            None
+        }
+        KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => {
+            assistant.move_regen_selection(1);
+            None
+        }
+        KeyCode::Enter => assistant.take_regen_entry(None),
+        // 1-9 => choose an entry directly
+        KeyCode::Char(c @ '1'..='9') => assistant.take_regen_entry(Some(c as usize - '1' as usize)),
+        KeyCode::Esc | KeyCode::Char('q') => {
+            assistant.close_regen_menu();
+            None
+        }
+        _ => None,
+    };
+    if let Some(steer) = entry {
+        regenerate(assistant, ai_sessions, steer);
+    }
+    Ok(())
+}
+
+/// Handle a key while a dropped file is offered for attaching.
+fn handle_drop_offer_key(assistant: &mut TuiAssistant, key_evt: KeyEvent) {
+    match key_evt.code {
+        KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => assistant.accept_drop(),
+        KeyCode::Char('p') | KeyCode::Char('P') => assistant.paste_dropped_path(),
+        KeyCode::Esc | KeyCode::Char('n') | KeyCode::Char('N') => assistant.dismiss_drop(),
+        _ => {}
+    }
+}
+
+/// Handle a key while the attachment picker is open.
+fn handle_attach_picker_key(assistant: &mut TuiAssistant, key_evt: KeyEvent) -> Result<()> {
+    match key_evt.code {
+        KeyCode::Up | KeyCode::Char('k') => assistant.move_attach_selection(-1),
+        KeyCode::Down | KeyCode::Char('j') | KeyCode::Tab => assistant.move_attach_selection(1),
+        KeyCode::Enter => assistant.take_attach_entry(None),
+        // 1-9 => choose an entry directly
+        KeyCode::Char(c @ '1'..='9') => assistant.take_attach_entry(Some(c as usize - '1' as usize)),
+        KeyCode::Esc | KeyCode::Char('q') => assistant.close_attach_picker(),
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+    use crate::ai::session::{ScriptedReplies, SessionId};
+    use crate::context::ContextManager;
+    use crate::event::{init_app_eventsource, AiStreamData, AiUiUpdate, AppEvent, Stamped};
+    use tokio::sync::mpsc::UnboundedReceiver;
+    use crate::shell::ScriptedShell;
+    use crate::ui::assistant::{ChatMessage, CommandStatus};
+
+    struct Pane {
+        assistant: TuiAssistant,
+        ai: AiSessionManager,
+        replies: ScriptedReplies,
+        context: ContextManager,
+        shell: ShellManager,
+        /// What the pane asked the app to do
+        app_events: UnboundedReceiver<Stamped<AppEvent>>,
+    }
+
+    impl Pane {
+        fn new() -> Result<Self, Box<dyn std::error::Error>> {
+            let (event_sink, app_events) = init_app_eventsource();
+            let (shell, _, _) = ScriptedShell::new(event_sink.clone());
+            let mut ai = AiSessionManager::new(event_sink, "gpt-4o-mini")?;
+            let replies = ai.script_replies();
+            Ok(Self { assistant: TuiAssistant::new(), ai, replies, context: ContextManager::new(), shell, app_events })
+        }
+
+        fn press(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Result<()> {
+            let key = KeyEvent::new(code, modifiers);
+            handle_key_event(&mut self.assistant, &mut self.ai, &self.context, &self.shell, key)?;
+            sync_sessions(&mut self.assistant, &mut self.ai);
+            Ok(())
+        }
+
+        fn type_text(&mut self, text: &str) -> Result<()> {
+            text.chars().try_for_each(|c| self.press(KeyCode::Char(c), KeyModifiers::NONE))
+        }
+
+        fn interrupt(&mut self) -> Interrupt {
+            interrupt(&mut self.assistant, &mut self.ai)
+        }
+
+        /// Apply queued stream data, returning the UI updates it produced.
+        fn drain(&mut self) -> Vec<AiUiUpdate> {
+            let mut updates = Vec::new();
+            while let Some(update) = self.ai.recv_ai_stream().now_or_never() {
+                if let Some(update) = update {
+                    self.assistant.handle_ai_update(update.event.clone());
+                    updates.push(update.event);
+                }
+            }
+            sync_sessions(&mut self.assistant, &mut self.ai);
+            updates
+        }
+    }
+
+    #[test]
+    fn test_ctrl_c_copies_input_selection_first() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.type_text("hello")?;
+        pane.assistant.select_all_input();
+        pane.assistant.enter_visual_mode();
+        pane.assistant.handle_visual_key(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE));
+        assert_eq!(pane.interrupt(), Interrupt::CopiedInput);
+        assert_eq!(pane.assistant.get_input(), "hello");
+        assert!(pane.assistant.is_visual_selecting());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctrl_c_copies_visual_selection_before_clearing() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.type_text("hello")?;
+        pane.assistant.enter_visual_mode();
+        pane.assistant.handle_visual_key(KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE));
+        assert_eq!(pane.interrupt(), Interrupt::CopiedMessages);
+        assert_eq!(pane.assistant.get_input(), "hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctrl_c_clears_input_and_ctrl_z_restores_it() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.type_text("hello")?;
+        pane.press(KeyCode::Left, KeyModifiers::NONE)?;
+        pane.press(KeyCode::Char('c'), KeyModifiers::CONTROL)?;
+        assert_eq!(pane.assistant.get_input(), "");
+        assert_eq!(pane.assistant.get_pane_status().key_hint, None);
+
+        pane.press(KeyCode::Char('z'), KeyModifiers::CONTROL)?;
+        assert_eq!(pane.assistant.get_input(), "hello");
+        assert_eq!(pane.assistant.cursor_position(), 4);
+        assert_eq!(pane.assistant.get_pane_status().key_hint, Some("Ctrl+C: Clear"));
+        assert!(!pane.assistant.take_suspend_request());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctrl_z_with_nothing_to_restore_suspends() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.type_text("hello")?;
+        pane.press(KeyCode::Char('z'), KeyModifiers::CONTROL)?;
+        assert_eq!(pane.assistant.get_input(), "hello");
+        assert!(pane.assistant.take_suspend_request());
+        // Taken once
+        assert!(!pane.assistant.take_suspend_request());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctrl_c_cancels_streaming_reply() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        pane.type_text("explain tar")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_pane_status().key_hint, Some("Ctrl+C: Cancel"));
+
+        pane.replies.send(AiStreamData::Chunk { session_id, text: "tar packs ".into() })?;
+        pane.drain();
+        // Queued but not yet applied when the user gives up
+        pane.replies.send(AiStreamData::Chunk { session_id, text: "files".into() })?;
+        assert_eq!(pane.interrupt(), Interrupt::CancelledStream);
+
+        let updates = pane.drain();
+        assert!(matches!(updates.as_slice(), [AiUiUpdate::Warning { .. }, AiUiUpdate::End { .. }]));
+        assert!(!pane.assistant.is_streaming());
+        let messages = pane.ai.get_session_messages(session_id);
+        assert!(messages.iter().any(|m| matches!(m, crate::ui::assistant::ChatMessage::Assistant { text, .. } if text == "tar packs ")));
+
+        // Nothing is streaming any more
+        assert_eq!(pane.interrupt(), Interrupt::Nothing);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctrl_t_asks_for_a_rewrite_for_the_target_shell() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.assistant.set_shell(crate::shell::ShellKind::Fish);
+        let session_id = pane.assistant.active_session_id();
+        pane.type_text("set my editor")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        let args = serde_json::json!({"command": "export EDITOR=vim", "explanation": "Set the editor", "risk_level": "low"});
+        let tool_calls = vec![("call_1".to_string(), "suggest_command".to_string(), args.to_string())];
+        pane.replies.send(AiStreamData::ToolCalls { session_id, tool_calls })?;
+        pane.replies.send(AiStreamData::End { session_id, cached: false })?;
+        pane.drain();
+        assert!(pane.assistant.pending_incompatible_command().is_some());
+
+        pane.press(KeyCode::Char('t'), KeyModifiers::CONTROL)?;
+        let asked = pane.replies.asked();
+        assert_eq!(asked.last().map(|(_, q)| q.as_str()), Some("Rewrite this command for fish: `export EDITOR=vim`"));
+        assert!(!pane.ai.has_pending_suggestion(session_id));
+        assert!(pane.assistant.is_streaming());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctrl_t_ignores_compatible_commands() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.assistant.set_shell(crate::shell::ShellKind::Fish);
+        let session_id = pane.assistant.active_session_id();
+        pane.type_text("list files")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        let args = serde_json::json!({"command": "ls -la", "explanation": "List files", "risk_level": "low"});
+        let tool_calls = vec![("call_1".to_string(), "suggest_command".to_string(), args.to_string())];
+        pane.replies.send(AiStreamData::ToolCalls { session_id, tool_calls })?;
+        pane.replies.send(AiStreamData::End { session_id, cached: false })?;
+        pane.drain();
+
+        pane.press(KeyCode::Char('t'), KeyModifiers::CONTROL)?;
+        assert_eq!(pane.replies.asked().len(), 1);
+        assert!(pane.ai.has_pending_suggestion(session_id));
+        Ok(())
+    }
+
+    #[test]
+    fn test_i_toggles_findings_until_something_is_typed() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        pane.type_text("clean up")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        let args = serde_json::json!({"command": "rm -r build; ls", "explanation": "Clean", "risk_level": "low"});
+        let tool_calls = vec![("call_1".to_string(), "suggest_command".to_string(), args.to_string())];
+        pane.replies.send(AiStreamData::ToolCalls { session_id, tool_calls })?;
+        pane.replies.send(AiStreamData::End { session_id, cached: false })?;
+        pane.drain();
+
+        let expanded = |pane: &Pane| {
+            pane.assistant.messages().iter().any(|m| {
+                matches!(m, ChatMessage::CommandCard { findings_expanded: true, .. })
+            })
+        };
+        pane.press(KeyCode::Char('i'), KeyModifiers::NONE)?;
+        assert!(expanded(&pane));
+        assert_eq!(pane.assistant.get_input(), "");
+        pane.press(KeyCode::Char('i'), KeyModifiers::NONE)?;
+        assert!(!expanded(&pane));
+
+        // Once typing has started, 'i' is just a letter
+        pane.type_text("wh")?;
+        pane.press(KeyCode::Char('i'), KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_input(), "whi");
+        assert!(!expanded(&pane));
+        Ok(())
+    }
+
+    #[test]
+    fn test_each_session_keeps_its_own_draft() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let first = pane.assistant.active_session_id();
+        pane.type_text("why is the build slow")?;
+        pane.press(KeyCode::Left, KeyModifiers::NONE)?;
+        pane.press(KeyCode::Left, KeyModifiers::NONE)?;
+
+        request_new_session(&mut pane.assistant, &mut pane.ai);
+        sync_sessions(&mut pane.assistant, &mut pane.ai);
+        let second = pane.assistant.active_session_id();
+        assert_ne!(first, second);
+        assert_eq!(pane.assistant.get_input(), "");
+        pane.type_text("list open ports")?;
+        pane.press(KeyCode::Home, KeyModifiers::NONE)?;
+
+        pane.press(KeyCode::Tab, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.active_session_id(), first);
+        assert_eq!(pane.assistant.get_input(), "why is the build slow");
+        assert_eq!(pane.assistant.cursor_position(), "why is the build sl".len());
+
+        pane.press(KeyCode::Tab, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.active_session_id(), second);
+        assert_eq!(pane.assistant.get_input(), "list open ports");
+        assert_eq!(pane.assistant.cursor_position(), 0);
+
+        // Both drafts are saved; sending one clears it
+        let saved = pane.assistant.saved_drafts();
+        assert_eq!(saved.active, Some(second));
+        assert_eq!(saved.drafts.len(), 2);
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        let saved = pane.assistant.saved_drafts();
+        assert_eq!(saved.drafts.keys().copied().collect::<Vec<_>>(), vec![first]);
+        Ok(())
+    }
+
+    /// Ask something and get a card that waits for Ctrl+Y or Ctrl+N.
+    fn pending_card(pane: &mut Pane) -> Result<SessionId, Box<dyn std::error::Error>> {
+        let session_id = pane.assistant.active_session_id();
+        pane.type_text("list files")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        let args = serde_json::json!({"command": "ls -la", "explanation": "List", "risk_level": "low"});
+        let tool_calls = vec![("call_1".to_string(), "suggest_command".to_string(), args.to_string())];
+        pane.replies.send(AiStreamData::ToolCalls { session_id, tool_calls })?;
+        pane.replies.send(AiStreamData::End { session_id, cached: false })?;
+        pane.drain();
+        assert!(pane.ai.has_pending_suggestion(session_id));
+        Ok(session_id)
+    }
+
+    #[test]
+    fn test_h_fills_in_a_rename_of_the_session() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        let name = |pane: &Pane| pane.ai.get_session_tabs().into_iter().find(|tab| tab.id == session_id).map(|tab| tab.name);
+        let default_name = name(&pane);
+
+        let h = UserEvent::Key(KeyEvent::new(KeyCode::Char('h'), KeyModifiers::NONE));
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, h.clone())?);
+        assert_eq!(Some(pane.assistant.get_input().to_string()), default_name.as_ref().map(|n| format!("/rename {}", n)));
+        pane.assistant.take_input();
+        pane.type_text("/rename  disk   cleanup ")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(name(&pane).as_deref(), Some("disk cleanup"));
+        assert!(pane.replies.asked().is_empty());
+        assert!(pane.assistant.messages().is_empty());
+
+        // Something typed already stays put
+        pane.type_text("why")?;
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, h)?);
+        assert_eq!(pane.assistant.get_input(), "why");
+
+        // No name at all brings back the numbered one
+        pane.assistant.take_input();
+        pane.type_text("/rename")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(name(&pane), default_name);
+        Ok(())
+    }
+
+    #[test]
+    fn test_closing_the_last_session_clears_what_it_showed() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pending_card(&mut pane)?;
+        pane.type_text("and hidden")?;
+        pane.press(KeyCode::Left, KeyModifiers::SHIFT)?;
+        pane.assistant.enter_visual_mode();
+        assert!(pane.assistant.has_pending_command());
+
+        // The close button of the only tab does what `w` does
+        let w = UserEvent::Key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::NONE));
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, w)?);
+        sync_sessions(&mut pane.assistant, &mut pane.ai);
+        assert_eq!(pane.assistant.active_session_id(), session_id);
+        assert!(pane.assistant.messages().is_empty());
+        assert!(!pane.assistant.has_pending_command());
+        assert_eq!(pane.assistant.suggestion_pagination(), None);
+        assert!(!pane.assistant.is_visual_mode());
+        assert_eq!(pane.assistant.get_input(), "");
+        assert_eq!(pane.assistant.get_input_selection_range(), None);
+
+        // Ctrl+Y has nothing left to run
+        pane.press(KeyCode::Char('y'), KeyModifiers::CONTROL)?;
+        assert!(pane.assistant.messages().is_empty());
+        assert!(!pane.ai.has_pending_suggestion(session_id));
+        assert_eq!(pane.ai.accept_suggestion(session_id, "call_1"), Err(SuggestionError::NotPending));
+        Ok(())
+    }
+
+    #[test]
+    fn test_comparison_accepts_the_highlighted_suggestion() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        pane.type_text("disk usage")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        let tool_calls = ["du -sh .", "du -sh --max-depth=1 .", "ncdu ."]
+            .iter()
+            .enumerate()
+            .map(|(i, command)| {
+                let args = serde_json::json!({"command": command, "explanation": "Size", "risk_level": "low"});
+                (format!("call_{}", i + 1), "suggest_command".to_string(), args.to_string())
+            })
+            .collect();
+        pane.replies.send(AiStreamData::ToolCalls { session_id, tool_calls })?;
+        pane.replies.send(AiStreamData::End { session_id, cached: false })?;
+        pane.drain();
+
+        pane.press(KeyCode::Char('c'), KeyModifiers::NONE)?;
+        assert!(pane.assistant.is_comparing());
+        pane.press(KeyCode::Char('j'), KeyModifiers::NONE)?;
+        pane.press(KeyCode::Down, KeyModifiers::NONE)?;
+        pane.press(KeyCode::Char('k'), KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_input(), "");
+        pane.press(KeyCode::Char('y'), KeyModifiers::CONTROL)?;
+
+        // The highlighted one ran; the others are ignored, as on one card
+        let ran = std::iter::from_fn(|| pane.app_events.try_recv().ok()).find_map(|event| match event.event {
+            AppEvent::ExecuteAiCommand { command, .. } => Some(command),
+            _ => None,
+        });
+        assert_eq!(ran.as_deref(), Some("du -sh --max-depth=1 ."));
+        assert!(!pane.ai.has_pending_suggestion(session_id));
+        let statuses: Vec<_> = pane
+            .ai
+            .get_session_messages(session_id)
+            .into_iter()
+            .filter_map(|msg| match msg {
+                ChatMessage::CommandCard { status, .. } => Some(status),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(statuses, [CommandStatus::Rejected, CommandStatus::Executed, CommandStatus::Rejected]);
+        assert!(!pane.assistant.is_comparing());
+        Ok(())
+    }
+
+    #[test]
+    fn test_enter_with_blank_input_sends_nothing() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert!(pane.assistant.prompt_flash_until().is_some());
+        pane.type_text("  ")?;
+        assert_eq!(pane.assistant.get_pane_status().enter_hint, None);
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        // Kept as typed, and nothing asked
+        assert_eq!(pane.assistant.get_input(), "  ");
+        assert!(pane.replies.asked().is_empty());
+
+        // A context-free question with no question in it neither
+        pane.type_text("? ")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert!(pane.replies.asked().is_empty());
+        assert!(pane.assistant.prompt_flash_until().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_question_without_an_api_key_stays_in_the_input() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let (event_sink, _) = init_app_eventsource();
+        pane.ai = AiSessionManager::new(event_sink, "gpt-4o-mini")?;
+        pane.ai.set_provider(crate::ai::provider::from_config(crate::ai::provider::ProviderKind::OpenAI, None, Some("")));
+        pane.type_text("list big files")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_input(), "list big files");
+        assert!(!pane.assistant.is_streaming());
+        let rejected = pane.assistant.messages().last();
+        assert!(matches!(rejected, Some(ChatMessage::Error { text }) if text.starts_with("Not sent: No API key found — set OPENAI_API_KEY")));
+
+        // Local answers need no key
+        pane.assistant.take_input();
+        pane.type_text("=2+3")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert!(matches!(pane.assistant.messages().last(), Some(ChatMessage::Local { text }) if text == "5"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_enter_while_streaming_queues_the_question() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        pane.type_text("explain tar")?;
+        assert_eq!(pane.assistant.get_pane_status().enter_hint, Some("Enter: Send"));
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert!(pane.assistant.is_streaming());
+
+        // Commands of the pane still wait for the reply
+        pane.type_text("/rename tar")?;
+        assert_eq!(pane.assistant.get_pane_status().enter_hint, Some("Enter: Reply streaming"));
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_input(), "/rename tar");
+        let status = pane.assistant.get_pane_status().title_status.unwrap_or_default();
+        assert!(status.contains("reply still streaming"), "{}", status);
+        pane.assistant.take_input();
+
+        // Questions go in the queue, and the reply streams on
+        pane.type_text("and gzip")?;
+        assert_eq!(pane.assistant.get_pane_status().enter_hint, Some("Enter: Queue"));
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_input(), "");
+        assert_eq!(pane.ai.queued_messages(session_id), ["and gzip"]);
+        pane.replies.send(AiStreamData::Chunk { session_id, text: "tar packs files".into() })?;
+        pane.drain();
+        assert!(matches!(pane.assistant.messages().last(), Some(ChatMessage::Assistant { text, is_streaming: true, .. }) if text == "tar packs files"));
+        assert_eq!(pane.replies.asked().len(), 1);
+
+        // It is sent as the reply ends
+        pane.replies.send(AiStreamData::End { session_id, cached: false })?;
+        pane.drain();
+        let asked: Vec<_> = pane.replies.asked().into_iter().map(|(_, text)| text).collect();
+        assert_eq!(asked, ["explain tar", "and gzip"]);
+        assert!(pane.ai.queued_messages(session_id).is_empty());
+        assert!(pane.assistant.is_streaming());
+        let questions: Vec<_> = pane
+            .assistant
+            .messages()
+            .iter()
+            .filter_map(|m| match m {
+                ChatMessage::User { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(questions, ["explain tar", "and gzip"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctrl_c_puts_queued_questions_back_in_the_input() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        pane.type_text("explain tar")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        for question in ["and gzip", "and zstd"] {
+            pane.type_text(question)?;
+            pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        }
+        assert_eq!(pane.ai.queued_messages(session_id).len(), 2);
+
+        assert_eq!(pane.interrupt(), Interrupt::CancelledStream);
+        pane.drain();
+        assert!(!pane.assistant.is_streaming());
+        assert_eq!(pane.assistant.get_input(), "and gzip\n\nand zstd");
+        assert!(pane.ai.queued_messages(session_id).is_empty());
+        assert_eq!(pane.replies.asked().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_enter_with_a_card_waiting_asks_to_answer_it() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pending_card(&mut pane)?;
+
+        // Nothing typed: Enter does nothing at all
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.prompt_flash_until(), None);
+        assert!(pane.ai.has_pending_suggestion(session_id));
+
+        // A question waits for the card
+        pane.type_text("and hidden ones?")?;
+        assert_eq!(pane.assistant.get_pane_status().enter_hint, Some("Enter: Answer card first"));
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.replies.asked().len(), 1);
+        assert_eq!(pane.assistant.get_input(), "and hidden ones?");
+        assert!(pane.ai.has_pending_suggestion(session_id));
+        let status = pane.assistant.get_pane_status().title_status.unwrap_or_default();
+        assert!(status.contains("answer the card first"), "{}", status);
+
+        // Local answers leave the card alone
+        pane.press(KeyCode::Char('c'), KeyModifiers::CONTROL)?;
+        pane.type_text("=6*7")?;
+        assert_eq!(pane.assistant.get_pane_status().enter_hint, Some("Enter: Send"));
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.replies.asked().len(), 1);
+        assert!(pane.ai.has_pending_suggestion(session_id));
+        Ok(())
+    }
+
+    #[test]
+    fn test_enter_can_reject_the_waiting_card_and_send() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.assistant.set_enter_rejects_pending(true);
+        let session_id = pending_card(&mut pane)?;
+        pane.type_text("and hidden ones?")?;
+        assert_eq!(pane.assistant.get_pane_status().enter_hint, Some("Enter: Reject card & send"));
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.replies.asked().last().map(|(_, q)| q.as_str()), Some("and hidden ones?"));
+        assert!(!pane.ai.has_pending_suggestion(session_id));
+        assert_eq!(pane.assistant.get_input(), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctrl_c_with_nothing_to_act_on() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        assert_eq!(pane.interrupt(), Interrupt::Nothing);
+        pane.press(KeyCode::Char('c'), KeyModifiers::CONTROL)?;
+        assert_eq!(pane.assistant.get_input(), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_vi_edits_share_the_undo_stack() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.assistant.set_vi_editing(true);
+        pane.type_text("git 変更\nlog -n 5")?;
+        pane.press(KeyCode::Esc, KeyModifiers::NONE)?;
+        assert!(pane.assistant.is_vi_normal_mode());
+        assert_eq!(pane.assistant.cursor_position(), "git 変更\nlog -n ".len());
+
+        // Up a row, then change the wide word
+        pane.type_text("kciwdiff")?;
+        assert!(!pane.assistant.is_vi_normal_mode());
+        pane.press(KeyCode::Esc, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_input(), "git diff\nlog -n 5");
+        pane.type_text("jddA --stat")?;
+        pane.press(KeyCode::Esc, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_input(), "git diff --stat");
+
+        // `u` and Ctrl+Z take back the same changes, newest first
+        pane.type_text("u")?;
+        assert_eq!(pane.assistant.get_input(), "git diff");
+        pane.press(KeyCode::Char('z'), KeyModifiers::CONTROL)?;
+        assert_eq!(pane.assistant.get_input(), "git diff\nlog -n 5");
+        pane.type_text("u")?;
+        assert_eq!(pane.assistant.get_input(), "git 変更\nlog -n 5");
+        Ok(())
+    }
+
+    #[test]
+    fn test_esc_in_vi_normal_mode_keeps_its_pane_meaning() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        pane.assistant.set_vi_editing(true);
+        pane.type_text("one two")?;
+        pane.press(KeyCode::Esc, KeyModifiers::NONE)?;
+        pane.press(KeyCode::Char('0'), KeyModifiers::NONE)?;
+
+        // Esc drops a half-typed command first
+        pane.type_text("d")?;
+        pane.press(KeyCode::Esc, KeyModifiers::NONE)?;
+        pane.type_text("w")?;
+        assert_eq!((pane.assistant.get_input(), pane.assistant.cursor_position()), ("one two", 4));
+
+        // Then it clears the selection, as it does without vi editing
+        pane.press(KeyCode::Char('a'), KeyModifiers::CONTROL)?;
+        assert!(pane.assistant.has_input_selection());
+        pane.press(KeyCode::Esc, KeyModifiers::NONE)?;
+        assert!(!pane.assistant.has_input_selection());
+        assert!(pane.assistant.is_vi_normal_mode());
+
+        // A sent input leaves the next one in insert mode
+        pane.assistant.take_input();
+        assert!(!pane.assistant.is_vi_normal_mode());
+        Ok(())
+    }
+
+    // ------------------------------------------------------------------
+    // Parity: what streamed in is what loading the session again shows
+    // ------------------------------------------------------------------
+
+    type Scenario = fn(&mut Pane, SessionId) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Ask `question` and let `reply` stream in.
+    fn exchange(pane: &mut Pane, question: &str, reply: Vec<AiStreamData>) -> Result<(), Box<dyn std::error::Error>> {
+        pane.type_text(question)?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        for data in reply {
+            pane.replies.send(data)?;
+        }
+        pane.drain();
+        Ok(())
+    }
+
+    fn chunk(session_id: SessionId, text: &str) -> AiStreamData {
+        AiStreamData::Chunk { session_id, text: text.to_string() }
+    }
+
+    fn end(session_id: SessionId) -> AiStreamData {
+        AiStreamData::End { session_id, cached: false }
+    }
+
+    fn failure(session_id: SessionId) -> AiStreamData {
+        AiStreamData::Error { session_id, error: "API error: overloaded".to_string() }
+    }
+
+    /// Tool calls as (id, command); a command of None is a call to a tool
+    /// that suggests nothing.
+    fn calls(session_id: SessionId, calls: &[(&str, Option<&str>)]) -> AiStreamData {
+        let tool_calls = calls
+            .iter()
+            .map(|(id, command)| match command {
+                Some(command) => {
+                    let args = serde_json::json!({"command": command, "explanation": "Step", "risk_level": "low"});
+                    (id.to_string(), "suggest_command".to_string(), args.to_string())
+                }
+                None => (id.to_string(), "read_file".to_string(), "{}".to_string()),
+            })
+            .collect();
+        AiStreamData::ToolCalls { session_id, tool_calls }
+    }
+
+    fn key(pane: &mut Pane, c: char, modifiers: KeyModifiers) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(pane.press(KeyCode::Char(c), modifiers)?)
+    }
+
+    const BATCH: [(&str, Option<&str>); 3] =
+        [("call_1", Some("du -sh .")), ("call_2", Some("df -h")), ("call_3", Some("ncdu ."))];
+
+    const SCENARIOS: &[(&str, Scenario)] = &[
+        ("text only", |pane, id| exchange(pane, "explain tar", vec![chunk(id, "tar packs "), chunk(id, "files"), end(id)])),
+        ("reasoning, then text", |pane, id| {
+            let reasoning = AiStreamData::Reasoning { session_id: id, text: "They mean GNU tar".to_string() };
+            exchange(pane, "explain tar", vec![reasoning, chunk(id, "tar packs files"), end(id)])
+        }),
+        ("error mid-stream", |pane, id| exchange(pane, "explain tar", vec![chunk(id, "tar pa"), failure(id)])),
+        ("error before any text", |pane, id| exchange(pane, "explain tar", vec![failure(id)])),
+        ("reasoning, then error", |pane, id| {
+            let reasoning = AiStreamData::Reasoning { session_id: id, text: "Hmm".to_string() };
+            exchange(pane, "explain tar", vec![reasoning, failure(id)])
+        }),
+        ("error between turns", |pane, id| {
+            exchange(pane, "explain tar", vec![chunk(id, "tar packs files"), end(id)])?;
+            exchange(pane, "and gzip?", vec![chunk(id, "gzip comp"), failure(id)])?;
+            exchange(pane, "and gzip?", vec![chunk(id, "gzip compresses"), end(id)])
+        }),
+        ("cut off mid-reply", |pane, id| {
+            let cut = AiStreamData::Truncated { session_id: id, partial: true };
+            exchange(pane, "explain tar", vec![chunk(id, "tar pa"), cut])
+        }),
+        ("cut off before any text", |pane, id| {
+            exchange(pane, "explain tar", vec![AiStreamData::Truncated { session_id: id, partial: true }])
+        }),
+        ("length limit, then continued", |pane, id| {
+            let limit = AiStreamData::Truncated { session_id: id, partial: false };
+            exchange(pane, "explain tar", vec![chunk(id, "tar packs fi"), limit])?;
+            key(pane, 'g', KeyModifiers::CONTROL)?;
+            for data in [chunk(id, "les into one."), end(id)] {
+                pane.replies.send(data)?;
+            }
+            pane.drain();
+            Ok(())
+        }),
+        ("pending suggestion", |pane, id| exchange(pane, "list files", vec![calls(id, &[("call_1", Some("ls -la"))]), end(id)])),
+        ("accepted suggestion", |pane, id| {
+            exchange(pane, "list files", vec![calls(id, &[("call_1", Some("ls -la"))]), end(id)])?;
+            key(pane, 'y', KeyModifiers::CONTROL)
+        }),
+        ("rejected suggestion", |pane, id| {
+            exchange(pane, "list files", vec![calls(id, &[("call_1", Some("ls -la"))]), end(id)])?;
+            key(pane, 'n', KeyModifiers::CONTROL)
+        }),
+        ("text, then a suggestion", |pane, id| {
+            exchange(pane, "list files", vec![chunk(id, "Try this:"), calls(id, &[("call_1", Some("ls -la"))]), end(id)])
+        }),
+        ("pending batch", |pane, id| exchange(pane, "disk usage", vec![calls(id, &BATCH), end(id)])),
+        ("batch, second one accepted", |pane, id| {
+            exchange(pane, "disk usage", vec![calls(id, &BATCH), end(id)])?;
+            key(pane, 'a', KeyModifiers::CONTROL)?;
+            key(pane, 'y', KeyModifiers::CONTROL)
+        }),
+        ("batch rejected", |pane, id| {
+            exchange(pane, "disk usage", vec![calls(id, &BATCH), end(id)])?;
+            key(pane, 'n', KeyModifiers::CONTROL)
+        }),
+        ("batch run from the checklist", |pane, id| {
+            exchange(pane, "disk usage", vec![calls(id, &BATCH), end(id)])?;
+            key(pane, 'm', KeyModifiers::NONE)?;
+            key(pane, ' ', KeyModifiers::NONE)?;
+            pane.press(KeyCode::Down, KeyModifiers::NONE)?;
+            key(pane, ' ', KeyModifiers::NONE)?;
+            key(pane, 'y', KeyModifiers::CONTROL)
+        }),
+        ("batch with a call that suggests nothing, ids reused", |pane, id| {
+            let batch = [("call_1", Some("git status")), ("call_2", None), ("call_3", Some("git diff"))];
+            exchange(pane, "what changed?", vec![calls(id, &batch), end(id)])?;
+            key(pane, 'a', KeyModifiers::CONTROL)?;
+            key(pane, 'y', KeyModifiers::CONTROL)?;
+            exchange(pane, "and staged?", vec![calls(id, &[("call_1", Some("git diff --staged"))]), end(id)])
+        }),
+    ];
+
+    #[test]
+    fn test_conversation_loaded_again_is_the_one_streamed() -> Result<(), Box<dyn std::error::Error>> {
+        for (name, scenario) in SCENARIOS {
+            let mut pane = Pane::new()?;
+            let session_id = pane.assistant.active_session_id();
+            scenario(&mut pane, session_id)?;
+            assert!(!pane.assistant.is_streaming(), "{}: still streaming", name);
+
+            let mut loaded = TuiAssistant::new();
+            loaded.load_messages(pane.ai.get_session_messages(session_id));
+            assert_eq!(format!("{:#?}", loaded.messages()), format!("{:#?}", pane.assistant.messages()), "{}", name);
+            assert_eq!(loaded.pending_tool_call_id(), pane.assistant.pending_tool_call_id(), "{}", name);
+            assert_eq!(loaded.suggestion_pagination(), pane.assistant.suggestion_pagination(), "{}", name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_r_asks_the_latest_question_again() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        let r = UserEvent::Key(KeyEvent::new(KeyCode::Char('r'), KeyModifiers::NONE));
+        // Nothing to ask again yet
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, r.clone())?);
+        assert!(pane.replies.asked().is_empty());
+
+        exchange(&mut pane, "list files", vec![calls(session_id, &[("call_1", Some("ls -la"))]), end(session_id)])?;
+        // Ctrl+R only asks again for a reply from the cache
+        key(&mut pane, 'r', KeyModifiers::CONTROL)?;
+        assert_eq!(pane.replies.asked().len(), 1);
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, r)?);
+        sync_sessions(&mut pane.assistant, &mut pane.ai);
+        let asked = pane.replies.asked();
+        assert_eq!(asked.len(), 2);
+        assert_eq!(asked[1].1, "list files");
+        assert!(pane.assistant.is_streaming());
+        // The suggestion left waiting is superseded, not answered for
+        let superseded = pane.ai.get_session_messages(session_id).into_iter().find_map(|m| match m {
+            ChatMessage::Superseded { messages } => Some(messages),
+            _ => None,
+        });
+        assert!(superseded.is_some_and(|messages| messages
+            .iter()
+            .any(|m| matches!(m, ChatMessage::CommandCard { status: CommandStatus::Superseded, .. }))));
+        assert_eq!(pane.assistant.pending_tool_call_id(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_edited_question_is_asked_in_place_of_the_old_one() -> Result<(), Box<dyn std::error::Error>> {
+        use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        pane.type_text("=6*7")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        exchange(&mut pane, "explain tar", vec![chunk(session_id, "tar packs files"), end(session_id)])?;
+        exchange(&mut pane, "and gzip?", vec![calls(session_id, &[("call_1", Some("gzip -k file"))]), end(session_id)])?;
+        let area = Rect::new(0, 0, 60, 40);
+        (&pane.assistant).render(area, &mut Buffer::empty(area));
+
+        // Up to the top: the sum was answered here, so there is nothing to edit
+        pane.assistant.enter_visual_mode();
+        for _ in 0..40 {
+            pane.assistant.handle_visual_key(KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE));
+        }
+        assert_eq!(pane.assistant.question_at_visual_cursor(), None);
+        // Down to the first question to the AI
+        for _ in 0..40 {
+            if pane.assistant.question_at_visual_cursor().is_some() {
+                break;
+            }
+            pane.assistant.handle_visual_key(KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE));
+        }
+        assert_eq!(pane.assistant.question_at_visual_cursor(), Some((0, "explain tar")));
+        edit_question(&mut pane.assistant, &pane.ai);
+        assert!(!pane.assistant.is_visual_mode());
+        assert_eq!(pane.assistant.get_input(), "explain tar");
+        assert_eq!(pane.assistant.editing(), Some(0));
+
+        // The card waiting goes with the turns replaced
+        pane.type_text(" briefly")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.replies.asked().last().map(|(_, q)| q.as_str()), Some("explain tar briefly"));
+        assert_eq!(pane.assistant.editing(), None);
+        assert_eq!(pane.assistant.pending_tool_call_id(), None);
+        assert!(pane.assistant.is_streaming());
+        let questions: Vec<_> = pane
+            .assistant
+            .messages()
+            .iter()
+            .filter_map(|m| match m {
+                ChatMessage::User { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(questions, vec!["explain tar briefly"]);
+        assert_eq!(pane.ai.user_message(session_id, 1), None);
+
+        // Ctrl+C gives an edit up
+        pane.drain();
+        pane.replies.send(end(session_id))?;
+        pane.drain();
+        pane.assistant.start_editing(0, "explain tar briefly".to_string());
+        pane.interrupt();
+        assert_eq!(pane.assistant.editing(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ctrl_g_continues_a_reply_cut_off() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        // Nothing to continue yet
+        key(&mut pane, 'g', KeyModifiers::CONTROL)?;
+        assert!(pane.assistant.messages().is_empty());
+
+        exchange(&mut pane, "explain tar", vec![chunk(session_id, "tar pa"), AiStreamData::Truncated { session_id, partial: true }])?;
+        assert!(pane.assistant.can_continue_reply());
+        key(&mut pane, 'g', KeyModifiers::CONTROL)?;
+        assert!(pane.assistant.is_streaming());
+        assert!(matches!(
+            &pane.assistant.messages()[3],
+            ChatMessage::User { text } if text == prompt::CONTINUE_PROMPT
+        ));
+        // Once only
+        assert!(!pane.assistant.can_continue_reply());
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_goes_to_the_shells_directory() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let mut pane = Pane::new()?;
+        pane.context.update_cwd(dir.display().to_string());
+        let session_id = pane.assistant.active_session_id();
+        let export = UserEvent::Key(KeyEvent::new(KeyCode::Char('>'), KeyModifiers::NONE));
+
+        // Nothing asked, nothing written
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, export.clone())?);
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 0);
+
+        exchange(&mut pane, "explain tar", vec![chunk(session_id, "tar packs files"), end(session_id)])?;
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, export)?);
+        pane.type_text("/export json")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        let mut written: Vec<_> = std::fs::read_dir(&dir)?.map(|entry| entry.map(|e| e.path())).collect::<Result<_, _>>()?;
+        written.sort_by_key(|path| path.extension().map(|ext| ext.to_os_string()));
+        let [json, markdown] = written.as_slice() else {
+            return Err(format!("expected two files, got {:?}", written).into());
+        };
+        assert!(std::fs::read_to_string(markdown)?.contains("**AI:** tar packs files"));
+        assert!(std::fs::read_to_string(json)?.contains("\"version\": 1"));
+        let status = pane.assistant.get_pane_status().title_status.unwrap_or_default();
+        assert!(status.contains(&format!("Exported to {}", json.display())), "{}", status);
+        assert_eq!(pane.replies.asked().len(), 1);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_bang_fills_in_the_system_prompt_to_edit() -> Result<(), Box<dyn std::error::Error>> {
+        let mut pane = Pane::new()?;
+        let session_id = pane.assistant.active_session_id();
+        let bang = UserEvent::Key(KeyEvent::new(KeyCode::Char('!'), KeyModifiers::NONE));
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, bang.clone())?);
+        assert_eq!(pane.assistant.get_input(), format!("/prompt {}", prompt::SYSTEM_PROMPT));
+
+        pane.assistant.clear_input();
+        pane.type_text("/prompt Answer only with fish shell syntax.")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.ai.session_system_prompt(session_id), Some("Answer only with fish shell syntax."));
+        assert!(pane.replies.asked().is_empty());
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, bang)?);
+        assert_eq!(pane.assistant.get_input(), "/prompt Answer only with fish shell syntax.");
+
+        // Alone, back to the default
+        pane.assistant.clear_input();
+        pane.type_text("/prompt")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.ai.session_system_prompt(session_id), Some(prompt::SYSTEM_PROMPT));
+        Ok(())
+    }
+
+    #[test]
+    fn test_prompt_template_is_picked_and_filled_in() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-prompt-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("git.md"), "Act as a git expert.")?;
+        std::fs::write(dir.join("k8s.md"), "You help with Kubernetes in {{cwd}}, using {{shell}}.")?;
+        let mut pane = Pane::new()?;
+        pane.ai.set_prompt_templates(prompt::TemplateStore::load(&dir));
+        std::fs::remove_dir_all(&dir)?;
+        pane.context.update_cwd("/srv/cluster".to_string());
+        let session_id = pane.assistant.active_session_id();
+        let bang = UserEvent::Key(KeyEvent::new(KeyCode::Char('!'), KeyModifiers::NONE));
+
+        // Editing the current prompt, then the templates
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, bang.clone())?);
+        assert!(pane.assistant.is_prompt_picker_open());
+        pane.press(KeyCode::Down, KeyModifiers::NONE)?;
+        pane.press(KeyCode::Down, KeyModifiers::NONE)?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert!(!pane.assistant.is_prompt_picker_open());
+        assert_eq!(pane.ai.session_system_prompt(session_id), Some("You help with Kubernetes in /srv/cluster, using bash."));
+        assert!(pane.assistant.get_input().is_empty());
+
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, bang.clone())?);
+        pane.press(KeyCode::Char('2'), KeyModifiers::NONE)?;
+        assert_eq!(pane.ai.session_system_prompt(session_id), Some("Act as a git expert."));
+
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, bang)?);
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_input(), "/prompt Act as a git expert.");
+        assert!(pane.replies.asked().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_opens_an_export_in_a_new_tab() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-import-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let mut pane = Pane::new()?;
+        pane.context.update_cwd(dir.display().to_string());
+        let session_id = pane.assistant.active_session_id();
+        exchange(&mut pane, "explain tar", vec![chunk(session_id, "tar packs files"), end(session_id)])?;
+        let json = pane.ai.export_session(session_id, ExportFormat::Json).ok_or("no export")?;
+        std::fs::write(dir.join("tar.json"), json)?;
+
+        // < starts the command; the file is relative to the shell's directory
+        let import = UserEvent::Key(KeyEvent::new(KeyCode::Char('<'), KeyModifiers::NONE));
+        assert!(handle_command_mode(&mut pane.assistant, &mut pane.ai, &pane.context, import)?);
+        assert_eq!(pane.assistant.get_input(), "/import ");
+        pane.type_text("tar.json")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        let imported = pane.assistant.active_session_id();
+        assert_ne!(imported, session_id);
+        assert_eq!(pane.ai.get_session_tabs().len(), 2);
+        assert!(pane.assistant.messages().iter().any(|m| matches!(m, ChatMessage::Assistant { text, .. } if text == "tar packs files")));
+
+        // A file that isn't an export leaves an error card and no tab
+        std::fs::write(dir.join("notes.md"), "# explain tar")?;
+        pane.type_text("/import notes.md")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.active_session_id(), imported);
+        assert_eq!(pane.ai.get_session_tabs().len(), 2);
+        assert!(matches!(pane.assistant.messages().last(), Some(ChatMessage::Error { text }) if text.starts_with("Not a session export")));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_dropped_file_goes_with_the_next_message_only() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-dropped-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let file = dir.join("error log.txt");
+        std::fs::write(&file, "panicked at src/main.rs:3\n")?;
+        let dropped = format!("'{}' ", file.display());
+        let over = || tokio::time::Instant::now() + crate::ui::file_drop::BURST_GAP;
+
+        let mut pane = Pane::new()?;
+        // Typed, then a pause: nothing dropped
+        pane.type_text("why ")?;
+        assert!(!pane.assistant.check_file_drop(None, over()));
+
+        // The terminal types the path: it leaves the input, and y attaches it
+        pane.type_text(&dropped)?;
+        assert!(pane.assistant.check_file_drop(None, over()));
+        assert_eq!(pane.assistant.get_input(), "why ");
+        pane.press(KeyCode::Char('y'), KeyModifiers::NONE)?;
+        assert!(!pane.assistant.has_drop_offer());
+        assert_eq!(pane.assistant.dropped_files().len(), 1);
+
+        // p puts the path back as typed instead
+        pane.type_text(&dropped)?;
+        assert!(pane.assistant.check_file_drop(None, over()));
+        pane.press(KeyCode::Char('p'), KeyModifiers::NONE)?;
+        assert_eq!(pane.assistant.get_input(), format!("why {}", dropped));
+        assert_eq!(pane.assistant.dropped_files().len(), 1);
+
+        pane.assistant.clear_input();
+        pane.type_text("why did it panic?")?;
+        pane.press(KeyCode::Enter, KeyModifiers::NONE)?;
+        let session = pane.ai.current_session().ok_or("no current session")?;
+        let sent = session
+            .conversation_history
+            .iter()
+            .rev()
+            .find_map(|msg| match msg {
+                async_openai::types::ChatCompletionRequestMessage::User(user_msg) => match &user_msg.content {
+                    async_openai::types::ChatCompletionRequestUserMessageContent::Text(t) => prompt::parse_user_prompt(t),
+                    async_openai::types::ChatCompletionRequestUserMessageContent::Array(_) => None,
+                },
+                _ => None,
+            })
+            .ok_or("nothing sent")?;
+        assert_eq!(sent.user_request, "why did it panic?");
+        assert_eq!(sent.context.files.len(), 1);
+        assert_eq!(sent.context.files[0].path, file.display().to_string());
+        assert_eq!(sent.context.files[0].content, "panicked at src/main.rs:3\n");
+        assert!(pane.assistant.dropped_files().is_empty());
+        Ok(())
+    }
+}
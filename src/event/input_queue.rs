@@ -0,0 +1,339 @@
+//! The queue between the terminal reader thread and the main loop.
+//!
+//! The reader thread puts every event it reads in a bounded queue, and
+//! waits while the queue is full. When the main loop stalls (a flood of
+//! shell output, a slow frame) keys pile up behind it and all come out at
+//! once when it is back: a held arrow key or Backspace would carry on well
+//! after it was let go. So while the loop is stalled, a run of the same
+//! repeating key stops growing past [`MAX_REPEAT_RUN`], and a pointer move
+//! or drag replaces the one queued just before it. No other event is ever
+//! merged or dropped, and none is reordered.
+//!
+//! How deep the queue got, how often the loop stalled and how many events
+//! were merged go to the metrics registry.
+
+use std::collections::VecDeque;
+use std::io::Result;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, MouseEvent, MouseEventKind};
+use tokio::sync::Notify;
+
+use super::UserEvent;
+use crate::utils::metrics::metrics;
+
+/// Events the queue holds before the reader thread waits.
+pub const CAPACITY: usize = 64;
+
+/// The main loop counts as stalled once the oldest waiting event has waited
+/// this long.
+pub const STALL_AFTER: Duration = Duration::from_millis(100);
+
+/// Copies of a repeating key kept in a row at the end of the queue while
+/// the main loop is stalled.
+pub const MAX_REPEAT_RUN: usize = 3;
+
+/// How deep the queue got and what it merged.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InputStats {
+    /// Most events waiting at once
+    pub high_water: usize,
+    /// Times the main loop stalled with events waiting
+    pub stalls: u64,
+    /// Events merged into the one queued before them
+    pub coalesced: u64,
+}
+
+/// User events waiting for the main loop, each with the time it was read.
+#[derive(Debug, Default)]
+pub struct InputQueue {
+    events: VecDeque<(Result<UserEvent>, Instant)>,
+    /// The oldest waiting event waited past [`STALL_AFTER`]
+    stalled: bool,
+    stats: InputStats,
+}
+
+impl InputQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.events.len() >= CAPACITY
+    }
+
+    pub fn stats(&self) -> InputStats {
+        self.stats
+    }
+
+    /// Queue `event`, read at `now`. Returns false when it was merged into
+    /// the event before it instead.
+    pub fn push(&mut self, event: Result<UserEvent>, now: Instant) -> bool {
+        let stalled = self.events.front().is_some_and(|(_, read)| now.saturating_duration_since(*read) >= STALL_AFTER);
+        if stalled && !self.stalled {
+            self.stats.stalls += 1;
+            metrics().note_input_stall();
+        }
+        self.stalled = stalled;
+
+        if let Ok(event) = &event {
+            // The pointer is only ever wanted where it is now
+            if let Some((Ok(last), _)) = self.events.back_mut()
+                && same_motion(last, event)
+            {
+                *last = event.clone();
+                self.note_coalesced();
+                return false;
+            }
+            if stalled && is_repeat_key(event) && self.tail_run(event) >= MAX_REPEAT_RUN {
+                self.note_coalesced();
+                return false;
+            }
+        }
+
+        self.events.push_back((event, now));
+        if self.events.len() > self.stats.high_water {
+            self.stats.high_water = self.events.len();
+        }
+        metrics().note_input_queued(self.events.len());
+        true
+    }
+
+    /// The oldest waiting event.
+    pub fn pop(&mut self) -> Option<Result<UserEvent>> {
+        let (event, _) = self.events.pop_front()?;
+        if self.events.is_empty() {
+            self.stalled = false;
+        }
+        Some(event)
+    }
+
+    fn note_coalesced(&mut self) {
+        self.stats.coalesced += 1;
+        metrics().note_input_coalesced();
+    }
+
+    /// Events equal to `event` at the end of the queue.
+    fn tail_run(&self, event: &UserEvent) -> usize {
+        self.events.iter().rev().take_while(|(queued, _)| queued.as_ref().is_ok_and(|queued| queued == event)).count()
+    }
+}
+
+/// Keys a terminal auto-repeats while held, where each repeat moves
+/// something (the cursor, a scroll position) a step further.
+fn is_repeat_key(event: &UserEvent) -> bool {
+    matches!(
+        event,
+        Event::Key(KeyEvent {
+            code: KeyCode::Up
+                | KeyCode::Down
+                | KeyCode::Left
+                | KeyCode::Right
+                | KeyCode::PageUp
+                | KeyCode::PageDown
+                | KeyCode::Backspace
+                | KeyCode::Delete,
+            kind: KeyEventKind::Press | KeyEventKind::Repeat,
+            ..
+        })
+    )
+}
+
+/// `event` moves the pointer the same way `last` did (a plain move, or a
+/// drag with the same button), so it can take its place.
+fn same_motion(last: &UserEvent, event: &UserEvent) -> bool {
+    let (Event::Mouse(last), Event::Mouse(event)) = (last, event) else {
+        return false;
+    };
+    let motion = |mouse: &MouseEvent| match mouse.kind {
+        MouseEventKind::Moved => Some(None),
+        MouseEventKind::Drag(button) => Some(Some(button)),
+        _ => None,
+    };
+    motion(last).is_some() && motion(last) == motion(event) && last.modifiers == event.modifiers
+}
+
+/// The queue and what the two sides wait on.
+#[derive(Debug, Default)]
+struct Shared {
+    queue: Mutex<InputQueue>,
+    /// The main loop took an event (the reader waits on it when full)
+    taken: Condvar,
+    /// The reader queued an event
+    queued: Notify,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, InputQueue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The main loop's end of the queue.
+#[derive(Debug)]
+pub struct UserEvents {
+    /// None for an app with no terminal to read
+    shared: Option<Arc<Shared>>,
+}
+
+impl UserEvents {
+    /// Start a thread reading the terminal into the queue. It stops once
+    /// the returned end is dropped.
+    pub fn spawn_reader() -> Self {
+        let shared = Arc::new(Shared::default());
+        let reader = Arc::clone(&shared);
+        thread::spawn(move || {
+            loop {
+                let event = crossterm::event::read();
+                if Arc::strong_count(&reader) == 1 {
+                    break;
+                }
+                let mut queue = reader.lock();
+                while queue.is_full() {
+                    queue = reader
+                        .taken
+                        .wait_timeout(queue, STALL_AFTER)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                    if Arc::strong_count(&reader) == 1 {
+                        return;
+                    }
+                }
+                queue.push(event, Instant::now());
+                drop(queue);
+                reader.queued.notify_one();
+            }
+        });
+        Self { shared: Some(shared) }
+    }
+
+    /// An end that never yields anything, for an app driven by hand.
+    pub fn detached() -> Self {
+        Self { shared: None }
+    }
+
+    /// The next event, waiting for one. None when detached.
+    pub async fn recv(&mut self) -> Option<Result<UserEvent>> {
+        let shared = self.shared.as_ref()?;
+        loop {
+            let queued = shared.queued.notified();
+            let event = shared.lock().pop();
+            if let Some(event) = event {
+                shared.taken.notify_one();
+                return Some(event);
+            }
+            queued.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyModifiers, MouseButton};
+
+    fn key(code: KeyCode) -> Result<UserEvent> {
+        Ok(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)))
+    }
+
+    fn drag(column: u16) -> Result<UserEvent> {
+        Ok(Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Drag(MouseButton::Left),
+            column,
+            row: 3,
+            modifiers: KeyModifiers::NONE,
+        }))
+    }
+
+    fn drain(queue: &mut InputQueue) -> Vec<UserEvent> {
+        std::iter::from_fn(|| queue.pop()).filter_map(|event| event.ok()).collect()
+    }
+
+    fn count(events: &[UserEvent], code: KeyCode) -> usize {
+        events.iter().filter(|event| **event == Event::Key(KeyEvent::new(code, KeyModifiers::NONE))).count()
+    }
+
+    #[test]
+    fn test_stall_caps_repeat_bursts_and_keeps_distinct_keys() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut queue = InputQueue::new();
+
+        // A loop keeping up takes every repeat
+        for ms in 0..5 {
+            queue.push(key(KeyCode::Down), at(ms));
+        }
+        assert_eq!(count(&drain(&mut queue), KeyCode::Down), 5);
+
+        // Then it stalls with Down held: repeats keep coming for 400ms,
+        // then a word is typed, Backspace is held and Enter pressed
+        queue.push(key(KeyCode::Char('x')), at(1000));
+        for i in 0..40 {
+            queue.push(key(KeyCode::Down), at(1000 + 10 * i));
+        }
+        for c in ['l', 'l', 's'] {
+            queue.push(key(KeyCode::Char(c)), at(1400));
+        }
+        for _ in 0..20 {
+            queue.push(key(KeyCode::Backspace), at(1500));
+        }
+        queue.push(key(KeyCode::Enter), at(1500));
+        let applied = drain(&mut queue);
+
+        // Ten repeats came before the stall was told; none after
+        assert_eq!(count(&applied, KeyCode::Down), 10);
+        assert_eq!(count(&applied, KeyCode::Backspace), MAX_REPEAT_RUN);
+        let distinct: String = applied
+            .iter()
+            .filter_map(|event| match event {
+                Event::Key(KeyEvent { code: KeyCode::Char(c), .. }) => Some(*c),
+                Event::Key(KeyEvent { code: KeyCode::Enter, .. }) => Some('⏎'),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(distinct, "xlls⏎");
+        // In the order they came
+        let backspace = applied.iter().position(|event| matches!(event, Event::Key(KeyEvent { code: KeyCode::Backspace, .. })));
+        assert_eq!(backspace, Some(14));
+        assert_eq!(queue.stats(), InputStats { high_water: 18, stalls: 1, coalesced: 30 + 17 });
+
+        // A drained queue is no longer stalled
+        queue.push(key(KeyCode::Up), at(5000));
+        queue.push(key(KeyCode::Up), at(5001));
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn test_pointer_motion_keeps_the_latest_position() {
+        let now = Instant::now();
+        let mut queue = InputQueue::new();
+        assert!(queue.push(drag(10), now));
+        assert!(!queue.push(drag(11), now));
+        assert!(!queue.push(drag(12), now));
+        // A key in between keeps both sides apart
+        assert!(queue.push(key(KeyCode::Char('a')), now));
+        assert!(queue.push(drag(20), now));
+        assert!(!queue.push(drag(21), now));
+
+        let events = drain(&mut queue);
+        let columns: Vec<_> = events
+            .iter()
+            .map(|event| match event {
+                Event::Mouse(mouse) => mouse.column,
+                _ => 0,
+            })
+            .collect();
+        assert_eq!(columns, vec![12, 0, 21]);
+        assert!(queue.is_empty());
+    }
+}
@@ -15,18 +15,24 @@
 //!
 //! # Submodules
 //!
+//! - `actions`: Registry of the key bindings, with descriptions for the help
 //! - `assistant`: Key event handling for the AI Assistant pane
+//! - `input_queue`: Queue of user events, merging key repeats during stalls
 //! - `terminal`: Key event handling for the Terminal pane
 //! - `mouse`: Mouse event handling (click, drag, scroll, passthrough)
+//! - `trace`: Sequence stamping, event trace ring and ordering checks
 
+pub mod actions;
 pub mod assistant;
+pub mod input_queue;
 pub mod mouse;
 pub mod terminal;
+pub mod trace;
 
-use std::thread;
+pub use input_queue::UserEvents;
+pub use trace::{EventOrigin, Stamped};
 
-use tokio::sync::mpsc::{self, Receiver, UnboundedReceiver, UnboundedSender};
-use std::io::Result;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
 /// Type alias for user input events from the terminal.
 ///
@@ -36,33 +42,30 @@ pub type UserEvent = crossterm::event::Event;
 
 /// Initializes the user event stream.
 ///
-/// Creates a channel for receiving user input events and spawns a dedicated
-/// thread to read events from the terminal. This ensures that user input
-/// is always processed promptly without being blocked by other operations.
+/// Spawns a dedicated thread to read events from the terminal into a
+/// bounded queue. This ensures that user input is always processed promptly
+/// without being blocked by other operations.
 ///
 /// # Returns
 ///
-/// A receiver that yields `Result<UserEvent>` items. The `Result` wrapper
-/// handles potential I/O errors when reading from the terminal.
+/// The main loop's end of the queue, yielding `Result<UserEvent>` items. The
+/// `Result` wrapper handles potential I/O errors when reading from the
+/// terminal.
 ///
 /// # Implementation Details
 ///
 /// The spawned thread continuously reads events using `crossterm::event::read()`
-/// and sends them through the channel. If the receiver is dropped, the thread
-/// will automatically terminate when the send operation fails.
-pub fn init_user_event() -> Receiver<Result<UserEvent>> {
-    let (tx, rx) = mpsc::channel(64);
-    thread::spawn(move || {
-        loop {
-            if tx.blocking_send(crossterm::event::read()).is_err() {
-                break;
-            }
-        }
-    });
-    rx
+/// and queues them, waiting while the queue is full. While the main loop is
+/// stalled, repeats of a held key stop piling up (see [`input_queue`]). If
+/// the returned end is dropped, the thread terminates after its next read.
+pub fn init_user_event() -> UserEvents {
+    UserEvents::spawn_reader()
 }
 
-use crate::ai::session::SessionId;
+use crate::ai::draft::SuggestionDraft;
+use crate::ai::session::{ExplainOutputArgs, SessionId};
+use crate::ai::timing::RequestTiming;
+use crate::ai::usage::UsageStats;
 
 // =============================================================================
 // AI Stream Data (Dedicated Channel)
@@ -75,7 +78,8 @@ use crate::ai::session::SessionId;
 /// 2. End-of-stream must be in the same channel to preserve ordering with chunks
 ///
 /// This channel is owned by AiSessionManager which processes the data and
-/// forwards UI updates to TuiAssistant.
+/// forwards UI updates to TuiAssistant. Data is sent wrapped in [`Stamped`] so
+/// the manager can detect out-of-order application.
 #[derive(Debug, Clone)]
 pub enum AiStreamData {
     /// A chunk of text from the streaming response
@@ -83,21 +87,112 @@ pub enum AiStreamData {
         session_id: SessionId,
         text: String,
     },
+    /// A chunk of the model's reasoning, streamed ahead of its answer
+    Reasoning {
+        session_id: SessionId,
+        text: String,
+    },
+    /// What is known so far of the first command suggestion, while its
+    /// arguments are still streaming
+    SuggestionDraft {
+        session_id: SessionId,
+        draft: SuggestionDraft,
+    },
     /// Tool calls from the AI (accumulated from streaming chunks)
     /// Each tuple is (tool_call_id, function_name, arguments_json)
     ToolCalls {
         session_id: SessionId,
         tool_calls: Vec<(String, String, String)>,
     },
+    /// Where the request's time went; sent just before End
+    Timing {
+        session_id: SessionId,
+        timing: RequestTiming,
+    },
     /// The streaming response has completed
     End {
         session_id: SessionId,
+        /// The response was replayed from the response cache
+        cached: bool,
+    },
+    /// The stream ended before the reply was complete: cut off without a
+    /// finish reason (`partial`, the connection dropped), or at the model's
+    /// length limit. Sent instead of End.
+    Truncated {
+        session_id: SessionId,
+        partial: bool,
     },
     /// An error occurred during streaming
     Error {
         session_id: SessionId,
         error: String,
     },
+    /// Something degraded the request without failing it
+    Warning {
+        session_id: SessionId,
+        message: String,
+    },
+    /// What the request is doing while no reply comes (a retry after a
+    /// transient failure)
+    Status {
+        session_id: SessionId,
+        text: String,
+    },
+    /// The model cannot call tools; the request is being sent again
+    /// without them
+    ToolsUnsupported {
+        session_id: SessionId,
+    },
+    /// The model was not found on its first use; `suggestions` are the
+    /// available models closest to its name
+    ModelNotFound {
+        session_id: SessionId,
+        model: String,
+        suggestions: Vec<String>,
+    },
+    /// Tokens the reply used, reported by the API or estimated; sent
+    /// before its timing
+    Usage {
+        session_id: SessionId,
+        usage: UsageStats,
+    },
+    /// A summary of folded history came back, or its request failed. Sent
+    /// by a request of its own, so it is not part of any reply stream.
+    Summary {
+        session_id: SessionId,
+        summary: std::result::Result<String, String>,
+    },
+    /// A name for the session came back from the model, or its request
+    /// failed. A request of its own too.
+    SessionName {
+        session_id: SessionId,
+        name: std::result::Result<String, String>,
+    },
+    /// The AI's explanation of the verdict on the card of `tool_call_id`
+    /// came back, or its request failed. A request of its own too.
+    Rationale {
+        session_id: SessionId,
+        tool_call_id: String,
+        rationale: std::result::Result<String, String>,
+    },
+    /// The follow-up asking for a safer command than the one of
+    /// `tool_call_id` came back: the suggestion the AI made instead as
+    /// (tool_call_id, function_name, arguments_json), None if it found
+    /// none, or why the request failed. A request of its own too.
+    Alternative {
+        session_id: SessionId,
+        tool_call_id: String,
+        alternative: std::result::Result<Option<(String, String, String)>, String>,
+    },
+    /// The stretches of `reply`, the history message at `at`, quoted
+    /// verbatim from the context of its question, as byte ranges. Found on
+    /// the blocking pool once the reply ended.
+    Quotes {
+        session_id: SessionId,
+        at: usize,
+        reply: String,
+        spans: Vec<std::ops::Range<usize>>,
+    },
 }
 
 // =============================================================================
@@ -116,20 +211,105 @@ pub enum AiUiUpdate {
         session_id: SessionId,
         text: String,
     },
+    /// A chunk of reasoning to append to the thinking block above it
+    Reasoning {
+        session_id: SessionId,
+        text: String,
+    },
+    /// Timing of the request behind the reply streaming in
+    Timing {
+        session_id: SessionId,
+        timing: RequestTiming,
+    },
     /// The streaming response has completed
     End {
         session_id: SessionId,
+        /// The response was replayed from the response cache
+        cached: bool,
+    },
+    /// The streaming response ended before it was complete; it can be
+    /// continued. `partial` when the connection dropped, rather than the
+    /// length limit being reached.
+    Truncated {
+        session_id: SessionId,
+        partial: bool,
     },
     /// An error occurred during streaming
     Error {
         session_id: SessionId,
         error: String,
     },
+    /// The model was not found: the question was taken back out of the
+    /// conversation and `input` is its text, to be sent again
+    ModelNotFound {
+        session_id: SessionId,
+        message: String,
+        input: String,
+    },
+    /// A notice about the request in flight, shown next to the pane status
+    Warning {
+        session_id: SessionId,
+        message: String,
+    },
+    /// What the request in flight is doing, shown in the input prompt
+    /// until its reply starts
+    Status {
+        session_id: SessionId,
+        text: String,
+    },
+    /// Provisional card for a suggestion still streaming; replaced by
+    /// CommandSuggestion, dropped if the stream ends without one
+    CommandSuggestionDraft {
+        session_id: SessionId,
+        draft: SuggestionDraft,
+    },
     /// AI suggested one or more commands that should be displayed as card(s)
     CommandSuggestion {
         session_id: SessionId,
-        /// List of (command, explanation) tuples
-        commands: Vec<(String, String)>,
+        /// List of (tool_call_id, command, explanation, cwd) tuples
+        commands: Vec<(String, String, String, Option<String>)>,
+        /// Commands among them that appear verbatim in the terminal output
+        /// sent as context
+        from_output: Vec<String>,
+    },
+    /// AI explained some output; shown as a card of its own, in its place
+    /// among the command suggestions of the same reply
+    Explanation {
+        session_id: SessionId,
+        explanation: ExplainOutputArgs,
+    },
+    /// The summary of the session's folded history is now `text`
+    Summary {
+        session_id: SessionId,
+        text: String,
+    },
+    /// The explanation of a card's verdict, or why there is none
+    Rationale {
+        session_id: SessionId,
+        tool_call_id: String,
+        rationale: std::result::Result<String, String>,
+    },
+    /// A safer command than the pending suggestion `original`, shown
+    /// ahead of it as (tool_call_id, command, explanation, cwd)
+    SaferAlternative {
+        session_id: SessionId,
+        original: String,
+        alternative: (String, String, String, Option<String>),
+    },
+    /// The stretches of the reply `reply` quoted from its context, to be
+    /// underlined
+    Quotes {
+        session_id: SessionId,
+        reply: String,
+        spans: Vec<std::ops::Range<usize>>,
+    },
+    /// Part `sent` of `parts` of a full output is on its way; the last one
+    /// goes with the `question`
+    ContextPart {
+        session_id: SessionId,
+        sent: usize,
+        parts: usize,
+        question: Option<String>,
     },
 }
 
@@ -153,6 +333,9 @@ pub enum AiUiUpdate {
 /// High-frequency streaming data (AI chunks, shell output) uses dedicated channels
 /// instead of AppEvent to avoid flooding this queue. AppEvent is reserved for
 /// low-frequency coordination events.
+///
+/// Events travel through the channel wrapped in [`Stamped`], which records a
+/// sequence id and origin at creation for ordering diagnostics.
 #[non_exhaustive]
 pub enum AppEvent {
     // =========================================================================
@@ -162,7 +345,19 @@ pub enum AppEvent {
     /// User has confirmed execution of the AI-suggested command.
     ExecuteAiCommand {
         session_id: SessionId,
+        /// Tool call that suggested it
+        tool_call_id: String,
         command: String,
+        /// Directory to run it in (None = the shell's own)
+        cwd: Option<String>,
+        /// Send Ctrl+C first, to stop what runs in the foreground
+        interrupt_first: bool,
+    },
+
+    /// User has accepted several AI-suggested commands, to run in order.
+    ExecuteAiBatch {
+        session_id: SessionId,
+        steps: Vec<crate::ai::batch::BatchStep>,
     },
 
     // =========================================================================
@@ -174,10 +369,17 @@ pub enum AppEvent {
         message: String,
     },
 
-    /// Shell command execution completed
+    /// A shell command finished, whether typed by the user or run by the
+    /// app (see [`crate::shell::CommandTracker`])
     ShellCommandCompleted {
         command: String,
-        exit_code: i32,
+        /// Exit status, known with shell integration
+        exit_code: Option<i32>,
+        /// Signal that stopped it (SIGINT after a Ctrl+C)
+        signal: Option<i32>,
+        duration: std::time::Duration,
+        /// Session and tool call it ran for, if the assistant suggested it
+        origin: Option<crate::shell::CommandOrigin>,
     },
 
     /// Shell produced output chunk (throttled to short snippets)
@@ -185,6 +387,18 @@ pub enum AppEvent {
         data: String,
     },
 
+    /// Shell reported its working directory (OSC 7 payload, `file://host/path`)
+    ShellCwdChanged {
+        payload: String,
+    },
+
+    /// The project files of a directory were read (`project` is None if it
+    /// has none)
+    ProjectRead {
+        stamp: crate::context::ProjectStamp,
+        project: Option<crate::context::ProjectContext>,
+    },
+
     /// Terminal request of writing PTY
     PtyWrite(Vec<u8>),
 }
@@ -200,8 +414,8 @@ pub enum AppEvent {
 /// # Returns
 ///
 /// A tuple containing:
-/// - `UnboundedSender<AppEvent>`: For sending application events (can be cloned)
-/// - `UnboundedReceiver<AppEvent>`: For receiving and processing application events
+/// - `UnboundedSender<Stamped<AppEvent>>`: For sending application events (can be cloned)
+/// - `UnboundedReceiver<Stamped<AppEvent>>`: For receiving and processing application events
 ///
 /// # Usage
 ///
@@ -209,6 +423,6 @@ pub enum AppEvent {
 /// while the receiver is used in the main event loop to handle these events.
 ///
 /// Unbounded is appropriate here because AppEvent is low-frequency and lightweight.
-pub fn init_app_eventsource() -> (UnboundedSender<AppEvent>, UnboundedReceiver<AppEvent>) {
+pub fn init_app_eventsource() -> (UnboundedSender<Stamped<AppEvent>>, UnboundedReceiver<Stamped<AppEvent>>) {
     mpsc::unbounded_channel()
 }
@@ -8,16 +8,17 @@
 //! - Mouse passthrough to PTY when terminal program enables mouse mode
 //! - Tab bar interactions (switch session, new session, close session)
 //! - Command card button clicks (execute, cancel)
+//! - Regenerate hint clicks (opens the regenerate menu)
 //! - Input box cursor positioning
 
 use anyhow::Result;
-use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 
 use crate::ai::session::AiSessionManager;
 use crate::app::{ActivePane, MouseTarget};
 use crate::shell::ShellManager;
 use crate::ui::assistant::{MessageAreaClickResult, TabClickResult, TuiAssistant};
-use crate::ui::layout::AppLayout;
+use crate::ui::layout::{AppLayout, LayoutMode};
 use crate::ui::terminal::TuiTerminal;
 
 /// Maximum time between clicks to count as double-click (in milliseconds)
@@ -41,15 +42,22 @@ pub struct MouseDragState {
     pub selection_started: bool,
     /// Whether this is an input box drag (for Assistant pane)
     pub is_input_box_drag: bool,
+    /// Whether this drag scrubs the pane's scrollbar
+    pub is_scrollbar_drag: bool,
 }
 
 /// State for separator drag (pane resizing)
+///
+/// While it lasts the panes are drawn at `ratio`, but the PTY keeps its
+/// size until the button is released (Esc puts back `initial_ratio`).
 #[derive(Debug, Clone, Copy)]
 pub struct SeparatorDragState {
     /// Starting column position of the drag
     pub start_col: u16,
     /// Initial split ratio when drag started
     pub initial_ratio: u16,
+    /// Split ratio under the pointer now
+    pub ratio: u16,
 }
 
 /// State for double/triple-click detection
@@ -71,6 +79,8 @@ pub struct LastClickState {
 pub enum AssistantRegion {
     TabBar,
     MessageArea,
+    /// The "what the AI knows" chips above the input
+    ContextChips,
     InputBox,
 }
 
@@ -80,8 +90,10 @@ pub fn get_mouse_target(layout: &AppLayout, col: u16, row: u16) -> MouseTarget {
     let asst_area = layout.assistant_area;
     let sep_area = layout.separator_area;
 
-    // Check separator first (it's thin and between the panes)
-    if col >= sep_area.x && col < sep_area.x + sep_area.width
+    // Check separator first (it's thin and between the panes). With one
+    // pane shown it is only that pane's border.
+    if layout.mode == LayoutMode::Split
+        && col >= sep_area.x && col < sep_area.x + sep_area.width
         && row >= sep_area.y && row < sep_area.y + sep_area.height
     {
         return MouseTarget::Separator;
@@ -129,9 +141,64 @@ pub fn get_assistant_region(
         return AssistantRegion::InputBox;
     }
 
+    // Chips sit right above the input box
+    if assistant.is_chip_row(mouse.row) {
+        return AssistantRegion::ContextChips;
+    }
+
     AssistantRegion::MessageArea
 }
 
+/// Interactive elements under the mouse pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HoverState {
+    /// Tab bar element (tab, close button, "+")
+    pub tab: TabClickResult,
+    /// Command card button
+    pub card_button: MessageAreaClickResult,
+    /// Whether the pane separator is hovered
+    pub separator: bool,
+}
+
+impl Default for HoverState {
+    fn default() -> Self {
+        Self {
+            tab: TabClickResult::None,
+            card_button: MessageAreaClickResult::None,
+            separator: false,
+        }
+    }
+}
+
+/// Hit-test a pointer position against the cached tab, card and separator hit areas.
+pub fn hover_at(layout: &AppLayout, assistant: &TuiAssistant, col: u16, row: u16) -> HoverState {
+    let mut hover = HoverState::default();
+    match get_mouse_target(layout, col, row) {
+        MouseTarget::Separator => hover.separator = true,
+        MouseTarget::Assistant => {
+            let pointer = MouseEvent {
+                kind: MouseEventKind::Moved,
+                column: col,
+                row,
+                modifiers: KeyModifiers::NONE,
+            };
+            let inner = layout.assistant_inner;
+            match get_assistant_region(&pointer, layout, assistant) {
+                AssistantRegion::TabBar => {
+                    hover.tab = assistant.get_tab_click_result(col, inner.x);
+                }
+                AssistantRegion::MessageArea => {
+                    // Message area starts after tab bar (1 line)
+                    hover.card_button = assistant.get_message_click_result(col, row, inner.x, inner.y + 1);
+                }
+                AssistantRegion::ContextChips | AssistantRegion::InputBox => {}
+            }
+        }
+        MouseTarget::Terminal | MouseTarget::Outside => {}
+    }
+    hover
+}
+
 /// Check click count (1=single, 2=double, 3=triple) based on previous click state.
 /// Returns the click count for this click.
 pub fn get_click_count(
@@ -182,57 +249,53 @@ pub fn check_double_click(
 /// Result of mouse event handling that may require App-level action.
 #[derive(Debug, Clone, Copy, Default)]
 pub struct MouseEventResult {
-    /// New split ratio to set (if separator was dragged)
-    pub new_split_ratio: Option<u16>,
+    /// The separator drag moved: lay the panes out at its ratio
+    pub separator_moved: bool,
+    /// The separator was released: split ratio to commit
+    pub committed_ratio: Option<u16>,
+}
+
+/// The app state a mouse event can read or change.
+pub struct MouseCtx<'a> {
+    pub layout: &'a AppLayout,
+    pub terminal: &'a mut TuiTerminal,
+    pub assistant: &'a mut TuiAssistant,
+    pub shell: &'a mut ShellManager,
+    pub ai_sessions: &'a mut AiSessionManager,
+    pub active_pane: &'a mut ActivePane,
+    pub drag_state: &'a mut Option<MouseDragState>,
+    pub separator_drag: &'a mut Option<SeparatorDragState>,
+    pub last_click: &'a mut Option<LastClickState>,
+    /// Split ratio the panes are laid out at now
+    pub current_split_ratio: u16,
 }
 
 /// Handle mouse events from crossterm.
 ///
 /// This is the main entry point for mouse event handling.
 /// Returns a MouseEventResult that may contain actions for the App to perform.
-pub fn handle_mouse_event(
-    mouse: MouseEvent,
-    layout: &AppLayout,
-    terminal: &mut TuiTerminal,
-    assistant: &mut TuiAssistant,
-    shell: &mut ShellManager,
-    ai_sessions: &mut AiSessionManager,
-    active_pane: &mut ActivePane,
-    drag_state: &mut Option<MouseDragState>,
-    separator_drag: &mut Option<SeparatorDragState>,
-    last_click: &mut Option<LastClickState>,
-    current_split_ratio: u16,
-) -> Result<MouseEventResult> {
-    let target = get_mouse_target(layout, mouse.column, mouse.row);
+pub fn handle_mouse_event(mouse: MouseEvent, ctx: MouseCtx<'_>) -> Result<MouseEventResult> {
+    let target = get_mouse_target(ctx.layout, mouse.column, mouse.row);
     let mut result = MouseEventResult::default();
 
     match mouse.kind {
         MouseEventKind::Down(button) => {
-            handle_mouse_down(
-                target, mouse, button, layout, terminal, assistant, shell, ai_sessions,
-                active_pane, drag_state, separator_drag, last_click, current_split_ratio,
-            )?;
+            handle_mouse_down(target, mouse, button, ctx)?;
         }
         MouseEventKind::Up(button) => {
-            handle_mouse_up(
-                target, mouse, button, layout, terminal, assistant, shell,
-                drag_state, separator_drag,
-            )?;
+            result.committed_ratio = handle_mouse_up(target, mouse, button, ctx)?;
         }
         MouseEventKind::Drag(button) => {
-            result.new_split_ratio = handle_mouse_drag(
-                target, mouse, button, layout, terminal, assistant, shell,
-                drag_state, separator_drag,
-            )?;
+            result.separator_moved = handle_mouse_drag(target, mouse, button, ctx)?.is_some();
         }
         MouseEventKind::ScrollUp => {
-            handle_scroll(target, mouse, -3, layout, terminal, assistant, shell)?;
+            handle_scroll(target, mouse, -3, ctx.layout, ctx.terminal, ctx.assistant, ctx.shell)?;
         }
         MouseEventKind::ScrollDown => {
-            handle_scroll(target, mouse, 3, layout, terminal, assistant, shell)?;
+            handle_scroll(target, mouse, 3, ctx.layout, ctx.terminal, ctx.assistant, ctx.shell)?;
         }
         MouseEventKind::Moved => {
-            // Ignore move without button (could be used for hover effects later)
+            // Hover is tracked by the App (see `hover_at`); nothing to do here
         }
         _ => {}
     }
@@ -241,21 +304,12 @@ pub fn handle_mouse_event(
 }
 
 /// Handle mouse button down event.
-fn handle_mouse_down(
-    target: MouseTarget,
-    mouse: MouseEvent,
-    button: MouseButton,
-    layout: &AppLayout,
-    terminal: &mut TuiTerminal,
-    assistant: &mut TuiAssistant,
-    shell: &mut ShellManager,
-    ai_sessions: &mut AiSessionManager,
-    active_pane: &mut ActivePane,
-    drag_state: &mut Option<MouseDragState>,
-    separator_drag: &mut Option<SeparatorDragState>,
-    last_click: &mut Option<LastClickState>,
-    current_split_ratio: u16,
-) -> Result<()> {
+fn handle_mouse_down(target: MouseTarget, mouse: MouseEvent, button: MouseButton, ctx: MouseCtx<'_>) -> Result<()> {
+    let MouseCtx {
+        layout, terminal, assistant, shell, ai_sessions, active_pane, drag_state, separator_drag, last_click,
+        current_split_ratio,
+    } = ctx;
+
     // Handle middle-click paste first (works regardless of pane)
     if button == MouseButton::Middle {
         handle_middle_click_paste(target, terminal, assistant, shell, active_pane, layout)?;
@@ -267,6 +321,7 @@ fn handle_mouse_down(
         *separator_drag = Some(SeparatorDragState {
             start_col: mouse.column,
             initial_ratio: current_split_ratio,
+            ratio: current_split_ratio,
         });
         *drag_state = None;
         return Ok(());
@@ -298,6 +353,11 @@ fn handle_mouse_down(
         1
     };
 
+    // The scrollbar column takes precedence over pane content clicks
+    if button == MouseButton::Left && start_scrollbar_drag(target, mouse, layout, terminal, assistant, drag_state) {
+        return Ok(());
+    }
+
     match target {
         MouseTarget::Terminal => {
             // Check if terminal program wants mouse events
@@ -347,32 +407,59 @@ fn handle_mouse_down(
                             }
                             MessageAreaClickResult::ExecuteCommand(_msg_idx) => {
                                 // Execute or copy the pending command (depending on verdict)
-                                let session_id = assistant.active_session_id();
-                                let pending_idx = assistant.current_suggestion_index();
-
-                                // Check if the command is denied (should copy instead of execute)
-                                if assistant.is_pending_command_denied() {
-                                    // Deny verdict: copy to clipboard instead of executing
-                                    if assistant.copy_pending_command().is_some() {
-                                        // Update backend state
-                                        ai_sessions.reject_suggestion(session_id);
-                                    }
-                                } else {
-                                    // Allow/RequireConfirmation verdict: execute the command
-                                    if let Some(command) = ai_sessions.accept_suggestion(session_id, pending_idx) {
-                                        // Update UI to show command as executed
-                                        assistant.confirm_command();
-                                        // Tell the session manager to execute the suggested command
-                                        ai_sessions.execute_suggestion(session_id, command)?;
-                                    }
-                                }
-                                return Ok(());
+                                return super::assistant::confirm_pending(assistant, ai_sessions);
                             }
                             MessageAreaClickResult::CancelCommand(_msg_idx) => {
                                 // Cancel the pending command
-                                let session_id = assistant.active_session_id();
-                                ai_sessions.reject_suggestion(session_id);
-                                assistant.reject_command();
+                                super::assistant::reject_pending(assistant, ai_sessions);
+                                return Ok(());
+                            }
+                            MessageAreaClickResult::ToggleFindings(msg_idx) => {
+                                // Show or hide why the card got its verdict
+                                assistant.toggle_findings(msg_idx);
+                                return Ok(());
+                            }
+                            MessageAreaClickResult::ExplainVerdict(msg_idx) => {
+                                // Ask the AI why the card got its verdict
+                                let why = assistant.why_verdict(msg_idx);
+                                super::assistant::explain_verdict(assistant, ai_sessions, why);
+                                return Ok(());
+                            }
+                            MessageAreaClickResult::ToggleChecklist(_msg_idx) => {
+                                // Pick several suggestions, or one at a time again
+                                assistant.toggle_checklist();
+                                return Ok(());
+                            }
+                            MessageAreaClickResult::ToggleCompare(_msg_idx) => {
+                                // List all the suggestions to compare, or one at a time again
+                                assistant.toggle_compare();
+                                return Ok(());
+                            }
+                            MessageAreaClickResult::CompareRow(row) => {
+                                // Highlight the clicked suggestion
+                                assistant.compare_row(row);
+                                return Ok(());
+                            }
+                            MessageAreaClickResult::ToggleChecklistRow(row) => {
+                                // Select or unselect the clicked suggestion
+                                if let Some(checklist) = assistant.checklist_mut() {
+                                    checklist.toggle_row(row);
+                                }
+                                return Ok(());
+                            }
+                            MessageAreaClickResult::Regenerate => {
+                                // Pick how to ask the question again
+                                assistant.open_regen_menu();
+                                return Ok(());
+                            }
+                            MessageAreaClickResult::ToggleSummary => {
+                                // Show or hide the summary of the folded turns
+                                assistant.toggle_summary();
+                                return Ok(());
+                            }
+                            MessageAreaClickResult::JumpToLatest => {
+                                // Back to the bottom, following what arrives
+                                assistant.scroll_to_bottom();
                                 return Ok(());
                             }
                             MessageAreaClickResult::None => {
@@ -394,6 +481,12 @@ fn handle_mouse_down(
                             }
                         }
                     }
+                    AssistantRegion::ContextChips => {
+                        // Leave the clicked section out of the next message, or put it back
+                        if let Some(kind) = assistant.chip_at(mouse.column, mouse.row) {
+                            assistant.toggle_attachment(kind);
+                        }
+                    }
                     AssistantRegion::TabBar => {
                         // Handle tab bar click
                         let inner = layout.assistant_inner;
@@ -402,29 +495,15 @@ fn handle_mouse_down(
                         match click_result {
                             TabClickResult::SwitchToTab(session_id) => {
                                 // Switch to the clicked session
-                                if ai_sessions.switch_session(session_id) {
-                                    assistant.switch_session(session_id);
-                                    let messages = ai_sessions.get_session_messages(session_id);
-                                    assistant.load_messages(messages);
-                                    assistant.sync_session_tabs(ai_sessions.get_session_tabs());
-                                }
+                                ai_sessions.switch_session(session_id);
                             }
                             TabClickResult::NewTab => {
-                                // Create a new session
-                                if let Ok(new_id) = ai_sessions.new_session() {
-                                    assistant.switch_session(new_id);
-                                    assistant.load_messages(vec![]);
-                                    assistant.sync_session_tabs(ai_sessions.get_session_tabs());
-                                }
+                                // Create a new session (or pick a template if any are configured)
+                                super::assistant::request_new_session(assistant, ai_sessions);
                             }
                             TabClickResult::CloseTab(session_id) => {
                                 // Close the session
-                                if let Some(new_id) = ai_sessions.close_session(session_id) {
-                                    assistant.switch_session(new_id);
-                                    let messages = ai_sessions.get_session_messages(new_id);
-                                    assistant.load_messages(messages);
-                                    assistant.sync_session_tabs(ai_sessions.get_session_tabs());
-                                }
+                                ai_sessions.close_session(session_id);
                             }
                             TabClickResult::None => {
                                 // Clicked on empty area - do nothing
@@ -474,6 +553,7 @@ fn handle_mouse_down(
                                     start_row: mouse.row,
                                     selection_started: false,
                                     is_input_box_drag: true,
+                                    is_scrollbar_drag: false,
                                 });
                             }
                         }
@@ -502,21 +582,13 @@ fn handle_mouse_down(
 }
 
 /// Handle mouse button up event.
-fn handle_mouse_up(
-    target: MouseTarget,
-    mouse: MouseEvent,
-    _button: MouseButton,
-    layout: &AppLayout,
-    terminal: &mut TuiTerminal,
-    assistant: &mut TuiAssistant,
-    shell: &mut ShellManager,
-    drag_state: &mut Option<MouseDragState>,
-    separator_drag: &mut Option<SeparatorDragState>,
-) -> Result<()> {
+/// Returns the ratio to commit if this ends a separator drag.
+fn handle_mouse_up(target: MouseTarget, mouse: MouseEvent, _button: MouseButton, ctx: MouseCtx<'_>) -> Result<Option<u16>> {
+    let MouseCtx { layout, terminal, assistant, shell, drag_state, separator_drag, .. } = ctx;
+
     // End separator drag if active
-    if separator_drag.is_some() {
-        *separator_drag = None;
-        return Ok(());
+    if let Some(sep_state) = separator_drag.take() {
+        return Ok(Some(sep_state.ratio));
     }
 
     // If terminal has mouse mode enabled, forward the event
@@ -526,7 +598,9 @@ fn handle_mouse_up(
 
     // If we have an active drag, finalize the selection
     if let Some(state) = drag_state.take() {
-        if state.selection_started {
+        if state.is_scrollbar_drag {
+            // Scrubbing already moved the view; nothing to finalize
+        } else if state.selection_started {
             // Update final cursor position
             match state.target {
                 MouseTarget::Terminal => {
@@ -540,39 +614,31 @@ fn handle_mouse_up(
         } else {
             // Mouse was pressed but not dragged - exit visual mode if entered
             match state.target {
-                MouseTarget::Terminal => {
-                    if terminal.is_visual_mode() && !terminal.is_visual_selecting() {
-                        terminal.exit_visual_mode();
-                    }
+                MouseTarget::Terminal
+                    if terminal.is_visual_mode() && !terminal.is_visual_selecting() =>
+                {
+                    terminal.exit_visual_mode();
                 }
-                MouseTarget::Assistant => {
-                    if assistant.is_visual_mode() && !assistant.is_visual_selecting() {
-                        assistant.exit_visual_mode();
-                    }
+                MouseTarget::Assistant
+                    if assistant.is_visual_mode() && !assistant.is_visual_selecting() =>
+                {
+                    assistant.exit_visual_mode();
                 }
                 _ => {}
             }
         }
     }
 
-    Ok(())
+    Ok(None)
 }
 
 /// Handle mouse drag event.
-/// Returns Some(ratio) if separator was dragged and split ratio should be updated.
-fn handle_mouse_drag(
-    target: MouseTarget,
-    mouse: MouseEvent,
-    button: MouseButton,
-    layout: &AppLayout,
-    terminal: &mut TuiTerminal,
-    assistant: &mut TuiAssistant,
-    shell: &mut ShellManager,
-    drag_state: &mut Option<MouseDragState>,
-    separator_drag: &mut Option<SeparatorDragState>,
-) -> Result<Option<u16>> {
+/// Returns Some(ratio) if the separator moved: the drag now previews that ratio.
+fn handle_mouse_drag(target: MouseTarget, mouse: MouseEvent, button: MouseButton, ctx: MouseCtx<'_>) -> Result<Option<u16>> {
+    let MouseCtx { layout, terminal, assistant, shell, drag_state, separator_drag, .. } = ctx;
+
     // Handle separator drag first
-    if let Some(sep_state) = separator_drag.as_ref() {
+    if let Some(sep_state) = separator_drag.as_mut() {
         if button == MouseButton::Left {
             // Calculate new split ratio based on mouse position
             let full_width = layout.full_area.width;
@@ -583,9 +649,9 @@ fn handle_mouse_drag(
                 // Clamp to valid range (10-90%)
                 let clamped_ratio = new_ratio.clamp(10, 90);
 
-                // Only update if significantly different from initial to avoid jitter
-                let delta = (clamped_ratio as i32 - sep_state.initial_ratio as i32).unsigned_abs();
-                if delta >= 1 {
+                // Only re-layout when the ratio changes, not on every cell moved
+                if clamped_ratio != sep_state.ratio {
+                    sep_state.ratio = clamped_ratio;
                     return Ok(Some(clamped_ratio));
                 }
             }
@@ -605,6 +671,11 @@ fn handle_mouse_drag(
             return Ok(None);
         }
 
+        if state.is_scrollbar_drag {
+            scrub_scrollbar(state.target, mouse, layout, terminal, assistant);
+            return Ok(None);
+        }
+
         match state.target {
             MouseTarget::Terminal => {
                 // Check if we've moved enough to start selection
@@ -668,6 +739,62 @@ fn handle_mouse_drag(
     Ok(None)
 }
 
+/// Start scrubbing if the click is on a shown scrollbar.
+/// Returns true if the click was consumed.
+fn start_scrollbar_drag(
+    target: MouseTarget,
+    mouse: MouseEvent,
+    layout: &AppLayout,
+    terminal: &mut TuiTerminal,
+    assistant: &mut TuiAssistant,
+    drag_state: &mut Option<MouseDragState>,
+) -> bool {
+    let on_scrollbar = match target {
+        MouseTarget::Terminal => {
+            !terminal.is_mouse_mode_enabled()
+                && terminal.scrollbar_hit(layout.terminal_inner, mouse.column, mouse.row).is_some()
+        }
+        MouseTarget::Assistant => assistant.scrollbar_hit(mouse.column, mouse.row).is_some(),
+        _ => false,
+    };
+    if !on_scrollbar {
+        return false;
+    }
+
+    scrub_scrollbar(target, mouse, layout, terminal, assistant);
+    *drag_state = Some(MouseDragState {
+        target,
+        start_col: mouse.column,
+        start_row: mouse.row,
+        selection_started: false,
+        is_input_box_drag: false,
+        is_scrollbar_drag: true,
+    });
+    true
+}
+
+/// Move the scroll position of the pane to follow the pointer on its scrollbar.
+/// Rows above or below the track clamp to its ends.
+fn scrub_scrollbar(
+    target: MouseTarget,
+    mouse: MouseEvent,
+    layout: &AppLayout,
+    terminal: &mut TuiTerminal,
+    assistant: &mut TuiAssistant,
+) {
+    match target {
+        MouseTarget::Terminal => {
+            let area = layout.terminal_inner;
+            terminal.scroll_to_scrollbar_row(mouse.row.saturating_sub(area.y), area.height);
+        }
+        MouseTarget::Assistant => {
+            let area = assistant.message_area();
+            assistant.scroll_to_scrollbar_row(mouse.row.saturating_sub(area.y));
+        }
+        _ => {}
+    }
+}
+
 /// Check if mouse has moved enough to be considered a drag (not just a click).
 fn has_moved_enough(col: u16, row: u16, start_col: u16, start_row: u16) -> bool {
     let col_diff = (col as i32 - start_col as i32).unsigned_abs();
@@ -739,6 +866,7 @@ fn start_terminal_drag(
         start_row: mouse.row,
         selection_started: false,
         is_input_box_drag: false,
+        is_scrollbar_drag: false,
     });
 
     Ok(())
@@ -775,6 +903,7 @@ fn start_assistant_drag(
         start_row: mouse.row,
         selection_started: false,
         is_input_box_drag: false,
+        is_scrollbar_drag: false,
     });
 
     Ok(())
@@ -875,7 +1004,7 @@ fn update_assistant_selection(
 /// - Assistant InputBox: inserts at cursor position
 fn handle_middle_click_paste(
     target: MouseTarget,
-    _terminal: &mut TuiTerminal,
+    terminal: &mut TuiTerminal,
     assistant: &mut TuiAssistant,
     shell: &mut ShellManager,
     active_pane: &ActivePane,
@@ -899,6 +1028,8 @@ fn handle_middle_click_paste(
     match target {
         MouseTarget::Terminal => {
             // Paste to terminal PTY
+            terminal.end_held_output();
+            terminal.record_input(clipboard_text.as_bytes());
             shell.handle_user_input(clipboard_text.as_bytes())?;
         }
         MouseTarget::Assistant => {
@@ -969,3 +1100,82 @@ fn select_assistant_line(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::buffer::Buffer;
+    use ratatui::layout::Rect;
+    use ratatui::widgets::Widget;
+
+    use crate::ui::assistant::SessionTab;
+    use crate::ui::layout::LayoutBuilder;
+
+    /// Column where `label` is drawn on the assistant's tab bar.
+    fn tab_label_column(assistant: &TuiAssistant, layout: &AppLayout, label: &str) -> Option<u16> {
+        let area = layout.assistant_inner;
+        let mut buf = Buffer::empty(area);
+        assistant.render(area, &mut buf);
+        let label: Vec<String> = label.chars().map(String::from).collect();
+        (area.x..area.x + area.width).find(|&x| {
+            label.iter().enumerate().all(|(i, ch)| {
+                x + (i as u16) < area.x + area.width && buf[(x + i as u16, area.y)].symbol() == ch
+            })
+        })
+    }
+
+    #[test]
+    fn test_click_right_after_resize_uses_new_layout() -> Result<(), Box<dyn std::error::Error>> {
+        let mut assistant = TuiAssistant::new();
+        assistant.sync_session_tabs(
+            (1..=3).map(|id| SessionTab { id, name: format!("Session {}", id), ..SessionTab::default() }).collect(),
+        );
+        let builder = LayoutBuilder::new();
+
+        // Rendered at the old size, then the host zooms in: fewer, wider cells
+        let old_layout = builder.build(Rect::new(0, 0, 200, 40));
+        assistant.render(old_layout.assistant_inner, &mut Buffer::empty(old_layout.assistant_inner));
+        let new_layout = builder.build(Rect::new(0, 0, 100, 40));
+
+        // What the next frame will show for the third tab
+        let col = tab_label_column(&assistant, &new_layout, "S3")
+            .ok_or("third tab not drawn")?;
+        // Re-render with the old caches to check the click would have missed
+        assistant.render(old_layout.assistant_inner, &mut Buffer::empty(old_layout.assistant_inner));
+        let row = new_layout.assistant_inner.y;
+        assert_ne!(hover_at(&new_layout, &assistant, col, row).tab, TabClickResult::SwitchToTab(3));
+
+        // The resize is applied before the click is handled
+        assistant.on_resize(new_layout.assistant_inner);
+        assert_eq!(hover_at(&new_layout, &assistant, col, row).tab, TabClickResult::SwitchToTab(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_chip_row_sits_above_the_input_box() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::context::ContextSnapshot;
+        use crate::ui::context_chips::{ChipKind, ContextSummary};
+
+        let mut assistant = TuiAssistant::new();
+        let snapshot = ContextSnapshot { cwd: "/srv/app".to_string(), ..ContextSnapshot::empty() };
+        assistant.set_context_summary(ContextSummary::from_snapshot(&snapshot, Some("main".to_string())));
+        let layout = LayoutBuilder::new().build(Rect::new(0, 0, 120, 40));
+        let inner = layout.assistant_inner;
+        assistant.render(inner, &mut Buffer::empty(inner));
+
+        let input_height = assistant.calculate_input_box_height(inner.height, inner.width);
+        let row = inner.y + inner.height - input_height - 1;
+        let at = |col| MouseEvent { kind: MouseEventKind::Moved, column: col, row, modifiers: KeyModifiers::NONE };
+        assert_eq!(get_assistant_region(&at(inner.x + 1), &layout, &assistant), AssistantRegion::ContextChips);
+        assert_eq!(assistant.chip_at(inner.x + 1, row), Some(ChipKind::Cwd));
+        // " 📁 /srv/app " is 13 columns wide, the branch chip follows a gap
+        assert_eq!(assistant.chip_at(inner.x + 14, row), Some(ChipKind::Git));
+        assert_eq!(assistant.chip_at(inner.x + 13, row), None);
+        let above = MouseEvent { row: row - 1, ..at(inner.x + 1) };
+        assert_eq!(get_assistant_region(&above, &layout, &assistant), AssistantRegion::MessageArea);
+
+        assistant.toggle_attachment(ChipKind::Git);
+        assert!(!assistant.attachments().includes(ChipKind::Git));
+        Ok(())
+    }
+}
@@ -1,10 +1,9 @@
 //! Key event handling for the Terminal pane.
 
 use anyhow::Result;
-use crossterm::event::{KeyEvent, KeyEventKind, KeyCode, KeyModifiers};
-
-use super::UserEvent;
+use crossterm::event::{KeyEvent, KeyCode, KeyEventKind, KeyModifiers};
 
+use crate::event::UserEvent;
 use crate::ui::terminal::TuiTerminal;
 use crate::shell::ShellManager;
 
@@ -20,8 +19,7 @@ pub fn handle_key_event(
 ) -> Result<()> {
     let KeyEvent { code, modifiers, .. } = key_evt;
     let shift = modifiers.contains(KeyModifiers::SHIFT);
-    let ctrl = modifiers.contains(KeyModifiers::CONTROL);
-    let alt = modifiers.contains(KeyModifiers::ALT);
+    terminal.clear_notice();
 
     // Handle scrolling with Shift + PageUp/PageDown/Up/Down
     if shift {
@@ -50,6 +48,32 @@ pub fn handle_key_event(
         }
     }
 
+    send_key(terminal, shell, key_evt, shell_input_buffer, true)
+}
+
+/// Send a key of a keyboard macro being replayed to the shell, as if typed.
+pub fn replay_key(
+    terminal: &mut TuiTerminal,
+    shell: &mut ShellManager,
+    key_evt: KeyEvent,
+    shell_input_buffer: &mut String,
+) -> Result<()> {
+    send_key(terminal, shell, key_evt, shell_input_buffer, false)
+}
+
+/// Forward a key to the shell, tracking the command line it types. A key
+/// `typed` by the user goes into the macro being recorded too.
+fn send_key(
+    terminal: &mut TuiTerminal,
+    shell: &mut ShellManager,
+    key_evt: KeyEvent,
+    shell_input_buffer: &mut String,
+    typed: bool,
+) -> Result<()> {
+    let KeyEvent { code, modifiers, .. } = key_evt;
+    let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+    let alt = modifiers.contains(KeyModifiers::ALT);
+
     // For any other input, if scrolled back, auto-scroll to bottom
     if terminal.is_scrolled() {
         // Only auto-scroll for actual input keys (not just modifiers)
@@ -69,10 +93,15 @@ pub fn handle_key_event(
                 shell_input_buffer.pop();
             }
             KeyCode::Enter => {
-                // Record command in log if non-empty
+                // Remember where the command line is, so its output can be selected later
                 let cmd = shell_input_buffer.trim();
+                let position = terminal.mark_command_start(cmd);
+                // Record command in log if non-empty
                 if !cmd.is_empty() {
-                    shell.start_new_command(cmd.to_string());
+                    shell.start_new_command(cmd.to_string(), None);
+                    if let Some(position) = position {
+                        shell.set_command_position(position);
+                    }
                 }
                 // Clear buffer after Enter
                 shell_input_buffer.clear();
@@ -93,28 +122,25 @@ pub fn handle_key_event(
     // Convert key event to bytes and forward to shell
     let bytes = key_to_bytes(key_evt);
     if !bytes.is_empty() {
+        if typed {
+            terminal.macros_mut().typed(key_evt);
+        }
+        terminal.end_held_output();
+        terminal.record_input(&bytes);
         shell.handle_user_input(&bytes)?;
     }
 
     Ok(())
 }
 
-/// Handle command mode keys specific to Terminal pane.
+/// Handle Terminal-pane specific commands in command mode.
 ///
 /// Returns true if the event was handled.
-pub fn handle_command_mode(
-    terminal: &mut TuiTerminal,
-    shell: &mut ShellManager,
-    shell_input_buffer: &mut String,
-    event: UserEvent,
-) -> Result<bool> {
+pub fn handle_command_mode(terminal: &mut TuiTerminal, event: UserEvent) -> Result<bool> {
     match event {
-        // Forward Ctrl+B to terminal
-        UserEvent::Key(e) if
-            matches!(e.kind, KeyEventKind::Press)
-         && matches!(e.modifiers, KeyModifiers::CONTROL)
-         && matches!(e.code, KeyCode::Char('b') | KeyCode::Char('B')) => {
-            handle_key_event(terminal, shell, e, shell_input_buffer)?;
+        // o => select output of the last command (enters visual mode)
+        UserEvent::Key(e) if matches!(e.kind, KeyEventKind::Press) && matches!(e.code, KeyCode::Char('o') | KeyCode::Char('O')) => {
+            let _ = terminal.select_command_output();
             Ok(true)
         }
         _ => Ok(false),
@@ -122,7 +148,7 @@ pub fn handle_command_mode(
 }
 
 /// Converts a crossterm key event to terminal byte sequence.
-fn key_to_bytes(key_event: KeyEvent) -> Vec<u8> {
+pub(crate) fn key_to_bytes(key_event: KeyEvent) -> Vec<u8> {
     let KeyEvent { code, modifiers, .. } = key_event;
 
     let ctrl = modifiers.contains(KeyModifiers::CONTROL);
@@ -172,7 +198,7 @@ fn key_to_bytes(key_event: KeyEvent) -> Vec<u8> {
         KeyCode::PageDown => vec![0x1b, b'[', b'6', b'~'],
         KeyCode::Delete => vec![0x1b, b'[', b'3', b'~'],
         KeyCode::Insert => vec![0x1b, b'[', b'2', b'~'],
-        KeyCode::F(n) if n >= 1 && n <= 12 => {
+        KeyCode::F(n) if (1..=12).contains(&n) => {
             match n {
                 1 => vec![0x1b, b'O', b'P'],
                 2 => vec![0x1b, b'O', b'Q'],
@@ -0,0 +1,632 @@
+//! Event sequencing and tracing for debugging ordering bugs.
+//!
+//! Every `AiStreamData`, `AiUiUpdate` and `AppEvent` is wrapped in a [`Stamped`]
+//! envelope when it is created. The stamp carries a process-wide monotonically
+//! increasing sequence id and an origin tag, so the order in which events were
+//! *created* can be compared with the order in which they are *applied*.
+//!
+//! - [`EventTrace`] is a fixed-size ring of the most recently processed events,
+//!   rendered by the debug overlay and by `--dump-events-on-exit`.
+//! - [`OrderingChecker`] validates AI stream data as it is applied and flags
+//!   out-of-order application (chunks after End, updates after session close).
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+use crate::ai::session::SessionId;
+
+use super::{AiStreamData, AiUiUpdate, AppEvent, UserEvent};
+
+/// Global sequence counter shared by all event sources.
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// Allocate the next event sequence id.
+///
+/// Sequence ids are unique and strictly increasing across all threads.
+pub fn next_seq() -> u64 {
+    NEXT_SEQ.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Where an event was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventOrigin {
+    /// Terminal input thread (keyboard, mouse, resize)
+    User,
+    /// Spawned AI streaming task
+    AiStream,
+    /// AiSessionManager (UI updates, command execution requests)
+    AiSession,
+    /// PTY reader thread / shell manager
+    Shell,
+    /// Terminal emulator widget
+    Terminal,
+}
+
+impl fmt::Display for EventOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EventOrigin::User => "user",
+            EventOrigin::AiStream => "ai-stream",
+            EventOrigin::AiSession => "ai-session",
+            EventOrigin::Shell => "shell",
+            EventOrigin::Terminal => "terminal",
+        };
+        f.write_str(name)
+    }
+}
+
+/// An event tagged with its sequence id, origin and creation time.
+#[derive(Debug, Clone)]
+pub struct Stamped<T> {
+    pub seq: u64,
+    pub origin: EventOrigin,
+    pub created_at: Instant,
+    pub event: T,
+}
+
+impl<T> Stamped<T> {
+    /// Stamp an event with the next sequence id.
+    pub fn new(origin: EventOrigin, event: T) -> Self {
+        Self {
+            seq: next_seq(),
+            origin,
+            created_at: Instant::now(),
+            event,
+        }
+    }
+}
+
+/// Events that can be recorded in the [`EventTrace`].
+pub trait Traceable {
+    /// Short name of the event variant.
+    fn kind(&self) -> &'static str;
+
+    /// Session the event belongs to, if any.
+    fn session_id(&self) -> Option<SessionId> {
+        None
+    }
+}
+
+impl Traceable for AiStreamData {
+    fn kind(&self) -> &'static str {
+        match self {
+            AiStreamData::Chunk { .. } => "StreamChunk",
+            AiStreamData::Reasoning { .. } => "StreamReasoning",
+            AiStreamData::SuggestionDraft { .. } => "StreamSuggestionDraft",
+            AiStreamData::ToolCalls { .. } => "StreamToolCalls",
+            AiStreamData::Usage { .. } => "StreamUsage",
+            AiStreamData::Timing { .. } => "StreamTiming",
+            AiStreamData::End { .. } => "StreamEnd",
+            AiStreamData::Truncated { .. } => "StreamTruncated",
+            AiStreamData::Error { .. } => "StreamError",
+            AiStreamData::Warning { .. } => "StreamWarning",
+            AiStreamData::Status { .. } => "StreamStatus",
+            AiStreamData::ToolsUnsupported { .. } => "StreamToolsUnsupported",
+            AiStreamData::ModelNotFound { .. } => "StreamModelNotFound",
+            AiStreamData::Summary { .. } => "StreamSummary",
+            AiStreamData::SessionName { .. } => "StreamSessionName",
+            AiStreamData::Rationale { .. } => "StreamRationale",
+            AiStreamData::Alternative { .. } => "StreamAlternative",
+            AiStreamData::Quotes { .. } => "StreamQuotes",
+        }
+    }
+
+    fn session_id(&self) -> Option<SessionId> {
+        match self {
+            AiStreamData::Chunk { session_id, .. }
+            | AiStreamData::Reasoning { session_id, .. }
+            | AiStreamData::SuggestionDraft { session_id, .. }
+            | AiStreamData::ToolCalls { session_id, .. }
+            | AiStreamData::Usage { session_id, .. }
+            | AiStreamData::Timing { session_id, .. }
+            | AiStreamData::End { session_id, .. }
+            | AiStreamData::Truncated { session_id, .. }
+            | AiStreamData::Error { session_id, .. }
+            | AiStreamData::Warning { session_id, .. }
+            | AiStreamData::Status { session_id, .. }
+            | AiStreamData::ToolsUnsupported { session_id }
+            | AiStreamData::ModelNotFound { session_id, .. }
+            | AiStreamData::Summary { session_id, .. }
+            | AiStreamData::SessionName { session_id, .. }
+            | AiStreamData::Rationale { session_id, .. }
+            | AiStreamData::Alternative { session_id, .. }
+            | AiStreamData::Quotes { session_id, .. } => Some(*session_id),
+        }
+    }
+}
+
+impl Traceable for AiUiUpdate {
+    fn kind(&self) -> &'static str {
+        match self {
+            AiUiUpdate::Chunk { .. } => "UiChunk",
+            AiUiUpdate::Reasoning { .. } => "UiReasoning",
+            AiUiUpdate::Timing { .. } => "UiTiming",
+            AiUiUpdate::End { .. } => "UiEnd",
+            AiUiUpdate::Truncated { .. } => "UiTruncated",
+            AiUiUpdate::Error { .. } => "UiError",
+            AiUiUpdate::ModelNotFound { .. } => "UiModelNotFound",
+            AiUiUpdate::Warning { .. } => "UiWarning",
+            AiUiUpdate::Status { .. } => "UiStatus",
+            AiUiUpdate::CommandSuggestionDraft { .. } => "UiCommandSuggestionDraft",
+            AiUiUpdate::CommandSuggestion { .. } => "UiCommandSuggestion",
+            AiUiUpdate::Explanation { .. } => "UiExplanation",
+            AiUiUpdate::Summary { .. } => "UiSummary",
+            AiUiUpdate::Rationale { .. } => "UiRationale",
+            AiUiUpdate::SaferAlternative { .. } => "UiSaferAlternative",
+            AiUiUpdate::ContextPart { .. } => "UiContextPart",
+            AiUiUpdate::Quotes { .. } => "UiQuotes",
+        }
+    }
+
+    fn session_id(&self) -> Option<SessionId> {
+        match self {
+            AiUiUpdate::Chunk { session_id, .. }
+            | AiUiUpdate::Reasoning { session_id, .. }
+            | AiUiUpdate::Timing { session_id, .. }
+            | AiUiUpdate::End { session_id, .. }
+            | AiUiUpdate::Truncated { session_id, .. }
+            | AiUiUpdate::Error { session_id, .. }
+            | AiUiUpdate::ModelNotFound { session_id, .. }
+            | AiUiUpdate::Warning { session_id, .. }
+            | AiUiUpdate::Status { session_id, .. }
+            | AiUiUpdate::CommandSuggestionDraft { session_id, .. }
+            | AiUiUpdate::CommandSuggestion { session_id, .. }
+            | AiUiUpdate::Explanation { session_id, .. }
+            | AiUiUpdate::Summary { session_id, .. }
+            | AiUiUpdate::Rationale { session_id, .. }
+            | AiUiUpdate::SaferAlternative { session_id, .. }
+            | AiUiUpdate::ContextPart { session_id, .. }
+            | AiUiUpdate::Quotes { session_id, .. } => Some(*session_id),
+        }
+    }
+}
+
+impl Traceable for AppEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            AppEvent::ExecuteAiCommand { .. } => "ExecuteAiCommand",
+            AppEvent::ExecuteAiBatch { .. } => "ExecuteAiBatch",
+            AppEvent::ShellError { .. } => "ShellError",
+            AppEvent::ShellCommandCompleted { .. } => "ShellCommandCompleted",
+            AppEvent::ShellOutput { .. } => "ShellOutput",
+            AppEvent::ShellCwdChanged { .. } => "ShellCwdChanged",
+            AppEvent::ProjectRead { .. } => "ProjectRead",
+            AppEvent::PtyWrite(_) => "PtyWrite",
+        }
+    }
+
+    fn session_id(&self) -> Option<SessionId> {
+        match self {
+            AppEvent::ExecuteAiCommand { session_id, .. } | AppEvent::ExecuteAiBatch { session_id, .. } => {
+                Some(*session_id)
+            }
+            AppEvent::ShellCommandCompleted { origin, .. } => origin.as_ref().map(|origin| origin.session_id),
+            _ => None,
+        }
+    }
+}
+
+/// Trace name for a user input event, or `None` for high-frequency events
+/// (mouse motion, drags) that would flush the ring.
+pub fn user_event_kind(event: &UserEvent) -> Option<&'static str> {
+    use crossterm::event::MouseEventKind;
+
+    match event {
+        UserEvent::Key(_) => Some("Key"),
+        UserEvent::Resize(..) => Some("Resize"),
+        UserEvent::Paste(_) => Some("Paste"),
+        UserEvent::Mouse(m) => match m.kind {
+            MouseEventKind::Down(_) => Some("MouseDown"),
+            MouseEventKind::Up(_) => Some("MouseUp"),
+            _ => None,
+        },
+        UserEvent::FocusGained | UserEvent::FocusLost => None,
+    }
+}
+
+// =============================================================================
+// Event Trace Ring
+// =============================================================================
+
+/// One processed event in the trace ring.
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub seq: u64,
+    pub origin: EventOrigin,
+    pub kind: &'static str,
+    pub session_id: Option<SessionId>,
+    /// Wall-clock time at which the event was applied
+    pub applied_at: DateTime<Local>,
+    /// Time the event spent between creation and application
+    pub queued_for: Duration,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} #{:<6} {:<10} {:<22}",
+            self.applied_at.format("%H:%M:%S%.3f"),
+            self.seq,
+            self.origin,
+            self.kind,
+        )?;
+        if let Some(id) = self.session_id {
+            write!(f, " session={}", id)?;
+        }
+        write!(f, " (+{}ms)", self.queued_for.as_millis())
+    }
+}
+
+/// Fixed-size ring of the most recently processed events.
+#[derive(Debug)]
+pub struct EventTrace {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl Default for EventTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventTrace {
+    /// Default number of events kept in the ring.
+    pub const DEFAULT_CAPACITY: usize = 500;
+
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record a stamped event as it is being applied.
+    pub fn record<T: Traceable>(&mut self, stamped: &Stamped<T>) {
+        self.push(TraceEntry {
+            seq: stamped.seq,
+            origin: stamped.origin,
+            kind: stamped.event.kind(),
+            session_id: stamped.event.session_id(),
+            applied_at: Local::now(),
+            queued_for: stamped.created_at.elapsed(),
+        });
+    }
+
+    /// Record an event that was not stamped at creation (e.g. user input).
+    pub fn record_unstamped(&mut self, origin: EventOrigin, kind: &'static str) {
+        self.push(TraceEntry {
+            seq: next_seq(),
+            origin,
+            kind,
+            session_id: None,
+            applied_at: Local::now(),
+            queued_for: Duration::ZERO,
+        });
+    }
+
+    fn push(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Entries from oldest to newest.
+    pub fn entries(&self) -> impl DoubleEndedIterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Render the whole ring as text, one event per line, for bug reports.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        for entry in &self.entries {
+            out.push_str(&entry.to_string());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// =============================================================================
+// Ordering Checks
+// =============================================================================
+
+/// An event was applied in an order that should not be possible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderingViolation {
+    /// Event was created before one already applied for the same session
+    OutOfOrder {
+        session_id: SessionId,
+        kind: &'static str,
+        seq: u64,
+        last_applied: u64,
+    },
+    /// Stream content arrived for a session whose stream already ended
+    AfterEnd {
+        session_id: SessionId,
+        kind: &'static str,
+        seq: u64,
+    },
+    /// Event arrived for a session that has been closed
+    AfterClose {
+        session_id: SessionId,
+        kind: &'static str,
+        seq: u64,
+    },
+}
+
+impl fmt::Display for OrderingViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderingViolation::OutOfOrder { session_id, kind, seq, last_applied } => write!(
+                f,
+                "{} #{} for session {} applied after #{}",
+                kind, seq, session_id, last_applied
+            ),
+            OrderingViolation::AfterEnd { session_id, kind, seq } => write!(
+                f,
+                "{} #{} for session {} applied after its stream ended",
+                kind, seq, session_id
+            ),
+            OrderingViolation::AfterClose { session_id, kind, seq } => write!(
+                f,
+                "{} #{} for session {} applied after the session was closed",
+                kind, seq, session_id
+            ),
+        }
+    }
+}
+
+/// Validates the order in which AI stream data is applied.
+///
+/// In strict mode a violation also trips a `debug_assert!`, which makes the
+/// race loud in tests and debug builds.
+#[derive(Debug, Default)]
+pub struct OrderingChecker {
+    strict: bool,
+    last_applied: HashMap<SessionId, u64>,
+    streaming: HashSet<SessionId>,
+    closed: HashSet<SessionId>,
+}
+
+impl OrderingChecker {
+    /// Create a checker that only reports violations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a checker that also `debug_assert!`s on every violation.
+    pub fn strict() -> Self {
+        Self {
+            strict: true,
+            ..Self::default()
+        }
+    }
+
+    /// A new response stream has been requested for the session.
+    pub fn begin_stream(&mut self, session_id: SessionId) {
+        self.streaming.insert(session_id);
+    }
+
+    /// The session has been closed; any later data for it is stale.
+    pub fn close_session(&mut self, session_id: SessionId) {
+        self.streaming.remove(&session_id);
+        self.last_applied.remove(&session_id);
+        self.closed.insert(session_id);
+    }
+
+    /// Check stream data that is about to be applied.
+    pub fn observe(&mut self, data: &Stamped<AiStreamData>) -> Result<(), OrderingViolation> {
+        let result = self.check(data);
+        debug_assert!(!self.strict || result.is_ok(), "event ordering violation: {:?}", result);
+        result
+    }
+
+    fn check(&mut self, data: &Stamped<AiStreamData>) -> Result<(), OrderingViolation> {
+        let kind = data.event.kind();
+        let seq = data.seq;
+        let Some(session_id) = data.event.session_id() else {
+            return Ok(());
+        };
+        // Summaries, names, rationales and alternatives come from a request
+        // of their own, whenever it finishes; quotes from a task of their own
+        if matches!(
+            data.event,
+            AiStreamData::Summary { .. }
+                | AiStreamData::SessionName { .. }
+                | AiStreamData::Rationale { .. }
+                | AiStreamData::Alternative { .. }
+                | AiStreamData::Quotes { .. }
+        ) {
+            return Ok(());
+        }
+
+        if self.closed.contains(&session_id) {
+            return Err(OrderingViolation::AfterClose { session_id, kind, seq });
+        }
+
+        if let Some(&last_applied) = self.last_applied.get(&session_id)
+            && seq <= last_applied
+        {
+            return Err(OrderingViolation::OutOfOrder { session_id, kind, seq, last_applied });
+        }
+        self.last_applied.insert(session_id, seq);
+
+        match &data.event {
+            AiStreamData::Chunk { .. }
+            | AiStreamData::Reasoning { .. }
+            | AiStreamData::SuggestionDraft { .. }
+            | AiStreamData::ToolCalls { .. }
+            | AiStreamData::Usage { .. }
+            | AiStreamData::Timing { .. }
+            | AiStreamData::Warning { .. }
+            | AiStreamData::Status { .. }
+            | AiStreamData::ToolsUnsupported { .. } => {
+                if !self.streaming.contains(&session_id) {
+                    return Err(OrderingViolation::AfterEnd { session_id, kind, seq });
+                }
+            }
+            AiStreamData::End { .. }
+            | AiStreamData::Truncated { .. }
+            | AiStreamData::Error { .. }
+            | AiStreamData::ModelNotFound { .. } => {
+                self.streaming.remove(&session_id);
+            }
+            AiStreamData::Summary { .. }
+            | AiStreamData::SessionName { .. }
+            | AiStreamData::Rationale { .. }
+            | AiStreamData::Alternative { .. }
+            | AiStreamData::Quotes { .. } => {}
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn chunk(session_id: SessionId, text: &str) -> Stamped<AiStreamData> {
+        Stamped::new(EventOrigin::AiStream, AiStreamData::Chunk { session_id, text: text.into() })
+    }
+
+    fn end(session_id: SessionId) -> Stamped<AiStreamData> {
+        Stamped::new(EventOrigin::AiStream, AiStreamData::End { session_id, cached: false })
+    }
+
+    /// Push events through a fake stream channel in the given order and
+    /// collect the checker's verdict for each one.
+    fn replay(
+        checker: &mut OrderingChecker,
+        events: Vec<Stamped<AiStreamData>>,
+    ) -> Vec<Result<(), OrderingViolation>> {
+        let (tx, mut rx) = mpsc::channel(events.len().max(1));
+        for ev in events {
+            assert!(tx.try_send(ev).is_ok());
+        }
+        drop(tx);
+
+        let mut results = Vec::new();
+        while let Ok(ev) = rx.try_recv() {
+            results.push(checker.observe(&ev));
+        }
+        results
+    }
+
+    #[test]
+    fn test_sequence_ids_are_monotonic() {
+        let a = chunk(1, "a");
+        let b = chunk(1, "b");
+        let c = end(1);
+        assert!(a.seq < b.seq);
+        assert!(b.seq < c.seq);
+    }
+
+    #[test]
+    fn test_in_order_stream_passes() {
+        let mut checker = OrderingChecker::strict();
+        checker.begin_stream(1);
+        let results = replay(&mut checker, vec![chunk(1, "a"), chunk(1, "b"), end(1)]);
+        assert!(results.iter().all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn test_reordered_end_is_flagged() {
+        let mut checker = OrderingChecker::new();
+        checker.begin_stream(1);
+        let first = chunk(1, "a");
+        let second = chunk(1, "b");
+        let last = end(1);
+        let (second_seq, last_seq) = (second.seq, last.seq);
+
+        // End overtakes a chunk that was created before it
+        let results = replay(&mut checker, vec![first, last, second]);
+
+        assert_eq!(results[0], Ok(()));
+        assert_eq!(results[1], Ok(()));
+        assert_eq!(
+            results[2],
+            Err(OrderingViolation::OutOfOrder {
+                session_id: 1,
+                kind: "StreamChunk",
+                seq: second_seq,
+                last_applied: last_seq,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chunk_after_end_is_flagged() {
+        let mut checker = OrderingChecker::new();
+        checker.begin_stream(1);
+        let results = replay(&mut checker, vec![end(1), chunk(1, "late")]);
+        assert_eq!(results[0], Ok(()));
+        assert!(matches!(results[1], Err(OrderingViolation::AfterEnd { session_id: 1, .. })));
+    }
+
+    #[test]
+    fn test_update_after_close_is_flagged() {
+        let mut checker = OrderingChecker::new();
+        checker.begin_stream(2);
+        let pending = chunk(2, "in flight");
+        checker.close_session(2);
+        let results = replay(&mut checker, vec![pending]);
+        assert!(matches!(results[0], Err(OrderingViolation::AfterClose { session_id: 2, .. })));
+    }
+
+    #[test]
+    fn test_sessions_are_checked_independently() {
+        let mut checker = OrderingChecker::strict();
+        checker.begin_stream(1);
+        checker.begin_stream(2);
+        let a1 = chunk(1, "a");
+        let b1 = chunk(2, "b");
+        let results = replay(&mut checker, vec![b1, a1, end(2), end(1)]);
+        assert!(results.iter().all(|r| r.is_ok()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "event ordering violation")]
+    fn test_strict_checker_asserts_on_reorder() {
+        let mut checker = OrderingChecker::strict();
+        checker.begin_stream(1);
+        let early = chunk(1, "a");
+        replay(&mut checker, vec![end(1), early]);
+    }
+
+    #[test]
+    fn test_trace_ring_is_bounded() {
+        let mut trace = EventTrace::with_capacity(3);
+        for i in 0..5 {
+            trace.record(&chunk(1, &i.to_string()));
+        }
+        assert_eq!(trace.len(), 3);
+        let seqs: Vec<u64> = trace.entries().map(|e| e.seq).collect();
+        assert!(seqs.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(trace.dump().lines().count(), 3);
+    }
+}
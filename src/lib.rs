@@ -29,6 +29,9 @@
 //!         recent_history: vec![],
 //!         recent_output: vec![],
 //!         recent_commands: vec![],
+//!         project: None,
+//!         files: Vec::new(),
+//!         screen: None,
 //!     };
 //!
 //!     let session_id = manager.current_session_id();
@@ -41,8 +44,10 @@
 
 pub mod ai;
 pub mod app;
+pub mod config;
 pub mod context;
 pub mod event;
+pub mod plugin;
 pub mod security;
 pub mod shell;
 pub mod ui;
@@ -5,6 +5,8 @@
 
 use rusty_term::utils;
 use rusty_term::app;
+use rusty_term::security::{AllowlistStore, security_command};
+use rusty_term::shell::{ShellKind, integration_script};
 
 use anyhow::Result;
 use app::App;
@@ -18,23 +20,83 @@ async fn main() -> Result<()> {
     // Initialize logging before anything else
     utils::logger::init_logging();
 
+    // `security export|import`: manage the allowlist without starting the UI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(("security", rest)) = args.split_first().map(|(first, rest)| (first.as_str(), rest)) {
+        // Changes are refused while a running RustyTerm owns the allowlist
+        let result = AllowlistStore::open().and_then(|mut store| security_command(rest, &mut store));
+        match result {
+            Ok(summary) => {
+                println!("{}", summary);
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // `shell-integration <shell>`: print the snippet that makes the shell
+    // mark its prompts and commands, for its startup file to evaluate
+    if let Some(("shell-integration", rest)) = args.split_first().map(|(first, rest)| (first.as_str(), rest)) {
+        let shell = rest.first().cloned().or_else(|| std::env::var("SHELL").ok()).unwrap_or_default();
+        match integration_script(ShellKind::from_program(&shell)) {
+            Some(script) => {
+                print!("{}", script);
+                return Ok(());
+            }
+            None => {
+                eprintln!("No shell integration for `{}`; bash and zsh are supported", shell);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // --dump-events-on-exit: print the event trace ring to stderr for bug reports
+    let dump_events_on_exit = std::env::args().any(|arg| arg == "--dump-events-on-exit");
+    // --performance / --no-performance: override `[display] performance`
+    let performance = args.iter().rev().find_map(|arg| match arg.as_str() {
+        "--performance" => Some(true),
+        "--no-performance" => Some(false),
+        _ => None,
+    });
+
     let mut terminal = ratatui::init();
 
     // Enable mouse capture for mouse event handling
     execute!(std::io::stdout(), EnableMouseCapture)?;
 
+    // From here on stderr goes to the log: a dependency printing there
+    // would paint over the UI
+    if let Err(e) = utils::logger::redirect_stderr() {
+        tracing::warn!("Failed to redirect stderr to the log file: {}", e);
+    }
+
     // Context guard ensures cleanup on both normal exit and panic
-    let _ctx = Context::with(|| {
+    let ctx = Context::with(|| {
         // Disable mouse capture before restoring terminal
         if let Err(e) = execute!(std::io::stdout(), DisableMouseCapture) {
             tracing::error!("Failed to disable mouse capture: {}", e);
         }
         ratatui::restore();
+        utils::logger::restore_stderr();
     });
 
-    let mut app = App::new()?;
+    let mut app = App::with_performance(performance)?;
     // draw 1st frame
     app.draw(&mut terminal)?;
     // run event-driven main loop of app
-    app.run(&mut terminal).await
-}
\ No newline at end of file
+    let result = app.run(&mut terminal).await;
+    // Keep whatever was typed but not sent, even if the loop failed
+    app.save_drafts();
+    app.stop_recording();
+
+    // Restore the terminal first so the dump lands on a clean screen
+    drop(ctx);
+    if dump_events_on_exit {
+        eprint!("{}", app.event_trace().dump());
+    }
+
+    result
+}
@@ -0,0 +1,205 @@
+//! Compiled-in plugins: code that watches the app, can hold suggested
+//! commands back and adds a few words to the status line.
+//!
+//! A plugin implements [`Plugin`] and is handed to
+//! [`App::register_plugin`](crate::app::App::register_plugin) before the
+//! event loop starts; there is no loading at run time. Hooks are called in
+//! registration order:
+//!
+//! - [`Plugin::on_event`] for every [`AppEvent`], before the app handles it
+//! - [`Plugin::on_pre_execute`] for every suggested command about to go
+//!   through the execution gate, whatever the other plugins said
+//! - [`Plugin::status_segment`] on every frame, for the terminal pane title
+//!
+//! Hooks run on the event loop, so they must not block: no network, no
+//! waiting on a child process, no file I/O that can stall. Longer work goes
+//! to a thread or task of the plugin's own, which reports back through a
+//! channel that a later hook drains (usually `on_event`, which gets `&mut
+//! self`), and `status_segment` shows the last value it got.
+//!
+//! Like project rules, plugins are only trusted to make the security policy
+//! stricter. A [`PluginVerdict`] becomes a finding next to the built-in ones,
+//! so a plugin can deny a command or ask for confirmation, never allow one
+//! that the policy holds back.
+
+use crate::config::project::Project;
+use crate::event::AppEvent;
+use crate::security::{Category, Finding, ProjectRules, Severity, Verdict, verdict_from};
+
+/// What a plugin can see of the app when a hook is called.
+#[derive(Debug, Clone, Copy)]
+pub struct PluginCtx<'a> {
+    /// Working directory of the shell, as last reported
+    pub cwd: &'a str,
+    /// The trusted project overlay in effect, if any
+    pub project: Option<&'a Project>,
+}
+
+/// A plugin's say on a command about to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginVerdict {
+    /// No objection; the rest of the policy decides
+    Allow,
+    /// Run it only after the user confirms, for this reason
+    Confirm(String),
+    /// Never run it, for this reason
+    Deny(String),
+}
+
+/// Hooks into the app. Every hook has a default that does nothing, so a
+/// plugin only implements the ones it needs. See the module docs for the
+/// rules they run under.
+pub trait Plugin {
+    /// An app event is about to be handled.
+    fn on_event(&mut self, _event: &AppEvent, _ctx: &PluginCtx<'_>) {}
+
+    /// A suggested command is about to be gated.
+    fn on_pre_execute(&mut self, _cmd: &str, _ctx: &PluginCtx<'_>) -> PluginVerdict {
+        PluginVerdict::Allow
+    }
+
+    /// A short text for the status line, or `None` to show nothing.
+    fn status_segment(&self) -> Option<String> {
+        None
+    }
+}
+
+/// The registered plugins, in registration order.
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    pub fn on_event(&mut self, event: &AppEvent, ctx: &PluginCtx<'_>) {
+        for plugin in &mut self.plugins {
+            plugin.on_event(event, ctx);
+        }
+    }
+
+    /// One finding per plugin that objects to `cmd`. Every plugin is asked,
+    /// even after one has denied it, so observers see every command.
+    pub fn findings(&mut self, cmd: &str, ctx: &PluginCtx<'_>) -> Vec<Finding> {
+        let span = cmd.len() - cmd.trim_start().len()..cmd.trim_end().len();
+        let mut findings = Vec::new();
+        for plugin in &mut self.plugins {
+            let (severity, message) = match plugin.on_pre_execute(cmd, ctx) {
+                PluginVerdict::Allow => continue,
+                PluginVerdict::Confirm(reason) => (Severity::Confirm, reason),
+                PluginVerdict::Deny(reason) => (Severity::Deny, reason),
+            };
+            findings.push(Finding { span: span.clone(), category: Category::Plugin, severity, message });
+        }
+        findings
+    }
+
+    /// Verdict of the built-in policy and `rules`, tightened by the
+    /// plugins. It is never less strict than `rules.evaluate(cmd)`, and of
+    /// the plugins' objections the first most serious one is reported.
+    pub fn evaluate(&mut self, cmd: &str, rules: &ProjectRules, ctx: &PluginCtx<'_>) -> Verdict {
+        let mut findings = rules.findings(cmd);
+        findings.extend(self.findings(cmd, ctx));
+        verdict_from(&findings)
+    }
+
+    /// Status texts of the plugins that have one, in registration order.
+    pub fn status_segments(&self) -> Vec<String> {
+        self.plugins.iter().filter_map(|plugin| plugin.status_segment()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Writes its name and the hook to a shared log and answers with a
+    /// fixed verdict and status.
+    struct Recorder {
+        name: &'static str,
+        log: Rc<RefCell<Vec<String>>>,
+        verdict: PluginVerdict,
+        status: Option<&'static str>,
+    }
+
+    impl Plugin for Recorder {
+        fn on_event(&mut self, _event: &AppEvent, _ctx: &PluginCtx<'_>) {
+            self.log.borrow_mut().push(format!("{} event", self.name));
+        }
+
+        fn on_pre_execute(&mut self, cmd: &str, _ctx: &PluginCtx<'_>) -> PluginVerdict {
+            self.log.borrow_mut().push(format!("{} {}", self.name, cmd));
+            self.verdict.clone()
+        }
+
+        fn status_segment(&self) -> Option<String> {
+            self.status.map(str::to_string)
+        }
+    }
+
+    fn registry(plugins: Vec<(&'static str, PluginVerdict, Option<&'static str>)>) -> (PluginRegistry, Rc<RefCell<Vec<String>>>) {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut registry = PluginRegistry::new();
+        for (name, verdict, status) in plugins {
+            registry.register(Box::new(Recorder { name, log: log.clone(), verdict, status }));
+        }
+        (registry, log)
+    }
+
+    const CTX: PluginCtx<'static> = PluginCtx { cwd: "/tmp", project: None };
+
+    #[test]
+    fn test_hooks_run_in_registration_order() {
+        let (mut registry, log) = registry(vec![
+            ("a", PluginVerdict::Deny("a says no".into()), Some("A")),
+            ("b", PluginVerdict::Allow, None),
+            ("c", PluginVerdict::Deny("c says no".into()), Some("C")),
+        ]);
+
+        registry.on_event(&AppEvent::PtyWrite("x".into()), &CTX);
+        // Every plugin sees the command, even after the first denial,
+        // and the first one's reason is the one reported
+        let verdict = registry.evaluate("ls", &ProjectRules::default(), &CTX);
+        assert_eq!(verdict, Verdict::Deny("a says no".into()));
+        assert_eq!(*log.borrow(), ["a event", "b event", "c event", "a ls", "b ls", "c ls"]);
+        assert_eq!(registry.status_segments(), ["A", "C"]);
+    }
+
+    #[test]
+    fn test_plugins_only_tighten_the_verdict() {
+        let rules = ProjectRules { deny: vec!["make deploy".into()], confirm: vec![] };
+        let (mut allow, _) = registry(vec![("a", PluginVerdict::Allow, None)]);
+        let (mut confirm, _) = registry(vec![("c", PluginVerdict::Confirm("check the ticket".into()), None)]);
+        let (mut deny, _) = registry(vec![("d", PluginVerdict::Deny("frozen".into()), None)]);
+
+        // Allowing never loosens the policy
+        for cmd in ["ls", "rm -rf /tmp/x", "sudo ls", "make deploy"] {
+            assert_eq!(allow.evaluate(cmd, &rules, &CTX), rules.evaluate(cmd), "{}", cmd);
+        }
+
+        // Objections hold back what the policy allows
+        assert_eq!(confirm.evaluate("ls", &rules, &CTX), Verdict::RequireConfirmation("check the ticket".into()));
+        assert_eq!(deny.evaluate("ls", &rules, &CTX), Verdict::Deny("frozen".into()));
+
+        // A confirmation does not soften a denial, and the built-in reason
+        // wins over a plugin's of the same severity
+        assert!(confirm.evaluate("make deploy", &rules, &CTX).is_deny());
+        let builtin = rules.evaluate("sudo ls");
+        assert!(matches!(builtin, Verdict::RequireConfirmation(_)));
+        assert_eq!(confirm.evaluate("sudo ls", &rules, &CTX), builtin);
+        assert_eq!(deny.evaluate("sudo ls", &rules, &CTX), Verdict::Deny("frozen".into()));
+    }
+}
@@ -1,9 +1,32 @@
 //! Command allowlist management.
 //!
-//! This module maintains a list of allowed command prefixes that can be
-//! executed without additional security warnings or blocks.
-
-use std::collections::HashSet;
+//! This module maintains a list of allowed command prefixes. A command a
+//! rule covers runs without the confirmations the built-in checks ask for
+//! ([`Allowlist::apply`]); their denials and the project's rules still stand.
+//!
+//! The rules the user added are kept in `allowlist.toml` in the data
+//! directory, each with when it was added and the command that prompted it.
+//! Like the other state files, it is owned by one instance at a time
+//! ([`AllowlistStore`]). [`Allowlist::export`] writes the rules to a
+//! commented TOML file to share or sync, and [`Allowlist::import`] merges
+//! such a file back in, refusing rules that would let through commands the
+//! built-in checks deny unless forced. Both are reachable as
+//! `/security export|import` in the assistant and as
+//! `rusty-term security export|import` (see [`security_command`]).
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use super::analyzer::denied_samples;
+use super::{Category, Finding, Severity, analyze_command, verdict_from};
+use crate::utils::persist::{self, PersistError, StateFile};
+
+/// File name in the data directory.
+const FILE_NAME: &str = "allowlist.toml";
 
 /// Verdict for command evaluation
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -31,10 +54,137 @@ impl Verdict {
     pub fn is_deny(&self) -> bool {
         matches!(self, Verdict::Deny(_))
     }
+
+    /// Check if this verdict is less severe than `other`
+    pub fn is_safer_than(&self, other: &Verdict) -> bool {
+        let severity = |verdict: &Verdict| match verdict {
+            Verdict::Allow => 0,
+            Verdict::RequireConfirmation(_) => 1,
+            Verdict::Deny(_) => 2,
+        };
+        severity(self) < severity(other)
+    }
+}
+
+/// A command prefix the user allowed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowRule {
+    pub prefix: String,
+    /// Seconds since the Unix epoch (0 = unknown)
+    pub created: u64,
+    /// The command that prompted the rule
+    pub origin: Option<String>,
+}
+
+/// How [`Allowlist::import`] combines a file with the rules already there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Add the file's rules, skipping prefixes already allowed
+    Merge,
+    /// The file's rules replace all of them
+    Replace,
+    /// Change nothing, only report what would change
+    DryRun,
+}
+
+/// What an import changed (or, for a dry run, would change).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub added: Vec<String>,
+    /// Already allowed
+    pub skipped: Vec<String>,
+    /// Dropped by a replace
+    pub removed: Vec<String>,
+    /// False for a dry run
+    pub applied: bool,
+}
+
+impl ImportSummary {
+    /// One paragraph for the assistant or the console.
+    pub fn describe(&self) -> String {
+        let list = |prefixes: &[String]| {
+            prefixes.iter().map(|prefix| format!("\n  {}", prefix)).collect::<String>()
+        };
+        let mut text = format!(
+            "{}{} added, {} already allowed, {} removed",
+            if self.applied { "" } else { "Dry run, nothing changed: " },
+            self.added.len(),
+            self.skipped.len(),
+            self.removed.len()
+        );
+        if !self.added.is_empty() {
+            text.push_str(&format!("\nAdded:{}", list(&self.added)));
+        }
+        if !self.removed.is_empty() {
+            text.push_str(&format!("\nRemoved:{}", list(&self.removed)));
+        }
+        text
+    }
+}
+
+/// Why an allowlist file could not be imported. Lines and columns count
+/// from 1.
+#[derive(Debug)]
+pub enum ImportError {
+    Io(io::Error),
+    /// Not a TOML allowlist
+    Malformed { line: usize, column: usize, message: String },
+    /// A rule that can't be used as a prefix
+    InvalidRule { line: usize, prefix: String, reason: String },
+    /// A rule letting through commands the built-in checks deny; taken
+    /// only when forced
+    LoosensDeny { line: usize, prefix: String, reason: String },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "{}", e),
+            ImportError::Malformed { line, column, message } => {
+                write!(f, "line {}, column {}: {}", line, column, message)
+            }
+            ImportError::InvalidRule { line, prefix, reason } => {
+                write!(f, "line {}: rule {:?} is invalid: {}", line, prefix, reason)
+            }
+            ImportError::LoosensDeny { line, prefix, reason } => write!(
+                f,
+                "line {}: rule {:?} would allow what the built-in checks deny ({}); use --force to import it anyway",
+                line, prefix, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+/// The allowlist file as written by [`Allowlist::export`].
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AllowlistFile {
+    #[serde(default)]
+    rule: Vec<FileRule>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileRule {
+    prefix: toml::Spanned<String>,
+    #[serde(default)]
+    created: u64,
+    #[serde(default)]
+    origin: Option<String>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Allowlist {
-    allowed_prefixes: HashSet<String>,
+    /// Rules the user added, oldest first
+    rules: Vec<AllowRule>,
 }
 
 impl Default for Allowlist {
@@ -46,135 +196,426 @@ impl Default for Allowlist {
 impl Allowlist {
     pub fn new() -> Self {
         Self {
-            allowed_prefixes: HashSet::new(),
+            rules: Vec::new(),
         }
     }
 
-    pub fn allow_prefix(&mut self, prefix: &str) {
-        self.allowed_prefixes.insert(prefix.to_string());
+    /// Path of the user's allowlist in the data directory (None if there
+    /// is none).
+    pub fn default_path() -> Option<PathBuf> {
+        persist::data_dir().map(|dir| dir.join(FILE_NAME))
     }
 
-    pub fn is_allowed(&self, cmd: &str) -> bool {
-        self.allowed_prefixes
-            .iter()
-            .any(|p| cmd.trim_start().starts_with(p))
+    /// The allowlist saved in `file`; empty if there is no file yet. The
+    /// user's own file is trusted as it is.
+    fn load(file: &StateFile) -> Result<Self, ImportError> {
+        match file.load()? {
+            Some(bytes) => Ok(Self { rules: parse(&String::from_utf8_lossy(&bytes), true)? }),
+            None => Ok(Self::new()),
+        }
     }
-}
-
-/// Evaluate a command and return a verdict on whether it can be executed.
-///
-/// # Rules
-/// - Deny: Commands containing shell composition tokens (|, ;, &&, ||, >, <, $(, backticks, &)
-/// - Allow: Minimal safe set (pwd, ls, whoami, date, uname, which, echo)
-/// - Git policy: Allow status/diff/log/show, deny push/reset/clean/..., others require confirmation
-/// - Default: RequireConfirmation for other commands
-pub fn evaluate(cmd: &str) -> Verdict {
-    let trimmed = cmd.trim();
 
-    // Empty command
-    if trimmed.is_empty() {
-        return Verdict::Deny("Empty command".to_string());
+    pub fn allow_prefix(&mut self, prefix: &str) {
+        self.trust(prefix, None);
     }
 
-    // Check for dangerous shell composition tokens
-    if contains_shell_composition(trimmed) {
-        return Verdict::Deny("Contains dangerous shell operators".to_string());
+    /// Allow `prefix` from now on, remembering the command that prompted it.
+    pub fn trust(&mut self, prefix: &str, origin: Option<&str>) {
+        if !self.contains(prefix) {
+            self.rules.push(AllowRule {
+                prefix: prefix.to_string(),
+                created: now(),
+                origin: origin.map(str::to_string),
+            });
+        }
     }
 
-    // Extract the base command (first word)
-    let base_cmd = trimmed.split_whitespace().next().unwrap_or("");
-
-    // Check minimal safe set
-    if is_safe_command(base_cmd) {
-        return Verdict::Allow;
+    pub fn rules(&self) -> &[AllowRule] {
+        &self.rules
     }
 
-    // Handle git commands specially
-    if base_cmd == "git" {
-        return evaluate_git_command(trimmed);
+    fn contains(&self, prefix: &str) -> bool {
+        self.rules.iter().any(|rule| rule.prefix == prefix)
     }
 
-    // Default: require confirmation
-    Verdict::RequireConfirmation("Requires confirmation".to_string())
-}
-
-/// Check if command contains dangerous shell composition tokens
-fn contains_shell_composition(cmd: &str) -> bool {
-    // Check for pipe
-    if cmd.contains('|') {
-        return true;
+    pub fn is_allowed(&self, cmd: &str) -> bool {
+        self.rules.iter().any(|rule| covers(&rule.prefix, cmd))
     }
 
-    // Check for semicolon
-    if cmd.contains(';') {
-        return true;
+    /// The `findings` for `cmd` with the allowlist applied: when a rule
+    /// covers the command and nothing denies it, the confirmations the
+    /// built-in checks ask for are dropped. Those of project rules stay.
+    pub fn apply(&self, cmd: &str, mut findings: Vec<Finding>) -> Vec<Finding> {
+        if self.is_allowed(cmd) && !findings.iter().any(|f| f.severity == Severity::Deny) {
+            findings.retain(|f| f.category == Category::ProjectRule);
+        }
+        findings
+    }
+
+    /// The rules as a commented TOML file, which [`Allowlist::import`] and
+    /// [`Allowlist::load`] read back.
+    pub fn to_toml(&self) -> String {
+        let mut text = String::from(
+            "# RustyTerm allowlist: commands starting with one of these prefixes run\n\
+             # without asking for confirmation (denials still stand). Import with\n\
+             # `/security import <file>` in the assistant or\n\
+             # `rusty-term security import <file>`.\n",
+        );
+        for rule in &self.rules {
+            text.push('\n');
+            if rule.created > 0
+                && let Some(created) = chrono::DateTime::from_timestamp(i64::try_from(rule.created).unwrap_or(0), 0)
+            {
+                text.push_str(&format!("# Added {}\n", created.format("%Y-%m-%d %H:%M UTC")));
+            }
+            text.push_str("[[rule]]\n");
+            text.push_str(&format!("prefix = {}\n", toml::Value::String(rule.prefix.clone())));
+            text.push_str(&format!("created = {}\n", rule.created));
+            if let Some(origin) = &rule.origin {
+                text.push_str(&format!("origin = {}\n", toml::Value::String(origin.clone())));
+            }
+        }
+        text
+    }
+
+    /// Write the rules the user added (never the built-in ones) to `path`.
+    pub fn export(&self, path: &Path) -> io::Result<()> {
+        persist::write_atomic(path, self.to_toml().as_bytes())
+    }
+
+    /// Read the rules of the file at `path` into this allowlist. Every rule
+    /// is checked first, and nothing changes if one fails: a rule that
+    /// would let through a command the built-in checks deny fails too,
+    /// unless `force` is set.
+    pub fn import(&mut self, path: &Path, mode: ImportMode, force: bool) -> Result<ImportSummary, ImportError> {
+        let rules = parse(&std::fs::read_to_string(path)?, force)?;
+        Ok(self.merge(rules, mode))
+    }
+
+    fn merge(&mut self, rules: Vec<AllowRule>, mode: ImportMode) -> ImportSummary {
+        let mut summary = ImportSummary { applied: mode != ImportMode::DryRun, ..ImportSummary::default() };
+        let mut merged = match mode {
+            ImportMode::Replace => Vec::new(),
+            ImportMode::Merge | ImportMode::DryRun => self.rules.clone(),
+        };
+        for rule in rules {
+            if merged.iter().any(|kept| kept.prefix == rule.prefix) {
+                summary.skipped.push(rule.prefix);
+            } else if mode == ImportMode::Replace && self.contains(&rule.prefix) {
+                summary.skipped.push(rule.prefix.clone());
+                merged.push(rule);
+            } else {
+                summary.added.push(rule.prefix.clone());
+                merged.push(rule);
+            }
+        }
+        if mode == ImportMode::Replace {
+            summary.removed = self
+                .rules
+                .iter()
+                .filter(|rule| !merged.iter().any(|kept| kept.prefix == rule.prefix))
+                .map(|rule| rule.prefix.clone())
+                .collect();
+        }
+        if summary.applied {
+            self.rules = merged;
+        }
+        summary
     }
+}
 
-    // Check for logical operators
-    if cmd.contains("&&") || cmd.contains("||") {
-        return true;
+/// The user's allowlist, and the file it is saved in. The file is owned
+/// by one instance at a time: the others (and `rusty-term security import`
+/// while one runs) can read the rules but their changes are refused.
+#[derive(Debug)]
+pub struct AllowlistStore {
+    file: StateFile,
+    allowlist: Allowlist,
+}
+
+impl AllowlistStore {
+    /// Store backed by `path`, loaded from it.
+    pub fn at(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let file = StateFile::open(path, "allowlist").map_err(|e| e.to_string())?;
+        let allowlist = Allowlist::load(&file).map_err(|e| format!("{}: {}", file.path().display(), e))?;
+        Ok(Self { file, allowlist })
     }
 
-    // Check for redirects
-    if cmd.contains('>') || cmd.contains('<') {
-        return true;
+    /// Store in the data directory.
+    pub fn open() -> Result<Self, String> {
+        Self::at(Allowlist::default_path().ok_or("No data directory to keep the allowlist in")?)
     }
 
-    // Check for command substitution
-    if cmd.contains("$(") || cmd.contains('`') {
-        return true;
+    pub fn allowlist(&self) -> &Allowlist {
+        &self.allowlist
     }
 
-    // Check for background execution
-    // Note: we need to check for & but not within && (already checked above)
-    // A simple approach: if & exists and && doesn't explain all &, it's dangerous
-    let ampersand_count = cmd.matches('&').count();
-    let double_ampersand_count = cmd.matches("&&").count();
-    if ampersand_count > double_ampersand_count * 2 {
-        return true;
+    /// Save `allowlist` and use it from now on; nothing changes if it
+    /// can't be saved.
+    fn replace(&mut self, allowlist: Allowlist) -> Result<(), PersistError> {
+        self.file.save(allowlist.to_toml().as_bytes())?;
+        self.allowlist = allowlist;
+        Ok(())
     }
+}
 
-    false
+/// Rules of an allowlist file, each checked (see [`Allowlist::import`]).
+fn parse(text: &str, force: bool) -> Result<Vec<AllowRule>, ImportError> {
+    let file: AllowlistFile = toml::from_str(text).map_err(|e| {
+        let (line, column) = position(text, e.span().map_or(0, |span| span.start));
+        ImportError::Malformed { line, column, message: e.message().to_string() }
+    })?;
+    file.rule
+        .into_iter()
+        .map(|rule| {
+            let line = position(text, rule.prefix.span().start).0;
+            let prefix = rule.prefix.into_inner();
+            if let Some(reason) = invalid_prefix(&prefix) {
+                return Err(ImportError::InvalidRule { line, prefix, reason: reason.to_string() });
+            }
+            if let Some(reason) = loosened_deny(&prefix)
+                && !force
+            {
+                return Err(ImportError::LoosensDeny { line, prefix, reason });
+            }
+            Ok(AllowRule { prefix, created: rule.created, origin: rule.origin })
+        })
+        .collect()
 }
 
-/// Check if command is in the minimal safe set
-fn is_safe_command(cmd: &str) -> bool {
-    matches!(cmd, "pwd" | "ls" | "whoami" | "date" | "uname" | "which" | "echo")
+/// Whether `cmd` starts with `prefix` as whole words: `rm` covers
+/// `rm -rf /` but not `rmdir`.
+fn covers(prefix: &str, cmd: &str) -> bool {
+    cmd.trim_start().strip_prefix(prefix).is_some_and(|rest| {
+        rest.is_empty() || rest.starts_with(char::is_whitespace) || prefix.ends_with(char::is_whitespace)
+    })
 }
 
-/// Evaluate git subcommand policy
-fn evaluate_git_command(cmd: &str) -> Verdict {
-    // Extract git subcommand (second word after "git")
-    let parts: Vec<&str> = cmd.split_whitespace().collect();
+/// Why a rule for `prefix` would let through a command the built-in checks
+/// deny, if it would: the prefix itself, or a command of one of their deny
+/// rules it covers, is denied.
+fn loosened_deny(prefix: &str) -> Option<String> {
+    let samples = denied_samples();
+    std::iter::once(prefix)
+        .chain(samples.iter().map(String::as_str).filter(|denied| covers(prefix, denied)))
+        .find_map(|cmd| match evaluate(cmd) {
+            Verdict::Deny(reason) => Some(reason),
+            _ => None,
+        })
+}
 
-    if parts.len() < 2 {
-        // Just "git" without subcommand
-        return Verdict::Allow;
+/// Why `prefix` can't be a rule, if it can't.
+fn invalid_prefix(prefix: &str) -> Option<&'static str> {
+    if prefix.trim().is_empty() {
+        Some("it is empty, so it would allow every command")
+    } else if prefix.trim_start() != prefix {
+        Some("it starts with whitespace")
+    } else if prefix.chars().any(char::is_control) {
+        Some("it contains a control character")
+    } else {
+        None
     }
+}
 
-    let subcommand = parts[1];
+/// Line and column (from 1, in characters) of a byte offset into `text`.
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
 
-    // Allow safe read-only git commands
-    if matches!(subcommand, "status" | "diff" | "log" | "show") {
-        return Verdict::Allow;
-    }
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
 
-    // Deny dangerous git commands
-    if matches!(
-        subcommand,
-        "push" | "reset" | "clean" | "rebase" | "force" | "branch" | "checkout" | "merge" | "pull"
-    ) {
-        return Verdict::Deny(format!("git {} is a destructive operation", subcommand));
+/// Run `security export <file>` or `security import <file> [--replace |
+/// --dry-run] [--force]` against the allowlist in `store`, the same from the
+/// assistant and from the command line. Returns what to tell the user.
+pub fn security_command(args: &[String], store: &mut AllowlistStore) -> Result<String, String> {
+    const USAGE: &str = "usage: security export <file> | security import <file> [--replace | --dry-run] [--force]";
+    let mut allowlist = store.allowlist().clone();
+    match args {
+        [command, file] if command == "export" => {
+            allowlist.export(Path::new(file)).map_err(|e| format!("{}: {}", file, e))?;
+            Ok(format!("Exported {} rules to {}", allowlist.rules().len(), file))
+        }
+        [command, file, flags @ ..] if command == "import" => {
+            let mut mode = ImportMode::Merge;
+            let mut force = false;
+            for flag in flags {
+                match flag.as_str() {
+                    "--replace" => mode = ImportMode::Replace,
+                    "--dry-run" => mode = ImportMode::DryRun,
+                    "--force" => force = true,
+                    _ => return Err(USAGE.to_string()),
+                }
+            }
+            let summary = allowlist.import(Path::new(file), mode, force).map_err(|e| format!("{}: {}", file, e))?;
+            if summary.applied {
+                store.replace(allowlist).map_err(|e| e.to_string())?;
+            }
+            Ok(summary.describe())
+        }
+        _ => Err(USAGE.to_string()),
     }
+}
 
-    // Other git commands require confirmation
-    Verdict::RequireConfirmation("Requires confirmation".to_string())
+/// Evaluate a command and return a verdict on whether it can be executed.
+///
+/// The verdict follows from [`analyze_command`]: the reason is the message
+/// of the top finding.
+///
+/// # Rules
+/// - Deny: Commands containing shell composition tokens (|, ;, &&, ||, >, <, $(, backticks, &)
+/// - Deny: Downloaded scripts piped into a shell, recursive deletes of `/` or `~`
+/// - Allow: Minimal safe set (pwd, ls, whoami, date, uname, which, echo)
+/// - Git policy: Allow status/diff/log/show, deny push/reset/clean/..., others require confirmation
+/// - Default: RequireConfirmation for other commands
+pub fn evaluate(cmd: &str) -> Verdict {
+    verdict_from(&analyze_command(cmd))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn temp_dir(name: &str) -> Result<PathBuf, io::Error> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-allowlist-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_export_and_import_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = temp_dir("round-trip")?;
+        let mut allowlist = Allowlist::new();
+        allowlist.trust("cargo build", Some("cargo build --release"));
+        allowlist.trust("npm \"run\" test", None);
+        allowlist.rules[0].created = 1_700_000_000;
+        allowlist.export(&dir.join("shared.toml"))?;
+
+        let text = std::fs::read_to_string(dir.join("shared.toml"))?;
+        assert!(text.starts_with("# RustyTerm allowlist"), "{}", text);
+        assert!(text.contains("# Added 2023-11-14 22:13 UTC\n[[rule]]\nprefix = \"cargo build\"\n"), "{}", text);
+
+        let mut imported = Allowlist::new();
+        let summary = imported.import(&dir.join("shared.toml"), ImportMode::Merge, false)?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(imported.rules(), allowlist.rules());
+        assert_eq!(summary.added, vec!["cargo build", "npm \"run\" test"]);
+        assert!(imported.is_allowed("cargo build --release"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_modes_and_conflicts() -> Result<(), Box<dyn std::error::Error>> {
+        let rules = |text: &str| parse(text, false);
+        let mut allowlist = Allowlist::new();
+        allowlist.trust("make", None);
+        allowlist.trust("cargo test", None);
+
+        // Merge skips what is already there
+        let file = "[[rule]]\nprefix = \"cargo test\"\n[[rule]]\nprefix = \"docker ps\"\n";
+        let summary = allowlist.merge(rules(file)?, ImportMode::DryRun);
+        assert_eq!((summary.added.len(), summary.skipped.len(), summary.applied), (1, 1, false));
+        assert_eq!(allowlist.rules().len(), 2);
+        assert!(summary.describe().starts_with("Dry run, nothing changed: 1 added, 1 already allowed"));
+        let summary = allowlist.merge(rules(file)?, ImportMode::Merge);
+        assert_eq!(summary.added, vec!["docker ps"]);
+        assert_eq!(allowlist.rules().len(), 3);
+
+        // Replace drops the rest
+        let summary = allowlist.merge(rules("[[rule]]\nprefix = \"make\"\n")?, ImportMode::Replace);
+        assert_eq!(summary.removed, vec!["cargo test", "docker ps"]);
+        assert_eq!(summary.skipped, vec!["make"]);
+        assert!(!allowlist.is_allowed("docker ps"));
+
+        // A rule allowing what the checks deny fails the whole file, unless forced
+        let file = "[[rule]]\nprefix = \"ls\"\n\n[[rule]]\nprefix = \"git push\"\n";
+        assert!(matches!(rules(file), Err(ImportError::LoosensDeny { line: 5, .. })));
+        assert_eq!(parse(file, true)?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_covering_a_denied_command_is_refused() {
+        // The bare prefixes are not denied, but they cover commands that are
+        for prefix in ["git", "rm", "rm -rf"] {
+            let file = format!("[[rule]]\nprefix = {:?}\n", prefix);
+            assert!(matches!(parse(&file, false), Err(ImportError::LoosensDeny { .. })), "{}", prefix);
+        }
+        let file = "[[rule]]\nprefix = \"git status\"\n[[rule]]\nprefix = \"rmdir\"\n";
+        assert!(parse(file, false).is_ok());
+        // Every sample really is denied, and each deny rule has one
+        let samples = denied_samples();
+        assert!(samples.iter().all(|cmd| evaluate(cmd).is_deny()), "{:?}", samples);
+        assert!(samples.iter().any(|cmd| cmd == "git checkout"));
+        assert!(samples.iter().any(|cmd| cmd.ends_with("| python3")));
+    }
+
+    #[test]
+    fn test_rules_lift_confirmations_but_not_denials() {
+        let mut allowlist = Allowlist::new();
+        allowlist.trust("cargo", None);
+        allowlist.trust("git", None);
+        let apply = |cmd: &str| verdict_from(&allowlist.apply(cmd, analyze_command(cmd)));
+        assert_eq!(apply("cargo build --release"), Verdict::Allow);
+        assert_eq!(apply("git commit -m wip"), Verdict::Allow);
+        assert!(apply("git push").is_deny());
+        assert!(apply("cargo build > log.txt").is_deny());
+        assert!(matches!(apply("make"), Verdict::RequireConfirmation(_)));
+
+        // A project rule asking for confirmation still does
+        let rules = crate::security::ProjectRules { confirm: vec!["cargo publish".to_string()], ..Default::default() };
+        let findings = allowlist.apply("cargo publish", rules.findings("cargo publish"));
+        assert!(matches!(verdict_from(&findings), Verdict::RequireConfirmation(_)));
+    }
+
+    #[test]
+    fn test_import_is_refused_while_another_instance_owns_the_file() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = temp_dir("owned")?;
+        std::fs::write(dir.join("shared.toml"), "[[rule]]\nprefix = \"make\"\n")?;
+        let import = vec!["import".to_string(), dir.join("shared.toml").display().to_string()];
+        let mut owner = AllowlistStore::at(dir.join(FILE_NAME))?;
+        let mut other = AllowlistStore::at(dir.join(FILE_NAME))?;
+
+        let refused = security_command(&import, &mut other);
+        assert!(refused.as_ref().is_err_and(|e| e.contains("another RustyTerm is running")), "{:?}", refused);
+        assert!(other.allowlist().rules().is_empty());
+
+        security_command(&import, &mut owner)?;
+        let saved = std::fs::read_to_string(dir.join(FILE_NAME))?;
+        std::fs::remove_dir_all(&dir)?;
+        assert!(owner.allowlist().is_allowed("make test"));
+        assert!(saved.contains("prefix = \"make\""), "{}", saved);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules_match_whole_words() {
+        let mut allowlist = Allowlist::new();
+        allowlist.trust("rm", None);
+        allowlist.trust("git log ", None);
+        assert!(allowlist.is_allowed("rm build.log"));
+        assert!(allowlist.is_allowed("  rm"));
+        assert!(!allowlist.is_allowed("rmdir build"));
+        assert!(allowlist.is_allowed("git log --oneline"));
+        assert!(!allowlist.is_allowed("git logout"));
+    }
+
+    #[test]
+    fn test_malformed_file_reports_the_line() {
+        let file = "# shared rules\n[[rule]]\nprefix = \"make\"\n\n[[rule]]\nprefix = make test\n";
+        match parse(file, false) {
+            Err(ImportError::Malformed { line, column, .. }) => assert_eq!((line, column), (6, 10)),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let file = "[[rule]]\nprefix = \"make\"\n[[rule]]\nprefix = \"  \"\n";
+        let error = parse(file, false).map(|_| ()).err().map(|e| e.to_string());
+        assert_eq!(error.as_deref(), Some("line 4: rule \"  \" is invalid: it is empty, so it would allow every command"));
+    }
+
     #[test]
     fn test_empty_command() {
         assert!(evaluate("").is_deny());
@@ -0,0 +1,114 @@
+//! Audit log of decisions on suggested commands.
+//!
+//! Each accepted or rejected suggestion is appended to `audit.jsonl` in the
+//! data directory as one JSON line: when, which command, what the user did,
+//! the verdict and the findings behind it. Every instance appends to the same
+//! file (see [`AppendLog`]).
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use tracing::warn;
+
+use super::{Finding, Verdict, verdict_from};
+use crate::utils::persist::{self, AppendLog};
+
+/// File name in the data directory.
+const FILE_NAME: &str = "audit.jsonl";
+
+/// What happened to a suggested command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Decision {
+    /// The user ran it
+    Accepted,
+    /// The user turned it down (copying a denied command counts too)
+    Rejected,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    /// Seconds since the Unix epoch
+    time: u64,
+    command: &'a str,
+    decision: Decision,
+    /// `allow`, `confirm` or `deny`
+    verdict: &'static str,
+    findings: &'a [Finding],
+}
+
+/// Append-only record of command decisions.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    log: AppendLog,
+}
+
+impl AuditLog {
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        Self { log: AppendLog::new(path) }
+    }
+
+    /// Log in the data directory (None if there is none).
+    pub fn open() -> Option<Self> {
+        persist::data_dir().map(|dir| Self::at(dir.join(FILE_NAME)))
+    }
+
+    pub fn path(&self) -> &Path {
+        self.log.path()
+    }
+
+    /// Record a decision. Failures are logged, never fatal: a full disk must
+    /// not stop the user from running commands.
+    pub fn record(&self, command: &str, decision: Decision, findings: &[Finding]) {
+        let verdict = match verdict_from(findings) {
+            Verdict::Allow => "allow",
+            Verdict::RequireConfirmation(_) => "confirm",
+            Verdict::Deny(_) => "deny",
+        };
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let record = AuditRecord { time, command, decision, verdict, findings };
+        let result = serde_json::to_vec(&record)
+            .map_err(|e| e.to_string())
+            .and_then(|line| self.log.append(&line).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Failed to write audit log {}: {}", self.path().display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::analyze_command;
+
+    #[test]
+    fn test_records_carry_the_findings() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-audit-{}", std::process::id()));
+        let log = AuditLog::at(dir.join(FILE_NAME));
+
+        let command = "ls && sudo rm -rf /";
+        log.record(command, Decision::Rejected, &analyze_command(command));
+        log.record("pwd", Decision::Accepted, &analyze_command("pwd"));
+
+        let records = AppendLog::new(log.path()).read_records()?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(records.len(), 2);
+
+        let rejected: serde_json::Value = serde_json::from_str(&records[0])?;
+        assert_eq!(rejected["decision"], "rejected");
+        assert_eq!(rejected["verdict"], "deny");
+        let top = &rejected["findings"][0];
+        assert_eq!(top["category"], "destructive-delete");
+        assert_eq!(top["severity"], "deny");
+        assert_eq!((top["span"]["start"].as_u64(), top["span"]["end"].as_u64()), (Some(11), Some(19)));
+
+        let accepted: serde_json::Value = serde_json::from_str(&records[1])?;
+        assert_eq!(accepted["verdict"], "allow");
+        assert_eq!(accepted["findings"].as_array().map(Vec::len), Some(0));
+        Ok(())
+    }
+}
@@ -2,18 +2,23 @@
 //!
 //! This module provides functionality to analyze commands for safety,
 //! maintain allowlists, and warn or block potentially dangerous operations.
+//! Every decision is explained by [`Finding`]s and can be recorded in the
+//! [`AuditLog`].
 
 mod allowlist;
 mod analyzer;
+mod audit;
 pub mod executor;
+mod rules;
 
-pub use allowlist::{Allowlist, Verdict, evaluate};
-pub use analyzer::analyze_command;
+pub use allowlist::{
+    AllowRule, Allowlist, AllowlistStore, ImportError, ImportMode, ImportSummary, Verdict, evaluate, security_command,
+};
+pub use analyzer::{
+    Category, Finding, Severity, analyze_command, composed_findings, lint_for_shell, sort_findings,
+    terminal_output_finding, verdict_from,
+};
+pub use audit::{AuditLog, Decision};
 pub use executor::{ExecutionDecision, gate_command};
+pub use rules::{Policy, ProjectRules};
 
-#[derive(Debug)]
-pub enum CommandSafety {
-    Safe,
-    Warn(String),  // print warning message
-    Block(String), // forbid execution
-}
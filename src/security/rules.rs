@@ -0,0 +1,179 @@
+//! Extra command rules from a project config file.
+//!
+//! Project files come with whatever repository is being worked on, so they
+//! are only trusted to make the built-in policy stricter: a rule can deny a
+//! command or ask for confirmation, never allow one. Their findings are added
+//! to the built-in ones, so a denial from [`evaluate`] always stands.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Allowlist, Category, Finding, Severity, Verdict, analyze_command, sort_findings, verdict_from};
+
+/// Deny and confirm rules layered on top of [`evaluate`].
+///
+/// A rule matches a command that starts with the same words, so
+/// `"kubectl delete"` matches `kubectl delete pod web` but not
+/// `kubectl describe`. The program name is compared without its directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectRules {
+    /// Commands that are never run
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+    /// Commands that always need confirmation
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub confirm: Vec<String>,
+}
+
+impl ProjectRules {
+    pub fn is_empty(&self) -> bool {
+        self.deny.is_empty() && self.confirm.is_empty()
+    }
+
+    /// Evaluate a command with the built-in policy, then apply the rules.
+    pub fn evaluate(&self, cmd: &str) -> Verdict {
+        verdict_from(&self.findings(cmd))
+    }
+
+    /// Findings of the built-in policy plus one for the first matching
+    /// deny rule and one for the first matching confirm rule, most serious
+    /// first. A built-in finding outranks a project one of the same
+    /// severity, so the verdict is never less strict than [`evaluate`]'s.
+    pub fn findings(&self, cmd: &str) -> Vec<Finding> {
+        let mut findings = analyze_command(cmd);
+        let span = cmd.len() - cmd.trim_start().len()..cmd.trim_end().len();
+        if let Some(rule) = self.deny.iter().find(|rule| matches_rule(cmd, rule)) {
+            findings.push(Finding {
+                span: span.clone(),
+                category: Category::ProjectRule,
+                severity: Severity::Deny,
+                message: format!("Denied by project rule \"{}\"", rule.trim()),
+            });
+        }
+        if let Some(rule) = self.confirm.iter().find(|rule| matches_rule(cmd, rule)) {
+            findings.push(Finding {
+                span,
+                category: Category::ProjectRule,
+                severity: Severity::Confirm,
+                message: format!("Project rule \"{}\" asks for confirmation", rule.trim()),
+            });
+        }
+        sort_findings(&mut findings);
+        findings
+    }
+}
+
+/// Everything a suggested command is held to: the built-in policy, the
+/// project's rules and the user's allowlist.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    pub rules: ProjectRules,
+    pub allowlist: Allowlist,
+}
+
+impl Policy {
+    /// Findings of the built-in policy and the project rules, minus the
+    /// confirmations the allowlist lifts (see [`Allowlist::apply`]).
+    pub fn findings(&self, cmd: &str) -> Vec<Finding> {
+        self.allowlist.apply(cmd, self.rules.findings(cmd))
+    }
+
+    pub fn evaluate(&self, cmd: &str) -> Verdict {
+        verdict_from(&self.findings(cmd))
+    }
+}
+
+/// Does `cmd` start with the words of `rule`? An empty rule matches nothing.
+fn matches_rule(cmd: &str, rule: &str) -> bool {
+    let program = |word: &str| {
+        Path::new(word)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| word.to_string())
+    };
+    let mut cmd_words = cmd.split_whitespace();
+    let mut rule_words = rule.split_whitespace();
+    match (cmd_words.next(), rule_words.next()) {
+        (Some(cmd_program), Some(rule_program)) if program(cmd_program) == program(rule_program) => {}
+        _ => return false,
+    }
+    rule_words.all(|word| cmd_words.next() == Some(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::evaluate;
+
+    fn rules(deny: &[&str], confirm: &[&str]) -> ProjectRules {
+        ProjectRules {
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+            confirm: confirm.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_rules_match_whole_words() {
+        assert!(matches_rule("kubectl delete pod web", "kubectl delete"));
+        assert!(matches_rule("/usr/local/bin/kubectl  delete ns", "kubectl delete"));
+        assert!(!matches_rule("kubectl describe pod", "kubectl delete"));
+        assert!(!matches_rule("kubectl deleted", "kubectl delete"));
+        assert!(!matches_rule("kubectl", "kubectl delete"));
+        assert!(!matches_rule("ls", ""));
+    }
+
+    #[test]
+    fn test_rules_only_tighten() {
+        let rules = rules(&["terraform apply", "ls"], &["git status", "date", "echo"]);
+
+        // Deny rules turn anything into a denial
+        assert!(rules.evaluate("terraform apply -auto-approve").is_deny());
+        assert!(rules.evaluate("ls -la").is_deny());
+        // Confirm rules turn an allowed command into a confirmation
+        assert!(matches!(rules.evaluate("git status"), Verdict::RequireConfirmation(_)));
+        assert!(matches!(rules.evaluate("date"), Verdict::RequireConfirmation(_)));
+        // Built-in denials and confirmations keep their reason
+        assert_eq!(rules.evaluate("echo hi | sh"), evaluate("echo hi | sh"));
+        assert_eq!(rules.evaluate("git push"), evaluate("git push"));
+        assert_eq!(rules.evaluate("terraform plan"), evaluate("terraform plan"));
+
+        // Never looser than the built-in policy, whatever the rules say
+        let strictness = |verdict: &Verdict| match verdict {
+            Verdict::Allow => 0,
+            Verdict::RequireConfirmation(_) => 1,
+            Verdict::Deny(_) => 2,
+        };
+        for cmd in ["pwd", "rm -rf /", "git reset --hard", "cat x > y", "terraform apply", "whoami", "echo"] {
+            assert!(strictness(&rules.evaluate(cmd)) >= strictness(&evaluate(cmd)), "{cmd}");
+        }
+        assert_eq!(ProjectRules::default().evaluate("pwd"), Verdict::Allow);
+    }
+
+    #[test]
+    fn test_rule_findings_follow_built_in_ones() {
+        let rules = rules(&["kubectl delete"], &["kubectl"]);
+        let findings = rules.findings("  kubectl delete pod web ");
+        let summary: Vec<_> = findings.iter().map(|f| (f.category, f.severity, f.message.as_str())).collect();
+        assert_eq!(
+            summary,
+            [
+                (Category::ProjectRule, Severity::Deny, "Denied by project rule \"kubectl delete\""),
+                (Category::ProjectRule, Severity::Confirm, "Project rule \"kubectl\" asks for confirmation"),
+                (Category::Unlisted, Severity::Confirm, "`kubectl` is not a known read-only command"),
+            ]
+        );
+        assert_eq!(findings[0].matched("  kubectl delete pod web "), "kubectl delete pod web");
+
+        // A built-in denial stays on top
+        let findings = rules.findings("kubectl delete pod web > log");
+        assert_eq!(findings[0].category, Category::Redirect);
+    }
+
+    #[test]
+    fn test_allow_rules_are_rejected() {
+        let parsed: Result<ProjectRules, _> = toml::from_str("allow = [\"rm\"]\n");
+        assert!(parsed.is_err());
+    }
+}
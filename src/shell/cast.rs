@@ -0,0 +1,295 @@
+//! Recording of the terminal pane to an asciinema v2 cast file.
+//!
+//! A cast file is a JSON header line (`{"version": 2, "width": .., ..}`)
+//! followed by one JSON array per event: `[seconds, "o", "output"]` for
+//! what the shell printed, `[seconds, "i", "input"]` for what was typed and
+//! `[seconds, "r", "COLSxROWS"]` for a resize. The pane hands every PTY
+//! read to [`CastRecorder`], which only timestamps it and passes it over a
+//! channel; a writer thread does the encoding and file I/O.
+//!
+//! Input is only recorded when asked for, and never while the shell seems
+//! to have echo off (a password prompt): a keystroke is held back until
+//! output follows it, and when a second keystroke arrives first, both are
+//! dropped along with everything typed until the shell prints again.
+
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::persist;
+use crate::utils::utf8::Utf8Decoder;
+
+/// First line of a cast file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CastHeader {
+    pub version: u8,
+    pub width: u16,
+    pub height: u16,
+    /// Unix time the recording started
+    pub timestamp: u64,
+}
+
+/// Kind of a cast event, as its one-letter code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastEventKind {
+    Output,
+    Input,
+    Resize,
+}
+
+impl CastEventKind {
+    pub fn code(self) -> &'static str {
+        match self {
+            CastEventKind::Output => "o",
+            CastEventKind::Input => "i",
+            CastEventKind::Resize => "r",
+        }
+    }
+}
+
+/// One event on its way to the writer thread.
+struct Record {
+    /// Seconds since the recording started
+    time: f64,
+    kind: CastEventKind,
+    data: Vec<u8>,
+}
+
+/// A recording in progress. Dropping it also finishes the file, but only
+/// [`CastRecorder::stop`] waits for the writer and reports errors.
+pub struct CastRecorder {
+    path: PathBuf,
+    start: Instant,
+    records: Sender<Record>,
+    writer: JoinHandle<io::Result<()>>,
+    record_input: bool,
+    /// Keystrokes not echoed yet, held back until output shows the shell echoes
+    unechoed: Vec<Record>,
+    /// Input is being dropped until the shell prints something
+    no_echo: bool,
+}
+
+impl CastRecorder {
+    /// Start recording a `width`x`height` terminal to a new file in `dir`.
+    /// With `record_input`, typed input is recorded too (see the module docs).
+    pub fn start(dir: &Path, width: u16, height: u16, record_input: bool) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        // Named after the start time, with a counter if that name is taken
+        let (path, file) = persist::create_unique(dir, &format!("rusty-term-{}", timestamp), "cast")?;
+
+        let mut out = BufWriter::new(file);
+        let header = CastHeader { version: 2, width, height, timestamp };
+        serde_json::to_writer(&mut out, &header)?;
+        out.write_all(b"\n")?;
+        out.flush()?;
+
+        let (records, rx) = mpsc::channel::<Record>();
+        let writer = std::thread::spawn(move || -> io::Result<()> {
+            let mut encoder = EventEncoder::default();
+            for record in rx {
+                encoder.write(&mut out, &record)?;
+            }
+            encoder.finish(&mut out)?;
+            out.flush()
+        });
+
+        Ok(Self {
+            path,
+            start: Instant::now(),
+            records,
+            writer,
+            record_input,
+            unechoed: Vec::new(),
+            no_echo: false,
+        })
+    }
+
+    /// File being written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The shell printed `bytes`.
+    pub fn output(&mut self, bytes: &[u8]) {
+        // Output after a keystroke: it was echoed, so it may be recorded
+        for record in self.unechoed.drain(..) {
+            self.records.send(record).ok();
+        }
+        self.no_echo = false;
+        self.send(CastEventKind::Output, bytes.to_vec());
+    }
+
+    /// `bytes` were typed into the shell.
+    pub fn input(&mut self, bytes: &[u8]) {
+        if !self.record_input || self.no_echo {
+            return;
+        }
+        if !self.unechoed.is_empty() {
+            // The last keystroke got no echo: probably a password prompt
+            self.unechoed.clear();
+            self.no_echo = true;
+            return;
+        }
+        let time = self.elapsed();
+        self.unechoed.push(Record { time, kind: CastEventKind::Input, data: bytes.to_vec() });
+    }
+
+    /// The terminal was resized.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.send(CastEventKind::Resize, format!("{}x{}", width, height).into_bytes());
+    }
+
+    /// Finish the file and return where it is.
+    pub fn stop(self) -> io::Result<PathBuf> {
+        let Self { path, records, writer, .. } = self;
+        drop(records);
+        match writer.join() {
+            Ok(result) => result.map(|()| path),
+            Err(_) => Err(io::Error::other("recording writer panicked")),
+        }
+    }
+
+    fn send(&mut self, kind: CastEventKind, data: Vec<u8>) {
+        let time = self.elapsed();
+        // The writer only stops early on a write error, reported by stop()
+        self.records.send(Record { time, kind, data }).ok();
+    }
+
+    fn elapsed(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
+
+/// Turns records into event lines. PTY reads can split a UTF-8 sequence,
+/// so an incomplete one at the end of an output read waits for the next.
+#[derive(Default)]
+struct EventEncoder {
+    output: Utf8Decoder,
+    /// Time of the last output line, for the leftover bytes at the end
+    last_output: f64,
+}
+
+impl EventEncoder {
+    fn write(&mut self, out: &mut impl Write, record: &Record) -> io::Result<()> {
+        let text = match record.kind {
+            CastEventKind::Output => {
+                self.last_output = record.time;
+                let text = self.output.decode(&record.data);
+                if text.is_empty() {
+                    return Ok(());
+                }
+                text
+            }
+            _ => String::from_utf8_lossy(&record.data).into_owned(),
+        };
+        write_event(out, record.time, record.kind, &text)
+    }
+
+    fn finish(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let text = self.output.finish();
+        if text.is_empty() {
+            return Ok(());
+        }
+        write_event(out, self.last_output, CastEventKind::Output, &text)
+    }
+}
+
+fn write_event(out: &mut impl Write, time: f64, kind: CastEventKind, text: &str) -> io::Result<()> {
+    // Microsecond precision, as asciinema writes it
+    let time = (time * 1e6).round() / 1e6;
+    serde_json::to_writer(&mut *out, &(time, kind.code(), text))?;
+    out.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rusty-term-cast-{}-{}", name, std::process::id()))
+    }
+
+    /// An event of a cast file: (time, code, data).
+    type CastEvent = (f64, String, String);
+
+    /// Parse a cast file into its header and events, checking every line
+    /// has the shape the v2 format requires.
+    fn parse_cast(text: &str) -> Result<(CastHeader, Vec<CastEvent>), Box<dyn std::error::Error>> {
+        let mut lines = text.lines();
+        let header: CastHeader = serde_json::from_str(lines.next().ok_or("empty file")?)?;
+        let mut events = Vec::new();
+        for line in lines {
+            let value: serde_json::Value = serde_json::from_str(line)?;
+            let event = value.as_array().ok_or("event is not an array")?;
+            assert_eq!(event.len(), 3, "{}", line);
+            let time = event[0].as_f64().ok_or("time is not a number")?;
+            let code = event[1].as_str().ok_or("code is not a string")?;
+            let data = event[2].as_str().ok_or("data is not a string")?;
+            events.push((time, code.to_string(), data.to_string()));
+        }
+        Ok((header, events))
+    }
+
+    #[test]
+    fn test_recording_roundtrips_as_asciicast_v2() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = temp_dir("roundtrip");
+        let mut recorder = CastRecorder::start(&dir, 80, 24, true)?;
+        recorder.output(b"$ ");
+        recorder.input(b"l");
+        recorder.output(b"l");
+        // "é" split across two reads
+        recorder.output(b"\r\ncaf\xC3");
+        recorder.output(b"\xA9\r\n");
+        recorder.resize(100, 30);
+        let path = recorder.stop()?;
+        let text = std::fs::read_to_string(&path)?;
+        std::fs::remove_dir_all(&dir)?;
+
+        let (header, events) = parse_cast(&text)?;
+        assert_eq!(header, CastHeader { version: 2, width: 80, height: 24, timestamp: header.timestamp });
+        let codes: Vec<(&str, &str)> = events.iter().map(|(_, code, data)| (code.as_str(), data.as_str())).collect();
+        assert_eq!(codes, vec![
+            ("o", "$ "),
+            ("i", "l"),
+            ("o", "l"),
+            ("o", "\r\ncaf"),
+            ("o", "é\r\n"),
+            ("r", "100x30"),
+        ]);
+        assert!(events.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_is_off_by_default_and_skipped_without_echo() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = temp_dir("input");
+        let mut recorder = CastRecorder::start(&dir, 80, 24, false)?;
+        recorder.input(b"x");
+        recorder.output(b"x");
+        let quiet = std::fs::read_to_string(recorder.stop()?)?;
+
+        let mut recorder = CastRecorder::start(&dir, 80, 24, true)?;
+        recorder.output(b"Password: ");
+        for key in [b"h", b"u", b"n", b"t"] {
+            recorder.input(key);
+        }
+        recorder.input(b"\r");
+        recorder.output(b"\r\n$ ");
+        recorder.input(b"l");
+        recorder.output(b"l");
+        let secret = std::fs::read_to_string(recorder.stop()?)?;
+        std::fs::remove_dir_all(&dir)?;
+
+        let (_, events) = parse_cast(&quiet)?;
+        assert!(events.iter().all(|(_, code, _)| code == "o"));
+        let (_, events) = parse_cast(&secret)?;
+        let inputs: Vec<&str> = events.iter().filter(|(_, code, _)| code == "i").map(|(_, _, data)| data.as_str()).collect();
+        assert_eq!(inputs, vec!["l"]);
+        Ok(())
+    }
+}
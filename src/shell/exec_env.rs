@@ -0,0 +1,203 @@
+//! How an AI-suggested command is typed into the shell.
+//!
+//! A suggestion may name a directory to run in; the `cd` is composed here,
+//! quoted for the shell, rather than left to the model. With marking on, the
+//! command also gets `RUSTYTERM_AI=1` in its environment (`env RUSTYTERM_AI=1`
+//! on fish), so shell history and the command records show which commands
+//! came from the assistant. The marker is left off wherever it would change
+//! what the command does: shell keywords and builtins it can't prefix, and
+//! PowerShell, which has no such syntax.
+//!
+//! The suggested command always comes last, unchanged, so the composed text
+//! is a prefix followed by it.
+
+use std::ops::Range;
+
+use super::ShellKind;
+
+/// A suggested command and how to run it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Execution<'a> {
+    /// The command as suggested
+    pub command: &'a str,
+    /// Directory to run it in (None = the shell's own)
+    pub cwd: Option<&'a str>,
+    /// Set the marker variable
+    pub mark: bool,
+}
+
+/// The text typed into the shell for an [`Execution`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Composed {
+    pub text: String,
+    /// Byte offset where the suggested command starts in `text`
+    pub command_start: usize,
+    /// Where the directory is in `text`, as quoted
+    pub dir_span: Option<Range<usize>>,
+}
+
+impl Composed {
+    /// The part before the suggested command (`cd`, marker).
+    pub fn prefix(&self) -> &str {
+        &self.text[..self.command_start]
+    }
+}
+
+impl Execution<'_> {
+    pub fn compose(&self, shell: ShellKind) -> Composed {
+        let mut text = String::new();
+        let mut dir_span = None;
+        if let Some(dir) = self.cwd.map(str::trim).filter(|dir| !dir.is_empty()) {
+            let (command, quoted) = change_dir(dir, shell);
+            text.push_str(&command);
+            dir_span = Some(quoted);
+        }
+        if self.mark
+            && let Some(marker) = marker_prefix(self.command, shell)
+        {
+            text.push_str(marker);
+        }
+        let command_start = text.len();
+        text.push_str(self.command);
+        Composed { text, command_start, dir_span }
+    }
+}
+
+/// `cd` into `dir`, up to and including the operator that runs the command
+/// only if it worked, and where the quoted directory is in it.
+fn change_dir(dir: &str, shell: ShellKind) -> (String, Range<usize>) {
+    // A directory named like an option would be taken for one
+    let dir = if dir.starts_with('-') { format!("./{}", dir) } else { dir.to_string() };
+    let (before, after) = match shell {
+        ShellKind::PowerShell => ("Set-Location -LiteralPath ", " -ErrorAction Stop; "),
+        _ => ("cd ", " && "),
+    };
+    let quoted = quote(&dir, shell);
+    let span = before.len()..before.len() + quoted.len();
+    (format!("{}{}{}", before, quoted, after), span)
+}
+
+/// Quote a path as one word for `shell`. A leading `~/` stays outside the
+/// quotes so it still expands. PowerShell paths are always quoted (`,` and
+/// `@` mean something there).
+fn quote(path: &str, shell: ShellKind) -> String {
+    if shell != ShellKind::PowerShell
+        && let Some(rest) = path.strip_prefix("~/")
+    {
+        return if rest.is_empty() { "~/".to_string() } else { format!("~/{}", quote(rest, shell)) };
+    }
+    let plain = shell != ShellKind::PowerShell
+        && !path.is_empty()
+        && path.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '.' | '_' | '-' | '+' | ',' | ':' | '@'));
+    if plain {
+        return path.to_string();
+    }
+    match shell {
+        ShellKind::Bash | ShellKind::Zsh | ShellKind::Sh => format!("'{}'", path.replace('\'', "'\\''")),
+        ShellKind::Fish => format!("'{}'", path.replace('\\', "\\\\").replace('\'', "\\'")),
+        ShellKind::PowerShell => format!("'{}'", path.replace('\'', "''")),
+    }
+}
+
+/// Words that start something other than a simple command in a POSIX shell
+const POSIX_KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac", "select",
+    "function", "time", "coproc", "{", "}", "!", "[[",
+];
+
+/// Fish keywords and builtins: `env` can only start external programs
+const FISH_BUILTINS: &[&str] = &[
+    "if", "else", "end", "for", "while", "switch", "case", "function", "begin", "and", "or", "not", "time",
+    "set", "cd", "source", ".", "alias", "abbr", "functions", "builtin", "command", "exec", "exit", "return",
+    "break", "continue", "eval", "read", "string", "math", "status", "contains", "count", "test", "[",
+    "echo", "printf", "pwd", "type", "emit", "bind", "complete", "history", "jobs", "fg", "bg", "disown",
+    "wait", "ulimit", "umask", "prevd", "nextd", "dirh", "pushd", "popd", "set_color", "argparse", "fish_add_path",
+];
+
+/// The marker to put before `command`, or None if it would break it.
+fn marker_prefix(command: &str, shell: ShellKind) -> Option<&'static str> {
+    let mut words = command.split_whitespace();
+    let first = words.next()?;
+    if first.starts_with(['(', '{', '!', '#']) {
+        return None;
+    }
+    match shell {
+        ShellKind::Bash | ShellKind::Zsh | ShellKind::Sh => {
+            // Assignments alone would keep the marker in the shell for good
+            let program = std::iter::once(first).chain(words).find(|word| !is_assignment(word))?;
+            (!POSIX_KEYWORDS.contains(&program)).then_some("RUSTYTERM_AI=1 ")
+        }
+        ShellKind::Fish => (!FISH_BUILTINS.contains(&first) && !is_assignment(first)).then_some("env RUSTYTERM_AI=1 "),
+        ShellKind::PowerShell => None,
+    }
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compose(command: &str, cwd: Option<&str>, mark: bool, shell: ShellKind) -> String {
+        Execution { command, cwd, mark }.compose(shell).text
+    }
+
+    #[test]
+    fn test_nothing_to_add() {
+        for shell in [ShellKind::Bash, ShellKind::Zsh, ShellKind::Fish, ShellKind::PowerShell, ShellKind::Sh] {
+            let composed = Execution { command: "ls -la", cwd: None, mark: false }.compose(shell);
+            assert_eq!(composed, Composed { text: "ls -la".to_string(), command_start: 0, dir_span: None });
+            assert_eq!(compose("ls", Some("  "), false, shell), "ls");
+        }
+    }
+
+    #[test]
+    fn test_marker_per_shell() {
+        assert_eq!(compose("npm test", None, true, ShellKind::Bash), "RUSTYTERM_AI=1 npm test");
+        assert_eq!(compose("npm test", None, true, ShellKind::Zsh), "RUSTYTERM_AI=1 npm test");
+        assert_eq!(compose("npm test", None, true, ShellKind::Sh), "RUSTYTERM_AI=1 npm test");
+        assert_eq!(compose("npm test", None, true, ShellKind::Fish), "env RUSTYTERM_AI=1 npm test");
+        assert_eq!(compose("npm test", None, true, ShellKind::PowerShell), "npm test");
+
+        // Prefixing these would change what they do
+        assert_eq!(compose("for f in *; do echo $f; done", None, true, ShellKind::Bash), "for f in *; do echo $f; done");
+        assert_eq!(compose("(cd x; make)", None, true, ShellKind::Bash), "(cd x; make)");
+        assert_eq!(compose("FOO=1", None, true, ShellKind::Bash), "FOO=1");
+        assert_eq!(compose("cd src", None, true, ShellKind::Fish), "cd src");
+        assert_eq!(compose("set -gx FOO 1", None, true, ShellKind::Fish), "set -gx FOO 1");
+        // Builtins take a temporary assignment in POSIX shells
+        assert_eq!(compose("FOO=1 cd src", None, true, ShellKind::Zsh), "RUSTYTERM_AI=1 FOO=1 cd src");
+    }
+
+    #[test]
+    fn test_cwd_per_shell() {
+        assert_eq!(compose("npm test", Some("backend"), false, ShellKind::Bash), "cd backend && npm test");
+        assert_eq!(compose("npm test", Some("backend"), false, ShellKind::Fish), "cd backend && npm test");
+        assert_eq!(
+            compose("npm test", Some("backend"), false, ShellKind::PowerShell),
+            "Set-Location -LiteralPath 'backend' -ErrorAction Stop; npm test"
+        );
+
+        // Quoting
+        assert_eq!(compose("ls", Some("my dir"), false, ShellKind::Bash), "cd 'my dir' && ls");
+        assert_eq!(compose("ls", Some("it's"), false, ShellKind::Sh), "cd 'it'\\''s' && ls");
+        assert_eq!(compose("ls", Some("it's"), false, ShellKind::Fish), "cd 'it\\'s' && ls");
+        assert_eq!(compose("ls", Some("it's"), false, ShellKind::PowerShell), "Set-Location -LiteralPath 'it''s' -ErrorAction Stop; ls");
+        assert_eq!(compose("ls", Some("$(rm -rf ~)"), false, ShellKind::Zsh), "cd '$(rm -rf ~)' && ls");
+        assert_eq!(compose("ls", Some("~/src/my app"), false, ShellKind::Bash), "cd ~/'src/my app' && ls");
+        assert_eq!(compose("ls", Some("-x"), false, ShellKind::Bash), "cd ./-x && ls");
+
+        // Both: the marker goes on the command, after the cd
+        let composed = Execution { command: "npm test", cwd: Some("web app"), mark: true }.compose(ShellKind::Fish);
+        assert_eq!(composed.text, "cd 'web app' && env RUSTYTERM_AI=1 npm test");
+        assert_eq!(&composed.text[composed.command_start..], "npm test");
+        assert_eq!(composed.prefix(), "cd 'web app' && env RUSTYTERM_AI=1 ");
+        assert_eq!(composed.dir_span.clone().map(|span| &composed.text[span]), Some("'web app'"));
+    }
+}
@@ -0,0 +1,82 @@
+//! Which shell runs in the terminal pane.
+//!
+//! Suggestions are written for a particular shell: the model is told which
+//! one, command cards show it, and the compatibility lint in
+//! [`crate::security::lint_for_shell`] checks commands against it.
+
+use std::path::Path;
+
+/// Shell family, as far as command syntax is concerned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShellKind {
+    #[default]
+    Bash,
+    Zsh,
+    Fish,
+    /// PowerShell (`pwsh` or Windows PowerShell)
+    PowerShell,
+    /// Any other POSIX-style shell (sh, dash, ksh, ...)
+    Sh,
+}
+
+impl ShellKind {
+    /// Guess the shell from the program that was spawned (`/usr/bin/fish`,
+    /// `-zsh` for a login shell, `pwsh.exe`).
+    pub fn from_program(program: &str) -> Self {
+        let name = Path::new(program)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let name = name.trim_start_matches('-');
+        let name = name.strip_suffix(".exe").unwrap_or(name);
+        match name {
+            "bash" => ShellKind::Bash,
+            "zsh" => ShellKind::Zsh,
+            "fish" => ShellKind::Fish,
+            "pwsh" | "powershell" => ShellKind::PowerShell,
+            _ => ShellKind::Sh,
+        }
+    }
+
+    /// Short name shown on command cards.
+    pub fn label(self) -> &'static str {
+        match self {
+            ShellKind::Bash => "bash",
+            ShellKind::Zsh => "zsh",
+            ShellKind::Fish => "fish",
+            ShellKind::PowerShell => "pwsh",
+            ShellKind::Sh => "sh",
+        }
+    }
+
+    /// What the model is told about the shell commands will run in.
+    pub fn prompt_note(self) -> String {
+        let syntax = match self {
+            ShellKind::Bash | ShellKind::Zsh => "",
+            ShellKind::Fish => {
+                " Use fish syntax: `set -gx VAR value` instead of export, `(cmd)` for command substitution, `$status` instead of `$?`, and fish's own if/for/function blocks."
+            }
+            ShellKind::PowerShell => {
+                " Use PowerShell syntax: `$env:VAR = 'value'` for environment variables, `$null` instead of /dev/null, and cmdlets or native tools that exist on Windows."
+            }
+            ShellKind::Sh => " Stick to POSIX sh: no `[[ ]]`, arrays or `function` keyword.",
+        };
+        format!("Target shell: {}. Every suggested command runs in it.{}", self.label(), syntax)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_kind_from_program() {
+        assert_eq!(ShellKind::from_program("/bin/bash"), ShellKind::Bash);
+        assert_eq!(ShellKind::from_program("-zsh"), ShellKind::Zsh);
+        assert_eq!(ShellKind::from_program("/opt/homebrew/bin/fish"), ShellKind::Fish);
+        assert_eq!(ShellKind::from_program("pwsh"), ShellKind::PowerShell);
+        assert_eq!(ShellKind::from_program("PowerShell.exe"), ShellKind::PowerShell);
+        assert_eq!(ShellKind::from_program("/bin/dash"), ShellKind::Sh);
+        assert_eq!(ShellKind::from_program("").label(), "sh");
+    }
+}
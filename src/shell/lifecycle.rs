@@ -0,0 +1,418 @@
+//! Where shell commands start and end.
+//!
+//! A command opens when it is submitted: typed in the terminal pane and
+//! sent with Enter, or sent by the app for a quick action or the assistant.
+//! A shell with integration (see [`integration_script`]) then says itself
+//! where each command runs and how it exits, in OSC 133 marks around every
+//! prompt: `A` before the prompt, `B` after it, `C` once the command line
+//! runs and `D;status` when it is done. Without them a command is taken to
+//! be over when the terminal shows its prompt again or the next command is
+//! submitted, and its exit status stays unknown. Either way
+//! [`CommandTracker`] reports one [`FinishedCommand`] per command.
+
+use std::time::{Duration, Instant};
+
+use crate::ai::session::SessionId;
+
+use super::ShellKind;
+
+/// Signal number of SIGINT, which Ctrl+C sends to the foreground job.
+pub const SIGINT: i32 = 2;
+
+/// The assistant tool call a command was run for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandOrigin {
+    pub session_id: SessionId,
+    pub tool_call_id: String,
+}
+
+/// An OSC 133 mark printed by a shell with integration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellMark {
+    /// `A`: a prompt is about to be printed
+    PromptStart,
+    /// `B`: the prompt is printed; the command line follows
+    CommandStart,
+    /// `C`: the command line was accepted and runs
+    OutputStart,
+    /// `D`: the command is done, with its exit status if the shell gave one
+    Finished(Option<i32>),
+}
+
+impl ShellMark {
+    /// The mark an OSC payload (`133;D;1`) carries, if it is one. Options
+    /// some shells add after the fields (`133;A;cl=m`) are ignored.
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        let payload = std::str::from_utf8(payload.strip_prefix(b"133;")?).ok()?;
+        let mut fields = payload.split(';');
+        match fields.next()? {
+            "A" => Some(ShellMark::PromptStart),
+            "B" => Some(ShellMark::CommandStart),
+            "C" => Some(ShellMark::OutputStart),
+            "D" => Some(ShellMark::Finished(fields.next().and_then(|status| status.trim().parse().ok()))),
+            _ => None,
+        }
+    }
+}
+
+/// A command that is over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinishedCommand {
+    /// The command line, its continuation lines joined by newlines
+    pub command: String,
+    /// Exit status; None without shell integration or when the shell exited
+    pub exit_code: Option<i32>,
+    /// Signal that stopped it: SIGINT after a Ctrl+C, or the one a status
+    /// above 128 stands for
+    pub signal: Option<i32>,
+    /// From when it started running to when it was seen to end
+    pub duration: Duration,
+    /// Set for commands run for the assistant
+    pub origin: Option<CommandOrigin>,
+}
+
+#[derive(Debug)]
+struct Command {
+    line: String,
+    origin: Option<CommandOrigin>,
+    started: Instant,
+    /// Ctrl+C was sent while it ran
+    interrupted: bool,
+}
+
+/// Follows the command running in the shell, from the commands submitted
+/// to it and the marks it prints.
+#[derive(Debug, Default)]
+pub struct CommandTracker {
+    /// The shell prints OSC 133 marks: it says when commands start and end
+    integrated: bool,
+    /// Submitted, until the shell runs it (with integration)
+    submitted: Option<Command>,
+    running: Option<Command>,
+}
+
+impl CommandTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the shell has printed OSC 133 marks.
+    pub fn is_integrated(&self) -> bool {
+        self.integrated
+    }
+
+    /// `line` was submitted to the shell at `now`.
+    ///
+    /// With integration it runs once the shell says so; lines submitted
+    /// before that continue the same command (a `for` loop typed over
+    /// several lines). Without, it runs from now on, and the command before
+    /// it, if still taken to run, is over.
+    pub fn submitted(&mut self, line: &str, origin: Option<CommandOrigin>, now: Instant) -> Option<FinishedCommand> {
+        if self.integrated {
+            match &mut self.submitted {
+                Some(command) if origin.is_none() && command.origin.is_none() => {
+                    command.line.push('\n');
+                    command.line.push_str(line);
+                }
+                _ => self.submitted = Some(Command::new(line, origin, now)),
+            }
+            return None;
+        }
+        let finished = self.finish(None, now);
+        self.running = Some(Command::new(line, origin, now));
+        finished
+    }
+
+    /// Ctrl+C was sent to the shell: whatever runs gets SIGINT, and the
+    /// command line being typed is dropped.
+    pub fn interrupted(&mut self) {
+        if let Some(command) = &mut self.running {
+            command.interrupted = true;
+        }
+        self.submitted = None;
+    }
+
+    /// The shell printed `mark` at `now`.
+    pub fn mark(&mut self, mark: ShellMark, now: Instant) -> Option<FinishedCommand> {
+        self.integrated = true;
+        match mark {
+            // A prompt without a `D` before it: what ran is over all the same
+            ShellMark::PromptStart => self.finish(None, now),
+            ShellMark::CommandStart => None,
+            ShellMark::OutputStart => {
+                let finished = self.finish(None, now);
+                // Nothing submitted: a line recalled from history, or just Enter
+                let command = self.submitted.take().unwrap_or_else(|| Command::new("", None, now));
+                self.running = Some(Command { started: now, ..command });
+                finished
+            }
+            ShellMark::Finished(exit_code) => {
+                // Lines submitted while it ran were its input, except those
+                // typed after a Ctrl+C, which wait for the prompt
+                if self.running.as_ref().is_some_and(|command| !command.interrupted) {
+                    self.submitted = None;
+                }
+                self.finish(exit_code, now)
+            }
+        }
+    }
+
+    /// The command taken to run when the shell gives no marks, for the
+    /// caller to watch the prompt come back.
+    pub fn unmarked_command(&self) -> Option<&str> {
+        self.running.as_ref().filter(|_| !self.integrated).map(|command| command.line.as_str())
+    }
+
+    /// Without integration: the prompt is back at `now`, the command is over.
+    pub fn prompt_back(&mut self, now: Instant) -> Option<FinishedCommand> {
+        if self.integrated {
+            return None;
+        }
+        self.finish(None, now)
+    }
+
+    /// The shell exited at `now`, ending the command running in it.
+    pub fn shell_exited(&mut self, now: Instant) -> Option<FinishedCommand> {
+        self.submitted = None;
+        self.finish(None, now)
+    }
+
+    fn finish(&mut self, exit_code: Option<i32>, now: Instant) -> Option<FinishedCommand> {
+        let command = self.running.take()?;
+        if command.line.is_empty() {
+            return None;
+        }
+        let signal = match exit_code {
+            // Ctrl+C in a program that reads it (an editor, a REPL) stops nothing
+            Some(0) => None,
+            _ if command.interrupted => Some(SIGINT),
+            Some(status) if (129..=192).contains(&status) => Some(status - 128),
+            _ => None,
+        };
+        Some(FinishedCommand {
+            command: command.line,
+            exit_code,
+            signal,
+            duration: now.saturating_duration_since(command.started),
+            origin: command.origin,
+        })
+    }
+}
+
+impl Command {
+    fn new(line: &str, origin: Option<CommandOrigin>, now: Instant) -> Self {
+        Self { line: line.to_string(), origin, started: now, interrupted: false }
+    }
+}
+
+/// Script that makes `kind` print OSC 133 marks, to be evaluated from its
+/// startup file (`eval "$(rusty-term shell-integration bash)"`). None for
+/// shells it isn't written for.
+pub fn integration_script(kind: ShellKind) -> Option<&'static str> {
+    match kind {
+        ShellKind::Bash => Some(BASH_INTEGRATION),
+        ShellKind::Zsh => Some(ZSH_INTEGRATION),
+        ShellKind::Fish | ShellKind::PowerShell | ShellKind::Sh => None,
+    }
+}
+
+// The status goes out first, before anything else can change `$?`. A `D`
+// at a prompt where nothing ran is ignored.
+const BASH_INTEGRATION: &str = r#"# rusty-term: mark prompts and commands (OSC 133)
+__rusty_term_prompt() {
+    printf '\033]133;D;%s\007\033]133;A\007' "$?"
+}
+PROMPT_COMMAND="__rusty_term_prompt${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
+PS1="$PS1"'\[\033]133;B\007\]'
+PS0='\033]133;C\007'
+"#;
+
+const ZSH_INTEGRATION: &str = r#"# rusty-term: mark prompts and commands (OSC 133)
+__rusty_term_precmd() {
+    printf '\033]133;D;%s\007\033]133;A\007' "$?"
+}
+__rusty_term_preexec() {
+    printf '\033]133;C\007'
+}
+precmd_functions=(__rusty_term_precmd $precmd_functions)
+preexec_functions+=(__rusty_term_preexec)
+PS1="$PS1"$'%{\e]133;B\a%}'
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::OscScanner;
+
+    /// Shell output fed through the scanner and tracker as the terminal
+    /// pane does, `ms` milliseconds after `start`.
+    struct Pty {
+        scanner: OscScanner,
+        tracker: CommandTracker,
+        start: Instant,
+    }
+
+    impl Pty {
+        fn new() -> Self {
+            Self { scanner: OscScanner::new(), tracker: CommandTracker::new(), start: Instant::now() }
+        }
+
+        fn at(&self, ms: u64) -> Instant {
+            self.start + Duration::from_millis(ms)
+        }
+
+        fn print(&mut self, bytes: &[u8], ms: u64) -> Vec<FinishedCommand> {
+            let mut marks = Vec::new();
+            self.scanner.scan(bytes, |payload| marks.extend(ShellMark::parse(payload)));
+            let now = self.at(ms);
+            marks.into_iter().filter_map(|mark| self.tracker.mark(mark, now)).collect()
+        }
+
+        fn submit(&mut self, line: &str, ms: u64) -> Option<FinishedCommand> {
+            let now = self.at(ms);
+            self.tracker.submitted(line, None, now)
+        }
+    }
+
+    const PROMPT: &[u8] = b"\x1b]133;A\x07$ \x1b]133;B\x07";
+
+    fn finished(command: &str, exit_code: Option<i32>, signal: Option<i32>, ms: u64) -> FinishedCommand {
+        FinishedCommand {
+            command: command.to_string(),
+            exit_code,
+            signal,
+            duration: Duration::from_millis(ms),
+            origin: None,
+        }
+    }
+
+    #[test]
+    fn test_marks_parse_from_osc_payloads() {
+        assert_eq!(ShellMark::parse(b"133;A"), Some(ShellMark::PromptStart));
+        assert_eq!(ShellMark::parse(b"133;A;cl=m"), Some(ShellMark::PromptStart));
+        assert_eq!(ShellMark::parse(b"133;C"), Some(ShellMark::OutputStart));
+        assert_eq!(ShellMark::parse(b"133;D;127"), Some(ShellMark::Finished(Some(127))));
+        assert_eq!(ShellMark::parse(b"133;D"), Some(ShellMark::Finished(None)));
+        assert_eq!(ShellMark::parse(b"133;D;;aid=1"), Some(ShellMark::Finished(None)));
+        assert_eq!(ShellMark::parse(b"133;Z"), None);
+        assert_eq!(ShellMark::parse(b"7;file://host/tmp"), None);
+    }
+
+    #[test]
+    fn test_integrated_commands_end_with_their_status() {
+        let mut pty = Pty::new();
+        assert!(pty.print(PROMPT, 0).is_empty());
+        assert_eq!(pty.submit("make", 100), None);
+        assert!(pty.print(b"\r\n\x1b]133;C\x07cc -o app main.c\r\n", 150).is_empty());
+        // The mark split across reads
+        assert!(pty.print(b"main.c:3: error\r\n\x1b]133;D;", 1100).is_empty());
+        assert_eq!(pty.print(b"2\x07\x1b]133;A\x07$ \x1b]133;B\x07", 1150), [finished("make", Some(2), None, 1000)]);
+        assert!(pty.tracker.is_integrated());
+        assert_eq!(pty.tracker.unmarked_command(), None);
+
+        // Enter alone, or a line recalled from history: nothing to report
+        assert!(pty.print(b"\x1b]133;C\x07\x1b]133;D;0\x07", 1200).is_empty());
+    }
+
+    #[test]
+    fn test_integrated_lines_continue_until_the_command_runs() {
+        let mut pty = Pty::new();
+        pty.print(PROMPT, 0);
+        pty.submit("for f in a b", 100);
+        pty.print(b"\r\n> ", 110);
+        pty.submit("do echo $f", 200);
+        pty.print(b"\r\n> ", 210);
+        pty.submit("done", 300);
+        pty.print(b"\r\n\x1b]133;C\x07a\r\nb\r\n", 300);
+        assert_eq!(
+            pty.print(b"\x1b]133;D;0\x07", 320),
+            [finished("for f in a b\ndo echo $f\ndone", Some(0), None, 20)]
+        );
+
+        // Lines typed into a running program are its input
+        pty.print(PROMPT, 330);
+        pty.submit("python3", 400);
+        pty.print(b"\x1b]133;C\x07>>> ", 400);
+        pty.submit("print(1)", 500);
+        pty.submit("exit()", 600);
+        assert_eq!(pty.print(b"\x1b]133;D;0\x07", 700), [finished("python3", Some(0), None, 300)]);
+        pty.print(PROMPT, 700);
+        assert!(pty.print(b"\x1b]133;C\x07\x1b]133;D;0\x07", 800).is_empty());
+    }
+
+    #[test]
+    fn test_ctrl_c_records_sigint() {
+        let mut pty = Pty::new();
+        pty.print(PROMPT, 0);
+        pty.submit("sleep 60", 0);
+        pty.print(b"\x1b]133;C\x07", 10);
+        pty.tracker.interrupted();
+        // A command sent right behind the Ctrl+C waits for the prompt
+        pty.submit("ls", 500);
+        assert_eq!(
+            pty.print(b"^C\r\n\x1b]133;D;130\x07\x1b]133;A\x07$ \x1b]133;B\x07", 510),
+            [finished("sleep 60", Some(130), Some(SIGINT), 500)]
+        );
+        pty.print(b"\x1b]133;C\x07", 520);
+        assert_eq!(pty.print(b"a.txt\r\n\x1b]133;D;0\x07", 530), [finished("ls", Some(0), None, 10)]);
+
+        // Ctrl+C in an editor that exits cleanly later
+        pty.submit("vim notes", 600);
+        pty.print(b"\x1b]133;C\x07", 600);
+        pty.tracker.interrupted();
+        assert_eq!(pty.print(b"\x1b]133;D;0\x07", 900), [finished("vim notes", Some(0), None, 300)]);
+
+        // Killed by a signal the status tells of
+        pty.submit("./server", 1000);
+        pty.print(b"\x1b]133;C\x07", 1000);
+        assert_eq!(pty.print(b"Killed\r\n\x1b]133;D;137\x07", 1200), [finished("./server", Some(137), Some(9), 200)]);
+    }
+
+    #[test]
+    fn test_without_marks_the_prompt_ends_a_command() {
+        let mut pty = Pty::new();
+        assert!(pty.print(b"$ ", 0).is_empty());
+        assert_eq!(pty.submit("cargo build", 100), None);
+        assert_eq!(pty.tracker.unmarked_command(), Some("cargo build"));
+        assert_eq!(pty.tracker.prompt_back(pty.at(2100)), Some(finished("cargo build", None, None, 2000)));
+        assert_eq!(pty.tracker.prompt_back(pty.at(2200)), None);
+
+        // The next command ends one whose prompt was never seen
+        pty.submit("tail -f log", 3000);
+        pty.tracker.interrupted();
+        assert_eq!(pty.submit("ls", 3500), Some(finished("tail -f log", None, Some(SIGINT), 500)));
+    }
+
+    #[test]
+    fn test_shell_exiting_ends_the_running_command() {
+        let mut pty = Pty::new();
+        pty.print(PROMPT, 0);
+        pty.submit("exit", 0);
+        pty.print(b"\x1b]133;C\x07", 5);
+        assert_eq!(pty.tracker.shell_exited(pty.at(10)), Some(finished("exit", None, None, 5)));
+        assert_eq!(pty.tracker.shell_exited(pty.at(20)), None);
+    }
+
+    #[test]
+    fn test_origin_stays_with_the_command() {
+        let mut tracker = CommandTracker::new();
+        let start = Instant::now();
+        tracker.mark(ShellMark::PromptStart, start);
+        let origin = CommandOrigin { session_id: 1, tool_call_id: "call_1".to_string() };
+        tracker.submitted("git status", Some(origin.clone()), start);
+        tracker.mark(ShellMark::OutputStart, start);
+        let finished = tracker.mark(ShellMark::Finished(Some(0)), start);
+        assert_eq!(finished.and_then(|command| command.origin), Some(origin));
+    }
+
+    #[test]
+    fn test_integration_scripts_print_every_mark() {
+        for kind in [ShellKind::Bash, ShellKind::Zsh] {
+            let script = integration_script(kind).unwrap_or_default();
+            for mark in ["133;A", "133;B", "133;C", "133;D;"] {
+                assert!(script.contains(mark), "{:?} lacks {}", kind, mark);
+            }
+        }
+        assert_eq!(integration_script(ShellKind::Fish), None);
+    }
+}
@@ -3,5 +3,19 @@
 //! This module handles shell subprocess creation, command execution,
 //! and output capturing for the terminal interface.
 
+mod cast;
+mod exec_env;
+mod kind;
+mod lifecycle;
+mod scripted;
 mod subprocess;
-pub use subprocess::ShellManager;
+mod writer;
+pub use cast::{CastEventKind, CastHeader, CastRecorder};
+pub use exec_env::{Composed, Execution};
+pub use kind::ShellKind;
+pub use lifecycle::{
+    CommandOrigin, CommandTracker, FinishedCommand, SIGINT, ShellMark, integration_script,
+};
+pub use scripted::ScriptedShell;
+pub use subprocess::{ShellBackend, ShellManager, Submit, command_bytes};
+pub use writer::PtyWriter;
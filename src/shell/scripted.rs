@@ -0,0 +1,94 @@
+//! A stand-in shell for driving the UI without a PTY.
+//!
+//! Output is pushed by the caller and arrives through the same channel PTY
+//! output does; input the app writes is recorded instead of reaching a
+//! process.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender, UnboundedSender};
+
+use super::{PtyWriter, ShellBackend, ShellManager};
+use crate::event::{AppEvent, Stamped};
+
+/// Chunks of output that can be queued before the app reads them.
+const OUTPUT_BUFFER: usize = 1024;
+
+/// Handle for scripting a [`ShellManager`] created by [`ScriptedShell::new`].
+#[derive(Debug, Clone)]
+pub struct ScriptedShell {
+    output: Sender<Vec<u8>>,
+    /// The app's writes, to wait for before looking at them
+    writer: PtyWriter,
+    state: Arc<Mutex<ScriptedState>>,
+}
+
+#[derive(Debug, Default)]
+struct ScriptedState {
+    input: Vec<u8>,
+    size: Option<(u16, u16)>,
+    resizes: usize,
+}
+
+struct ScriptedBackend {
+    state: Arc<Mutex<ScriptedState>>,
+}
+
+impl ShellBackend for ScriptedBackend {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        let mut state = self.state.lock().map_err(|e| anyhow::anyhow!("Failed to lock shell input: {}", e))?;
+        state.input.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+        let mut state = self.state.lock().map_err(|e| anyhow::anyhow!("Failed to lock shell size: {}", e))?;
+        state.size = Some((cols, rows));
+        state.resizes += 1;
+        Ok(())
+    }
+}
+
+impl ScriptedShell {
+    /// A shell manager on a scripted backend, the receiver its output
+    /// arrives on (as from [`ShellManager::new`]) and the handle to script it.
+    pub fn new(event_sink: UnboundedSender<Stamped<AppEvent>>) -> (ShellManager, Receiver<Vec<u8>>, Self) {
+        let (output, output_rx) = mpsc::channel(OUTPUT_BUFFER);
+        let state = Arc::new(Mutex::new(ScriptedState::default()));
+        let backend = ScriptedBackend { state: state.clone() };
+        let shell = ShellManager::with_backend(event_sink, Box::new(backend));
+        let writer = shell.writer();
+        (shell, output_rx, Self { output, writer, state })
+    }
+
+    /// Queue bytes as if the shell had printed them.
+    pub fn print(&self, data: impl Into<Vec<u8>>) -> Result<(), TrySendError<Vec<u8>>> {
+        self.output.try_send(data.into())
+    }
+
+    /// Everything the app has written to the shell so far.
+    pub fn input(&self) -> Vec<u8> {
+        self.settle();
+        self.state.lock().map(|state| state.input.clone()).unwrap_or_default()
+    }
+
+    /// The size the app last gave the shell, if it resized it.
+    pub fn size(&self) -> Option<(u16, u16)> {
+        self.settle();
+        self.state.lock().ok().and_then(|state| state.size)
+    }
+
+    /// How many times the app has resized the shell.
+    pub fn resize_count(&self) -> usize {
+        self.settle();
+        self.state.lock().map(|state| state.resizes).unwrap_or_default()
+    }
+
+    /// Wait for writes and resizes still queued to reach the backend.
+    fn settle(&self) {
+        if let Err(e) = self.writer.flush() {
+            tracing::warn!("Scripted shell input: {}", e);
+        }
+    }
+}
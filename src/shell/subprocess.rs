@@ -6,6 +6,8 @@
 use std::io::Write;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::time::Instant;
 
 use anyhow::{Context, Result};
 use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
@@ -14,19 +16,109 @@ use tokio::sync::mpsc::{self, Receiver, UnboundedSender};
 use tracing::error;
 
 use crate::context::CommandLog;
-use crate::event::AppEvent;
+use crate::event::{AppEvent, EventOrigin, Stamped};
+use crate::utils::metrics::metrics;
+
+use super::{CommandOrigin, CommandTracker, FinishedCommand, PtyWriter, ShellKind, ShellMark};
 
 // Channel buffer sizes
 const PTY_OUTPUT_BUFFER: usize = 1024;  // Can buffer ~1-5MB data for smooth rendering
 const PTY_READ_BUFFER: usize = 16384;   // 16KB per read for good throughput
+const SPARE_OUTPUT_BUFFERS: usize = 64; // Processed output buffers kept for reuse
+
+/// The shell end that input goes to: a PTY, or a scripted stand-in.
+pub trait ShellBackend: Send {
+    /// Write (and flush) bytes to the shell's input.
+    fn write(&mut self, data: &[u8]) -> Result<()>;
+    /// Change the terminal size the shell sees.
+    fn resize(&mut self, cols: u16, rows: u16) -> Result<()>;
+}
+
+/// A shell process on a real PTY.
+struct PtyBackend {
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+}
+
+impl ShellBackend for PtyBackend {
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+        self.master.resize(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })?;
+        Ok(())
+    }
+}
 
 /// Manages the shell subprocess using a PTY.
 pub struct ShellManager {
-    #[allow(unused)]
-    event_sink: UnboundedSender<AppEvent>,
-    pty_master: Arc<Mutex<Box<dyn MasterPty + Send>>>,
-    pty_writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    event_sink: UnboundedSender<Stamped<AppEvent>>,
+    /// Queue every write to the shell goes through
+    writer: PtyWriter,
     command_log: Arc<Mutex<CommandLog>>,
+    /// Where the command running starts and ends
+    commands: CommandTracker,
+    /// Shell family of the spawned program
+    kind: ShellKind,
+    /// Returns processed output buffers to the reader thread
+    spare_buffers: Option<SyncSender<Vec<u8>>>,
+    /// The shell asked for bracketed paste (it reads a paste as text, not keys)
+    bracketed_paste: bool,
+}
+
+/// How [`ShellManager::submit_command`] leaves a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Submit {
+    /// Run it, as if Enter was pressed after typing it
+    Execute,
+    /// Leave it on the command line for the user to edit
+    InsertOnly,
+}
+
+/// Start and end of a bracketed paste.
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Bytes that put `cmd` on a shell's command line.
+///
+/// Trailing newlines and carriage returns are dropped, CR LF and lone CRs
+/// inside become LF, and other control characters (escape sequences
+/// included, so nothing can end the paste early) are removed. Tabs are kept
+/// as they are. With `bracketed_paste` the text is wrapped in paste markers;
+/// without it a newline would act as Enter, so for [`Submit::InsertOnly`]
+/// newlines become spaces. [`Submit::Execute`] ends with a single CR.
+pub fn command_bytes(cmd: &str, submit: Submit, bracketed_paste: bool) -> Vec<u8> {
+    let cmd = cmd.trim_end_matches(['\r', '\n']).replace("\r\n", "\n");
+    let text: String = cmd
+        .chars()
+        .map(|c| match c {
+            '\r' => '\n',
+            '\n' if !bracketed_paste && submit == Submit::InsertOnly => ' ',
+            c => c,
+        })
+        .filter(|&c| matches!(c, '\t' | '\n') || !c.is_control())
+        .collect();
+
+    let mut bytes = Vec::with_capacity(text.len() + PASTE_START.len() + PASTE_END.len() + 1);
+    if bracketed_paste {
+        bytes.extend_from_slice(PASTE_START);
+    }
+    bytes.extend_from_slice(text.as_bytes());
+    if bracketed_paste {
+        bytes.extend_from_slice(PASTE_END);
+    }
+    if submit == Submit::Execute {
+        bytes.push(b'\r');
+    }
+    bytes
 }
 
 impl ShellManager {
@@ -40,7 +132,7 @@ impl ShellManager {
     /// # Returns
     /// A tuple of (ShellManager, Receiver for PTY output)
     pub fn new(
-        event_sink: UnboundedSender<AppEvent>,
+        event_sink: UnboundedSender<Stamped<AppEvent>>,
         cols: u16,
         rows: u16,
     ) -> Result<(Self, Receiver<Vec<u8>>)> {
@@ -63,6 +155,7 @@ impl ShellManager {
             }
         });
 
+        let kind = ShellKind::from_program(&shell_cmd);
         let mut cmd = CommandBuilder::new(&shell_cmd);
         cmd.env("TERM", "xterm-256color");
 
@@ -80,15 +173,14 @@ impl ShellManager {
         // as long as pty_master exists. The child process will exit when the PTY closes.
 
         let reader = pair.master.try_clone_reader()?;
-        let pty_writer = pair.master.take_writer()?;
-        let pty_master = Arc::new(Mutex::new(pair.master));
-        let pty_writer = Arc::new(Mutex::new(pty_writer));
+        let writer = pair.master.take_writer()?;
+        let backend = PtyBackend { master: pair.master, writer };
 
-        // Create command log with max 200 entries
-        let command_log = Arc::new(Mutex::new(CommandLog::new(200)));
+        let command_log = Self::new_command_log();
 
-        // Create channel for PTY output
+        // Create channel for PTY output, and one for handing the buffers back
         let (output_tx, output_rx) = mpsc::channel::<Vec<u8>>(PTY_OUTPUT_BUFFER);
+        let (spare_tx, spare_rx) = sync_channel::<Vec<u8>>(SPARE_OUTPUT_BUFFERS);
 
         // Clone event sink for reader thread
         let event_sink_clone = event_sink.clone();
@@ -105,15 +197,19 @@ impl ShellManager {
                 match reader.read(&mut buf) {
                     Ok(0) => {
                         // EOF: shell exited
-                        if let Err(e) = event_sink_clone.send(AppEvent::ShellError {
+                        if let Err(e) = event_sink_clone.send(Stamped::new(EventOrigin::Shell, AppEvent::ShellError {
                             message: "Shell process exited".to_string(),
-                        }) {
+                        })) {
                             error!("Failed to send ShellError event (shell exited): {:?}", e);
                         }
                         break;
                     }
                     Ok(n) => {
-                        let data = buf[..n].to_vec();
+                        metrics().add_pty_read(n);
+                        // Reuse a buffer the display is done with, if there is one
+                        let mut data = spare_rx.try_recv().unwrap_or_default();
+                        data.clear();
+                        data.extend_from_slice(&buf[..n]);
                         
                         // Append output to current command in log
                         if let Ok(mut log) = command_log_clone.lock() {
@@ -129,9 +225,9 @@ impl ShellManager {
                     Err(e) => {
                         // IO error
                         if e.kind() != std::io::ErrorKind::Interrupted {
-                            if let Err(send_err) = event_sink_clone.send(AppEvent::ShellError {
+                            if let Err(send_err) = event_sink_clone.send(Stamped::new(EventOrigin::Shell, AppEvent::ShellError {
                                 message: format!("PTY read error: {}", e),
-                            }) {
+                            })) {
                                 error!("Failed to send ShellError event (PTY read error): {:?}", send_err);
                             }
                             break;
@@ -145,23 +241,119 @@ impl ShellManager {
         Ok((
             Self {
                 event_sink,
-                pty_master,
-                pty_writer,
+                writer: PtyWriter::spawn(Box::new(backend)),
                 command_log,
+                commands: CommandTracker::new(),
+                kind,
+                spare_buffers: Some(spare_tx),
+                bracketed_paste: false,
             },
             output_rx,
         ))
     }
 
-    /// Start recording a new command in the log.
+    /// Creates a shell manager on another backend. Output is whatever the
+    /// caller feeds to the PTY output receiver; nothing is read here. The
+    /// shell is taken to be bash.
+    pub fn with_backend(
+        event_sink: UnboundedSender<Stamped<AppEvent>>,
+        backend: Box<dyn ShellBackend>,
+    ) -> Self {
+        Self {
+            event_sink,
+            writer: PtyWriter::spawn(backend),
+            command_log: Self::new_command_log(),
+            commands: CommandTracker::new(),
+            kind: ShellKind::default(),
+            spare_buffers: None,
+            bracketed_paste: false,
+        }
+    }
+
+    /// Shell family that commands are written for.
+    pub fn kind(&self) -> ShellKind {
+        self.kind
+    }
+
+    /// Where to send PTY output buffers once processed, so the reader thread
+    /// can read into them again. None if nothing reads a PTY.
+    pub fn output_buffer_recycler(&self) -> Option<SyncSender<Vec<u8>>> {
+        self.spare_buffers.clone()
+    }
+
+    /// Command log with max 200 entries
+    fn new_command_log() -> Arc<Mutex<CommandLog>> {
+        Arc::new(Mutex::new(CommandLog::new(200)))
+    }
+
+    /// Start recording a new command in the log, and follow it until it
+    /// finishes (see [`CommandTracker`]). `origin` is the tool call it runs
+    /// for, if the assistant suggested it.
     ///
     /// This should be called when the user presses Enter to execute a command.
-    pub fn start_new_command(&mut self, command_line: String) {
+    pub fn start_new_command(&mut self, command_line: String, origin: Option<CommandOrigin>) {
+        let finished = self.commands.submitted(&command_line, origin, Instant::now());
+        self.report(finished);
         if let Ok(mut log) = self.command_log.lock() {
             log.start_new_command(command_line);
         }
     }
 
+    /// The shell printed an OSC 133 `mark`.
+    pub fn shell_mark(&mut self, mark: ShellMark) {
+        let finished = self.commands.mark(mark, Instant::now());
+        self.report(finished);
+    }
+
+    /// Whether the shell marks its commands (OSC 133), so their end and
+    /// exit status are known.
+    pub fn has_shell_integration(&self) -> bool {
+        self.commands.is_integrated()
+    }
+
+    /// The command taken to be running when the shell doesn't mark its
+    /// commands: it ends once [`ShellManager::prompt_back`] is called.
+    pub fn unmarked_command(&self) -> Option<String> {
+        self.commands.unmarked_command().map(str::to_string)
+    }
+
+    /// The terminal shows the prompt again, so the unmarked command is over.
+    pub fn prompt_back(&mut self) {
+        let finished = self.commands.prompt_back(Instant::now());
+        self.report(finished);
+    }
+
+    /// The shell process is gone, and the command running in it with it.
+    pub fn shell_exited(&mut self) {
+        let finished = self.commands.shell_exited(Instant::now());
+        self.report(finished);
+    }
+
+    /// Send [`AppEvent::ShellCommandCompleted`] for a command that is over.
+    fn report(&self, finished: Option<FinishedCommand>) {
+        let Some(FinishedCommand { command, exit_code, signal, duration, origin }) = finished else {
+            return;
+        };
+        let event = AppEvent::ShellCommandCompleted { command, exit_code, signal, duration, origin };
+        if let Err(e) = self.event_sink.send(Stamped::new(EventOrigin::Shell, event)) {
+            error!("Failed to send ShellCommandCompleted event: {:?}", e);
+        }
+    }
+
+    /// Record where the most recent command sits in the terminal scrollback.
+    pub fn set_command_position(&mut self, position: crate::context::ScrollbackPosition) {
+        if let Ok(mut log) = self.command_log.lock() {
+            log.set_position(position);
+        }
+    }
+
+    /// Record the exit status of a finished command.
+    pub fn record_exit_code(&mut self, command_line: &str, exit_code: i32) {
+        if let Ok(mut log) = self.command_log.lock() {
+            log.set_exit_code(command_line, exit_code);
+        }
+    }
+
     /// Get recent command records for context.
     ///
     /// Returns up to `limit` most recent commands with their outputs.
@@ -173,69 +365,60 @@ impl ShellManager {
         }
     }
 
-    /// Handles user input by writing it to the PTY.
+    /// Handles user input by queueing it for the PTY, in one piece (see
+    /// [`PtyWriter`]).
+    ///
+    /// A Ctrl+C in it is noted as interrupting the command running.
     ///
     /// # Arguments
     /// * `data` - Raw bytes to send to the shell
     pub fn handle_user_input(&mut self, data: &[u8]) -> Result<()> {
-        let mut pty_writer = self.pty_writer.lock().map_err(|e| {
-            anyhow::anyhow!("Failed to lock PTY writer: {}", e)
-        })?;
-
-        pty_writer.write_all(data)?;
-        pty_writer.flush()?;
-        Ok(())
+        if data.contains(&0x03) {
+            self.commands.interrupted();
+        }
+        self.writer.write(data)
     }
 
-    /// Injects a command into the shell as if the user typed it and pressed Enter.
-    ///
-    /// # Arguments
-    /// * `cmd` - Command string to execute
-    pub fn inject_command(&mut self, cmd: &str) -> Result<()> {
-        let mut pty_writer = self.pty_writer.lock().map_err(|e| {
-            anyhow::anyhow!("Failed to lock PTY writer: {}", e)
-        })?;
-
-        // Write command
-        pty_writer.write_all(cmd.as_bytes())?;
-        // Add newline to execute
-        pty_writer.write_all(b"\n")?;
-        pty_writer.flush()?;
+    /// A handle for writing to the shell from elsewhere, such as a task of
+    /// its own. Its writes queue up with the ones made here.
+    pub fn writer(&self) -> PtyWriter {
+        self.writer.clone()
+    }
 
-        Ok(())
+    /// Follow the shell's bracketed paste mode (DECSET 2004), as seen in its output.
+    pub fn set_bracketed_paste(&mut self, enabled: bool) {
+        self.bracketed_paste = enabled;
     }
 
-    /// Executes a command visibly in the shell, as if the user typed it.
+    /// Writes a command to the shell's command line, as if the user typed it.
     ///
-    /// This method writes the command string followed by a newline to the PTY,
-    /// making it appear in the terminal as if the user had manually entered it.
-    /// The command will be visible in the shell's history and output.
+    /// This is the one way suggested commands reach the shell. The command
+    /// is cleaned up by [`command_bytes`]: trailing newlines are dropped and
+    /// control characters other than tab and newline removed. When the shell
+    /// has bracketed paste on, the command is sent as a paste, so a tab is
+    /// inserted instead of starting completion and a newline doesn't run
+    /// half of it. [`Submit::Execute`] then presses Enter exactly once.
     ///
     /// # Non-blocking Behavior
-    /// This method returns immediately after writing the command to the PTY.
+    /// The command is queued behind any input queued before it, as one
+    /// message, so no keystroke or mouse event can land inside it. This
+    /// method returns once it has been written to the PTY.
     /// It does NOT wait for the command to complete. Output from the command
     /// will appear asynchronously through the existing PTY read loop and will
     /// be delivered via the output channel created in `ShellManager::new()`.
     ///
-    /// # Arguments
-    /// * `cmd` - The command string to execute (without trailing newline)
-    ///
-    /// # Returns
-    /// * `Ok(())` if the command was successfully written to the PTY
-    /// * `Err(_)` if the PTY writer lock could not be acquired or write failed
-    ///
     /// # Example
     /// ```no_run
-    /// # use rusty_term::shell::ShellManager;
+    /// # use rusty_term::shell::{ShellManager, Submit};
     /// # use tokio::sync::mpsc::unbounded_channel;
     /// # fn example() -> anyhow::Result<()> {
     /// # let (tx, _) = unbounded_channel();
     /// # let (mut shell, _rx) = ShellManager::new(tx, 80, 24)?;
-    /// // Execute a simple command
-    /// shell.execute_visible("ls -la")?;
+    /// // Run a command
+    /// shell.submit_command("git status", Submit::Execute)?;
     ///
-    /// // Execute a git command
-    /// shell.execute_visible("git status")?;
+    /// // Leave a command at the prompt for the user to edit
+    /// shell.submit_command("git commit -m \"\"", Submit::InsertOnly)?;
     ///
     /// // The output will appear asynchronously via the output receiver
     /// # Ok(())
@@ -243,33 +426,12 @@ impl ShellManager {
     /// ```
     ///
     /// # Errors
-    /// Returns an error if:
-    /// - The PTY writer mutex is poisoned
-    /// - Writing to the PTY fails (e.g., shell process has exited)
-    /// - Flushing the PTY writer fails
-    pub fn execute_visible(&mut self, cmd: &str) -> Result<()> {
-        let mut pty_writer = self
-            .pty_writer
-            .lock()
-            .map_err(|e| anyhow::anyhow!("Failed to lock PTY writer: {}", e))
-            .context("Unable to acquire PTY writer lock for command execution")?;
-
-        // Write command string to PTY
-        pty_writer
-            .write_all(cmd.as_bytes())
-            .context("Failed to write command to PTY")?;
-
-        // Add newline to execute the command
-        pty_writer
-            .write_all(b"\n")
-            .context("Failed to write newline to PTY")?;
-
-        // Flush to ensure command is sent immediately
-        pty_writer
-            .flush()
-            .context("Failed to flush PTY writer")?;
-
-        Ok(())
+    /// Returns an error if writing to the PTY fails (e.g., the shell has exited).
+    pub fn submit_command(&mut self, cmd: &str, submit: Submit) -> Result<()> {
+        self.writer
+            .write(command_bytes(cmd, submit, self.bracketed_paste))
+            .and_then(|()| self.writer.flush())
+            .context("Failed to write command to PTY")
     }
 
     /// Resizes the PTY to the specified dimensions.
@@ -278,18 +440,7 @@ impl ShellManager {
     /// * `cols` - New terminal width in columns
     /// * `rows` - New terminal height in rows
     pub fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
-        let pty = self.pty_master.lock().map_err(|e| {
-            anyhow::anyhow!("Failed to lock PTY master: {}", e)
-        })?;
-
-        pty.resize(PtySize {
-            rows,
-            cols,
-            pixel_width: 0,
-            pixel_height: 0,
-        })?;
-
-        Ok(())
+        self.writer.resize(cols, rows)
     }
 
     /// Sends a mouse event to the shell using SGR extended mouse protocol.
@@ -360,3 +511,96 @@ fn mouse_to_sgr_bytes(event: MouseEvent, terminal_area: ratatui::layout::Rect) -
     let terminator = if is_release { 'm' } else { 'M' };
     format!("\x1b[<{};{};{}{}", button_code, x, y, terminator).into_bytes()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use crate::shell::{ScriptedShell, SIGINT};
+
+    #[test]
+    fn test_command_bytes_without_bracketed_paste() {
+        assert_eq!(command_bytes("ls -la", Submit::Execute, false), b"ls -la\r");
+        assert_eq!(command_bytes("ls -la", Submit::InsertOnly, false), b"ls -la");
+        // Trailing newlines never press Enter on their own
+        assert_eq!(command_bytes("make\n\r\n", Submit::Execute, false), b"make\r");
+        assert_eq!(command_bytes("make\r\n", Submit::InsertOnly, false), b"make");
+        // Trailing spaces, quotes and tabs are kept as typed
+        assert_eq!(
+            command_bytes("printf 'a\tb' \"$x\"  ", Submit::Execute, false),
+            b"printf 'a\tb' \"$x\"  \r"
+        );
+        // Inside, line endings become LF; left unexecuted they can't be Enter
+        assert_eq!(command_bytes("a &&\r\nb\rc", Submit::Execute, false), b"a &&\nb\nc\r");
+        assert_eq!(command_bytes("a &&\r\nb", Submit::InsertOnly, false), b"a && b");
+    }
+
+    #[test]
+    fn test_command_bytes_with_bracketed_paste() {
+        assert_eq!(
+            command_bytes("echo\t'hi'\n", Submit::Execute, true),
+            b"\x1b[200~echo\t'hi'\x1b[201~\r"
+        );
+        assert_eq!(
+            command_bytes("for f in *\ndo echo $f\ndone\n", Submit::InsertOnly, true),
+            b"\x1b[200~for f in *\ndo echo $f\ndone\x1b[201~"
+        );
+        // Control characters can't end the paste early
+        assert_eq!(
+            command_bytes("ls\x1b[201~; rm x\x07\x7f", Submit::InsertOnly, true),
+            b"\x1b[200~ls[201~; rm x\x1b[201~"
+        );
+    }
+
+    #[test]
+    fn test_submit_command_follows_bracketed_paste() {
+        let (tx, _events) = unbounded_channel();
+        let (mut shell, _output, scripted) = ScriptedShell::new(tx);
+        shell.submit_command("git status\n", Submit::Execute).ok();
+        shell.set_bracketed_paste(true);
+        shell.submit_command("git commit -m \"\"", Submit::InsertOnly).ok();
+        assert_eq!(scripted.input(), b"git status\r\x1b[200~git commit -m \"\"\x1b[201~");
+    }
+
+    #[test]
+    fn test_finished_commands_are_reported() -> Result<(), Box<dyn std::error::Error>> {
+        let (tx, mut events) = unbounded_channel();
+        let (mut shell, _output, _scripted) = ScriptedShell::new(tx);
+
+        // Without shell integration: over when the prompt comes back
+        shell.start_new_command("ls".to_string(), None);
+        assert_eq!(shell.unmarked_command().as_deref(), Some("ls"));
+        shell.prompt_back();
+        let event = events.try_recv()?.event;
+        assert!(matches!(
+            event,
+            AppEvent::ShellCommandCompleted { command, exit_code: None, signal: None, origin: None, .. } if command == "ls"
+        ));
+
+        // With it, the marks carry the status
+        let origin = CommandOrigin { session_id: 3, tool_call_id: "call_7".to_string() };
+        shell.shell_mark(ShellMark::PromptStart);
+        shell.start_new_command("make test".to_string(), Some(origin.clone()));
+        shell.shell_mark(ShellMark::OutputStart);
+        shell.handle_user_input(b"\x03")?;
+        assert!(events.try_recv().is_err());
+        shell.shell_mark(ShellMark::Finished(Some(130)));
+        let event = events.try_recv()?.event;
+        assert!(matches!(
+            event,
+            AppEvent::ShellCommandCompleted { command, exit_code: Some(130), signal: Some(SIGINT), origin: Some(o), .. }
+                if command == "make test" && o == origin
+        ));
+        assert!(shell.has_shell_integration());
+        assert_eq!(shell.unmarked_command(), None);
+
+        // The shell going away ends what runs in it
+        shell.start_new_command("sleep 9".to_string(), None);
+        shell.shell_mark(ShellMark::OutputStart);
+        shell.shell_exited();
+        let event = events.try_recv()?.event;
+        assert!(matches!(event, AppEvent::ShellCommandCompleted { exit_code: None, .. }));
+        Ok(())
+    }
+}
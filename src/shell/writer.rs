@@ -0,0 +1,177 @@
+//! The one way bytes reach the shell.
+//!
+//! Keys, mouse reports, pastes and commands are written from the UI loop,
+//! and through a [`PtyWriter`] handle from tasks of their own. Every write
+//! is one message on a bounded queue, and a single thread owning the
+//! [`ShellBackend`] writes the messages whole, in the order they were
+//! queued: an encoded mouse event can't land inside a paste or a submitted
+//! command, and what one sender writes arrives in the order it was sent. A
+//! sender waits while the queue is full.
+
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+
+use anyhow::{Result, anyhow};
+use tracing::error;
+
+use super::ShellBackend;
+
+/// Messages that can wait for the writer thread before senders block.
+const QUEUE_DEPTH: usize = 256;
+
+enum Request {
+    Write(Vec<u8>),
+    Resize(u16, u16),
+    /// Everything queued before is done: reply with the first error since
+    /// the last flush
+    Flush(SyncSender<Option<String>>),
+}
+
+/// Handle to the queue in front of the shell's input. Clones share it.
+#[derive(Debug, Clone)]
+pub struct PtyWriter {
+    queue: SyncSender<Request>,
+}
+
+impl PtyWriter {
+    /// Start the thread that writes to `backend`. It runs until every
+    /// handle is dropped, and the backend is dropped with it.
+    pub fn spawn(backend: Box<dyn ShellBackend>) -> Self {
+        let (queue, requests) = sync_channel(QUEUE_DEPTH);
+        std::thread::spawn(move || run(backend, requests));
+        Self { queue }
+    }
+
+    /// Queue `data` to be written in one piece, after everything queued
+    /// before it. Errors writing it come from the next [`PtyWriter::flush`].
+    pub fn write(&self, data: impl Into<Vec<u8>>) -> Result<()> {
+        self.send(Request::Write(data.into()))
+    }
+
+    /// Queue a change of the terminal size the shell sees.
+    pub fn resize(&self, cols: u16, rows: u16) -> Result<()> {
+        self.send(Request::Resize(cols, rows))
+    }
+
+    /// Wait until everything queued so far has reached the shell. Fails
+    /// with the first write or resize that failed since the last flush.
+    pub fn flush(&self) -> Result<()> {
+        let (reply, replied) = sync_channel(1);
+        self.send(Request::Flush(reply))?;
+        match replied.recv() {
+            Ok(None) => Ok(()),
+            Ok(Some(e)) => Err(anyhow!(e)),
+            Err(_) => Err(closed()),
+        }
+    }
+
+    fn send(&self, request: Request) -> Result<()> {
+        self.queue.send(request).map_err(|_| closed())
+    }
+}
+
+fn closed() -> anyhow::Error {
+    anyhow!("The shell input is closed")
+}
+
+/// The writer thread: carry out requests in order until the queue closes.
+fn run(mut backend: Box<dyn ShellBackend>, requests: Receiver<Request>) {
+    let mut failed: Option<String> = None;
+    for request in requests {
+        let result = match request {
+            Request::Write(data) => backend.write(&data),
+            Request::Resize(cols, rows) => backend.resize(cols, rows),
+            Request::Flush(reply) => {
+                // The flusher may have given up waiting
+                reply.send(failed.take()).ok();
+                continue;
+            }
+        };
+        if let Err(e) = result {
+            error!("Failed to write to the shell: {:#}", e);
+            failed.get_or_insert_with(|| format!("{:#}", e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Takes each message a byte at a time, so a message written while
+    /// another one is half done would show up inside it.
+    struct Recorder {
+        bytes: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl ShellBackend for Recorder {
+        fn write(&mut self, data: &[u8]) -> Result<()> {
+            for &b in data {
+                self.bytes.lock().map_err(|e| anyhow!("{}", e))?.push(b);
+                std::thread::yield_now();
+            }
+            Ok(())
+        }
+
+        fn resize(&mut self, _cols: u16, _rows: u16) -> Result<()> {
+            Err(anyhow!("no terminal to resize"))
+        }
+    }
+
+    /// A paste block or an encoded mouse event, telling who sent it.
+    fn message(sender: usize, seq: usize) -> Vec<u8> {
+        if seq.is_multiple_of(2) {
+            format!("\x1b[200~sender {} paste {}\nline two\x1b[201~", sender, seq).into_bytes()
+        } else {
+            format!("\x1b[<0;{};{}M", sender + 1, seq).into_bytes()
+        }
+    }
+
+    #[test]
+    fn test_concurrent_writes_arrive_whole_and_in_order() -> Result<(), Box<dyn std::error::Error>> {
+        const SENDERS: usize = 8;
+        const MESSAGES: usize = 100;
+        let bytes = Arc::new(Mutex::new(Vec::new()));
+        let writer = PtyWriter::spawn(Box::new(Recorder { bytes: bytes.clone() }));
+
+        let senders: Vec<_> = (0..SENDERS)
+            .map(|sender| {
+                let writer = writer.clone();
+                std::thread::spawn(move || (0..MESSAGES).try_for_each(|seq| writer.write(message(sender, seq))))
+            })
+            .collect();
+        for sender in senders {
+            sender.join().map_err(|_| "a sender panicked")??;
+        }
+        writer.flush()?;
+
+        // Each message follows the one before it from any sender, whole
+        let bytes = bytes.lock().map_err(|e| e.to_string())?.clone();
+        let mut next = [0; SENDERS];
+        let mut rest = bytes.as_slice();
+        while !rest.is_empty() {
+            let sender = (0..SENDERS)
+                .find(|&sender| next[sender] < MESSAGES && rest.starts_with(&message(sender, next[sender])))
+                .ok_or_else(|| format!("interleaved at {:?}", String::from_utf8_lossy(&rest[..rest.len().min(40)])))?;
+            rest = &rest[message(sender, next[sender]).len()..];
+            next[sender] += 1;
+        }
+        assert_eq!(next, [MESSAGES; SENDERS]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_reports_what_failed_since_the_last_one() -> Result<(), Box<dyn std::error::Error>> {
+        let bytes = Arc::new(Mutex::new(Vec::new()));
+        let writer = PtyWriter::spawn(Box::new(Recorder { bytes: bytes.clone() }));
+        writer.write(b"ls\r".to_vec())?;
+        writer.resize(80, 24)?;
+        writer.write(b"pwd\r".to_vec())?;
+        let error = writer.flush().err().map(|e| e.to_string());
+        assert_eq!(error.as_deref(), Some("no terminal to resize"));
+        // Writes after the failure still went through
+        assert_eq!(bytes.lock().map_err(|e| e.to_string())?.as_slice(), b"ls\rpwd\r");
+        assert!(writer.flush().is_ok());
+        Ok(())
+    }
+}
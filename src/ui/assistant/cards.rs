@@ -0,0 +1,1163 @@
+//! Command suggestion cards: the AI's suggestions waiting for a decision,
+//! the verdicts on them and how a card is drawn.
+
+use super::*;
+
+/// What a pending card says while the previous AI command runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BusyNote<'a> {
+    /// The command seems to still be running
+    Running(&'a str),
+    /// Execute was pressed once; the next press sends anyway
+    SendAnyway(&'a str),
+}
+
+impl TuiAssistant {
+    // ========================================================================
+    // Command Suggestions
+    // ========================================================================
+
+    /// Set the index used to annotate pending cards whose command already ran
+    pub fn set_prior_runs(&mut self, prior_runs: PriorRuns) {
+        self.prior_runs = prior_runs;
+    }
+
+    /// Findings for a suggested command: the project rules and allowlist,
+    /// plus one if the command was copied from the terminal output.
+    fn findings(&self, command: &str) -> Vec<Finding> {
+        let mut findings = self.policy.findings(command);
+        if self.from_output.iter().any(|c| c == command) {
+            findings.push(terminal_output_finding(command));
+            sort_findings(&mut findings);
+        }
+        findings
+    }
+
+    /// A suggested command as it will be typed: in `cwd`, with the marker
+    /// if enabled.
+    fn compose(&self, command: &str, cwd: Option<&str>) -> Composed {
+        Execution { command, cwd, mark: self.mark_ai_commands }.compose(self.shell)
+    }
+
+    /// Findings for a suggested command as it will be typed.
+    pub(super) fn card_findings(&self, command: &str, cwd: Option<&str>) -> Vec<Finding> {
+        composed_findings(self.findings(command), &self.compose(command, cwd), cwd)
+    }
+
+    /// Earlier run of a pending card's command (completed cards are not annotated)
+    pub(super) fn prior_run_for(&self, command: &str, status: CommandStatus) -> Option<PriorRun> {
+        if status != CommandStatus::Pending {
+            return None;
+        }
+        self.prior_runs.lookup(command)
+    }
+
+    /// Note the AI command sent last while it seems to still be running
+    /// (None once it is done). Pending cards warn that theirs would be
+    /// typed into it.
+    pub fn set_running_ai_command(&mut self, command: Option<String>) {
+        if self.running_ai_command != command {
+            self.running_ai_command = command;
+            self.send_anyway = false;
+        }
+    }
+
+    /// The AI command sent last, if it seems to still be running.
+    pub fn running_ai_command(&self) -> Option<&str> {
+        self.running_ai_command.as_deref()
+    }
+
+    /// Whether the pending command may be sent now. While the previous AI
+    /// command runs, the first press only asks to press again.
+    pub fn confirm_send_while_running(&mut self) -> bool {
+        if self.running_ai_command.is_none() || self.send_anyway {
+            return true;
+        }
+        self.send_anyway = true;
+        false
+    }
+
+    /// Warning for a pending card while the previous AI command runs
+    pub(super) fn busy_note_for(&self, status: CommandStatus) -> Option<BusyNote<'_>> {
+        if status != CommandStatus::Pending {
+            return None;
+        }
+        let command = self.running_ai_command.as_deref()?;
+        Some(if self.send_anyway { BusyNote::SendAnyway(command) } else { BusyNote::Running(command) })
+    }
+
+    fn draft_card_idx(&self) -> Option<usize> {
+        self.view.messages.iter().rposition(|m| matches!(m, ChatMessage::DraftCard { .. }))
+    }
+
+    /// Show or update the provisional card for a suggestion still streaming
+    pub(super) fn show_draft_card(&mut self, draft: SuggestionDraft) {
+        let card = ChatMessage::DraftCard {
+            command: draft.command,
+            explanation: draft.explanation,
+        };
+        self.arrive(|this| match this.draft_card_idx() {
+            Some(idx) => this.view.messages[idx] = card,
+            None => this.view.messages.push(card),
+        });
+    }
+
+    /// Remove the provisional card (the stream ended or failed without a suggestion)
+    pub(super) fn discard_draft_card(&mut self) {
+        if let Some(idx) = self.draft_card_idx() {
+            self.view.messages.remove(idx);
+        }
+    }
+
+    /// Add a command suggestion card (evaluates verdict automatically)
+    pub fn push_command_card(&mut self, tool_call_id: String, command: String, explanation: String) {
+        let findings = self.card_findings(&command, None);
+        self.push_command_card_with_findings(tool_call_id, command, explanation, None, findings);
+    }
+
+    /// Add a command suggestion card with pre-evaluated findings (of the
+    /// command as typed in `cwd`). A draft card for the same suggestion is
+    /// replaced in place.
+    pub(super) fn push_command_card_with_findings(
+        &mut self,
+        tool_call_id: String,
+        command: String,
+        explanation: String,
+        cwd: Option<String>,
+        findings: Vec<Finding>,
+    ) {
+        let composed = self.compose(&command, cwd.as_deref());
+        let card = ChatMessage::CommandCard {
+            command: composed.text,
+            command_start: composed.command_start,
+            cwd,
+            explanation,
+            status: CommandStatus::Pending,
+            verdict: verdict_from(&findings),
+            findings,
+            findings_expanded: false,
+            rationale: Rationale::NotAsked,
+            shell: self.shell,
+            tool_call_id,
+            safer: false,
+        };
+        let idx = self.arrive(|this| match this.draft_card_idx() {
+            Some(idx) => {
+                this.view.messages[idx] = card;
+                idx
+            }
+            None => {
+                this.view.messages.push(card);
+                this.view.messages.len() - 1
+            }
+        });
+        self.view.pending_command_idx = Some(idx);
+        self.send_anyway = false;
+    }
+
+    /// Set multiple pending commands from AI response.
+    /// Only displays the first command card; user can cycle through with Ctrl+A.
+    /// Pre-evaluates findings for all commands.
+    pub fn set_pending_commands(&mut self, commands: Vec<(String, String, String, Option<String>)>) {
+        if commands.is_empty() {
+            return;
+        }
+
+        // Pre-evaluate findings for all commands and store
+        self.view.pending_commands = commands
+            .into_iter()
+            .map(|(id, cmd, exp, cwd)| {
+                let findings = self.card_findings(&cmd, cwd.as_deref());
+                (id, cmd, exp, cwd, findings)
+            })
+            .collect();
+        self.view.current_suggestion_idx = 0;
+        self.view.safer_alternative = None;
+        self.view.checklist = None;
+        self.view.comparing = false;
+
+        // Add a command card for the first command
+        let (tool_call_id, command, explanation, cwd, findings) = self.view.pending_commands[0].clone();
+        self.push_command_card_with_findings(tool_call_id, command, explanation, cwd, findings);
+    }
+
+    /// Cycle to the next command suggestion (wraps around).
+    /// Updates the displayed command card with pre-stored findings.
+    pub fn cycle_suggestion(&mut self) {
+        if self.view.pending_commands.len() <= 1 {
+            return; // Nothing to cycle
+        }
+
+        // Move to next suggestion (wrap around)
+        self.show_suggestion((self.view.current_suggestion_idx + 1) % self.view.pending_commands.len());
+    }
+
+    /// Show suggestion `suggestion` on the pending card, with its pre-stored
+    /// findings: it is the one Ctrl+Y decides on.
+    fn show_suggestion(&mut self, suggestion: usize) {
+        if suggestion >= self.view.pending_commands.len() {
+            return;
+        }
+        self.view.current_suggestion_idx = suggestion;
+        self.send_anyway = false;
+
+        // Update the displayed command card with stored findings
+        if let Some(idx) = self.view.pending_command_idx {
+            let (new_id, new_cmd, new_exp, new_cwd, new_findings) = &self.view.pending_commands[suggestion];
+            let composed = self.compose(new_cmd, new_cwd.as_deref());
+            if let Some(ChatMessage::CommandCard {
+                command, command_start, cwd, explanation, verdict, findings, tool_call_id, safer, ..
+            }) = self.view.messages.get_mut(idx)
+            {
+                *safer = self.view.safer_alternative.as_ref() == Some(new_id);
+                *tool_call_id = new_id.clone();
+                *command = composed.text;
+                *command_start = composed.command_start;
+                *cwd = new_cwd.clone();
+                *explanation = new_exp.clone();
+                *verdict = verdict_from(new_findings);
+                *findings = new_findings.clone();
+            }
+        }
+    }
+
+    /// Offer `alternative` (tool_call_id, command, explanation, cwd), a
+    /// safer command than the pending suggestion `original`, ahead of it:
+    /// the card shows it now, and the original is one Ctrl+A away.
+    pub(super) fn add_safer_alternative(&mut self, original: &str, alternative: (String, String, String, Option<String>)) {
+        if !self.view.pending_commands.iter().any(|(id, ..)| id == original) {
+            return;
+        }
+        let (id, command, explanation, cwd) = alternative;
+        let findings = self.card_findings(&command, cwd.as_deref());
+        self.view.safer_alternative = Some(id.clone());
+        self.view.pending_commands.insert(0, (id, command, explanation, cwd, findings));
+        self.view.checklist = None;
+        self.view.comparing = false;
+        // Cycling on from the last one shows the first
+        self.view.current_suggestion_idx = self.view.pending_commands.len() - 1;
+        self.cycle_suggestion();
+    }
+
+    /// Switch the pending card between one suggestion at a time and the
+    /// checklist of all of them ('m'). Only a reply with several
+    /// suggestions has a checklist.
+    pub fn toggle_checklist(&mut self) -> bool {
+        if self.view.pending_commands.len() <= 1 || self.view.pending_command_idx.is_none() {
+            return false;
+        }
+        self.view.checklist = match self.view.checklist {
+            Some(_) => None,
+            None => Some(Checklist::new(self.view.pending_commands.len())),
+        };
+        self.view.comparing = false;
+        true
+    }
+
+    /// Switch the pending card between one suggestion at a time and all of
+    /// them listed to compare ('c'). Only a reply with several suggestions
+    /// can be compared.
+    pub fn toggle_compare(&mut self) -> bool {
+        if self.view.pending_commands.len() <= 1 || self.view.pending_command_idx.is_none() {
+            return false;
+        }
+        self.view.comparing = !self.view.comparing;
+        self.view.checklist = None;
+        true
+    }
+
+    pub fn is_comparing(&self) -> bool {
+        self.view.comparing
+    }
+
+    /// Whether the card at `msg_idx` lists the suggestions to compare.
+    pub(super) fn comparing_for(&self, msg_idx: usize) -> bool {
+        self.view.comparing && self.view.pending_command_idx == Some(msg_idx)
+    }
+
+    /// Move the highlight of the comparison `delta` rows, stopping at
+    /// either end.
+    pub fn move_compare(&mut self, delta: isize) {
+        if !self.view.comparing {
+            return;
+        }
+        let last = self.view.pending_commands.len().saturating_sub(1);
+        self.show_suggestion(self.view.current_suggestion_idx.saturating_add_signed(delta).min(last));
+    }
+
+    /// Highlight the suggestion of row `row` of the comparison (a click).
+    pub fn compare_row(&mut self, row: usize) {
+        if self.view.comparing {
+            self.show_suggestion(row);
+        }
+    }
+
+    pub fn checklist(&self) -> Option<&Checklist> {
+        self.view.checklist.as_ref()
+    }
+
+    pub fn checklist_mut(&mut self) -> Option<&mut Checklist> {
+        self.view.checklist.as_mut()
+    }
+
+    /// The checklist, if the card at `msg_idx` shows it.
+    pub(super) fn checklist_for(&self, msg_idx: usize) -> Option<&Checklist> {
+        self.view.checklist.as_ref().filter(|_| self.view.pending_command_idx == Some(msg_idx))
+    }
+
+    /// Tool calls of the suggestions selected in the checklist, in the
+    /// order they run.
+    pub fn selected_tool_call_ids(&self) -> Vec<String> {
+        let Some(checklist) = &self.view.checklist else {
+            return Vec::new();
+        };
+        checklist
+            .selected()
+            .into_iter()
+            .filter_map(|idx| self.view.pending_commands.get(idx).map(|(id, ..)| id.clone()))
+            .collect()
+    }
+
+    /// The selected suggestions were accepted. The session shows every card
+    /// of the reply with what became of it once its messages are loaded
+    /// again.
+    pub fn confirm_checklist(&mut self) {
+        self.view.pending_command_idx = None;
+        self.view.pending_commands.clear();
+        self.view.current_suggestion_idx = 0;
+        self.view.checklist = None;
+        self.view.comparing = false;
+        self.send_anyway = false;
+    }
+
+    /// Get the current suggestion index and total count for display.
+    /// Returns (current_index, total_count) where current_index is 1-based.
+    pub fn suggestion_pagination(&self) -> Option<(usize, usize)> {
+        if self.view.pending_commands.len() <= 1 {
+            None // Don't show pagination for single command
+        } else {
+            Some((self.view.current_suggestion_idx + 1, self.view.pending_commands.len()))
+        }
+    }
+
+    /// Tool call of the pending card on display, which names the
+    /// suggestion to the session manager when the user decides on it.
+    pub fn pending_tool_call_id(&self) -> Option<String> {
+        match self.view.messages.get(self.view.pending_command_idx?)? {
+            ChatMessage::CommandCard { tool_call_id, status: CommandStatus::Pending, .. } => Some(tool_call_id.clone()),
+            _ => None,
+        }
+    }
+
+    /// Another session with a suggestion waiting for a decision, if the
+    /// active one has none on display.
+    pub fn pending_elsewhere(&self) -> Option<&SessionTab> {
+        if self.has_pending_command() {
+            return None;
+        }
+        self.session_tabs.iter().find(|tab| tab.pending && tab.id != self.active_session)
+    }
+
+    // ========================================================================
+    // Command Confirmation
+    // ========================================================================
+
+    /// Check if there's a pending command waiting for confirmation
+    pub fn has_pending_command(&self) -> bool {
+        self.view.pending_command_idx.is_some()
+    }
+
+    /// Confirm the pending command (Y key) - returns the command string if allowed
+    ///
+    /// This method enforces verdict gating:
+    /// - `Verdict::Allow`: Can be executed immediately
+    /// - `Verdict::RequireConfirmation`: Can be executed after user confirmation
+    /// - `Verdict::Deny`: Cannot be executed, returns None
+    ///
+    /// # Returns
+    /// - `Some(command)` if the command can be executed (Allow or RequireConfirmation)
+    /// - `None` if the command is denied or no pending command exists
+    pub fn confirm_command(&mut self) -> Option<String> {
+        if let Some(idx) = self.view.pending_command_idx.take()
+            && let Some(ChatMessage::CommandCard { command, status, verdict, .. }) =
+                self.view.messages.get_mut(idx)
+        {
+            // Verdict gating: only Allow and RequireConfirmation can execute
+            if verdict.is_allowed() {
+                *status = CommandStatus::Executed;
+                let result = command.clone();
+                // Clear multi-command state
+                self.view.pending_commands.clear();
+                self.view.current_suggestion_idx = 0;
+                self.view.checklist = None;
+                self.view.comparing = false;
+                self.send_anyway = false;
+                return Some(result);
+            } else {
+                // Deny verdict: do not execute, just clear pending state
+                *status = CommandStatus::Rejected;
+                // Clear multi-command state
+                self.view.pending_commands.clear();
+                self.view.current_suggestion_idx = 0;
+                self.view.checklist = None;
+                self.view.comparing = false;
+                self.send_anyway = false;
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Reject the pending command (N key)
+    pub fn reject_command(&mut self) {
+        self.copy_error = None;
+        if let Some(idx) = self.view.pending_command_idx.take()
+            && let Some(ChatMessage::CommandCard { status, .. }) = self.view.messages.get_mut(idx)
+        {
+            *status = CommandStatus::Rejected;
+        }
+        // Clear multi-command state
+        self.view.pending_commands.clear();
+        self.view.current_suggestion_idx = 0;
+        self.view.checklist = None;
+        self.view.comparing = false;
+        self.send_anyway = false;
+    }
+
+    /// Check if the current pending command has a Deny verdict
+    pub fn is_pending_command_denied(&self) -> bool {
+        if let Some(idx) = self.view.pending_command_idx
+            && let Some(ChatMessage::CommandCard { verdict, .. }) = self.view.messages.get(idx)
+        {
+            return verdict.is_deny();
+        }
+        false
+    }
+
+    /// The pending command, if it uses syntax the card's shell won't accept.
+    /// Only the suggested part is checked: the `cd` and marker are written
+    /// for the shell.
+    pub fn pending_incompatible_command(&self) -> Option<(&str, ShellKind)> {
+        let idx = self.view.pending_command_idx?;
+        match self.view.messages.get(idx) {
+            Some(ChatMessage::CommandCard { command, command_start, shell, .. })
+                if !lint_for_shell(&command[*command_start..], *shell).is_empty() =>
+            {
+                Some((&command[*command_start..], *shell))
+            }
+            _ => None,
+        }
+    }
+
+    /// Show or hide all findings of the card at `msg_idx`. Returns false if
+    /// it is not a card with findings.
+    pub fn toggle_findings(&mut self, msg_idx: usize) -> bool {
+        match self.view.messages.get_mut(msg_idx) {
+            Some(ChatMessage::CommandCard { findings, findings_expanded, .. }) if !findings.is_empty() => {
+                *findings_expanded = !*findings_expanded;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Ask why the card at `msg_idx` got its Confirm or Deny verdict ('?' or
+    /// its "[?] why"). The first time the card starts asking and the
+    /// question is returned for the AI; once the answer is in, this shows
+    /// or hides it. A second request never goes out while one is on its way.
+    pub fn why_verdict(&mut self, msg_idx: usize) -> WhyVerdict {
+        let Some(ChatMessage::CommandCard {
+            command,
+            cwd,
+            status: CommandStatus::Pending,
+            verdict: Verdict::RequireConfirmation(_) | Verdict::Deny(_),
+            findings,
+            rationale,
+            shell,
+            tool_call_id,
+            ..
+        }) = self.view.messages.get_mut(msg_idx)
+        else {
+            return WhyVerdict::NotApplicable;
+        };
+        match rationale {
+            Rationale::NotAsked => {
+                *rationale = Rationale::Asking;
+                WhyVerdict::Ask(VerdictQuestion {
+                    tool_call_id: tool_call_id.clone(),
+                    command: command.clone(),
+                    cwd: cwd.clone(),
+                    shell: *shell,
+                    findings: findings.clone(),
+                })
+            }
+            Rationale::Asking => WhyVerdict::Handled,
+            Rationale::Ready { expanded, .. } => {
+                *expanded = !*expanded;
+                WhyVerdict::Handled
+            }
+        }
+    }
+
+    /// Ask why the pending card got its verdict (see [`Self::why_verdict`]).
+    pub fn why_pending_verdict(&mut self) -> WhyVerdict {
+        match self.view.pending_command_idx {
+            Some(idx) => self.why_verdict(idx),
+            None => WhyVerdict::NotApplicable,
+        }
+    }
+
+    /// Show the AI's explanation of the verdict on the card of
+    /// `tool_call_id`. Without one (the AI is unavailable) the card lists
+    /// all its findings instead.
+    pub fn set_rationale(&mut self, id: &str, result: Result<String, String>) {
+        let Some(ChatMessage::CommandCard { rationale, findings_expanded, .. }) = self
+            .view.messages
+            .iter_mut()
+            .find(|m| matches!(m, ChatMessage::CommandCard { tool_call_id, .. } if tool_call_id == id))
+        else {
+            return;
+        };
+        match result {
+            Ok(text) => *rationale = Rationale::Ready { text, expanded: true },
+            Err(_) => {
+                *rationale = Rationale::NotAsked;
+                *findings_expanded = true;
+                self.notice = Some("AI explanation unavailable: all findings shown".to_string());
+            }
+        }
+    }
+
+    /// Show or hide all findings of the pending card.
+    pub fn toggle_pending_findings(&mut self) -> bool {
+        self.view.pending_command_idx.is_some_and(|idx| self.toggle_findings(idx))
+    }
+
+    /// Copy the pending command to clipboard (for Deny verdict)
+    /// Returns the command string if successful; on failure the card stays pending
+    pub fn copy_pending_command(&mut self) -> Option<String> {
+        let idx = self.view.pending_command_idx?;
+        let Some(ChatMessage::CommandCard { command, .. }) = self.view.messages.get(idx) else {
+            return None;
+        };
+        let cmd = command.clone();
+        let outcome = copy_to_clipboard(&cmd);
+        self.finish_pending_copy(outcome).then_some(cmd)
+    }
+
+    /// Mark the pending card as copied if a backend accepted it.
+    pub(super) fn finish_pending_copy(&mut self, outcome: CopyOutcome) -> bool {
+        if !outcome.is_copied() {
+            self.copy_error = Some("clipboard unavailable — command not copied");
+            return false;
+        }
+        self.copy_error = None;
+        if let Some(idx) = self.view.pending_command_idx.take()
+            && let Some(ChatMessage::CommandCard { status, .. }) = self.view.messages.get_mut(idx)
+        {
+            *status = CommandStatus::Executed; // "Executed" means "Copied" for Deny
+        }
+        // Clear multi-command state
+        self.view.pending_commands.clear();
+        self.view.current_suggestion_idx = 0;
+        self.view.checklist = None;
+        self.view.comparing = false;
+        self.send_anyway = false;
+        true
+    }
+}
+
+/// What a tracked card shows, for its hit areas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CardLayout {
+    Single,
+    /// One of several suggestions, with Next and Multi buttons
+    Paginated,
+    /// The checklist of that many suggestions
+    Checklist(usize),
+    /// That many suggestions listed to compare, two lines each
+    Compare(usize),
+}
+
+/// Render the suggestions of a reply listed to compare: each with its
+/// verdict, the words that differ from the first suggestion picked out and
+/// a line of its explanation, then the buttons. `highlighted` is the one
+/// Ctrl+Y runs.
+pub(super) fn render_comparison(
+    suggestions: &[PendingCommand],
+    highlighted: usize,
+    width: u16,
+) -> Vec<Line<'static>> {
+    let card_width = (width as usize).saturating_sub(4).max(20);
+    let border_style = Style::default().fg(Color::Blue);
+    let title = format!(" Compare ({} suggestions) ", suggestions.len());
+    let mut lines = vec![Line::from(vec![
+        Span::styled(" ┌─", border_style),
+        Span::styled(title.clone(), border_style.bold()),
+        Span::styled(format!("{}┐", "─".repeat(card_width.saturating_sub(title.width() + 1))), border_style),
+    ])];
+
+    let base = suggestions.first().map_or("", |(_, command, ..)| command.as_str());
+    for (idx, (_, command, explanation, _, findings)) in suggestions.iter().enumerate() {
+        let (badge, badge_style) = match verdict_from(findings) {
+            Verdict::Allow => ("✓ Allow  ", Style::default().fg(Color::Green)),
+            Verdict::RequireConfirmation(_) => ("⚠ Confirm", Style::default().fg(Color::Yellow)),
+            Verdict::Deny(_) => ("✗ Deny   ", Style::default().fg(Color::Red).bold()),
+        };
+        let current = idx == highlighted;
+        let text_style = if current { Style::default().bold() } else { Style::default() };
+        let changed_style = text_style.fg(Color::Yellow).add_modifier(Modifier::UNDERLINED);
+        let pointer = if current { "▸ " } else { "  " };
+
+        let mut spans = vec![
+            Span::styled(" │", border_style),
+            Span::styled(pointer, text_style),
+            Span::styled(badge, badge_style),
+        ];
+        let mut room = card_width.saturating_sub(pointer.width() + badge.width());
+        for (word, changed) in word_diff(base, command) {
+            let word = format!(" {}", word);
+            if word.width() > room {
+                let cut: String = word.chars().take(room.saturating_sub(1)).collect();
+                spans.push(Span::styled(format!("{}…", cut), text_style));
+                room = room.saturating_sub(cut.width() + 1);
+                break;
+            }
+            room -= word.width();
+            spans.push(Span::styled(word, if changed { changed_style } else { text_style }));
+        }
+        spans.push(Span::raw(" ".repeat(room)));
+        spans.push(Span::styled("│", border_style));
+        lines.push(Line::from(spans));
+
+        let summary = explanation.lines().next().unwrap_or_default();
+        lines.push(Line::from(vec![
+            Span::styled(" │", border_style),
+            Span::styled(format_card_line(&format!("    {}", summary), card_width), Style::default().fg(Color::DarkGray)),
+            Span::styled("│", border_style),
+        ]));
+    }
+
+    let hint = format_card_line("j/k or ↑/↓: highlight  c: one at a time", card_width);
+    lines.push(Line::from(vec![
+        Span::styled(" │", border_style),
+        Span::styled(hint, Style::default().fg(Color::DarkGray)),
+        Span::styled("│", border_style),
+    ]));
+
+    let exec_btn_style = Style::default().fg(Color::White).bg(Color::Rgb(0, 100, 0)).bold();
+    let cancel_btn_style = Style::default().fg(Color::White).bg(Color::Rgb(139, 0, 0)).bold();
+    let mut spans = vec![
+        Span::styled(" │", border_style),
+        Span::styled(" [Ctrl+Y] Run highlighted ", exec_btn_style),
+        Span::raw(" "),
+        Span::styled(" [Ctrl+N] Cancel ", cancel_btn_style),
+    ];
+    let btn_content_width: usize = spans.iter().skip(1).map(|s| s.content.chars().count()).sum();
+    spans.push(Span::styled(" ".repeat(card_width.saturating_sub(btn_content_width)), border_style));
+    spans.push(Span::styled("│", border_style));
+    lines.push(Line::from(spans));
+    lines.push(Line::styled(format!(" └{}┘", "─".repeat(card_width)), border_style));
+    lines
+}
+
+/// Render the checklist of a reply's suggestions: a row each, with the
+/// place it runs in if selected and its verdict, then the buttons.
+pub(super) fn render_checklist(
+    suggestions: &[PendingCommand],
+    checklist: &Checklist,
+    width: u16,
+) -> Vec<Line<'static>> {
+    let card_width = (width as usize).saturating_sub(4).max(20);
+    let border_style = Style::default().fg(Color::Blue);
+    let selected = checklist.selected().len();
+    let title = format!(" Run several ({} of {} selected) ", selected, suggestions.len());
+    let mut lines = vec![Line::from(vec![
+        Span::styled(" ┌─", border_style),
+        Span::styled(title.clone(), border_style.bold()),
+        Span::styled(format!("{}┐", "─".repeat(card_width.saturating_sub(title.width() + 1))), border_style),
+    ])];
+
+    for row in checklist.rows() {
+        let Some((_, command, _, _, findings)) = suggestions.get(row.suggestion) else {
+            continue;
+        };
+        let (badge, badge_style) = match verdict_from(findings) {
+            Verdict::Allow => ("✓ Allow  ", Style::default().fg(Color::Green)),
+            Verdict::RequireConfirmation(_) => ("⚠ Confirm", Style::default().fg(Color::Yellow)),
+            Verdict::Deny(_) => ("✗ Deny   ", Style::default().fg(Color::Red).bold()),
+        };
+        let pointer = if row.current { "▸ " } else { "  " };
+        let step = row.step.map_or_else(|| "[ ]".to_string(), |step| format!("[{}]", step));
+        let lead = format!("{}{} ", pointer, step);
+        let text_style = if row.current { Style::default().bold() } else { Style::default() };
+        let rest = format_card_line(
+            &format!(" {}", command),
+            card_width.saturating_sub(lead.width() + badge.width()),
+        );
+        lines.push(Line::from(vec![
+            Span::styled(" │", border_style),
+            Span::styled(lead, text_style),
+            Span::styled(badge, badge_style),
+            Span::styled(rest, text_style),
+            Span::styled("│", border_style),
+        ]));
+    }
+
+    let hint = format_card_line("Space: select  j/k: move  ↑/↓: cursor  m: one at a time", card_width);
+    lines.push(Line::from(vec![
+        Span::styled(" │", border_style),
+        Span::styled(hint, Style::default().fg(Color::DarkGray)),
+        Span::styled("│", border_style),
+    ]));
+
+    let exec_btn_style = Style::default().fg(Color::White).bg(Color::Rgb(0, 100, 0)).bold();
+    let cancel_btn_style = Style::default().fg(Color::White).bg(Color::Rgb(139, 0, 0)).bold();
+    let mut spans = vec![
+        Span::styled(" │", border_style),
+        Span::styled(" [Ctrl+Y] Run selected ", exec_btn_style),
+        Span::raw(" "),
+        Span::styled(" [Ctrl+N] Cancel ", cancel_btn_style),
+    ];
+    let btn_content_width: usize = spans.iter().skip(1).map(|s| s.content.chars().count()).sum();
+    spans.push(Span::styled(" ".repeat(card_width.saturating_sub(btn_content_width)), border_style));
+    spans.push(Span::styled("│", border_style));
+    lines.push(Line::from(spans));
+    lines.push(Line::styled(format!(" └{}┘", "─".repeat(card_width)), border_style));
+    lines
+}
+
+/// The "why" button at the end of a card's verdict line: only pending
+/// cards the analyzer flagged have one.
+pub(super) fn why_label(card: &ChatMessage) -> Option<&'static str> {
+    let ChatMessage::CommandCard { status: CommandStatus::Pending, verdict, rationale, .. } = card else {
+        return None;
+    };
+    if *verdict == Verdict::Allow {
+        return None;
+    }
+    Some(match rationale {
+        Rationale::NotAsked | Rationale::Ready { expanded: false, .. } => "[?] why",
+        Rationale::Asking => "[?] asking…",
+        Rationale::Ready { expanded: true, .. } => "[?] hide",
+    })
+}
+
+/// Render a command suggestion card (anything else renders as nothing)
+/// `busy` warns that the previous AI command still runs (pending cards only)
+/// `pagination` is Some((current, total)) for multi-command display, None for single command or history
+pub(super) fn render_command_card(
+    card: &ChatMessage,
+    prior_run: Option<PriorRun>,
+    busy: Option<BusyNote<'_>>,
+    width: u16,
+    pagination: Option<(usize, usize)>,
+) -> Vec<Line<'static>> {
+    let ChatMessage::CommandCard {
+        command,
+        command_start,
+        explanation,
+        status,
+        verdict,
+        findings,
+        findings_expanded,
+        rationale,
+        shell,
+        safer,
+        ..
+    } = card
+    else {
+        return Vec::new();
+    };
+    let (status, shell) = (*status, *shell);
+    let mut lines = Vec::new();
+
+    // Determine verdict label and style based on verdict
+    let (verdict_label, verdict_style) = match verdict {
+        Verdict::Allow => ("✓ Allow", Style::default().fg(Color::Green)),
+        Verdict::RequireConfirmation(_) => ("⚠ Confirm", Style::default().fg(Color::Yellow)),
+        Verdict::Deny(_) => ("✗ Deny", Style::default().fg(Color::Red).bold()),
+    };
+
+    // Determine border color based on status (overrides verdict color when not pending)
+    let border_color = match status {
+        CommandStatus::Executed => Color::Cyan,
+        CommandStatus::Rejected => Color::Red,
+        CommandStatus::Superseded | CommandStatus::Skipped => Color::DarkGray,
+        CommandStatus::Pending => match verdict {
+            Verdict::Allow => Color::Green,
+            Verdict::RequireConfirmation(_) => Color::Yellow,
+            Verdict::Deny(_) => Color::Red,
+        },
+    };
+
+    let card_width = (width as usize).saturating_sub(4).max(20);
+
+    // Top border with the shell badge and pagination indicator
+    let label = if *safer { "Safer alternative" } else { "Suggestion" };
+    let title = match pagination {
+        Some((current, total)) => format!(" {} ({}/{}) ", label, current, total),
+        None if *safer => format!(" {} ", label),
+        None => String::new(),
+    };
+
+    let border_style = Style::default().fg(border_color);
+    let badge = format!(" {} ", shell.label());
+    let badge_len = badge.chars().count() + 1;
+    if title.is_empty() {
+        lines.push(Line::from(vec![
+            Span::styled(" ┌─", border_style),
+            Span::styled(badge, Style::default().fg(Color::DarkGray)),
+            Span::styled(format!("{}┐", "─".repeat(card_width.saturating_sub(badge_len))), border_style),
+        ]));
+    } else {
+        // Center the title in the top border
+        let title_len = title.chars().count();
+        let remaining = card_width.saturating_sub(title_len);
+        let left_dashes = remaining / 2;
+        let right_dashes = remaining - left_dashes;
+        lines.push(Line::from(vec![
+            Span::styled(" ┌─", border_style),
+            Span::styled(badge, Style::default().fg(Color::DarkGray)),
+            Span::styled("─".repeat(left_dashes.saturating_sub(badge_len)), border_style),
+            Span::styled(title, border_style),
+            Span::styled(format!("{}┐", "─".repeat(right_dashes)), border_style),
+        ]));
+    }
+
+    // Verdict line with reason (the top finding); 'i' or a click lists
+    // every finding with the part of the command it is about
+    let mut verdict_text = if let Some(r) = verdict.reason() {
+        format!("{}: {}", verdict_label, r)
+    } else {
+        verdict_label.to_string()
+    };
+    if !findings_expanded && findings.len() > 1 {
+        verdict_text.push_str(&format!(" (+{} more)", findings.len() - 1));
+    }
+    // '?' asks the AI why, right-aligned so the reason keeps the room
+    let why = why_label(card);
+    let why_width = why.map_or(0, |label| label.width());
+    let verdict_line = format_card_line(&verdict_text, card_width.saturating_sub(why_width));
+    let mut verdict_spans = vec![
+        Span::styled(" │", Style::default().fg(border_color)),
+        Span::styled(verdict_line, verdict_style),
+    ];
+    verdict_spans.extend(why.map(|label| Span::styled(label, Style::default().fg(Color::DarkGray))));
+    verdict_spans.push(Span::styled("│", Style::default().fg(border_color)));
+    lines.push(Line::from(verdict_spans));
+    if *findings_expanded {
+        for finding in findings {
+            let style = match finding.severity {
+                Severity::Deny => Style::default().fg(Color::Red),
+                Severity::Confirm => Style::default().fg(Color::Yellow),
+            };
+            let text = format!(
+                "  • {} `{}`: {}",
+                finding.category.id(),
+                finding.matched(command),
+                finding.message
+            );
+            for finding_line in wrap_text(&text, card_width) {
+                lines.push(Line::from(vec![
+                    Span::styled(" │", border_style),
+                    Span::styled(format_card_line(&finding_line, card_width), style),
+                    Span::styled("│", border_style),
+                ]));
+            }
+        }
+    }
+
+    // The AI's take on the verdict; it never changes the verdict itself
+    let explanation_lines = match rationale {
+        Rationale::Asking => vec!["AI explanation: asking…".to_string()],
+        Rationale::Ready { text, expanded: true } => {
+            let mut wrapped = vec!["AI explanation (advisory, may be wrong):".to_string()];
+            for paragraph in text.lines() {
+                let indented = wrap_text(paragraph, card_width.saturating_sub(2)).into_iter();
+                wrapped.extend(indented.map(|line| format!("  {}", line)));
+            }
+            wrapped
+        }
+        Rationale::NotAsked | Rationale::Ready { expanded: false, .. } => Vec::new(),
+    };
+    for explanation_line in explanation_lines {
+        lines.push(Line::from(vec![
+            Span::styled(" │", border_style),
+            Span::styled(format_card_line(&explanation_line, card_width), Style::default().fg(Color::Gray)),
+            Span::styled("│", border_style),
+        ]));
+    }
+
+    // Constructs that won't work in this shell, with the way to fix them
+    if let Some(problem) = lint_for_shell(&command[*command_start..], shell).first() {
+        let offer = if status == CommandStatus::Pending {
+            format!(" (Ctrl+T: rewrite for {})", shell.label())
+        } else {
+            String::new()
+        };
+        let warning = format!("⚠ {}: {}{}", shell.label(), problem, offer);
+        for warning_line in wrap_text(&warning, card_width) {
+            lines.push(Line::from(vec![
+                Span::styled(" │", border_style),
+                Span::styled(format_card_line(&warning_line, card_width), Style::default().fg(Color::Yellow)),
+                Span::styled("│", border_style),
+            ]));
+        }
+    }
+
+    // Informational note when the command already ran (never affects the verdict)
+    if let Some(run) = prior_run {
+        let note_style = if run.failed() {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        let note_line = format_card_line(&run.annotation(std::time::Instant::now()), card_width);
+        lines.push(Line::from(vec![
+            Span::styled(" │", border_style),
+            Span::styled(note_line, note_style),
+            Span::styled("│", border_style),
+        ]));
+    }
+
+    // The previous AI command would get this one as input
+    if let Some(busy) = busy {
+        let text = match busy {
+            BusyNote::Running(running) if verdict.is_deny() => {
+                format!("⚠ previous AI command `{}` appears to still be running", running)
+            }
+            BusyNote::Running(running) => format!(
+                "⚠ previous AI command `{}` appears to still be running (Ctrl+K: stop it, then run)",
+                running
+            ),
+            BusyNote::SendAnyway(running) => {
+                format!("⚠ `{}` is still running: press again to send anyway, or Ctrl+N to cancel", running)
+            }
+        };
+        for busy_line in wrap_text(&text, card_width) {
+            lines.push(Line::from(vec![
+                Span::styled(" │", border_style),
+                Span::styled(format_card_line(&busy_line, card_width), Style::default().fg(Color::Yellow)),
+                Span::styled("│", border_style),
+            ]));
+        }
+    }
+
+    // Explanation lines (supports multi-line wrapping)
+    if !explanation.is_empty() {
+        for exp_line in wrap_text(explanation, card_width) {
+            let padded = format!("{:width$}", exp_line, width = card_width);
+            lines.push(Line::from(vec![
+                Span::styled(" │", border_style),
+                Span::styled(padded, border_style),
+                Span::styled("│", border_style),
+            ]));
+        }
+    }
+
+    // Command line with > prefix
+    let cmd_display = format!("> {}", command);
+    let cmd_line = format_card_line(&cmd_display, card_width);
+    lines.push(Line::from(vec![
+        Span::styled(" │", border_style),
+        Span::styled(cmd_line, Style::default().fg(Color::White).bold()),
+        Span::styled("│", border_style),
+    ]));
+
+    // Status/action line based on verdict and status
+    match status {
+        CommandStatus::Pending => {
+            // Button styles with distinct backgrounds
+            let next_btn_style = Style::default().fg(Color::White).bg(Color::Blue).bold();
+            let exec_btn_style = Style::default().fg(Color::White).bg(Color::Rgb(0, 100, 0)).bold();
+            let cancel_btn_style = Style::default().fg(Color::White).bg(Color::Rgb(139, 0, 0)).bold();
+
+            // Determine button labels based on verdict
+            let exec_label = if verdict.is_deny() { " [Ctrl+Y] Copy " } else { " [Ctrl+Y] Execute " };
+
+            let mut spans = vec![Span::styled(" │", border_style)];
+
+            // Add Next button if pagination exists
+            if pagination.is_some() {
+                spans.push(Span::styled(" [Ctrl+A] Next ", next_btn_style));
+                spans.push(Span::raw(" "));
+            }
+
+            // Execute/Copy button
+            spans.push(Span::styled(exec_label, exec_btn_style));
+            spans.push(Span::raw(" "));
+
+            // Cancel button
+            spans.push(Span::styled(" [Ctrl+N] Cancel ", cancel_btn_style));
+
+            // Multi and Compare buttons: pick several of the suggestions
+            // instead, or list them all
+            if pagination.is_some() {
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(" [m] Multi ", next_btn_style));
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(" [c] Compare ", next_btn_style));
+            }
+
+            // Calculate padding to fill card width
+            let btn_content_width: usize = spans.iter().skip(1).map(|s| s.content.chars().count()).sum();
+            let padding_needed = card_width.saturating_sub(btn_content_width);
+            if padding_needed > 0 {
+                spans.push(Span::styled(" ".repeat(padding_needed), border_style));
+            }
+
+            spans.push(Span::styled("│", border_style));
+            lines.push(Line::from(spans));
+        }
+        CommandStatus::Executed => {
+            let status_text = if verdict.is_deny() { "✓ Copied" } else { "✓ Executed" };
+            let status_line = format_card_line(status_text, card_width);
+            lines.push(Line::from(vec![
+                Span::styled(" │", border_style),
+                Span::styled(status_line, Style::default().fg(Color::Cyan)),
+                Span::styled("│", border_style),
+            ]));
+        }
+        CommandStatus::Rejected => {
+            let status_line = format_card_line("✗ Rejected", card_width);
+            lines.push(Line::from(vec![
+                Span::styled(" │", border_style),
+                Span::styled(status_line, Style::default().fg(Color::Red).dim()),
+                Span::styled("│", border_style),
+            ]));
+        }
+        CommandStatus::Skipped => {
+            let status_line = format_card_line("– Not run (an earlier command stopped the batch)", card_width);
+            lines.push(Line::from(vec![
+                Span::styled(" │", border_style),
+                Span::styled(status_line, Style::default().fg(Color::DarkGray)),
+                Span::styled("│", border_style),
+            ]));
+        }
+        CommandStatus::Superseded => {
+            let status_line = format_card_line("↻ Superseded", card_width);
+            lines.push(Line::from(vec![
+                Span::styled(" │", border_style),
+                Span::styled(status_line, Style::default().fg(Color::DarkGray)),
+                Span::styled("│", border_style),
+            ]));
+        }
+    }
+
+    // Bottom border
+    let bottom_border = format!(" └{}┘", "─".repeat(card_width));
+    lines.push(Line::from(vec![Span::styled(bottom_border, border_style)]));
+
+    lines
+}
+
+/// A card drawn on its own, outside the assistant pane: without the notes
+/// only the pane keeps track of (earlier runs, the running AI command, the
+/// other suggestions of the reply).
+pub(crate) fn render_card(card: &ChatMessage, width: u16) -> Vec<Line<'static>> {
+    render_command_card(card, None, None, width, None)
+}
+
+/// Render the greyed-out card for a suggestion whose arguments are still
+/// streaming, with whatever fields have arrived
+pub(super) fn render_draft_card(command: Option<&str>, explanation: Option<&str>, width: u16) -> Vec<Line<'static>> {
+    let style = Style::default().fg(Color::DarkGray);
+    let card_width = (width as usize).saturating_sub(4).max(20);
+    let mut lines = Vec::new();
+
+    let title = " suggestion incoming… ";
+    let remaining = card_width.saturating_sub(title.chars().count());
+    let left_dashes = remaining / 2;
+    lines.push(Line::styled(
+        format!(" ┌{}{}{}┐", "─".repeat(left_dashes), title, "─".repeat(remaining - left_dashes)),
+        style,
+    ));
+
+    let mut body = Vec::new();
+    if let Some(explanation) = explanation.filter(|e| !e.is_empty()) {
+        body.extend(wrap_text(explanation, card_width).iter().map(|l| format_card_line(l, card_width)));
+    }
+    body.push(format_card_line(&format!("> {}", command.unwrap_or("…")), card_width));
+    for text in body {
+        lines.push(Line::styled(format!(" │{}│", text), style));
+    }
+
+    lines.push(Line::styled(format!(" └{}┘", "─".repeat(card_width)), style));
+    lines
+}
+
+/// Format a line to fit within the card width, padding or truncating as needed
+fn format_card_line(text: &str, width: usize) -> String {
+    let text_width = text.chars().count();
+    if text_width >= width {
+        // Truncate with ellipsis
+        let truncated: String = text.chars().take(width.saturating_sub(3)).collect();
+        format!("{}...", truncated)
+    } else {
+        // Pad with spaces
+        format!("{:width$}", text, width = width)
+    }
+}
+
+/// Wrap text into multiple lines based on width (word-aware wrapping)
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    if width == 0 {
+        return vec![text.to_string()];
+    }
+
+    let mut result = Vec::new();
+    let mut current_line = String::new();
+    let mut current_width = 0;
+
+    for word in text.split_whitespace() {
+        let word_len = word.chars().count();
+
+        if current_width == 0 {
+            // First word on line
+            if word_len > width {
+                // Word itself is too long, force split
+                let mut chars = word.chars().peekable();
+                while chars.peek().is_some() {
+                    let chunk: String = chars.by_ref().take(width).collect();
+                    result.push(chunk);
+                }
+            } else {
+                current_line = word.to_string();
+                current_width = word_len;
+            }
+        } else if current_width + 1 + word_len <= width {
+            // Word fits on current line with space
+            current_line.push(' ');
+            current_line.push_str(word);
+            current_width += 1 + word_len;
+        } else {
+            // Start new line
+            result.push(current_line);
+            if word_len > width {
+                // Word itself is too long, force split
+                let mut chars = word.chars().peekable();
+                while chars.peek().is_some() {
+                    let chunk: String = chars.by_ref().take(width).collect();
+                    result.push(chunk);
+                }
+                current_line = String::new();
+                current_width = 0;
+            } else {
+                current_line = word.to_string();
+                current_width = word_len;
+            }
+        }
+    }
+
+    // Don't forget the last line
+    if !current_line.is_empty() {
+        result.push(current_line);
+    }
+
+    if result.is_empty() {
+        result.push(String::new());
+    }
+
+    result
+}
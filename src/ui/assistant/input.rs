@@ -0,0 +1,958 @@
+//! The input box: editing (emacs-style or vi), selection, where the text
+//! wraps, the context chips above it and the files dropped on it.
+
+use super::*;
+use ratatui::widgets::Wrap;
+
+/// Text typed into the input box and how it is being edited.
+#[derive(Default)]
+pub(super) struct Input {
+    pub(super) buffer: String,
+    /// Byte offset of the cursor
+    pub(super) cursor: usize,
+    /// Byte offset of the selection anchor (None = no selection)
+    pub(super) selection_anchor: Option<usize>,
+    /// Whether the input is coming from an IME in a burst (holds the input
+    /// box height until it's over)
+    pub(super) composition: input_wrap::Composition,
+    /// Inputs cleared with Ctrl+C or changed by a vi command, newest last,
+    /// as (text, cursor)
+    pub(super) cleared: Vec<(String, usize)>,
+    /// Vi editing state (None = emacs-style editing)
+    pub(super) vi: Option<ViInput>,
+    /// Characters typed too fast for a person: maybe a dropped file's path
+    pub(super) burst: file_drop::Burst,
+    /// A file just dropped, waiting for y / p / Esc
+    pub(super) drop_offer: Option<DropOffer>,
+    /// Files that go with the message, in the order they were dropped
+    pub(super) files: Vec<FileAttachment>,
+}
+
+/// A file dropped on the input, offered for attaching.
+pub(super) struct DropOffer {
+    path: std::path::PathBuf,
+    size: u64,
+    /// The path as the terminal typed it, and where in the input
+    typed: String,
+    at: usize,
+}
+
+/// Cleared inputs kept for restoring
+const MAX_CLEARED_INPUTS: usize = 20;
+
+impl TuiAssistant {
+    // ========================================================================
+    // Input Box
+    // ========================================================================
+
+    /// Get the current input text
+    pub fn get_input(&self) -> &str {
+        &self.input.buffer
+    }
+
+    /// Take the input text and clear the buffer (and with it the session's
+    /// draft)
+    pub fn take_input(&mut self) -> String {
+        self.input.cursor = 0;
+        self.input.selection_anchor = None;
+        if let Some(vi) = &mut self.input.vi {
+            vi.reset();
+        }
+        std::mem::take(&mut self.input.buffer)
+    }
+
+    /// Put `text` back in the input after [`Self::take_input`], the cursor
+    /// at its end (a message that could not be sent).
+    pub fn put_back_input(&mut self, text: String) {
+        self.input.cursor = text.len();
+        self.input.buffer = text;
+    }
+
+    /// The unsent input of the active session.
+    pub fn current_draft(&self) -> InputDraft {
+        InputDraft {
+            text: self.input.buffer.clone(),
+            cursor: self.input.cursor,
+            selection_anchor: self.input.selection_anchor,
+            files: self.input.files.clone(),
+        }
+    }
+
+    pub(super) fn set_input_draft(&mut self, draft: InputDraft) {
+        let draft = draft.clamped();
+        self.input.buffer = draft.text;
+        self.input.cursor = draft.cursor;
+        self.input.selection_anchor = draft.selection_anchor;
+        self.input.files = draft.files;
+        self.input.burst.clear();
+        self.input.drop_offer = None;
+        if let Some(vi) = &mut self.input.vi {
+            vi.reset();
+        }
+    }
+
+    /// Drafts of every session that has one, for saving to disk.
+    pub fn saved_drafts(&self) -> SavedDrafts {
+        let mut drafts: std::collections::BTreeMap<_, _> =
+            self.drafts.iter().map(|(id, draft)| (*id, draft.clone())).collect();
+        let current = self.current_draft();
+        if !current.is_empty() {
+            drafts.insert(self.active_session, current);
+        }
+        SavedDrafts { active: Some(self.active_session), drafts }
+    }
+
+    /// Bring back a draft saved before a restart, unless something was
+    /// typed already. A note in the title says where the text came from.
+    pub fn restore_draft(&mut self, draft: InputDraft) {
+        if draft.is_empty() || !self.input.buffer.is_empty() {
+            return;
+        }
+        self.set_input_draft(draft);
+        self.notice = Some("draft restored".to_string());
+    }
+
+    /// Put a question that could not be sent back in the input box, unless
+    /// something new was typed meanwhile.
+    pub fn restore_unsent_input(&mut self, text: String) {
+        if text.is_empty() || !self.input.buffer.is_empty() {
+            return;
+        }
+        let cursor = text.len();
+        let files = std::mem::take(&mut self.input.files);
+        self.set_input_draft(InputDraft { text, cursor, selection_anchor: None, files });
+    }
+
+    /// Load question `index` (0 is the first shown) into the input to be
+    /// edited; sending it asks it in place of the old one. Whatever was
+    /// typed can be brought back with [`Self::restore_cleared_input`].
+    pub fn start_editing(&mut self, index: usize, text: String) {
+        self.exit_visual_mode();
+        self.clear_input();
+        let cursor = text.len();
+        let files = std::mem::take(&mut self.input.files);
+        self.set_input_draft(InputDraft { text, cursor, selection_anchor: None, files });
+        self.view.editing = Some(index);
+    }
+
+    /// The question the input is an edit of, if it is one.
+    pub fn editing(&self) -> Option<usize> {
+        self.view.editing
+    }
+
+    /// Stop editing a question, returning which one it was.
+    pub fn take_editing(&mut self) -> Option<usize> {
+        self.view.editing.take()
+    }
+
+    /// Clear the input, keeping it so [`Self::restore_cleared_input`] can
+    /// bring it back; an edit of a question is given up. Returns false if
+    /// there was nothing to clear.
+    pub fn clear_input(&mut self) -> bool {
+        self.view.editing = None;
+        if self.input.buffer.is_empty() {
+            return false;
+        }
+        let text = std::mem::take(&mut self.input.buffer);
+        self.push_undo(text, self.input.cursor);
+        self.input.cursor = 0;
+        self.input.selection_anchor = None;
+        true
+    }
+
+    /// Keep an earlier input for [`Self::restore_cleared_input`].
+    fn push_undo(&mut self, text: String, cursor: usize) {
+        if self.input.cleared.len() == MAX_CLEARED_INPUTS {
+            self.input.cleared.remove(0);
+        }
+        self.input.cleared.push((text, cursor));
+    }
+
+    /// Put back the most recently cleared input in place of the current one
+    /// (Ctrl+Z, or `u` in vi normal mode). Returns false if nothing was
+    /// cleared.
+    pub fn restore_cleared_input(&mut self) -> bool {
+        let Some((text, cursor)) = self.input.cleared.pop() else {
+            return false;
+        };
+        self.input.buffer = text;
+        self.input.cursor = cursor;
+        self.input.selection_anchor = None;
+        true
+    }
+
+    /// Ask the App to suspend RustyTerm (as SIGTSTP does).
+    pub fn request_suspend(&mut self) {
+        self.suspend_requested = true;
+    }
+
+    /// Whether a suspension was asked for since the last call.
+    pub fn take_suspend_request(&mut self) -> bool {
+        std::mem::take(&mut self.suspend_requested)
+    }
+
+    /// Set what the next message would carry (shown as chips).
+    pub fn set_context_summary(&mut self, summary: ContextSummary) {
+        self.context_summary = summary;
+    }
+
+    /// Rows taken by the chips above the input (0 when there are none).
+    pub fn chip_row_height(&self) -> u16 {
+        if self.context_summary.is_empty() { 0 } else { 1 }
+    }
+
+    /// Sections left out of the next message.
+    pub fn attachments(&self) -> Attachments {
+        self.attachments
+    }
+
+    /// Leave a section out of the next message, or put it back.
+    pub fn toggle_attachment(&mut self, kind: ChipKind) {
+        self.attachments.toggle(kind);
+    }
+
+    /// Include every section again (after a message is sent).
+    pub fn reset_attachments(&mut self) {
+        self.attachments = Attachments::default();
+    }
+
+    /// The chip at a screen position, if any.
+    pub fn chip_at(&self, col: u16, row: u16) -> Option<ChipKind> {
+        let area = self.cached_chip_row.get()?;
+        if row != area.y {
+            return None;
+        }
+        self.cached_chips
+            .borrow()
+            .iter()
+            .find(|chip| col >= area.x + chip.x && col < area.x + chip.x + chip.width)
+            .map(|chip| chip.kind)
+    }
+
+    // ========================================================================
+    // Dropped Files
+    // ========================================================================
+
+    /// Offer files dropped on the input for attaching (off: their paths
+    /// stay in the input as typed).
+    pub fn set_file_drop(&mut self, enabled: bool) {
+        self.file_drop = enabled;
+        self.input.burst.clear();
+    }
+
+    /// When to look at the burst typed last for a dropped file (see
+    /// [`Self::check_file_drop`]), if one is going on.
+    pub fn file_drop_due(&self) -> Option<Instant> {
+        if !self.file_drop || self.input.drop_offer.is_some() {
+            return None;
+        }
+        self.input.burst.due()
+    }
+
+    /// If the burst typed last is over and was a readable file's path (see
+    /// [`file_drop`]), take the path out of the input and offer to attach
+    /// the file. `home` stands for `~/`. Returns whether an offer opened.
+    pub fn check_file_drop(&mut self, home: Option<&str>, now: Instant) -> bool {
+        if !self.file_drop || self.input.drop_offer.is_some() {
+            return false;
+        }
+        let Some((at, typed)) = self.input.burst.take_finished(now) else {
+            return false;
+        };
+        let end = at + typed.len();
+        // Still in the input as typed, and not stuck to a word before it
+        if self.input.buffer.get(at..end) != Some(typed.as_str())
+            || self.input.buffer[..at].chars().next_back().is_some_and(|c| !c.is_whitespace())
+        {
+            return false;
+        }
+        let Some((path, size)) = file_drop::dropped_file(&typed, home) else {
+            return false;
+        };
+        self.input.buffer.replace_range(at..end, "");
+        self.input.selection_anchor = None;
+        if self.input.cursor >= end {
+            self.input.cursor -= typed.len();
+        } else if self.input.cursor > at {
+            self.input.cursor = at;
+        }
+        self.input.drop_offer = Some(DropOffer { path, size, typed, at });
+        true
+    }
+
+    /// Whether a dropped file waits for y / p / Esc.
+    pub fn has_drop_offer(&self) -> bool {
+        self.input.drop_offer.is_some()
+    }
+
+    /// Attach the offered file to the message. A file that can't be (a
+    /// binary or unreadable one) is named in the title instead.
+    pub fn accept_drop(&mut self) {
+        let Some(offer) = self.input.drop_offer.take() else {
+            return;
+        };
+        match read_attachment(&offer.path, FILE_BUDGET) {
+            Ok(file) => {
+                // Dropped again: the fresh read replaces the old one
+                self.input.files.retain(|attached| attached.path != file.path);
+                self.input.files.push(file);
+            }
+            Err(error) => self.notice = Some(format!("{} {}: not attached", offer.path.display(), error)),
+        }
+    }
+
+    /// Put the offered path back in the input as the terminal typed it.
+    pub fn paste_dropped_path(&mut self) {
+        let Some(offer) = self.input.drop_offer.take() else {
+            return;
+        };
+        let at = if self.input.buffer.is_char_boundary(offer.at) { offer.at } else { self.input.buffer.len() };
+        self.input.buffer.insert_str(at, &offer.typed);
+        self.input.cursor = at + offer.typed.len();
+        self.input.selection_anchor = None;
+    }
+
+    /// Forget the offer; the path stays out of the input.
+    pub fn dismiss_drop(&mut self) {
+        self.input.drop_offer = None;
+    }
+
+    /// Files that go with the message.
+    pub fn dropped_files(&self) -> &[FileAttachment] {
+        &self.input.files
+    }
+
+    /// Take the files that go with the message (it is being sent).
+    pub fn take_dropped_files(&mut self) -> Vec<FileAttachment> {
+        std::mem::take(&mut self.input.files)
+    }
+
+    /// Leave every dropped file out of the message. Returns false if there
+    /// was none.
+    pub fn drop_dropped_files(&mut self) -> bool {
+        if self.input.files.is_empty() {
+            return false;
+        }
+        self.input.files.clear();
+        self.notice = Some("attached files dropped".to_string());
+        true
+    }
+
+    /// Rows taken by the dropped files, or the offer, under the context
+    /// chips (0 when there is neither).
+    pub fn file_row_height(&self) -> u16 {
+        if self.input.drop_offer.is_none() && self.input.files.is_empty() { 0 } else { 1 }
+    }
+
+    /// Whether a screen row is the chip row.
+    pub fn is_chip_row(&self, row: u16) -> bool {
+        self.cached_chip_row.get().is_some_and(|area| area.y == row)
+    }
+
+    /// Insert a character at the cursor position
+    pub fn insert_char(&mut self, c: char) {
+        self.input.buffer.insert(self.input.cursor, c);
+        self.input.cursor += c.len_utf8();
+    }
+
+    /// Delete the grapheme before the cursor (backspace): a whole Hangul
+    /// syllable, emoji sequence or accented letter, not its last part
+    pub fn delete_char(&mut self) {
+        if self.input.cursor > 0 {
+            let prev_pos = input_wrap::prev_boundary(&self.input.buffer, self.input.cursor);
+            self.input.buffer.replace_range(prev_pos..self.input.cursor, "");
+            self.input.cursor = prev_pos;
+        }
+    }
+
+    /// Delete the grapheme at the cursor (delete key)
+    pub fn delete_char_forward(&mut self) {
+        if self.input.cursor < self.input.buffer.len() {
+            let next_pos = input_wrap::next_boundary(&self.input.buffer, self.input.cursor);
+            self.input.buffer.replace_range(self.input.cursor..next_pos, "");
+        }
+    }
+
+    /// Move cursor left/right by delta graphemes
+    pub fn move_cursor(&mut self, delta: i16) {
+        for _ in 0..delta.unsigned_abs() {
+            self.input.cursor = if delta < 0 {
+                input_wrap::prev_boundary(&self.input.buffer, self.input.cursor)
+            } else {
+                input_wrap::next_boundary(&self.input.buffer, self.input.cursor)
+            };
+        }
+    }
+
+    /// Move cursor to the beginning of the input
+    pub fn move_cursor_to_start(&mut self) {
+        self.input.cursor = 0;
+    }
+
+    /// Move cursor to the end of the input
+    pub fn move_cursor_to_end(&mut self) {
+        self.input.cursor = self.input.buffer.len();
+    }
+
+    /// Get the current cursor position (in bytes)
+    pub fn cursor_position(&self) -> usize {
+        self.input.cursor
+    }
+
+    // ========================================================================
+    // Vi Editing
+    // ========================================================================
+
+    /// Whether vi editing is on and the input is in normal mode.
+    pub fn is_vi_normal_mode(&self) -> bool {
+        self.input.vi.as_ref().is_some_and(|vi| vi.mode() == ViMode::Normal)
+    }
+
+    /// Handle a key of vi editing. Keys that edit the same way in both
+    /// modes (Enter, arrows, Ctrl shortcuts) are not consumed, nor is Esc in
+    /// normal mode with no command half-typed, so it keeps its usual meaning.
+    pub fn handle_vi_key(&mut self, key: KeyEvent) -> KeyHandleResult {
+        let Some(vi) = &mut self.input.vi else {
+            return KeyHandleResult::NotConsumed;
+        };
+        if key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return KeyHandleResult::NotConsumed;
+        }
+        let action = match (vi.mode(), key.code) {
+            (ViMode::Insert, KeyCode::Esc) => {
+                let typed = vi.normal(&self.input.buffer);
+                if let Some((text, cursor)) = typed {
+                    self.push_undo(text, cursor);
+                }
+                // Like vi, step back onto the last character typed
+                let start = self.input.buffer[..self.input.cursor].rfind('\n').map_or(0, |i| i + 1);
+                if self.input.cursor > start {
+                    self.move_cursor(-1);
+                }
+                self.input.selection_anchor = None;
+                return KeyHandleResult::Consumed;
+            }
+            (ViMode::Normal, KeyCode::Esc) if vi.cancel() => return KeyHandleResult::Consumed,
+            (ViMode::Normal, KeyCode::Char(c)) => vi.key(c, &self.input.buffer, self.input.cursor),
+            (ViMode::Normal, _) => {
+                vi.cancel();
+                return KeyHandleResult::NotConsumed;
+            }
+            (ViMode::Insert, _) => return KeyHandleResult::NotConsumed,
+        };
+        self.apply_vi_action(action);
+        KeyHandleResult::Consumed
+    }
+
+    fn apply_vi_action(&mut self, action: ViAction) {
+        self.input.selection_anchor = None;
+        let before = (self.input.buffer.clone(), self.input.cursor);
+        match action {
+            ViAction::Move(target) => self.input.cursor = target,
+            ViAction::Up => self.move_cursor_up(self.input_area_width()),
+            ViAction::Down => self.move_cursor_down(self.input_area_width()),
+            ViAction::Delete { range, insert } => {
+                let changed = !range.is_empty();
+                if changed {
+                    self.push_undo(before.0.clone(), before.1);
+                    self.input.selection_anchor = Some(range.start);
+                    self.input.cursor = range.end;
+                    self.delete_input_selection();
+                }
+                if insert {
+                    if let Some(vi) = &mut self.input.vi {
+                        vi.insert((!changed).then_some(before));
+                    }
+                    return;
+                }
+            }
+            ViAction::DeleteLines(range) if !range.is_empty() => {
+                self.push_undo(before.0, before.1);
+                self.input.buffer.replace_range(range.clone(), "");
+                self.input.cursor = range.start.min(self.input.buffer.len());
+                self.input.cursor = vi_input::first_non_blank(&self.input.buffer, self.input.cursor);
+            }
+            ViAction::Insert(at) => {
+                self.input.cursor = at;
+                if let Some(vi) = &mut self.input.vi {
+                    vi.insert(Some(before));
+                }
+                return;
+            }
+            ViAction::Undo => {
+                self.restore_cleared_input();
+            }
+            ViAction::DeleteLines(_) | ViAction::Pending | ViAction::Ignored => {}
+        }
+        self.input.cursor = vi_input::normal_cursor(&self.input.buffer, self.input.cursor);
+    }
+
+    // ========================================================================
+    // Input Selection
+    // ========================================================================
+
+    /// Check if there's an active selection in the input box.
+    pub fn has_input_selection(&self) -> bool {
+        self.input.selection_anchor.is_some()
+    }
+
+    /// Start input selection at current cursor position.
+    pub fn start_input_selection(&mut self) {
+        if self.input.selection_anchor.is_none() {
+            self.input.selection_anchor = Some(self.input.cursor);
+        }
+    }
+
+    /// Clear input selection.
+    pub fn clear_input_selection(&mut self) {
+        self.input.selection_anchor = None;
+    }
+
+    /// Get input selection range as (start, end) byte offsets, where start <= end.
+    /// Returns None if no selection.
+    pub fn get_input_selection_range(&self) -> Option<(usize, usize)> {
+        self.input.selection_anchor.map(|anchor| {
+            let start = anchor.min(self.input.cursor);
+            let end = anchor.max(self.input.cursor);
+            (start, end)
+        })
+    }
+
+    /// Get selected text from input buffer.
+    pub fn get_input_selected_text(&self) -> Option<String> {
+        self.get_input_selection_range().map(|(start, end)| {
+            self.input.buffer[start..end].to_string()
+        })
+    }
+
+    /// Delete selected text and return it. Cursor moves to start of selection.
+    pub fn delete_input_selection(&mut self) -> Option<String> {
+        if let Some((start, end)) = self.get_input_selection_range()
+            && start < end
+        {
+            let deleted = self.input.buffer[start..end].to_string();
+            self.input.buffer.replace_range(start..end, "");
+            self.input.cursor = start;
+            self.input.selection_anchor = None;
+            return Some(deleted);
+        }
+        None
+    }
+
+    /// Copy selected text to clipboard without deleting.
+    pub fn copy_input_selection(&self) -> bool {
+        if let Some(text) = self.get_input_selected_text()
+            && !text.is_empty()
+        {
+            return copy_to_clipboard(&text).is_copied();
+        }
+        false
+    }
+
+    /// Cut selected text (copy and delete).
+    pub fn cut_input_selection(&mut self) -> bool {
+        if self.copy_input_selection() {
+            self.delete_input_selection();
+            return true;
+        }
+        false
+    }
+
+    /// Move cursor with optional selection extension (for Shift+Arrow).
+    /// If `extend_selection` is true, starts or extends selection.
+    pub fn move_cursor_with_selection(&mut self, delta: i16, extend_selection: bool) {
+        if extend_selection {
+            self.start_input_selection();
+        } else {
+            self.clear_input_selection();
+        }
+        self.move_cursor(delta);
+    }
+
+    /// Select all text in input buffer.
+    pub fn select_all_input(&mut self) {
+        if !self.input.buffer.is_empty() {
+            self.input.selection_anchor = Some(0);
+            self.input.cursor = self.input.buffer.len();
+        }
+    }
+
+    /// Handle text input - if there's a selection, replace it with the new character.
+    pub fn insert_char_with_selection(&mut self, c: char) {
+        let now = Instant::now();
+        self.input.composition.note_typed(c, now);
+        // Delete selection first if present
+        self.delete_input_selection();
+        self.input.burst.note_typed(c, self.input.cursor, now);
+        // Then insert the character
+        self.insert_char(c);
+    }
+
+    /// Handle backspace - if there's a selection, delete it.
+    pub fn delete_char_with_selection(&mut self) {
+        if self.has_input_selection() {
+            self.delete_input_selection();
+        } else {
+            self.delete_char();
+        }
+    }
+
+    /// Handle delete key - if there's a selection, delete it.
+    pub fn delete_char_forward_with_selection(&mut self) {
+        if self.has_input_selection() {
+            self.delete_input_selection();
+        } else {
+            self.delete_char_forward();
+        }
+    }
+
+    /// Set input cursor position from mouse click, optionally extending selection.
+    pub fn set_input_cursor_from_click_with_selection(
+        &mut self,
+        rel_col: u16,
+        rel_row: u16,
+        extend_selection: bool,
+    ) {
+        if extend_selection {
+            self.start_input_selection();
+        } else {
+            self.clear_input_selection();
+        }
+        self.set_input_cursor_from_click(rel_col, rel_row);
+    }
+
+    /// Select the word at the current cursor position in the input buffer.
+    pub fn select_input_word_at_cursor(&mut self) {
+        if self.input.buffer.is_empty() {
+            return;
+        }
+
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+
+        // Find byte index at cursor position
+        let mut byte_idx = 0;
+        for (i, c) in self.input.buffer.char_indices() {
+            if i >= self.input.cursor {
+                break;
+            }
+            byte_idx = i;
+            let _ = c; // Suppress unused warning
+        }
+
+        // If cursor is at the end, use the last byte position
+        if self.input.cursor >= self.input.buffer.len() && !self.input.buffer.is_empty() {
+            byte_idx = self.input.buffer.len();
+        }
+
+        let chars: Vec<(usize, char)> = self.input.buffer.char_indices().collect();
+        if chars.is_empty() {
+            return;
+        }
+
+        // Find the character index for the current byte position
+        let mut current_char_idx = 0;
+        for (idx, (bi, _)) in chars.iter().enumerate() {
+            if *bi >= byte_idx {
+                current_char_idx = idx;
+                break;
+            }
+            current_char_idx = idx;
+        }
+
+        // Get the character at cursor
+        let current_char = chars.get(current_char_idx).map(|(_, c)| *c).unwrap_or(' ');
+
+        // If on whitespace, no selection
+        if current_char.is_whitespace() {
+            return;
+        }
+
+        // Find word start
+        let mut start_idx = current_char_idx;
+        while start_idx > 0 {
+            let prev_char = chars.get(start_idx - 1).map(|(_, c)| *c).unwrap_or(' ');
+            if !is_word_char(prev_char) {
+                break;
+            }
+            start_idx -= 1;
+        }
+
+        // Find word end
+        let mut end_idx = current_char_idx;
+        while end_idx < chars.len().saturating_sub(1) {
+            let next_char = chars.get(end_idx + 1).map(|(_, c)| *c).unwrap_or(' ');
+            if !is_word_char(next_char) {
+                break;
+            }
+            end_idx += 1;
+        }
+
+        // Convert char indices back to byte indices
+        let start_byte = chars.get(start_idx).map(|(bi, _)| *bi).unwrap_or(0);
+        let end_byte = chars.get(end_idx).map(|(bi, c)| bi + c.len_utf8()).unwrap_or(self.input.buffer.len());
+
+        // Set selection
+        self.input.selection_anchor = Some(start_byte);
+        self.input.cursor = end_byte;
+    }
+
+    // ========================================================================
+    // Input Layout
+    // ========================================================================
+
+    /// Calculate the number of lines needed to display the input text.
+    /// Returns the number of lines (minimum 1).
+    pub fn calculate_input_lines(&self, width: u16) -> u16 {
+        if width == 0 {
+            return 1;
+        }
+        self.input_layout(width).rows()
+    }
+
+    /// The input laid out in a box `width` wide, after the prompt.
+    fn input_layout(&self, width: u16) -> InputLayout<'_> {
+        InputLayout::new(&self.input.buffer, self.prompt_width(), width)
+    }
+
+    /// Move cursor up one line in multi-line input
+    pub fn move_cursor_up(&mut self, input_area_width: u16) {
+        if input_area_width == 0 {
+            return;
+        }
+        let target = {
+            let layout = self.input_layout(input_area_width);
+            let (row, col) = layout.cursor(self.input.cursor);
+            row.checked_sub(1).and_then(|row| layout.byte_at(row, col))
+        };
+        if let Some(target) = target {
+            self.input.cursor = target;
+        }
+    }
+
+    /// Move cursor down one line in multi-line input
+    pub fn move_cursor_down(&mut self, input_area_width: u16) {
+        if input_area_width == 0 {
+            return;
+        }
+        let target = {
+            let layout = self.input_layout(input_area_width);
+            let (row, col) = layout.cursor(self.input.cursor);
+            layout.byte_at(row + 1, col)
+        };
+        if let Some(target) = target {
+            self.input.cursor = target;
+        }
+    }
+
+    /// Calculate the actual input box height (including border) for a given area height and width.
+    pub fn calculate_input_box_height(&self, area_height: u16, area_width: u16) -> u16 {
+        let input_text_lines = self.input.composition.rows(self.calculate_input_lines(area_width), Instant::now());
+        let min_input_height = 3u16;
+        let max_input_height = (area_height / 2).max(min_input_height);
+        (input_text_lines + 1).clamp(min_input_height, max_input_height)
+    }
+
+    // ========================================================================
+    // Mouse Click Detection
+    // ========================================================================
+
+    /// Set the input cursor position based on screen click coordinates.
+    ///
+    /// # Arguments
+    /// * `rel_col` - Column relative to the input box inner area
+    /// * `rel_row` - Row relative to the input box inner area
+    pub fn set_input_cursor_from_click(&mut self, rel_col: u16, rel_row: u16) {
+        let input_area_width = self.last_input_area_width.get();
+        if input_area_width == 0 {
+            return;
+        }
+
+        // Below the last row: the end of the input
+        let target = self.input_layout(input_area_width).byte_at(rel_row, rel_col);
+        self.input.cursor = target.unwrap_or(self.input.buffer.len());
+    }
+
+    /// Get cursor position for rendering.
+    /// Returns Some((x, y)) if cursor should be shown, None otherwise.
+    /// Coordinates are relative to the input box inner area.
+    pub fn get_cursor_position(&self) -> Option<(u16, u16)> {
+        let prompt_width = self.prompt_width();
+        let input_area_width = self.input_area_width();
+
+        // Calculate available width for text (excluding prompt)
+        let available_width = input_area_width.saturating_sub(prompt_width);
+        if available_width == 0 {
+            return None;
+        }
+
+        // On the grapheme at the cursor, where it is drawn: a wide one that
+        // wrapped puts the cursor on the next row with it
+        let (row, col) = self.input_layout(input_area_width).cursor(self.input.cursor);
+        Some((col, row))
+    }
+}
+
+/// Render the "what the AI knows" chips and cache where they are.
+pub(super) fn render_context_chips(assistant: &TuiAssistant, area: Rect, buf: &mut Buffer) {
+    if area.height == 0 {
+        assistant.cached_chip_row.set(None);
+        assistant.cached_chips.borrow_mut().clear();
+        return;
+    }
+    let chips = layout_chips(&assistant.context_summary, area.width);
+    render_chips(&chips, assistant.attachments, area, buf);
+    assistant.cached_chip_row.set(Some(area));
+    *assistant.cached_chips.borrow_mut() = chips;
+}
+
+/// Render the files dropped on the input as `📎 name size` chips, or the
+/// offer to attach the one just dropped.
+pub(super) fn render_file_chips(assistant: &TuiAssistant, area: Rect, buf: &mut Buffer) {
+    if area.height == 0 {
+        return;
+    }
+    if let Some(offer) = &assistant.input.drop_offer {
+        let text = format!(
+            " Attach {} ({}) to next message? [y]es / [p]aste path / [Esc] ",
+            offer.path.display(),
+            format_bytes(offer.size)
+        );
+        let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+        buf.set_stringn(area.x, area.y, text, area.width as usize, style);
+        return;
+    }
+    let mut x = area.x;
+    for file in &assistant.input.files {
+        if x >= area.right() {
+            break;
+        }
+        let text = format!(" 📎 {} {} ", file.name(), format_bytes(file.size));
+        let style = Style::default().fg(Color::Black).bg(Color::LightCyan);
+        let (end, _) = buf.set_stringn(x, area.y, &text, (area.right() - x) as usize, style);
+        x = end + 1;
+    }
+}
+
+/// Render the input box at the bottom with multi-line support and selection highlighting
+pub(super) fn render_input_box(assistant: &TuiAssistant, area: Rect, buf: &mut Buffer) {
+    let block = Block::default()
+        .borders(Borders::TOP)
+        .border_style(Style::default().fg(Color::DarkGray));
+
+    let inner = block.inner(area);
+    block.render(area, buf);
+
+    if inner.width == 0 || inner.height == 0 {
+        return;
+    }
+
+    // Cache the input area width for cursor movement calculations
+    assistant.last_input_area_width.set(inner.width);
+
+    // Until a question can be sent, the empty input says what is missing
+    if let Some(hint) = assistant.setup_hint.as_deref().filter(|_| assistant.input.buffer.is_empty()) {
+        Paragraph::new(hint)
+            .style(Style::default().fg(Color::Yellow))
+            .wrap(Wrap { trim: true })
+            .render(inner, buf);
+        return;
+    }
+
+    // Get selection range (if any)
+    let selection_range = assistant.get_input_selection_range();
+
+    // Render input prompt and text directly to buffer for selection support
+    let prompt = assistant.prompt();
+
+    let prompt_style = if assistant.prompt_flash_until().is_some() {
+        Style::default().fg(Color::Black).bg(Color::Red)
+    } else {
+        Style::default().fg(Color::Cyan)
+    };
+    let normal_style = Style::default();
+    let selection_style = Style::default().fg(Color::White).bg(Color::Blue);
+
+    // Render prompt
+    let mut x = inner.x;
+    let y = inner.y;
+    for ch in prompt.chars() {
+        if x >= inner.x + inner.width {
+            break;
+        }
+        if let Some(cell) = buf.cell_mut((x, y)) {
+            cell.set_char(ch).set_style(prompt_style);
+        }
+        x += unicode_width::UnicodeWidthChar::width(ch).unwrap_or(1) as u16;
+    }
+
+    // A retry in the empty input, after the prompt
+    if let Some(status) = assistant.stream_status().filter(|_| assistant.input.buffer.is_empty()) {
+        let width = (inner.x + inner.width).saturating_sub(x);
+        buf.set_stringn(x, y, status, width as usize, Style::default().fg(Color::DarkGray));
+    }
+
+    // Whole graphemes, wrapped the way the cursor and the arrow keys see them
+    for placed in assistant.input_layout(inner.width).placed() {
+        if placed.width == 0 {
+            continue;
+        }
+        // Check if we're still within the visible area
+        if placed.row >= inner.height {
+            break;
+        }
+
+        // Determine style based on selection
+        let is_selected = selection_range.is_some_and(|(start, end)| {
+            placed.byte >= start && placed.byte < end
+        });
+        let style = if is_selected { selection_style } else { normal_style };
+
+        if let Some(cell) = buf.cell_mut((inner.x + placed.col, inner.y + placed.row)) {
+            cell.set_symbol(placed.text).set_style(style);
+        }
+    }
+}
+
+/// Find word boundaries at the given character position in a string.
+/// Returns (start_col, end_col) of the word.
+pub(super) fn find_word_boundaries_in_string(text: &str, col: usize) -> (usize, usize) {
+    let chars: Vec<char> = text.chars().collect();
+    let len = chars.len();
+
+    if len == 0 || col >= len {
+        return (col, col);
+    }
+
+    let is_word_char = |c: char| -> bool {
+        c.is_alphanumeric() || c == '_' || c == '-'
+    };
+
+    let current_char = chars[col];
+
+    // If clicking on whitespace, just select that cell
+    if current_char.is_whitespace() {
+        return (col, col);
+    }
+
+    // Find word start
+    let mut start = col;
+    while start > 0 {
+        let prev_char = chars[start - 1];
+        if !is_word_char(prev_char) {
+            break;
+        }
+        start -= 1;
+    }
+
+    // Find word end
+    let mut end = col;
+    while end < len.saturating_sub(1) {
+        let next_char = chars[end + 1];
+        if !is_word_char(next_char) {
+            break;
+        }
+        end += 1;
+    }
+
+    (start, end)
+}
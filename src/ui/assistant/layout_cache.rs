@@ -0,0 +1,224 @@
+//! Where the last render put things, kept for the mouse clicks and keys
+//! that arrive before the next frame.
+
+use super::*;
+
+/// Hit area for a tab in the tab bar
+#[derive(Debug, Clone, Copy)]
+pub struct TabHitArea {
+    /// Start X position (screen coordinates)
+    pub start_x: u16,
+    /// End X position (exclusive)
+    pub end_x: u16,
+    /// Session ID (None for "+" button)
+    pub session_id: Option<SessionId>,
+    /// Whether this is the close button area
+    pub is_close_button: bool,
+}
+
+/// Hit area for a command card in the message area
+#[derive(Debug, Clone, Copy)]
+pub struct CommandCardHitArea {
+    /// Message index in the messages array
+    pub message_idx: usize,
+    /// Start Y position (screen coordinates, relative to message area)
+    pub start_y: u16,
+    /// End Y position (exclusive)
+    pub end_y: u16,
+    /// Button row Y position (relative to message area)
+    pub button_y: Option<u16>,
+    /// Next button area (start_x, end_x) - only present with pagination
+    pub next_btn: Option<(u16, u16)>,
+    /// Execute button area (start_x, end_x)
+    pub execute_btn: Option<(u16, u16)>,
+    /// Cancel button area (start_x, end_x)
+    pub cancel_btn: Option<(u16, u16)>,
+    /// Verdict line Y position (relative to message area), if visible
+    pub verdict_y: Option<u16>,
+    /// "[?] why" area on the verdict line (start_x, end_x)
+    pub why_btn: Option<(u16, u16)>,
+    /// Multi button area (start_x, end_x) - only present with pagination
+    pub multi_btn: Option<(u16, u16)>,
+    /// Visible checklist rows: (start_y, end_y, index of the first)
+    pub checklist_rows: Option<(u16, u16, usize)>,
+    /// Compare button area (start_x, end_x) - only present with pagination
+    pub compare_btn: Option<(u16, u16)>,
+    /// Visible lines of the compared suggestions, two per suggestion:
+    /// (start_y, end_y, index of the first line)
+    pub compare_rows: Option<(u16, u16, usize)>,
+}
+
+impl TuiAssistant {
+    /// Rebuild every width-dependent cache for a new pane area.
+    ///
+    /// The caches are normally refreshed while rendering, but after a host
+    /// resize (window or font zoom) a click or key can arrive before the next
+    /// frame, so they are rebuilt here by rendering off-screen.
+    pub fn on_resize(&self, area: Rect) {
+        self.clear_layout_caches();
+        self.render(area, &mut Buffer::empty(area));
+    }
+
+    /// Forget everything placed by the last render, as if nothing had been
+    /// drawn: no hit areas, no scroll range.
+    pub(super) fn clear_layout_caches(&self) {
+        self.last_input_area_width.set(0);
+        self.clear_message_list_caches();
+        self.cached_tab_positions.borrow_mut().clear();
+        self.cached_chips.borrow_mut().clear();
+        self.cached_chip_row.set(None);
+    }
+
+    /// Forget what the last render placed in the message list.
+    pub(super) fn clear_message_list_caches(&self) {
+        self.max_scroll_offset.set(0);
+        self.cached_total_lines.set(0);
+        self.cached_visible_width.set(0);
+        self.cached_message_area.set(Rect::default());
+        self.cached_command_cards.borrow_mut().clear();
+        self.cached_regen_hint.set(None);
+        self.cached_summary_divider.set(None);
+        self.cached_new_lines_pill.set(None);
+    }
+
+    /// Get the last known input area width (updated during rendering)
+    pub fn input_area_width(&self) -> u16 {
+        self.last_input_area_width.get()
+    }
+
+    // ========================================================================
+    // Mouse Click Detection
+    // ========================================================================
+
+    /// Detect what was clicked in the message area.
+    ///
+    /// # Arguments
+    /// * `screen_col` - Screen column position
+    /// * `screen_row` - Screen row position
+    /// * `area` - The message area rect (for coordinate conversion)
+    ///
+    /// Returns a MessageAreaClickResult indicating what action to take.
+    pub fn get_message_click_result(
+        &self,
+        screen_col: u16,
+        screen_row: u16,
+        area_x: u16,
+        area_y: u16,
+    ) -> MessageAreaClickResult {
+        // The pill lies over the bottom row, so it comes first
+        if let Some((pill_y, start_x, end_x)) = self.cached_new_lines_pill.get()
+            && screen_row == area_y + pill_y
+            && screen_col >= area_x + start_x
+            && screen_col < area_x + end_x
+        {
+            return MessageAreaClickResult::JumpToLatest;
+        }
+
+        let command_cards = self.cached_command_cards.borrow();
+
+        for card in command_cards.iter() {
+            // Check if click is on the button row
+            if let Some(button_y) = card.button_y {
+                let button_screen_y = area_y + button_y;
+
+                if screen_row == button_screen_y {
+                    // Check next button (pagination)
+                    if let Some((btn_start, btn_end)) = card.next_btn {
+                        let btn_start_x = area_x + btn_start;
+                        let btn_end_x = area_x + btn_end;
+                        if screen_col >= btn_start_x && screen_col < btn_end_x {
+                            return MessageAreaClickResult::NextCommand(card.message_idx);
+                        }
+                    }
+
+                    // Check execute button
+                    if let Some((btn_start, btn_end)) = card.execute_btn {
+                        let btn_start_x = area_x + btn_start;
+                        let btn_end_x = area_x + btn_end;
+                        if screen_col >= btn_start_x && screen_col < btn_end_x {
+                            return MessageAreaClickResult::ExecuteCommand(card.message_idx);
+                        }
+                    }
+
+                    // Check cancel button
+                    if let Some((btn_start, btn_end)) = card.cancel_btn {
+                        let btn_start_x = area_x + btn_start;
+                        let btn_end_x = area_x + btn_end;
+                        if screen_col >= btn_start_x && screen_col < btn_end_x {
+                            return MessageAreaClickResult::CancelCommand(card.message_idx);
+                        }
+                    }
+
+                    // Check multi button (pagination)
+                    if let Some((btn_start, btn_end)) = card.multi_btn {
+                        let btn_start_x = area_x + btn_start;
+                        let btn_end_x = area_x + btn_end;
+                        if screen_col >= btn_start_x && screen_col < btn_end_x {
+                            return MessageAreaClickResult::ToggleChecklist(card.message_idx);
+                        }
+                    }
+
+                    // Check compare button (pagination)
+                    if let Some((btn_start, btn_end)) = card.compare_btn
+                        && screen_col >= area_x + btn_start
+                        && screen_col < area_x + btn_end
+                    {
+                        return MessageAreaClickResult::ToggleCompare(card.message_idx);
+                    }
+                }
+            }
+
+            // A checklist row selects or unselects its suggestion
+            if let Some((start_y, end_y, first)) = card.checklist_rows
+                && screen_row >= area_y + start_y
+                && screen_row < area_y + end_y
+            {
+                return MessageAreaClickResult::ToggleChecklistRow(first + (screen_row - area_y - start_y) as usize);
+            }
+
+            // A compared suggestion gets the highlight
+            if let Some((start_y, end_y, first)) = card.compare_rows
+                && screen_row >= area_y + start_y
+                && screen_row < area_y + end_y
+            {
+                return MessageAreaClickResult::CompareRow((first + (screen_row - area_y - start_y) as usize) / 2);
+            }
+
+            // "[?] why" at the end of the verdict line
+            if let (Some(verdict_y), Some((start, end))) = (card.verdict_y, card.why_btn)
+                && screen_row == area_y + verdict_y
+                && screen_col >= area_x + start
+                && screen_col < area_x + end
+            {
+                return MessageAreaClickResult::ExplainVerdict(card.message_idx);
+            }
+
+            // The verdict line toggles the findings list, if there is one
+            let has_findings = matches!(
+                self.view.messages.get(card.message_idx),
+                Some(ChatMessage::CommandCard { findings, .. }) if !findings.is_empty()
+            );
+            if has_findings && card.verdict_y.is_some_and(|verdict_y| screen_row == area_y + verdict_y) {
+                return MessageAreaClickResult::ToggleFindings(card.message_idx);
+            }
+        }
+
+        if let Some(hint_y) = self.cached_regen_hint.get()
+            && screen_row == area_y + hint_y
+            && screen_col >= area_x
+            && screen_col < area_x + REGEN_HINT.width() as u16
+        {
+            return MessageAreaClickResult::Regenerate;
+        }
+
+        if let Some(divider_y) = self.cached_summary_divider.get()
+            && screen_row == area_y + divider_y
+            && screen_col >= area_x
+            && screen_col < area_x + SUMMARY_DIVIDER.width() as u16
+        {
+            return MessageAreaClickResult::ToggleSummary;
+        }
+
+        MessageAreaClickResult::None
+    }
+}
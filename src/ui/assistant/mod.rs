@@ -0,0 +1,2207 @@
+//! TUI Assistant sidebar component for AI chat interactions.
+//!
+//! This module provides a sidebar widget that supports:
+//! - Multiple chat sessions with tab switching
+//! - Chat message display (user messages, AI responses, command cards)
+//! - Streaming AI response rendering
+//! - Command suggestion cards with execute/cancel actions
+//! - Multi-line text input with cursor support (Ctrl+O for newline)
+//!
+//! [`TuiAssistant`] is the widget; its parts live in submodules:
+//! `view` draws the active session's messages, `cards` the command
+//! suggestion cards, `input` the input box, `tabs` the tab bar,
+//! `visual` handles visual mode and `layout_cache` keeps where the last
+//! render put things for mouse clicks.
+
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::prelude::Buffer;
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Widget};
+use unicode_width::UnicodeWidthStr;
+use std::cell::Cell;
+use std::collections::HashMap;
+use tokio::time::Instant;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+use crate::ai::draft::SuggestionDraft;
+use crate::ai::reasoning;
+use crate::ai::session::{SessionId, Steer};
+use crate::ai::timing::RequestTiming;
+use crate::ai::usage::{Prices, UsageStats};
+use crate::context::{read_attachment, CommandRecord, FileAttachment, PriorRun, PriorRuns, FILE_BUDGET};
+use crate::event::AiUiUpdate;
+use crate::security::{
+    Allowlist, Finding, Policy, ProjectRules, Severity, Verdict, composed_findings, lint_for_shell, sort_findings,
+    terminal_output_finding, verdict_from,
+};
+use crate::shell::{Composed, Execution, ShellKind};
+use crate::utils::metrics::format_bytes;
+use super::checklist::Checklist;
+use super::compare::word_diff;
+use super::context_chips::{layout_chips, render_chips, Attachments, ChipKind, ContextSummary, PlacedChip};
+use super::drafts::{InputDraft, SavedDrafts};
+use super::file_drop;
+use super::input_wrap::{self, InputLayout};
+use super::scrollbar;
+use super::vi_input::{self, ViAction, ViInput, ViMode};
+use super::visual::{VisualState, SelectionMode, PaneStatus, StatusMemo, KeyHandleResult, copy_to_clipboard, is_in_selection_with_mode, CopyOutcome, COPY_UNAVAILABLE};
+
+mod cards;
+mod input;
+mod layout_cache;
+mod tabs;
+mod view;
+mod visual;
+
+use cards::{render_checklist, render_command_card, render_comparison, render_draft_card, why_label, CardLayout};
+pub(crate) use cards::render_card;
+use input::{find_word_boundaries_in_string, render_context_chips, render_file_chips, render_input_box, Input};
+pub use layout_cache::{CommandCardHitArea, TabHitArea};
+use tabs::{apply_hover_style, render_tab_bar};
+use view::{PendingCommand, SessionView, REGEN_HINT, SUMMARY_DIVIDER};
+
+// ============================================================================
+// Data Structures
+// ============================================================================
+
+/// Status of a command suggestion card
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandStatus {
+    /// Waiting for user confirmation (Ctrl+Y/Ctrl+N)
+    Pending,
+    /// User confirmed and command was sent to shell
+    Executed,
+    /// User rejected the command
+    Rejected,
+    /// The reply was regenerated before a decision
+    Superseded,
+    /// Accepted to run after another command that failed, so never run
+    Skipped,
+}
+
+/// The AI's explanation of a card's verdict, asked for with '?'
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Rationale {
+    #[default]
+    NotAsked,
+    /// The request is on its way
+    Asking,
+    Ready { text: String, expanded: bool },
+}
+
+/// A card's verdict to have explained: the command as it will be typed and
+/// what the analyzer found in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerdictQuestion {
+    pub tool_call_id: String,
+    pub command: String,
+    pub cwd: Option<String>,
+    pub shell: ShellKind,
+    pub findings: Vec<Finding>,
+}
+
+/// What asking why a card got its verdict did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WhyVerdict {
+    /// Not a pending card with a Confirm or Deny verdict
+    NotApplicable,
+    /// The explanation was shown or hidden, or is already on its way
+    Handled,
+    /// Ask the AI this; the card now says it is asking
+    Ask(VerdictQuestion),
+}
+
+/// What Enter does with the input as it is (see [`TuiAssistant::enter_action`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnterAction {
+    /// Nothing but whitespace typed: nothing is sent
+    Nothing,
+    /// A reply is streaming and the input is not for the AI (or edits a
+    /// question): it stays until the reply is done
+    Wait,
+    /// A reply is streaming: the input is sent once it is done
+    Queue,
+    /// A card waits for Ctrl+Y or Ctrl+N: the input stays until it is answered
+    AnswerCard,
+    /// The waiting card is rejected and the input sent (`enter_rejects_pending`)
+    RejectAndSend,
+    Send,
+}
+
+/// A chat message in the conversation
+#[derive(Debug, Clone)]
+pub enum ChatMessage {
+    /// Message from the user
+    User { text: String },
+    /// Response from the AI assistant
+    Assistant {
+        text: String,
+        /// Whether the message is still being streamed
+        is_streaming: bool,
+        /// Whether the reply was replayed from the response cache
+        cached: bool,
+        /// Where the time of its request went, once known
+        timing: Option<RequestTiming>,
+        /// Whether that is shown under the reply
+        timing_shown: bool,
+        /// Stretches of `text` quoted verbatim from the context of its
+        /// question, as byte ranges; underlined, never copied as such
+        quotes: Vec<std::ops::Range<usize>>,
+    },
+    /// What the model reasoned before the reply below it; one line until
+    /// expanded
+    Reasoning {
+        text: String,
+        expanded: bool,
+    },
+    /// A command suggestion card
+    CommandCard {
+        /// The command as it will be typed (with any `cd` and marker)
+        command: String,
+        /// Where the suggested command starts in `command`
+        command_start: usize,
+        /// Directory the suggestion runs in (None = the shell's own)
+        cwd: Option<String>,
+        explanation: String,
+        status: CommandStatus,
+        verdict: Verdict,
+        /// Why the verdict is what it is, most serious first
+        findings: Vec<Finding>,
+        /// Whether all findings are listed or just the top one
+        findings_expanded: bool,
+        /// The AI's explanation of the verdict (advisory)
+        rationale: Rationale,
+        /// Shell the command was suggested for (badge and compatibility lint)
+        shell: ShellKind,
+        /// Tool call that made the suggestion; decisions on the card name it
+        tool_call_id: String,
+        /// Offered as a safer alternative to the suggestion it was asked for
+        safer: bool,
+    },
+    /// Greyed-out card for a suggestion still streaming; never executable
+    DraftCard {
+        command: Option<String>,
+        explanation: Option<String>,
+    },
+    /// Follows a reply that ended before it was complete: the connection
+    /// dropped (`partial`) or the length limit was reached. The reply can be
+    /// continued from there.
+    Interrupted { partial: bool },
+    /// An error message from the system
+    Error { text: String },
+    /// Answer computed locally (calculator, conversions), not part of the AI conversation
+    Local { text: String },
+    /// What some output means, from the explain_output tool
+    Explanation {
+        summary: String,
+        probable_cause: String,
+        suggested_next_step: String,
+    },
+    /// A question and reply replaced by a regenerated one, shown greyed out
+    Superseded { messages: Vec<ChatMessage> },
+    /// Where older turns were folded into a summary; expands to show it
+    Summary {
+        /// The summary (empty until it comes back)
+        text: String,
+        /// The commands suggested in the folded turns, each with what
+        /// became of it
+        suggestions: Vec<String>,
+        expanded: bool,
+    },
+    /// The whole output of a command, sent in parts before a question;
+    /// stands for the requests and replies that carried them
+    ContextParts {
+        command: String,
+        /// Parts sent so far (the one in flight included)
+        sent: usize,
+        parts: usize,
+        /// The parts are still on their way
+        sending: bool,
+    },
+}
+
+/// A session tab displayed in the tab bar
+#[derive(Debug, Clone, Default)]
+pub struct SessionTab {
+    pub id: SessionId,
+    pub name: String,
+    /// When a question was last sent or a reply last ended
+    pub last_activity: Option<Instant>,
+    /// A reply ended while another session was shown
+    pub unread: bool,
+    /// A suggested command is waiting for a decision
+    pub pending: bool,
+    /// Tokens the session's replies used
+    pub usage: UsageStats,
+}
+
+/// Result of clicking on the tab bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TabClickResult {
+    /// Switch to a different session
+    SwitchToTab(SessionId),
+    /// Create a new session ("+" button clicked)
+    NewTab,
+    /// Close the specified session ("x" button on active tab)
+    CloseTab(SessionId),
+    /// No action (clicked on empty area)
+    None,
+}
+
+/// Result of clicking on the message area
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageAreaClickResult {
+    /// Next command button clicked (for pagination)
+    NextCommand(usize),
+    /// Execute command button clicked on a pending command card
+    ExecuteCommand(usize),
+    /// Cancel command button clicked on a pending command card
+    CancelCommand(usize),
+    /// Verdict line clicked: show or hide the card's findings
+    ToggleFindings(usize),
+    /// "[?] why" on the verdict line clicked
+    ExplainVerdict(usize),
+    /// Multi button clicked: pick several suggestions instead of one
+    ToggleChecklist(usize),
+    /// A checklist row clicked: select or unselect it
+    ToggleChecklistRow(usize),
+    /// Compare button clicked: list all suggestions at once
+    ToggleCompare(usize),
+    /// A suggestion of the comparison clicked: highlight it
+    CompareRow(usize),
+    /// Regenerate hint under the latest reply clicked
+    Regenerate,
+    /// Summary divider clicked: show or hide the summary
+    ToggleSummary,
+    /// "New lines below" pill clicked: jump to the latest and follow it
+    JumpToLatest,
+    /// No special action (normal area click)
+    None,
+}
+
+// ============================================================================
+// TuiAssistant Widget
+// ============================================================================
+
+/// The main AI Assistant sidebar widget.
+///
+/// This is a pure UI component that receives updates from AiSessionManager
+/// through the App layer. It does not own any data channels.
+///
+/// Session state is synchronized from the backend (AiSessionManager).
+/// The frontend only maintains UI state and caches for rendering.
+pub struct TuiAssistant {
+    // Session management (synced from backend)
+    session_tabs: Vec<SessionTab>,
+    active_session: SessionId,
+
+    // Current session's messages, scroll position and pending suggestions
+    view: SessionView,
+
+    // Input box text, cursor and editing state
+    input: Input,
+
+    // Ctrl+Z with nothing to bring back: the App suspends RustyTerm
+    suspend_requested: bool,
+
+    // Unsent input of the sessions that are not active
+    drafts: HashMap<SessionId, InputDraft>,
+
+    // Cached rendering dimensions (updated during render, uses Cell for interior mutability)
+    last_input_area_width: Cell<u16>,
+
+    // Cached max scroll offset (updated during render)
+    max_scroll_offset: Cell<usize>,
+
+    // Visual mode state
+    visual_state: Option<VisualState>,
+
+    // Cached total lines for visual mode (updated during render)
+    cached_total_lines: Cell<usize>,
+
+    // Cached visible width for visual mode
+    cached_visible_width: Cell<usize>,
+
+    // Cached message list area (updated during render, used for scrollbar hits)
+    cached_message_area: Cell<Rect>,
+
+    // Cached tab positions for mouse click detection [(start_x, end_x, tab_id, is_close_button)]
+    // Updated during render_tab_bar
+    cached_tab_positions: std::cell::RefCell<Vec<TabHitArea>>,
+
+    // Cached command card positions for mouse click detection
+    // Updated during render_message_list
+    cached_command_cards: std::cell::RefCell<Vec<CommandCardHitArea>>,
+
+    // New-session picker popup (None = closed)
+    template_picker: Option<TemplatePicker>,
+
+    // System prompt picker (command-mode `!` with prompt templates)
+    prompt_picker: Option<PromptPicker>,
+
+    // Regenerate menu popup: highlighted entry (None = closed)
+    regen_menu: Option<usize>,
+
+    // Attachment picker popup (None = closed)
+    attach_picker: Option<AttachPicker>,
+    // Command whose whole output goes with the next message, in parts
+    full_output: Option<CommandRecord>,
+
+    // Row of the regenerate hint, relative to the message area, if visible
+    // Updated during render_message_list
+    cached_regen_hint: Cell<Option<u16>>,
+
+    // Row of the summary divider, relative to the message area, if visible
+    // Updated during render_message_list
+    cached_summary_divider: Cell<Option<u16>>,
+
+    // The "new lines below" pill as (row, start_x, end_x), relative to the
+    // message area, if shown. Updated during render_message_list
+    cached_new_lines_pill: Cell<Option<(u16, u16, u16)>>,
+
+    // Interactive elements under the mouse pointer (set by App, applied during render)
+    hovered_tab: TabClickResult,
+    hovered_card_button: MessageAreaClickResult,
+
+    // Answer calculator-style questions locally instead of asking the AI
+    local_answers: bool,
+    // Dollars per token, for the cost shown with the token count
+    prices: Option<Prices>,
+    // Enter with a card waiting rejects it and sends, instead of asking to
+    // answer the card first
+    enter_rejects_pending: bool,
+    // Until when the prompt flashes (Enter with nothing to send)
+    prompt_flash: Option<Instant>,
+
+    // What the next message carries, shown as chips above the input
+    context_summary: ContextSummary,
+    // Sections left out of the next message (chips clicked off)
+    attachments: Attachments,
+    // Chips as placed on the chip row, and the row itself if shown
+    // Updated during render
+    cached_chips: std::cell::RefCell<Vec<PlacedChip>>,
+    cached_chip_row: Cell<Option<Rect>>,
+
+    // Offer to attach files dropped on the input (paths typed in a burst)
+    file_drop: bool,
+
+    // Extra command rules of the current project and the user's allowlist,
+    // applied to new verdicts
+    policy: Policy,
+
+    // Shell that new command cards target
+    shell: ShellKind,
+
+    // Whether new command cards show the AI marker the app adds
+    mark_ai_commands: bool,
+
+    // Whether visual mode copies include reasoning blocks
+    copy_reasoning: bool,
+
+    // Whether replies with quotes from their context say what the
+    // underline means
+    quote_legend: bool,
+
+    // Drawn the cheap way: named colors, coalesced styles, no scrollbar
+    performance: bool,
+
+    // Commands already run, captured when the latest suggestions arrived
+    prior_runs: PriorRuns,
+
+    // The AI command sent last, while it seems to still be running
+    running_ai_command: Option<String>,
+    // Execute was pressed once while it ran: the next press sends anyway
+    send_anyway: bool,
+
+    // Latest suggested commands that were copied from the terminal output
+    from_output: Vec<String>,
+
+    // Shown in the title after a copy that no backend accepted
+    copy_error: Option<&'static str>,
+
+    // Shown in the title until the next question (e.g. context was skipped)
+    notice: Option<String>,
+
+    // The pane status last worked out, reused while its inputs stay the same
+    status_memo: StatusMemo<StatusInputs>,
+
+    // Shown in the input prompt until the streaming reply starts (e.g. a
+    // retry)
+    stream_status: Option<String>,
+
+    // Shown in place of the empty input while questions can't be sent
+    // (e.g. there is no API key)
+    setup_hint: Option<String>,
+}
+
+/// Popup state for choosing how to create a new session.
+#[derive(Debug, Clone)]
+pub struct TemplatePicker {
+    /// Entries in display order; index 0 is always "Blank"
+    items: Vec<String>,
+    /// Highlighted entry
+    selected: usize,
+}
+
+/// Popup state for choosing the system prompt of the session.
+#[derive(Debug, Clone)]
+pub struct PromptPicker {
+    /// Entries in display order; index 0 is always editing the current one
+    items: Vec<String>,
+    /// Highlighted entry
+    selected: usize,
+}
+
+/// Popup state for choosing a command whose whole output goes with the
+/// next message.
+#[derive(Debug, Clone)]
+pub struct AttachPicker {
+    /// Commands with output over the message budget, newest first, and
+    /// their entries (what sending it takes)
+    records: Vec<(CommandRecord, String)>,
+    /// Highlighted entry
+    selected: usize,
+}
+
+impl TuiAssistant {
+    /// Input prompt prefix (normal state)
+    const INPUT_PROMPT: &'static str = "> ";
+    /// Input prompt when AI is streaming (same length as normal prompt)
+    const STREAMING_PROMPT: &'static str = "⋯ ";
+    /// How long the prompt flashes
+    const PROMPT_FLASH: std::time::Duration = std::time::Duration::from_millis(300);
+    /// Input prompt in vi insert mode
+    const VI_INSERT_PROMPT: &'static str = "› ";
+    /// Input prompt in vi normal mode
+    const VI_NORMAL_PROMPT: &'static str = ": ";
+
+    /// Get the input prompt based on current state
+    fn prompt(&self) -> &str {
+        if self.is_streaming() {
+            return Self::STREAMING_PROMPT;
+        }
+        match self.input.vi.as_ref().map(ViInput::mode) {
+            None => Self::INPUT_PROMPT,
+            Some(ViMode::Insert) => Self::VI_INSERT_PROMPT,
+            Some(ViMode::Normal) => Self::VI_NORMAL_PROMPT,
+        }
+    }
+
+    /// What the request streaming in is doing, while its reply has not
+    /// started
+    fn stream_status(&self) -> Option<&str> {
+        self.stream_status.as_deref().filter(|_| self.is_streaming())
+    }
+
+    /// Get the input prompt width in characters
+    fn prompt_width(&self) -> u16 {
+        self.prompt().width() as u16
+    }
+}
+
+impl TuiAssistant {
+    pub fn new() -> Self {
+        // Initial session tab - will be overwritten by sync_session_tabs()
+        let initial_session = SessionTab {
+            id: 1,
+            name: "Session 1".to_string(),
+            ..SessionTab::default()
+        };
+        Self {
+            session_tabs: vec![initial_session],
+            active_session: 1,
+            view: SessionView::default(),
+            input: Input::default(),
+            suspend_requested: false,
+            drafts: HashMap::new(),
+            last_input_area_width: Cell::new(80), // Default value
+            max_scroll_offset: Cell::new(0),
+            visual_state: None,
+            cached_total_lines: Cell::new(0),
+            cached_visible_width: Cell::new(80),
+            cached_message_area: Cell::new(Rect::default()),
+            cached_tab_positions: std::cell::RefCell::new(Vec::new()),
+            cached_command_cards: std::cell::RefCell::new(Vec::new()),
+            template_picker: None,
+            prompt_picker: None,
+            regen_menu: None,
+            attach_picker: None,
+            full_output: None,
+            cached_regen_hint: Cell::new(None),
+            cached_summary_divider: Cell::new(None),
+            cached_new_lines_pill: Cell::new(None),
+            hovered_tab: TabClickResult::None,
+            hovered_card_button: MessageAreaClickResult::None,
+            local_answers: true,
+            prices: None,
+            enter_rejects_pending: false,
+            prompt_flash: None,
+            context_summary: ContextSummary::default(),
+            attachments: Attachments::default(),
+            cached_chips: std::cell::RefCell::new(Vec::new()),
+            cached_chip_row: Cell::new(None),
+            file_drop: true,
+            policy: Policy::default(),
+            shell: ShellKind::default(),
+            mark_ai_commands: false,
+            copy_reasoning: false,
+            quote_legend: false,
+            performance: false,
+            prior_runs: PriorRuns::default(),
+            running_ai_command: None,
+            send_anyway: false,
+            from_output: Vec::new(),
+            copy_error: None,
+            notice: None,
+            status_memo: StatusMemo::default(),
+            stream_status: None,
+            setup_hint: None,
+        }
+    }
+
+    /// Handle AI updates forwarded from AiSessionManager via App.
+    ///
+    /// This is the main entry point for AI data updates. The App layer
+    /// receives updates from AiSessionManager and forwards them here.
+    pub fn handle_ai_update(&mut self, update: AiUiUpdate) {
+        match update {
+            AiUiUpdate::Chunk { session_id, text } => {
+                if session_id == self.active_session {
+                    self.append_stream_chunk(&text);
+                }
+            }
+            AiUiUpdate::Reasoning { session_id, text } => {
+                if session_id == self.active_session {
+                    self.append_reasoning(&text);
+                }
+            }
+            AiUiUpdate::Timing { session_id, timing } => {
+                if session_id == self.active_session {
+                    self.set_reply_timing(timing);
+                }
+            }
+            AiUiUpdate::End { session_id, cached } => {
+                if session_id == self.active_session {
+                    self.end_stream();
+                    self.discard_draft_card();
+                    if cached {
+                        self.mark_reply_cached();
+                    }
+                }
+            }
+            AiUiUpdate::Truncated { session_id, partial } => {
+                if session_id == self.active_session {
+                    self.end_stream();
+                    self.discard_draft_card();
+                    self.arrive(|this| this.view.messages.push(ChatMessage::Interrupted { partial }));
+                }
+            }
+            AiUiUpdate::Error { session_id, error } => {
+                if session_id == self.active_session {
+                    self.end_stream();
+                    self.discard_draft_card();
+                    self.push_error_message(error);
+                }
+            }
+            AiUiUpdate::ModelNotFound { session_id, message, input } => {
+                if session_id == self.active_session {
+                    self.discard_draft_card();
+                    // The question was taken back: it goes to the input box
+                    if let Some(at) = self.view.messages.iter().rposition(|m| matches!(m, ChatMessage::User { .. })) {
+                        self.view.messages.truncate(at);
+                    }
+                    self.push_error_message(message);
+                    self.restore_unsent_input(input);
+                }
+            }
+            AiUiUpdate::Warning { session_id, message } => {
+                if session_id == self.active_session {
+                    self.notice = Some(message);
+                }
+            }
+            AiUiUpdate::Status { session_id, text } => {
+                if session_id == self.active_session {
+                    self.stream_status = Some(text);
+                }
+            }
+            AiUiUpdate::CommandSuggestionDraft { session_id, draft } => {
+                if session_id == self.active_session {
+                    self.show_draft_card(draft);
+                }
+            }
+            AiUiUpdate::CommandSuggestion {
+                session_id,
+                commands,
+                from_output,
+            } => {
+                if session_id == self.active_session {
+                    // End the streaming message first
+                    self.end_stream();
+                    // Store all commands and show the first one
+                    self.from_output = from_output;
+                    self.set_pending_commands(commands);
+                }
+            }
+            AiUiUpdate::Explanation { session_id, explanation } => {
+                if session_id == self.active_session {
+                    self.end_stream();
+                    self.discard_draft_card();
+                    self.arrive(|this| {
+                        this.view.messages.push(ChatMessage::Explanation {
+                            summary: explanation.summary,
+                            probable_cause: explanation.probable_cause,
+                            suggested_next_step: explanation.suggested_next_step,
+                        })
+                    });
+                }
+            }
+            AiUiUpdate::Summary { session_id, text } => {
+                if session_id == self.active_session {
+                    self.set_summary(text);
+                }
+            }
+            AiUiUpdate::Rationale { session_id, tool_call_id, rationale } => {
+                if session_id == self.active_session {
+                    self.set_rationale(&tool_call_id, rationale);
+                }
+            }
+            AiUiUpdate::SaferAlternative { session_id, original, alternative } => {
+                if session_id == self.active_session {
+                    self.add_safer_alternative(&original, alternative);
+                }
+            }
+            AiUiUpdate::ContextPart { session_id, sent, parts, question } => {
+                if session_id == self.active_session {
+                    self.show_context_part(sent, parts, question);
+                }
+            }
+            AiUiUpdate::Quotes { session_id, reply, spans } => {
+                if session_id == self.active_session {
+                    self.set_reply_quotes(&reply, spans);
+                }
+            }
+        }
+    }
+}
+
+impl Default for TuiAssistant {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TuiAssistant {
+    // ========================================================================
+    // Session Management (synced from backend)
+    // ========================================================================
+
+    /// Sync session tabs from backend.
+    ///
+    /// Called by `event::assistant::sync_sessions` whenever the sessions of
+    /// the AiSessionManager changed.
+    pub fn sync_session_tabs(&mut self, tabs: Vec<SessionTab>) {
+        // Drafts of closed sessions go with them
+        self.drafts.retain(|id, _| tabs.iter().any(|tab| tab.id == *id));
+        self.session_tabs = tabs;
+    }
+
+    /// Load messages for the current session from backend.
+    ///
+    /// Called after switching sessions, or when the conversation changed
+    /// other than by streaming, to populate the message list.
+    ///
+    /// The pending suggestions of a reply come as a card each; they go back
+    /// on one card, as when the reply came in.
+    pub fn load_messages(&mut self, mut messages: Vec<ChatMessage>) {
+        let is_pending = |m: &ChatMessage| matches!(m, ChatMessage::CommandCard { status: CommandStatus::Pending, .. });
+        let pending_command_idx = messages.iter().position(is_pending);
+        let mut pending_commands = Vec::new();
+        let mut safer_alternative = None;
+        if let Some(first) = pending_command_idx {
+            let count = messages[first..].iter().take_while(|m| is_pending(m)).count();
+            let cards: Vec<ChatMessage> = messages.drain(first + 1..first + count).collect();
+            for card in messages[first..=first].iter().chain(&cards) {
+                if let ChatMessage::CommandCard {
+                    command, command_start, cwd, explanation, findings, tool_call_id, safer, ..
+                } = card
+                {
+                    if *safer {
+                        safer_alternative = Some(tool_call_id.clone());
+                    }
+                    let typed = command.get(*command_start..).unwrap_or(command).to_string();
+                    pending_commands.push((tool_call_id.clone(), typed, explanation.clone(), cwd.clone(), findings.clone()));
+                }
+            }
+        }
+        self.view.messages = messages;
+        self.view.scroll_offset = 0;
+        self.view.unseen_lines = 0;
+        self.view.pending_command_idx = pending_command_idx;
+        self.view.pending_commands = pending_commands;
+        self.view.current_suggestion_idx = 0;
+        self.view.safer_alternative = safer_alternative;
+        self.view.checklist = None;
+        self.view.comparing = false;
+        self.view.local_questions.clear();
+    }
+
+    /// Switch to a different session by ID.
+    ///
+    /// This updates the active session ID and swaps the unsent input for
+    /// the draft of the new session. The caller should also call
+    /// `load_messages()` with the messages from the backend.
+    pub fn switch_session(&mut self, id: SessionId) {
+        if self.active_session != id {
+            let draft = self.current_draft();
+            if draft.is_empty() {
+                self.drafts.remove(&self.active_session);
+            } else {
+                self.drafts.insert(self.active_session, draft);
+            }
+            let draft = self.drafts.remove(&id).unwrap_or_default();
+            self.set_input_draft(draft);
+            self.active_session = id;
+            self.reset_session_view();
+        }
+    }
+
+    /// Forget the conversation of the active session, which was dropped
+    /// while the session stayed (closing the last tab): its messages,
+    /// cards and unsent input.
+    pub fn clear_session(&mut self) {
+        self.reset_session_view();
+        self.drafts.remove(&self.active_session);
+        self.set_input_draft(InputDraft::default());
+        self.notice = None;
+    }
+
+    /// Drop everything shown for the active session. Messages should then
+    /// be loaded by `load_messages()`.
+    fn reset_session_view(&mut self) {
+        self.view = SessionView::default();
+        self.send_anyway = false;
+        self.stream_status = None;
+        // Lines and cards picked out no longer exist
+        self.exit_visual_mode();
+        self.clear_message_list_caches();
+        // An attached output goes with a message of the session it was
+        // attached in only
+        self.full_output = None;
+    }
+
+    /// Get the current active session ID
+    pub fn active_session_id(&self) -> SessionId {
+        self.active_session
+    }
+
+    /// Get all session tabs
+    pub fn session_tabs(&self) -> &[SessionTab] {
+        &self.session_tabs
+    }
+
+    // ========================================================================
+    // Hover State
+    // ========================================================================
+
+    /// Set the hovered tab bar element and card button.
+    /// Returns true if the hover state changed (a redraw is needed).
+    pub fn set_hover(&mut self, tab: TabClickResult, card_button: MessageAreaClickResult) -> bool {
+        let changed = self.hovered_tab != tab || self.hovered_card_button != card_button;
+        self.hovered_tab = tab;
+        self.hovered_card_button = card_button;
+        changed
+    }
+
+    // ========================================================================
+    // New-Session Picker
+    // ========================================================================
+
+    /// Open the new-session picker with "Blank" followed by the template names.
+    pub fn open_template_picker(&mut self, template_names: Vec<String>) {
+        let mut items = vec!["Blank".to_string()];
+        items.extend(template_names);
+        self.template_picker = Some(TemplatePicker { items, selected: 0 });
+    }
+
+    /// Close the new-session picker without choosing.
+    pub fn close_template_picker(&mut self) {
+        self.template_picker = None;
+    }
+
+    /// Check if the new-session picker is open.
+    pub fn is_template_picker_open(&self) -> bool {
+        self.template_picker.is_some()
+    }
+
+    /// Move the picker highlight by delta (wraps around).
+    pub fn move_template_selection(&mut self, delta: i32) {
+        if let Some(picker) = &mut self.template_picker {
+            let len = picker.items.len() as i32;
+            picker.selected = (picker.selected as i32 + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Close the picker and return the highlighted entry (0 = Blank, i = template i - 1).
+    pub fn take_template_selection(&mut self) -> Option<usize> {
+        self.template_picker.take().map(|picker| picker.selected)
+    }
+
+    /// Close the picker and return entry `index` if it exists.
+    pub fn take_template_entry(&mut self, index: usize) -> Option<usize> {
+        let len = self.template_picker.as_ref()?.items.len();
+        if index >= len {
+            return None;
+        }
+        self.template_picker = None;
+        Some(index)
+    }
+
+    // ========================================================================
+    // System Prompt Picker
+    // ========================================================================
+
+    /// Open the system prompt picker with "Edit current prompt" followed by
+    /// the prompt template names.
+    pub fn open_prompt_picker(&mut self, template_names: Vec<String>) {
+        let mut items = vec!["Edit current prompt".to_string()];
+        items.extend(template_names);
+        self.prompt_picker = Some(PromptPicker { items, selected: 0 });
+    }
+
+    /// Close the system prompt picker without choosing.
+    pub fn close_prompt_picker(&mut self) {
+        self.prompt_picker = None;
+    }
+
+    /// Check if the system prompt picker is open.
+    pub fn is_prompt_picker_open(&self) -> bool {
+        self.prompt_picker.is_some()
+    }
+
+    /// Move the picker highlight by delta (wraps around).
+    pub fn move_prompt_selection(&mut self, delta: i32) {
+        if let Some(picker) = &mut self.prompt_picker {
+            let len = picker.items.len() as i32;
+            picker.selected = (picker.selected as i32 + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Close the picker and return entry `index` (the highlighted one if
+    /// None): None inside for editing the current prompt, or the name of
+    /// a template. The outer None means the picker was closed or the entry
+    /// doesn't exist.
+    pub fn take_prompt_entry(&mut self, index: Option<usize>) -> Option<Option<String>> {
+        let picker = self.prompt_picker.as_ref()?;
+        let index = index.unwrap_or(picker.selected);
+        let name = picker.items.get(index)?.clone();
+        self.prompt_picker = None;
+        Some((index > 0).then_some(name))
+    }
+
+    // ========================================================================
+    // Regenerate Menu
+    // ========================================================================
+
+    /// Menu entries: the question as it was, then each steer.
+    fn regen_menu_items() -> Vec<&'static str> {
+        std::iter::once("Same question").chain(Steer::ALL.iter().map(|steer| steer.label())).collect()
+    }
+
+    /// Open the menu for regenerating the latest reply.
+    pub fn open_regen_menu(&mut self) {
+        self.regen_menu = Some(0);
+    }
+
+    /// Close the regenerate menu without choosing.
+    pub fn close_regen_menu(&mut self) {
+        self.regen_menu = None;
+    }
+
+    /// Check if the regenerate menu is open.
+    pub fn is_regen_menu_open(&self) -> bool {
+        self.regen_menu.is_some()
+    }
+
+    /// Move the menu highlight by delta (wraps around).
+    pub fn move_regen_selection(&mut self, delta: i32) {
+        if let Some(selected) = &mut self.regen_menu {
+            let len = Self::regen_menu_items().len() as i32;
+            *selected = (*selected as i32 + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Close the menu and return entry `index` (the highlighted one if
+    /// None) as a steer, or None for the question as it was. The outer
+    /// None means the menu was closed or the entry doesn't exist.
+    pub fn take_regen_entry(&mut self, index: Option<usize>) -> Option<Option<Steer>> {
+        let index = index.or(self.regen_menu)?;
+        if index >= Self::regen_menu_items().len() {
+            return None;
+        }
+        self.regen_menu = None;
+        Some(index.checked_sub(1).map(|i| Steer::ALL[i]))
+    }
+
+    // ========================================================================
+    // Attachment Picker
+    // ========================================================================
+
+    /// Open the picker over `records`: commands with output over the
+    /// message budget, each with a line on what sending it whole takes.
+    pub fn open_attach_picker(&mut self, records: Vec<(CommandRecord, String)>) {
+        self.attach_picker = Some(AttachPicker { records, selected: 0 });
+    }
+
+    /// Close the attachment picker without choosing.
+    pub fn close_attach_picker(&mut self) {
+        self.attach_picker = None;
+    }
+
+    /// Check if the attachment picker is open.
+    pub fn is_attach_picker_open(&self) -> bool {
+        self.attach_picker.is_some()
+    }
+
+    /// Move the picker highlight by delta (wraps around).
+    pub fn move_attach_selection(&mut self, delta: i32) {
+        if let Some(picker) = &mut self.attach_picker {
+            let len = picker.records.len() as i32;
+            picker.selected = (picker.selected as i32 + delta).rem_euclid(len) as usize;
+        }
+    }
+
+    /// Close the picker and attach entry `index` (the highlighted one if
+    /// None): its whole output goes with the next message. What that takes
+    /// is shown until then.
+    pub fn take_attach_entry(&mut self, index: Option<usize>) {
+        let Some(picker) = &self.attach_picker else {
+            return;
+        };
+        let index = index.unwrap_or(picker.selected);
+        if index >= picker.records.len() {
+            return;
+        }
+        let Some((record, description)) =
+            self.attach_picker.take().and_then(|picker| picker.records.into_iter().nth(index))
+        else {
+            return;
+        };
+        self.notice = Some(format!(
+            "full output of `{}` goes with the next message: {} (Esc drops it)",
+            record.command_line, description
+        ));
+        self.full_output = Some(record);
+    }
+
+    /// The command whose whole output goes with the next message, taken.
+    pub fn take_full_output(&mut self) -> Option<CommandRecord> {
+        self.full_output.take()
+    }
+
+    /// Drop the attached output. Returns false if there was none.
+    pub fn drop_full_output(&mut self) -> bool {
+        if self.full_output.take().is_none() {
+            return false;
+        }
+        self.notice = Some("full output dropped".to_string());
+        true
+    }
+
+    /// Show that the whole output of `command` is about to go in `parts`.
+    pub fn begin_context_parts(&mut self, command: String, parts: usize) {
+        self.view.messages.push(ChatMessage::ContextParts { command, sent: 0, parts, sending: true });
+        self.notice = None;
+        self.scroll_to_bottom();
+    }
+
+    /// Part `sent` of `parts` is on its way. With the last one goes the
+    /// `question`: it is shown under the note, with the reply below.
+    pub fn show_context_part(&mut self, sent: usize, parts: usize, question: Option<String>) {
+        let done = question.is_some();
+        self.arrive(|this| {
+            let note = this.view.messages.iter_mut().rev().find_map(|msg| match msg {
+                ChatMessage::ContextParts { sent, parts, sending, .. } if *sending => Some((sent, parts, sending)),
+                _ => None,
+            });
+            if let Some((note_sent, note_parts, sending)) = note {
+                *note_sent = sent;
+                *note_parts = parts;
+                *sending = !done;
+            }
+        });
+        if let Some(question) = question {
+            self.push_user_message(question);
+            self.start_assistant_message();
+        }
+    }
+
+    // ========================================================================
+    // Message Management
+    // ========================================================================
+
+    /// Add a user message to the conversation
+    pub fn push_user_message(&mut self, text: String) {
+        self.view.messages.push(ChatMessage::User { text });
+        self.notice = None;
+        self.scroll_to_bottom();
+    }
+
+    /// Add a user message answered here rather than by the AI (a sum, a
+    /// slash command). It is gone once the conversation is loaded again.
+    pub fn push_local_question(&mut self, text: String) {
+        self.view.local_questions.push(self.view.messages.len());
+        self.push_user_message(text);
+    }
+
+    /// Show `message` next to the pane status until the next question.
+    pub fn show_notice(&mut self, message: String) {
+        self.notice = Some(message);
+    }
+
+    /// Show `hint` in place of the empty input until it is taken away
+    /// (None), for as long as questions can't be sent.
+    pub fn set_setup_hint(&mut self, hint: Option<String>) {
+        self.setup_hint = hint;
+    }
+
+    /// Add an error message to the conversation
+    pub fn push_error_message(&mut self, text: String) {
+        self.arrive(|this| this.view.messages.push(ChatMessage::Error { text }));
+    }
+
+    /// Add an answer computed locally (shown like an AI reply, tagged "local")
+    pub fn push_local_answer(&mut self, text: String) {
+        self.arrive(|this| this.view.messages.push(ChatMessage::Local { text }));
+    }
+
+    /// Enable or disable local answers for calculator-style questions
+    pub fn set_local_answers(&mut self, enabled: bool) {
+        self.local_answers = enabled;
+    }
+
+    /// Show what the tokens of a session cost at `prices`, next to their
+    /// count (None = the count alone)
+    pub fn set_prices(&mut self, prices: Option<Prices>) {
+        self.prices = prices;
+    }
+
+    /// Edit the input with vi modes instead of the emacs-style keys
+    pub fn set_vi_editing(&mut self, enabled: bool) {
+        self.input.vi = enabled.then(ViInput::new);
+    }
+
+    /// Use a project's extra command rules (empty rules for none). Pending
+    /// suggestions are evaluated again.
+    pub fn set_project_rules(&mut self, rules: ProjectRules) {
+        self.policy.rules = rules;
+        self.refresh_pending_findings();
+    }
+
+    /// Use the user's allowlist for new verdicts, and for the pending
+    /// commands' (a rule may lift their confirmation).
+    pub fn set_allowlist(&mut self, allowlist: Allowlist) {
+        self.policy.allowlist = allowlist;
+        self.refresh_pending_findings();
+    }
+
+    // The policy changed: work out the pending commands' findings again
+    fn refresh_pending_findings(&mut self) {
+        let pending: Vec<Vec<Finding>> = self
+            .view.pending_commands
+            .iter()
+            .map(|(_, command, _, cwd, _)| self.card_findings(command, cwd.as_deref()))
+            .collect();
+        for ((_, _, _, _, findings), new) in self.view.pending_commands.iter_mut().zip(pending) {
+            *findings = new;
+        }
+        if let Some(idx) = self.view.pending_command_idx
+            && let Some(ChatMessage::CommandCard { command, command_start, cwd, .. }) = self.view.messages.get(idx)
+        {
+            let new = self.card_findings(&command[*command_start..], cwd.as_deref());
+            if let Some(ChatMessage::CommandCard { verdict, findings, .. }) = self.view.messages.get_mut(idx) {
+                *verdict = verdict_from(&new);
+                *findings = new;
+            }
+        }
+    }
+
+    /// Set the shell that suggested commands run in.
+    pub fn set_shell(&mut self, shell: ShellKind) {
+        self.shell = shell;
+    }
+
+    /// Show suggested commands with the AI marker the app adds to them.
+    pub fn set_mark_ai_commands(&mut self, mark: bool) {
+        self.mark_ai_commands = mark;
+    }
+
+    /// Copy reasoning blocks along with the rest of a visual selection.
+    pub fn set_copy_reasoning(&mut self, copy: bool) {
+        self.copy_reasoning = copy;
+    }
+
+    /// Draw the pane the cheap way (see [`crate::ui::performance`]).
+    pub fn set_performance(&mut self, on: bool) {
+        self.performance = on;
+    }
+
+    /// Shell that suggested commands run in.
+    pub fn shell(&self) -> ShellKind {
+        self.shell
+    }
+
+    /// Whether calculator-style questions are answered locally
+    pub fn local_answers_enabled(&self) -> bool {
+        self.local_answers
+    }
+
+    /// Let Enter reject a waiting card and send the input.
+    pub fn set_enter_rejects_pending(&mut self, enabled: bool) {
+        self.enter_rejects_pending = enabled;
+    }
+
+    /// What Enter would do now. Local answers (`=`, calculator-style
+    /// questions) and `/` commands leave a waiting card alone, so only
+    /// questions for the AI have to wait for it.
+    pub fn enter_action(&self) -> EnterAction {
+        let input = self.input.buffer.as_str();
+        if input.trim().is_empty() {
+            return EnterAction::Nothing;
+        }
+        let trimmed = input.trim_start();
+        let asks_ai = !trimmed.starts_with(['=', '/'])
+            && (!self.local_answers || crate::ai::local::try_answer(input).is_none());
+        if self.is_streaming() {
+            return if asks_ai && self.view.editing.is_none() { EnterAction::Queue } else { EnterAction::Wait };
+        }
+        // An edited question replaces the turn the card is in
+        if self.view.editing.is_some() {
+            return EnterAction::Send;
+        }
+        match self.pending_tool_call_id() {
+            Some(_) if asks_ai && self.enter_rejects_pending => EnterAction::RejectAndSend,
+            Some(_) if asks_ai => EnterAction::AnswerCard,
+            _ => EnterAction::Send,
+        }
+    }
+
+    /// Flash the prompt, for Enter with nothing to send.
+    pub fn flash_prompt(&mut self) {
+        self.prompt_flash = Some(Instant::now() + Self::PROMPT_FLASH);
+    }
+
+    /// When the prompt stops flashing, if it is flashing: the frame then
+    /// has to be drawn again.
+    pub fn prompt_flash_until(&self) -> Option<Instant> {
+        self.prompt_flash.filter(|&until| until > Instant::now())
+    }
+
+    /// Start a new streaming assistant message
+    pub fn start_assistant_message(&mut self) {
+        self.arrive(|this| {
+            this.view.messages.push(ChatMessage::Assistant {
+                text: String::new(),
+                is_streaming: true,
+                cached: false,
+                timing: None,
+                timing_shown: false,
+                quotes: Vec::new(),
+            })
+        });
+    }
+
+    /// The latest message, looking past a draft card shown below the reply
+    fn latest_reply_mut(&mut self) -> Option<&mut ChatMessage> {
+        self.view.messages.iter_mut().rev().find(|m| !matches!(m, ChatMessage::DraftCard { .. }))
+    }
+
+    /// Append a chunk to the current streaming message
+    pub fn append_stream_chunk(&mut self, chunk: &str) {
+        self.stream_status = None;
+        self.arrive(|this| {
+            if let Some(ChatMessage::Assistant { text, is_streaming: true, .. }) = this.latest_reply_mut() {
+                text.push_str(chunk);
+            }
+        });
+    }
+
+    /// Underline what `reply`, the latest reply with that text, quoted from
+    /// its context.
+    fn set_reply_quotes(&mut self, reply: &str, spans: Vec<std::ops::Range<usize>>) {
+        let found = self.view.messages.iter_mut().rev().find_map(|m| match m {
+            ChatMessage::Assistant { text, is_streaming: false, quotes, .. } if text == reply => Some(quotes),
+            _ => None,
+        });
+        if let Some(quotes) = found {
+            *quotes = spans;
+        }
+    }
+
+    /// Keep the timing of the request behind the streaming message
+    fn set_reply_timing(&mut self, new: RequestTiming) {
+        if let Some(ChatMessage::Assistant { timing, is_streaming: true, .. }) = self.latest_reply_mut() {
+            *timing = Some(new);
+        }
+    }
+
+    /// Append a chunk of reasoning to the block above the streaming message
+    pub fn append_reasoning(&mut self, chunk: &str) {
+        self.stream_status = None;
+        let Some(idx) = self
+            .view.messages
+            .iter()
+            .rposition(|m| matches!(m, ChatMessage::Assistant { is_streaming: true, .. }))
+        else {
+            return;
+        };
+        self.arrive(|this| match idx.checked_sub(1).and_then(|prev| this.view.messages.get_mut(prev)) {
+            Some(ChatMessage::Reasoning { text, .. }) => text.push_str(chunk),
+            _ => this.view.messages.insert(idx, ChatMessage::Reasoning { text: chunk.to_string(), expanded: false }),
+        });
+    }
+
+    /// Mark the current streaming message as complete (or the parts of a
+    /// full output as no longer on their way)
+    pub fn end_stream(&mut self) {
+        self.stream_status = None;
+        match self.latest_reply_mut() {
+            Some(ChatMessage::Assistant { is_streaming, .. }) => *is_streaming = false,
+            Some(ChatMessage::ContextParts { sending, .. }) => *sending = false,
+            _ => {}
+        }
+    }
+
+    /// Whether the latest reply ended before it was complete, and can be
+    /// continued (Ctrl+G)
+    pub fn can_continue_reply(&self) -> bool {
+        matches!(self.view.messages.last(), Some(ChatMessage::Interrupted { .. }))
+    }
+
+    /// Badge the latest reply as replayed from the response cache (it may be
+    /// followed by its command cards)
+    fn mark_reply_cached(&mut self) {
+        let latest = self.view.messages.iter_mut().rev().find_map(|m| match m {
+            ChatMessage::Assistant { cached, .. } => Some(cached),
+            _ => None,
+        });
+        if let Some(cached) = latest {
+            *cached = true;
+        }
+    }
+
+    /// Index of the reply that can be regenerated: the latest message, or
+    /// the reply its command cards and explanations belong to, once it has
+    /// ended. Cached replies are asked again with Ctrl+R instead.
+    fn regenerable_reply(&self) -> Option<usize> {
+        let idx = self
+            .view
+            .messages
+            .iter()
+            .rposition(|m| !matches!(m, ChatMessage::CommandCard { .. } | ChatMessage::Explanation { .. }))?;
+        let after_question = self.view.messages[..idx].iter().any(|m| matches!(m, ChatMessage::User { .. }));
+        let ended = matches!(self.view.messages[idx], ChatMessage::Assistant { is_streaming: false, cached: false, .. });
+        (ended && after_question).then_some(idx)
+    }
+
+    /// Check if the visual cursor is on the reply that can be regenerated
+    /// (its text, command cards or hint).
+    pub fn visual_cursor_on_regenerable_reply(&self) -> bool {
+        let (Some(visual), Some(idx)) = (&self.visual_state, self.regenerable_reply()) else {
+            return false;
+        };
+        let width = self.cached_visible_width.get() as u16;
+        let reply_start = self.rendered_lines(&self.view.messages[..idx], width).len();
+        visual.cursor.0 >= reply_start
+    }
+
+    /// Index of the cached reply that Ctrl+R would ask again: the latest
+    /// reply, if cached and nothing was asked after it
+    fn refreshable_reply(&self) -> Option<usize> {
+        let idx = self.view.messages.iter().rposition(|m| {
+            matches!(m, ChatMessage::Assistant { .. } | ChatMessage::User { .. })
+        })?;
+        matches!(self.view.messages[idx], ChatMessage::Assistant { cached: true, .. }).then_some(idx)
+    }
+
+    // ========================================================================
+    // Pane Status API (for App to query rendering info)
+    // ========================================================================
+
+    /// Get pane status for rendering title bar and hints.
+    /// This allows the component to control its appearance without exposing internal state.
+    pub fn get_pane_status(&self) -> PaneStatus {
+        self.status_memo.get(self.status_inputs(), build_pane_status)
+    }
+
+    fn status_inputs(&self) -> StatusInputs {
+        let visual = self.visual_state.as_ref().map(|visual| {
+            let name = self.get_visual_selection_mode().and_then(|mode| mode.display_name()).unwrap_or("VISUAL");
+            (name, visual.get_repeat_count())
+        });
+        // What Ctrl+C does right now (see event::assistant::interrupt)
+        let key_hint = if self.has_input_selection() {
+            Some("Ctrl+C: Copy")
+        } else if !self.input.buffer.is_empty() {
+            Some("Ctrl+C: Clear")
+        } else if self.is_streaming() {
+            Some("Ctrl+C: Cancel")
+        } else {
+            None
+        };
+        StatusInputs {
+            visual,
+            scrolled: self.is_scrolled().then_some(self.view.scroll_offset),
+            copy_error: self.copy_error,
+            notice: self.notice.clone(),
+            editing: self.view.editing,
+            // Tokens the active session used so far
+            usage: self.session_tabs.iter().find(|tab| tab.id == self.active_session).map(|tab| tab.usage),
+            prices: self.prices,
+            key_hint,
+            enter_action: self.enter_action(),
+        }
+    }
+}
+
+/// What the assistant's pane status is worked out from.
+#[derive(Debug, Clone, PartialEq)]
+struct StatusInputs {
+    /// Selection mode name and repeat count, in visual mode
+    visual: Option<(&'static str, Option<usize>)>,
+    scrolled: Option<usize>,
+    copy_error: Option<&'static str>,
+    notice: Option<String>,
+    editing: Option<usize>,
+    usage: Option<UsageStats>,
+    prices: Option<Prices>,
+    key_hint: Option<&'static str>,
+    enter_action: EnterAction,
+}
+
+fn build_pane_status(inputs: &StatusInputs) -> PaneStatus {
+    let mut status_parts: Vec<String> = Vec::new();
+
+    if let Some((name, repeat)) = inputs.visual {
+        status_parts.push(name.to_string());
+        // Show repeat count if being accumulated
+        if let Some(count) = repeat {
+            status_parts.push(format!("{}×", count));
+        }
+    }
+
+    if let Some(offset) = inputs.scrolled {
+        status_parts.push(format!("Scrolled ↑{}", offset));
+    }
+
+    if let Some(error) = inputs.copy_error {
+        status_parts.push(error.to_string());
+    }
+
+    if let Some(ref notice) = inputs.notice {
+        status_parts.push(notice.clone());
+    }
+
+    if let Some(index) = inputs.editing {
+        status_parts.push(format!("Editing question {}", index + 1));
+    }
+
+    if let Some(label) = inputs.usage.and_then(|usage| usage.label(inputs.prices)) {
+        status_parts.push(label);
+    }
+
+    let title_status = if status_parts.is_empty() {
+        None
+    } else {
+        Some(status_parts.join(" "))
+    };
+
+    let hint_text = if inputs.visual.is_some() {
+        Some(" ESC: Exit | Space: Select | y/Ctrl+C: Copy | hjkl: Move ")
+    } else {
+        None
+    };
+
+    // What Enter does right now, once something is typed
+    let enter_hint = match inputs.enter_action {
+        EnterAction::Nothing => None,
+        EnterAction::Wait => Some("Enter: Reply streaming"),
+        EnterAction::Queue => Some("Enter: Queue"),
+        EnterAction::AnswerCard => Some("Enter: Answer card first"),
+        EnterAction::RejectAndSend => Some("Enter: Reject card & send"),
+        EnterAction::Send => Some("Enter: Send"),
+    };
+
+    let border_color = if inputs.visual.is_some() {
+        Some(Color::Magenta)
+    } else {
+        None
+    };
+
+    PaneStatus {
+        title_status,
+        hint_text,
+        enter_hint,
+        key_hint: inputs.key_hint,
+        border_color,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn denied_card(assistant: &mut TuiAssistant) {
+        assistant.push_command_card("call_1".to_string(), "rm -rf /".to_string(), String::new());
+    }
+
+    fn card_status(assistant: &TuiAssistant) -> Option<CommandStatus> {
+        assistant.messages().iter().rev().find_map(|m| match m {
+            ChatMessage::CommandCard { status, .. } => Some(*status),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_denied_card_is_not_marked_copied_when_copy_fails() {
+        let mut assistant = TuiAssistant::new();
+        denied_card(&mut assistant);
+
+        assert!(!assistant.finish_pending_copy(CopyOutcome::Unavailable));
+        assert_eq!(card_status(&assistant), Some(CommandStatus::Pending));
+        assert!(assistant.is_pending_command_denied());
+        let status = assistant.get_pane_status().title_status.unwrap_or_default();
+        assert!(status.contains("clipboard unavailable"));
+
+        // Retrying once a backend works marks it copied
+        assert!(assistant.finish_pending_copy(CopyOutcome::Osc52));
+        assert_eq!(card_status(&assistant), Some(CommandStatus::Executed));
+        assert!(assistant.get_pane_status().title_status.is_none());
+    }
+
+    #[test]
+    fn test_status_shows_the_tokens_of_the_active_session() -> Result<(), String> {
+        let mut assistant = TuiAssistant::new();
+        assert!(assistant.get_pane_status().title_status.is_none());
+        let tab = |id, usage| SessionTab { id, name: format!("Session {}", id), usage, ..SessionTab::default() };
+        assistant.sync_session_tabs(vec![tab(1, UsageStats::reply(11_400, 900)), tab(2, UsageStats::default())]);
+        assert_eq!(assistant.get_pane_status().title_status.as_deref(), Some("12.3k tok"));
+
+        let dollars = |value: f64| crate::ai::usage::Price::try_from(value);
+        assistant.set_prices(Some(Prices { prompt_per_1k: dollars(0.0025)?, completion_per_1k: dollars(0.01)? }));
+        assert_eq!(assistant.get_pane_status().title_status.as_deref(), Some("12.3k tok $0.04"));
+
+        // Nothing used yet in the session shown
+        assistant.switch_session(2);
+        assert!(assistant.get_pane_status().title_status.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_copy_keeps_selection() {
+        let mut assistant = TuiAssistant::new();
+        assistant.enter_visual_mode();
+        if let Some(visual) = assistant.visual_state.as_mut() {
+            visual.cycle_selection_mode();
+        }
+        assert!(assistant.is_visual_selecting());
+
+        assistant.finish_copy(Some(CopyOutcome::Unavailable));
+        assert!(assistant.is_visual_selecting());
+        let status = assistant.get_pane_status().title_status.unwrap_or_default();
+        assert!(status.contains(COPY_UNAVAILABLE));
+
+        assistant.finish_copy(Some(CopyOutcome::Clipboard));
+        assert!(!assistant.is_visual_selecting());
+    }
+
+    #[test]
+    fn test_parts_fold_into_one_note_until_the_question() {
+        let mut assistant = TuiAssistant::new();
+        let note = |assistant: &TuiAssistant| {
+            assistant.messages().iter().find_map(|m| match m {
+                ChatMessage::ContextParts { sent, parts, sending, .. } => Some((*sent, *parts, *sending)),
+                _ => None,
+            })
+        };
+        assistant.begin_context_parts("cargo test".to_string(), 3);
+        assistant.show_context_part(1, 3, None);
+        assert_eq!(note(&assistant), Some((1, 3, true)));
+        assert!(assistant.is_streaming());
+
+        let progress = |sent, question: Option<&str>| AiUiUpdate::ContextPart {
+            session_id: 1,
+            sent,
+            parts: 3,
+            question: question.map(str::to_string),
+        };
+        assistant.handle_ai_update(progress(2, None));
+        assert_eq!(assistant.messages().len(), 1);
+        assistant.handle_ai_update(progress(3, Some("why?")));
+        assert_eq!(note(&assistant), Some((3, 3, false)));
+        assert!(matches!(&assistant.messages()[1], ChatMessage::User { text } if text == "why?"));
+        assert!(assistant.is_streaming());
+
+        // Stopped before the question: the note says how far it got
+        let mut assistant = TuiAssistant::new();
+        assistant.begin_context_parts("make".to_string(), 3);
+        assistant.show_context_part(2, 3, None);
+        assistant.handle_ai_update(AiUiUpdate::End { session_id: 1, cached: false });
+        assert_eq!(note(&assistant), Some((2, 3, false)));
+        assert!(!assistant.is_streaming());
+    }
+
+    /// Stream `args` to the assistant as drafts, split into the given deltas
+    fn stream_draft(assistant: &mut TuiAssistant, args: &str, cuts: &[usize]) {
+        let mut tracker = crate::ai::draft::DraftTracker::default();
+        let mut received = String::new();
+        let mut start = 0;
+        for &end in cuts.iter().chain(std::iter::once(&args.len())) {
+            received.push_str(&args[start..end]);
+            start = end;
+            if let Some(draft) = tracker.update(0, &received) {
+                assistant.handle_ai_update(AiUiUpdate::CommandSuggestionDraft { session_id: 1, draft });
+            }
+        }
+    }
+
+    fn draft_cards(assistant: &TuiAssistant) -> usize {
+        assistant.messages().iter().filter(|m| matches!(m, ChatMessage::DraftCard { .. })).count()
+    }
+
+    #[test]
+    fn test_draft_card_upgrades_in_place() {
+        let args = r#"{"command": "echo \"a\\b\" \u00e9", "explanation": "Prints it", "risk_level": "low"}"#;
+        let mid_key = args.find("mand").unwrap_or(0);
+        let mid_escape = args.find("\\\\").map_or(0, |i| i + 1);
+        let mid_unicode = args.find("00e9").unwrap_or(0);
+        let mut assistant = TuiAssistant::new();
+        assistant.start_assistant_message();
+        assistant.append_stream_chunk("Try this:");
+        stream_draft(&mut assistant, args, &[1, mid_key, mid_escape, mid_unicode]);
+
+        assert_eq!(draft_cards(&assistant), 1);
+        assert!(matches!(
+            &assistant.messages()[1],
+            ChatMessage::DraftCard { command: Some(c), explanation: Some(e) } if c == "echo \"a\\b\" é" && e == "Prints it"
+        ));
+        // Drafts never count as a pending command
+        assert!(!assistant.has_pending_command());
+        assert_eq!(assistant.confirm_command(), None);
+
+        // The final parse differs: its content wins
+        assistant.handle_ai_update(AiUiUpdate::CommandSuggestion {
+            session_id: 1,
+            commands: vec![("call_1".to_string(), "echo done".to_string(), "Prints done".to_string(), None)],
+            from_output: Vec::new(),
+        });
+        assistant.handle_ai_update(AiUiUpdate::End { session_id: 1, cached: false });
+        assert_eq!(draft_cards(&assistant), 0);
+        assert_eq!(assistant.messages().len(), 2);
+        assert!(matches!(
+            &assistant.messages()[0],
+            ChatMessage::Assistant { text, is_streaming: false, .. } if text == "Try this:"
+        ));
+        assert!(matches!(
+            &assistant.messages()[1],
+            ChatMessage::CommandCard { command, status: CommandStatus::Pending, .. } if command == "echo done"
+        ));
+        assert_eq!(assistant.confirm_command().as_deref(), Some("echo done"));
+    }
+
+    #[test]
+    fn test_draft_card_is_dropped_when_stream_fails() {
+        let mut assistant = TuiAssistant::new();
+        assistant.start_assistant_message();
+        stream_draft(&mut assistant, r#"{"command": "ls -la""#, &[3, 15]);
+        // Chunks after the draft still reach the reply above it
+        assistant.append_stream_chunk("Listing");
+        assert_eq!(draft_cards(&assistant), 1);
+
+        assistant.handle_ai_update(AiUiUpdate::Error { session_id: 1, error: "Stream error".to_string() });
+        assert_eq!(draft_cards(&assistant), 0);
+        assert!(matches!(
+            &assistant.messages()[0],
+            ChatMessage::Assistant { text, is_streaming: false, .. } if text == "Listing"
+        ));
+        assert!(matches!(assistant.messages().last(), Some(ChatMessage::Error { .. })));
+        assert!(!assistant.has_pending_command());
+    }
+
+    #[test]
+    fn test_question_to_a_missing_model_goes_back_to_the_input() {
+        let mut assistant = TuiAssistant::new();
+        assistant.push_user_message("list open ports".to_string());
+        assistant.start_assistant_message();
+
+        assistant.handle_ai_update(AiUiUpdate::ModelNotFound {
+            session_id: 1,
+            message: "Model `gpt-4o-mimi` was not found.".to_string(),
+            input: "list open ports".to_string(),
+        });
+        assert_eq!(assistant.messages().len(), 1);
+        assert!(matches!(&assistant.messages()[0], ChatMessage::Error { text } if text.contains("gpt-4o-mimi")));
+        assert_eq!(assistant.get_input(), "list open ports");
+    }
+
+    #[test]
+    fn test_draft_card_renders_without_buttons() {
+        let lines = render_draft_card(None, Some("Lists files"), 40);
+        let text: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+        assert!(text[0].contains("suggestion incoming…"));
+        assert!(text.iter().any(|l| l.contains("Lists files")));
+        assert!(text.iter().any(|l| l.contains("> …")));
+        assert!(!text.iter().any(|l| l.contains("Ctrl+Y")));
+    }
+
+    #[test]
+    fn test_command_card_shows_the_composed_command() -> Result<(), Box<dyn std::error::Error>> {
+        let mut assistant = TuiAssistant::new();
+        assistant.set_shell(ShellKind::Fish);
+        assistant.set_mark_ai_commands(true);
+        assistant.set_pending_commands(vec![
+            ("call_1".to_string(), "npm test".to_string(), String::new(), Some("web app".to_string())),
+            ("call_2".to_string(), "ls".to_string(), String::new(), Some("/etc".to_string())),
+        ]);
+        let Some(ChatMessage::CommandCard { command, verdict, .. }) = assistant.messages().last() else {
+            return Err("no card".into());
+        };
+        assert_eq!(command, "cd 'web app' && env RUSTYTERM_AI=1 npm test");
+        assert_eq!(*verdict, Verdict::RequireConfirmation("`npm` is not a known read-only command".to_string()));
+
+        // The directory is held to the scope policy
+        assistant.cycle_suggestion();
+        let Some(ChatMessage::CommandCard { command, findings, .. }) = assistant.messages().last() else {
+            return Err("no card".into());
+        };
+        assert_eq!(command, "cd /etc && env RUSTYTERM_AI=1 ls");
+        assert_eq!(findings.iter().map(|f| f.matched(command)).collect::<Vec<_>>(), vec!["/etc"]);
+        assert_eq!(assistant.confirm_command().as_deref(), Some("cd /etc && env RUSTYTERM_AI=1 ls"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_checklist_picks_several_suggestions_in_order() -> Result<(), Box<dyn std::error::Error>> {
+        let mut assistant = TuiAssistant::new();
+        assert!(!assistant.toggle_checklist());
+
+        assistant.set_pending_commands(vec![
+            ("call_1".to_string(), "docker container prune".to_string(), String::new(), None),
+            ("call_2".to_string(), "docker image prune".to_string(), String::new(), None),
+            ("call_3".to_string(), "git push origin main".to_string(), String::new(), None),
+        ]);
+        let rendered = |assistant: &TuiAssistant| -> Vec<String> {
+            assistant.build_rendered_lines(80).iter().map(|l| l.to_string()).collect()
+        };
+        assert!(rendered(&assistant).iter().any(|l| l.contains("[m] Multi")));
+        assert!(assistant.toggle_checklist());
+
+        let checklist = assistant.checklist_mut().ok_or("no checklist")?;
+        checklist.move_cursor(1);
+        checklist.toggle();
+        checklist.move_item(-1);
+        checklist.move_cursor(1);
+        checklist.toggle();
+        assert_eq!(assistant.selected_tool_call_ids(), ["call_2", "call_1"]);
+
+        let lines = rendered(&assistant);
+        let card = lines.iter().skip_while(|l| !l.contains("Run several")).collect::<Vec<_>>();
+        assert!(card[0].contains("Run several (2 of 3 selected)"), "{:?}", card);
+        assert!(card[1].starts_with(" │  [1] ⚠ Confirm docker image prune"), "{:?}", card);
+        assert!(card[2].starts_with(" │▸ [2] ⚠ Confirm docker container prune"), "{:?}", card);
+        assert!(card[3].starts_with(" │  [ ] ✗ Deny    git push origin main"), "{:?}", card);
+        assert!(card[5].contains("[Ctrl+Y] Run selected"));
+
+        // Back to one at a time, and the selection starts over
+        assert!(assistant.toggle_checklist());
+        assert!(assistant.selected_tool_call_ids().is_empty());
+        assistant.reject_command();
+        assert!(!assistant.toggle_checklist());
+        Ok(())
+    }
+
+    #[test]
+    fn test_comparison_lists_every_suggestion_and_runs_the_highlighted_one() -> Result<(), Box<dyn std::error::Error>> {
+        let mut assistant = TuiAssistant::new();
+        assert!(!assistant.toggle_compare());
+        assistant.set_pending_commands(vec![
+            ("call_1".to_string(), "du -sh .".to_string(), "Total size".to_string(), None),
+            ("call_2".to_string(), "du -sh --max-depth=1 .".to_string(), "Size per folder".to_string(), None),
+            ("call_3".to_string(), "ncdu .".to_string(), "Browse sizes".to_string(), None),
+        ]);
+        let rendered = |assistant: &TuiAssistant| -> Vec<String> {
+            assistant.build_rendered_lines(80).iter().map(|l| l.to_string()).collect()
+        };
+        assert!(rendered(&assistant).iter().any(|l| l.contains("[c] Compare")));
+        assert!(assistant.toggle_compare());
+        assert!(assistant.is_comparing());
+
+        let lines = rendered(&assistant);
+        let card = lines.iter().skip_while(|l| !l.contains("Compare (3 suggestions)")).collect::<Vec<_>>();
+        assert!(card[1].starts_with(" │▸ ⚠ Confirm du -sh ."), "{:?}", card);
+        assert!(card[2].contains("Total size"));
+        assert!(card[3].starts_with(" │  ⚠ Confirm du -sh --max-depth=1 ."), "{:?}", card);
+        assert!(card[8].contains("[Ctrl+Y] Run highlighted"), "{:?}", card);
+
+        // Only the words that differ from the first are picked out
+        let built = assistant.build_rendered_lines(80);
+        let line = built.iter().find(|l| l.to_string().contains("--max-depth=1")).ok_or("no row")?;
+        let underlined: Vec<&str> = line
+            .spans
+            .iter()
+            .filter(|span| span.style.add_modifier.contains(Modifier::UNDERLINED))
+            .map(|span| span.content.trim())
+            .collect();
+        assert_eq!(underlined, ["--max-depth=1"]);
+
+        // The highlight is the suggestion Ctrl+Y decides on
+        assistant.move_compare(1);
+        assert_eq!(assistant.pending_tool_call_id().as_deref(), Some("call_2"));
+        assistant.move_compare(5);
+        assert_eq!(assistant.pending_tool_call_id().as_deref(), Some("call_3"));
+        assistant.compare_row(0);
+        assert_eq!(assistant.pending_tool_call_id().as_deref(), Some("call_1"));
+        assistant.move_compare(1);
+
+        // Back to one at a time, on the highlighted one
+        assert!(assistant.toggle_compare());
+        assert!(!assistant.is_comparing());
+        assert_eq!(assistant.suggestion_pagination(), Some((2, 3)));
+        assert!(assistant.toggle_compare());
+        assert_eq!(assistant.confirm_command().as_deref(), Some("du -sh --max-depth=1 ."));
+        assert!(!assistant.is_comparing());
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_card_shows_shell_badge_and_lint() -> Result<(), Box<dyn std::error::Error>> {
+        let mut assistant = TuiAssistant::new();
+        assistant.set_shell(ShellKind::Fish);
+        assistant.push_command_card_with_findings("call_1".to_string(), "export EDITOR=vim".to_string(), String::new(), None, Vec::new());
+        let card = assistant.messages().last().ok_or("no card")?;
+        let text: Vec<String> = render_command_card(card, None, None, 60, None).iter().map(|l| l.to_string()).collect();
+        assert!(text[0].starts_with(" ┌─ fish ─"));
+        assert!(text.iter().any(|l| l.contains("⚠ fish:") && l.contains("Ctrl+T")));
+
+        // Only the command itself is run or copied
+        assert_eq!(assistant.pending_incompatible_command(), Some(("export EDITOR=vim", ShellKind::Fish)));
+        assert_eq!(assistant.confirm_command().as_deref(), Some("export EDITOR=vim"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ime_input_is_edited_and_placed_by_grapheme() {
+        let mut assistant = TuiAssistant::new();
+        // "> ab" leaves 日 the last two cells; 本 goes to the next row
+        assistant.last_input_area_width.set(6);
+        "ab日本".chars().for_each(|c| assistant.insert_char_with_selection(c));
+        assert_eq!(assistant.get_cursor_position(), Some((2, 1)));
+        assistant.move_cursor(-1);
+        assert_eq!(assistant.get_cursor_position(), Some((0, 1)));
+        assistant.move_cursor(-1);
+        assert_eq!(assistant.get_cursor_position(), Some((4, 0)));
+
+        // A syllable typed as jamo and a ZWJ sequence go in one Backspace
+        let mut assistant = TuiAssistant::new();
+        "a\u{1112}\u{1161}\u{11ab}👩\u{200d}💻".chars().for_each(|c| assistant.insert_char_with_selection(c));
+        assistant.delete_char();
+        assert_eq!(assistant.get_input(), "a\u{1112}\u{1161}\u{11ab}");
+        assistant.delete_char();
+        assert_eq!(assistant.get_input(), "a");
+    }
+
+    #[test]
+    fn test_restored_draft_is_noted_and_never_overwrites_input() {
+        let draft = InputDraft { text: "explain this error".to_string(), cursor: 7, selection_anchor: None, files: Vec::new() };
+        let mut assistant = TuiAssistant::new();
+        assistant.restore_draft(draft.clone());
+        assert_eq!(assistant.current_draft(), draft);
+        assert_eq!(assistant.get_pane_status().title_status.as_deref(), Some("draft restored"));
+
+        let mut typed = TuiAssistant::new();
+        typed.insert_char('x');
+        typed.restore_draft(draft);
+        assert_eq!(typed.get_input(), "x");
+    }
+
+    #[test]
+    fn test_command_card_expands_to_all_findings() -> Result<(), Box<dyn std::error::Error>> {
+        let mut assistant = TuiAssistant::new();
+        assistant.push_command_card("call_1".to_string(), "sudo rm -r build".to_string(), String::new());
+        // Deleting comes first, then the privilege escalation
+        let render = |assistant: &TuiAssistant| -> Result<Vec<String>, &'static str> {
+            let card = assistant.messages().last().ok_or("no card")?;
+            Ok(render_command_card(card, None, None, 80, None).iter().map(|l| l.to_string()).collect())
+        };
+
+        // Collapsed: the top finding is the reason, the rest is counted
+        let collapsed = render(&assistant)?;
+        assert!(collapsed[1].contains("⚠ Confirm: `rm -r` deletes files and directories for good (+1 more)"));
+        assert!(!collapsed.iter().any(|l| l.contains("•")));
+
+        assert!(assistant.toggle_pending_findings());
+        let expanded = render(&assistant)?;
+        assert!(!expanded[1].contains("more"));
+        assert!(expanded[2].contains("• destructive-delete `rm -r build`"));
+        assert!(expanded.iter().any(|l| l.contains("• privilege-escalation `sudo`: `sudo` runs the command as root")));
+
+        // Allowed commands have nothing to expand
+        assistant.push_command_card("call_1".to_string(), "ls".to_string(), String::new());
+        assert!(!assistant.toggle_pending_findings());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verdict_explanation_is_asked_for_and_toggled() -> Result<(), Box<dyn std::error::Error>> {
+        let mut assistant = TuiAssistant::new();
+        assistant.push_command_card("call_1".to_string(), "sudo rm -r build".to_string(), String::new());
+        let render = |assistant: &TuiAssistant| -> Result<Vec<String>, &'static str> {
+            let card = assistant.messages().last().ok_or("no card")?;
+            Ok(render_command_card(card, None, None, 80, None).iter().map(|l| l.to_string()).collect())
+        };
+        assert!(render(&assistant)?[1].ends_with("[?] why│"));
+
+        // The question carries the command and every finding
+        let WhyVerdict::Ask(question) = assistant.why_pending_verdict() else {
+            return Err("nothing to ask".into());
+        };
+        assert_eq!((question.tool_call_id.as_str(), question.command.as_str()), ("call_1", "sudo rm -r build"));
+        assert_eq!(question.findings.len(), 2);
+        assert!(render(&assistant)?.iter().any(|l| l.contains("AI explanation: asking…")));
+        // Asked once
+        assert_eq!(assistant.why_pending_verdict(), WhyVerdict::Handled);
+
+        assistant.set_rationale("call_1", Ok("It deletes build as root.".to_string()));
+        let shown = render(&assistant)?;
+        assert!(shown[1].ends_with("[?] hide│"));
+        assert!(shown.iter().any(|l| l.contains("AI explanation (advisory, may be wrong):")));
+        assert!(shown.iter().any(|l| l.contains("  It deletes build as root.")));
+        assert_eq!(assistant.why_pending_verdict(), WhyVerdict::Handled);
+        assert!(!render(&assistant)?.iter().any(|l| l.contains("AI explanation")));
+
+        // Allowed commands have nothing to explain
+        assistant.push_command_card("call_2".to_string(), "ls".to_string(), String::new());
+        assert!(!render(&assistant)?[1].contains("[?]"));
+        assert_eq!(assistant.why_pending_verdict(), WhyVerdict::NotApplicable);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unavailable_explanation_shows_all_findings() -> Result<(), Box<dyn std::error::Error>> {
+        let mut assistant = TuiAssistant::new();
+        assistant.push_command_card("call_1".to_string(), "sudo rm -r build".to_string(), String::new());
+        assert!(matches!(assistant.why_pending_verdict(), WhyVerdict::Ask(_)));
+        assistant.set_rationale("call_1", Err("no API key".to_string()));
+        assert!(matches!(
+            assistant.messages().last(),
+            Some(ChatMessage::CommandCard { findings_expanded: true, rationale: Rationale::NotAsked, .. })
+        ));
+        assert_eq!(assistant.notice.as_deref(), Some("AI explanation unavailable: all findings shown"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reasoning_folds_above_the_reply_and_is_left_out_of_copies() {
+        let mut assistant = TuiAssistant::new();
+        assistant.start_assistant_message();
+        assistant.append_reasoning("Check the ");
+        assistant.append_stream_chunk("Run df -h.");
+        assistant.append_reasoning("mounts.");
+        assert!(matches!(
+            assistant.messages(),
+            [ChatMessage::Reasoning { text, expanded: false }, ChatMessage::Assistant { .. }] if text == "Check the mounts."
+        ));
+
+        let rendered = |assistant: &TuiAssistant| -> Vec<String> {
+            assistant.build_rendered_lines(40).iter().map(|l| l.to_string()).collect()
+        };
+        assert_eq!(rendered(&assistant)[0], "▸ thinking… (17 chars)");
+        assert!(assistant.toggle_reasoning());
+        assert_eq!(rendered(&assistant)[..2], ["▾ thinking (17 chars)", "  Check the mounts."]);
+
+        assistant.end_stream();
+        assistant.cached_visible_width.set(40);
+        let last = rendered(&assistant).len() - 1;
+        let copied = assistant.get_text_range(0, 0, last, 40, SelectionMode::Line);
+        assert!(copied.contains("AI: Run df -h."));
+        assert!(!copied.contains("mounts"));
+        assistant.set_copy_reasoning(true);
+        assert!(assistant.get_text_range(0, 0, last, 40, SelectionMode::Line).contains("Check the mounts."));
+    }
+
+    #[test]
+    fn test_timing_of_the_reply_under_the_cursor_shows_on_demand() {
+        use std::time::Duration;
+
+        let timing = RequestTiming {
+            shell2: Duration::from_millis(200),
+            first_byte: Duration::from_millis(1800),
+            stream: Duration::from_millis(6400),
+            tokens: 269,
+            ..RequestTiming::default()
+        };
+        let mut assistant = TuiAssistant::new();
+        assistant.push_user_message("how big is it?".to_string());
+        assistant.start_assistant_message();
+        assistant.handle_ai_update(AiUiUpdate::Chunk { session_id: 1, text: "About 2 GB.".to_string() });
+        assistant.handle_ai_update(AiUiUpdate::Timing { session_id: 1, timing });
+        assistant.handle_ai_update(AiUiUpdate::End { session_id: 1, cached: false });
+        let area = Rect::new(0, 0, 60, 20);
+        assistant.render(area, &mut Buffer::empty(area));
+
+        let shows_timing = |assistant: &TuiAssistant| {
+            assistant
+                .build_rendered_lines(60)
+                .iter()
+                .any(|line| line.to_string() == "  ctx 0.2s · ttfb 1.8s · stream 6.4s · 42 tok/s")
+        };
+        assert!(!shows_timing(&assistant));
+        assistant.enter_visual_mode();
+        assistant.handle_visual_key(KeyEvent::new(KeyCode::Char('i'), KeyModifiers::NONE));
+        assert!(shows_timing(&assistant));
+        assistant.handle_visual_key(KeyEvent::new(KeyCode::Char('i'), KeyModifiers::NONE));
+        assert!(!shows_timing(&assistant));
+
+        // The question has no timing
+        for _ in 0..10 {
+            assistant.handle_visual_key(KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE));
+        }
+        assert_eq!(assistant.message_at_visual_cursor(), Some(0));
+        assert!(!assistant.toggle_timing_at_cursor());
+    }
+
+    #[test]
+    fn test_quotes_are_underlined_across_wrapped_lines_and_copied_plain() {
+        let reply = "The log says permission denied on /var/lib/app/data/cache.db, so fix the owner.";
+        let quoted = reply.find("permission").unwrap_or_default()..reply.find(", so").unwrap_or_default();
+        let mut assistant = TuiAssistant::new();
+        assistant.start_assistant_message();
+        assistant.handle_ai_update(AiUiUpdate::Chunk { session_id: 1, text: reply.to_string() });
+        assistant.handle_ai_update(AiUiUpdate::End { session_id: 1, cached: false });
+        assistant.handle_ai_update(AiUiUpdate::Quotes { session_id: 1, reply: reply.to_string(), spans: vec![quoted] });
+
+        let lines = assistant.build_rendered_lines(40);
+        let underlined: String = lines
+            .iter()
+            .flat_map(|line| &line.spans)
+            .filter(|span| span.style.add_modifier.contains(Modifier::UNDERLINED))
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(underlined, "permission denied on /var/lib/app/data/cache.db");
+        let text: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(text[..2], ["AI: The log says permission denied on /", "    var/lib/app/data/cache.db, so fix"]);
+
+        // Copies are the plain text
+        assistant.cached_visible_width.set(40);
+        let copied = assistant.get_text_range(0, 0, 2, 40, SelectionMode::Line);
+        assert!(copied.starts_with("AI: The log says permission denied on /\n    var/lib/app/data/cache.db, so fix"));
+
+        assert!(assistant.toggle_quote_legend());
+        let legend = "  underlined: quoted verbatim from your context";
+        assert!(assistant.build_rendered_lines(40).iter().any(|line| line.to_string() == legend));
+        assert!(!assistant.toggle_quote_legend());
+        assert!(!assistant.build_rendered_lines(40).iter().any(|line| line.to_string() == legend));
+    }
+
+    #[test]
+    fn test_streaming_while_scrolled_back_keeps_the_view_anchored() {
+        let render = |assistant: &TuiAssistant| -> Vec<String> {
+            let area = Rect::new(0, 0, 60, 20);
+            let mut buf = Buffer::empty(area);
+            assistant.render(area, &mut buf);
+            let list = assistant.message_area();
+            (list.y..list.y + list.height)
+                .map(|y| (list.x..list.x + list.width).map(|x| buf[(x, y)].symbol()).collect())
+                .collect()
+        };
+        let mut assistant = TuiAssistant::new();
+        for i in 0..20 {
+            assistant.push_user_message(format!("question {}", i));
+        }
+        assistant.start_assistant_message();
+        assistant.append_stream_chunk("First line.");
+        render(&assistant);
+
+        // Scrolled back, the chunks don't move what is read
+        assistant.scroll(-6);
+        let before = render(&assistant);
+        let width = assistant.cached_visible_width.get() as u16;
+        let lines = assistant.build_rendered_lines(width).len();
+        for _ in 0..5 {
+            assistant.append_stream_chunk("\nAnother line.");
+        }
+        assistant.push_error_message("rate limited".to_string());
+        let grown = assistant.build_rendered_lines(width).len() - lines;
+        assert!(grown >= 6);
+        assert_eq!(assistant.unseen_lines(), grown);
+        assert_eq!(assistant.scroll_offset(), 6 + grown);
+        let after = render(&assistant);
+        let bottom = after.len() - 1;
+        // The scrollbar on the right shrinks, the text stays put
+        let text = |rows: &[String]| -> Vec<String> {
+            rows.iter().map(|row| row.chars().take(row.chars().count() - 1).collect()).collect()
+        };
+        assert_eq!(text(&before[..bottom]), text(&after[..bottom]));
+        assert!(after[bottom].contains(&format!("↓ {} new lines below", grown)));
+
+        // Scrolling down toward them counts them as seen
+        assistant.scroll(grown as i16 + 2);
+        assert_eq!(assistant.unseen_lines(), 4);
+        assert!(render(&assistant)[bottom].contains("↓ 4 new lines below"));
+
+        // Clicking the pill jumps down and follows again
+        let list = assistant.message_area();
+        let row = render(&assistant)[bottom].clone();
+        let col = row.chars().position(|c| c == '↓').map_or(0, |col| col as u16);
+        let clicked = assistant.get_message_click_result(list.x + col, list.y + bottom as u16, list.x, list.y);
+        assert_eq!(clicked, MessageAreaClickResult::JumpToLatest);
+        assistant.scroll_to_bottom();
+        assistant.push_error_message("retrying".to_string());
+        assert_eq!((assistant.scroll_offset(), assistant.unseen_lines()), (0, 0));
+        let followed = render(&assistant);
+        assert!(followed.iter().any(|row| row.contains("retrying")));
+        assert!(!followed.iter().any(|row| row.contains("new line")));
+    }
+
+    #[test]
+    fn test_retry_shows_in_the_prompt_until_the_reply_starts() {
+        let screen = |assistant: &TuiAssistant| -> String {
+            let area = Rect::new(0, 0, 60, 20);
+            let mut buf = Buffer::empty(area);
+            assistant.render(area, &mut buf);
+            (0..area.height)
+                .map(|y| (0..area.width).map(|x| buf[(x, y)].symbol()).collect::<String>())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut assistant = TuiAssistant::new();
+        let session_id = assistant.active_session_id();
+        assistant.push_user_message("why".to_string());
+        assistant.start_assistant_message();
+        assistant.handle_ai_update(AiUiUpdate::Status { session_id, text: "Retrying (1/3)…".to_string() });
+        assert!(screen(&assistant).contains("\n⋯ Retrying (1/3)…"));
+
+        // Typed input comes first
+        assistant.input.buffer = "next".to_string();
+        assert!(screen(&assistant).contains("\n⋯ next "));
+        assistant.input.buffer.clear();
+
+        assistant.handle_ai_update(AiUiUpdate::Chunk { session_id, text: "Because.".to_string() });
+        assert!(!screen(&assistant).contains("Retrying"));
+        // Nor does a status outlast the reply
+        assistant.handle_ai_update(AiUiUpdate::Status { session_id, text: "Retrying (2/3)…".to_string() });
+        assistant.handle_ai_update(AiUiUpdate::End { session_id, cached: false });
+        assert!(!screen(&assistant).contains("Retrying"));
+    }
+
+    #[test]
+    fn test_queued_messages_show_below_the_streaming_reply() {
+        let mut assistant = TuiAssistant::new();
+        assistant.push_user_message("explain tar".to_string());
+        assistant.start_assistant_message();
+        assistant.append_stream_chunk("tar packs files");
+        assistant.set_queued(vec!["and gzip".to_string()]);
+        let rows: Vec<String> = assistant.build_rendered_lines(40).iter().map(|l| l.to_string()).collect();
+        let reply = rows.iter().position(|row| row.contains("tar packs files"));
+        let queued = rows.iter().position(|row| row == "You (queued): and gzip");
+        assert!(reply.is_some() && queued > reply, "{:?}", rows);
+
+        // Chunks still go to the reply above it
+        assistant.append_stream_chunk(" into one");
+        assert!(matches!(assistant.messages().last(), Some(ChatMessage::Assistant { text, .. }) if text == "tar packs files into one"));
+        assistant.set_queued(Vec::new());
+        assert!(!assistant.build_rendered_lines(40).iter().any(|l| l.to_string().contains("queued")));
+    }
+
+    #[test]
+    fn test_setup_hint_takes_the_place_of_the_prompt() {
+        let last_rows = |assistant: &TuiAssistant| -> Vec<String> {
+            let area = Rect::new(0, 0, 40, 12);
+            let mut buf = Buffer::empty(area);
+            assistant.render(area, &mut buf);
+            (area.height - 2..area.height)
+                .map(|y| (0..area.width).map(|x| buf[(x, y)].symbol()).collect::<String>().trim_end().to_string())
+                .collect()
+        };
+        let mut assistant = TuiAssistant::new();
+        assistant.set_setup_hint(Some("No API key found — set OPENAI_API_KEY or edit ~/.config/rusty-term/config.toml".to_string()));
+        assert_eq!(last_rows(&assistant), ["No API key found — set OPENAI_API_KEY or", "edit ~/.config/rusty-term/config.toml"]);
+
+        // Typing still shows what is typed
+        assistant.input.buffer = "ls".to_string();
+        assert_eq!(last_rows(&assistant)[0], "> ls");
+        assistant.input.buffer.clear();
+        assistant.set_setup_hint(None);
+        assert_eq!(last_rows(&assistant)[0], ">");
+    }
+
+    #[test]
+    fn test_explanation_shows_above_the_card_of_its_reply() {
+        use crate::ai::session::ExplainOutputArgs;
+
+        let mut assistant = TuiAssistant::new();
+        let session_id = assistant.active_session_id();
+        assistant.push_user_message("what is this error?".to_string());
+        assistant.start_assistant_message();
+        let explanation = ExplainOutputArgs {
+            summary: "Permission was denied".to_string(),
+            probable_cause: "The script is not executable".to_string(),
+            suggested_next_step: "Make it executable".to_string(),
+        };
+        assistant.handle_ai_update(AiUiUpdate::Explanation { session_id, explanation });
+        assistant.handle_ai_update(AiUiUpdate::CommandSuggestion {
+            session_id,
+            commands: vec![("call_2".to_string(), "chmod +x run.sh".to_string(), String::new(), None)],
+            from_output: Vec::new(),
+        });
+        assistant.handle_ai_update(AiUiUpdate::End { session_id, cached: false });
+        assert!(!assistant.is_streaming());
+
+        let text: Vec<String> =
+            assistant.rendered_lines(&assistant.view.messages, 60).iter().map(|line| line.to_string()).collect();
+        let at = |wanted: &str| text.iter().position(|line| line.contains(wanted));
+        assert_eq!(at("▎ Explanation").map(|i| text[i + 1].as_str()), Some("▎ Permission was denied"));
+        assert!(at("▎ Cause: The script is not executable").is_some());
+        assert!(at("▎ Next: Make it executable") < at("chmod +x run.sh"));
+        // The reply can still be regenerated from its card
+        assert!(assistant.regenerable_reply().is_some());
+    }
+
+    #[test]
+    fn test_tiny_areas_render_and_leave_no_stale_caches() {
+        let populated = || {
+            let mut assistant = TuiAssistant::new();
+            assistant.start_assistant_message();
+            assistant.append_stream_chunk("Here is a listing.");
+            assistant.end_stream();
+            assistant.set_pending_commands(vec![("call_1".to_string(), "ls -la".to_string(), String::new(), None)]);
+            assistant
+        };
+        let render = |assistant: &TuiAssistant, area: Rect| {
+            let mut buf = Buffer::empty(area);
+            assistant.render(area, &mut buf);
+            buf
+        };
+        let normal = Rect::new(0, 0, 60, 20);
+
+        let assistant = populated();
+        render(&assistant, normal);
+        assert!(!assistant.cached_command_cards.borrow().is_empty());
+        for width in 0..=5 {
+            for height in 0..=5 {
+                render(&assistant, Rect::new(0, 0, width, height));
+            }
+        }
+        render(&assistant, Rect::new(0, 0, 0, 3));
+        assert!(assistant.cached_command_cards.borrow().is_empty());
+        assert!(assistant.cached_tab_positions.borrow().is_empty());
+        assert_eq!(assistant.cached_message_area.get(), Rect::default());
+        assert_eq!(assistant.max_scroll_offset.get(), 0);
+
+        let fresh = populated();
+        assert_eq!(render(&assistant, normal), render(&fresh, normal));
+        assert_eq!(assistant.cached_message_area.get(), fresh.cached_message_area.get());
+        assert_eq!(assistant.cached_command_cards.borrow().len(), fresh.cached_command_cards.borrow().len());
+    }
+}
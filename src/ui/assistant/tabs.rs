@@ -0,0 +1,382 @@
+//! The session tab bar: fitting the tabs into the pane, drawing them and
+//! telling which one a click landed on.
+
+use super::*;
+
+// Tab bar configuration constants
+const TAB_PADDING: usize = 2;       // " name " -> 2 spaces around name
+const TAB_SEPARATOR: usize = 1;     // Space between tabs
+const TAB_PLUS_BUTTON_WIDTH: usize = 4; // " + " with leading space
+
+impl TuiAssistant {
+    // ========================================================================
+    // Mouse Click Detection
+    // ========================================================================
+
+    /// Detect what was clicked in the tab bar.
+    ///
+    /// # Arguments
+    /// * `rel_col` - Column position relative to the assistant inner area
+    /// * `area_x` - X offset of the assistant inner area (for screen coordinate conversion)
+    ///
+    /// Returns a TabClickResult indicating what action to take.
+    pub fn get_tab_click_result(&self, screen_col: u16, area_x: u16) -> TabClickResult {
+        let tab_positions = self.cached_tab_positions.borrow();
+
+        // Convert screen column to absolute position for comparison
+        let abs_col = screen_col;
+
+        for hit in tab_positions.iter() {
+            let start = area_x + hit.start_x;
+            let end = area_x + hit.end_x;
+
+            if abs_col >= start && abs_col < end {
+                if hit.is_close_button {
+                    if let Some(id) = hit.session_id {
+                        return TabClickResult::CloseTab(id);
+                    }
+                } else if let Some(id) = hit.session_id {
+                    // Regular tab click - switch to it
+                    return TabClickResult::SwitchToTab(id);
+                } else {
+                    // "+" button
+                    return TabClickResult::NewTab;
+                }
+            }
+        }
+
+        TabClickResult::None
+    }
+}
+
+/// Generate a short tab name like "S1", "S2" from the tab name.
+/// Extracts the number from "Session N" names, or uses the provided index + 1.
+/// Other names (template sessions) are shortened to their first three characters.
+fn get_short_tab_name(name: &str, index: usize) -> String {
+    // Template sessions are named after the template: use its first letters
+    let Some(rest) = name.strip_prefix("Session ") else {
+        let short: String = name.chars().take(3).collect();
+        return if short.is_empty() { format!("S{}", index + 1) } else { short };
+    };
+    // Try to extract number from name (e.g., "Session 3" -> "3")
+    let num: String = rest.chars().filter(|c| c.is_ascii_digit()).collect();
+    if num.is_empty() {
+        format!("S{}", index + 1)
+    } else {
+        format!("S{}", num)
+    }
+}
+
+/// Calculate the display width of a tab.
+/// If `use_short` is true, uses short form (S1, S2); otherwise uses full name.
+fn calculate_tab_width(name: &str, index: usize, use_short: bool) -> usize {
+    let display_name = if use_short {
+        get_short_tab_name(name, index)
+    } else {
+        name.to_string()
+    };
+    TAB_PADDING + unicode_width::UnicodeWidthStr::width(display_name.as_str())
+}
+
+/// Calculate total width for tabs in a range.
+/// Active tab always uses full name, others use short form if `others_short` is true.
+fn calculate_tabs_width_mixed(
+    tabs: &[SessionTab],
+    range: std::ops::Range<usize>,
+    active_idx: usize,
+    others_short: bool,
+) -> usize {
+    let mut total = 0;
+    for (i, idx) in range.clone().enumerate() {
+        if i > 0 {
+            total += TAB_SEPARATOR;
+        }
+        let use_short = others_short && idx != active_idx;
+        total += calculate_tab_width(&tabs[idx].name, idx, use_short);
+    }
+    total
+}
+
+/// Check if all tabs fit with full names.
+fn all_tabs_fit_full(tabs: &[SessionTab], active_idx: usize, available_width: usize) -> bool {
+    calculate_tabs_width_mixed(tabs, 0..tabs.len(), active_idx, false) <= available_width
+}
+
+/// Check if all tabs fit with active full, others short.
+fn all_tabs_fit_mixed(tabs: &[SessionTab], active_idx: usize, available_width: usize) -> bool {
+    calculate_tabs_width_mixed(tabs, 0..tabs.len(), active_idx, true) <= available_width
+}
+
+/// Calculate visible tab range centered around active tab.
+/// Active tab uses full name, others use short form.
+/// Returns (start_idx, end_idx, hidden_left, hidden_right).
+fn calculate_visible_range(
+    tabs: &[SessionTab],
+    active_idx: usize,
+    available_width: usize,
+) -> (usize, usize, usize, usize) {
+    let num_tabs = tabs.len();
+    if num_tabs == 0 {
+        return (0, 0, 0, 0);
+    }
+
+    // Width of hidden indicators: "‹N " and " N›" (each ~3-4 chars)
+    let indicator_width = 4;
+
+    // Start with just the active tab (full name)
+    let mut start = active_idx;
+    let mut end = active_idx + 1;
+
+    // Helper to calculate current width including potential indicators
+    let calc_width = |s: usize, e: usize| -> usize {
+        let mut w = calculate_tabs_width_mixed(tabs, s..e, active_idx, true);
+        if s > 0 {
+            w += indicator_width;
+        }
+        if e < num_tabs {
+            w += indicator_width;
+        }
+        w
+    };
+
+    // Expand alternately left and right while we have space
+    loop {
+        let mut expanded = false;
+
+        // Try to expand right
+        if end < num_tabs {
+            let new_width = calc_width(start, end + 1);
+            if new_width <= available_width {
+                end += 1;
+                expanded = true;
+            }
+        }
+
+        // Try to expand left
+        if start > 0 {
+            let new_width = calc_width(start - 1, end);
+            if new_width <= available_width {
+                start -= 1;
+                expanded = true;
+            }
+        }
+
+        if !expanded {
+            break;
+        }
+    }
+
+    (start, end, start, num_tabs - end)
+}
+
+/// Render the session tab bar with overflow handling.
+///
+/// Strategy:
+/// 1. Try to fit all tabs with full names
+/// 2. If not, show active tab with full name, others with short form (S1, S2, ...)
+/// 3. If still doesn't fit, show only tabs around active one with hidden indicators
+pub(super) fn render_tab_bar(assistant: &TuiAssistant, area: Rect, buf: &mut Buffer) {
+    let mut spans = Vec::new();
+    let tabs = &assistant.session_tabs;
+    let num_tabs = tabs.len();
+
+    // Clear and prepare to track hit areas
+    let mut hit_areas: Vec<TabHitArea> = Vec::new();
+    let mut current_x: u16 = 0;
+
+    if num_tabs == 0 {
+        // Just show the "+" button
+        let plus_text = " + ";
+        hit_areas.push(TabHitArea {
+            start_x: current_x,
+            end_x: current_x + plus_text.len() as u16,
+            session_id: None,
+            is_close_button: false,
+        });
+        spans.push(Span::styled(plus_text, Style::default().fg(Color::Green)));
+        let line = Line::from(spans);
+        Paragraph::new(line).render(area, buf);
+        *assistant.cached_tab_positions.borrow_mut() = hit_areas;
+        return;
+    }
+
+    // Find the index of the active session
+    let active_idx = tabs
+        .iter()
+        .position(|t| t.id == assistant.active_session)
+        .unwrap_or(0);
+
+    // Calculate available width for tabs (excluding "+" button, and account for close button on active)
+    let total_width = area.width as usize;
+    // Active tab will have " x" suffix (2 chars), so account for that
+    let close_button_width = 2;
+    let available_for_tabs = total_width.saturating_sub(TAB_PLUS_BUTTON_WIDTH + close_button_width);
+
+    // Determine display mode:
+    // - Mode 0: All tabs with full names
+    // - Mode 1: Active full, others short (S1, S2, ...)
+    // - Mode 2: Only visible range around active, with hidden indicators
+    let (visible_start, visible_end, hidden_left, hidden_right, use_short_for_others) =
+        if all_tabs_fit_full(tabs, active_idx, available_for_tabs) {
+            // All tabs fit with full names
+            (0, num_tabs, 0, 0, false)
+        } else if all_tabs_fit_mixed(tabs, active_idx, available_for_tabs) {
+            // All tabs fit with active full, others short
+            (0, num_tabs, 0, 0, true)
+        } else {
+            // Need to hide some tabs
+            let (start, end, left, right) =
+                calculate_visible_range(tabs, active_idx, available_for_tabs);
+            (start, end, left, right, true)
+        };
+
+    // Render left hidden indicator
+    if hidden_left > 0 {
+        let indicator = format!("‹{} ", hidden_left);
+        current_x += indicator.width() as u16;
+        spans.push(Span::styled(
+            indicator,
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+
+    // Find next tab index for Tab hint (within visible range)
+    let next_idx_global = (active_idx + 1) % num_tabs;
+    let next_in_visible = if next_idx_global >= visible_start && next_idx_global < visible_end {
+        Some(next_idx_global)
+    } else {
+        None
+    };
+
+    // Render visible tabs
+    for (render_idx, tab_idx) in (visible_start..visible_end).enumerate() {
+        let tab = &tabs[tab_idx];
+
+        if render_idx > 0 {
+            spans.push(Span::raw(" "));
+            current_x += 1;
+        }
+
+        let is_active = tab.id == assistant.active_session;
+        let is_next = Some(tab_idx) == next_in_visible && num_tabs > 1;
+
+        // Add Tab hint before the next session (subtle indicator)
+        if is_next && !is_active {
+            spans.push(Span::styled("⇥", Style::default().fg(Color::DarkGray)));
+            current_x += 1;
+        }
+
+        let style = if is_active {
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Cyan)
+                .add_modifier(Modifier::BOLD)
+        } else if tab.pending {
+            // A suggestion there waits for a decision
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default().fg(Color::Gray)
+        };
+
+        // Active tab always shows full name, others may be shortened
+        let display_name = if is_active || !use_short_for_others {
+            tab.name.clone()
+        } else {
+            get_short_tab_name(&tab.name, tab_idx)
+        };
+
+        let tab_text = format!(" {} ", display_name);
+        let tab_text_width = tab_text.width() as u16;
+
+        // Track hit area for the tab itself
+        let tab_start_x = current_x;
+        spans.push(Span::styled(tab_text, style));
+        current_x += tab_text_width;
+
+        // For active tab, add close button "x"
+        if is_active {
+            let close_btn_start = current_x;
+            let close_style = Style::default()
+                .fg(Color::White)
+                .bg(Color::Rgb(180, 40, 40))
+                .add_modifier(Modifier::BOLD);
+            spans.push(Span::styled("×", close_style));
+            current_x += 1;
+
+            // Track tab area (excluding close button)
+            hit_areas.push(TabHitArea {
+                start_x: tab_start_x,
+                end_x: close_btn_start,
+                session_id: Some(tab.id),
+                is_close_button: false,
+            });
+
+            // Track close button area
+            hit_areas.push(TabHitArea {
+                start_x: close_btn_start,
+                end_x: current_x,
+                session_id: Some(tab.id),
+                is_close_button: true,
+            });
+        } else {
+            // Non-active tab - just the tab area
+            hit_areas.push(TabHitArea {
+                start_x: tab_start_x,
+                end_x: current_x,
+                session_id: Some(tab.id),
+                is_close_button: false,
+            });
+        }
+    }
+
+    // Render right hidden indicator
+    if hidden_right > 0 {
+        let indicator = format!(" {}›", hidden_right);
+        current_x += indicator.width() as u16;
+        spans.push(Span::styled(
+            indicator,
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+
+    // Add "+" button for new session (Ctrl+B,T in command mode)
+    spans.push(Span::raw(" "));
+    current_x += 1;
+
+    let plus_start = current_x;
+    let plus_text = " + ";
+    spans.push(Span::styled(plus_text, Style::default().fg(Color::Green)));
+    current_x += plus_text.len() as u16;
+
+    hit_areas.push(TabHitArea {
+        start_x: plus_start,
+        end_x: current_x,
+        session_id: None,
+        is_close_button: false,
+    });
+
+    let line = Line::from(spans);
+    let paragraph = Paragraph::new(line);
+    paragraph.render(area, buf);
+
+    // Highlight the hovered element
+    let hovered = hit_areas.iter().find(|hit| match assistant.hovered_tab {
+        TabClickResult::SwitchToTab(id) => hit.session_id == Some(id) && !hit.is_close_button,
+        TabClickResult::CloseTab(id) => hit.session_id == Some(id) && hit.is_close_button,
+        TabClickResult::NewTab => hit.session_id.is_none(),
+        TabClickResult::None => false,
+    });
+    if let Some(hit) = hovered {
+        let x = area.x + hit.start_x;
+        let width = hit.end_x.saturating_sub(hit.start_x).min((area.x + area.width).saturating_sub(x));
+        apply_hover_style(buf, Rect { x, y: area.y, width, height: 1 });
+    }
+
+    // Store hit areas for click detection
+    *assistant.cached_tab_positions.borrow_mut() = hit_areas;
+}
+
+/// Style applied on top of an interactive element under the mouse pointer.
+pub(super) fn apply_hover_style(buf: &mut Buffer, area: Rect) {
+    buf.set_style(area, Style::default().add_modifier(Modifier::UNDERLINED | Modifier::BOLD));
+}
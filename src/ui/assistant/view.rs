@@ -0,0 +1,1310 @@
+//! The active session as it is shown: the message list, how it scrolls
+//! and the sidebar drawn as a whole.
+
+use super::*;
+
+/// A suggestion waiting for a decision: (tool_call_id, command,
+/// explanation, cwd, findings).
+pub(super) type PendingCommand = (String, String, String, Option<String>, Vec<Finding>);
+
+/// The active session as it is shown: its messages, where the list is
+/// scrolled to and the suggestions waiting for a decision.
+#[derive(Default)]
+pub(super) struct SessionView {
+    /// Messages in UI rendering format, cached from the backend
+    pub(super) messages: Vec<ChatMessage>,
+    /// Scroll state (0 = at bottom, >0 = scrolled up by N lines)
+    pub(super) scroll_offset: usize,
+    /// Lines that arrived below the view while scrolled back
+    pub(super) unseen_lines: usize,
+    /// Index into messages of the first pending command card
+    pub(super) pending_command_idx: Option<usize>,
+    /// All pending commands from the AI
+    pub(super) pending_commands: Vec<PendingCommand>,
+    /// Currently displayed suggestion index (0-based, for cycling through suggestions)
+    pub(super) current_suggestion_idx: usize,
+    /// Tool call of the safer alternative among the pending commands
+    pub(super) safer_alternative: Option<String>,
+    /// Several suggestions picked to run in order, instead of one ('m')
+    pub(super) checklist: Option<Checklist>,
+    /// All suggestions listed at once to compare them ('c'); the one on the
+    /// card is highlighted
+    pub(super) comparing: bool,
+    /// Question being edited in the input ('e' in visual mode): sending
+    /// asks it in place of this one (0 is the first shown)
+    pub(super) editing: Option<usize>,
+    /// Indices into messages of the questions answered here, which the
+    /// session never saw
+    pub(super) local_questions: Vec<usize>,
+    /// Messages waiting for the reply streaming in, shown below everything
+    pub(super) queued: Vec<String>,
+}
+
+impl TuiAssistant {
+    // ========================================================================
+    // Messages
+    // ========================================================================
+
+    /// Get all messages in the current session
+    pub fn messages(&self) -> &[ChatMessage] {
+        &self.view.messages
+    }
+
+    /// Show `queued`, the messages waiting in the session's queue, below
+    /// the conversation.
+    pub fn set_queued(&mut self, queued: Vec<String>) {
+        self.arrive(|this| this.view.queued = queued);
+    }
+
+    /// Show the new summary text under the summary divider.
+    pub(super) fn set_summary(&mut self, new_text: String) {
+        for msg in &mut self.view.messages {
+            if let ChatMessage::Summary { text, .. } = msg {
+                *text = new_text.clone();
+            }
+        }
+    }
+
+    /// Show or hide the summary of the folded turns. Returns false if the
+    /// conversation has none.
+    pub fn toggle_summary(&mut self) -> bool {
+        let mut found = false;
+        for msg in &mut self.view.messages {
+            if let ChatMessage::Summary { expanded, .. } = msg {
+                *expanded = !*expanded;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Show or hide the latest reasoning block. Returns false if the
+    /// conversation has none.
+    pub fn toggle_reasoning(&mut self) -> bool {
+        match self.view.messages.iter_mut().rev().find(|m| matches!(m, ChatMessage::Reasoning { .. })) {
+            Some(ChatMessage::Reasoning { expanded, .. }) => {
+                *expanded = !*expanded;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Say under every reply that quotes its context what the underline
+    /// means, or stop. Returns whether it is said now.
+    pub fn toggle_quote_legend(&mut self) -> bool {
+        self.quote_legend = !self.quote_legend;
+        self.quote_legend
+    }
+
+    /// Show or hide the timing of the reply under the visual cursor (or of
+    /// the reply the card under it belongs to). Returns false if there is
+    /// no timing there.
+    pub fn toggle_timing_at_cursor(&mut self) -> bool {
+        let Some(idx) = self.message_at_visual_cursor() else {
+            return false;
+        };
+        let reply = self.view.messages[..=idx]
+            .iter()
+            .rposition(|m| {
+                !matches!(m, ChatMessage::CommandCard { .. } | ChatMessage::DraftCard { .. } | ChatMessage::Explanation { .. })
+            });
+        match reply.and_then(|reply| self.view.messages.get_mut(reply)) {
+            Some(ChatMessage::Assistant { timing: Some(_), timing_shown, .. }) => {
+                *timing_shown = !*timing_shown;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // ========================================================================
+    // Scrolling
+    // ========================================================================
+
+    /// Scroll the message list by delta lines (negative = up/back, positive = down/forward)
+    pub fn scroll(&mut self, delta: i16) {
+        if delta < 0 {
+            // Scrolling up (into history) - increase offset, but limit to max
+            let max_scroll = self.max_scroll_offset.get();
+            self.view.scroll_offset = (self.view.scroll_offset.saturating_add((-delta) as usize)).min(max_scroll);
+        } else {
+            // Scrolling down (toward latest) - decrease offset
+            self.view.scroll_offset = self.view.scroll_offset.saturating_sub(delta as usize);
+        }
+        self.view.unseen_lines = self.view.unseen_lines.min(self.view.scroll_offset);
+    }
+
+    /// The scrollbar is shown while scrolled back or in visual mode,
+    /// except in performance mode.
+    pub fn scrollbar_visible(&self) -> bool {
+        !self.performance && (self.is_scrolled() || self.is_visual_mode())
+    }
+
+    /// Scrollbar thumb for the last rendered message list.
+    fn scrollbar_thumb(&self) -> Option<scrollbar::Thumb> {
+        let area = self.cached_message_area.get();
+        let total = self.cached_total_lines.get();
+        let offset = self.view.scroll_offset.min(self.max_scroll_offset.get());
+        scrollbar::thumb(area.height, total, usize::from(area.height), offset)
+    }
+
+    /// Message list area from the last render (screen coordinates).
+    pub fn message_area(&self) -> Rect {
+        self.cached_message_area.get()
+    }
+
+    /// Row within the scrollbar track if `(col, row)` hits the shown scrollbar.
+    pub fn scrollbar_hit(&self, col: u16, row: u16) -> Option<u16> {
+        if !self.scrollbar_visible() {
+            return None;
+        }
+        self.scrollbar_thumb()?;
+        scrollbar::hit(self.cached_message_area.get(), col, row)
+    }
+
+    /// Scroll so the thumb is centered on `row` of the scrollbar track.
+    pub fn scroll_to_scrollbar_row(&mut self, row: u16) {
+        let area = self.cached_message_area.get();
+        let total = self.cached_total_lines.get();
+        if let Some(offset) = scrollbar::offset_at(area.height, total, usize::from(area.height), row) {
+            self.view.scroll_offset = offset.min(self.max_scroll_offset.get());
+            self.view.unseen_lines = self.view.unseen_lines.min(self.view.scroll_offset);
+        }
+    }
+
+    /// Scroll to the bottom of the message list, and follow what arrives
+    pub fn scroll_to_bottom(&mut self) {
+        self.view.scroll_offset = 0;
+        self.view.unseen_lines = 0;
+    }
+
+    /// Lines that arrived below the view while scrolled back.
+    pub fn unseen_lines(&self) -> usize {
+        self.view.unseen_lines
+    }
+
+    /// Apply `change`, which adds to (or rewrites) the conversation without
+    /// the user asking. At the bottom the view follows it; scrolled back,
+    /// the lines on screen stay where they are and what arrived below is
+    /// counted for the "new lines below" pill.
+    pub(super) fn arrive<R>(&mut self, change: impl FnOnce(&mut Self) -> R) -> R {
+        let width = self.cached_visible_width.get() as u16;
+        if self.view.scroll_offset == 0 || width == 0 {
+            let result = change(self);
+            self.scroll_to_bottom();
+            return result;
+        }
+        let before = self.build_rendered_lines(width).len();
+        let result = change(self);
+        let after = self.build_rendered_lines(width).len();
+        if after > before {
+            let grown = after - before;
+            self.view.scroll_offset += grown;
+            self.view.unseen_lines += grown;
+            // Until the next render the clamps must allow the new offset
+            self.max_scroll_offset.set(self.max_scroll_offset.get() + grown);
+            self.cached_total_lines.set(self.cached_total_lines.get() + grown);
+        } else {
+            let shrunk = before - after;
+            self.view.scroll_offset = self.view.scroll_offset.saturating_sub(shrunk).max(1);
+            self.view.unseen_lines = self.view.unseen_lines.saturating_sub(shrunk);
+        }
+        result
+    }
+
+    /// Get current scroll offset
+    pub fn scroll_offset(&self) -> usize {
+        self.view.scroll_offset
+    }
+
+    /// Check if we're scrolled back (not at bottom)
+    pub fn is_scrolled(&self) -> bool {
+        self.view.scroll_offset > 0
+    }
+
+    /// Check if there's a message currently being streamed (parts of a
+    /// full output on their way count)
+    pub fn is_streaming(&self) -> bool {
+        match self.view.messages.last() {
+            Some(ChatMessage::Assistant { is_streaming, .. }) => *is_streaming,
+            Some(ChatMessage::ContextParts { sending, .. }) => *sending,
+            _ => false,
+        }
+    }
+
+    // ========================================================================
+    // Rendered Lines
+    // ========================================================================
+
+    /// Rendered rows taken by reasoning blocks.
+    pub(super) fn reasoning_rows(&self, width: u16) -> Vec<std::ops::Range<usize>> {
+        self.view.messages
+            .iter()
+            .enumerate()
+            .filter(|(_, msg)| matches!(msg, ChatMessage::Reasoning { .. }))
+            .map(|(idx, _)| {
+                let start = self.rendered_lines(&self.view.messages[..idx], width).len();
+                start..self.rendered_lines(&self.view.messages[..=idx], width).len()
+            })
+            .collect()
+    }
+
+    /// Build rendered lines for text extraction (used by visual mode).
+    pub(super) fn build_rendered_lines(&self, width: u16) -> Vec<Line<'static>> {
+        let mut all_lines = self.rendered_lines(&self.view.messages, width);
+        if self.regenerable_reply().is_some() {
+            all_lines.push(regen_hint());
+        }
+        for text in &self.view.queued {
+            all_lines.extend(render_queued(text, width));
+        }
+        all_lines
+    }
+
+    /// Rendered lines of `messages`, the first messages of the conversation.
+    pub(super) fn rendered_lines(&self, messages: &[ChatMessage], width: u16) -> Vec<Line<'static>> {
+        let mut all_lines: Vec<Line<'static>> = Vec::new();
+
+        let refreshable = self.refreshable_reply();
+        for (msg_idx, msg) in messages.iter().enumerate() {
+            match msg {
+                ChatMessage::User { text } => {
+                    let wrapped = wrap_text_lines(text, width, "You: ");
+                    for (i, line) in wrapped.into_iter().enumerate() {
+                        if i == 0 {
+                            let line_str = line.to_string();
+                            let content = line_str.trim_start_matches("You: ").to_string();
+                            all_lines.push(Line::from(vec![
+                                Span::styled("You: ", Style::default().fg(Color::Green).bold()),
+                                Span::raw(content),
+                            ]));
+                        } else {
+                            all_lines.push(line);
+                        }
+                    }
+                    all_lines.push(Line::raw(""));
+                }
+                ChatMessage::Assistant { text, is_streaming, cached: true, timing, timing_shown, .. } => {
+                    all_lines.extend(render_tagged_reply(text, "cached", width));
+                    if refreshable == Some(msg_idx) {
+                        all_lines.push(refresh_hint());
+                    }
+                    all_lines.extend(timing.filter(|_| *timing_shown).map(|timing| timing_line(&timing)));
+                    if !text.is_empty() || *is_streaming {
+                        all_lines.push(Line::raw(""));
+                    }
+                }
+                ChatMessage::Assistant { text, is_streaming, timing, timing_shown, quotes, .. } => {
+                    let content = if *is_streaming && text.is_empty() {
+                        "...".to_string()
+                    } else if *is_streaming {
+                        format!("{}▌", text)
+                    } else {
+                        text.clone()
+                    };
+
+                    all_lines.extend(render_reply(&content, quotes, width));
+                    if self.quote_legend && !quotes.is_empty() {
+                        all_lines.push(quote_legend());
+                    }
+                    all_lines.extend(timing.filter(|_| *timing_shown).map(|timing| timing_line(&timing)));
+                    if !text.is_empty() || *is_streaming {
+                        all_lines.push(Line::raw(""));
+                    }
+                }
+                ChatMessage::Reasoning { text, expanded } => {
+                    all_lines.extend(render_reasoning(text, *expanded, width));
+                }
+                ChatMessage::CommandCard { command, command_start, status, .. } => {
+                    // Show pagination only for pending commands
+                    let pagination = if *status == CommandStatus::Pending {
+                        self.suggestion_pagination()
+                    } else {
+                        None
+                    };
+                    let prior_run = self.prior_run_for(&command[*command_start..], *status);
+                    let busy = self.busy_note_for(*status);
+                    match self.checklist_for(msg_idx) {
+                        Some(checklist) => all_lines.extend(render_checklist(&self.view.pending_commands, checklist, width)),
+                        None if self.comparing_for(msg_idx) => all_lines.extend(render_comparison(
+                            &self.view.pending_commands,
+                            self.view.current_suggestion_idx,
+                            width,
+                        )),
+                        None => all_lines.extend(render_command_card(msg, prior_run, busy, width, pagination)),
+                    }
+                    all_lines.push(Line::raw(""));
+                }
+                ChatMessage::DraftCard { command, explanation } => {
+                    all_lines.extend(render_draft_card(command.as_deref(), explanation.as_deref(), width));
+                    all_lines.push(Line::raw(""));
+                }
+                ChatMessage::Interrupted { partial } => {
+                    all_lines.extend(render_interrupted(*partial, msg_idx + 1 == self.view.messages.len(), width));
+                }
+                ChatMessage::Error { text } => {
+                    let wrapped = wrap_text_lines(text, width, "⚠ ");
+                    for (i, line) in wrapped.into_iter().enumerate() {
+                        if i == 0 {
+                            let line_str = line.to_string();
+                            let content = line_str.trim_start_matches("⚠ ").to_string();
+                            all_lines.push(Line::from(vec![
+                                Span::styled("⚠ ", Style::default().fg(Color::Red).bold()),
+                                Span::styled(content, Style::default().fg(Color::Red)),
+                            ]));
+                        } else {
+                            all_lines.push(Line::styled(
+                                line.to_string(),
+                                Style::default().fg(Color::Red),
+                            ));
+                        }
+                    }
+                    all_lines.push(Line::raw(""));
+                }
+                ChatMessage::Local { text } => {
+                    all_lines.extend(render_tagged_reply(text, "local", width));
+                    all_lines.push(Line::raw(""));
+                }
+                ChatMessage::Explanation { summary, probable_cause, suggested_next_step } => {
+                    all_lines.extend(render_explanation(summary, probable_cause, suggested_next_step, width));
+                }
+                ChatMessage::Superseded { messages } => {
+                    all_lines.extend(render_superseded(messages, width));
+                }
+                ChatMessage::Summary { text, suggestions, expanded } => {
+                    all_lines.extend(render_summary(text, suggestions, *expanded, width));
+                }
+                ChatMessage::ContextParts { command, sent, parts, sending } => {
+                    all_lines.extend(render_context_parts(command, *sent, *parts, *sending, width));
+                }
+            }
+        }
+
+        all_lines
+    }
+}
+
+impl Widget for &TuiAssistant {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // Nothing is drawn, so nothing can be clicked or scrolled either
+        if area.is_empty() {
+            self.clear_layout_caches();
+            return;
+        }
+
+        // Calculate dynamic input box height based on content
+        let input_box_height = self.calculate_input_box_height(area.height, area.width);
+
+        // Split into five regions: tabs (1 line), messages (flexible),
+        // context chips and dropped files (1 line each if any), input (dynamic)
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),                     // Tab bar
+                Constraint::Min(1),                        // Message list
+                Constraint::Length(self.chip_row_height()), // Context chips
+                Constraint::Length(self.file_row_height()), // Dropped files
+                Constraint::Length(input_box_height),      // Input box (dynamic)
+            ])
+            .split(area);
+
+        // Render each section
+        render_tab_bar(self, chunks[0], buf);
+        render_message_list(self, chunks[1], buf);
+        render_context_chips(self, chunks[2], buf);
+        render_file_chips(self, chunks[3], buf);
+        render_input_box(self, chunks[4], buf);
+
+        if let Some(picker) = &self.template_picker {
+            render_picker(&picker.items, picker.selected, " New session ", chunks[1], buf);
+        }
+        if let Some(picker) = &self.prompt_picker {
+            render_picker(&picker.items, picker.selected, " System prompt ", chunks[1], buf);
+        }
+        if let Some(selected) = self.regen_menu {
+            render_picker(&TuiAssistant::regen_menu_items(), selected, " Regenerate ", chunks[1], buf);
+        }
+        if let Some(picker) = &self.attach_picker {
+            let items: Vec<String> = picker
+                .records
+                .iter()
+                .map(|(record, description)| format!("{} · {}", record.command_line, description))
+                .collect();
+            render_picker(&items, picker.selected, " Send full output ", chunks[1], buf);
+        }
+        if self.performance {
+            crate::ui::performance::simplify(buf, area);
+        }
+    }
+}
+
+/// Render a reply that did not come from a live AI request: like an AI
+/// reply, with a tag such as "local" or "cached".
+fn render_tagged_reply(text: &str, tag: &str, width: u16) -> Vec<Line<'static>> {
+    let prefix = format!("AI ({}): ", tag);
+    let mut lines = wrap_text_lines(text, width, &prefix);
+    if let Some(first) = lines.first_mut() {
+        let content = first.to_string().trim_start_matches(prefix.as_str()).to_string();
+        *first = Line::from(vec![
+            Span::styled("AI ", Style::default().fg(Color::Cyan).bold()),
+            Span::styled(format!("({})", tag), Style::default().fg(Color::DarkGray)),
+            Span::styled(": ", Style::default().fg(Color::Cyan).bold()),
+            Span::raw(content),
+        ]);
+    }
+    lines
+}
+
+/// How the stretches of a reply quoted from its context are shown.
+fn quoted_style() -> Style {
+    Style::default().add_modifier(Modifier::UNDERLINED)
+}
+
+/// Render a reply after the "AI: " prefix: `content` wrapped to `width`,
+/// with the byte ranges in `quotes` underlined. Wrapping only drops
+/// whitespace, so each wrapped line is found in `content` after the one
+/// before it; a line that is not (or quotes that don't fall on its
+/// characters) is shown plain.
+fn render_reply(content: &str, quotes: &[std::ops::Range<usize>], width: u16) -> Vec<Line<'static>> {
+    const PREFIX: &str = "AI: ";
+    let indent = " ".repeat(PREFIX.len());
+    let mut lines = Vec::new();
+    // Where the next line is looked for in `content`
+    let mut from = 0;
+    for (i, line) in wrap_text_lines(content, width, PREFIX).into_iter().enumerate() {
+        let line_str = line.to_string();
+        let (lead, body) = match i {
+            0 => (Span::styled(PREFIX, Style::default().fg(Color::Cyan).bold()), line_str.strip_prefix(PREFIX)),
+            _ => (Span::raw(indent.clone()), line_str.strip_prefix(indent.as_str())),
+        };
+        let Some(body) = body else {
+            lines.push(line);
+            continue;
+        };
+        let mut spans = vec![lead];
+        match content.get(from..).and_then(|rest| rest.find(body)).filter(|_| !body.is_empty()) {
+            Some(offset) => {
+                let start = from + offset;
+                from = start + body.len();
+                spans.extend(quoted_spans(content, start..from, quotes));
+            }
+            None => spans.push(Span::raw(body.to_string())),
+        }
+        lines.push(Line::from(spans));
+    }
+    lines
+}
+
+/// The text of `content` in `range`, split where `quotes` start and end,
+/// the quoted parts underlined.
+fn quoted_spans(content: &str, range: std::ops::Range<usize>, quotes: &[std::ops::Range<usize>]) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    let mut at = range.start;
+    for quote in quotes.iter().filter(|quote| quote.start < range.end && quote.end > range.start) {
+        let start = quote.start.max(at);
+        let end = quote.end.min(range.end);
+        let (Some(before), Some(quoted)) = (content.get(at..start), content.get(start..end)) else {
+            continue;
+        };
+        if !before.is_empty() {
+            spans.push(Span::raw(before.to_string()));
+        }
+        spans.push(Span::styled(quoted.to_string(), quoted_style()));
+        at = end;
+    }
+    if let Some(rest) = content.get(at..range.end).filter(|rest| !rest.is_empty()) {
+        spans.push(Span::raw(rest.to_string()));
+    }
+    spans
+}
+
+/// Line under a reply with quotes, when asked for (command mode `u`).
+fn quote_legend() -> Line<'static> {
+    let grey = Style::default().fg(Color::DarkGray);
+    Line::from(vec![
+        Span::styled("  ", grey),
+        Span::styled("underlined", grey.patch(quoted_style())),
+        Span::styled(": quoted verbatim from your context", grey),
+    ])
+}
+
+/// Hint under a cached reply that can still be asked again.
+fn refresh_hint() -> Line<'static> {
+    Line::from(Span::styled(
+        "[Ctrl+R] Ask again without the cache",
+        Style::default().fg(Color::DarkGray),
+    ))
+}
+
+/// Where the time of a reply's request went, shown under it on demand.
+fn timing_line(timing: &RequestTiming) -> Line<'static> {
+    Line::from(Span::styled(format!("  {}", timing), Style::default().fg(Color::DarkGray)))
+}
+
+/// Clickable hint under the reply that can be regenerated.
+pub(super) const REGEN_HINT: &str = "[↻ Regenerate]";
+
+/// Hint under the reply that can be regenerated.
+fn regen_hint() -> Line<'static> {
+    Line::from(Span::styled(REGEN_HINT, Style::default().fg(Color::DarkGray)))
+}
+
+/// Pill at the bottom of the message list counting lines that arrived
+/// while scrolled back.
+fn new_lines_pill(count: usize) -> String {
+    format!(" ↓ {} new {} below ", count, if count == 1 { "line" } else { "lines" })
+}
+
+/// Clickable divider where older turns were folded into a summary.
+pub(super) const SUMMARY_DIVIDER: &str = "· earlier conversation summarized ·";
+
+/// Render the summary divider, and the summary under it if `expanded`,
+/// followed by the commands suggested in the summarized turns.
+fn render_summary(text: &str, suggestions: &[String], expanded: bool, width: u16) -> Vec<Line<'static>> {
+    let grey = Style::default().fg(Color::DarkGray);
+    let mut lines = vec![Line::from(Span::styled(SUMMARY_DIVIDER, grey))];
+    if expanded {
+        let text = if text.is_empty() { "(not summarized yet)" } else { text };
+        let mut text = text.to_string();
+        if !suggestions.is_empty() {
+            text.push_str("\n\nSuggested earlier:\n");
+            text.push_str(&suggestions.join("\n"));
+        }
+        lines.extend(
+            wrap_text_lines(&text, width, "  ")
+                .into_iter()
+                .map(|line| Line::from(line.spans.into_iter().map(|span| span.style(grey)).collect::<Vec<_>>())),
+        );
+    }
+    lines.push(Line::raw(""));
+    lines
+}
+
+/// Render a reasoning block: a dim line saying how long it is, and the
+/// reasoning itself under it when expanded.
+fn render_reasoning(text: &str, expanded: bool, width: u16) -> Vec<Line<'static>> {
+    let grey = Style::default().fg(Color::DarkGray);
+    let (marker, label) = if expanded { ("▾", "thinking") } else { ("▸", "thinking…") };
+    let mut lines = vec![Line::from(Span::styled(
+        format!("{} {} ({})", marker, label, reasoning::describe_length(text)),
+        grey,
+    ))];
+    if expanded {
+        lines.extend(
+            wrap_text_lines(text, width, "  ")
+                .into_iter()
+                .map(|line| Line::from(line.spans.into_iter().map(|span| span.style(grey)).collect::<Vec<_>>())),
+        );
+    }
+    lines.push(Line::raw(""));
+    lines
+}
+
+/// Render the folded note on a full output sent in parts: the progress
+/// while they are on their way, then how many went.
+fn render_context_parts(command: &str, sent: usize, parts: usize, sending: bool, width: u16) -> Vec<Line<'static>> {
+    let text = if sending {
+        format!("sending context {}/{}…", sent, parts)
+    } else if sent < parts {
+        format!("{}/{} parts of the output of `{}` sent, stopped", sent, parts, command)
+    } else if parts == 1 {
+        format!("full output of `{}` sent", command)
+    } else {
+        format!("full output of `{}` sent in {} parts", command, parts)
+    };
+    let grey = Style::default().fg(Color::DarkGray);
+    let mut lines: Vec<Line<'static>> = wrap_text_lines(&text, width, "· ")
+        .into_iter()
+        .map(|line| Line::from(line.spans.into_iter().map(|span| span.style(grey)).collect::<Vec<_>>()))
+        .collect();
+    lines.push(Line::raw(""));
+    lines
+}
+
+/// Render what the explain_output tool said about some output: the
+/// summary, then the cause and the next step, behind a magenta bar that
+/// sets it apart from replies and command cards.
+fn render_explanation(summary: &str, cause: &str, next_step: &str, width: u16) -> Vec<Line<'static>> {
+    let magenta = Style::default().fg(Color::Magenta);
+    let bar = Span::styled("▎ ", magenta);
+    let inner = width.saturating_sub(2);
+    let mut lines = vec![Line::from(vec![bar.clone(), Span::styled("Explanation", magenta.bold())])];
+    for (text, prefix) in [(summary, ""), (cause, "Cause: "), (next_step, "Next: ")] {
+        for line in wrap_text_lines(text, inner, prefix) {
+            let mut spans = vec![bar.clone()];
+            spans.extend(line.spans);
+            lines.push(Line::from(spans));
+        }
+    }
+    lines.push(Line::raw(""));
+    lines
+}
+
+/// Render the note under a reply that ended before it was complete: in
+/// yellow when the connection dropped, grey at the length limit. The key
+/// that continues it only works while it is the latest message, so it is
+/// named only then (`latest`).
+fn render_interrupted(partial: bool, latest: bool, width: u16) -> Vec<Line<'static>> {
+    let (text, prefix, style) = match partial {
+        true => ("response interrupted", "⚠ ", Style::default().fg(Color::Yellow)),
+        false => ("response reached the length limit", "· ", Style::default().fg(Color::DarkGray)),
+    };
+    let text = match latest {
+        true => format!("{} — Ctrl+G continues it", text),
+        false => text.to_string(),
+    };
+    let mut lines: Vec<Line<'static>> = wrap_text_lines(&text, width, prefix)
+        .into_iter()
+        .map(|line| Line::from(line.spans.into_iter().map(|span| span.style(style)).collect::<Vec<_>>()))
+        .collect();
+    lines.push(Line::raw(""));
+    lines
+}
+
+/// Render a message waiting in the queue: greyed, with a "(queued)" tag
+/// until the reply before it ends and it is sent.
+fn render_queued(text: &str, width: u16) -> Vec<Line<'static>> {
+    let prefix = "You (queued): ";
+    let grey = Style::default().fg(Color::DarkGray);
+    let mut lines: Vec<Line<'static>> = wrap_text_lines(text, width, prefix).into_iter().map(|line| line.style(grey)).collect();
+    if let Some(first) = lines.first_mut() {
+        let content = first.to_string().trim_start_matches(prefix).to_string();
+        *first = Line::from(vec![
+            Span::styled("You ", Style::default().fg(Color::Green).bold()),
+            Span::styled("(queued)", grey),
+            Span::styled(": ", Style::default().fg(Color::Green).bold()),
+            Span::styled(content, grey),
+        ]);
+    }
+    lines.push(Line::raw(""));
+    lines
+}
+
+/// Render a superseded question and reply: all greyed out, with a
+/// "superseded" tag, and cards showing how they were left.
+fn render_superseded(messages: &[ChatMessage], width: u16) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    for msg in messages {
+        match msg {
+            ChatMessage::User { text } => {
+                lines.extend(wrap_text_lines(text, width, "You (superseded): "));
+                lines.push(Line::raw(""));
+            }
+            ChatMessage::Assistant { text, .. } if !text.is_empty() => {
+                lines.extend(wrap_text_lines(text, width, "AI (superseded): "));
+                lines.push(Line::raw(""));
+            }
+            ChatMessage::CommandCard { .. } => {
+                lines.extend(render_command_card(msg, None, None, width, None));
+                lines.push(Line::raw(""));
+            }
+            ChatMessage::Explanation { summary, probable_cause, suggested_next_step } => {
+                lines.extend(render_explanation(summary, probable_cause, suggested_next_step, width));
+            }
+            _ => {}
+        }
+    }
+    let grey = Style::default().fg(Color::DarkGray);
+    lines
+        .into_iter()
+        .map(|line| Line::from(line.spans.into_iter().map(|span| span.style(grey)).collect::<Vec<_>>()))
+        .collect()
+}
+
+/// Wrap text to fit within a given width, returning multiple lines.
+/// Uses the `textwrap` crate for intelligent word-boundary wrapping.
+///
+/// This function handles text wrapping to ensure accurate line counting
+/// for scrolling calculations. We can't use Paragraph.wrap() because:
+///
+/// 1. Paragraph.wrap() only executes during render(), but we need line counts BEFORE rendering
+/// 2. Ratatui doesn't provide a public API to pre-calculate wrapped line counts
+/// 3. Using textwrap gives us smart word-boundary wrapping and precise line counting
+fn wrap_text_lines(text: &str, width: u16, prefix: &str) -> Vec<Line<'static>> {
+    if width == 0 {
+        return vec![Line::from(text.to_string())];
+    }
+
+    let mut result_lines = Vec::new();
+    let prefix_width = unicode_width::UnicodeWidthStr::width(prefix);
+
+    // Split by manual newlines first
+    let paragraphs: Vec<&str> = text.split('\n').collect();
+
+    for (para_idx, paragraph) in paragraphs.iter().enumerate() {
+        if para_idx == 0 {
+            // First paragraph: first line has prefix, subsequent lines are indented
+            if paragraph.is_empty() {
+                result_lines.push(Line::from(prefix.to_string()));
+            } else {
+                let first_line_width = (width as usize).saturating_sub(prefix_width);
+                let wrapped = textwrap::wrap(paragraph, first_line_width.max(10));
+
+                for (i, line) in wrapped.iter().enumerate() {
+                    if i == 0 {
+                        // First line with prefix
+                        result_lines.push(Line::from(format!("{}{}", prefix, line)));
+                    } else {
+                        // Continuation lines with indentation
+                        result_lines.push(Line::from(format!("{:width$}{}", "", line, width = prefix_width)));
+                    }
+                }
+            }
+        } else {
+            // Subsequent paragraphs (after manual newlines): all lines are indented
+            if paragraph.is_empty() {
+                // Empty line (from consecutive newlines)
+                result_lines.push(Line::from(format!("{:width$}", "", width = prefix_width)));
+            } else {
+                let wrap_width = (width as usize).saturating_sub(prefix_width);
+                let wrapped = textwrap::wrap(paragraph, wrap_width.max(10));
+                for line in wrapped {
+                    result_lines.push(Line::from(format!("{:width$}{}", "", line, width = prefix_width)));
+                }
+            }
+        }
+    }
+
+    // Ensure we have at least one line
+    if result_lines.is_empty() {
+        result_lines.push(Line::from(prefix.to_string()));
+    }
+
+    result_lines
+}
+
+/// Render the message list area
+pub(super) fn render_message_list(assistant: &TuiAssistant, area: Rect, buf: &mut Buffer) {
+    if area.height == 0 || area.width == 0 {
+        assistant.clear_message_list_caches();
+        return;
+    }
+
+    // Build all lines from messages and track command card positions
+    let mut all_lines: Vec<Line> = Vec::new();
+    // Track: (message_idx, start_line_idx, card_height, is_pending, layout)
+    let mut card_line_ranges: Vec<(usize, usize, usize, bool, CardLayout)> = Vec::new();
+    let refreshable = assistant.refreshable_reply();
+    // Line of the summary divider, if there is one
+    let mut summary_line = None;
+
+    for (msg_idx, msg) in assistant.view.messages.iter().enumerate() {
+        match msg {
+            ChatMessage::User { text } => {
+                // Manually wrap user message text
+                let wrapped = wrap_text_lines(text, area.width, "You: ");
+                for (i, line) in wrapped.into_iter().enumerate() {
+                    if i == 0 {
+                        // First line with styled prefix
+                        let line_str = line.to_string();
+                        let content = line_str.trim_start_matches("You: ").to_string();
+                        all_lines.push(Line::from(vec![
+                            Span::styled("You: ", Style::default().fg(Color::Green).bold()),
+                            Span::raw(content),
+                        ]));
+                    } else {
+                        // Continuation lines
+                        all_lines.push(line);
+                    }
+                }
+                all_lines.push(Line::raw("")); // Empty line after message
+            }
+            ChatMessage::Assistant { text, is_streaming, cached: true, timing, timing_shown, .. } => {
+                all_lines.extend(render_tagged_reply(text, "cached", area.width));
+                if refreshable == Some(msg_idx) {
+                    all_lines.push(refresh_hint());
+                }
+                all_lines.extend(timing.filter(|_| *timing_shown).map(|timing| timing_line(&timing)));
+                if !text.is_empty() || *is_streaming {
+                    all_lines.push(Line::raw("")); // Empty line after message
+                }
+            }
+            ChatMessage::Assistant { text, is_streaming, timing, timing_shown, quotes, .. } => {
+                // Prepare content with streaming indicator
+                let content = if *is_streaming && text.is_empty() {
+                    "...".to_string()
+                } else if *is_streaming {
+                    format!("{}▌", text) // Add cursor for streaming
+                } else {
+                    text.clone()
+                };
+
+                // Manually wrap assistant message text, quotes underlined
+                all_lines.extend(render_reply(&content, quotes, area.width));
+                if assistant.quote_legend && !quotes.is_empty() {
+                    all_lines.push(quote_legend());
+                }
+                all_lines.extend(timing.filter(|_| *timing_shown).map(|timing| timing_line(&timing)));
+                // Only add empty line if message has content (skip for empty placeholder before command cards)
+                if !text.is_empty() || *is_streaming {
+                    all_lines.push(Line::raw("")); // Empty line after message
+                }
+            }
+            ChatMessage::CommandCard { command, command_start, status, .. } => {
+                // Show pagination only for pending commands
+                let pagination = if *status == CommandStatus::Pending {
+                    assistant.suggestion_pagination()
+                } else {
+                    None
+                };
+                let start_line = all_lines.len();
+                let is_pending = *status == CommandStatus::Pending;
+                let prior_run = assistant.prior_run_for(&command[*command_start..], *status);
+                let busy = assistant.busy_note_for(*status);
+                let checklist = assistant.checklist_for(msg_idx);
+                let comparing = assistant.comparing_for(msg_idx);
+                let card_lines = match checklist {
+                    Some(checklist) => render_checklist(&assistant.view.pending_commands, checklist, area.width),
+                    None if comparing => render_comparison(
+                        &assistant.view.pending_commands,
+                        assistant.view.current_suggestion_idx,
+                        area.width,
+                    ),
+                    None => render_command_card(msg, prior_run, busy, area.width, pagination),
+                };
+                let card_height = card_lines.len();
+                all_lines.extend(card_lines);
+                all_lines.push(Line::raw("")); // Empty line after card
+
+                // Track this card's position (include pagination info for button hit area calculation)
+                let layout = match checklist {
+                    Some(checklist) => CardLayout::Checklist(checklist.rows().len()),
+                    None if comparing => CardLayout::Compare(assistant.view.pending_commands.len()),
+                    None if pagination.is_some() => CardLayout::Paginated,
+                    None => CardLayout::Single,
+                };
+                card_line_ranges.push((msg_idx, start_line, card_height, is_pending, layout));
+            }
+            ChatMessage::DraftCard { command, explanation } => {
+                // No buttons and not tracked for clicks: a draft can't be acted on
+                all_lines.extend(render_draft_card(command.as_deref(), explanation.as_deref(), area.width));
+                all_lines.push(Line::raw("")); // Empty line after card
+            }
+            ChatMessage::Interrupted { partial } => {
+                let latest = msg_idx + 1 == assistant.view.messages.len();
+                all_lines.extend(render_interrupted(*partial, latest, area.width));
+            }
+            ChatMessage::Error { text } => {
+                // Render error message with distinct styling
+                let wrapped = wrap_text_lines(text, area.width, "⚠ ");
+                for (i, line) in wrapped.into_iter().enumerate() {
+                    if i == 0 {
+                        let line_str = line.to_string();
+                        let content = line_str.trim_start_matches("⚠ ").to_string();
+                        all_lines.push(Line::from(vec![
+                            Span::styled("⚠ ", Style::default().fg(Color::Red).bold()),
+                            Span::styled(content, Style::default().fg(Color::Red)),
+                        ]));
+                    } else {
+                        all_lines.push(Line::styled(
+                            line.to_string(),
+                            Style::default().fg(Color::Red),
+                        ));
+                    }
+                }
+                all_lines.push(Line::raw("")); // Empty line after error
+            }
+            ChatMessage::Local { text } => {
+                all_lines.extend(render_tagged_reply(text, "local", area.width));
+                all_lines.push(Line::raw("")); // Empty line after message
+            }
+            ChatMessage::Explanation { summary, probable_cause, suggested_next_step } => {
+                all_lines.extend(render_explanation(summary, probable_cause, suggested_next_step, area.width));
+            }
+            ChatMessage::Superseded { messages } => {
+                all_lines.extend(render_superseded(messages, area.width));
+            }
+            ChatMessage::Reasoning { text, expanded } => {
+                all_lines.extend(render_reasoning(text, *expanded, area.width));
+            }
+            ChatMessage::Summary { text, suggestions, expanded } => {
+                summary_line = Some(all_lines.len());
+                all_lines.extend(render_summary(text, suggestions, *expanded, area.width));
+            }
+            ChatMessage::ContextParts { command, sent, parts, sending } => {
+                all_lines.extend(render_context_parts(command, *sent, *parts, *sending, area.width));
+            }
+        }
+    }
+    let regen_hint_line = assistant.regenerable_reply().map(|_| {
+        all_lines.push(regen_hint());
+        all_lines.len() - 1
+    });
+    for text in &assistant.view.queued {
+        all_lines.extend(render_queued(text, area.width));
+    }
+
+    // Calculate scroll offset (0 = at bottom, >0 = scrolled up)
+    let total_lines = all_lines.len();
+    let visible_lines = area.height as usize;
+
+    // Clamp scroll_offset to valid range
+    let max_scroll = total_lines.saturating_sub(visible_lines);
+
+    // Cache values for visual mode
+    assistant.max_scroll_offset.set(max_scroll);
+    assistant.cached_total_lines.set(total_lines);
+    assistant.cached_visible_width.set(area.width as usize);
+    assistant.cached_message_area.set(area);
+
+    let effective_scroll = assistant.view.scroll_offset.min(max_scroll);
+
+    // Calculate which lines to show
+    // When scroll_offset = 0, we show the last N lines (at bottom)
+    // When scroll_offset = max, we show the first N lines (at top)
+    let skip = total_lines.saturating_sub(visible_lines + effective_scroll);
+
+    // Get visual mode state for highlighting
+    let visual_cursor_pos = assistant.get_visual_cursor_screen_pos();
+    let selection_range = assistant.visual_state.as_ref().and_then(|v| v.selection_range());
+    let selection_mode = assistant.visual_state.as_ref().map(|v| v.get_selection_mode()).unwrap_or(SelectionMode::None);
+
+    // Render with visual mode support
+    let visible: Vec<Line> = all_lines.into_iter().skip(skip).take(visible_lines).collect();
+
+    // Build line widths for selection clamping (for Line mode)
+    // We collect the effective width (trimmed) for each visible line's content row
+    let line_widths: Vec<usize> = visible.iter().map(|line| {
+        let line_text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+        line_text.trim_end().chars().count()
+    }).collect();
+
+    // Render lines with visual mode highlighting
+    for (screen_row, line) in visible.iter().enumerate() {
+        if screen_row >= area.height as usize {
+            break;
+        }
+
+        let y = area.y + screen_row as u16;
+        let mut x = area.x;
+        let mut col: usize = 0;
+
+        for span in &line.spans {
+            for c in span.content.chars() {
+                if x >= area.x + area.width {
+                    break;
+                }
+
+                let char_width = unicode_width::UnicodeWidthChar::width(c).unwrap_or(1) as u16;
+
+                // Determine style with visual mode modifications
+                let mut style = span.style;
+
+                // Check if this cell is the visual cursor
+                let is_cursor = visual_cursor_pos.is_some_and(|(cr, cc)| cr == screen_row && cc == col);
+
+                // Check if this cell is in the selection range
+                let is_selected = selection_range.is_some_and(|(start, end)| {
+                    let content_row = assistant.screen_row_to_content_row(screen_row);
+                    is_in_selection_with_mode(
+                        content_row,
+                        col,
+                        start,
+                        end,
+                        selection_mode,
+                        |r| {
+                            // Map content row to screen row to get the line width
+                            let visible_top = total_lines.saturating_sub(visible_lines + effective_scroll);
+                            if r >= visible_top && r < visible_top + line_widths.len() {
+                                line_widths[r - visible_top]
+                            } else {
+                                // Row not in visible area, use cached width as fallback
+                                assistant.cached_visible_width.get()
+                            }
+                        },
+                    )
+                });
+
+                if is_cursor {
+                    // Visual cursor: blue background, white foreground
+                    style = Style::default().fg(Color::White).bg(Color::Blue);
+                } else if is_selected {
+                    // Selection: blue background, white foreground
+                    style = Style::default().fg(Color::White).bg(Color::Blue);
+                }
+
+                if let Some(cell) = buf.cell_mut((x, y)) {
+                    cell.set_char(c).set_style(style);
+                }
+                x += char_width;
+                col += 1;
+            }
+        }
+
+        // If cursor is on this row but beyond the rendered content, render it
+        if let Some((cursor_row, cursor_col)) = visual_cursor_pos
+            && cursor_row == screen_row && cursor_col >= col
+        {
+            let cursor_x = area.x + cursor_col as u16;
+            if cursor_x < area.x + area.width {
+                let style = Style::default().fg(Color::White).bg(Color::Blue);
+                if let Some(cell) = buf.cell_mut((cursor_x, y)) {
+                    cell.set_char(' ').set_style(style);
+                }
+            }
+        }
+    }
+
+    // Scrollbar overlays the rightmost column
+    if assistant.scrollbar_visible()
+        && let Some(thumb) = scrollbar::thumb(area.height, total_lines, visible_lines, effective_scroll)
+    {
+        scrollbar::render(area, buf, thumb);
+    }
+
+    // Calculate command card hit areas for mouse click detection
+    // Convert content line indices to screen coordinates
+    let mut command_card_hits: Vec<CommandCardHitArea> = Vec::new();
+
+    for (msg_idx, start_line, card_height, is_pending, layout) in card_line_ranges {
+        // Check if any part of this card is visible
+        let card_end_line = start_line + card_height;
+
+        // Skip lines before visible area
+        if card_end_line <= skip {
+            continue;
+        }
+        // Skip lines after visible area
+        if start_line >= skip + visible_lines {
+            continue;
+        }
+
+        // Calculate screen Y coordinates (relative to area)
+        let screen_start_y = if start_line >= skip {
+            (start_line - skip) as u16
+        } else {
+            0
+        };
+        let screen_end_y = ((card_end_line - skip).min(visible_lines)) as u16;
+
+        // Only track button positions for pending cards
+        let (button_y, next_btn, execute_btn, cancel_btn, multi_btn) = if is_pending {
+            // Card layout (with verdict line):
+            // Line 0: Top border
+            // Line 1: Verdict line
+            // Already-ran note (optional)
+            // Explanation lines (optional)
+            // Command line
+            // Status/action line with buttons
+            // Bottom border
+            //
+            // Button line is always the second-to-last line (card_height - 2)
+            let button_line_offset = card_height.saturating_sub(2);
+            let button_screen_y = screen_start_y + button_line_offset as u16;
+
+            if button_screen_y < screen_end_y {
+                // Button positions based on actual rendered spans:
+                // " │" = 2 chars
+                //
+                // With pagination:
+                //   " [Ctrl+A] Next " (15 chars at 2-16)
+                //   " " (1 char)
+                //   " [Ctrl+Y] Execute " (18 chars at 18-35)
+                //   " " (1 char)
+                //   " [Ctrl+N] Cancel " (17 chars at 37-53)
+                //   " " (1 char)
+                //   " [m] Multi " (11 chars at 55-65)
+                //   " " (1 char)
+                //   " [c] Compare " (13 chars at 67-79)
+                //
+                // Without pagination:
+                //   " [Ctrl+Y] Execute " (18 chars at 2-19)
+                //   " " (1 char)
+                //   " [Ctrl+N] Cancel " (17 chars at 21-37)
+                //
+                // Checklist:
+                //   " [Ctrl+Y] Run selected " (23 chars at 2-24)
+                //   " " (1 char)
+                //   " [Ctrl+N] Cancel " (17 chars at 26-42)
+                //
+                // Compare:
+                //   " [Ctrl+Y] Run highlighted " (26 chars at 2-27)
+                //   " " (1 char)
+                //   " [Ctrl+N] Cancel " (17 chars at 29-45)
+                let (next_btn, exec_start, exec_end, cancel_start, cancel_end, multi_btn) = match layout {
+                    // Next button at 2-16, Execute after Next, Cancel after Execute
+                    CardLayout::Paginated => (Some((2u16, 17u16)), 18u16, 36u16, 37u16, 54u16, Some((55u16, 66u16))),
+                    // No Next button, Execute starts right after border
+                    CardLayout::Single => (None, 2u16, 20u16, 21u16, 38u16, None),
+                    CardLayout::Checklist(_) => (None, 2u16, 25u16, 26u16, 43u16, None),
+                    CardLayout::Compare(_) => (None, 2u16, 28u16, 29u16, 46u16, None),
+                };
+
+                (
+                    Some(button_screen_y),
+                    next_btn,
+                    Some((exec_start, exec_end)),
+                    Some((cancel_start, cancel_end)),
+                    multi_btn,
+                )
+            } else {
+                (None, None, None, None, None)
+            }
+        } else {
+            (None, None, None, None, None)
+        };
+
+        // Checklist rows follow the top border
+        let checklist_rows = match layout {
+            CardLayout::Checklist(rows) => {
+                let first = (start_line + 1).max(skip);
+                let end = (start_line + 1 + rows).min(skip + visible_lines);
+                (first < end).then(|| ((first - skip) as u16, (end - skip) as u16, first - (start_line + 1)))
+            }
+            _ => None,
+        };
+
+        // Compared suggestions follow the top border, two lines each
+        let compare_rows = match layout {
+            CardLayout::Compare(suggestions) => {
+                let first = (start_line + 1).max(skip);
+                let end = (start_line + 1 + 2 * suggestions).min(skip + visible_lines);
+                (first < end).then(|| ((first - skip) as u16, (end - skip) as u16, first - (start_line + 1)))
+            }
+            _ => None,
+        };
+        let compare_btn = button_y.filter(|_| layout == CardLayout::Paginated).map(|_| (67u16, 80u16));
+
+        // The verdict line is the card's second line (a checklist or a
+        // comparison has none)
+        let verdict_line = start_line + 1;
+        let verdict_y = (!matches!(layout, CardLayout::Checklist(_) | CardLayout::Compare(_))
+            && verdict_line >= skip
+            && verdict_line < skip + visible_lines)
+            .then(|| (verdict_line - skip) as u16);
+        let why_btn = verdict_y
+            .and_then(|_| assistant.view.messages.get(msg_idx))
+            .and_then(why_label)
+            .map(|label| {
+                let card_width = (area.width as usize).saturating_sub(4).max(20);
+                let end = 2 + card_width as u16;
+                (end.saturating_sub(label.width() as u16), end)
+            });
+
+        command_card_hits.push(CommandCardHitArea {
+            message_idx: msg_idx,
+            start_y: screen_start_y,
+            end_y: screen_end_y,
+            button_y,
+            next_btn,
+            execute_btn,
+            cancel_btn,
+            verdict_y,
+            why_btn,
+            multi_btn,
+            checklist_rows,
+            compare_btn,
+            compare_rows,
+        });
+    }
+
+    // Highlight the hovered card button
+    let hovered_button = command_card_hits.iter().find_map(|card| {
+        if let MessageAreaClickResult::ExplainVerdict(idx) = assistant.hovered_card_button
+            && idx == card.message_idx
+        {
+            return card.verdict_y.zip(card.why_btn);
+        }
+        let button_y = card.button_y?;
+        let range = match assistant.hovered_card_button {
+            MessageAreaClickResult::NextCommand(idx) if idx == card.message_idx => card.next_btn,
+            MessageAreaClickResult::ExecuteCommand(idx) if idx == card.message_idx => card.execute_btn,
+            MessageAreaClickResult::CancelCommand(idx) if idx == card.message_idx => card.cancel_btn,
+            MessageAreaClickResult::ToggleChecklist(idx) if idx == card.message_idx => card.multi_btn,
+            MessageAreaClickResult::ToggleCompare(idx) if idx == card.message_idx => card.compare_btn,
+            _ => None,
+        }?;
+        Some((button_y, range))
+    });
+    if let Some((button_y, (start, end))) = hovered_button {
+        let x = area.x + start;
+        let width = end.saturating_sub(start).min((area.x + area.width).saturating_sub(x));
+        apply_hover_style(buf, Rect { x, y: area.y + button_y, width, height: 1 });
+    }
+
+    *assistant.cached_command_cards.borrow_mut() = command_card_hits;
+
+    // The regenerate hint, if visible
+    let regen_hint_y = regen_hint_line
+        .filter(|&line| line >= skip && line < skip + visible_lines)
+        .map(|line| (line - skip) as u16);
+    if let Some(y) = regen_hint_y
+        && assistant.hovered_card_button == MessageAreaClickResult::Regenerate
+    {
+        let width = (REGEN_HINT.width() as u16).min(area.width);
+        apply_hover_style(buf, Rect { x: area.x, y: area.y + y, width, height: 1 });
+    }
+    assistant.cached_regen_hint.set(regen_hint_y);
+
+    // The summary divider, if visible
+    let summary_y = summary_line
+        .filter(|&line| line >= skip && line < skip + visible_lines)
+        .map(|line| (line - skip) as u16);
+    if let Some(y) = summary_y
+        && assistant.hovered_card_button == MessageAreaClickResult::ToggleSummary
+    {
+        let width = (SUMMARY_DIVIDER.width() as u16).min(area.width);
+        apply_hover_style(buf, Rect { x: area.x, y: area.y + y, width, height: 1 });
+    }
+    assistant.cached_summary_divider.set(summary_y);
+
+    // What arrived below while scrolled back, over the bottom row
+    let unseen = assistant.view.unseen_lines.min(effective_scroll);
+    let pill = (unseen > 0 && area.height > 0).then(|| {
+        let text = new_lines_pill(unseen);
+        let width = (text.width() as u16).min(area.width);
+        let start_x = (area.width - width) / 2;
+        let y = area.height - 1;
+        buf.set_stringn(
+            area.x + start_x,
+            area.y + y,
+            &text,
+            usize::from(width),
+            Style::default().fg(Color::Black).bg(Color::Cyan),
+        );
+        if assistant.hovered_card_button == MessageAreaClickResult::JumpToLatest {
+            apply_hover_style(buf, Rect { x: area.x + start_x, y: area.y + y, width, height: 1 });
+        }
+        (y, start_x, start_x + width)
+    });
+    assistant.cached_new_lines_pill.set(pill);
+}
+
+/// Render a picker popup (new session, regenerate) centered over the
+/// message area, with `items` numbered and `selected` highlighted.
+fn render_picker(items: &[impl AsRef<str>], selected: usize, title: &str, area: Rect, buf: &mut Buffer) {
+    let lines: Vec<Line<'_>> = items
+        .iter()
+        .map(AsRef::as_ref)
+        .enumerate()
+        .map(|(i, name)| {
+            let text = if i < 9 {
+                format!(" {} {} ", i + 1, name)
+            } else {
+                format!("   {} ", name)
+            };
+            if i == selected {
+                Line::from(Span::styled(text, Style::default().fg(Color::Black).bg(Color::Cyan)))
+            } else {
+                Line::from(text)
+            }
+        })
+        .collect();
+
+    let content_width = lines.iter().map(|l| l.width()).max().unwrap_or(0) as u16;
+    let width = (content_width + 2).max(24).min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let popup = crate::ui::popup_area(area, width, height);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .title_bottom(" ↑/↓ Enter · Esc ")
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup);
+
+    Clear.render(popup, buf);
+    block.render(popup, buf);
+    Paragraph::new(lines).render(inner, buf);
+}
@@ -0,0 +1,588 @@
+//! Visual mode over the message list: moving the cursor, selecting and
+//! copying.
+
+use super::*;
+
+impl TuiAssistant {
+    // ========================================================================
+    // Visual Mode
+    // ========================================================================
+
+    /// Index of the message the visual cursor is on (the last one for the
+    /// hint below it).
+    pub(super) fn message_at_visual_cursor(&self) -> Option<usize> {
+        let row = self.visual_state.as_ref()?.cursor.0;
+        let width = self.cached_visible_width.get() as u16;
+        (0..self.view.messages.len())
+            .find(|&idx| self.rendered_lines(&self.view.messages[..=idx], width).len() > row)
+            .or(self.view.messages.len().checked_sub(1))
+    }
+
+    /// The question to the AI the visual cursor is on, as its place among
+    /// the questions shown (0 is the first) and its text.
+    pub fn question_at_visual_cursor(&self) -> Option<(usize, &str)> {
+        let idx = self.message_at_visual_cursor()?;
+        let ChatMessage::User { text } = &self.view.messages[idx] else {
+            return None;
+        };
+        if self.view.local_questions.contains(&idx) {
+            return None;
+        }
+        let index = (0..idx)
+            .filter(|i| matches!(self.view.messages[*i], ChatMessage::User { .. }) && !self.view.local_questions.contains(i))
+            .count();
+        Some((index, text))
+    }
+
+    /// Check if visual mode is active.
+    pub fn is_visual_mode(&self) -> bool {
+        self.visual_state.is_some()
+    }
+
+    /// Check if visual selection is active.
+    pub fn is_visual_selecting(&self) -> bool {
+        self.visual_state.as_ref().is_some_and(|s| s.is_selecting())
+    }
+
+    /// Get visual selection mode.
+    pub fn get_visual_selection_mode(&self) -> Option<SelectionMode> {
+        self.visual_state.as_ref().map(|s| s.get_selection_mode())
+    }
+
+    /// Get visual state reference.
+    pub fn visual_state(&self) -> Option<&VisualState> {
+        self.visual_state.as_ref()
+    }
+
+    /// Enter visual mode.
+    /// Initializes the cursor at the bottom-left of the visible message area.
+    pub fn enter_visual_mode(&mut self) {
+        // If never rendered, place cursor at row 0
+        let cached_width = self.cached_visible_width.get();
+        if cached_width == 0 {
+            self.visual_state = Some(VisualState::new(0, 0));
+            return;
+        }
+
+        // Compute total_lines fresh to avoid stale cache issues
+        // (cached_total_lines is only updated during render)
+        let all_lines = self.build_rendered_lines(cached_width as u16);
+        let total_lines = all_lines.len();
+
+        // Calculate initial cursor position
+        // Use the bottom of visible area, or if no messages, row 0
+        let row = if total_lines == 0 {
+            0
+        } else {
+            // Calculate visible bottom in content coordinates
+            let visible_bottom = total_lines.saturating_sub(1).saturating_sub(self.view.scroll_offset);
+            visible_bottom.min(total_lines.saturating_sub(1))
+        };
+
+        self.visual_state = Some(VisualState::new(row, 0));
+    }
+
+    /// Exit visual mode.
+    pub fn exit_visual_mode(&mut self) {
+        self.visual_state = None;
+        self.copy_error = None;
+    }
+
+    /// Move visual cursor by delta.
+    /// If cursor moves out of visible area, auto-scroll to keep it visible.
+    pub fn move_visual_cursor(&mut self, delta_row: i32, delta_col: i32) {
+        let Some(ref mut visual) = self.visual_state else {
+            return;
+        };
+
+        let total_lines = self.cached_total_lines.get();
+        let max_col = self.cached_visible_width.get().saturating_sub(1);
+        let max_row = total_lines.saturating_sub(1);
+
+        // Move cursor
+        visual.move_cursor(delta_row, delta_col, max_row, max_col);
+
+        // Auto-scroll to keep cursor in view
+        self.scroll_to_visual_cursor();
+    }
+
+    /// Scroll viewport to ensure visual cursor is visible.
+    fn scroll_to_visual_cursor(&mut self) {
+        let Some(ref visual) = self.visual_state else {
+            return;
+        };
+
+        let total_lines = self.cached_total_lines.get();
+        let max_scroll = self.max_scroll_offset.get();
+
+        if total_lines == 0 {
+            return;
+        }
+
+        let (cursor_row, _) = visual.cursor;
+
+        // Calculate visible height (approximation)
+        let visible_height = total_lines.saturating_sub(max_scroll);
+        if visible_height == 0 {
+            return;
+        }
+
+        // Calculate visible range in content coordinates
+        // scroll_offset = 0 means showing the bottom (most recent)
+        // scroll_offset = max_scroll means showing the top (oldest)
+        let visible_bottom = total_lines.saturating_sub(1).saturating_sub(self.view.scroll_offset);
+        let visible_top = visible_bottom.saturating_sub(visible_height.saturating_sub(1));
+
+        // Adjust scroll if cursor is out of visible range
+        if cursor_row < visible_top {
+            // Cursor is above visible area, scroll up
+            let new_offset = total_lines.saturating_sub(1).saturating_sub(cursor_row).saturating_sub(visible_height.saturating_sub(1));
+            self.view.scroll_offset = new_offset.min(max_scroll);
+        } else if cursor_row > visible_bottom {
+            // Cursor is below visible area, scroll down
+            self.view.scroll_offset = total_lines.saturating_sub(1).saturating_sub(cursor_row);
+        }
+    }
+
+    /// Cycle visual selection mode: None -> Line -> Block -> None
+    pub fn cycle_visual_selection(&mut self) {
+        if let Some(ref mut visual) = self.visual_state {
+            visual.cycle_selection_mode();
+        }
+    }
+
+    /// Clear visual selection (keep cursor position, reset to None mode).
+    pub fn clear_visual_selection(&mut self) {
+        if let Some(ref mut visual) = self.visual_state {
+            visual.clear_selection();
+        }
+    }
+
+    /// Start visual selection at current cursor position.
+    ///
+    /// This enters Line selection mode and sets the anchor to the current cursor.
+    /// Used when starting mouse-based selection.
+    pub fn start_visual_selection(&mut self) {
+        if let Some(ref mut visual) = self.visual_state {
+            // If not already selecting, start selection
+            if !visual.is_selecting() {
+                visual.cycle_selection_mode(); // None -> Line
+            }
+        }
+    }
+
+    /// Set visual cursor position from screen-relative coordinates.
+    ///
+    /// This is used for mouse-based cursor positioning. The coordinates are
+    /// relative to the message area (excluding tab bar and input box).
+    ///
+    /// # Arguments
+    /// * `screen_row` - Row in the visible message area (0 = top of visible content)
+    /// * `screen_col` - Column in the visible area
+    pub fn set_visual_cursor_from_screen(&mut self, screen_row: usize, screen_col: usize) {
+        let Some(ref mut visual) = self.visual_state else {
+            return;
+        };
+
+        let cached_width = self.cached_visible_width.get();
+        if cached_width == 0 {
+            return;
+        }
+
+        let total_lines = self.cached_total_lines.get();
+        if total_lines == 0 {
+            return;
+        }
+
+        // Calculate visible range and convert screen row to content row
+        let max_scroll = self.max_scroll_offset.get();
+        let visible_height = total_lines.saturating_sub(max_scroll);
+        let visible_bottom = total_lines.saturating_sub(1).saturating_sub(self.view.scroll_offset);
+        let visible_top = visible_bottom.saturating_sub(visible_height.saturating_sub(1));
+
+        let content_row = (visible_top + screen_row).min(total_lines.saturating_sub(1));
+        let content_col = screen_col.min(cached_width.saturating_sub(1));
+
+        visual.set_cursor(content_row, content_col);
+    }
+
+    /// Select the word at the given screen position.
+    ///
+    /// This finds word boundaries and sets both anchor and cursor to select the word.
+    /// Note: This is a simplified implementation for the Assistant pane. Due to the
+    /// complex formatting (wrapped lines, message prefixes), it may not perfectly
+    /// match word boundaries in all cases.
+    ///
+    /// # Arguments
+    /// * `screen_row` - Row in the visible message area (0 = top of visible content)
+    /// * `screen_col` - Column in the visible area
+    pub fn select_word_at(&mut self, screen_row: usize, screen_col: usize) {
+        let cached_width = self.cached_visible_width.get();
+        if cached_width == 0 {
+            return;
+        }
+
+        // Build rendered lines to get content
+        let all_lines = self.build_rendered_lines(cached_width as u16);
+        let total_lines = all_lines.len();
+
+        if total_lines == 0 {
+            return;
+        }
+
+        // Calculate visible range and convert screen row to content row
+        let visible_bottom = total_lines.saturating_sub(1).saturating_sub(self.view.scroll_offset);
+        let max_visible = self.max_scroll_offset.get();
+        let visible_height = total_lines.saturating_sub(max_visible);
+        let visible_top = visible_bottom.saturating_sub(visible_height.saturating_sub(1));
+
+        let content_row = (visible_top + screen_row).min(total_lines.saturating_sub(1));
+
+        // Get the line content
+        let line = &all_lines[content_row];
+        let line_text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+        let col = screen_col.min(line_text.chars().count().saturating_sub(1));
+
+        // Find word boundaries
+        let (word_start, word_end) = find_word_boundaries_in_string(&line_text, col);
+
+        // Set up visual state with selection
+        if let Some(ref mut visual) = self.visual_state {
+            // Set anchor at word start
+            visual.anchor = Some((content_row, word_start));
+            // Set cursor at word end
+            visual.set_cursor(content_row, word_end);
+            // Ensure we're in Line selection mode
+            if !visual.is_selecting() {
+                visual.cycle_selection_mode(); // None -> Line
+            }
+        }
+    }
+
+    /// Select the entire line at the given screen position (triple-click).
+    ///
+    /// This selects from column 0 to the end of the line's content.
+    ///
+    /// # Arguments
+    /// * `screen_row` - Row in the visible message area (0 = top of visible content)
+    pub fn select_line_at(&mut self, screen_row: usize) {
+        let cached_width = self.cached_visible_width.get();
+        if cached_width == 0 {
+            return;
+        }
+
+        // Build rendered lines to get content
+        let all_lines = self.build_rendered_lines(cached_width as u16);
+        let total_lines = all_lines.len();
+
+        if total_lines == 0 {
+            return;
+        }
+
+        // Calculate visible range and convert screen row to content row
+        let visible_bottom = total_lines.saturating_sub(1).saturating_sub(self.view.scroll_offset);
+        let max_visible = self.max_scroll_offset.get();
+        let visible_height = total_lines.saturating_sub(max_visible);
+        let visible_top = visible_bottom.saturating_sub(visible_height.saturating_sub(1));
+
+        let content_row = (visible_top + screen_row).min(total_lines.saturating_sub(1));
+
+        // Get the line content
+        let line = &all_lines[content_row];
+        let line_text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+        let line_len = line_text.trim_end().chars().count();
+        let end_col = line_len.saturating_sub(1);
+
+        // Set up visual state with selection
+        if let Some(ref mut visual) = self.visual_state {
+            // Set anchor at line start
+            visual.anchor = Some((content_row, 0));
+            // Set cursor at line end
+            visual.set_cursor(content_row, end_col);
+            // Ensure we're in Line selection mode
+            if !visual.is_selecting() {
+                visual.cycle_selection_mode(); // None -> Line
+            }
+        }
+    }
+
+    /// Copy selected text to clipboard.
+    /// Returns None if there is nothing to copy.
+    /// Line mode: trims trailing spaces from each line.
+    /// Block mode: preserves all characters in the rectangle.
+    pub fn copy_visual_selection(&mut self) -> Option<CopyOutcome> {
+        let text = self.selected_text()?;
+        Some(copy_to_clipboard(&text))
+    }
+
+    /// Text of the visual selection, as copied (None without a selection
+    /// or if it is blank).
+    pub fn selected_text(&self) -> Option<String> {
+        let visual = self.visual_state.as_ref()?;
+        let ((start_row, start_col), (end_row, end_col)) = visual.selection_range()?;
+        // We need to extract text from the rendered lines
+        let text = self.get_text_range(start_row, start_col, end_row, end_col, visual.get_selection_mode());
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// Copy the visual selection and clear it, as `y` does.
+    pub fn yank_visual_selection(&mut self) {
+        let outcome = self.copy_visual_selection();
+        self.finish_copy(outcome);
+    }
+
+    /// Clear the selection after a copy, or keep it and report the failure.
+    pub(super) fn finish_copy(&mut self, outcome: Option<CopyOutcome>) {
+        if outcome == Some(CopyOutcome::Unavailable) {
+            self.copy_error = Some(COPY_UNAVAILABLE);
+            return;
+        }
+        self.copy_error = None;
+        if let Some(ref mut v) = self.visual_state {
+            v.clear_selection();
+        }
+    }
+
+    // ========================================================================
+    // Keys and Screen Positions
+    // ========================================================================
+
+    /// Handle a key event when in visual mode.
+    /// Returns KeyHandleResult indicating how the key was processed.
+    pub fn handle_visual_key(&mut self, key: KeyEvent) -> KeyHandleResult {
+        if !matches!(key.kind, KeyEventKind::Press) {
+            return KeyHandleResult::NotConsumed;
+        }
+        // A copy error is only reported until the next key
+        self.copy_error = None;
+
+        // If not in visual mode, don't consume
+        let Some(ref mut visual) = self.visual_state else {
+            return KeyHandleResult::NotConsumed;
+        };
+
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+
+        match key.code {
+            // Digit keys for repeat count
+            KeyCode::Char(c @ '1'..='9') => {
+                let digit = c.to_digit(10).unwrap_or(0) as usize;
+                visual.accumulate_repeat_digit(digit);
+                return KeyHandleResult::Consumed;
+            }
+            KeyCode::Char('0') if visual.has_repeat_count() => {
+                visual.accumulate_repeat_digit(0);
+                return KeyHandleResult::Consumed;
+            }
+
+            // Escape => if in selection mode, go back to VISUAL; if in VISUAL, exit
+            KeyCode::Esc => {
+                if visual.is_selecting() {
+                    // In Line/Block mode, go back to None (VISUAL) mode
+                    visual.clear_selection();
+                } else {
+                    // Already in None mode, exit visual mode
+                    self.visual_state = None;
+                }
+            }
+
+            // Space => toggle selection mode: None -> Line, then Line <-> Block
+            KeyCode::Char(' ') => {
+                visual.cycle_selection_mode();
+            }
+
+            // y => copy selected text and clear selection (kept if the copy failed)
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                let outcome = self.copy_visual_selection();
+                self.finish_copy(outcome);
+            }
+
+            // i => show or hide where the time of the reply under the cursor went
+            KeyCode::Char('i') => {
+                visual.clear_repeat_count();
+                self.toggle_timing_at_cursor();
+            }
+
+            // Scroll keys (Shift + arrows) - scroll without moving cursor
+            KeyCode::Up if shift => {
+                let repeat = visual.take_repeat_count();
+                for _ in 0..repeat {
+                    self.scroll(-1);
+                }
+            }
+            KeyCode::Down if shift => {
+                let repeat = visual.take_repeat_count();
+                for _ in 0..repeat {
+                    self.scroll(1);
+                }
+            }
+            KeyCode::PageUp => {
+                let repeat = visual.take_repeat_count();
+                self.scroll(-(repeat as i16 * 10));
+            }
+            KeyCode::PageDown => {
+                let repeat = visual.take_repeat_count();
+                self.scroll(repeat as i16 * 10);
+            }
+
+            // Cursor movement keys (hjkl and arrows)
+            KeyCode::Left | KeyCode::Char('h') | KeyCode::Char('H') => {
+                let repeat = visual.take_repeat_count();
+                for _ in 0..repeat {
+                    self.move_visual_cursor(0, -1);
+                }
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                let repeat = visual.take_repeat_count();
+                for _ in 0..repeat {
+                    self.move_visual_cursor(0, 1);
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') | KeyCode::Char('K') => {
+                let repeat = visual.take_repeat_count();
+                for _ in 0..repeat {
+                    self.move_visual_cursor(-1, 0);
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') | KeyCode::Char('J') => {
+                let repeat = visual.take_repeat_count();
+                for _ in 0..repeat {
+                    self.move_visual_cursor(1, 0);
+                }
+            }
+
+            _ => {
+                // Unknown key in visual mode - clear repeat count but consume
+                if let Some(ref mut v) = self.visual_state {
+                    v.clear_repeat_count();
+                }
+            }
+        }
+
+        KeyHandleResult::Consumed
+    }
+
+    /// Get text from a range in rendered line coordinates.
+    /// Get text from a range in content coordinates.
+    /// Line mode: trims trailing spaces from each line.
+    /// Block mode: extracts exact rectangle, preserves spaces.
+    pub(super) fn get_text_range(&self, start_row: usize, start_col: usize, end_row: usize, end_col: usize, mode: SelectionMode) -> String {
+        // Build all rendered lines (similar to render_message_list)
+        let width = self.cached_visible_width.get() as u16;
+        if width == 0 {
+            return String::new();
+        }
+
+        let all_lines = self.build_rendered_lines(width);
+        // Reasoning is left out of copies unless asked for
+        let skipped = match self.copy_reasoning {
+            true => Vec::new(),
+            false => self.reasoning_rows(width),
+        };
+
+        let mut result = String::new();
+
+        for row in start_row..=end_row {
+            if row >= all_lines.len() {
+                break;
+            }
+            if skipped.iter().any(|rows| rows.contains(&row)) {
+                continue;
+            }
+
+            let line = &all_lines[row];
+            let line_text: String = line.spans.iter().map(|s| s.content.as_ref()).collect();
+            let chars: Vec<char> = line_text.chars().collect();
+            let line_len = chars.len();
+
+            // Determine column range based on mode
+            let (col_start, col_end) = match mode {
+                SelectionMode::None => continue,
+                SelectionMode::Line => {
+                    // Line mode: clamp to line length
+                    let cs = if row == start_row { start_col } else { 0 };
+                    let ce = if row == end_row {
+                        end_col.min(line_len.saturating_sub(1))
+                    } else {
+                        line_len.saturating_sub(1)
+                    };
+                    (cs, ce)
+                }
+                SelectionMode::Block => {
+                    // Block mode: use exact rectangle columns
+                    (start_col, end_col.min(line_len.saturating_sub(1)))
+                }
+            };
+
+            // Extract characters from this line
+            if col_start < line_len {
+                let actual_end = col_end.min(line_len.saturating_sub(1));
+                if col_start <= actual_end {
+                    let line_slice: String = chars[col_start..=actual_end].iter().collect();
+                    // For Line mode, trim trailing whitespace
+                    // For Block mode, preserve as-is
+                    let final_text = match mode {
+                        SelectionMode::Line => line_slice.trim_end().to_string(),
+                        SelectionMode::Block | SelectionMode::None => line_slice,
+                    };
+                    result.push_str(&final_text);
+                }
+            }
+
+            // Add newline between lines (but not after the last line)
+            if row < end_row {
+                result.push('\n');
+            }
+        }
+
+        result
+    }
+
+    /// Get visual cursor position in screen coordinates (for rendering).
+    /// Returns (screen_row, screen_col) if cursor is visible, None otherwise.
+    pub fn get_visual_cursor_screen_pos(&self) -> Option<(usize, usize)> {
+        let visual = self.visual_state.as_ref()?;
+        let (cursor_row, cursor_col) = visual.cursor;
+
+        let total_lines = self.cached_total_lines.get();
+        let max_scroll = self.max_scroll_offset.get();
+
+        if total_lines == 0 {
+            return Some((0, cursor_col));
+        }
+
+        // Calculate visible height
+        let visible_height = total_lines.saturating_sub(max_scroll);
+        if visible_height == 0 {
+            return None;
+        }
+
+        // Calculate visible range
+        let visible_bottom = total_lines.saturating_sub(1).saturating_sub(self.view.scroll_offset);
+        let visible_top = visible_bottom.saturating_sub(visible_height.saturating_sub(1));
+
+        // Check if cursor is in visible range
+        if cursor_row >= visible_top && cursor_row <= visible_bottom {
+            let screen_row = cursor_row - visible_top;
+            Some((screen_row, cursor_col))
+        } else {
+            None
+        }
+    }
+
+    /// Convert screen row to content row for selection checking.
+    pub(super) fn screen_row_to_content_row(&self, screen_row: usize) -> usize {
+        let total_lines = self.cached_total_lines.get();
+        let max_scroll = self.max_scroll_offset.get();
+
+        if total_lines == 0 {
+            return 0;
+        }
+
+        let visible_height = total_lines.saturating_sub(max_scroll);
+        let visible_bottom = total_lines.saturating_sub(1).saturating_sub(self.view.scroll_offset);
+        let visible_top = visible_bottom.saturating_sub(visible_height.saturating_sub(1));
+
+        visible_top + screen_row
+    }
+}
@@ -0,0 +1,126 @@
+//! Picking several of the AI's suggested commands, and their order.
+//!
+//! A reply with several suggestions shows them one at a time, as
+//! alternatives. When they go together instead ("prune containers, prune
+//! images, prune volumes"), `m` turns the card into a checklist: Space
+//! selects the suggestion under the cursor, j/k move it down or up the
+//! list, and Ctrl+Y runs the selected ones from the top down.
+
+/// One row of the checklist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecklistRow {
+    /// Index of the suggestion in the reply
+    pub suggestion: usize,
+    /// Place it runs in, 1-based (None = not selected)
+    pub step: Option<usize>,
+    /// The cursor is on it
+    pub current: bool,
+}
+
+/// The suggestions of a reply in the order they would run, and which ones
+/// are selected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checklist {
+    /// Suggestion indices, top to bottom
+    order: Vec<usize>,
+    /// Selected, by suggestion index
+    selected: Vec<bool>,
+    /// Row of the cursor
+    cursor: usize,
+}
+
+impl Checklist {
+    /// A checklist of `len` suggestions in the order the AI gave them, none
+    /// selected yet.
+    pub fn new(len: usize) -> Self {
+        Self { order: (0..len).collect(), selected: vec![false; len], cursor: 0 }
+    }
+
+    pub fn rows(&self) -> Vec<ChecklistRow> {
+        let mut step = 0;
+        self.order
+            .iter()
+            .enumerate()
+            .map(|(row, &suggestion)| {
+                let selected = self.selected.get(suggestion).copied().unwrap_or(false);
+                step += usize::from(selected);
+                ChecklistRow { suggestion, step: selected.then_some(step), current: row == self.cursor }
+            })
+            .collect()
+    }
+
+    /// Move the cursor `delta` rows, stopping at either end.
+    pub fn move_cursor(&mut self, delta: isize) {
+        let last = self.order.len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(delta).min(last);
+    }
+
+    /// Select the suggestion under the cursor, or unselect it.
+    pub fn toggle(&mut self) {
+        if let Some(&suggestion) = self.order.get(self.cursor)
+            && let Some(selected) = self.selected.get_mut(suggestion)
+        {
+            *selected = !*selected;
+        }
+    }
+
+    /// Put the cursor on `row` and select or unselect it (a click).
+    pub fn toggle_row(&mut self, row: usize) {
+        if row < self.order.len() {
+            self.cursor = row;
+            self.toggle();
+        }
+    }
+
+    /// Move the suggestion under the cursor `delta` rows, taking the cursor
+    /// along: it runs that much later (or earlier) if selected.
+    pub fn move_item(&mut self, delta: isize) {
+        let last = self.order.len().saturating_sub(1);
+        let to = self.cursor.saturating_add_signed(delta).min(last);
+        if to != self.cursor {
+            let suggestion = self.order.remove(self.cursor);
+            self.order.insert(to, suggestion);
+            self.cursor = to;
+        }
+    }
+
+    /// The selected suggestions, in the order they run.
+    pub fn selected(&self) -> Vec<usize> {
+        self.order.iter().copied().filter(|&s| self.selected.get(s).copied().unwrap_or(false)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selected_suggestions_run_in_list_order() {
+        let mut checklist = Checklist::new(4);
+        assert!(checklist.selected().is_empty());
+
+        // Pick the first and third only
+        checklist.toggle();
+        checklist.move_cursor(2);
+        checklist.toggle();
+        assert_eq!(checklist.selected(), vec![0, 2]);
+        let steps: Vec<_> = checklist.rows().iter().map(|row| row.step).collect();
+        assert_eq!(steps, vec![Some(1), None, Some(2), None]);
+
+        // The third goes to the top, so it runs first
+        checklist.move_item(-1);
+        checklist.move_item(-1);
+        checklist.move_item(-1);
+        assert_eq!(checklist.selected(), vec![2, 0]);
+        assert_eq!(checklist.rows()[0], ChecklistRow { suggestion: 2, step: Some(1), current: true });
+
+        // Unselected rows keep their place, and clicks select
+        checklist.toggle_row(3);
+        assert_eq!(checklist.selected(), vec![2, 0, 3]);
+        checklist.toggle_row(1);
+        assert_eq!(checklist.selected(), vec![2, 3]);
+        checklist.move_cursor(9);
+        checklist.move_item(1);
+        assert_eq!(checklist.rows().iter().map(|row| row.suggestion).collect::<Vec<_>>(), vec![2, 0, 1, 3]);
+    }
+}
@@ -0,0 +1,315 @@
+//! Command boundary marks for the terminal scrollback.
+//!
+//! A mark records the content row of a command line at the moment the command
+//! was submitted. The output of command `i` is everything between its mark and
+//! the next mark (or the current prompt for the most recent command).
+//!
+//! Content rows are stable while the scrollback is growing. Once it is full,
+//! every new line evicts the oldest one and shifts all rows up, so marks keep a
+//! fingerprint (the text of the marked line) and are re-located after output is
+//! processed. Marks whose line has been evicted are dropped.
+
+use std::collections::VecDeque;
+
+/// Maximum number of marks kept (oldest are dropped first).
+const MAX_MARKS: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CommandMark {
+    /// Content row of the command line (0 = oldest line in scrollback)
+    row: usize,
+    /// Text of the command line, used to re-locate it after eviction
+    fingerprint: String,
+    /// The command as typed (without the prompt), empty if unknown
+    command: String,
+}
+
+/// Ordered list of command boundaries (oldest first).
+#[derive(Debug, Clone, Default)]
+pub struct CommandMarks {
+    marks: VecDeque<CommandMark>,
+}
+
+impl CommandMarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a command submitted on the given content row.
+    ///
+    /// Marks at or below `row` are stale (e.g. the screen was cleared) and are
+    /// replaced.
+    pub fn push(&mut self, row: usize, fingerprint: impl Into<String>, command: impl Into<String>) {
+        while self.marks.back().is_some_and(|m| m.row >= row) {
+            self.marks.pop_back();
+        }
+        if self.marks.len() >= MAX_MARKS {
+            self.marks.pop_front();
+        }
+        self.marks.push_back(CommandMark {
+            row,
+            fingerprint: fingerprint.into(),
+            command: command.into(),
+        });
+    }
+
+    /// Forget all marks (scrollback cleared, terminal resized).
+    pub fn clear(&mut self) {
+        self.marks.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.marks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.marks.is_empty()
+    }
+
+    /// Content row of the mark at `index`.
+    pub fn row(&self, index: usize) -> Option<usize> {
+        self.marks.get(index).map(|m| m.row)
+    }
+
+    /// Command whose line or output contains `row` (the latest mark at or above it).
+    pub fn command_at(&self, row: usize) -> Option<&str> {
+        self.marks
+            .iter()
+            .rev()
+            .find(|m| m.row <= row)
+            .map(|m| m.command.as_str())
+            .filter(|command| !command.is_empty())
+    }
+
+    /// Whether `command`, as last marked, seems to still be running, judged
+    /// from `line_text` (text of a row) and the cursor row: it has not come
+    /// back to a line ending like the prompt the command was typed at.
+    /// Without shell integration this is a guess from the screen. None if
+    /// there is no mark of the command or no prompt to go by.
+    pub fn still_running<F>(&self, command: &str, cursor_row: usize, line_text: F) -> Option<bool>
+    where
+        F: Fn(usize) -> Option<String>,
+    {
+        let mark = self.marks.iter().rev().find(|m| m.command == command.trim())?;
+        // Marked before the command was typed, the line is just the prompt
+        let prompt = mark.fingerprint.strip_suffix(mark.command.as_str()).unwrap_or(&mark.fingerprint);
+        let prompt_end = prompt.split_whitespace().last()?;
+        if cursor_row <= mark.row {
+            return Some(true);
+        }
+        let line = line_text(cursor_row).unwrap_or_default();
+        Some(!line.trim_end().ends_with(prompt_end))
+    }
+
+    /// Move all marks up by `lines` evicted rows, dropping marks that fell off.
+    pub fn shift_up(&mut self, lines: usize) {
+        if lines == 0 {
+            return;
+        }
+        self.marks.retain(|m| m.row >= lines);
+        for mark in &mut self.marks {
+            mark.row -= lines;
+        }
+    }
+
+    /// Re-locate marks after up to `max_shift` lines may have been evicted.
+    ///
+    /// The newest mark's fingerprint is searched for at its old row and up to
+    /// `max_shift` rows above; the smallest matching shift is applied to all
+    /// marks. If the line cannot be found, all marks are dropped.
+    pub fn resync<F, T>(&mut self, max_shift: usize, line_text: F)
+    where
+        F: Fn(usize) -> Option<T>,
+        T: PartialEq<str>,
+    {
+        let Some(newest) = self.marks.back() else {
+            return;
+        };
+
+        let found = (0..=max_shift.min(newest.row))
+            .find(|&shift| line_text(newest.row - shift).is_some_and(|text| text == *newest.fingerprint.as_str()));
+
+        match found {
+            Some(shift) => self.shift_up(shift),
+            None => self.clear(),
+        }
+    }
+
+    /// Output rows `(start, end)` (inclusive) of the command at `index`.
+    ///
+    /// `prompt_row` is the row of the current prompt; it terminates the output
+    /// of the most recent command. Returns `None` if the command has not
+    /// completed yet or printed nothing.
+    pub fn output_region(&self, index: usize, prompt_row: usize) -> Option<(usize, usize)> {
+        let mark = self.marks.get(index)?;
+        let next_row = match self.marks.get(index + 1) {
+            Some(next) => next.row,
+            None => prompt_row,
+        };
+        let start = mark.row + 1;
+        let end = next_row.checked_sub(1)?;
+        (start <= end).then_some((start, end))
+    }
+
+    /// Index of the most recent completed command that printed output.
+    pub fn last_with_output(&self, prompt_row: usize) -> Option<usize> {
+        (0..self.marks.len())
+            .rev()
+            .find(|&i| self.output_region(i, prompt_row).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A synthetic terminal session: one string per content row.
+    struct Session {
+        lines: Vec<String>,
+        marks: CommandMarks,
+    }
+
+    impl Session {
+        fn new() -> Self {
+            Self { lines: Vec::new(), marks: CommandMarks::new() }
+        }
+
+        /// Type a command at a prompt and print its output.
+        fn run(&mut self, command: &str, output: &[&str]) {
+            let line = format!("$ {}", command);
+            self.marks.push(self.lines.len(), line.clone(), command);
+            self.lines.push(line);
+            self.lines.extend(output.iter().map(|s| s.to_string()));
+        }
+
+        /// Row of the prompt waiting for the next command.
+        fn prompt_row(&self) -> usize {
+            self.lines.len()
+        }
+
+        fn text(&self, (start, end): (usize, usize)) -> Vec<&str> {
+            self.lines[start..=end].iter().map(|s| s.as_str()).collect()
+        }
+
+        /// Drop `n` lines from the top, as a full scrollback does.
+        fn evict(&mut self, n: usize) {
+            self.lines.drain(..n);
+            let lines = self.lines.clone();
+            self.marks.resync(n, |row| lines.get(row).cloned());
+        }
+    }
+
+    #[test]
+    fn test_last_command_output_region() {
+        let mut s = Session::new();
+        s.run("ls", &["a.txt", "b.txt"]);
+        s.run("echo hi", &["hi"]);
+
+        let idx = s.marks.last_with_output(s.prompt_row());
+        assert_eq!(idx, Some(1));
+        let region = s.marks.output_region(1, s.prompt_row());
+        assert_eq!(region.map(|r| s.text(r)), Some(vec!["hi"]));
+        let region = s.marks.output_region(0, s.prompt_row());
+        assert_eq!(region.map(|r| s.text(r)), Some(vec!["a.txt", "b.txt"]));
+    }
+
+    #[test]
+    fn test_commands_without_output_are_skipped() {
+        let mut s = Session::new();
+        s.run("ls", &["a.txt"]);
+        s.run("true", &[]);
+        assert_eq!(s.marks.output_region(1, s.prompt_row()), None);
+        assert_eq!(s.marks.last_with_output(s.prompt_row()), Some(0));
+    }
+
+    #[test]
+    fn test_running_command_is_not_completed() {
+        let mut marks = CommandMarks::new();
+        marks.push(3, "$ sleep 10", "sleep 10");
+        // Cursor is still on the command line
+        assert_eq!(marks.output_region(0, 3), None);
+        assert_eq!(marks.last_with_output(3), None);
+    }
+
+    #[test]
+    fn test_push_replaces_stale_marks() {
+        let mut marks = CommandMarks::new();
+        marks.push(10, "$ a", "a");
+        marks.push(12, "$ b", "b");
+        // Screen cleared, prompt back near the top
+        marks.push(2, "$ c", "c");
+        assert_eq!(marks.len(), 1);
+        assert_eq!(marks.row(0), Some(2));
+    }
+
+    #[test]
+    fn test_marks_survive_eviction() {
+        let mut s = Session::new();
+        s.run("first", &["1", "2", "3"]);
+        s.run("second", &["x", "y"]);
+        s.evict(2);
+
+        // "first" lost its command line; "second" was re-located
+        assert_eq!(s.marks.len(), 1);
+        assert_eq!(s.marks.row(0), Some(2));
+        let region = s.marks.output_region(0, s.prompt_row());
+        assert_eq!(region.map(|r| s.text(r)), Some(vec!["x", "y"]));
+    }
+
+    #[test]
+    fn test_evicted_marks_are_dropped() {
+        let mut s = Session::new();
+        s.run("first", &["1"]);
+        s.run("second", &["2"]);
+        s.evict(3);
+        assert_eq!(s.marks.len(), 0);
+    }
+
+    #[test]
+    fn test_resync_without_eviction_keeps_rows() {
+        let mut s = Session::new();
+        s.run("ls", &["a"]);
+        s.evict(0);
+        assert_eq!(s.marks.row(0), Some(0));
+    }
+
+    #[test]
+    fn test_command_at_row() {
+        let mut s = Session::new();
+        s.run("ls", &["a.txt", "b.txt"]);
+        s.run("pwd", &["/tmp"]);
+        assert_eq!(s.marks.command_at(0), Some("ls"));
+        assert_eq!(s.marks.command_at(2), Some("ls"));
+        assert_eq!(s.marks.command_at(4), Some("pwd"));
+        assert_eq!(CommandMarks::new().command_at(3), None);
+    }
+
+    #[test]
+    fn test_command_runs_until_the_prompt_is_back() {
+        let mut s = Session::new();
+        s.lines.push("user@host ~/app $".to_string());
+        // Marked at the prompt, before the command was typed
+        s.marks.push(0, "user@host ~/app $", "npm run dev");
+        s.lines[0].push_str(" npm run dev");
+        let running = |s: &Session, cursor_row: usize| s.marks.still_running("npm run dev", cursor_row, |row| s.lines.get(row).cloned());
+
+        assert_eq!(running(&s, 0), Some(true));
+        s.lines.push("ready on :3000".to_string());
+        assert_eq!(running(&s, 2), Some(true));
+
+        // Ctrl+C: a new prompt (the directory part may differ)
+        s.lines.push("^C".to_string());
+        s.lines.push("user@host ~/app/src $".to_string());
+        assert_eq!(running(&s, 3), Some(false));
+
+        assert_eq!(s.marks.still_running("ls", 3, |row| s.lines.get(row).cloned()), None);
+    }
+
+    #[test]
+    fn test_lost_fingerprint_invalidates_marks() {
+        let mut marks = CommandMarks::new();
+        marks.push(5, "$ ls", "ls");
+        marks.resync(3, |_| Some("something else".to_string()));
+        assert!(marks.is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+//! Comparing the AI's alternative suggestions side by side.
+//!
+//! Cycling through three alternatives one at a time makes it hard to see
+//! which flags differ. `c` on a card with several suggestions lists them
+//! all at once instead, each with the words that differ from the first
+//! one picked out. j/k move the highlight, and Ctrl+Y runs the highlighted
+//! one like it runs the card's command.
+
+/// The words of `other`, each marked true if it is not part of what it
+/// has in common with `base` (the longest common run of words, in order).
+pub fn word_diff(base: &str, other: &str) -> Vec<(String, bool)> {
+    let base: Vec<&str> = base.split_whitespace().collect();
+    let other: Vec<&str> = other.split_whitespace().collect();
+
+    // common[i][j]: words in common between base[i..] and other[j..]
+    let mut common = vec![vec![0usize; other.len() + 1]; base.len() + 1];
+    for i in (0..base.len()).rev() {
+        for j in (0..other.len()).rev() {
+            common[i][j] = if base[i] == other[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut words = Vec::with_capacity(other.len());
+    let (mut i, mut j) = (0, 0);
+    while j < other.len() {
+        if i < base.len() && base[i] == other[j] {
+            words.push((other[j].to_string(), false));
+            i += 1;
+            j += 1;
+        } else if i < base.len() && common[i + 1][j] >= common[i][j + 1] {
+            i += 1;
+        } else {
+            words.push((other[j].to_string(), true));
+            j += 1;
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(base: &str, other: &str) -> Vec<String> {
+        word_diff(base, other).into_iter().filter(|(_, changed)| *changed).map(|(word, _)| word).collect()
+    }
+
+    #[test]
+    fn test_word_diff_picks_out_the_differing_flags() {
+        assert!(changed("du -sh .", "du -sh .").is_empty());
+        assert_eq!(changed("du -sh .", "du -sh --max-depth=1 ."), ["--max-depth=1"]);
+        assert_eq!(changed("find . -name '*.log' -delete", "find . -name '*.log' -print"), ["-print"]);
+        assert_eq!(changed("ls -la", "tree -L 2"), ["tree", "-L", "2"]);
+
+        // Every word of the other command is kept, in order
+        let words: Vec<String> = word_diff("rm -r build", "rm -ri build dist").into_iter().map(|(w, _)| w).collect();
+        assert_eq!(words, ["rm", "-ri", "build", "dist"]);
+    }
+}
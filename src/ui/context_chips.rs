@@ -0,0 +1,420 @@
+//! "What the AI knows" chips: one line above the assistant input showing
+//! what goes with the next message.
+//!
+//! The line is built from a [`ContextSnapshot`]: the directory
+//! (`📁 ~/src/app`), the git branch (`git:main`), the project the
+//! directory is in (`📦 rusty-term`, see [`crate::context::project`]), the
+//! commands ran (`⌘ 3 cmds`) and the environment variables (`env 12`). Clicking a chip
+//! leaves that section out of the next message, or puts it back; the
+//! choice is kept in [`Attachments`] and applies to one message.
+//!
+//! The chips always come in that order. When they don't fit, the directory
+//! is shortened from the left first, then the branch and the project name
+//! from the right, each down to a few columns; after that chips are dropped from the end. So
+//! the line never overflows and the same width always gives the same line.
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::config::ContextSection;
+use crate::context::ContextSnapshot;
+
+/// Columns around a chip's label (one space on each side)
+const CHIP_PADDING: usize = 2;
+
+/// Columns between chips
+const CHIP_GAP: usize = 1;
+
+/// Fewest columns a shortened path or branch keeps, ellipsis included
+const MIN_ELIDED: usize = 4;
+
+const CWD_ICON: &str = "📁 ";
+const GIT_PREFIX: &str = "git:";
+const PROJECT_ICON: &str = "📦 ";
+
+/// A section of the context, as a chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChipKind {
+    Cwd,
+    Git,
+    Project,
+    Commands,
+    Env,
+}
+
+impl ChipKind {
+    /// Display order, which is also the order chips are kept in
+    pub const ALL: [ChipKind; 5] = [ChipKind::Cwd, ChipKind::Git, ChipKind::Project, ChipKind::Commands, ChipKind::Env];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn color(self) -> Color {
+        match self {
+            ChipKind::Cwd => Color::Cyan,
+            ChipKind::Git => Color::Magenta,
+            ChipKind::Project => Color::LightBlue,
+            ChipKind::Commands => Color::Yellow,
+            ChipKind::Env => Color::Green,
+        }
+    }
+}
+
+/// What the next message would carry, reduced to what the chips show.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextSummary {
+    /// Working directory, with the home directory as `~` (empty = none)
+    cwd: String,
+    /// Branch checked out in the directory's repository
+    git_branch: Option<String>,
+    /// Name of the project whose README and manifests go along
+    project: Option<String>,
+    /// Commands ran: the records if there are any (shell integration),
+    /// otherwise the history
+    commands: usize,
+    /// Environment variables passed on
+    env: usize,
+}
+
+impl ContextSummary {
+    pub fn from_snapshot(snapshot: &ContextSnapshot, git_branch: Option<String>) -> Self {
+        let home = snapshot.env_vars.iter().find(|(name, _)| name == "HOME").map(|(_, value)| value.as_str());
+        let cwd = match home {
+            Some(home) if !home.is_empty() && snapshot.cwd == home => "~".to_string(),
+            Some(home) if !home.is_empty() => match snapshot.cwd.strip_prefix(home) {
+                Some(rest) if rest.starts_with('/') => format!("~{}", rest),
+                _ => snapshot.cwd.clone(),
+            },
+            _ => snapshot.cwd.clone(),
+        };
+        let commands = if snapshot.recent_commands.is_empty() {
+            snapshot.recent_history.len()
+        } else {
+            snapshot.recent_commands.len()
+        };
+        let project = snapshot.project.as_ref().map(|project| project.name.clone());
+        Self { cwd, git_branch, project, commands, env: snapshot.env_vars.len() }
+    }
+
+    /// No chip to show (the line takes no row).
+    pub fn is_empty(&self) -> bool {
+        ChipKind::ALL.iter().all(|&kind| !self.shows(kind))
+    }
+
+    fn shows(&self, kind: ChipKind) -> bool {
+        match kind {
+            ChipKind::Cwd => !self.cwd.is_empty(),
+            ChipKind::Git => self.git_branch.is_some(),
+            ChipKind::Project => self.project.is_some(),
+            ChipKind::Commands => self.commands > 0,
+            ChipKind::Env => self.env > 0,
+        }
+    }
+}
+
+/// Sections left out of the next message (all are included by default).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Attachments {
+    excluded: [bool; 5],
+}
+
+impl Attachments {
+    pub fn includes(&self, kind: ChipKind) -> bool {
+        !self.excluded[kind.index()]
+    }
+
+    pub fn toggle(&mut self, kind: ChipKind) {
+        self.excluded[kind.index()] = !self.excluded[kind.index()];
+    }
+
+    /// The snapshot without the sections left out. Leaving out the commands
+    /// also leaves out the history and the recent output; leaving out the
+    /// directory also leaves out its project files.
+    pub fn apply(&self, mut snapshot: ContextSnapshot) -> ContextSnapshot {
+        if !self.includes(ChipKind::Cwd) {
+            snapshot.cwd.clear();
+        }
+        if !self.includes(ChipKind::Project) || !self.includes(ChipKind::Cwd) {
+            snapshot.project = None;
+        }
+        if !self.includes(ChipKind::Commands) {
+            snapshot.recent_commands.clear();
+            snapshot.recent_history.clear();
+            snapshot.recent_output.clear();
+        }
+        if !self.includes(ChipKind::Env) {
+            snapshot.env_vars.clear();
+        }
+        snapshot
+    }
+
+    /// Shell2 sections not to collect. The files and git probes run in the
+    /// working directory, so leaving it out leaves them out too.
+    pub fn excluded_sections(&self) -> Vec<ContextSection> {
+        let mut sections = Vec::new();
+        if !self.includes(ChipKind::Git) || !self.includes(ChipKind::Cwd) {
+            sections.push(ContextSection::Git);
+        }
+        if !self.includes(ChipKind::Cwd) {
+            sections.push(ContextSection::Files);
+        }
+        sections
+    }
+}
+
+/// A chip placed on the line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlacedChip {
+    pub kind: ChipKind,
+    /// Column of the chip's first cell, from the start of the line
+    pub x: u16,
+    /// Columns, padding included
+    pub width: u16,
+    /// Label, as shortened to fit
+    pub label: String,
+}
+
+/// Place the chips of `summary` on a line `width` columns wide.
+pub fn layout_chips(summary: &ContextSummary, width: u16) -> Vec<PlacedChip> {
+    let width = width as usize;
+    let mut chips: Vec<(ChipKind, String)> = ChipKind::ALL
+        .iter()
+        .filter(|&&kind| summary.shows(kind))
+        .map(|&kind| (kind, full_label(summary, kind)))
+        .collect();
+
+    let total = |chips: &[(ChipKind, String)]| -> usize {
+        let labels: usize = chips.iter().map(|(_, label)| label.width() + CHIP_PADDING).sum();
+        labels + CHIP_GAP * chips.len().saturating_sub(1)
+    };
+
+    // Shorten the directory, then the branch and the project
+    for kind in [ChipKind::Cwd, ChipKind::Git, ChipKind::Project] {
+        let excess = total(&chips).saturating_sub(width);
+        if excess == 0 {
+            break;
+        }
+        if let Some(chip) = chips.iter_mut().find(|(k, _)| *k == kind) {
+            chip.1 = shorten(summary, kind, chip.1.width().saturating_sub(excess));
+        }
+    }
+    // Then drop chips from the end
+    while total(&chips) > width {
+        chips.pop();
+    }
+
+    let mut x = 0;
+    chips
+        .into_iter()
+        .map(|(kind, label)| {
+            let chip_width = label.width() + CHIP_PADDING;
+            let placed = PlacedChip { kind, x: x as u16, width: chip_width as u16, label };
+            x += chip_width + CHIP_GAP;
+            placed
+        })
+        .collect()
+}
+
+fn full_label(summary: &ContextSummary, kind: ChipKind) -> String {
+    match kind {
+        ChipKind::Cwd => format!("{}{}", CWD_ICON, summary.cwd),
+        ChipKind::Git => format!("{}{}", GIT_PREFIX, summary.git_branch.as_deref().unwrap_or_default()),
+        ChipKind::Project => format!("{}{}", PROJECT_ICON, summary.project.as_deref().unwrap_or_default()),
+        ChipKind::Commands if summary.commands == 1 => "⌘ 1 cmd".to_string(),
+        ChipKind::Commands => format!("⌘ {} cmds", summary.commands),
+        ChipKind::Env => format!("env {}", summary.env),
+    }
+}
+
+/// The label of a directory, branch or project chip in at most `max`
+/// columns (but never less than the prefix and [`MIN_ELIDED`] columns).
+fn shorten(summary: &ContextSummary, kind: ChipKind, max: usize) -> String {
+    let (prefix, text) = match kind {
+        ChipKind::Cwd => (CWD_ICON, summary.cwd.as_str()),
+        ChipKind::Git => (GIT_PREFIX, summary.git_branch.as_deref().unwrap_or_default()),
+        ChipKind::Project => (PROJECT_ICON, summary.project.as_deref().unwrap_or_default()),
+        _ => return full_label(summary, kind),
+    };
+    let room = max.saturating_sub(prefix.width()).max(MIN_ELIDED);
+    if text.width() <= room {
+        return format!("{}{}", prefix, text);
+    }
+    let kept = room - 1;
+    let text = match kind {
+        // The end of a path says more than its start
+        ChipKind::Cwd => format!("…{}", take_width(text.chars().rev(), kept).chars().rev().collect::<String>()),
+        _ => format!("{}…", take_width(text.chars(), kept)),
+    };
+    format!("{}{}", prefix, text)
+}
+
+/// Characters from `chars` while they fit in `max` columns.
+fn take_width(chars: impl Iterator<Item = char>, max: usize) -> String {
+    let mut used = 0;
+    chars
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= max
+        })
+        .collect()
+}
+
+/// Draw the chips on the first row of `area`; left-out sections are dim
+/// and struck through.
+pub fn render_chips(chips: &[PlacedChip], attachments: Attachments, area: Rect, buf: &mut Buffer) {
+    if area.height == 0 {
+        return;
+    }
+    for chip in chips {
+        let style = if attachments.includes(chip.kind) {
+            Style::default().fg(Color::Black).bg(chip.kind.color())
+        } else {
+            Style::default().fg(Color::DarkGray).add_modifier(Modifier::CROSSED_OUT)
+        };
+        let x = area.x + chip.x;
+        if x >= area.right() {
+            break;
+        }
+        let text = format!(" {} ", chip.label);
+        buf.set_stringn(x, area.y, text, (area.right() - x) as usize, style);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::{CommandRecord, ProjectContext};
+
+    fn snapshot() -> ContextSnapshot {
+        ContextSnapshot {
+            cwd: "/home/dev/src/app".to_string(),
+            env_vars: std::iter::once(("HOME".to_string(), "/home/dev".to_string()))
+                .chain((1..12).map(|i| (format!("VAR{}", i), i.to_string())))
+                .collect(),
+            recent_history: vec!["make".to_string()],
+            recent_output: vec!["ok".to_string()],
+            recent_commands: vec![
+                CommandRecord::new("ls".to_string(), String::new()),
+                CommandRecord::new("make".to_string(), String::new()),
+                CommandRecord::new("make test".to_string(), String::new()),
+            ],
+            project: None,
+            files: Vec::new(),
+            screen: None,
+        }
+    }
+
+    fn labels(chips: &[PlacedChip]) -> Vec<&str> {
+        chips.iter().map(|chip| chip.label.as_str()).collect()
+    }
+
+    fn line_width(chips: &[PlacedChip]) -> u16 {
+        chips.last().map(|chip| chip.x + chip.width).unwrap_or(0)
+    }
+
+    #[test]
+    fn test_summary_from_snapshot() {
+        let summary = ContextSummary::from_snapshot(&snapshot(), Some("main".to_string()));
+        let chips = layout_chips(&summary, 80);
+        assert_eq!(labels(&chips), vec!["📁 ~/src/app", "git:main", "⌘ 3 cmds", "env 12"]);
+        // " 📁 ~/src/app " is 14 columns, then one column between chips
+        assert_eq!(chips.iter().map(|c| (c.x, c.width)).collect::<Vec<_>>(), vec![(0, 14), (15, 10), (26, 10), (37, 8)]);
+
+        // Only what there is: no repository, no records (history counts)
+        let bare = ContextSnapshot { cwd: "/srv".to_string(), ..ContextSnapshot::empty() };
+        let summary = ContextSummary::from_snapshot(&bare, None);
+        assert_eq!(labels(&layout_chips(&summary, 80)), vec!["📁 /srv"]);
+        let history = ContextSnapshot { recent_history: vec!["ls".to_string()], ..ContextSnapshot::empty() };
+        assert_eq!(labels(&layout_chips(&ContextSummary::from_snapshot(&history, None), 80)), vec!["⌘ 1 cmd"]);
+        assert!(ContextSummary::from_snapshot(&ContextSnapshot::empty(), None).is_empty());
+
+        // A project goes after the branch, and leaves with the directory
+        let project = ProjectContext { name: "rusty-term".to_string(), excerpt: "== Cargo.toml ==".to_string() };
+        let in_project = ContextSnapshot { project: Some(project), ..snapshot() };
+        let summary = ContextSummary::from_snapshot(&in_project, Some("main".to_string()));
+        assert_eq!(labels(&layout_chips(&summary, 80)), vec!["📁 ~/src/app", "git:main", "📦 rusty-term", "⌘ 3 cmds", "env 12"]);
+        assert_eq!(labels(&layout_chips(&summary, 50)), vec!["📁 …app", "git:main", "📦 rus…", "⌘ 3 cmds", "env 12"]);
+        let mut attachments = Attachments::default();
+        assert!(attachments.apply(in_project.clone()).project.is_some());
+        attachments.toggle(ChipKind::Cwd);
+        assert!(attachments.apply(in_project.clone()).project.is_none());
+        attachments.toggle(ChipKind::Cwd);
+        attachments.toggle(ChipKind::Project);
+        assert!(attachments.apply(in_project).project.is_none());
+    }
+
+    #[test]
+    fn test_chips_never_overflow() {
+        let summary = ContextSummary::from_snapshot(&snapshot(), Some("feature/very-long-branch-name".to_string()));
+        for width in 0..100 {
+            let chips = layout_chips(&summary, width);
+            assert!(line_width(&chips) <= width, "overflow at {}", width);
+            // Same width, same line
+            assert_eq!(chips, layout_chips(&summary, width));
+        }
+    }
+
+    #[test]
+    fn test_directory_shrinks_first_then_branch_then_chips_drop() {
+        let summary = ContextSummary::from_snapshot(&snapshot(), Some("feature/chips".to_string()));
+        // Everything fits at 54 columns
+        assert_eq!(labels(&layout_chips(&summary, 54)), vec!["📁 ~/src/app", "git:feature/chips", "⌘ 3 cmds", "env 12"]);
+        // Four columns short: the path loses its start
+        assert_eq!(labels(&layout_chips(&summary, 50)), vec!["📁 …/app", "git:feature/chips", "⌘ 3 cmds", "env 12"]);
+        // The path is as short as it gets; the branch goes next
+        assert_eq!(labels(&layout_chips(&summary, 44)), vec!["📁 …app", "git:feature…", "⌘ 3 cmds", "env 12"]);
+        assert_eq!(labels(&layout_chips(&summary, 40)), vec!["📁 …app", "git:fea…", "⌘ 3 cmds", "env 12"]);
+        // Both at their shortest; env, then the commands, then the branch are dropped
+        assert_eq!(labels(&layout_chips(&summary, 39)), vec!["📁 …app", "git:fea…", "⌘ 3 cmds"]);
+        assert_eq!(labels(&layout_chips(&summary, 30)), vec!["📁 …app", "git:fea…"]);
+        assert_eq!(labels(&layout_chips(&summary, 19)), vec!["📁 …app"]);
+        assert_eq!(labels(&layout_chips(&summary, 9)), vec!["📁 …app"]);
+        assert!(layout_chips(&summary, 8).is_empty());
+    }
+
+    #[test]
+    fn test_attachments_leave_sections_out() {
+        let mut attachments = Attachments::default();
+        assert_eq!(attachments.apply(snapshot()).recent_commands.len(), 3);
+        assert!(attachments.excluded_sections().is_empty());
+
+        attachments.toggle(ChipKind::Commands);
+        attachments.toggle(ChipKind::Env);
+        let applied = attachments.apply(snapshot());
+        assert!(applied.recent_commands.is_empty() && applied.recent_history.is_empty());
+        assert!(applied.recent_output.is_empty() && applied.env_vars.is_empty());
+        assert_eq!(applied.cwd, "/home/dev/src/app");
+
+        attachments.toggle(ChipKind::Git);
+        assert_eq!(attachments.excluded_sections(), vec![ContextSection::Git]);
+        attachments.toggle(ChipKind::Git);
+        attachments.toggle(ChipKind::Cwd);
+        assert!(attachments.apply(snapshot()).cwd.is_empty());
+        assert_eq!(attachments.excluded_sections(), vec![ContextSection::Git, ContextSection::Files]);
+
+        // Toggling back includes everything again
+        for kind in [ChipKind::Cwd, ChipKind::Commands, ChipKind::Env] {
+            attachments.toggle(kind);
+        }
+        assert_eq!(attachments, Attachments::default());
+    }
+
+    #[test]
+    fn test_left_out_chips_are_struck_through() {
+        let summary = ContextSummary::from_snapshot(&snapshot(), None);
+        let chips = layout_chips(&summary, 40);
+        let mut attachments = Attachments::default();
+        attachments.toggle(ChipKind::Env);
+        let area = Rect::new(0, 0, 40, 1);
+        let mut buf = Buffer::empty(area);
+        render_chips(&chips, attachments, area, &mut buf);
+
+        let env = &chips[2];
+        assert_eq!(buf[(env.x + 1, 0)].symbol(), "e");
+        assert!(buf[(env.x + 1, 0)].modifier.contains(Modifier::CROSSED_OUT));
+        assert_eq!(buf[(1, 0)].bg, Color::Cyan);
+    }
+}
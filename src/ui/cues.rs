@@ -0,0 +1,204 @@
+//! Bell and desktop notification cues for things that finish while the user
+//! looks elsewhere.
+//!
+//! Each [`CueEvent`] maps to a [`Cue`] in the `[cues]` config section. Fired
+//! cues are queued and written to the host terminal by [`Cues::flush`],
+//! which the main loop calls right after a frame is drawn, so an escape
+//! sequence never lands in the middle of one. The bell is held back while
+//! the user is typing ([`TYPING_QUIET`] since the last key): they are
+//! looking at the screen already. Command mode `b` mutes everything.
+
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use crate::config::{Cue, CuesConfig};
+
+/// How long after the last key the bell stays quiet.
+pub const TYPING_QUIET: Duration = Duration::from_secs(2);
+
+/// Something that can ring a cue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CueEvent {
+    /// An AI reply finished streaming
+    AiFinished,
+    /// The AI suggested a command
+    Suggestion,
+    /// A shell command exited with a nonzero status
+    CommandFailed,
+    /// A shell command ran longer than the configured threshold
+    LongCommand,
+}
+
+impl CueEvent {
+    /// Text of the desktop notification.
+    fn message(self) -> &'static str {
+        match self {
+            CueEvent::AiFinished => "AI reply finished",
+            CueEvent::Suggestion => "AI suggested a command",
+            CueEvent::CommandFailed => "Command failed",
+            CueEvent::LongCommand => "Command finished",
+        }
+    }
+}
+
+/// The cue configuration, the mute toggle and what waits to be written.
+#[derive(Debug, Clone, Default)]
+pub struct Cues {
+    config: CuesConfig,
+    muted: bool,
+    last_input: Option<Instant>,
+    pending: Vec<u8>,
+}
+
+impl Cues {
+    pub fn new(config: CuesConfig) -> Self {
+        let muted = config.muted;
+        Self { config, muted, last_input: None, pending: Vec::new() }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Mute or unmute every cue. Returns whether they are muted now.
+    pub fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        if self.muted {
+            self.pending.clear();
+        }
+        self.muted
+    }
+
+    /// The user pressed a key at `now`.
+    pub fn note_input(&mut self, now: Instant) {
+        self.last_input = Some(now);
+    }
+
+    /// A shell command finished with `exit_code` after running `ran`
+    /// (None = unknown): a failure cue, else a long command cue.
+    pub fn command_finished(&mut self, exit_code: i32, ran: Option<Duration>, now: Instant) {
+        if exit_code != 0 {
+            self.fire(CueEvent::CommandFailed, now);
+        } else if ran.is_some_and(|ran| ran >= Duration::from_secs(self.config.long_command_secs)) {
+            self.fire(CueEvent::LongCommand, now);
+        }
+    }
+
+    /// Queue the cue configured for `event`.
+    pub fn fire(&mut self, event: CueEvent, now: Instant) {
+        if self.muted {
+            return;
+        }
+        let cue = match event {
+            CueEvent::AiFinished => self.config.ai_finished,
+            CueEvent::Suggestion => self.config.suggestion,
+            CueEvent::CommandFailed => self.config.command_failed,
+            CueEvent::LongCommand => self.config.long_command,
+        };
+        let typing = self.last_input.is_some_and(|at| now.saturating_duration_since(at) < TYPING_QUIET);
+        if matches!(cue, Cue::Bell | Cue::Both) && !typing {
+            self.pending.push(0x07);
+        }
+        if matches!(cue, Cue::Notify | Cue::Both) {
+            self.pending.extend_from_slice(notification(event.message()).as_bytes());
+        }
+    }
+
+    /// Bytes queued for the host terminal.
+    pub fn pending(&self) -> &[u8] {
+        &self.pending
+    }
+
+    /// Write the queued cues to `out`. Call between frames only.
+    pub fn flush(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending);
+        out.write_all(&pending)?;
+        out.flush()
+    }
+}
+
+/// OSC 9 desktop notification with `text` (iTerm2, kitty, WezTerm, foot;
+/// ignored by terminals that don't know it).
+fn notification(text: &str) -> String {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    format!("\x1b]9;RustyTerm: {}\x07", text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cues(cue: Cue) -> Cues {
+        Cues::new(CuesConfig {
+            ai_finished: cue,
+            suggestion: cue,
+            command_failed: cue,
+            long_command: cue,
+            ..CuesConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_bell_is_quiet_while_typing() {
+        let start = Instant::now();
+        let mut cues = cues(Cue::Bell);
+        cues.note_input(start);
+
+        cues.fire(CueEvent::AiFinished, start + Duration::from_millis(1500));
+        assert!(cues.pending().is_empty());
+        cues.fire(CueEvent::AiFinished, start + TYPING_QUIET);
+        assert_eq!(cues.pending(), b"\x07");
+    }
+
+    #[test]
+    fn test_notification_is_sent_while_typing() {
+        let start = Instant::now();
+        let mut cues = cues(Cue::Both);
+        cues.note_input(start);
+
+        cues.fire(CueEvent::Suggestion, start + Duration::from_millis(100));
+        assert_eq!(cues.pending(), b"\x1b]9;RustyTerm: AI suggested a command\x07");
+    }
+
+    #[test]
+    fn test_mute_drops_queued_and_new_cues() -> std::io::Result<()> {
+        let start = Instant::now();
+        let mut cues = cues(Cue::Bell);
+        cues.fire(CueEvent::CommandFailed, start);
+        assert!(cues.toggle_mute());
+        assert!(cues.pending().is_empty());
+        cues.fire(CueEvent::CommandFailed, start);
+        assert!(cues.pending().is_empty());
+
+        assert!(!cues.toggle_mute());
+        cues.fire(CueEvent::CommandFailed, start);
+        let mut out = Vec::new();
+        cues.flush(&mut out)?;
+        assert_eq!(out, b"\x07");
+        assert!(cues.pending().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_cues_go_by_status_then_duration() {
+        let start = Instant::now();
+        let mut cues = Cues::new(CuesConfig {
+            command_failed: Cue::Bell,
+            long_command: Cue::Notify,
+            long_command_secs: 10,
+            ..CuesConfig::default()
+        });
+        cues.command_finished(0, Some(Duration::from_secs(3)), start);
+        cues.command_finished(0, None, start);
+        assert!(cues.pending().is_empty());
+
+        cues.command_finished(2, Some(Duration::from_secs(1)), start);
+        assert_eq!(cues.pending(), b"\x07");
+        cues.pending.clear();
+        cues.command_finished(0, Some(Duration::from_secs(12)), start);
+        assert_eq!(cues.pending(), b"\x1b]9;RustyTerm: Command finished\x07");
+    }
+}
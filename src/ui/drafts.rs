@@ -0,0 +1,204 @@
+//! Unsent assistant input and the files dropped on it, kept per session
+//! and across restarts.
+//!
+//! The assistant pane holds one [`InputDraft`] per session: switching tabs
+//! stashes the input of the session left behind and brings back the one of
+//! the session switched to. With `save_drafts` on, a [`DraftStore`] also
+//! writes the drafts of all sessions to `drafts.json` in the data directory,
+//! a short while after the last change, so they survive a restart or a crash.
+//! Sessions themselves are not restored, so after a restart the draft of the
+//! session that was active comes back in the first session.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::ai::session::SessionId;
+use crate::context::FileAttachment;
+use crate::utils::persist::{self, PersistError, StateFile};
+
+/// File name in the data directory.
+const FILE_NAME: &str = "drafts.json";
+
+/// How long after a change the drafts are written.
+pub const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// Unsent text in the input box, with the cursor and selection in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputDraft {
+    pub text: String,
+    /// Byte offset of the cursor
+    pub cursor: usize,
+    /// Byte offset where the selection started, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection_anchor: Option<usize>,
+    /// Files dropped on the input, to go with the message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileAttachment>,
+}
+
+impl InputDraft {
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.files.is_empty()
+    }
+
+    /// Offsets moved back onto char boundaries inside the text (a draft
+    /// file may have been edited by hand).
+    pub fn clamped(mut self) -> Self {
+        let clamp = |mut offset: usize, text: &str| {
+            offset = offset.min(text.len());
+            while !text.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            offset
+        };
+        self.cursor = clamp(self.cursor, &self.text);
+        self.selection_anchor = self.selection_anchor.map(|anchor| clamp(anchor, &self.text));
+        self
+    }
+}
+
+/// Drafts of every session that has one, as written to disk.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedDrafts {
+    /// Session that was active when the drafts were saved
+    pub active: Option<SessionId>,
+    pub drafts: BTreeMap<SessionId, InputDraft>,
+}
+
+impl SavedDrafts {
+    /// The draft to bring back after a restart: the active session's, or
+    /// else the one of the lowest session id.
+    pub fn to_restore(&self) -> Option<&InputDraft> {
+        self.active
+            .and_then(|id| self.drafts.get(&id))
+            .or_else(|| self.drafts.values().next())
+    }
+}
+
+/// Writes drafts to disk, at most once per [`SAVE_DELAY`].
+#[derive(Debug)]
+pub struct DraftStore {
+    file: StateFile,
+    /// What the file holds now
+    saved: SavedDrafts,
+    /// When unsaved changes are due to be written
+    due: Option<Instant>,
+}
+
+impl DraftStore {
+    /// Store backed by `path`. Another instance may own the file, in which
+    /// case drafts are read but never written.
+    pub fn at(path: impl Into<PathBuf>) -> Result<Self, PersistError> {
+        let file = StateFile::open(path, "draft")?;
+        let saved = match file.load() {
+            Ok(Some(bytes)) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable drafts file {}: {}", file.path().display(), e);
+                SavedDrafts::default()
+            }),
+            Ok(None) => SavedDrafts::default(),
+            Err(e) => {
+                warn!("Failed to read drafts file {}: {}", file.path().display(), e);
+                SavedDrafts::default()
+            }
+        };
+        Ok(Self { file, saved, due: None })
+    }
+
+    /// Store in the data directory (None if there is none or it can't be
+    /// opened).
+    pub fn open() -> Option<Self> {
+        let path = persist::data_dir()?.join(FILE_NAME);
+        Self::at(&path)
+            .inspect_err(|e| warn!("Not saving drafts to {}: {}", path.display(), e))
+            .ok()
+    }
+
+    /// Drafts found on disk when the store was opened (or last saved).
+    pub fn saved(&self) -> &SavedDrafts {
+        &self.saved
+    }
+
+    /// The drafts are now `current`: schedule a save if they changed. An
+    /// already scheduled save is not pushed back, so typing without pause
+    /// still saves every [`SAVE_DELAY`].
+    pub fn note(&mut self, current: &SavedDrafts, now: Instant) {
+        if self.file.is_read_only() {
+            return;
+        }
+        if *current == self.saved {
+            self.due = None;
+        } else if self.due.is_none() {
+            self.due = Some(now + SAVE_DELAY);
+        }
+    }
+
+    /// When the next save is due.
+    pub fn due(&self) -> Option<Instant> {
+        self.due
+    }
+
+    /// Write `current` if it differs from what is on disk.
+    pub fn save(&mut self, current: SavedDrafts) {
+        self.due = None;
+        if current == self.saved || self.file.is_read_only() {
+            return;
+        }
+        let result = serde_json::to_vec_pretty(&current)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| self.file.save(&bytes).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => self.saved = current,
+            Err(e) => warn!("Failed to save drafts to {}: {}", self.file.path().display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draft(text: &str, cursor: usize) -> InputDraft {
+        InputDraft { text: text.to_string(), cursor, selection_anchor: None, files: Vec::new() }
+    }
+
+    #[test]
+    fn test_saves_are_debounced_and_survive_reopening() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-drafts-{}", std::process::id()));
+        let path = dir.join(FILE_NAME);
+        let mut store = DraftStore::at(&path)?;
+        assert_eq!(store.saved(), &SavedDrafts::default());
+
+        let mut current = SavedDrafts { active: Some(2), drafts: BTreeMap::new() };
+        current.drafts.insert(1, draft("first question", 5));
+        current.drafts.insert(2, draft("second", 6));
+
+        // The first change schedules a save; more changes don't delay it
+        let start = Instant::now();
+        store.note(&current, start);
+        assert_eq!(store.due(), Some(start + SAVE_DELAY));
+        store.note(&current, start + Duration::from_secs(1));
+        assert_eq!(store.due(), Some(start + SAVE_DELAY));
+
+        store.save(current.clone());
+        assert_eq!(store.due(), None);
+        drop(store);
+
+        let reopened = DraftStore::at(&path)?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(reopened.saved(), &current);
+        assert_eq!(reopened.saved().to_restore(), Some(&draft("second", 6)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_clamped_offsets_land_on_char_boundaries() {
+        let restored = InputDraft { text: "héllo".to_string(), cursor: 2, selection_anchor: Some(99), files: Vec::new() }.clamped();
+        assert_eq!(restored.cursor, 1);
+        assert_eq!(restored.selection_anchor, Some(6));
+    }
+}
@@ -0,0 +1,248 @@
+//! Files dropped on the assistant pane.
+//!
+//! A terminal takes a file dropped on it and types its path, quoted or with
+//! its spaces escaped the way a shell wants it, all at once. [`Burst`]
+//! follows what is typed into the assistant input: a run of characters that
+//! came faster than anyone types (each at most [`BURST_GAP`] after the one
+//! before) and is nothing but the absolute path of a readable file (see
+//! [`dropped_path`] and [`dropped_file`]) is taken for a drop, and the pane
+//! offers to attach the file instead. Anything else is left as typed: a
+//! path typed by hand, a pasted command, a path that doesn't exist or is a
+//! directory.
+
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Characters typed closer together than this come from the terminal, not
+/// from a person.
+pub const BURST_GAP: Duration = Duration::from_millis(30);
+
+/// Fewest characters a burst needs to be looked at.
+const MIN_BURST: usize = 4;
+
+/// The characters typed in a burst, at the end of what was typed.
+#[derive(Debug, Default)]
+pub struct Burst {
+    /// Byte offset in the input where the burst starts
+    start: usize,
+    text: String,
+    /// When its last character was typed
+    last: Option<Instant>,
+}
+
+impl Burst {
+    /// `c` was typed at byte `at` of the input at `now`. It goes on with the
+    /// burst if it came right after its last character, in time and in the
+    /// input; otherwise it starts a new one.
+    pub fn note_typed(&mut self, c: char, at: usize, now: Instant) {
+        let goes_on = self.last.is_some_and(|last| now.saturating_duration_since(last) <= BURST_GAP)
+            && at == self.start + self.text.len();
+        if !goes_on {
+            self.start = at;
+            self.text.clear();
+        }
+        self.text.push(c);
+        self.last = Some(now);
+    }
+
+    /// Forget the burst.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// When the burst is over, if one long enough to look at is going on.
+    pub fn due(&self) -> Option<Instant> {
+        let last = self.last?;
+        (self.text.chars().count() >= MIN_BURST).then_some(last + BURST_GAP)
+    }
+
+    /// The burst, taken once it is over at `now`: where it starts in the
+    /// input, and its text.
+    pub fn take_finished(&mut self, now: Instant) -> Option<(usize, String)> {
+        if now < self.due()? {
+            return None;
+        }
+        let burst = (self.start, std::mem::take(&mut self.text));
+        self.clear();
+        Some(burst)
+    }
+}
+
+/// The path `text` spells if it is one absolute path as terminals type a
+/// dropped file: as it is, in single or double quotes, with backslashes
+/// before spaces and other special characters, or as a `file://` URI. A
+/// leading `~/` is left for [`dropped_file`] to expand. None for anything
+/// else, including several paths or a path with unquoted spaces.
+pub fn dropped_path(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.chars().any(char::is_control) {
+        return None;
+    }
+    let path = match text.strip_prefix("file://") {
+        Some(uri) => percent_decode(uri)?,
+        None => shell_word(text)?,
+    };
+    (path.starts_with('/') || path.starts_with("~/")).then_some(path)
+}
+
+/// `text` as a single shell word, with its quotes and backslashes removed.
+fn shell_word(text: &str) -> Option<String> {
+    let mut word = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => word.push(chars.next()?),
+            '\'' => loop {
+                match chars.next()? {
+                    '\'' => break,
+                    c => word.push(c),
+                }
+            },
+            '"' => loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => match chars.next()? {
+                        c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                        c => {
+                            word.push('\\');
+                            word.push(c);
+                        }
+                    },
+                    c => word.push(c),
+                }
+            },
+            c if c.is_whitespace() => return None,
+            c => word.push(c),
+        }
+    }
+    Some(word)
+}
+
+/// The path of a `file://` URI (after the scheme), with `%XX` escapes
+/// decoded.
+fn percent_decode(uri: &str) -> Option<String> {
+    // file://localhost/path names the local host too
+    let uri = uri.strip_prefix("localhost").unwrap_or(uri);
+    let mut bytes = Vec::with_capacity(uri.len());
+    let mut rest = uri.as_bytes();
+    while let Some((&b, after)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(after.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &after[2..];
+        } else if b.is_ascii_whitespace() {
+            return None;
+        } else {
+            bytes.push(b);
+            rest = after;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// The file `text` drops and its size, if [`dropped_path`] reads a path in
+/// it and that is a regular file that can be opened. `~/` is `home`.
+pub fn dropped_file(text: &str, home: Option<&str>) -> Option<(PathBuf, u64)> {
+    let path = dropped_path(text)?;
+    let path = match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(home.filter(|home| !home.is_empty())?).join(rest),
+        None => PathBuf::from(path),
+    };
+    let metadata = std::fs::metadata(&path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    File::open(&path).ok()?;
+    Some((path, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_come_quoted_escaped_or_as_uris() {
+        assert_eq!(dropped_path("/var/log/syslog").as_deref(), Some("/var/log/syslog"));
+        // Terminals add a space after, so the next drop doesn't run into it
+        assert_eq!(dropped_path("/var/log/syslog ").as_deref(), Some("/var/log/syslog"));
+        assert_eq!(dropped_path("'/home/me/my error.log'").as_deref(), Some("/home/me/my error.log"));
+        assert_eq!(dropped_path("'/home/me/it'\\''s.log' ").as_deref(), Some("/home/me/it's.log"));
+        assert_eq!(dropped_path("\"/home/me/say \\\"hi\\\".txt\"").as_deref(), Some("/home/me/say \"hi\".txt"));
+        assert_eq!(dropped_path("/home/me/my\\ error\\ \\(1\\).log").as_deref(), Some("/home/me/my error (1).log"));
+        assert_eq!(dropped_path("file:///home/me/my%20error.log").as_deref(), Some("/home/me/my error.log"));
+        assert_eq!(dropped_path("file://localhost/tmp/a.txt").as_deref(), Some("/tmp/a.txt"));
+        assert_eq!(dropped_path("~/notes.md").as_deref(), Some("~/notes.md"));
+    }
+
+    #[test]
+    fn test_unicode_paths_are_kept_whole() {
+        assert_eq!(dropped_path("/home/me/日本語/ログ.txt").as_deref(), Some("/home/me/日本語/ログ.txt"));
+        assert_eq!(dropped_path("'/home/me/résumé final.pdf'").as_deref(), Some("/home/me/résumé final.pdf"));
+        assert_eq!(dropped_path("/home/me/📎\\ notes.md").as_deref(), Some("/home/me/📎 notes.md"));
+        assert_eq!(dropped_path("file:///tmp/%E6%97%A5%E6%9C%AC.txt").as_deref(), Some("/tmp/日本.txt"));
+    }
+
+    #[test]
+    fn test_anything_but_one_absolute_path_is_left_alone() {
+        assert_eq!(dropped_path("ls -la /tmp"), None);
+        assert_eq!(dropped_path("relative/path.txt"), None);
+        assert_eq!(dropped_path("/home/me/my error.log"), None);
+        assert_eq!(dropped_path("/tmp/a.txt /tmp/b.txt"), None);
+        assert_eq!(dropped_path("'/tmp/unterminated"), None);
+        assert_eq!(dropped_path("/tmp/a\nrm -rf ~"), None);
+        assert_eq!(dropped_path("file:///tmp/bad%zz"), None);
+        assert_eq!(dropped_path("~root/.bashrc"), None);
+    }
+
+    #[test]
+    fn test_only_readable_files_are_drops() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-drop-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let file = dir.join("build log ünï.txt");
+        std::fs::write(&file, "error: boom\n")?;
+
+        let quoted = format!("'{}'", file.display());
+        assert_eq!(dropped_file(&quoted, None), Some((file.clone(), 12)));
+        let escaped = file.display().to_string().replace(' ', "\\ ");
+        assert_eq!(dropped_file(&escaped, None), Some((file.clone(), 12)));
+        let home = dir.display().to_string();
+        assert_eq!(dropped_file("~/build\\ log\\ ünï.txt", Some(&home)), Some((file, 12)));
+
+        assert_eq!(dropped_file(&dir.display().to_string(), None), None);
+        assert_eq!(dropped_file(&format!("{}/missing.txt", dir.display()), None), None);
+        assert_eq!(dropped_file("~/build\\ log\\ ünï.txt", None), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_a_burst_is_what_came_too_fast_to_type() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut burst = Burst::default();
+
+        // Typed by hand: each character starts over
+        for (i, c) in "/tmp".chars().enumerate() {
+            burst.note_typed(c, i, ms(200 * i as u64));
+        }
+        assert_eq!(burst.due(), None);
+
+        // A drop after "see " was typed
+        for (i, c) in "/tmp/a.txt".chars().enumerate() {
+            burst.note_typed(c, 4 + i, ms(1000 + i as u64));
+        }
+        assert_eq!(burst.due(), Some(ms(1009) + BURST_GAP));
+        assert_eq!(burst.take_finished(ms(1010)), None);
+        assert_eq!(burst.take_finished(ms(1009) + BURST_GAP), Some((4, "/tmp/a.txt".to_string())));
+        assert_eq!(burst.due(), None);
+
+        // A character typed somewhere else starts over too
+        burst.note_typed('/', 0, ms(2000));
+        burst.note_typed('t', 1, ms(2001));
+        burst.note_typed('m', 7, ms(2002));
+        burst.note_typed('p', 8, ms(2003));
+        assert_eq!(burst.due(), None);
+    }
+}
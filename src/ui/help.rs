@@ -0,0 +1,206 @@
+//! Help overlay: every key that works where the user is.
+//!
+//! Opened with `?` in command mode, it lists the registered actions (see
+//! `event::actions`) for the active pane under their categories, the
+//! current mode's first. Typing filters on the keys, the action name and
+//! the description. The leader, just pressed to get here, is highlighted
+//! wherever the keys start with it.
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Widget};
+use unicode_width::UnicodeWidthStr;
+
+use crate::app::ActivePane;
+use crate::config::keymap::Leader;
+use crate::event::actions::{Action, Category, ACTIONS};
+
+/// State of the open help overlay.
+#[derive(Debug, Clone)]
+pub struct Help {
+    leader: Leader,
+    pane: ActivePane,
+    /// Categories in the order listed, the current mode's first
+    categories: Vec<Category>,
+    query: String,
+    /// First line of the list on display
+    scroll: usize,
+}
+
+impl Help {
+    /// Help for `pane`, with the `current` mode's keys on top.
+    pub fn new(leader: Leader, pane: ActivePane, current: Category) -> Self {
+        let mut categories = vec![current];
+        categories.extend(Category::ALL.into_iter().filter(|category| *category != current));
+        Self { leader, pane, categories, query: String::new(), scroll: 0 }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn push_char(&mut self, c: char) {
+        self.query.push(c);
+        self.scroll = 0;
+    }
+
+    pub fn pop_char(&mut self) {
+        self.query.pop();
+        self.scroll = 0;
+    }
+
+    /// Scroll the list by `delta` lines, stopping at either end.
+    pub fn scroll(&mut self, delta: isize) {
+        let last = self.list_lines().len().saturating_sub(1);
+        self.scroll = self.scroll.saturating_add_signed(delta).min(last);
+    }
+
+    /// The actions matching the query, under their categories in the order
+    /// listed (categories without any left out).
+    pub fn visible(&self) -> Vec<(Category, Vec<&'static Action>)> {
+        self.categories
+            .iter()
+            .filter_map(|&category| {
+                let actions: Vec<&Action> = ACTIONS
+                    .iter()
+                    .filter(|action| action.category == category && action.applies_to(self.pane) && self.matches(action))
+                    .collect();
+                (!actions.is_empty()).then_some((category, actions))
+            })
+            .collect()
+    }
+
+    /// Names of the matching actions, in the order listed.
+    pub fn visible_names(&self) -> Vec<&'static str> {
+        self.visible().into_iter().flat_map(|(_, actions)| actions).map(|action| action.name).collect()
+    }
+
+    /// Every word of the query is somewhere in the keys, name or description.
+    fn matches(&self, action: &Action) -> bool {
+        let text = format!("{} {} {}", action.chord_text(&self.leader), action.name, action.description).to_lowercase();
+        self.query.to_lowercase().split_whitespace().all(|word| text.contains(word))
+    }
+
+    /// The list below the query: a heading per category, then its actions.
+    fn list_lines(&self) -> Vec<Line<'static>> {
+        // Column widths over everything in the pane, so they don't jump
+        // while filtering
+        let in_pane = || ACTIONS.iter().filter(|action| action.applies_to(self.pane));
+        let chord_width = in_pane().map(|action| action.chord_text(&self.leader).width()).max().unwrap_or(0);
+        let name_width = in_pane().map(|action| action.name.width()).max().unwrap_or(0);
+
+        let leader = self.leader.to_string();
+        let pressed = Style::default().fg(Color::Black).bg(Color::Yellow);
+        let mut lines = Vec::new();
+        for (i, (category, actions)) in self.visible().into_iter().enumerate() {
+            if i > 0 {
+                lines.push(Line::from(""));
+            }
+            let mut heading = category.title(self.pane).to_string();
+            if category == self.categories[0] {
+                heading.push_str(" (current)");
+            }
+            lines.push(Line::from(format!(" {}", heading)).style(Style::default().add_modifier(Modifier::BOLD)));
+
+            for action in actions {
+                let chords = action.chord_text(&self.leader);
+                let (prefix, rest) = match chords.strip_prefix(leader.as_str()) {
+                    Some(rest) if action.starts_with_leader() => (leader.clone(), rest.to_string()),
+                    _ => (String::new(), chords.clone()),
+                };
+                let padding = chord_width.saturating_sub(chords.width()) + 2;
+                lines.push(Line::from(vec![
+                    Span::raw("   "),
+                    Span::styled(prefix, pressed),
+                    Span::raw(format!("{}{}", rest, " ".repeat(padding))),
+                    Span::styled(format!("{:<width$}  ", action.name, width = name_width), Style::default().fg(Color::Cyan)),
+                    Span::raw(action.description),
+                ]));
+            }
+        }
+        if lines.is_empty() {
+            lines.push(Line::from(" no match").style(Style::default().fg(Color::DarkGray)));
+        }
+        lines
+    }
+}
+
+impl Widget for &Help {
+    /// Draw the help centered in `area` (the whole screen).
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let width = area.width.saturating_sub(4).min(120);
+        let height = area.height.saturating_sub(4);
+        let popup = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        };
+
+        // Query line, a blank line, then the list from the scroll position
+        let rows_height = (height as usize).saturating_sub(4);
+        let mut lines = vec![Line::from(format!(" > {}", self.query)), Line::from("")];
+        lines.extend(self.list_lines().into_iter().skip(self.scroll).take(rows_height));
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(" Keys ")
+            .title_bottom(" type to filter · ↑/↓ PgUp/PgDn · Esc/? close ")
+            .border_style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(popup);
+        Clear.render(popup, buf);
+        block.render(popup, buf);
+        Paragraph::new(lines).render(inner, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_action_is_listed_exactly_once() {
+        for pane in [ActivePane::Terminal, ActivePane::Assistant] {
+            let names = Help::new(Leader::default(), pane, Category::Command).visible_names();
+            for action in ACTIONS {
+                let listed = names.iter().filter(|name| **name == action.name).count();
+                let expected = usize::from(action.applies_to(pane));
+                assert_eq!(listed, expected, "{} in {:?}", action.name, pane);
+            }
+        }
+    }
+
+    #[test]
+    fn test_current_mode_is_listed_first() {
+        let help = Help::new(Leader::default(), ActivePane::Terminal, Category::Visual);
+        let categories: Vec<Category> = help.visible().into_iter().map(|(category, _)| category).collect();
+        assert_eq!(categories[0], Category::Visual);
+        // Nothing typed in the terminal pane is RustyTerm's, so no such heading
+        assert!(!categories.contains(&Category::Pane));
+        assert!(!categories.contains(&Category::Card));
+    }
+
+    #[test]
+    fn test_filter_matches_chords_and_descriptions() -> Result<(), String> {
+        let mut help = Help::new("ctrl+a".parse()?, ActivePane::Assistant, Category::Command);
+        "ctrl+y".chars().for_each(|c| help.push_char(c));
+        assert_eq!(help.visible_names(), vec!["run-suggestion"]);
+
+        // Command mode keys are matched with the leader in front
+        help = Help::new("ctrl+a".parse()?, ActivePane::Assistant, Category::Command);
+        "ctrl+a shift+n".chars().for_each(|c| help.push_char(c));
+        assert_eq!(help.visible_names(), vec!["line-numbers"]);
+
+        help = Help::new(Leader::default(), ActivePane::Assistant, Category::Command);
+        "CLIPBOARD".chars().for_each(|c| help.push_char(c));
+        assert_eq!(help.visible_names(), vec!["copy-screen", "paste"]);
+
+        help.push_char('x');
+        assert!(help.visible_names().is_empty());
+        help.pop_char();
+        assert_eq!(help.query(), "CLIPBOARD");
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+//! Where the assistant input goes on screen.
+//!
+//! The input is laid out a grapheme at a time, not a character at a time:
+//! a CJK character, a Hangul syllable typed as jamo or an emoji ZWJ
+//! sequence that doesn't fit at the end of a row moves to the next row
+//! whole. Rendering, the hardware cursor (where an IME anchors its popup),
+//! the arrow keys and Backspace all go by the same [`InputLayout`], so none
+//! of them ever lands inside a grapheme or disagrees about where one is.
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Non-ASCII characters typed closer together than this come from an IME
+/// committing (or a terminal replaying) a composition, not from a person.
+pub const COMPOSITION_GAP: Duration = Duration::from_millis(30);
+
+/// Whether the input is being typed in a composition burst. While it is,
+/// the input box keeps the rows it had, so it doesn't grow and shrink as
+/// the composed text is replaced character by character.
+#[derive(Debug, Default)]
+pub struct Composition {
+    /// When the last non-ASCII character was typed
+    last: Option<Instant>,
+    /// It came right after another one
+    burst: bool,
+    /// Rows the input box showed last
+    rows: Cell<u16>,
+}
+
+impl Composition {
+    /// `c` was typed at `now`. ASCII ends a burst; deleting doesn't, since
+    /// IMEs that resend the composition delete the old one first.
+    pub fn note_typed(&mut self, c: char, now: Instant) {
+        if c.is_ascii() {
+            self.last = None;
+            self.burst = false;
+        } else {
+            self.burst = self.is_recent(now);
+            self.last = Some(now);
+        }
+    }
+
+    fn is_recent(&self, now: Instant) -> bool {
+        self.last.is_some_and(|last| now.saturating_duration_since(last) < COMPOSITION_GAP)
+    }
+
+    /// Rows to show for input that lays out in `rows`: never fewer than
+    /// last time while a burst lasts.
+    pub fn rows(&self, rows: u16, now: Instant) -> u16 {
+        let rows = if self.burst && self.is_recent(now) { rows.max(self.rows.get()) } else { rows };
+        self.rows.set(rows);
+        rows
+    }
+}
+
+/// A grapheme of the input and the cell it starts in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placed<'a> {
+    /// Byte offset in the input
+    pub byte: usize,
+    pub text: &'a str,
+    pub row: u16,
+    pub col: u16,
+    /// Cells it takes (0 for a newline)
+    pub width: u16,
+}
+
+impl Placed<'_> {
+    fn is_newline(&self) -> bool {
+        self.width == 0
+    }
+}
+
+/// Cells `grapheme` takes. Control characters and lone combining marks
+/// take one, like any other character the terminal shows as a box.
+pub fn grapheme_width(grapheme: &str) -> u16 {
+    u16::try_from(grapheme.width()).unwrap_or(u16::MAX).max(1)
+}
+
+/// Start of the grapheme before `byte` in `text`.
+pub fn prev_boundary(text: &str, byte: usize) -> usize {
+    text[..byte].grapheme_indices(true).next_back().map_or(0, |(i, _)| i)
+}
+
+/// End of the grapheme at `byte` in `text`.
+pub fn next_boundary(text: &str, byte: usize) -> usize {
+    text[byte..].graphemes(true).next().map_or(text.len(), |g| byte + g.len())
+}
+
+/// The input's graphemes placed in a box `width` cells wide, the first row
+/// starting after the prompt.
+#[derive(Debug)]
+pub struct InputLayout<'a> {
+    text: &'a str,
+    placed: Vec<Placed<'a>>,
+    width: u16,
+    /// Where a grapheme typed at the end would go, before wrapping
+    end: (u16, u16),
+}
+
+impl<'a> InputLayout<'a> {
+    pub fn new(text: &'a str, prompt_width: u16, width: u16) -> Self {
+        let (mut row, mut col) = (0u16, prompt_width);
+        let mut placed = Vec::new();
+        for (byte, g) in text.grapheme_indices(true) {
+            if g.starts_with('\n') || g == "\r\n" {
+                placed.push(Placed { byte, text: g, row, col, width: 0 });
+                row += 1;
+                col = 0;
+                continue;
+            }
+            let width_of = grapheme_width(g);
+            // Too wide for what's left of the row: all of it goes to the
+            // next (a row that is empty keeps it, however wide)
+            if col > 0 && col.saturating_add(width_of) > width {
+                row += 1;
+                col = 0;
+            }
+            placed.push(Placed { byte, text: g, row, col, width: width_of });
+            col = col.saturating_add(width_of);
+        }
+        Self { text, placed, width, end: (row, col) }
+    }
+
+    /// Rows the input takes (at least one).
+    pub fn rows(&self) -> u16 {
+        self.end.0 + 1
+    }
+
+    pub fn placed(&self) -> &[Placed<'a>] {
+        &self.placed
+    }
+
+    /// Row and column of the cursor at `byte`: on the grapheme there, or
+    /// after the last one. A cursor inside a grapheme shows on its start.
+    pub fn cursor(&self, byte: usize) -> (u16, u16) {
+        match self.placed.iter().find(|p| p.byte + p.text.len() > byte) {
+            // A newline at the right edge: the cursor can't go past it
+            Some(p) if p.is_newline() && p.col >= self.width => (p.row + 1, 0),
+            Some(p) => (p.row, p.col),
+            None => self.end,
+        }
+    }
+
+    /// Where the cursor goes on `row` to stay near column `col`: the first
+    /// grapheme starting at or after it, or else the end of the row. None
+    /// if there is no such row.
+    pub fn byte_at(&self, row: u16, col: u16) -> Option<usize> {
+        if row > self.end.0 {
+            return None;
+        }
+        let mut last = None;
+        for p in self.placed.iter().filter(|p| p.row == row) {
+            if p.is_newline() || p.col >= col {
+                return Some(p.byte);
+            }
+            last = Some(p.byte);
+        }
+        // A row that wraps ends on its last grapheme; the last row at the
+        // end of the input
+        if row == self.end.0 { Some(self.text.len()) } else { last }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rows of `text` as laid out, without the prompt.
+    fn rows(text: &str, prompt_width: u16, width: u16) -> Vec<String> {
+        let layout = InputLayout::new(text, prompt_width, width);
+        let mut rows = vec![String::new(); layout.rows() as usize];
+        for p in layout.placed().iter().filter(|p| !p.is_newline()) {
+            rows[p.row as usize].push_str(p.text);
+        }
+        rows
+    }
+
+    #[test]
+    fn test_wide_graphemes_wrap_whole() {
+        // "> " then 3 of the 4 cells left: the second character doesn't fit
+        assert_eq!(rows("日本語", 2, 5), vec!["日", "本語"]);
+        let layout = InputLayout::new("日本語", 2, 5);
+        assert_eq!(layout.cursor("日".len()), (1, 0));
+        assert_eq!(layout.cursor("日本語".len()), (1, 4));
+
+        // A ZWJ sequence and a flag are one grapheme of two cells each
+        let family = "👩\u{200d}💻";
+        assert_eq!(rows(&format!("ab{}🇯🇵", family), 0, 4), vec![format!("ab{}", family), "🇯🇵".to_string()]);
+        // Hangul typed as jamo is one syllable
+        assert_eq!(rows("\u{1112}\u{1161}\u{11ab}x", 0, 2), vec!["\u{1112}\u{1161}\u{11ab}", "x"]);
+    }
+
+    #[test]
+    fn test_boundaries_step_over_graphemes() {
+        let text = "a\u{1112}\u{1161}\u{11ab}👩\u{200d}💻e\u{301}";
+        let mut stops = vec![0];
+        while let Some(&last) = stops.last().filter(|&&b| b < text.len()) {
+            stops.push(next_boundary(text, last));
+        }
+        assert_eq!(stops, vec![0, 1, 10, 21, 24]);
+        let mut back = vec![text.len()];
+        while let Some(&last) = back.last().filter(|&&b| b > 0) {
+            back.push(prev_boundary(text, last));
+        }
+        back.reverse();
+        assert_eq!(back, stops);
+    }
+
+    #[test]
+    fn test_vertical_moves_keep_the_column() {
+        // Row 0: "> 一二", row 1: "三四", row 2 after a newline: "abcdef"
+        let text = "一二三四\nabcdef";
+        let layout = InputLayout::new(text, 2, 6);
+        assert_eq!(layout.rows(), 3);
+        // The first grapheme from the column on: 四 starts at column 2
+        assert_eq!(layout.byte_at(1, 2), Some("一二三".len()));
+        // Past the end of a row ended by a newline: before the newline
+        assert_eq!(layout.byte_at(1, 3), Some("一二三四".len()));
+        // Past the end of a wrapped row: on its last grapheme, so the
+        // cursor stays on that row
+        assert_eq!(layout.byte_at(0, 5), Some("一".len()));
+        assert_eq!(layout.cursor("一".len()), (0, 4));
+        assert_eq!(layout.byte_at(2, 4), Some("一二三四\nabcd".len()));
+        assert_eq!(layout.byte_at(2, 9), Some(text.len()));
+        assert_eq!(layout.byte_at(3, 0), None);
+    }
+
+    #[test]
+    fn test_composition_burst_holds_the_rows() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut composition = Composition::default();
+        assert_eq!(composition.rows(2, ms(0)), 2);
+
+        // One character on its own isn't a burst
+        composition.note_typed('한', ms(0));
+        assert_eq!(composition.rows(1, ms(1)), 1);
+        composition.note_typed('국', ms(10));
+        assert_eq!(composition.rows(2, ms(11)), 2);
+        // Mid-burst the box doesn't shrink back
+        assert_eq!(composition.rows(1, ms(12)), 2);
+        // Once it's over, it does
+        assert_eq!(composition.rows(1, ms(10) + COMPOSITION_GAP), 1);
+
+        // ASCII ends a burst at once
+        composition.note_typed('어', ms(100));
+        composition.note_typed('어', ms(110));
+        assert_eq!(composition.rows(3, ms(111)), 3);
+        composition.note_typed(' ', ms(112));
+        assert_eq!(composition.rows(1, ms(113)), 1);
+    }
+}
@@ -3,10 +3,44 @@
 //! This module handles the layout calculation and configuration for the split-pane
 //! interface between terminal and assistant panes. It separates the layout logic
 //! from the main application state.
+//!
+//! Small screens degrade in two steps: below the split threshold only the
+//! active pane is shown, full width; below [`MIN_WIDTH`]x[`MIN_HEIGHT`] no
+//! pane is laid out at all and a placeholder is drawn instead. Sizes past
+//! [`MAX_WIDTH`]x[`MAX_HEIGHT`] are not real screens: some emulators report
+//! them (or 0) for a moment while the window is created.
 
-use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
 use ratatui::widgets::{Block, Borders};
 
+use crate::app::ActivePane;
+
+/// Narrowest screen any pane is laid out on
+pub const MIN_WIDTH: u16 = 30;
+/// Shortest screen any pane is laid out on
+pub const MIN_HEIGHT: u16 = 8;
+/// Widest screen believed
+pub const MAX_WIDTH: u16 = 2000;
+/// Tallest screen believed
+pub const MAX_HEIGHT: u16 = 1000;
+
+/// Whether a reported screen size can be real: past the maximum it is a
+/// bogus report, and laying it out would allocate for billions of cells.
+pub fn plausible_size(width: u16, height: u16) -> bool {
+    width <= MAX_WIDTH && height <= MAX_HEIGHT
+}
+
+/// How much of the layout fits on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutMode {
+    /// Terminal and assistant side by side
+    Split,
+    /// Only the focused pane; the other one has an empty area
+    Single,
+    /// Nothing is laid out; every area is empty
+    TooSmall,
+}
+
 /// Layout builder - holds user preferences and configuration for layout calculation
 ///
 /// This is the "how to build" - contains all the constraints and preferences
@@ -17,9 +51,16 @@ pub struct LayoutBuilder {
     /// This is the user's preference and persists across window resizes
     split_ratio: u16,
 
+    /// Width of the line-number gutter at the left of the terminal pane (0 = hidden)
+    gutter_width: u16,
+
+    /// Smallest screen (width, height) that still gets both panes
+    min_split_size: (u16, u16),
+
+    /// The pane kept when only one fits
+    focus: ActivePane,
+
     // Future extensions can go here:
-    // min_terminal_width: u16,
-    // min_assistant_width: u16,
     // separator_draggable: bool,
     // etc.
 }
@@ -29,6 +70,9 @@ impl LayoutBuilder {
     pub fn new() -> Self {
         Self {
             split_ratio: 60, // Default: 60% terminal, 40% assistant
+            gutter_width: 0,
+            min_split_size: (70, 18),
+            focus: ActivePane::Terminal,
         }
     }
 
@@ -43,36 +87,104 @@ impl LayoutBuilder {
         self.split_ratio
     }
 
+    /// Set the width of the terminal line-number gutter (0 hides it)
+    pub fn with_gutter_width(mut self, width: u16) -> Self {
+        self.gutter_width = width;
+        self
+    }
+
+    /// Get current gutter width
+    pub fn gutter_width(&self) -> u16 {
+        self.gutter_width
+    }
+
+    /// Set the smallest screen that shows both panes side by side
+    pub fn with_min_split_size(mut self, width: u16, height: u16) -> Self {
+        self.min_split_size = (width, height);
+        self
+    }
+
+    /// Set the pane shown when the screen is too small for both
+    pub fn with_focus(mut self, pane: ActivePane) -> Self {
+        self.focus = pane;
+        self
+    }
+
+    /// Which layout mode fits `area`
+    pub fn mode_for(&self, area: Rect) -> LayoutMode {
+        let (split_width, split_height) = self.min_split_size;
+        if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+            LayoutMode::TooSmall
+        } else if area.width < split_width || area.height < split_height {
+            LayoutMode::Single
+        } else {
+            LayoutMode::Split
+        }
+    }
+
     /// Build an AppLayout from this configuration and terminal area
     ///
     /// # Arguments
     /// * `area` - The full terminal area to layout within
     pub fn build(&self, area: ratatui::layout::Rect) -> AppLayout {
-        // Split into three chunks: terminal, separator, assistant
-        let chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([
-                Constraint::Percentage(self.split_ratio),
-                Constraint::Length(1),
-                Constraint::Min(1),
-            ])
-            .split(area);
+        let mode = self.mode_for(area);
+        let (terminal_area, separator_area, assistant_area) = match (mode, self.focus) {
+            (LayoutMode::TooSmall, _) => {
+                let empty = Rect { width: 0, height: 0, ..area };
+                (empty, empty, empty)
+            }
+            // The separator stays as the border on the hidden pane's side
+            (LayoutMode::Single, ActivePane::Terminal) => {
+                let [terminal, separator] =
+                    Layout::horizontal([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+                (terminal, separator, Rect { x: area.right(), width: 0, ..area })
+            }
+            (LayoutMode::Single, ActivePane::Assistant) => {
+                let [separator, assistant] =
+                    Layout::horizontal([Constraint::Length(1), Constraint::Min(0)]).areas(area);
+                (Rect { width: 0, ..area }, separator, assistant)
+            }
+            // Split into three chunks: terminal, separator, assistant
+            (LayoutMode::Split, _) => {
+                let [terminal, separator, assistant] = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Percentage(self.split_ratio),
+                        Constraint::Length(1),
+                        Constraint::Min(1),
+                    ])
+                    .areas(area);
+                (terminal, separator, assistant)
+            }
+        };
 
         // Calculate inner areas (without borders)
         let term_block = Block::default()
             .borders(Borders::TOP | Borders::BOTTOM | Borders::LEFT);
-        let terminal_inner = term_block.inner(chunks[0]);
+        let term_content = term_block.inner(terminal_area);
+
+        // The gutter takes columns from the left of the terminal content; the
+        // terminal itself (and the PTY) only gets what remains
+        let gutter_width = self.gutter_width.min(term_content.width);
+        let terminal_gutter = Rect { width: gutter_width, ..term_content };
+        let terminal_inner = Rect {
+            x: term_content.x + gutter_width,
+            width: term_content.width.saturating_sub(gutter_width),
+            ..term_content
+        };
 
         let ai_block = Block::default()
             .borders(Borders::TOP | Borders::BOTTOM | Borders::RIGHT);
-        let assistant_inner = ai_block.inner(chunks[2]);
+        let assistant_inner = ai_block.inner(assistant_area);
 
         AppLayout {
+            mode,
             full_area: area,
-            terminal_area: chunks[0],
+            terminal_area,
+            terminal_gutter,
             terminal_inner,
-            separator_area: chunks[1],
-            assistant_area: chunks[2],
+            separator_area,
+            assistant_area,
             assistant_inner,
         }
     }
@@ -90,11 +202,15 @@ impl Default for LayoutBuilder {
 /// This is recalculated when the terminal size changes or user preferences change.
 #[derive(Debug, Clone, Copy)]
 pub struct AppLayout {
+    /// How much of the layout fits in `full_area`
+    pub mode: LayoutMode,
     /// Full terminal area
     pub full_area: ratatui::layout::Rect,
     /// Terminal pane area (with borders)
     pub terminal_area: ratatui::layout::Rect,
-    /// Terminal pane inner area (without borders)
+    /// Line-number gutter inside the terminal pane (zero width when hidden)
+    pub terminal_gutter: ratatui::layout::Rect,
+    /// Terminal pane inner area (without borders and gutter)
     pub terminal_inner: ratatui::layout::Rect,
     /// Separator area (the vertical line between panes)
     pub separator_area: ratatui::layout::Rect,
@@ -103,3 +219,126 @@ pub struct AppLayout {
     /// Assistant pane inner area (without borders)
     pub assistant_inner: ratatui::layout::Rect,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gutter_is_taken_from_terminal_content() {
+        let area = Rect::new(0, 0, 100, 30);
+        let plain = LayoutBuilder::new().build(area);
+        assert_eq!(plain.terminal_gutter.width, 0);
+
+        for width in [4u16, 5, 7] {
+            let layout = LayoutBuilder::new().with_gutter_width(width).build(area);
+            assert_eq!(layout.terminal_area, plain.terminal_area);
+            assert_eq!(layout.terminal_gutter.x, plain.terminal_inner.x);
+            assert_eq!(layout.terminal_gutter.width, width);
+            assert_eq!(layout.terminal_gutter.height, plain.terminal_inner.height);
+            assert_eq!(layout.terminal_inner.x, plain.terminal_inner.x + width);
+            assert_eq!(layout.terminal_inner.width, plain.terminal_inner.width - width);
+            assert_eq!(layout.terminal_inner.y, plain.terminal_inner.y);
+        }
+    }
+
+    #[test]
+    fn test_gutter_never_exceeds_terminal_width() {
+        let area = Rect::new(0, 0, 20, 10);
+        let plain = LayoutBuilder::new().build(area);
+        let layout = LayoutBuilder::new().with_gutter_width(50).build(area);
+        assert_eq!(layout.terminal_gutter.width, plain.terminal_inner.width);
+        assert_eq!(layout.terminal_inner.width, 0);
+    }
+
+    fn overlaps(a: Rect, b: Rect) -> bool {
+        !a.intersection(b).is_empty()
+    }
+
+    #[test]
+    fn test_layout_sweep_has_no_overlaps_or_overflow() {
+        for focus in [ActivePane::Terminal, ActivePane::Assistant] {
+            for gutter in [0u16, 5] {
+                let builder = LayoutBuilder::new().with_focus(focus).with_gutter_width(gutter);
+                for width in 0..=160u16 {
+                    for height in [0u16, 1, 2, 3, 7, 8, 9, 17, 18, 19, 24, 50] {
+                        let area = Rect::new(0, 0, width, height);
+                        let layout = builder.build(area);
+                        let panes = [layout.terminal_area, layout.separator_area, layout.assistant_area];
+                        let size = (width, height);
+                        for pane in panes {
+                            assert_eq!(pane.union(area), area, "{:?} outside screen at {:?}", pane, size);
+                        }
+                        assert!(!overlaps(layout.terminal_area, layout.separator_area), "at {:?}", size);
+                        assert!(!overlaps(layout.separator_area, layout.assistant_area), "at {:?}", size);
+                        assert!(!overlaps(layout.terminal_area, layout.assistant_area), "at {:?}", size);
+                        assert!(!overlaps(layout.terminal_gutter, layout.terminal_inner), "at {:?}", size);
+
+                        let inners = [layout.terminal_inner, layout.assistant_inner];
+                        match layout.mode {
+                            LayoutMode::TooSmall => {
+                                assert!(width < MIN_WIDTH || height < MIN_HEIGHT);
+                                assert!(panes.iter().chain(&inners).all(|r| r.is_empty()), "at {:?}", size);
+                            }
+                            LayoutMode::Single => {
+                                let shown = match focus {
+                                    ActivePane::Terminal => layout.terminal_inner,
+                                    ActivePane::Assistant => layout.assistant_inner,
+                                };
+                                // Borders on every side, the right/left one being the separator
+                                assert_eq!(shown.width + gutter_for(focus, &layout) + 2, width, "at {:?}", size);
+                                assert_eq!(shown.height + 2, height, "at {:?}", size);
+                                assert_eq!(layout.separator_area.width, 1);
+                            }
+                            LayoutMode::Split => {
+                                assert!(inners.iter().all(|r| r.width > 0 && r.height > 0), "at {:?}", size);
+                                let used = layout.terminal_area.width
+                                    + layout.separator_area.width
+                                    + layout.assistant_area.width;
+                                assert_eq!(used, width, "at {:?}", size);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn gutter_for(focus: ActivePane, layout: &AppLayout) -> u16 {
+        match focus {
+            ActivePane::Terminal => layout.terminal_gutter.width,
+            ActivePane::Assistant => 0,
+        }
+    }
+
+    #[test]
+    fn test_layout_mode_thresholds() {
+        let builder = LayoutBuilder::new().with_min_split_size(70, 18);
+        assert_eq!(builder.mode_for(Rect::new(0, 0, 70, 18)), LayoutMode::Split);
+        assert_eq!(builder.mode_for(Rect::new(0, 0, 69, 18)), LayoutMode::Single);
+        assert_eq!(builder.mode_for(Rect::new(0, 0, 70, 17)), LayoutMode::Single);
+        assert_eq!(builder.mode_for(Rect::new(0, 0, 30, 8)), LayoutMode::Single);
+        assert_eq!(builder.mode_for(Rect::new(0, 0, 29, 40)), LayoutMode::TooSmall);
+        assert_eq!(builder.mode_for(Rect::new(0, 0, 200, 7)), LayoutMode::TooSmall);
+    }
+
+    #[test]
+    fn test_absurd_sizes_are_not_plausible() {
+        assert!(plausible_size(0, 0));
+        assert!(plausible_size(MAX_WIDTH, MAX_HEIGHT));
+        assert!(!plausible_size(u16::MAX, 24));
+        assert!(!plausible_size(80, MAX_HEIGHT + 1));
+    }
+
+    #[test]
+    fn test_split_survives_a_trip_through_single_pane() {
+        let builder = LayoutBuilder::new().with_split_ratio(70);
+        let large = Rect::new(0, 0, 120, 40);
+        let before = builder.build(large);
+        assert_eq!(builder.build(Rect::new(0, 0, 50, 12)).mode, LayoutMode::Single);
+        assert_eq!(builder.build(Rect::new(0, 0, 20, 5)).mode, LayoutMode::TooSmall);
+        let after = builder.build(large);
+        assert_eq!(after.terminal_inner, before.terminal_inner);
+        assert_eq!(after.assistant_inner, before.assistant_inner);
+    }
+}
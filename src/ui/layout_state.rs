@@ -0,0 +1,103 @@
+//! The split between the panes, kept across restarts.
+//!
+//! Where the user last left the separator (dragged, or moved from command
+//! mode) is written to `layout.json` in the data directory when the move is
+//! done, and laid out again on the next start.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::utils::persist::{self, PersistError, StateFile};
+
+/// File name in the data directory.
+const FILE_NAME: &str = "layout.json";
+
+/// Layout preferences as written to disk.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedLayout {
+    /// Percentage of the width given to the terminal pane
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_ratio: Option<u16>,
+}
+
+/// Writes the layout preferences to disk.
+#[derive(Debug)]
+pub struct LayoutStore {
+    file: StateFile,
+    /// What the file holds now
+    saved: SavedLayout,
+}
+
+impl LayoutStore {
+    /// Store backed by `path`. Another instance may own the file, in which
+    /// case the layout is read but never written.
+    pub fn at(path: impl Into<PathBuf>) -> Result<Self, PersistError> {
+        let file = StateFile::open(path, "layout")?;
+        let saved = match file.load() {
+            Ok(Some(bytes)) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable layout file {}: {}", file.path().display(), e);
+                SavedLayout::default()
+            }),
+            Ok(None) => SavedLayout::default(),
+            Err(e) => {
+                warn!("Failed to read layout file {}: {}", file.path().display(), e);
+                SavedLayout::default()
+            }
+        };
+        Ok(Self { file, saved })
+    }
+
+    /// Store in the data directory (None if there is none or it can't be
+    /// opened).
+    pub fn open() -> Option<Self> {
+        let path = persist::data_dir()?.join(FILE_NAME);
+        Self::at(&path)
+            .inspect_err(|e| warn!("Not saving the layout to {}: {}", path.display(), e))
+            .ok()
+    }
+
+    /// Layout found on disk when the store was opened (or last saved).
+    pub fn saved(&self) -> &SavedLayout {
+        &self.saved
+    }
+
+    /// Write the split ratio if it differs from what is on disk.
+    pub fn save_split_ratio(&mut self, ratio: u16) {
+        let current = SavedLayout { split_ratio: Some(ratio) };
+        if current == self.saved || self.file.is_read_only() {
+            return;
+        }
+        let result = serde_json::to_vec_pretty(&current)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| self.file.save(&bytes).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => self.saved = current,
+            Err(e) => warn!("Failed to save the layout to {}: {}", self.file.path().display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_ratio_survives_reopening() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-layout-{}", std::process::id()));
+        let path = dir.join(FILE_NAME);
+        let mut store = LayoutStore::at(&path)?;
+        assert_eq!(store.saved().split_ratio, None);
+
+        store.save_split_ratio(72);
+        drop(store);
+
+        let reopened = LayoutStore::at(&path)?;
+        assert_eq!(reopened.saved().split_ratio, Some(72));
+        drop(reopened);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
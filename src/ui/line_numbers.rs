@@ -0,0 +1,237 @@
+//! Line-number gutter for the terminal pane.
+//!
+//! Numbers are absolute: the first line ever printed is line 1. Once the
+//! scrollback is full, every new line evicts the oldest one and all content
+//! rows shift up, so [`LineNumbering`] counts evicted lines to keep the number
+//! of each retained line unchanged. The same numbers are used for copied text
+//! and for command output attached to AI requests, so "line 42" means the
+//! same thing to the user, the gutter and the model.
+
+use unicode_width::UnicodeWidthStr;
+
+/// The gutter never shrinks below this many digits (avoids resizing the PTY
+/// at 10 and 100 lines).
+const MIN_DIGITS: usize = 3;
+
+/// Maps content rows (0 = oldest retained line) to absolute line numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineNumbering {
+    /// Lines dropped from the top of the scrollback so far
+    evicted: usize,
+}
+
+impl LineNumbering {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Absolute (1-based) number of a content row.
+    pub fn number(&self, content_row: usize) -> usize {
+        self.evicted + content_row + 1
+    }
+
+    /// Record that `lines` rows were dropped from the top of the scrollback.
+    pub fn evict(&mut self, lines: usize) {
+        self.evicted += lines;
+    }
+}
+
+/// Number of decimal digits in `n`.
+pub fn digits(n: usize) -> usize {
+    n.checked_ilog10().map_or(1, |d| d as usize + 1)
+}
+
+/// Width of the gutter (digits plus a separating space) for numbers up to `last_number`.
+pub fn gutter_width(last_number: usize) -> u16 {
+    (digits(last_number).max(MIN_DIGITS) + 1) as u16
+}
+
+/// Gutter text for one row: the number right-aligned, then a space.
+pub fn gutter_label(number: usize, width: u16) -> String {
+    let digits = usize::from(width.saturating_sub(1));
+    format!("{:>digits$} ", number)
+}
+
+/// Prefix a line of text with its number, as in copied text and AI context.
+pub fn prefix_line(number: usize, digits: usize, text: &str) -> String {
+    if text.is_empty() {
+        format!("{:>digits$} |", number)
+    } else {
+        format!("{:>digits$} | {}", number, text)
+    }
+}
+
+/// Number each `\n`-separated line of `text`, starting at `first`.
+pub fn number_lines(text: &str, first: usize) -> String {
+    let count = text.split('\n').count();
+    let digits = digits(first + count.saturating_sub(1));
+    text.split('\n')
+        .enumerate()
+        .map(|(i, line)| prefix_line(first + i, digits, line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Copied text with numbered lines under a header naming the line range and,
+/// when known, the command that produced it.
+pub fn numbered_excerpt(text: &str, first: usize, command: Option<&str>) -> String {
+    let last = first + text.split('\n').count().saturating_sub(1);
+    let range = if last == first {
+        format!("Line {}", first)
+    } else {
+        format!("Lines {}-{}", first, last)
+    };
+    let header = match command {
+        Some(command) => format!("{} (from `{}`)", range, command),
+        None => range,
+    };
+    format!("{}\n{}", header, number_lines(text, first))
+}
+
+/// Number raw command output the way the terminal displays it.
+///
+/// `command_line` is the number of the row the command was typed on. The
+/// output starts with the rest of that row (usually just the echoed newline);
+/// each following line takes as many rows as it wraps to at `columns`, and a
+/// carriage return (progress bars) overwrites the line instead of adding one.
+pub fn number_output(output: &str, command_line: usize, columns: usize) -> String {
+    let columns = columns.max(1);
+    let mut rows: Vec<(usize, &str)> = Vec::new();
+    let mut number = command_line;
+    for (i, raw) in output.split('\n').enumerate() {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        let visible = raw.rsplit('\r').next().unwrap_or(raw);
+        if i == 0 {
+            // Tail of the command line itself
+            if !visible.trim().is_empty() {
+                rows.push((number, visible));
+            }
+        } else {
+            rows.push((number, visible));
+        }
+        number += visible.width().div_ceil(columns).max(1);
+    }
+
+    let digits = digits(rows.last().map_or(command_line, |(n, _)| *n));
+    rows.iter()
+        .map(|(n, text)| prefix_line(*n, digits, text))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Find how many rows were evicted while processing output.
+///
+/// `window` holds `(content_row, text)` pairs captured from the scrollback
+/// before processing. Returns the smallest shift of at most `max_shift` that
+/// maps every captured row onto identical text afterwards. `line_text` may
+/// return anything comparable to text, so rows need not be copied out.
+pub fn detect_shift<F, T>(window: &[(usize, String)], max_shift: usize, line_text: F) -> Option<usize>
+where
+    F: Fn(usize) -> Option<T>,
+    T: PartialEq<str>,
+{
+    (0..=max_shift).find(|&shift| {
+        window.iter().all(|(row, text)| {
+            row.checked_sub(shift)
+                .and_then(&line_text)
+                .is_some_and(|current| current == *text.as_str())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers_survive_eviction() {
+        let mut numbering = LineNumbering::new();
+        assert_eq!(numbering.number(0), 1);
+        assert_eq!(numbering.number(41), 42);
+        // Two lines evicted: the line that was row 41 is now row 39
+        numbering.evict(2);
+        assert_eq!(numbering.number(39), 42);
+    }
+
+    #[test]
+    fn test_gutter_width_adapts_to_digits() {
+        assert_eq!(digits(0), 1);
+        assert_eq!(digits(9), 1);
+        assert_eq!(digits(10), 2);
+        assert_eq!(digits(12_345), 5);
+        assert_eq!(gutter_width(24), 4);
+        assert_eq!(gutter_width(999), 4);
+        assert_eq!(gutter_width(1_000), 5);
+        assert_eq!(gutter_width(123_456), 7);
+    }
+
+    #[test]
+    fn test_gutter_label_at_several_widths() {
+        assert_eq!(gutter_label(7, 4), "  7 ");
+        assert_eq!(gutter_label(999, 4), "999 ");
+        assert_eq!(gutter_label(1_000, 5), "1000 ");
+        assert_eq!(gutter_label(42, 7), "    42 ");
+        for width in [4u16, 5, 7] {
+            assert_eq!(gutter_label(1, width).len(), usize::from(width));
+        }
+    }
+
+    #[test]
+    fn test_number_lines() {
+        assert_eq!(number_lines("a\n\nb", 9), " 9 | a\n10 |\n11 | b");
+        assert_eq!(number_lines("only", 1), "1 | only");
+    }
+
+    #[test]
+    fn test_numbered_excerpt_header() {
+        assert_eq!(
+            numbered_excerpt("error\nwarning", 41, Some("cargo build")),
+            "Lines 41-42 (from `cargo build`)\n41 | error\n42 | warning"
+        );
+        assert_eq!(numbered_excerpt("$ ls", 7, None), "Line 7\n7 | $ ls");
+    }
+
+    #[test]
+    fn test_number_output_matches_terminal_rows() {
+        // Echoed newline ends the command row (40); output starts at 41
+        let output = "\r\nerror: one\r\nwarning: two\r\n$ ";
+        assert_eq!(
+            number_output(output, 40, 80),
+            "41 | error: one\n42 | warning: two\n43 | $ "
+        );
+    }
+
+    #[test]
+    fn test_number_output_accounts_for_wrapping_and_progress() {
+        // 25 columns wrap to 3 rows at width 10; the progress line redraws in place
+        let output = format!("\n{}\n10%\r50%\r100%\nok", "x".repeat(25));
+        let numbered = number_output(&output, 1, 10);
+        let lines: Vec<&str> = numbered.lines().collect();
+        assert_eq!(lines[0], format!("2 | {}", "x".repeat(25)));
+        assert_eq!(lines[1], "5 | 100%");
+        assert_eq!(lines[2], "6 | ok");
+    }
+
+    #[test]
+    fn test_detect_shift() {
+        let before: Vec<String> = (0..10).map(|i| format!("line {}", i)).collect();
+        let window: Vec<(usize, String)> = (6..9).map(|r| (r, before[r].clone())).collect();
+
+        // Three lines evicted
+        let after: Vec<String> = before[3..].to_vec();
+        assert_eq!(detect_shift(&window, 5, |r| after.get(r).cloned()), Some(3));
+        // Nothing evicted
+        assert_eq!(detect_shift(&window, 5, |r| before.get(r).cloned()), Some(0));
+        // More evicted than allowed
+        assert_eq!(detect_shift(&window, 2, |r| after.get(r).cloned()), None);
+    }
+
+    #[test]
+    fn test_detect_shift_needs_the_whole_window() {
+        // Repeated blank lines alone must not produce a false match
+        let before = ["a".to_string(), String::new(), String::new(), "b".to_string()];
+        let window: Vec<(usize, String)> = (1..4).map(|r| (r, before[r].clone())).collect();
+        let after: Vec<String> = before[1..].to_vec();
+        assert_eq!(detect_shift(&window, 3, |r| after.get(r).cloned()), Some(1));
+    }
+}
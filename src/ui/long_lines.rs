@@ -0,0 +1,502 @@
+//! Very long lines from the PTY: minified JSON from a curl, a log line with
+//! a payload in it.
+//!
+//! Wrapped into the grid, one such line would fill the whole scrollback and
+//! make every pass over a line (selection, word search, the dump) as long as
+//! the line. So PTY output goes through [`LongLines::filter`] first: once a
+//! line passes [`LONG_LINE_COLUMNS`], the rest of it is kept here, in
+//! chunks, and the grid gets a marker row in its place. The whole line can
+//! then be read in the viewer, which only ever touches the rows it shows.
+//!
+//! A line ends at a line break or at an escape sequence other than a color
+//! change (prompts start with one), or when the user types.
+
+use std::collections::VecDeque;
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Stylize},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+
+use crate::utils::metrics::format_bytes;
+use crate::utils::utf8::Utf8Decoder;
+
+/// Columns of a line shown in the grid; the rest goes to the store.
+pub const LONG_LINE_COLUMNS: usize = 8 * 1024;
+/// Characters per chunk of a stored line.
+const CHUNK_CHARS: usize = 64 * 1024;
+/// Text kept of a single line; past this only its size is counted.
+const MAX_LINE_BYTES: usize = 64 * 1024 * 1024;
+/// Lines kept for the viewer, the oldest are dropped first.
+const KEPT_LINES: usize = 8;
+
+/// Text of one long line, in chunks of [`CHUNK_CHARS`] characters (the last
+/// one may be shorter), so a part of it is found without a scan from the
+/// start.
+#[derive(Debug)]
+pub struct LongLine {
+    id: usize,
+    chunks: Vec<String>,
+    chars: usize,
+    /// Bytes of text received, including what was not kept
+    bytes: usize,
+    /// Bytes received but not yet in the chunks
+    partial: Vec<u8>,
+    /// The bytes as text; holds back a character cut off at the end
+    decoder: Utf8Decoder,
+    complete: bool,
+}
+
+impl LongLine {
+    fn new(id: usize, head: &[u8]) -> Self {
+        Self {
+            id,
+            chunks: Vec::new(),
+            chars: 0,
+            bytes: head.len(),
+            partial: head.to_vec(),
+            decoder: Utf8Decoder::default(),
+            complete: false,
+        }
+    }
+
+    /// Number shown in the marker row.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn chars(&self) -> usize {
+        self.chars
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Has the line ended? Until then more text may come.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        self.bytes += 1;
+        if self.bytes <= MAX_LINE_BYTES {
+            self.partial.push(byte);
+        }
+    }
+
+    /// Move the characters received so far into the chunks. An unfinished
+    /// one waits for the rest of its bytes, unless the line is complete.
+    fn flush(&mut self) {
+        let mut text = self.decoder.decode(&self.partial);
+        self.partial.clear();
+        if self.complete {
+            text.push_str(&self.decoder.finish());
+        }
+        self.push_str(&text);
+    }
+
+    fn push_str(&mut self, mut text: &str) {
+        while !text.is_empty() {
+            if self.chars.is_multiple_of(CHUNK_CHARS) {
+                self.chunks.push(String::new());
+            }
+            let room = CHUNK_CHARS - self.chars % CHUNK_CHARS;
+            let (split, taken) = match text.char_indices().nth(room) {
+                Some((split, _)) => (split, room),
+                None => (text.len(), text.chars().count()),
+            };
+            if let Some(chunk) = self.chunks.last_mut() {
+                chunk.push_str(&text[..split]);
+            }
+            self.chars += taken;
+            text = &text[split..];
+        }
+    }
+
+    /// Characters `start..start + len` (fewer at the end of the line).
+    pub fn slice(&self, start: usize, len: usize) -> String {
+        let mut out = String::new();
+        let mut chunk = start / CHUNK_CHARS;
+        let mut skip = start % CHUNK_CHARS;
+        let mut left = len;
+        while left > 0 && let Some(text) = self.chunks.get(chunk) {
+            let taken: String = text.chars().skip(skip).take(left).collect();
+            left -= taken.chars().count();
+            out.push_str(&taken);
+            chunk += 1;
+            skip = 0;
+        }
+        out
+    }
+
+    /// Rows of the line wrapped to `width` columns.
+    pub fn rows(&self, width: usize) -> usize {
+        self.chars.div_ceil(width.max(1))
+    }
+
+    /// Rows `top..top + rows` of the line wrapped to `width` columns.
+    pub fn window(&self, top: usize, rows: usize, width: usize) -> Vec<String> {
+        let width = width.max(1);
+        let text = self.slice(top * width, rows * width);
+        let chars: Vec<char> = text.chars().collect();
+        chars.chunks(width).map(|row| row.iter().collect()).collect()
+    }
+}
+
+/// What a PTY byte is, given the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Step {
+    /// Part of a character that is printed
+    Print,
+    /// CR or LF
+    LineBreak,
+    /// Another control character
+    Control,
+    /// Part of an unfinished escape sequence
+    Escape,
+    /// Last byte of a color or text attribute change
+    Sgr,
+    /// Last byte of any other escape sequence
+    OtherEscape,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Parse {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    /// OSC and the other string sequences, up to BEL or ST
+    String,
+    StringEscape,
+}
+
+/// Tells the bytes of PTY output apart: printed text, line breaks and
+/// escape sequences, color changes among them.
+#[derive(Debug, Default)]
+pub(super) struct EscapeParser {
+    parse: Parse,
+}
+
+impl EscapeParser {
+    pub(super) fn step(&mut self, byte: u8) -> Step {
+        let (parse, step) = match (self.parse, byte) {
+            (Parse::Ground, 0x1b) => (Parse::Escape, Step::Escape),
+            (Parse::Ground, b'\r' | b'\n') => (Parse::Ground, Step::LineBreak),
+            (Parse::Ground, b'\t') => (Parse::Ground, Step::Print),
+            (Parse::Ground, 0..0x20 | 0x7f) => (Parse::Ground, Step::Control),
+            (Parse::Ground, _) => (Parse::Ground, Step::Print),
+            (Parse::Escape, b'[') => (Parse::Csi, Step::Escape),
+            (Parse::Escape, b']' | b'P' | b'X' | b'^' | b'_') => (Parse::String, Step::Escape),
+            (Parse::Escape, 0x20..0x30) => (Parse::Escape, Step::Escape),
+            (Parse::Escape, _) => (Parse::Ground, Step::OtherEscape),
+            (Parse::Csi, 0x20..0x40) => (Parse::Csi, Step::Escape),
+            (Parse::Csi, b'm') => (Parse::Ground, Step::Sgr),
+            (Parse::Csi, _) => (Parse::Ground, Step::OtherEscape),
+            (Parse::String, 0x07) => (Parse::Ground, Step::OtherEscape),
+            (Parse::String, 0x1b) => (Parse::StringEscape, Step::Escape),
+            (Parse::String, _) => (Parse::String, Step::Escape),
+            (Parse::StringEscape, _) => (Parse::Ground, Step::OtherEscape),
+        };
+        self.parse = parse;
+        step
+    }
+}
+
+/// Splits long lines off the PTY output and keeps them.
+#[derive(Debug, Default)]
+pub struct LongLines {
+    parser: EscapeParser,
+    /// Characters of the current line shown so far
+    columns: usize,
+    /// Printed bytes of the current line, while it fits
+    head: Vec<u8>,
+    /// The line being kept here, not in the grid
+    current: Option<LongLine>,
+    /// Escape sequence met while keeping a line, until it is known
+    escape: Vec<u8>,
+    lines: VecDeque<LongLine>,
+    next_id: usize,
+}
+
+impl LongLines {
+    pub fn new() -> Self {
+        Self { next_id: 1, ..Self::default() }
+    }
+
+    /// The output for the grid: `data`, unless a line in it is too long.
+    /// Past [`LONG_LINE_COLUMNS`] the line's text is kept here instead, and
+    /// once it ends a marker row takes its place.
+    pub fn filter<'a>(&mut self, data: &'a [u8]) -> std::borrow::Cow<'a, [u8]> {
+        let mut out: Option<Vec<u8>> = None;
+        // Bytes from here on go to the grid as they are
+        let mut pass_from = 0;
+        for (i, &byte) in data.iter().enumerate() {
+            let step = self.parser.step(byte);
+            let Some(line) = &mut self.current else {
+                match step {
+                    // A character starts that does not fit
+                    Step::Print if byte & 0xc0 != 0x80 && self.columns == LONG_LINE_COLUMNS => {
+                        out.get_or_insert_with(Vec::new).extend_from_slice(&data[pass_from..i]);
+                        let mut line = LongLine::new(self.next_id, &self.head);
+                        line.push_byte(byte);
+                        self.next_id += 1;
+                        self.current = Some(line);
+                    }
+                    Step::Print => {
+                        if byte & 0xc0 != 0x80 {
+                            self.columns += 1;
+                        }
+                        self.head.push(byte);
+                    }
+                    Step::LineBreak | Step::OtherEscape => self.new_line(),
+                    Step::Control | Step::Escape | Step::Sgr => {}
+                }
+                continue;
+            };
+            match step {
+                Step::Print => line.push_byte(byte),
+                Step::Control => {}
+                Step::Escape => self.escape.push(byte),
+                // Colors of the hidden part are dropped with it
+                Step::Sgr => self.escape.clear(),
+                Step::LineBreak => {
+                    let out = out.get_or_insert_with(Vec::new);
+                    self.end_line(out);
+                    pass_from = i;
+                }
+                Step::OtherEscape => {
+                    let out = out.get_or_insert_with(Vec::new);
+                    self.escape.push(byte);
+                    let escape = std::mem::take(&mut self.escape);
+                    self.end_line(out);
+                    out.extend_from_slice(&escape);
+                    pass_from = i + 1;
+                }
+            }
+        }
+        if let Some(line) = &mut self.current {
+            line.flush();
+        }
+        match out {
+            None if self.current.is_some() => std::borrow::Cow::Borrowed(&[]),
+            None => std::borrow::Cow::Borrowed(data),
+            Some(mut out) => {
+                if self.current.is_none() {
+                    out.extend_from_slice(&data[pass_from..]);
+                }
+                std::borrow::Cow::Owned(out)
+            }
+        }
+    }
+
+    /// End a line being kept here, e.g. because the user typed, so what
+    /// comes next shows up again. Returns the marker row to put in the grid.
+    pub fn end_current(&mut self) -> Option<Vec<u8>> {
+        self.current.as_ref()?;
+        let mut out = Vec::new();
+        self.end_line(&mut out);
+        out.extend_from_slice(b"\r\n");
+        // The start of an unfinished escape sequence goes on to the grid
+        out.append(&mut self.escape);
+        Some(out)
+    }
+
+    /// Append the marker row of the line being kept and put it with the
+    /// others.
+    fn end_line(&mut self, out: &mut Vec<u8>) {
+        let Some(mut line) = self.current.take() else {
+            return;
+        };
+        line.complete = true;
+        line.flush();
+        out.extend_from_slice(marker(&line).as_bytes());
+        if self.lines.len() == KEPT_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+        self.new_line();
+    }
+
+    fn new_line(&mut self) {
+        self.columns = 0;
+        self.head.clear();
+    }
+
+    /// The line with marker number `id`, if it is still kept.
+    pub fn get(&self, id: usize) -> Option<&LongLine> {
+        self.lines.iter().chain(&self.current).find(|line| line.id == id)
+    }
+
+    /// The most recent line, still arriving or not.
+    pub fn latest(&self) -> Option<&LongLine> {
+        self.current.as_ref().or(self.lines.back())
+    }
+}
+
+/// Start of the text of every marker row.
+const MARKER_PREFIX: &str = "⤶ line ";
+
+/// The marker row that stands for the hidden part of `line`, on a row of
+/// its own and in reverse video.
+fn marker(line: &LongLine) -> String {
+    format!(
+        "\r\n\x1b[0;7m{}{} continues ({}) — command mode p opens it\x1b[0m",
+        MARKER_PREFIX,
+        line.id,
+        format_bytes(line.bytes as u64)
+    )
+}
+
+/// The marker number on a row of grid text, if the row is a marker row.
+pub fn marker_id(row: &str) -> Option<usize> {
+    let rest = row.trim_start().strip_prefix(MARKER_PREFIX)?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    rest[..digits].parse().ok()
+}
+
+/// Position in the viewer of one long line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LongLineView {
+    pub id: usize,
+    /// First row shown; kept in range when rendering
+    top: usize,
+}
+
+impl LongLineView {
+    pub fn new(id: usize) -> Self {
+        Self { id, top: 0 }
+    }
+
+    /// Area of the viewer on a screen of `screen_area`, and the area of the
+    /// text inside its border.
+    pub fn areas(screen_area: Rect) -> (Rect, Rect) {
+        let width = screen_area.width.saturating_sub(4);
+        let height = screen_area.height.saturating_sub(2);
+        let area = super::popup_area(screen_area, width, height);
+        let inner = Block::new().borders(Borders::all()).inner(area);
+        (area, inner)
+    }
+
+    /// First row shown of `line` in a text area of `inner`.
+    pub fn top(&self, line: &LongLine, inner: Rect) -> usize {
+        let max_top = line.rows(inner.width as usize).saturating_sub(inner.height as usize);
+        self.top.min(max_top)
+    }
+
+    /// Move by `rows` rows (negative = up). The end is found when rendering.
+    pub fn scroll(&mut self, line: &LongLine, inner: Rect, rows: isize) {
+        self.top = self.top(line, inner).saturating_add_signed(rows);
+    }
+
+    pub fn scroll_to_start(&mut self) {
+        self.top = 0;
+    }
+
+    pub fn scroll_to_end(&mut self) {
+        self.top = usize::MAX;
+    }
+
+    /// The rows of `line` shown in a text area of `inner`, one per line.
+    pub fn visible_text(&self, line: &LongLine, inner: Rect) -> String {
+        let rows = line.window(self.top(line, inner), inner.height as usize, inner.width as usize);
+        rows.join("\n")
+    }
+
+    /// Render the viewer centered on the screen, wrapping the line to its
+    /// width.
+    pub fn render(&self, screen_area: Rect, buf: &mut Buffer, line: &LongLine) {
+        let (area, inner) = Self::areas(screen_area);
+        let top = self.top(line, inner);
+        let rows = line.rows(inner.width as usize);
+        let state = if line.is_complete() { "" } else { ", still arriving" };
+        let block = Block::new()
+            .borders(Borders::all())
+            .title(format!(
+                " LINE {} ({}{}) rows {}-{} of {} ",
+                line.id,
+                format_bytes(line.bytes as u64),
+                state,
+                (top + 1).min(rows),
+                (top + inner.height as usize).min(rows),
+                rows
+            ))
+            .title_bottom(" j/k PgUp/PgDn g/G scroll · y copy shown rows · q close ")
+            .title_alignment(ratatui::layout::Alignment::Center)
+            .bg(Color::Black)
+            .fg(Color::Cyan);
+        let lines: Vec<Line<'_>> = line
+            .window(top, inner.height as usize, inner.width as usize)
+            .into_iter()
+            .map(Line::from)
+            .collect();
+
+        Clear.render(area, buf);
+        block.render(area, buf);
+        Paragraph::new(lines).fg(Color::White).render(inner, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_all(lines: &mut LongLines, chunks: &[&[u8]]) -> Vec<u8> {
+        chunks.iter().flat_map(|chunk| lines.filter(chunk).into_owned()).collect()
+    }
+
+    #[test]
+    fn test_short_lines_pass_unchanged() {
+        let mut lines = LongLines::new();
+        let data = b"\x1b[31mred\x1b[0m\r\n\x1b]7;file:///tmp\x07prompt$ ";
+        assert!(matches!(lines.filter(data), std::borrow::Cow::Borrowed(out) if out == data));
+        assert!(lines.latest().is_none());
+    }
+
+    #[test]
+    fn test_long_line_is_kept_and_marked() -> Result<(), Box<dyn std::error::Error>> {
+        let mut lines = LongLines::new();
+        let long = "é".repeat(LONG_LINE_COLUMNS + 70_000);
+        let text = format!("before\r\n\x1b[32m{}\x1b[0m\r\nafter", long);
+        // Split mid-character and mid-sequence
+        let (a, b) = text.as_bytes().split_at(LONG_LINE_COLUMNS * 2 + 18);
+        let out = String::from_utf8(filter_all(&mut lines, &[a, b]))?;
+
+        let head = "é".repeat(LONG_LINE_COLUMNS);
+        let marker = "\r\n\x1b[0;7m⤶ line 1 continues (152.7 KiB) — command mode p opens it\x1b[0m";
+        assert_eq!(out, format!("before\r\n\x1b[32m{}{}\r\nafter", head, marker));
+
+        let line = lines.latest().ok_or("no line")?;
+        assert!(line.is_complete());
+        assert_eq!(line.chars(), LONG_LINE_COLUMNS + 70_000);
+        // A window across a chunk boundary
+        assert_eq!(line.window(CHUNK_CHARS / 10 - 1, 2, 10), ["é".repeat(10), "é".repeat(10)]);
+        assert_eq!(line.window(line.rows(10) - 1, 5, 10), ["é".repeat(2)]);
+        assert_eq!(marker_id("⤶ line 1 continues (152.7 KiB)"), Some(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_prompt_or_typing_ends_a_long_line() -> Result<(), Box<dyn std::error::Error>> {
+        let mut lines = LongLines::new();
+        let long = "x".repeat(LONG_LINE_COLUMNS + 1);
+        // A prompt that starts with an escape sequence
+        let out = String::from_utf8(filter_all(&mut lines, &[long.as_bytes(), b"\x1b[1m\x1b]0;title\x07$ "]))?;
+        assert!(out.ends_with("opens it\x1b[0m\x1b]0;title\x07$ "), "{:?}", &out[out.len() - 60..]);
+        assert_eq!(lines.get(1).map(LongLine::chars), Some(LONG_LINE_COLUMNS + 1));
+
+        // No escape sequence: the line stays hidden until the user types
+        let mut lines = LongLines::new();
+        assert!(filter_all(&mut lines, &[long.as_bytes(), b"$ "]).len() == LONG_LINE_COLUMNS);
+        assert!(lines.latest().is_some_and(|line| !line.is_complete()));
+        let marker = String::from_utf8(lines.end_current().ok_or("no line")?)?;
+        assert!(marker.contains("line 1 continues") && marker.ends_with("\r\n"));
+        assert_eq!(lines.get(1).map(LongLine::chars), Some(LONG_LINE_COLUMNS + 3));
+        assert!(lines.end_current().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+//! Keyboard macros of the terminal pane.
+//!
+//! In command mode, `q` and a register letter (`a` to `z`) start recording
+//! the keys sent to the shell from then on, and `q` stops. `@` and the
+//! letter send them again, a count before it (`5@a`) as many times. Only
+//! keys that reach the shell are recorded, never the app's own (the
+//! leader, command mode, scrolling). Replayed keys go one
+//! [`DEFAULT_KEY_DELAY`] apart unless configured, since some shells drop
+//! keys that arrive all at once; a key typed meanwhile stops the replay.
+//! Registers last until exit.
+
+use std::collections::{HashMap, VecDeque};
+
+use crossterm::event::KeyEvent;
+use tokio::time::{Duration, Instant};
+
+/// Time between two replayed keys, unless configured.
+pub const DEFAULT_KEY_DELAY: Duration = Duration::from_millis(5);
+
+/// Most times one `@` replays a register.
+pub const MAX_COUNT: u32 = 999;
+
+/// What a command mode key did to the macros.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MacroStep {
+    /// Not a macro key
+    Ignored,
+    /// A count or a register is still to come: stay in command mode
+    Pending,
+    /// Recording into the register started
+    Recording(char),
+    /// Recording into the register stopped, with this many keys
+    Recorded(char, usize),
+    /// Replay the register `count` times; `force` even over a full-screen
+    /// program (the register letter in uppercase)
+    Play { register: char, count: u32, force: bool },
+    /// Not a register after `q` or `@`: nothing happened
+    NoRegister,
+}
+
+/// Keys typed after `q`, `@` or a count in command mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Prefix {
+    #[default]
+    None,
+    Count(u32),
+    Record,
+    Play(u32),
+}
+
+#[derive(Debug)]
+pub struct KeyMacros {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    /// Register being recorded, and the keys so far
+    recording: Option<(char, Vec<KeyEvent>)>,
+    prefix: Prefix,
+    /// Keys still to replay, and when the next one is due
+    replay: VecDeque<KeyEvent>,
+    next_key: Option<Instant>,
+    key_delay: Duration,
+}
+
+impl Default for KeyMacros {
+    fn default() -> Self {
+        Self {
+            registers: HashMap::new(),
+            recording: None,
+            prefix: Prefix::None,
+            replay: VecDeque::new(),
+            next_key: None,
+            key_delay: DEFAULT_KEY_DELAY,
+        }
+    }
+}
+
+impl KeyMacros {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_key_delay(&mut self, delay: Duration) {
+        self.key_delay = delay;
+    }
+
+    /// Register being recorded, if any.
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    /// Whether a count, `q` or `@` waits for the rest in command mode.
+    pub fn is_pending(&self) -> bool {
+        self.prefix != Prefix::None
+    }
+
+    /// Forget a count, `q` or `@` typed in command mode (it was left).
+    pub fn clear_pending(&mut self) {
+        self.prefix = Prefix::None;
+    }
+
+    /// `c` was typed in command mode. While recording, `q` stops; otherwise
+    /// `q` and `@` take the register after them, with an optional count
+    /// before `@`.
+    pub fn command_key(&mut self, c: char) -> MacroStep {
+        match (std::mem::take(&mut self.prefix), c) {
+            (Prefix::None, 'q') => match self.recording.take() {
+                Some((register, keys)) => {
+                    let count = keys.len();
+                    self.registers.insert(register, keys);
+                    MacroStep::Recorded(register, count)
+                }
+                None => self.pend(Prefix::Record),
+            },
+            (Prefix::None, '@') => self.pend(Prefix::Play(1)),
+            (Prefix::Count(count), '@') => self.pend(Prefix::Play(count)),
+            (Prefix::None, digit @ '1'..='9') => self.pend(Prefix::Count(digit_value(digit))),
+            (Prefix::Count(count), digit @ '0'..='9') => {
+                self.pend(Prefix::Count((count * 10 + digit_value(digit)).min(MAX_COUNT)))
+            }
+            (Prefix::Record, register @ 'a'..='z') => {
+                self.recording = Some((register, Vec::new()));
+                MacroStep::Recording(register)
+            }
+            (Prefix::Play(count), register) if register.is_ascii_alphabetic() => MacroStep::Play {
+                register: register.to_ascii_lowercase(),
+                count,
+                force: register.is_ascii_uppercase(),
+            },
+            (Prefix::None, _) => MacroStep::Ignored,
+            (Prefix::Count(_), _) => MacroStep::Ignored,
+            (Prefix::Record | Prefix::Play(_), _) => MacroStep::NoRegister,
+        }
+    }
+
+    fn pend(&mut self, prefix: Prefix) -> MacroStep {
+        self.prefix = prefix;
+        MacroStep::Pending
+    }
+
+    /// `key` was typed into the shell: it goes into the register being
+    /// recorded, and stops a replay.
+    pub fn typed(&mut self, key: KeyEvent) {
+        self.replay.clear();
+        self.next_key = None;
+        if let Some((_, keys)) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    /// Start sending the keys of `register` `count` times, the first one at
+    /// `now`. Fails if the register is empty.
+    pub fn play(&mut self, register: char, count: u32, now: Instant) -> Result<usize, String> {
+        let keys = self.registers.get(&register).filter(|keys| !keys.is_empty());
+        let Some(keys) = keys else {
+            return Err(format!("Nothing recorded in @{}", register));
+        };
+        for _ in 0..count.clamp(1, MAX_COUNT) {
+            self.replay.extend(keys.iter().copied());
+        }
+        self.next_key.get_or_insert(now);
+        Ok(self.replay.len())
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        !self.replay.is_empty()
+    }
+
+    /// When the next replayed key is due (None = no replay).
+    pub fn due(&self) -> Option<Instant> {
+        self.next_key
+    }
+
+    /// The replayed keys due at `now`. A replay that fell behind sends one
+    /// key and waits the delay again, so keys never bunch up.
+    pub fn take_due(&mut self, now: Instant) -> Vec<KeyEvent> {
+        let mut keys = Vec::new();
+        while let Some(at) = self.next_key
+            && at <= now
+        {
+            keys.extend(self.replay.pop_front());
+            self.next_key = (!self.replay.is_empty()).then_some(now + self.key_delay);
+        }
+        keys
+    }
+}
+
+fn digit_value(digit: char) -> u32 {
+    digit.to_digit(10).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    fn keys(text: &str) -> Vec<KeyEvent> {
+        text.chars().map(|c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)).collect()
+    }
+
+    fn record(macros: &mut KeyMacros, register: char, typed: &[KeyEvent]) {
+        assert_eq!(macros.command_key('q'), MacroStep::Pending);
+        assert_eq!(macros.command_key(register), MacroStep::Recording(register));
+        for key in typed {
+            macros.typed(*key);
+        }
+        assert_eq!(macros.command_key('q'), MacroStep::Recorded(register, typed.len()));
+    }
+
+    #[test]
+    fn test_replay_sends_the_recorded_keys_in_order() -> Result<(), String> {
+        let mut macros = KeyMacros::new();
+        macros.set_key_delay(Duration::ZERO);
+        let up = KeyEvent::new(KeyCode::Up, KeyModifiers::NONE);
+        let ctrl_a = KeyEvent::new(KeyCode::Char('a'), KeyModifiers::CONTROL);
+        let mut typed = vec![up, up, ctrl_a];
+        typed.extend(keys("é1"));
+        typed.push(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE));
+        record(&mut macros, 'a', &typed);
+        assert_eq!(macros.recording(), None);
+
+        let now = Instant::now();
+        assert_eq!(macros.play('a', 1, now)?, typed.len());
+        assert_eq!(macros.take_due(now), typed);
+        assert!(!macros.is_replaying());
+        assert_eq!(macros.due(), None);
+
+        assert_eq!(macros.play('b', 1, now), Err("Nothing recorded in @b".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_prefix_repeats_the_register() -> Result<(), String> {
+        let mut macros = KeyMacros::new();
+        macros.set_key_delay(Duration::ZERO);
+        record(&mut macros, 'x', &keys("ab"));
+
+        assert_eq!(macros.command_key('1'), MacroStep::Pending);
+        assert_eq!(macros.command_key('2'), MacroStep::Pending);
+        assert_eq!(macros.command_key('@'), MacroStep::Pending);
+        assert_eq!(macros.command_key('x'), MacroStep::Play { register: 'x', count: 12, force: false });
+        assert!(!macros.is_pending());
+        assert_eq!(macros.play('x', 12, Instant::now())?, 24);
+
+        // Capped, and forced with the letter in uppercase
+        for c in "99999@".chars() {
+            macros.command_key(c);
+        }
+        assert_eq!(macros.command_key('X'), MacroStep::Play { register: 'x', count: MAX_COUNT, force: true });
+
+        // Anything but a register after q or @ does nothing
+        macros.command_key('@');
+        assert_eq!(macros.command_key('!'), MacroStep::NoRegister);
+        macros.command_key('q');
+        assert_eq!(macros.command_key('A'), MacroStep::NoRegister);
+        assert_eq!(macros.recording(), None);
+        // Nor is a count anything without @
+        macros.command_key('3');
+        assert_eq!(macros.command_key('o'), MacroStep::Ignored);
+        assert_eq!(macros.command_key('0'), MacroStep::Ignored);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replayed_keys_are_paced() -> Result<(), String> {
+        let delay = Duration::from_millis(10);
+        let mut macros = KeyMacros::new();
+        macros.set_key_delay(delay);
+        record(&mut macros, 'a', &keys("abcd"));
+
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        macros.play('a', 1, start)?;
+        assert_eq!(macros.take_due(start), keys("a"));
+        assert_eq!(macros.due(), Some(at(10)));
+        assert!(macros.take_due(at(9)).is_empty());
+        assert_eq!(macros.take_due(at(10)), keys("b"));
+        // Late: one key now, the next a whole delay later
+        assert_eq!(macros.take_due(at(45)), keys("c"));
+        assert_eq!(macros.due(), Some(at(55)));
+
+        // A key typed meanwhile stops the replay
+        macros.typed(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(macros.due(), None);
+        assert!(macros.take_due(at(100)).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_recording_again_replaces_the_register() {
+        let mut macros = KeyMacros::new();
+        record(&mut macros, 'a', &keys("old"));
+        record(&mut macros, 'a', &keys("new"));
+        macros.set_key_delay(Duration::ZERO);
+        let now = Instant::now();
+        assert_eq!(macros.play('a', 1, now), Ok(3));
+        assert_eq!(macros.take_due(now), keys("new"));
+    }
+}
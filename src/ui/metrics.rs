@@ -0,0 +1,235 @@
+//! Metrics overlay: current values of the metrics registry, each with a
+//! sparkline of its recent samples (oldest on the left).
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+use unicode_width::UnicodeWidthStr;
+
+use crate::utils::metrics::{
+    self, MetricsHistory, MetricsSnapshot, format_bytes, format_duration, per_second,
+};
+
+/// Sparkline levels, lowest first.
+const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Width of the name column.
+const LABEL_WIDTH: usize = 16;
+/// Width of the value column.
+const VALUE_WIDTH: usize = 34;
+
+/// The last `width` values as a sparkline, scaled to the largest of them.
+/// Zero is the lowest level and any other value at least the next one up.
+pub fn sparkline(values: &[u64], width: usize) -> String {
+    let values = &values[values.len().saturating_sub(width)..];
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&value| match value {
+            0 => LEVELS[0],
+            _ => {
+                let top = (LEVELS.len() - 1) as u128;
+                let level = (u128::from(value) * top).div_ceil(u128::from(max));
+                LEVELS[usize::try_from(level).unwrap_or(LEVELS.len() - 1).min(LEVELS.len() - 1)]
+            }
+        })
+        .collect()
+}
+
+/// One row of the overlay: its name, current value and sample values.
+struct Row {
+    label: &'static str,
+    value: String,
+    samples: Vec<u64>,
+}
+
+fn rows(history: &MetricsHistory) -> Vec<Row> {
+    let total = history.total();
+    let last = history.samples().next_back().copied().unwrap_or_default();
+    let series = |f: fn(&MetricsSnapshot) -> u64| history.samples().map(f).collect::<Vec<_>>();
+    let duration = |d: Option<std::time::Duration>| d.map_or("-".to_string(), format_duration);
+    vec![
+        Row {
+            label: "Scrollback",
+            value: format!("{} ({} lines)", format_bytes(total.scrollback_bytes), total.scrollback_lines),
+            samples: series(|s| s.scrollback_bytes),
+        },
+        Row {
+            label: "Event loop p99",
+            value: format!(
+                "{} (max {})",
+                duration(last.loop_busy.quantile(0.99)),
+                duration(total.loop_busy.max())
+            ),
+            samples: series(|s| s.loop_busy.quantile(0.99).map_or(0, |d| d.as_micros() as u64)),
+        },
+        Row {
+            label: "PTY output",
+            value: format!(
+                "{}/s ({} total)",
+                format_bytes(per_second(last.pty_bytes, metrics::SAMPLE_INTERVAL)),
+                format_bytes(total.pty_bytes)
+            ),
+            samples: series(|s| s.pty_bytes),
+        },
+        Row {
+            label: "AI requests",
+            value: format!(
+                "{} ({} failed, p50 {}, ttfb {})",
+                total.ai_requests,
+                total.ai_errors,
+                duration(total.ai_latency.quantile(0.5)),
+                duration(total.ai_first_byte.quantile(0.5))
+            ),
+            samples: series(|s| s.ai_requests),
+        },
+        Row {
+            label: "Reply tokens",
+            value: format!("{} answer, {} reasoning", total.reply_tokens, total.reasoning_tokens),
+            samples: series(|s| s.reply_tokens + s.reasoning_tokens),
+        },
+        Row {
+            label: "Summaries",
+            value: format!(
+                "{} ({} failed, {} tokens)",
+                total.summaries,
+                total.summary_errors,
+                total.summary_tokens
+            ),
+            samples: series(|s| s.summary_tokens),
+        },
+        Row {
+            label: "Alternatives",
+            value: format!("{} ({} tokens)", total.alternatives, total.alternative_tokens),
+            samples: series(|s| s.alternative_tokens),
+        },
+        Row {
+            label: "Dropped frames",
+            value: format!("{} of {} ({} decoration)", total.dropped_frames, total.frames, total.decoration_frames),
+            samples: series(|s| s.dropped_frames),
+        },
+        Row {
+            label: "Input queue",
+            value: format!(
+                "{} deep, {} stalls, {} merged",
+                total.input_queue_max,
+                total.input_stalls,
+                total.input_coalesced
+            ),
+            samples: series(|s| s.input_coalesced),
+        },
+    ]
+}
+
+/// Render the overlay centered on the screen.
+pub fn render_metrics(screen_area: Rect, buf: &mut Buffer, history: &MetricsHistory) {
+    let rows = rows(history);
+    let width = screen_area.width.saturating_sub(4).min(100);
+    let height = (rows.len() as u16 + 2).min(screen_area.height);
+    let area = super::popup_area(screen_area, width, height);
+    let block = Block::new()
+        .borders(Borders::all())
+        .title(format!(
+            " METRICS (every {}s, {} samples) ",
+            metrics::SAMPLE_INTERVAL.as_secs(),
+            history.samples().len()
+        ))
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .bg(Color::Black)
+        .fg(Color::Magenta);
+    let inner = block.inner(area);
+    let spark_width = (inner.width as usize).saturating_sub(LABEL_WIDTH + VALUE_WIDTH + 2);
+
+    let lines: Vec<Line<'_>> = rows
+        .into_iter()
+        .map(|row| {
+            let mut value = row.value;
+            value.extend(std::iter::repeat_n(' ', VALUE_WIDTH.saturating_sub(value.width())));
+            Line::from(vec![
+                Span::raw(format!(" {:<width$}", row.label, width = LABEL_WIDTH - 1)),
+                Span::raw(value).bold(),
+                Span::raw(" "),
+                Span::raw(sparkline(&row.samples, spark_width)).fg(Color::Cyan),
+            ])
+        })
+        .collect();
+
+    Clear.render(area, buf);
+    block.render(area, buf);
+    Paragraph::new(lines).render(inner, buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    use crate::utils::metrics::Histogram;
+
+    #[test]
+    fn test_sparkline_scales_to_the_largest_value() {
+        assert_eq!(sparkline(&[], 10), "");
+        assert_eq!(sparkline(&[0, 0, 0], 10), "▁▁▁");
+        assert_eq!(sparkline(&[0, 1, 50, 100], 10), "▁▂▅█");
+        // Only the most recent values that fit
+        assert_eq!(sparkline(&[100, 0, 7], 2), "▁█");
+    }
+
+    #[test]
+    fn test_overlay_renders_synthetic_samples() {
+        let start = Instant::now();
+        let mut total = MetricsSnapshot::default();
+        let mut history = MetricsHistory::new(total, None, start);
+        let loop_busy = Histogram::new();
+        for i in 1..=6u32 {
+            total.pty_bytes += 5 * 1024 * u64::from(i);
+            total.scrollback_lines = 1000 * u64::from(i);
+            total.scrollback_bytes = 1024 * 1024 * u64::from(i);
+            loop_busy.record(Duration::from_millis(u64::from(i) * 10));
+            total.loop_busy = loop_busy.snapshot();
+            total.frames += 100;
+            total.decoration_frames += 10;
+            total.dropped_frames += u64::from(i % 2);
+            history.sample(total, start + metrics::SAMPLE_INTERVAL * i);
+        }
+        total.ai_requests = 2;
+        total.ai_errors = 1;
+        total.reply_tokens = 300;
+        total.reasoning_tokens = 900;
+        total.summaries = 1;
+        total.summary_tokens = 1234;
+        total.alternatives = 1;
+        total.alternative_tokens = 410;
+        total.input_queue_max = 64;
+        total.input_stalls = 2;
+        total.input_coalesced = 40;
+        history.sample(total, start + metrics::SAMPLE_INTERVAL * 7);
+
+        let area = Rect::new(0, 0, 80, 13);
+        let mut buf = Buffer::empty(area);
+        render_metrics(area, &mut buf, &history);
+        let text: Vec<String> = (0..area.height)
+            .map(|y| (0..area.width).map(|x| buf[(x, y)].symbol()).collect::<String>().trim_end().to_string())
+            .collect();
+
+        assert_eq!(text, vec![
+            "",
+            "  ┌───────────────────── METRICS (every 5s, 7 samples) ──────────────────────┐",
+            "  │ Scrollback     6.0 MiB (6000 lines)               ▃▄▅▆▇██                │",
+            "  │ Event loop p99 - (max 60ms)                       ▃▄▅▆▇█▁                │",
+            "  │ PTY output     0 B/s (105.0 KiB total)            ▃▄▅▆▇█▁                │",
+            "  │ AI requests    2 (1 failed, p50 -, ttfb -)        ▁▁▁▁▁▁█                │",
+            "  │ Reply tokens   300 answer, 900 reasoning          ▁▁▁▁▁▁█                │",
+            "  │ Summaries      1 (0 failed, 1234 tokens)          ▁▁▁▁▁▁█                │",
+            "  │ Alternatives   1 (410 tokens)                     ▁▁▁▁▁▁█                │",
+            "  │ Dropped frames 3 of 600 (60 decoration)           █▁█▁█▁▁                │",
+            "  │ Input queue    64 deep, 2 stalls, 40 merged       ▁▁▁▁▁▁█                │",
+            "  └──────────────────────────────────────────────────────────────────────────┘",
+            "",
+        ]);
+    }
+}
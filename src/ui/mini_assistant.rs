@@ -0,0 +1,375 @@
+//! Mini assistant: a one-shot question without leaving the terminal.
+//!
+//! Command mode Space opens a small popup over the terminal pane with a
+//! one-line input. The question goes to a session of its own that has no
+//! tab; the reply streams into the popup, and a suggested command shows as
+//! a card that Ctrl+Y runs through the same safety check as any other.
+//! Esc drops the session, Ctrl+T keeps it as a tab of the assistant pane.
+
+use std::cell::Cell;
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Widget};
+use unicode_width::UnicodeWidthStr;
+
+use crate::ai::session::SessionId;
+use super::assistant::{render_card, ChatMessage, CommandStatus};
+
+/// Rows of the reply shown at most; the rest scrolls.
+pub const MAX_ANSWER_ROWS: u16 = 12;
+
+/// Widest the popup gets, borders included.
+const MAX_WIDTH: u16 = 72;
+
+/// State of the open mini assistant.
+#[derive(Debug)]
+pub struct MiniAssistant {
+    /// The session it asks, kept out of the tab bar
+    session_id: SessionId,
+    input: String,
+    /// The conversation as the session has it
+    messages: Vec<ChatMessage>,
+    /// A reply is on its way
+    replying: bool,
+    /// Why the last question or Ctrl+Y went nowhere (None = it didn't)
+    notice: Option<String>,
+    /// Rows scrolled back from the end of the reply
+    scroll: usize,
+    /// Furthest it can scroll, as of the last render
+    max_scroll: Cell<usize>,
+}
+
+impl MiniAssistant {
+    pub fn new(session_id: SessionId) -> Self {
+        Self {
+            session_id,
+            input: String::new(),
+            messages: Vec::new(),
+            replying: false,
+            notice: None,
+            scroll: 0,
+            max_scroll: Cell::new(0),
+        }
+    }
+
+    pub fn session_id(&self) -> SessionId {
+        self.session_id
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn push_char(&mut self, c: char) {
+        self.input.push(c);
+    }
+
+    pub fn pop_char(&mut self) {
+        self.input.pop();
+    }
+
+    /// Pasted text goes in on the one line.
+    pub fn paste(&mut self, text: &str) {
+        self.input.extend(text.chars().map(|c| if c.is_control() { ' ' } else { c }));
+    }
+
+    /// The question typed, taken out of the input (None if it is blank).
+    pub fn take_question(&mut self) -> Option<String> {
+        let question = std::mem::take(&mut self.input).trim().to_string();
+        if question.is_empty() {
+            return None;
+        }
+        self.notice = None;
+        self.scroll = 0;
+        Some(question)
+    }
+
+    /// Show the conversation as it is now.
+    pub fn set_messages(&mut self, messages: Vec<ChatMessage>, replying: bool) {
+        self.messages = messages;
+        self.replying = replying;
+    }
+
+    pub fn show_notice(&mut self, notice: String) {
+        self.notice = Some(notice);
+    }
+
+    pub fn is_replying(&self) -> bool {
+        self.replying
+    }
+
+    /// The card Ctrl+Y and Ctrl+N decide on: the first suggestion still
+    /// waiting.
+    pub fn pending_card(&self) -> Option<&ChatMessage> {
+        self.messages
+            .iter()
+            .find(|msg| matches!(msg, ChatMessage::CommandCard { status: CommandStatus::Pending, .. }))
+    }
+
+    /// Tool call of the card Ctrl+Y and Ctrl+N decide on, and whether its
+    /// verdict denies running it.
+    pub fn pending_suggestion(&self) -> Option<(String, bool)> {
+        match self.pending_card()? {
+            ChatMessage::CommandCard { tool_call_id, verdict, .. } => Some((tool_call_id.clone(), verdict.is_deny())),
+            _ => None,
+        }
+    }
+
+    /// Scroll the reply by `delta` rows (negative = back).
+    pub fn scroll(&mut self, delta: isize) {
+        self.scroll = self.scroll.saturating_add_signed(-delta).min(self.max_scroll.get());
+    }
+
+    /// Rows of the conversation, `width` cells wide.
+    fn answer_lines(&self, width: u16) -> Vec<Line<'static>> {
+        let mut lines = Vec::new();
+        let mut shown_card = false;
+        for msg in &self.messages {
+            match msg {
+                ChatMessage::User { text } => {
+                    for (i, row) in textwrap::wrap(text, usize::from(width.saturating_sub(5)).max(10)).into_iter().enumerate() {
+                        let prefix = if i == 0 { "You: " } else { "     " };
+                        lines.push(Line::from(vec![
+                            Span::styled(prefix, Style::default().fg(Color::Green).bold()),
+                            Span::styled(row.into_owned(), Style::default().fg(Color::Gray)),
+                        ]));
+                    }
+                }
+                ChatMessage::Assistant { text, is_streaming, .. } => {
+                    lines.extend(markdown_lines(text, width));
+                    if *is_streaming && let Some(last) = lines.last_mut() {
+                        last.push_span(Span::raw("▌"));
+                    }
+                }
+                ChatMessage::CommandCard { status, .. } => {
+                    // One waiting card at a time, the one Ctrl+Y runs
+                    if *status == CommandStatus::Pending {
+                        if shown_card {
+                            continue;
+                        }
+                        shown_card = true;
+                    }
+                    lines.extend(render_card(msg, width));
+                }
+                ChatMessage::Error { text } => {
+                    for row in textwrap::wrap(text, usize::from(width).max(10)) {
+                        lines.push(Line::styled(row.into_owned(), Style::default().fg(Color::Red)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let streaming = matches!(self.messages.last(), Some(ChatMessage::Assistant { is_streaming: true, .. }));
+        if self.replying && !streaming {
+            lines.push(Line::styled("...", Style::default().fg(Color::DarkGray)));
+        }
+        lines
+    }
+
+    /// Where the popup goes in `area` (the terminal pane), and the reply
+    /// rows it shows.
+    fn layout(&self, area: Rect) -> (Rect, Vec<Line<'static>>) {
+        let width = (area.width - area.width / 5).max(area.width.min(30)).min(MAX_WIDTH);
+        let mut answer = self.answer_lines(width.saturating_sub(2));
+        let total = answer.len();
+        let shown = total.min(usize::from(MAX_ANSWER_ROWS));
+        self.max_scroll.set(total - shown);
+        let start = total - shown - self.scroll.min(total - shown);
+        answer.drain(..start);
+        answer.truncate(shown);
+        if let Some(notice) = &self.notice {
+            answer.push(Line::styled(notice.clone(), Style::default().fg(Color::Yellow)));
+        }
+
+        // Borders and the input row
+        let height = (answer.len() as u16 + 3).min(area.height);
+        let popup = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        };
+        (popup, answer)
+    }
+
+    /// Cell of the cursor, at the end of the input, when drawn in `area`.
+    pub fn cursor_position(&self, area: Rect) -> (u16, u16) {
+        let (popup, _) = self.layout(area);
+        let room = popup.width.saturating_sub(5);
+        let typed = u16::try_from(self.input.width()).unwrap_or(u16::MAX).min(room);
+        (popup.x + 3 + typed, popup.y + 1)
+    }
+}
+
+/// Rows of a reply with the little markdown models use in short answers:
+/// headings, bullets, code blocks, `code` and **bold**.
+fn markdown_lines(text: &str, width: u16) -> Vec<Line<'static>> {
+    let code_style = Style::default().fg(Color::Yellow);
+    let mut lines = Vec::new();
+    let mut in_block = false;
+    for raw in text.lines() {
+        if raw.trim_start().starts_with("```") {
+            in_block = !in_block;
+            continue;
+        }
+        if in_block {
+            lines.push(Line::styled(format!("  {}", raw), code_style));
+            continue;
+        }
+
+        let trimmed = raw.trim_start();
+        let (indent, body, base) = if let Some(heading) = trimmed.strip_prefix('#') {
+            ("", heading.trim_start_matches('#').trim_start(), Style::default().add_modifier(Modifier::BOLD))
+        } else if let Some(item) = trimmed.strip_prefix("- ").or_else(|| trimmed.strip_prefix("* ")) {
+            ("• ", item, Style::default())
+        } else {
+            ("", raw, Style::default())
+        };
+        if body.is_empty() {
+            lines.push(Line::raw(""));
+            continue;
+        }
+
+        // Markers count towards the width, so a row may come out short
+        let room = usize::from(width).saturating_sub(indent.width()).max(10);
+        let (mut code, mut bold) = (false, false);
+        for (i, row) in textwrap::wrap(body, room).into_iter().enumerate() {
+            let lead = if i == 0 { indent.to_string() } else { " ".repeat(indent.width()) };
+            let mut spans = vec![Span::raw(lead)];
+            spans.extend(inline_spans(&row, &mut code, &mut bold, base, code_style));
+            lines.push(Line::from(spans));
+        }
+    }
+    lines
+}
+
+/// Spans of `text` with `code` and **bold** styled and their markers left
+/// out. Whether a span is open carries over to the next row.
+fn inline_spans(text: &str, code: &mut bool, bold: &mut bool, base: Style, code_style: Style) -> Vec<Span<'static>> {
+    let style_of = |code: bool, bold: bool| match (code, bold) {
+        (true, _) => code_style,
+        (false, true) => base.add_modifier(Modifier::BOLD),
+        (false, false) => base,
+    };
+    let mut spans = Vec::new();
+    let mut current = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let marker = if c == '`' {
+            Some(1)
+        } else if !*code && rest.starts_with("**") {
+            Some(2)
+        } else {
+            None
+        };
+        match marker {
+            Some(len) => {
+                if !current.is_empty() {
+                    spans.push(Span::styled(std::mem::take(&mut current), style_of(*code, *bold)));
+                }
+                if len == 1 {
+                    *code = !*code;
+                } else {
+                    *bold = !*bold;
+                }
+                rest = &rest[len..];
+            }
+            None => {
+                current.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if !current.is_empty() {
+        spans.push(Span::styled(current, style_of(*code, *bold)));
+    }
+    spans
+}
+
+impl Widget for &MiniAssistant {
+    /// Draw the popup centered in `area` (the terminal pane).
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (popup, answer) = self.layout(area);
+        if popup.width < 5 || popup.height < 3 {
+            return;
+        }
+
+        // The end of the input, if it doesn't fit
+        let room = usize::from(popup.width.saturating_sub(5));
+        let mut shown = self.input.as_str();
+        while shown.width() > room {
+            let mut chars = shown.chars();
+            chars.next();
+            shown = chars.as_str();
+        }
+        let mut lines = vec![Line::from(vec![Span::styled("> ", Style::default().fg(Color::Cyan)), Span::raw(shown.to_string())])];
+        lines.extend(answer);
+
+        let hints = if self.pending_card().is_some() {
+            " Ctrl+Y run · Ctrl+N reject · Esc "
+        } else {
+            " Enter ask · Ctrl+T tab · Esc "
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(" Ask AI ")
+            .title_bottom(hints)
+            .border_style(Style::default().fg(Color::Cyan));
+        let inner = block.inner(popup);
+        Clear.render(popup, buf);
+        block.render(popup, buf);
+        Paragraph::new(lines).render(inner, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_of(line: &Line<'_>) -> String {
+        line.spans.iter().map(|span| span.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn test_markdown_markers_are_styled_away() {
+        let lines = markdown_lines("## Disk usage\n- run `du -sh`, **then** sort\n```\ndu -sh * | sort -h\n```", 40);
+        let texts: Vec<_> = lines.iter().map(text_of).collect();
+        assert_eq!(texts, vec!["Disk usage", "• run du -sh, then sort", "  du -sh * | sort -h"]);
+
+        // The code span is yellow, the bold one bold
+        let spans = &lines[1].spans;
+        assert!(spans.iter().any(|s| s.content == "du -sh" && s.style.fg == Some(Color::Yellow)));
+        assert!(spans.iter().any(|s| s.content == "then" && s.style.add_modifier.contains(Modifier::BOLD)));
+    }
+
+    #[test]
+    fn test_long_replies_scroll_within_the_popup() {
+        let mut mini = MiniAssistant::new(2);
+        assert_eq!(mini.take_question(), None);
+        let text = (1..=20).map(|i| format!("line {}", i)).collect::<Vec<_>>().join("\n");
+        mini.set_messages(vec![ChatMessage::Assistant {
+            text,
+            is_streaming: false,
+            cached: false,
+            timing: None,
+            timing_shown: false,
+            quotes: Vec::new(),
+        }], false);
+
+        let area = Rect::new(0, 0, 60, 30);
+        let (popup, rows) = mini.layout(area);
+        assert_eq!(popup.height, MAX_ANSWER_ROWS + 3);
+        assert_eq!(rows.last().map(text_of), Some("line 20".to_string()));
+
+        // Back to the top, and no further
+        mini.scroll(-100);
+        let (_, rows) = mini.layout(area);
+        assert_eq!(rows.first().map(text_of), Some("line 1".to_string()));
+        mini.scroll(3);
+        let (_, rows) = mini.layout(area);
+        assert_eq!(rows.first().map(text_of), Some("line 4".to_string()));
+    }
+}
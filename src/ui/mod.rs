@@ -15,22 +15,58 @@ use ratatui::{
     symbols::line,
     text::Line,
     widgets::{
-        Block, Borders, Paragraph, Widget
+        Block, Borders, Clear, Paragraph, Widget, Wrap
     },
 };
 use unicode_width::UnicodeWidthStr;
 
-use crate::app::{ActivePane, App};
+use crate::app::{ActivePane, App, QuickRun};
+use crate::config::project::Project;
+use crate::event::trace::EventTrace;
+use layout::{LayoutMode, MIN_HEIGHT, MIN_WIDTH};
+use visual::PaneStatus;
 
 pub mod assistant;
+pub mod checklist;
+pub mod command_marks;
+pub mod compare;
+pub mod context_chips;
+pub mod cues;
+pub mod drafts;
+pub mod file_drop;
+pub mod help;
+pub mod input_wrap;
 pub mod layout;
+pub mod layout_state;
+pub mod line_numbers;
+pub mod long_lines;
+pub mod macros;
+pub mod repeats;
+pub mod metrics;
+pub mod mini_assistant;
+pub mod pacing;
+pub mod performance;
+pub mod screen_dump;
+pub mod scrollbar;
+pub mod switcher;
 pub mod terminal;
+pub mod vi_input;
 pub mod visual;
 
 impl Widget for &App {
     fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
         // Use the pre-calculated layout
         let layout = self.layout();
+        if layout.mode == LayoutMode::TooSmall {
+            render_too_small(area, buf);
+            if let Some(project) = self.pending_project() {
+                render_trust_prompt(area, buf, project);
+            }
+            if let Some(banner) = self.state_banner() {
+                render_state_banner(area, buf, banner);
+            }
+            return;
+        }
 
         let active = self.get_active_pane();
         let cmdmode_color = Color::Yellow;
@@ -53,8 +89,39 @@ impl Widget for &App {
             )
         };
 
-        // Build terminal title with status from component
-        let term_title = build_pane_title("RustyTerm", &term_status.title_status);
+        // Command mode countdown (tmux-like) shown on the active pane's title
+        let leader = self.keymap().leader.to_string();
+        let command_status = self.command_mode_remaining().map(|remaining| {
+            format!("COMMAND {}s", remaining.as_millis().div_ceil(1000))
+        });
+        let term_title_status = match active {
+            ActivePane::Terminal if command_status.is_some() => &command_status,
+            _ => &term_status.title_status,
+        };
+        let ai_title_status = match active {
+            ActivePane::Assistant if command_status.is_some() => &command_status,
+            _ => &ai_status.title_status,
+        };
+
+        // Build terminal title with status from component; an active
+        // project overlay is named next to the app name, then come the
+        // plugins' status segments
+        let mut term_name = match self.active_project() {
+            Some(project) => format!("RustyTerm (project: {})", project.name()),
+            None => "RustyTerm".to_string(),
+        };
+        for segment in self.plugin_status() {
+            term_name.push_str(" · ");
+            term_name.push_str(&segment);
+        }
+        if self.cues_muted() {
+            term_name.push_str(" · muted");
+        }
+        if let Some(segment) = self.performance().title_segment() {
+            term_name.push_str(" · ");
+            term_name.push_str(segment);
+        }
+        let term_title = build_pane_title(&term_name, term_title_status);
         let block_term = Block::default()
             .title(term_title)
             .borders(Borders::TOP | Borders::BOTTOM | Borders::LEFT)
@@ -62,6 +129,8 @@ impl Widget for &App {
         let term_area = layout.terminal_inner;
         // Render terminal pane
         self.tui_terminal.render(term_area, buf);
+        self.tui_terminal.render_gutter(layout.terminal_gutter, buf);
+        self.tui_terminal.render_scrollbar(term_area, buf);
 
         // Determine separator style
         // Command mode: use same L-shape as normal mode based on active pane
@@ -83,11 +152,17 @@ impl Widget for &App {
                 }
             }
         };
+        // A lone pane always gets a closed box, the separator being its edge
+        let side = match (layout.mode, active, side) {
+            (LayoutMode::Single, ActivePane::Terminal, ActiveSide::None(color)) => ActiveSide::Left(color),
+            (LayoutMode::Single, ActivePane::Assistant, ActiveSide::None(color)) => ActiveSide::Right(color),
+            _ => side,
+        };
         // Render separator
         render_separator(layout.separator_area, buf, side, line::Set::default());
 
         // Build assistant title with status from component
-        let ai_title = build_pane_title("Assistant", &ai_status.title_status);
+        let ai_title = build_pane_title("Assistant", ai_title_status);
         let block_ai = Block::default()
             .title(ai_title)
             .borders(Borders::TOP | Borders::BOTTOM | Borders::RIGHT)
@@ -97,13 +172,30 @@ impl Widget for &App {
         self.tui_assistant.render(ai_area, buf);
 
         // Determine bottom hint from active pane's status
+        let default_hint = |status: &PaneStatus| {
+            let keys: Vec<&str> = status.enter_hint.into_iter().chain(status.key_hint).collect();
+            match layout.mode {
+                LayoutMode::Single => format!(" pane hidden — {} n to switch, enlarge for split view ", leader),
+                _ if keys.is_empty() => format!(" {}: Enter Command Mode ", leader),
+                _ => format!(" {} | {}: Command Mode ", keys.join(" | "), leader),
+            }
+        };
         let (hint, hint_color) = match active {
             ActivePane::Terminal => {
-                let hint = term_status.hint_text.unwrap_or(" Ctrl + B: Enter Command Mode ");
+                let hint = term_status.hint_text.map(String::from).unwrap_or_else(|| default_hint(&term_status));
                 (hint, active_termcolor)
             }
             ActivePane::Assistant => {
-                let hint = ai_status.hint_text.unwrap_or(" Ctrl + B: Enter Command Mode ");
+                // Point at a card waiting in another session, so Ctrl+Y isn't
+                // pressed here for it
+                let pending_elsewhere = self.tui_assistant.pending_elsewhere().map(|tab| {
+                    format!(" pending suggestion in {} — {} ] to view ", tab.name, leader)
+                });
+                let hint = ai_status
+                    .hint_text
+                    .map(String::from)
+                    .or(pending_elsewhere)
+                    .unwrap_or_else(|| default_hint(&ai_status));
                 (hint, active_aicolor)
             }
         };
@@ -118,12 +210,44 @@ impl Widget for &App {
         render_separator(layout.separator_area, buf, side, line::Set::default());
         block_ai.render(layout.assistant_area, buf);
 
+        // Resize glyph on the hovered separator
+        if self.is_separator_hovered() {
+            let sep = layout.separator_area;
+            let position = (sep.x, sep.y + sep.height / 2);
+            if let Some(cell) = buf.cell_mut(position) {
+                cell.set_symbol("↔").set_style(Style::default().bold());
+            }
+        }
+
+        // Split readout next to the separator while it is dragged
+        if let Some(ratio) = self.separator_drag_ratio() {
+            render_split_readout(layout.separator_area, area, buf, ratio);
+        }
+
+        // Render event trace overlay (debugging) if enabled
+        if self.is_event_trace_visible() {
+            render_event_trace(area, buf, self.event_trace());
+        }
+
+        // Render metrics overlay if enabled
+        if let Some(history) = self.metrics_overlay() {
+            metrics::render_metrics(area, buf, history);
+        }
+
+        // Render the long line viewer if open
+        if let Some((view, line)) = self.long_line_view() {
+            view.render(area, buf, line);
+        }
+
         // Render command mode popup if active
         if self.get_command_mode() {
-            let extra_hints: Vec<(String, String)> = match active {
+            let mut extra_hints: Vec<(String, String)> = vec![
+                (format!(" {}", leader), format!("Send {} to shell", leader)),
+            ];
+            extra_hints.extend(match active {
                 ActivePane::Terminal => vec![
-                    (" ^B".into(), "Send ^B to shell".into()),
                     (" V".into(), "Enter Visual mode".into()),
+                    (" O".into(), "Select last command output".into()),
                 ],
                 ActivePane::Assistant => vec![
                     (" T".into(), "New session".into()),
@@ -132,8 +256,35 @@ impl Widget for &App {
                     (" [".into(), "Previous session".into()),
                     (" V".into(), "Enter Visual mode".into()),
                 ],
-            };
-            render_command_mode_hint(area, buf, cmdmode_color, extra_hints);
+            });
+            let custom = self.quick_actions().iter().map(|action| (format!(" {}", action.key_text()), action.name.clone()));
+            render_command_mode_hint(area, buf, cmdmode_color, extra_hints, custom.collect());
+        }
+
+        if let Some(mini) = self.mini_assistant() {
+            mini.render(self.mini_assistant_area(), buf);
+        }
+
+        if let Some(switcher) = self.switcher() {
+            switcher.render(area, buf);
+        }
+
+        if let Some(help) = self.help() {
+            help.render(area, buf);
+        }
+
+        if let Some(run) = self.quick_run() {
+            render_quick_run_prompt(area, buf, run);
+        }
+
+        // The trust prompt is modal, so it goes on top of everything
+        if let Some(project) = self.pending_project() {
+            render_trust_prompt(area, buf, project);
+        }
+
+        // Over it the read-only banner, the first thing to answer
+        if let Some(banner) = self.state_banner() {
+            render_state_banner(area, buf, banner);
         }
     }
 }
@@ -157,6 +308,9 @@ enum ActiveSide {
 /// The separator's appearance changes based on which pane is active.
 fn render_separator(area: Rect, buf: &mut Buffer, side: ActiveSide, line_set: line::Set) {
     let height = area.height as usize;
+    if height == 0 {
+        return;
+    }
     let (top, vertical, bottom, color) = match side {
         ActiveSide::None(color) => (line_set.horizontal_down, line_set.vertical, line_set.horizontal_up, color),
         ActiveSide::Left(color) => (line_set.top_right, line_set.vertical, line_set.bottom_right, color),
@@ -174,6 +328,35 @@ fn render_separator(area: Rect, buf: &mut Buffer, side: ActiveSide, line_set: li
     separator.render(area, buf);
 }
 
+/// Renders "62% │ 38%" centered on the separator, a row above its middle
+/// (where the hover glyph goes), kept inside the screen.
+fn render_split_readout(separator: Rect, screen_area: Rect, buf: &mut Buffer, ratio: u16) {
+    let text = format!(" {}% │ {}% ", ratio, 100u16.saturating_sub(ratio));
+    let width = text.width() as u16;
+    if separator.height < 2 || width > screen_area.width {
+        return;
+    }
+    let max_x = screen_area.x + screen_area.width - width;
+    let x = separator.x.saturating_sub(width / 2).clamp(screen_area.x, max_x);
+    let y = separator.y + separator.height / 2 - 1;
+    buf.set_string(x, y, text, Style::default().fg(Color::Black).bg(Color::Yellow));
+}
+
+/// Render the placeholder shown instead of the panes on a tiny screen
+fn render_too_small(area: Rect, buf: &mut Buffer) {
+    let message = format!(
+        "terminal too small ({}×{}, need {}×{})",
+        area.width, area.height, MIN_WIDTH, MIN_HEIGHT
+    );
+    let height = (message.width() as u16).div_ceil(area.width.max(1));
+    let [text_area] = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center).areas(area);
+    Paragraph::new(message)
+        .alignment(ratatui::layout::Alignment::Center)
+        .wrap(Wrap { trim: true })
+        .fg(Color::Yellow)
+        .render(text_area, buf);
+}
+
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
 fn popup_area(area: Rect, width: u16, height: u16) -> Rect {
     let vertical = Layout::vertical([Constraint::Length(height)]).flex(Flex::Center);
@@ -183,28 +366,48 @@ fn popup_area(area: Rect, width: u16, height: u16) -> Rect {
     area
 }
 
-/// Render a pop-up with command mode hints
-fn render_command_mode_hint(screen_area: Rect, buf: &mut Buffer, fg_color: Color, extra_hints: impl IntoIterator<Item = (String, String)>) {
+/// Render a pop-up with command mode hints, the user's quick actions
+/// (`custom`) in a section of their own
+fn render_command_mode_hint(
+    screen_area: Rect,
+    buf: &mut Buffer,
+    fg_color: Color,
+    extra_hints: impl IntoIterator<Item = (String, String)>,
+    custom: Vec<(String, String)>,
+) {
     let mut lines: Vec<(String, String)> = vec![
-        (" N".into(), "Toggle active pane".into()),
+        (" n".into(), "Toggle active pane".into()),
+        (" Shift+N".into(), "Toggle line numbers".into()),
         (" Q".into(), "Exit program".into()),
         (" L".into(), "Force redraw (clear screen)".into()),
+        (" E".into(), "Toggle event trace".into()),
+        (" M".into(), "Toggle metrics".into()),
+        (" S".into(), "Switch session or pane".into()),
+        (" R".into(), "Start/stop recording".into()),
+        (" c".into(), "Copy terminal screen".into()),
+        (" Shift+C".into(), "Dump scrollback to a file".into()),
+        (" P".into(), "Open long line".into()),
         (" ←/→".into(), "Adjust separator".into()),
         (" <Any>".into(),"Quit command mode".into())
     ];
 
     lines.extend(extra_hints);
 
-    let max_key_width = lines.iter().map(|(a, _)| a.width()).max().unwrap_or(0);
-    let max_value_width = lines.iter().map(|(_, b)| b.width()).max().unwrap_or(0);
-    let lines: Vec<Line<'_>> = lines.into_iter().map(|(mut k, v)| {
+    let max_key_width = lines.iter().chain(&custom).map(|(a, _)| a.width()).max().unwrap_or(0);
+    let max_value_width = lines.iter().chain(&custom).map(|(_, b)| b.width()).max().unwrap_or(0);
+    let format = |(mut k, v): (String, String)| {
         k.extend(std::iter::repeat_n(' ', max_key_width - k.width()));
         k.push_str(": ");
         k.push_str(&v);
         k.extend(std::iter::repeat_n(' ', max_value_width - v.width()));
         k.push(' ');
         Line::from(k)
-    }).collect();
+    };
+    let mut lines: Vec<Line<'_>> = lines.into_iter().map(format).collect();
+    if !custom.is_empty() {
+        lines.push(Line::from(" Custom").bold());
+        lines.extend(custom.into_iter().map(format));
+    }
     let required_height = lines.len() + 2;
     let required_width = lines.iter().map(|l| l.width()).max().unwrap_or(0) + 2;
     let paragraph = Paragraph::new(lines);
@@ -213,9 +416,120 @@ fn render_command_mode_hint(screen_area: Rect, buf: &mut Buffer, fg_color: Color
         .borders(Borders::all())
         .title(" COMMAND MODE KEYMAP ")
         .title_alignment(ratatui::layout::Alignment::Center)
+        .title_bottom(Line::from(" ?: all keys ").right_aligned())
         .bg(Color::DarkGray)
         .fg(fg_color);
     let inner = block.inner(area);
     block.render(area, buf);
     paragraph.render(inner, buf);
 }
+
+/// Render the prompt asking whether to use a project's config file
+fn render_trust_prompt(screen_area: Rect, buf: &mut Buffer, project: &Project) {
+    let mut lines = vec![
+        Line::from(format!(" {} ", project.file.display())),
+        Line::from(""),
+    ];
+    let summary = project.config.summary();
+    if summary.is_empty() {
+        lines.push(Line::from("   (no settings)"));
+    }
+    lines.extend(summary.into_iter().map(|setting| Line::from(format!("   {} ", setting))));
+    lines.push(Line::from(""));
+    lines.push(Line::from(" y: Trust and apply   n/Esc: Ignore ").bold());
+
+    let width = (lines.iter().map(|l| l.width()).max().unwrap_or(0) + 2).min(screen_area.width as usize);
+    let area = popup_area(screen_area, width as u16, lines.len() as u16 + 2);
+    let block = Block::new()
+        .borders(Borders::all())
+        .title(" TRUST PROJECT CONFIG? ")
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .bg(Color::DarkGray)
+        .fg(Color::Yellow);
+    let inner = block.inner(area);
+    Clear.render(area, buf);
+    block.render(area, buf);
+    Paragraph::new(lines).render(inner, buf);
+}
+
+/// Render the banner saying why persisted state is read-only this run
+fn render_state_banner(screen_area: Rect, buf: &mut Buffer, text: &str) {
+    let width = screen_area.width.saturating_sub(4).min(72);
+    let text_width = usize::from(width.saturating_sub(4));
+    let mut lines: Vec<Line<'_>> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.width() + 1 + word.width() > text_width {
+            lines.push(Line::from(format!(" {} ", std::mem::take(&mut line))));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(Line::from(format!(" {} ", line)));
+    lines.push(Line::from(""));
+    lines.push(Line::from(" Enter/Esc: Dismiss until the next start ").bold());
+
+    let area = popup_area(screen_area, width, lines.len() as u16 + 2);
+    let block = Block::new()
+        .borders(Borders::all())
+        .title(" SAVED STATE IS READ-ONLY ")
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .bg(Color::DarkGray)
+        .fg(Color::Yellow);
+    let inner = block.inner(area);
+    Clear.render(area, buf);
+    block.render(area, buf);
+    Paragraph::new(lines).render(inner, buf);
+}
+
+/// Render the prompt asking whether to run a quick action's command
+fn render_quick_run_prompt(screen_area: Rect, buf: &mut Buffer, run: &QuickRun) {
+    let mut lines = vec![Line::from(format!(" $ {} ", run.command)), Line::from("")];
+    if let Some(reason) = &run.reason {
+        lines.push(Line::from(format!(" {} ", reason)).fg(Color::Yellow));
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::from(" y: Run   n/Esc: Cancel ").bold());
+
+    let width = (lines.iter().map(|l| l.width()).max().unwrap_or(0) + 2).min(screen_area.width as usize);
+    let area = popup_area(screen_area, width as u16, lines.len() as u16 + 2);
+    let block = Block::new()
+        .borders(Borders::all())
+        .title(format!(" RUN {}? ", run.name.to_uppercase()))
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .bg(Color::DarkGray)
+        .fg(Color::White);
+    let inner = block.inner(area);
+    Clear.render(area, buf);
+    block.render(area, buf);
+    Paragraph::new(lines).render(inner, buf);
+}
+
+/// Render the most recent events of the trace ring as an overlay (newest at the bottom)
+fn render_event_trace(screen_area: Rect, buf: &mut Buffer, trace: &EventTrace) {
+    let width = screen_area.width.saturating_sub(4).min(100);
+    let height = screen_area.height.saturating_sub(4);
+    let area = popup_area(screen_area, width, height);
+    let block = Block::new()
+        .borders(Borders::all())
+        .title(format!(" EVENT TRACE ({} events) ", trace.len()))
+        .title_alignment(ratatui::layout::Alignment::Center)
+        .bg(Color::Black)
+        .fg(Color::Magenta);
+    let inner = block.inner(area);
+
+    let visible = inner.height as usize;
+    let mut lines: Vec<Line<'_>> = trace
+        .entries()
+        .rev()
+        .take(visible)
+        .map(|entry| Line::from(entry.to_string()))
+        .collect();
+    lines.reverse();
+
+    Clear.render(area, buf);
+    block.render(area, buf);
+    Paragraph::new(lines).render(inner, buf);
+}
@@ -0,0 +1,204 @@
+//! When the next frame is drawn, and why.
+//!
+//! Content changes (PTY output, keys) are drawn one [`FRAME`] after they
+//! come in, so bursts are merged but typing stays responsive. Streamed text
+//! is drawn at most every [`STREAM_FRAME`]: chunks come every few
+//! milliseconds, and a reply growing 20 times a second reads as smoothly.
+//! Decoration changes (a countdown, a notice in a
+//! title, the metrics overlay) are drawn at most every [`DECORATION_FRAME`]:
+//! on their own they would otherwise keep the loop drawing at full rate for
+//! nothing anyone can read that fast. Performance mode stretches that to
+//! [`SLOW_DECORATION_FRAME`](super::performance::SLOW_DECORATION_FRAME).
+
+use tokio::time::{Duration, Instant};
+
+use crate::utils::metrics::DrawTrigger;
+
+/// Shortest time between two frames. A frame drawn more than this after it
+/// was due counts as dropped.
+pub const FRAME: Duration = Duration::from_millis(16);
+
+/// Shortest time between a frame and one drawn for streamed text (20 Hz).
+pub const STREAM_FRAME: Duration = Duration::from_millis(50);
+
+/// Shortest time between a frame and one drawn only for decorations (4 Hz).
+pub const DECORATION_FRAME: Duration = Duration::from_millis(250);
+
+/// The next frame's deadline and the strongest reason asked for it.
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    due: Option<(Instant, DrawTrigger)>,
+    last_frame: Option<Instant>,
+    decoration_frame: Duration,
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self { due: None, last_frame: None, decoration_frame: DECORATION_FRAME }
+    }
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shortest time between a frame and one drawn only for decorations.
+    pub fn set_decoration_interval(&mut self, interval: Duration) {
+        self.decoration_frame = interval;
+    }
+
+    /// When the next frame is due (None = nothing to draw).
+    pub fn due(&self) -> Option<Instant> {
+        self.due.map(|(at, _)| at)
+    }
+
+    /// Ask for a frame because content changed: `asap` draws it right away,
+    /// otherwise one [`FRAME`] from `now`.
+    pub fn content(&mut self, asap: bool, now: Instant) {
+        let at = if asap { now } else { now + FRAME };
+        self.schedule(at, DrawTrigger::Content);
+    }
+
+    /// Ask for a frame because a streamed reply grew: one [`FRAME`] from
+    /// `now`, and no sooner than [`STREAM_FRAME`] after the last one.
+    pub fn stream(&mut self, now: Instant) {
+        let at = self.last_frame.map_or(now + FRAME, |last| (now + FRAME).max(last + STREAM_FRAME));
+        self.schedule(at, DrawTrigger::Content);
+    }
+
+    /// Ask for a frame because only decorations changed: no sooner than
+    /// the decoration interval ([`DECORATION_FRAME`] unless set) after the
+    /// last one.
+    pub fn decoration(&mut self, now: Instant) {
+        let at = self.last_frame.map_or(now, |last| now.max(last + self.decoration_frame));
+        self.schedule(at, DrawTrigger::Decoration);
+    }
+
+    /// Ask for a frame at `at` exactly (a settling size, a flash ending).
+    pub fn at(&mut self, at: Instant, trigger: DrawTrigger) {
+        self.schedule(at, trigger);
+    }
+
+    /// The frame is being drawn: clears the deadline and returns it with the
+    /// reason. Cleared first, so a held frame can schedule itself again.
+    pub fn take(&mut self, now: Instant) -> Option<(Instant, DrawTrigger)> {
+        let due = self.due.take()?;
+        self.last_frame = Some(now);
+        Some(due)
+    }
+
+    // The earliest deadline wins, and does not keep moving while events
+    // keep coming; content wins over decorations for the reason
+    fn schedule(&mut self, at: Instant, trigger: DrawTrigger) {
+        self.due = Some(match self.due {
+            None => (at, trigger),
+            Some((existing, reason)) => (existing.min(at), reason.max(trigger)),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_earliest_deadline_wins_and_content_outranks_decoration() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new();
+        assert_eq!(pacer.due(), None);
+
+        pacer.content(false, start);
+        pacer.content(false, start + Duration::from_millis(10));
+        assert_eq!(pacer.due(), Some(start + FRAME));
+
+        // A decoration asked for after the content deadline leaves it be
+        pacer.decoration(start + FRAME + FRAME / 4);
+        assert_eq!(pacer.take(start + FRAME), Some((start + FRAME, DrawTrigger::Content)));
+        assert_eq!(pacer.due(), None);
+    }
+
+    #[test]
+    fn test_decorations_alone_are_drawn_at_most_four_times_a_second() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new();
+        let mut frames = 0;
+        // A decoration change every frame for ten seconds
+        for ms in (0..10_000).step_by(16) {
+            let now = start + Duration::from_millis(ms);
+            pacer.decoration(now);
+            if pacer.due().is_some_and(|due| due <= now) {
+                pacer.take(now);
+                frames += 1;
+            }
+        }
+        assert!((40..=41).contains(&frames), "{} frames", frames);
+    }
+
+    #[test]
+    fn test_decoration_interval_can_be_stretched() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new();
+        pacer.set_decoration_interval(Duration::from_secs(1));
+        pacer.decoration(start);
+        pacer.take(start);
+
+        pacer.decoration(start + DECORATION_FRAME);
+        assert_eq!(pacer.due(), Some(start + Duration::from_secs(1)));
+        // Content is drawn as soon as ever
+        pacer.content(true, start + DECORATION_FRAME);
+        assert_eq!(pacer.due(), Some(start + DECORATION_FRAME));
+    }
+
+    #[test]
+    fn test_content_after_a_decoration_frame_is_not_held_back() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new();
+        pacer.decoration(start);
+        pacer.take(start);
+
+        let now = start + Duration::from_millis(5);
+        pacer.decoration(now);
+        assert_eq!(pacer.due(), Some(start + DECORATION_FRAME));
+        pacer.content(false, now);
+        assert_eq!(pacer.take(now + FRAME), Some((now + FRAME, DrawTrigger::Content)));
+    }
+
+    // Frames drawn while a reply streams for 30 seconds: a chunk every 10
+    // to 16 ms, and the spinner and elapsed time changing 4 times a second
+    fn streamed_frames(on_chunk: impl Fn(&mut FramePacer, Instant)) -> (usize, usize) {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new();
+        let (mut frames, mut late) = (0, 0);
+        let mut next_chunk = 0;
+        for ms in 0..30_000u64 {
+            let now = start + Duration::from_millis(ms);
+            if ms == next_chunk {
+                on_chunk(&mut pacer, now);
+                next_chunk += 10 + ms % 7;
+            }
+            if ms % 250 == 0 {
+                pacer.decoration(now);
+            }
+            if let Some(due) = pacer.due().filter(|&due| due <= now) {
+                pacer.take(now);
+                frames += 1;
+                if now - due > FRAME {
+                    late += 1;
+                }
+            }
+        }
+        (frames, late)
+    }
+
+    #[test]
+    fn test_streamed_text_is_drawn_at_the_stream_rate() {
+        // Every chunk drawn as content, as before streamed text was paced
+        let (before, _) = streamed_frames(|pacer, now| pacer.content(false, now));
+        let (after, late) = streamed_frames(|pacer, now| pacer.stream(now));
+        assert!(before >= 2 * after, "{} frames before, {} after", before, after);
+        // The text still grows 20 times a second, and no frame is dropped
+        assert!(after >= 30 * 19, "{} frames", after);
+        assert_eq!(late, 0);
+    }
+}
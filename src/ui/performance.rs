@@ -0,0 +1,467 @@
+//! Performance mode: fewer expensive decorations for slow terminals.
+//!
+//! A pane in performance mode is drawn with the 16 named colors only (no
+//! RGB or 256-color escapes), without hover effects or a scrollbar, and
+//! with blank cells taking the style of their left neighbour, so a row is
+//! written with as few style changes as possible. Decoration-only frames
+//! are drawn at most every [`SLOW_DECORATION_FRAME`].
+//!
+//! `--performance` / `--no-performance` win over `[display] performance`
+//! in the config; its default, `auto`, draws a frame-sized payload at
+//! startup and times the terminal's answer to a DSR and DA1 query sent
+//! right behind it (see [`probe_terminal`]). Command mode `z` toggles the
+//! focused pane.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier};
+
+use crate::app::ActivePane;
+use crate::config::PerformanceSetting;
+
+/// Shortest time between a frame and one drawn only for decorations, in
+/// performance mode (1 Hz).
+pub const SLOW_DECORATION_FRAME: Duration = Duration::from_secs(1);
+
+/// How long the startup probe waits for the terminal to answer.
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// A terminal taking this long to draw the probe frame and answer is slow.
+pub const SLOW_ROUND_TRIP: Duration = Duration::from_millis(80);
+
+/// Cursor position report (DSR 6), then primary device attributes (DA1).
+/// Terminals answer in order, so the DA1 reply is the last one to come.
+const QUERY: &[u8] = b"\x1b[6n\x1b[c";
+
+/// Columns drawn in one color by the probe frame.
+const PROBE_COLOR_RUN: u16 = 8;
+
+/// Modifiers that do not show on a blank cell.
+const INVISIBLE_ON_BLANK: Modifier = Modifier::BOLD
+    .union(Modifier::DIM)
+    .union(Modifier::ITALIC)
+    .union(Modifier::SLOW_BLINK)
+    .union(Modifier::RAPID_BLINK)
+    .union(Modifier::HIDDEN);
+
+/// Which panes are in performance mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PerformanceMode {
+    pub terminal: bool,
+    pub assistant: bool,
+}
+
+impl PerformanceMode {
+    /// Both panes on, or both off.
+    pub fn both(on: bool) -> Self {
+        Self { terminal: on, assistant: on }
+    }
+
+    pub fn any(self) -> bool {
+        self.terminal || self.assistant
+    }
+
+    pub fn pane(self, pane: ActivePane) -> bool {
+        match pane {
+            ActivePane::Terminal => self.terminal,
+            ActivePane::Assistant => self.assistant,
+        }
+    }
+
+    /// Turn `pane` on or off. Returns whether it is on now.
+    pub fn toggle(&mut self, pane: ActivePane) -> bool {
+        let on = match pane {
+            ActivePane::Terminal => &mut self.terminal,
+            ActivePane::Assistant => &mut self.assistant,
+        };
+        *on = !*on;
+        *on
+    }
+
+    /// What the title bar shows (None = off everywhere).
+    pub fn title_segment(self) -> Option<&'static str> {
+        match (self.terminal, self.assistant) {
+            (true, true) => Some("perf"),
+            (true, false) => Some("perf: terminal"),
+            (false, true) => Some("perf: assistant"),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Outcome of the startup probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// The terminal answered this long after the frame started going out
+    Answered(Duration),
+    /// No answer before the timeout
+    NoAnswer,
+    /// Nothing to probe: not a terminal, or the query could not be sent
+    Unsupported,
+}
+
+/// Whether a probe outcome calls for performance mode. A terminal that
+/// does not answer at all within [`PROBE_TIMEOUT`] is taken as slow too.
+pub fn is_slow(probe: Probe) -> bool {
+    match probe {
+        Probe::Answered(round_trip) => round_trip >= SLOW_ROUND_TRIP,
+        Probe::NoAnswer => true,
+        Probe::Unsupported => false,
+    }
+}
+
+/// What decided the mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Flag,
+    Config,
+    Probe(Probe),
+}
+
+/// Whether performance mode starts on, and why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub on: bool,
+    pub source: Source,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = if self.on { "on" } else { "off" };
+        match self.source {
+            Source::Flag if self.on => write!(f, "{} (--performance)", state),
+            Source::Flag => write!(f, "{} (--no-performance)", state),
+            Source::Config => write!(f, "{} (config)", state),
+            Source::Probe(Probe::Answered(round_trip)) => {
+                write!(f, "{} (terminal answered in {} ms)", state, round_trip.as_millis())
+            }
+            Source::Probe(Probe::NoAnswer) => {
+                write!(f, "{} (no answer from the terminal in {} ms)", state, PROBE_TIMEOUT.as_millis())
+            }
+            Source::Probe(Probe::Unsupported) => write!(f, "{} (no terminal to probe)", state),
+        }
+    }
+}
+
+/// The command line flag wins, then the config; `auto` runs `probe`.
+pub fn decide(setting: PerformanceSetting, flag: Option<bool>, probe: impl FnOnce() -> Probe) -> Decision {
+    if let Some(on) = flag {
+        return Decision { on, source: Source::Flag };
+    }
+    match setting {
+        PerformanceSetting::On => Decision { on: true, source: Source::Config },
+        PerformanceSetting::Off => Decision { on: false, source: Source::Config },
+        PerformanceSetting::Auto => {
+            let probe = probe();
+            Decision { on: is_slow(probe), source: Source::Probe(probe) }
+        }
+    }
+}
+
+/// Write `payload` and the query to `out`, then wait for the replies.
+/// `read` waits up to the given time for input and returns what came
+/// (empty = nothing did). Timed from before the payload goes out.
+pub fn probe_round_trip(
+    out: &mut impl Write,
+    payload: &[u8],
+    mut read: impl FnMut(Duration) -> io::Result<Vec<u8>>,
+    timeout: Duration,
+) -> Probe {
+    let start = Instant::now();
+    if out.write_all(payload).and_then(|()| out.write_all(QUERY)).and_then(|()| out.flush()).is_err() {
+        return Probe::Unsupported;
+    }
+    let mut input = Vec::new();
+    let mut answered = None;
+    while let Some(left) = timeout.checked_sub(start.elapsed()) {
+        match read(left) {
+            Ok(chunk) if chunk.is_empty() => break,
+            Ok(chunk) => input.extend_from_slice(&chunk),
+            Err(_) => return Probe::Unsupported,
+        }
+        let (position, attributes) = replies(&input);
+        if answered.is_none() && (position || attributes) {
+            answered = Some(start.elapsed());
+        }
+        if attributes {
+            break;
+        }
+    }
+    answered.map_or(Probe::NoAnswer, Probe::Answered)
+}
+
+/// Whether `input` holds a cursor position report and a DA1 reply.
+fn replies(input: &[u8]) -> (bool, bool) {
+    let mut position = false;
+    let mut attributes = false;
+    for tail in (0..input.len()).map(|start| &input[start..]) {
+        let Some(rest) = tail.strip_prefix(b"\x1b[") else {
+            continue;
+        };
+        let (private, rest) = match rest.strip_prefix(b"?") {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let params = rest.iter().take_while(|&&b| b.is_ascii_digit() || b == b';').count();
+        match rest.get(params) {
+            Some(b'c') if private => attributes = true,
+            Some(b'R') if !private && params > 0 => position = true,
+            _ => {}
+        }
+    }
+    (position, attributes)
+}
+
+/// A screenful of text in changing RGB colors, about what a busy first
+/// frame costs the terminal.
+fn probe_frame(cols: u16, rows: u16) -> Vec<u8> {
+    let mut frame = String::from("\x1b[H");
+    for row in 0..rows {
+        frame.push_str(&format!("\x1b[{};1H", row + 1));
+        for col in 0..cols {
+            if col % PROBE_COLOR_RUN == 0 {
+                let shade = ((usize::from(row) * 7 + usize::from(col) * 3) % 256) as u8;
+                frame.push_str(&format!("\x1b[38;2;{};{};160m", shade, 255 - shade));
+            }
+            frame.push('#');
+        }
+    }
+    frame.push_str("\x1b[0m");
+    frame.into_bytes()
+}
+
+/// Time a frame-sized write plus a DSR/DA1 query on the real terminal, then
+/// blank the screen again. Call in raw mode, before anything else reads
+/// stdin: the replies (and any key typed meanwhile) are consumed here.
+#[cfg(unix)]
+pub fn probe_terminal(timeout: Duration) -> Probe {
+    use std::io::IsTerminal;
+
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Probe::Unsupported;
+    }
+    let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+    let mut out = io::stdout().lock();
+    let probe = probe_round_trip(&mut out, &probe_frame(cols, rows), read_stdin, timeout);
+    if let Err(e) = out.write_all(b"\x1b[2J\x1b[H").and_then(|()| out.flush()) {
+        tracing::warn!("Failed to clear the screen after the latency probe: {}", e);
+    }
+    probe
+}
+
+#[cfg(not(unix))]
+pub fn probe_terminal(_timeout: Duration) -> Probe {
+    Probe::Unsupported
+}
+
+/// Whatever stdin has within `wait` (empty if nothing came).
+#[cfg(unix)]
+fn read_stdin(wait: Duration) -> io::Result<Vec<u8>> {
+    let mut fds = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+    let millis = libc::c_int::try_from(wait.as_millis()).unwrap_or(libc::c_int::MAX);
+    // SAFETY: `fds` is one valid pollfd for the duration of the call
+    let ready = unsafe { libc::poll(&mut fds, 1, millis) };
+    if ready < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if ready == 0 {
+        return Ok(Vec::new());
+    }
+    let mut chunk = [0u8; 256];
+    // SAFETY: `chunk` is valid for writes of its whole length
+    let read = unsafe { libc::read(libc::STDIN_FILENO, chunk.as_mut_ptr().cast(), chunk.len()) };
+    match usize::try_from(read) {
+        Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+        Ok(read) => Ok(chunk[..read].to_vec()),
+        Err(_) => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Draw `area` of `buf` the performance mode way: named colors only, and
+/// blank cells styled like their left neighbour where it looks the same.
+pub fn simplify(buf: &mut Buffer, area: Rect) {
+    let area = area.intersection(buf.area);
+    for y in area.top()..area.bottom() {
+        let mut left: Option<(Color, Modifier)> = None;
+        for x in area.left()..area.right() {
+            let Some(cell) = buf.cell_mut((x, y)) else {
+                continue;
+            };
+            cell.fg = quantize(cell.fg);
+            cell.bg = quantize(cell.bg);
+            if let Some((fg, modifier)) = left
+                && cell.symbol() == " "
+                && INVISIBLE_ON_BLANK.contains(cell.modifier)
+                && INVISIBLE_ON_BLANK.contains(modifier)
+            {
+                cell.fg = fg;
+                cell.modifier = modifier;
+            }
+            left = Some((cell.fg, cell.modifier));
+        }
+    }
+}
+
+/// Style changes a renderer writes for `area` of `buf`: one per row, plus
+/// one per cell styled differently from its left neighbour.
+pub fn style_runs(buf: &Buffer, area: Rect) -> usize {
+    let area = area.intersection(buf.area);
+    let mut runs = 0;
+    for y in area.top()..area.bottom() {
+        let mut left = None;
+        for x in area.left()..area.right() {
+            let style = buf.cell((x, y)).map(|cell| (cell.fg, cell.bg, cell.modifier));
+            if left != Some(style) {
+                runs += 1;
+            }
+            left = Some(style);
+        }
+    }
+    runs
+}
+
+// xterm's default palette for the 16 named colors
+const NAMED: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// The nearest of the 16 named colors to an RGB or 256-color `color`;
+/// named colors and the default stay as they are.
+pub fn quantize(color: Color) -> Color {
+    let rgb = match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(index) if index < 16 => return NAMED[usize::from(index)].0,
+        Color::Indexed(index) if index < 232 => {
+            let level = |step: u8| if step == 0 { 0 } else { 55 + step * 40 };
+            let cube = index - 16;
+            (level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+        }
+        Color::Indexed(index) => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+        other => return other,
+    };
+    // A dark color is matched at normal intensity, so it keeps its hue
+    // instead of turning black
+    let (max, min) = (rgb.0.max(rgb.1).max(rgb.2), rgb.0.min(rgb.1).min(rgb.2));
+    let rgb = if max - min >= 32 && max < 205 {
+        let scale = |c: u8| (u16::from(c) * 205 / u16::from(max)) as u8;
+        (scale(rgb.0), scale(rgb.1), scale(rgb.2))
+    } else {
+        rgb
+    };
+    let distance = |(r, g, b): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+    };
+    NAMED
+        .iter()
+        .min_by_key(|(_, named)| distance(*named))
+        .map_or(color, |(named, _)| *named)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Style;
+
+    #[test]
+    fn test_slow_threshold() {
+        assert!(!is_slow(Probe::Answered(Duration::from_millis(3))));
+        assert!(!is_slow(Probe::Answered(SLOW_ROUND_TRIP - Duration::from_millis(1))));
+        assert!(is_slow(Probe::Answered(SLOW_ROUND_TRIP)));
+        assert!(is_slow(Probe::Answered(Duration::from_millis(400))));
+        assert!(is_slow(Probe::NoAnswer));
+        assert!(!is_slow(Probe::Unsupported));
+    }
+
+    #[test]
+    fn test_flag_then_config_then_probe() {
+        let slow = || Probe::Answered(Duration::from_millis(200));
+        let unused = || -> Probe { panic!("probed although the mode was set") };
+
+        assert_eq!(decide(PerformanceSetting::On, Some(false), unused), Decision { on: false, source: Source::Flag });
+        assert_eq!(decide(PerformanceSetting::Off, None, unused), Decision { on: false, source: Source::Config });
+        assert_eq!(decide(PerformanceSetting::On, None, unused), Decision { on: true, source: Source::Config });
+        let auto = decide(PerformanceSetting::Auto, None, slow);
+        assert!(auto.on);
+        assert_eq!(auto.to_string(), "on (terminal answered in 200 ms)");
+        assert!(!decide(PerformanceSetting::Auto, None, || Probe::Unsupported).on);
+    }
+
+    #[test]
+    fn test_probe_waits_for_the_attributes_reply() {
+        let mut replies = vec![b"\x1b[12;".to_vec(), b"40R\x1b[?62;".to_vec(), b"22c".to_vec()].into_iter();
+        let mut out = Vec::new();
+        let probe = probe_round_trip(&mut out, b"frame", |_| Ok(replies.next().unwrap_or_default()), PROBE_TIMEOUT);
+        assert!(matches!(probe, Probe::Answered(round_trip) if round_trip < PROBE_TIMEOUT));
+        assert_eq!(out, b"frame\x1b[6n\x1b[c");
+        // Everything was read: the attributes reply ends the probe
+        assert_eq!(replies.next(), None);
+    }
+
+    #[test]
+    fn test_probe_without_an_answer() {
+        let mut out = Vec::new();
+        // Keys typed meanwhile are not an answer
+        let mut input = vec![b"ls\r".to_vec()].into_iter();
+        let probe = probe_round_trip(&mut out, b"", |_| Ok(input.next().unwrap_or_default()), PROBE_TIMEOUT);
+        assert_eq!(probe, Probe::NoAnswer);
+        let probe = probe_round_trip(&mut out, b"", |_| Err(io::ErrorKind::UnexpectedEof.into()), PROBE_TIMEOUT);
+        assert_eq!(probe, Probe::Unsupported);
+    }
+
+    #[test]
+    fn test_quantize_to_named_colors() {
+        // The card buttons and the tab close button keep their colors
+        assert_eq!(quantize(Color::Rgb(0, 100, 0)), Color::Green);
+        assert_eq!(quantize(Color::Rgb(139, 0, 0)), Color::Red);
+        assert_eq!(quantize(Color::Rgb(180, 40, 40)), Color::Red);
+        assert_eq!(quantize(Color::Rgb(20, 20, 20)), Color::Black);
+        assert_eq!(quantize(Color::Rgb(250, 250, 250)), Color::White);
+        assert_eq!(quantize(Color::Indexed(9)), Color::LightRed);
+        assert_eq!(quantize(Color::Indexed(196)), Color::LightRed);
+        assert_eq!(quantize(Color::Indexed(244)), Color::DarkGray);
+        assert_eq!(quantize(Color::Cyan), Color::Cyan);
+        assert_eq!(quantize(Color::Reset), Color::Reset);
+    }
+
+    #[test]
+    fn test_simplify_coalesces_style_runs() {
+        let area = Rect::new(0, 0, 16, 1);
+        let mut buf = Buffer::empty(area);
+        // A gradient of words separated by unstyled blanks
+        for x in 0..16u16 {
+            let shade = (x * 16) as u8;
+            let style = if x % 4 == 3 { Style::default() } else { Style::default().fg(Color::Rgb(shade, 200, 200)).add_modifier(Modifier::BOLD) };
+            let symbol = if x % 4 == 3 { " " } else { "x" };
+            buf.set_string(x, 0, symbol, style);
+        }
+        let before = style_runs(&buf, area);
+        simplify(&mut buf, area);
+        let after = style_runs(&buf, area);
+        assert_eq!(before, 16);
+        assert!(after <= 3, "{} runs", after);
+        assert!(buf.content().iter().all(|cell| !matches!(cell.fg, Color::Rgb(..) | Color::Indexed(_))));
+    }
+}
@@ -0,0 +1,297 @@
+//! Runs of identical output lines, such as a process that logs the same
+//! line over and over prints.
+//!
+//! Thousands of copies of one line push everything else out of the
+//! scrollback and crowd the shell output the assistant sees. So PTY output
+//! goes through [`RepeatedLines::filter`], after the long lines are taken
+//! out. Once `threshold` copies of a line have arrived in a row, the first
+//! one stays and a dim marker row takes the place of the others:
+//! "… last line repeated 1,284 times …". The marker is rewritten in place
+//! while the run goes on. The copies themselves are not kept, only their
+//! count.
+//!
+//! Lines are compared without their color changes. A copy is held back
+//! until it is known whether the run collapses, so a few copies show up
+//! only when a different line starts or the user types. Blank lines never
+//! collapse, and an escape sequence other than a color change ends a run.
+
+use std::borrow::Cow;
+
+use super::long_lines::{EscapeParser, Step};
+
+/// Collapses runs of identical lines in the PTY output.
+#[derive(Debug)]
+pub struct RepeatedLines {
+    parser: EscapeParser,
+    /// Copies in a row, the first one included, that collapse
+    threshold: usize,
+    /// Copies a marker row stands for at most; the next one starts a new run
+    max_repeats: u64,
+    /// Text of the last line shown, without escape sequences and CRs;
+    /// `None` after a blank line or anything that ends a run
+    last: Option<Vec<u8>>,
+    /// Copies of `last` that arrived after it
+    repeats: u64,
+    /// Count on the marker row on screen (0 = no marker row)
+    shown: u64,
+    /// Bytes held back: copies not collapsed yet, then the current line
+    /// while it is one
+    held: Vec<u8>,
+    /// Text of the current line so far, compared as for `last`
+    line: Vec<u8>,
+    /// The current line is known not to be a copy; its bytes go through
+    diverged: bool,
+}
+
+impl RepeatedLines {
+    /// Collapse runs of `threshold` copies or more (at least 2), with up to
+    /// `max_repeats` copies behind one marker row.
+    pub fn new(threshold: usize, max_repeats: u64) -> Self {
+        Self {
+            parser: EscapeParser::default(),
+            threshold: threshold.max(2),
+            max_repeats: max_repeats.max(1),
+            last: None,
+            repeats: 0,
+            shown: 0,
+            held: Vec::new(),
+            line: Vec::new(),
+            diverged: true,
+        }
+    }
+
+    /// Has the run reached the threshold, so a marker row stands for it?
+    fn collapsed(&self) -> bool {
+        self.repeats + 1 >= self.threshold as u64
+    }
+
+    /// The output for the grid: `data`, with the copies of a line that
+    /// collapse left out and the marker row brought up to date.
+    pub fn filter<'a>(&mut self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        // Created when the output stops being `data` as it is
+        let mut out: Option<Vec<u8>> = None;
+        for (i, &byte) in data.iter().enumerate() {
+            match self.parser.step(byte) {
+                Step::Print | Step::Control => {
+                    let expected = self.last.as_ref().and_then(|last| last.get(self.line.len()));
+                    if expected != Some(&byte) {
+                        self.diverge_at(&mut out, data, i);
+                    }
+                    self.line.push(byte);
+                    self.keep(&mut out, data, i);
+                }
+                Step::LineBreak if byte == b'\n' => self.end_line(&mut out, data, i),
+                // With nothing held back, nothing can get ahead of them
+                Step::LineBreak | Step::Escape | Step::Sgr if self.held.is_empty() && !self.collapsed() => {
+                    if let Some(out) = &mut out {
+                        out.push(byte);
+                    }
+                }
+                Step::LineBreak | Step::Escape | Step::Sgr => self.keep(&mut out, data, i),
+                Step::OtherEscape => {
+                    self.diverge_at(&mut out, data, i);
+                    self.last = None;
+                    self.keep(&mut out, data, i);
+                }
+            }
+        }
+        if self.collapsed() && self.shown != self.repeats {
+            self.push_marker(owned(&mut out, data, data.len()));
+        }
+        match out {
+            Some(out) => Cow::Owned(out),
+            None => Cow::Borrowed(data),
+        }
+    }
+
+    /// Pass byte `i` of `data` on, or hold it back with the current line.
+    fn keep(&mut self, out: &mut Option<Vec<u8>>, data: &[u8], i: usize) {
+        match self.diverged {
+            true => {
+                if let Some(out) = out {
+                    out.push(data[i]);
+                }
+            }
+            false => {
+                owned(out, data, i);
+                self.held.push(data[i]);
+            }
+        }
+    }
+
+    /// [`Self::diverge`] before byte `i` of `data`, keeping the output
+    /// borrowed if nothing was held back.
+    fn diverge_at(&mut self, out: &mut Option<Vec<u8>>, data: &[u8], i: usize) {
+        if self.held.is_empty() && !self.collapsed() {
+            self.diverged = true;
+        } else {
+            self.diverge(owned(out, data, i));
+        }
+    }
+
+    /// The current line is not a copy: end the run and let out what was
+    /// held back.
+    fn diverge(&mut self, out: &mut Vec<u8>) {
+        if self.diverged {
+            return;
+        }
+        self.diverged = true;
+        if self.collapsed() {
+            if self.shown != self.repeats {
+                self.push_marker(out);
+            }
+            out.extend_from_slice(b"\r\n");
+        }
+        out.append(&mut self.held);
+        self.repeats = 0;
+        self.shown = 0;
+    }
+
+    /// A line break (byte `i` of `data`) ends the current line.
+    fn end_line(&mut self, out: &mut Option<Vec<u8>>, data: &[u8], i: usize) {
+        let copy = !self.diverged && self.last.as_ref().is_some_and(|last| last.len() == self.line.len());
+        if copy && self.repeats < self.max_repeats {
+            self.repeats += 1;
+            match self.collapsed() {
+                // The marker row stands for this copy and the ones held
+                true => self.held.clear(),
+                false => self.held.push(b'\n'),
+            }
+        } else {
+            self.diverge_at(out, data, i);
+            self.keep(out, data, i);
+            match self.line.iter().all(u8::is_ascii_whitespace) {
+                true => self.last = None,
+                false => std::mem::swap(self.last.get_or_insert_with(Vec::new), &mut self.line),
+            }
+        }
+        self.line.clear();
+        self.diverged = self.last.is_none();
+    }
+
+    /// Append the marker row for the copies so far, over the one on screen.
+    fn push_marker(&mut self, out: &mut Vec<u8>) {
+        out.extend_from_slice(marker(self.repeats).as_bytes());
+        self.shown = self.repeats;
+    }
+
+    /// End the run and let out what is held back, e.g. because the user
+    /// typed, so the echo does not land on the marker row or ahead of held
+    /// copies. Returns the bytes for the grid, if there are any.
+    pub fn end_held(&mut self) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        self.diverge(&mut out);
+        self.last = None;
+        self.line.clear();
+        self.diverged = true;
+        (!out.is_empty()).then_some(out)
+    }
+}
+
+/// The bytes for the grid so far, copying what went through of `data` (the
+/// bytes before `i`) on first use.
+fn owned<'o>(out: &'o mut Option<Vec<u8>>, data: &[u8], i: usize) -> &'o mut Vec<u8> {
+    out.get_or_insert_with(|| data[..i].to_vec())
+}
+
+/// The marker row for `repeats` copies, written over the row the cursor is
+/// on and leaving the cursor at its end.
+fn marker(repeats: u64) -> String {
+    format!("\r\x1b[2K\x1b[0;2m… last line repeated {} times …\x1b[0m", with_commas(repeats))
+}
+
+/// `n` with its digits grouped by thousands.
+fn with_commas(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_all(lines: &mut RepeatedLines, chunks: &[&[u8]]) -> Result<String, std::string::FromUtf8Error> {
+        String::from_utf8(chunks.iter().flat_map(|chunk| lines.filter(chunk).into_owned()).collect())
+    }
+
+    #[test]
+    fn test_short_runs_and_different_lines_pass() -> Result<(), Box<dyn std::error::Error>> {
+        let mut lines = RepeatedLines::new(5, 1_000_000);
+        let data = b"one\r\ntwo\r\n\r\n\r\n\r\n\r\n\r\nthree";
+        assert!(matches!(lines.filter(data), Cow::Borrowed(out) if out == data));
+
+        // Copies below the threshold are held until a different line starts
+        assert_eq!(filter_all(&mut lines, &[b"\r\nx\r\nx\r\n", b"x\r\nx\r\n"])?, "\r\nx\r\n");
+        assert_eq!(filter_all(&mut lines, &[b"y"])?, "x\r\nx\r\nx\r\ny");
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_collapses_into_an_updated_marker() -> Result<(), Box<dyn std::error::Error>> {
+        let mut lines = RepeatedLines::new(3, 1_000_000);
+        // Colors differ between copies, and a copy is split across reads
+        let out = filter_all(&mut lines, &[
+            b"\x1b[32mtick\x1b[0m\r\n\x1b[33mtick\x1b[0m\r\nti",
+            b"ck\r\ntick\r\n",
+            b"tick\r\n",
+            b"tock\r\n",
+        ])?;
+        // Leading color changes of a copy go through; the marker resets them
+        assert_eq!(out, [
+            "\x1b[32mtick\x1b[0m\r\n\x1b[33m",
+            "\r\x1b[2K\x1b[0;2m… last line repeated 3 times …\x1b[0m",
+            "\r\x1b[2K\x1b[0;2m… last line repeated 4 times …\x1b[0m",
+            "\r\ntock\r\n",
+        ].concat());
+
+        // An escape sequence other than a color change ends the run
+        let out = filter_all(&mut lines, &[b"tock\r\ntock\r\ntock\r\n\x1b]0;title\x07tock\r\n"])?;
+        assert!(out.ends_with("3 times …\x1b[0m\r\n\x1b]0;title\x07tock\r\n"), "{:?}", out);
+        Ok(())
+    }
+
+    #[test]
+    fn test_million_copies_keep_nothing_but_the_count() -> Result<(), Box<dyn std::error::Error>> {
+        let mut lines = RepeatedLines::new(5, 1_000_000);
+        let chunk = "2024-01-01 retrying connection\r\n".repeat(1000);
+        let mut shown = 0;
+        for _ in 0..1000 {
+            shown += lines.filter(chunk.as_bytes()).len();
+            assert!(lines.held.capacity() < 4 * 1024, "{} bytes held", lines.held.capacity());
+        }
+        // The first copy and one marker update per read
+        assert!(shown < 1000 * 64, "{} bytes shown", shown);
+        assert_eq!(lines.repeats, 999_999);
+
+        // A different line ends the run and starts a new one
+        let out = filter_all(&mut lines, &[b"done\r\n2024-01-01 retrying connection\r\n"])?;
+        assert_eq!(out, "\r\ndone\r\n2024-01-01 retrying connection\r\n");
+        assert_eq!(with_commas(999_999), "999,999");
+        assert_eq!(with_commas(1_284), "1,284");
+        assert_eq!(with_commas(12), "12");
+
+        // The copy after the cap starts a new run with the line shown again
+        let mut lines = RepeatedLines::new(2, 2);
+        let out = filter_all(&mut lines, &[b"a\na\na\na\n"])?;
+        assert_eq!(out, "a\n\r\x1b[2K\x1b[0;2m… last line repeated 2 times …\x1b[0m\r\na\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_typing_lets_held_copies_out() -> Result<(), Box<dyn std::error::Error>> {
+        let mut lines = RepeatedLines::new(5, 1_000_000);
+        assert_eq!(filter_all(&mut lines, &[b"$ ls\r\n$ ls\r\n$ "])?, "$ ls\r\n");
+        assert_eq!(lines.end_held(), Some(b"$ ls\r\n$ ".to_vec()));
+        assert_eq!(lines.end_held(), None);
+        // What comes next is not compared with the line before
+        assert_eq!(filter_all(&mut lines, &[b"ls\r\n"])?, "ls\r\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Text of terminal grid rows for copying the screen and dumping the
+//! scrollback. Colors and text attributes can be kept by re-encoding each
+//! cell's attributes as SGR sequences, so `less -R` shows the dump as the
+//! terminal did.
+
+use std::fmt::Write as _;
+
+use alacritty_terminal::term::cell::{Cell, Flags};
+use alacritty_terminal::vte::ansi::{Color, NamedColor};
+
+/// Flags that SGR sequences set; the rest describe the grid layout.
+const STYLE_FLAGS: Flags = Flags::BOLD
+    .union(Flags::DIM)
+    .union(Flags::ITALIC)
+    .union(Flags::ALL_UNDERLINES)
+    .union(Flags::INVERSE)
+    .union(Flags::HIDDEN)
+    .union(Flags::STRIKEOUT);
+
+/// What an SGR sequence can say about a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellAttrs {
+    pub fg: Color,
+    pub bg: Color,
+    pub flags: Flags,
+    pub underline_color: Option<Color>,
+}
+
+impl Default for CellAttrs {
+    fn default() -> Self {
+        Self {
+            fg: Color::Named(NamedColor::Foreground),
+            bg: Color::Named(NamedColor::Background),
+            flags: Flags::empty(),
+            underline_color: None,
+        }
+    }
+}
+
+impl CellAttrs {
+    pub fn of(cell: &Cell) -> Self {
+        Self {
+            fg: cell.fg,
+            bg: cell.bg,
+            flags: cell.flags & STYLE_FLAGS,
+            underline_color: cell.underline_color(),
+        }
+    }
+
+    /// Append the SGR sequence that switches any attributes to these ones.
+    /// It starts with a reset, so it does not depend on what came before.
+    pub fn push_sgr(&self, out: &mut String) {
+        out.push_str("\x1b[0");
+        for (flag, code) in [
+            (Flags::BOLD, "1"),
+            (Flags::DIM, "2"),
+            (Flags::ITALIC, "3"),
+            (Flags::UNDERLINE, "4"),
+            (Flags::DOUBLE_UNDERLINE, "4:2"),
+            (Flags::UNDERCURL, "4:3"),
+            (Flags::DOTTED_UNDERLINE, "4:4"),
+            (Flags::DASHED_UNDERLINE, "4:5"),
+            (Flags::INVERSE, "7"),
+            (Flags::HIDDEN, "8"),
+            (Flags::STRIKEOUT, "9"),
+        ] {
+            if self.flags.contains(flag) {
+                out.push(';');
+                out.push_str(code);
+            }
+        }
+        push_color(out, self.fg, Some(30), 38);
+        push_color(out, self.bg, Some(40), 48);
+        if let Some(color) = self.underline_color {
+            push_color(out, color, None, 58);
+        }
+        out.push('m');
+    }
+}
+
+/// Append the parameters selecting `color`: `basic` + 0..7 (and the bright
+/// `basic` + 60 range) for the 16 named colors if there is such a range,
+/// otherwise and for the rest `extended` with a 256-color index or RGB.
+/// The default colors need no parameter after a reset.
+fn push_color(out: &mut String, color: Color, basic: Option<u16>, extended: u16) {
+    match color {
+        Color::Named(named) if (named as u16) < 16 => match basic {
+            Some(basic) if (named as u16) < 8 => write!(out, ";{}", basic + named as u16),
+            Some(basic) => write!(out, ";{}", basic + 60 + named as u16 - 8),
+            None => write!(out, ";{};5;{}", extended, named as u16),
+        },
+        Color::Named(_) => Ok(()),
+        Color::Indexed(index) => write!(out, ";{};5;{}", extended, index),
+        Color::Spec(rgb) => write!(out, ";{};2;{};{};{}", extended, rgb.r, rgb.g, rgb.b),
+    }
+    .ok();
+}
+
+/// Whether the row continues on the next one (the line was wrapped).
+pub fn wraps(cells: &[Cell]) -> bool {
+    cells.last().is_some_and(|cell| cell.flags.contains(Flags::WRAPLINE))
+}
+
+/// Append the text of a grid row, without a line break. Spacers behind wide
+/// characters are skipped, combining marks kept, and a tab is the blank it
+/// is drawn as (the cells it skipped are blanks already). Trailing blanks
+/// are dropped unless the row wraps. With `colors`, attribute changes
+/// become SGR sequences and the row ends with the attributes reset.
+pub fn push_row(out: &mut String, cells: &[Cell], colors: bool) {
+    let is_blank = |cell: &Cell| {
+        matches!(cell.c, ' ' | '\0' | '\t') && (!colors || CellAttrs::of(cell) == CellAttrs::default())
+    };
+    let end = match wraps(cells) {
+        true => cells.len(),
+        false => cells.iter().rposition(|cell| !is_blank(cell)).map_or(0, |last| last + 1),
+    };
+
+    let mut current = CellAttrs::default();
+    for cell in &cells[..end] {
+        if cell.flags.intersects(Flags::WIDE_CHAR_SPACER | Flags::LEADING_WIDE_CHAR_SPACER) {
+            continue;
+        }
+        if colors {
+            let attrs = CellAttrs::of(cell);
+            if attrs != current {
+                attrs.push_sgr(out);
+                current = attrs;
+            }
+        }
+        out.push(if matches!(cell.c, '\0' | '\t') { ' ' } else { cell.c });
+        out.extend(cell.zerowidth().into_iter().flatten());
+    }
+    if current != CellAttrs::default() {
+        out.push_str("\x1b[0m");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alacritty_terminal::event::VoidListener;
+    use alacritty_terminal::grid::Dimensions;
+    use alacritty_terminal::index::{Column, Line};
+    use alacritty_terminal::term::{Config, Term, test::TermSize};
+    use alacritty_terminal::vte::ansi::{Processor, Rgb};
+
+    fn term_with(bytes: &[u8]) -> Term<VoidListener> {
+        let mut term = Term::new(Config::default(), &TermSize::new(10, 3), VoidListener);
+        let mut processor: Processor = Processor::new();
+        processor.advance(&mut term, bytes);
+        term
+    }
+
+    #[test]
+    fn test_sgr_roundtrips_through_the_parser() {
+        let named = |i| Color::Named([
+            NamedColor::Black, NamedColor::Red, NamedColor::Green, NamedColor::Yellow,
+            NamedColor::Blue, NamedColor::Magenta, NamedColor::Cyan, NamedColor::White,
+            NamedColor::BrightBlack, NamedColor::BrightRed, NamedColor::BrightGreen,
+            NamedColor::BrightYellow, NamedColor::BrightBlue, NamedColor::BrightMagenta,
+            NamedColor::BrightCyan, NamedColor::BrightWhite,
+        ][i]);
+        let default = CellAttrs::default();
+        let mut cases = vec![default];
+        for i in 0..16 {
+            cases.push(CellAttrs { fg: named(i), bg: named(15 - i), ..default });
+        }
+        for flag in [
+            Flags::BOLD, Flags::DIM, Flags::ITALIC, Flags::UNDERLINE, Flags::DOUBLE_UNDERLINE,
+            Flags::UNDERCURL, Flags::DOTTED_UNDERLINE, Flags::DASHED_UNDERLINE, Flags::INVERSE,
+            Flags::HIDDEN, Flags::STRIKEOUT,
+        ] {
+            cases.push(CellAttrs { flags: flag, ..default });
+        }
+        cases.extend([
+            CellAttrs { fg: Color::Indexed(0), bg: Color::Indexed(255), ..default },
+            CellAttrs { fg: Color::Indexed(100), flags: Flags::BOLD | Flags::ITALIC | Flags::INVERSE, ..default },
+            CellAttrs {
+                fg: Color::Spec(Rgb { r: 1, g: 128, b: 255 }),
+                bg: Color::Spec(Rgb { r: 0, g: 0, b: 0 }),
+                flags: Flags::UNDERCURL | Flags::DIM | Flags::STRIKEOUT,
+                underline_color: Some(Color::Spec(Rgb { r: 255, g: 0, b: 10 })),
+            },
+            CellAttrs { flags: Flags::UNDERLINE, underline_color: Some(Color::Indexed(9)), ..default },
+        ]);
+
+        for attrs in cases {
+            let mut bytes = String::from("\x1b[1;7;38;5;3m");
+            attrs.push_sgr(&mut bytes);
+            bytes.push('x');
+            let term = term_with(bytes.as_bytes());
+            let cell = &term.grid()[Line(0)][Column(0)];
+            assert_eq!(cell.c, 'x');
+            assert_eq!(CellAttrs::of(cell), attrs, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn test_row_reencodes_the_parsed_cells() {
+        let input = "\x1b[1;31mred\x1b[0m plain \x1b[4;48;5;200mu\x1b[0m";
+        let term = term_with(format!("{}漢e\u{301}", input).as_bytes());
+        let grid = term.grid();
+        assert_eq!(grid.columns(), 10);
+
+        let mut text = String::new();
+        push_row(&mut text, &grid[Line(0)][..], false);
+        assert_eq!(text, "red plain ");
+        assert!(wraps(&grid[Line(0)][..]));
+
+        // Feeding the colored row back in gives the same cells
+        let mut colored = String::new();
+        push_row(&mut colored, &grid[Line(0)][..], true);
+        assert_eq!(colored, "\x1b[0;1;31mred\x1b[0m plain ");
+        let again = term_with(colored.as_bytes());
+        for col in 0..10 {
+            let (a, b) = (&grid[Line(0)][Column(col)], &again.grid()[Line(0)][Column(col)]);
+            assert_eq!((a.c, CellAttrs::of(a)), (b.c, CellAttrs::of(b)));
+        }
+
+        // The last column went to the next row, where the wide character
+        // and the combining mark are kept and the blanks after them dropped
+        text.clear();
+        push_row(&mut text, &grid[Line(1)][..], true);
+        assert_eq!(text, "\x1b[0;4;48;5;200mu\x1b[0m漢e\u{301}");
+    }
+}
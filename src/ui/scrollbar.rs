@@ -0,0 +1,164 @@
+//! One-column scroll position indicator shared by both panes.
+//!
+//! The scrollbar overlays the rightmost column of a pane's content area and is
+//! only drawn while the pane is scrolled away from the bottom or in visual
+//! mode, so it never shifts content. Positions are expressed like the panes'
+//! own scroll state: `offset` is the number of lines scrolled up from the
+//! bottom.
+
+use ratatui::prelude::{Buffer, Rect};
+use ratatui::style::{Color, Style};
+
+const TRACK_SYMBOL: &str = "│";
+const THUMB_SYMBOL: &str = "┃";
+const TRACK_STYLE: Style = Style::new().fg(Color::DarkGray);
+const THUMB_STYLE: Style = Style::new().fg(Color::Gray);
+
+/// Position of the thumb within the track, in rows from the top.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Thumb {
+    pub start: u16,
+    pub len: u16,
+}
+
+/// Thumb for a viewport of `viewport` lines over `total` lines, scrolled
+/// `offset` lines up from the bottom. None when everything fits.
+pub fn thumb(track: u16, total: usize, viewport: usize, offset: usize) -> Option<Thumb> {
+    if track == 0 || total <= viewport {
+        return None;
+    }
+    let track = u128::from(track);
+    let (total, viewport) = (total as u128, viewport as u128);
+    let len = (track * viewport / total).clamp(1, track);
+
+    let max_offset = total - viewport;
+    let top = max_offset - (offset as u128).min(max_offset);
+    let travel = track - len;
+    // Rounded, so the thumb touches both ends only at the real top and bottom
+    let start = (travel * top + max_offset / 2) / max_offset;
+
+    Some(Thumb { start: start as u16, len: len as u16 })
+}
+
+/// Scroll offset that centers the thumb on `row` (clicking or dragging the track).
+pub fn offset_at(track: u16, total: usize, viewport: usize, row: u16) -> Option<usize> {
+    let Thumb { len, .. } = thumb(track, total, viewport, 0)?;
+    let max_offset = total - viewport;
+    let travel = u128::from(track - len);
+    if travel == 0 {
+        return Some(0);
+    }
+    let start = u128::from(row.saturating_sub(len / 2)).min(travel);
+    let top = (start * max_offset as u128 + travel / 2) / travel;
+    Some(max_offset - top as usize)
+}
+
+/// The column the scrollbar occupies in `area` (its rightmost column).
+pub fn column(area: Rect) -> Rect {
+    Rect {
+        x: (area.x + area.width).saturating_sub(1),
+        width: area.width.min(1),
+        ..area
+    }
+}
+
+/// Row within the track if `(col, row)` is on the scrollbar column of `area`.
+pub fn hit(area: Rect, col: u16, row: u16) -> Option<u16> {
+    let bar = column(area);
+    (bar.width > 0 && col == bar.x && row >= bar.y && row < bar.y + bar.height).then(|| row - bar.y)
+}
+
+/// Draw the track and thumb over the rightmost column of `area`.
+pub fn render(area: Rect, buf: &mut Buffer, thumb: Thumb) {
+    let bar = column(area);
+    if bar.width == 0 {
+        return;
+    }
+    for y in 0..bar.height {
+        let on_thumb = y >= thumb.start && y < thumb.start + thumb.len;
+        let (symbol, style) = if on_thumb {
+            (THUMB_SYMBOL, THUMB_STYLE)
+        } else {
+            (TRACK_SYMBOL, TRACK_STYLE)
+        };
+        if let Some(cell) = buf.cell_mut((bar.x, bar.y + y)) {
+            cell.set_symbol(symbol).set_style(style);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_thumb_when_content_fits() {
+        assert_eq!(thumb(10, 5, 10, 0), None);
+        assert_eq!(thumb(10, 10, 10, 0), None);
+        assert_eq!(thumb(0, 100, 10, 0), None);
+        assert_eq!(offset_at(10, 10, 10, 3), None);
+    }
+
+    #[test]
+    fn test_thumb_one_line_over_viewport() {
+        // 11 lines in a 10-line viewport: the thumb nearly fills the track
+        assert_eq!(thumb(10, 11, 10, 0), Some(Thumb { start: 1, len: 9 }));
+        assert_eq!(thumb(10, 11, 10, 1), Some(Thumb { start: 0, len: 9 }));
+    }
+
+    #[test]
+    fn test_thumb_position_and_size() {
+        // 100 lines, 20 visible, 10-row track: thumb is 2 rows
+        assert_eq!(thumb(10, 100, 20, 0), Some(Thumb { start: 8, len: 2 }));
+        assert_eq!(thumb(10, 100, 20, 80), Some(Thumb { start: 0, len: 2 }));
+        assert_eq!(thumb(10, 100, 20, 40), Some(Thumb { start: 4, len: 2 }));
+        // Offsets past the top clamp
+        assert_eq!(thumb(10, 100, 20, 1_000), Some(Thumb { start: 0, len: 2 }));
+    }
+
+    #[test]
+    fn test_thumb_for_huge_content() {
+        let total = 10_000_000_000_000;
+        assert_eq!(thumb(40, total, 40, 0), Some(Thumb { start: 39, len: 1 }));
+        assert_eq!(thumb(40, total, 40, total), Some(Thumb { start: 0, len: 1 }));
+        // A quarter of the way down: 39 rows of travel * 0.25, rounded
+        assert_eq!(thumb(40, total, 40, total / 4 * 3).map(|t| t.start), Some(10));
+        assert_eq!(thumb(u16::MAX, usize::MAX, 1, 0).map(|t| t.len), Some(1));
+    }
+
+    #[test]
+    fn test_offset_at_ends_of_track() {
+        assert_eq!(offset_at(10, 100, 20, 0), Some(80));
+        assert_eq!(offset_at(10, 100, 20, 9), Some(0));
+        // Dragging past the track clamps to the bottom
+        assert_eq!(offset_at(10, 100, 20, 50), Some(0));
+        // Thumb as tall as the track
+        assert_eq!(offset_at(10, 11, 10, 5), Some(0));
+    }
+
+    #[test]
+    fn test_offset_at_round_trips_through_thumb() {
+        for row in 0..10 {
+            let Some(offset) = offset_at(10, 1_000, 100, row) else {
+                continue;
+            };
+            let start = thumb(10, 1_000, 100, offset).map(|t| t.start);
+            assert_eq!(start, Some(row.min(9)));
+        }
+    }
+
+    #[test]
+    fn test_hit_and_render_use_rightmost_column() {
+        let area = Rect::new(2, 1, 8, 4);
+        assert_eq!(hit(area, 9, 1), Some(0));
+        assert_eq!(hit(area, 9, 4), Some(3));
+        assert_eq!(hit(area, 8, 2), None);
+        assert_eq!(hit(area, 9, 5), None);
+
+        let mut buf = Buffer::empty(Rect::new(0, 0, 12, 6));
+        render(area, &mut buf, Thumb { start: 1, len: 2 });
+        let column: Vec<&str> = (1..5).map(|y| buf[(9, y)].symbol()).collect();
+        assert_eq!(column, [TRACK_SYMBOL, THUMB_SYMBOL, THUMB_SYMBOL, TRACK_SYMBOL]);
+        assert_eq!(buf[(8, 2)].symbol(), " ");
+    }
+}
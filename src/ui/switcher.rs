@@ -0,0 +1,360 @@
+//! Quick switcher: one filterable list of AI sessions and the shell.
+//!
+//! Opened from command mode, it lists every session (name, how long ago it
+//! was last used, an unread badge and a pending-command flag) and the shell
+//! pane. Typing filters the list with a fuzzy, subsequence match on the
+//! titles; Enter jumps to the highlighted entry.
+
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Widget};
+use tokio::time::{Duration, Instant};
+use unicode_width::UnicodeWidthStr;
+
+use crate::ai::session::SessionId;
+use super::assistant::SessionTab;
+
+/// Where an entry jumps to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchTarget {
+    Session(SessionId),
+    Shell,
+}
+
+/// One row of the switcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwitchEntry {
+    pub target: SwitchTarget,
+    /// Matched against the query
+    pub title: String,
+    /// Shown dimmed after the title (last activity, last command)
+    pub detail: String,
+    pub unread: bool,
+    pub pending: bool,
+}
+
+impl SwitchEntry {
+    /// Entry for an AI session, its age measured at `now`.
+    pub fn session(tab: &SessionTab, now: Instant) -> Self {
+        let detail = match tab.last_activity {
+            Some(at) => format_age(now.saturating_duration_since(at)),
+            None => "new".to_string(),
+        };
+        Self {
+            target: SwitchTarget::Session(tab.id),
+            title: tab.name.clone(),
+            detail,
+            unread: tab.unread,
+            pending: tab.pending,
+        }
+    }
+
+    /// Entry for the shell pane, with the last command typed in it.
+    pub fn shell(shell: &str, last_command: Option<&str>) -> Self {
+        Self {
+            target: SwitchTarget::Shell,
+            title: format!("Shell ({})", shell),
+            detail: last_command.map(|cmd| format!("last: {}", cmd)).unwrap_or_default(),
+            unread: false,
+            pending: false,
+        }
+    }
+}
+
+/// "just now", "5m ago", "2h ago", "3d ago".
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3_600 => format!("{}m ago", secs / 60),
+        3_600..86_400 => format!("{}h ago", secs / 3_600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+/// How well a query matched a title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    pub score: i32,
+    /// Char indices of the matched characters in the title
+    pub positions: Vec<usize>,
+}
+
+/// Match `query` as a case-insensitive subsequence of `text`.
+///
+/// Every matched char scores, with bonuses for runs of consecutive chars
+/// and for chars that start a word, and a penalty for skipped chars, so
+/// "s2" ranks "Session 2" above "Session 12". An empty query matches
+/// everything with score 0; None if some char of the query is missing.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().collect();
+    // Jumping to word starts usually scores best, but can miss a match that
+    // taking the next occurrence of each char finds
+    match (scan(&query, &text, true), scan(&query, &text, false)) {
+        (Some(a), Some(b)) => Some(if a.score >= b.score { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// One left-to-right pass of [`fuzzy_match`]; with `word_starts`, each char
+/// is looked for at the next word start before anywhere else.
+fn scan(query: &[char], text: &[char], word_starts: bool) -> Option<FuzzyMatch> {
+    const MATCH: i32 = 1;
+    const CONSECUTIVE: i32 = 5;
+    const WORD_START: i32 = 8;
+    const GAP: i32 = 1;
+
+    let mut positions = Vec::new();
+    let mut score = 0;
+    let mut next = 0;
+    for &wanted in query {
+        let is_wanted = |i: &usize| text[*i].to_lowercase().eq(std::iter::once(wanted));
+        let found = (next..text.len())
+            .filter(is_wanted)
+            .find(|&i| word_starts && is_word_start(text, i))
+            .or_else(|| (next..text.len()).find(is_wanted))?;
+
+        score += MATCH;
+        if is_word_start(text, found) {
+            score += WORD_START;
+        }
+        match positions.last() {
+            Some(&prev) if prev + 1 == found => score += CONSECUTIVE,
+            _ => score -= GAP * (found - next).min(10) as i32,
+        }
+        positions.push(found);
+        next = found + 1;
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Does a word start at `i` (after a non-alphanumeric char or a lower to
+/// upper case change)?
+fn is_word_start(text: &[char], i: usize) -> bool {
+    match i.checked_sub(1).map(|prev| text[prev]) {
+        None => true,
+        Some(prev) => !prev.is_alphanumeric() || (prev.is_lowercase() && text[i].is_uppercase()),
+    }
+}
+
+/// State of the open switcher.
+#[derive(Debug, Clone)]
+pub struct Switcher {
+    entries: Vec<SwitchEntry>,
+    query: String,
+    /// Entries matching the query, best first: (entry index, match)
+    matches: Vec<(usize, FuzzyMatch)>,
+    /// Highlighted row in `matches`
+    selected: usize,
+}
+
+impl Switcher {
+    pub fn new(entries: Vec<SwitchEntry>) -> Self {
+        let mut switcher = Self { entries, query: String::new(), matches: Vec::new(), selected: 0 };
+        switcher.refilter();
+        switcher
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn push_char(&mut self, c: char) {
+        self.query.push(c);
+        self.refilter();
+    }
+
+    pub fn pop_char(&mut self) {
+        self.query.pop();
+        self.refilter();
+    }
+
+    /// Move the highlight by `delta` rows, wrapping around.
+    pub fn move_selection(&mut self, delta: isize) {
+        let len = self.matches.len();
+        if len > 0 {
+            self.selected = (self.selected as isize + delta).rem_euclid(len as isize) as usize;
+        }
+    }
+
+    /// Target of the highlighted entry (None if nothing matches).
+    pub fn selected_target(&self) -> Option<SwitchTarget> {
+        let (entry, _) = self.matches.get(self.selected)?;
+        Some(self.entries[*entry].target)
+    }
+
+    /// Titles of the matching entries, best first.
+    pub fn visible_titles(&self) -> Vec<&str> {
+        self.matches.iter().map(|(entry, _)| self.entries[*entry].title.as_str()).collect()
+    }
+
+    fn refilter(&mut self) {
+        self.matches = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| Some((i, fuzzy_match(&self.query, &entry.title)?)))
+            .collect();
+        // Stable, so equal scores keep the list order
+        self.matches.sort_by_key(|(_, m)| std::cmp::Reverse(m.score));
+        self.selected = 0;
+    }
+
+    fn entry_line(&self, entry: &SwitchEntry, positions: &[usize], title_width: usize, selected: bool) -> Line<'static> {
+        let base = if selected {
+            Style::default().fg(Color::Black).bg(Color::Cyan)
+        } else {
+            Style::default()
+        };
+        let matched = base.fg(if selected { Color::Black } else { Color::Yellow }).add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+
+        let mut spans = vec![Span::styled(" ", base)];
+        for (i, c) in entry.title.chars().enumerate() {
+            let style = if positions.contains(&i) { matched } else { base };
+            spans.push(Span::styled(c.to_string(), style));
+        }
+        let padding = title_width.saturating_sub(entry.title.width()) + 2;
+        spans.push(Span::styled(" ".repeat(padding), base));
+        let dim = if selected { base } else { base.fg(Color::DarkGray) };
+        spans.push(Span::styled(entry.detail.clone(), dim));
+        if entry.unread {
+            spans.push(Span::styled(" ● new", base.fg(if selected { Color::Black } else { Color::Green })));
+        }
+        if entry.pending {
+            spans.push(Span::styled(" ! pending", base.fg(if selected { Color::Black } else { Color::Yellow })));
+        }
+        spans.push(Span::styled(" ", base));
+        Line::from(spans)
+    }
+}
+
+impl Widget for &Switcher {
+    /// Draw the switcher centered in `area` (the whole screen).
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title_width = self.entries.iter().map(|e| e.title.width()).max().unwrap_or(0);
+
+        // Room for every entry, so the width doesn't jump while filtering
+        let widest = self
+            .entries
+            .iter()
+            .map(|e| title_width + e.detail.width() + 20)
+            .max()
+            .unwrap_or(0);
+        let width = (widest as u16 + 2).max(40).min(area.width);
+        let height = (self.entries.len() as u16 + 4).min(area.height);
+        let popup = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        };
+
+        // Query line, a blank line, then the rows, scrolled to keep the
+        // highlighted one in view
+        let mut lines = vec![Line::from(format!(" > {}", self.query)), Line::from("")];
+        if self.matches.is_empty() {
+            lines.push(Line::from(" no match").style(Style::default().fg(Color::DarkGray)));
+        }
+        let rows_height = (height as usize).saturating_sub(4);
+        let skip = (self.selected + 1).saturating_sub(rows_height);
+        lines.extend(self.matches.iter().enumerate().skip(skip).map(|(row, (entry, m))| {
+            self.entry_line(&self.entries[*entry], &m.positions, title_width, row == self.selected)
+        }));
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(" Switch to ")
+            .title_bottom(" type to filter · ↑/↓ Enter · Esc ")
+            .border_style(Style::default().fg(Color::Cyan));
+        let inner = block.inner(popup);
+        Clear.render(popup, buf);
+        block.render(popup, buf);
+        Paragraph::new(lines).render(inner, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: SessionId, name: &str) -> SwitchEntry {
+        let tab = SessionTab { id, name: name.to_string(), ..SessionTab::default() };
+        SwitchEntry::session(&tab, Instant::now())
+    }
+
+    #[test]
+    fn test_fuzzy_match_prefers_word_starts_and_runs() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(fuzzy_match("", "anything").map(|m| m.score), Some(0));
+        assert_eq!(fuzzy_match("xyz", "Session 1"), None);
+
+        // Case-insensitive, positions are char indices
+        let m = fuzzy_match("dpl", "Deploy").ok_or("no match")?;
+        assert_eq!(m.positions, vec![0, 2, 3]);
+
+        // The digit right after a word break beats one buried in "12"
+        let two = fuzzy_match("s2", "Session 2").ok_or("no match")?;
+        let twelve = fuzzy_match("s2", "Session 12").ok_or("no match")?;
+        assert!(two.score > twelve.score);
+
+        // A run of consecutive chars beats the same chars scattered
+        let run = fuzzy_match("log", "Logs review").ok_or("no match")?;
+        let scattered = fuzzy_match("log", "Linux ops guide").ok_or("no match")?;
+        assert!(run.score > scattered.score, "{} vs {}", run.score, scattered.score);
+
+        // A later word start is taken over an earlier mid-word occurrence
+        let m = fuzzy_match("sb", "subshell bash").ok_or("no match")?;
+        assert_eq!(m.positions, vec![0, 9]);
+
+        // ... unless that loses the match altogether
+        let m = fuzzy_match("ab", "xab a").ok_or("no match")?;
+        assert_eq!(m.positions, vec![1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_typing_filters_and_ranks_entries() {
+        let mut switcher = Switcher::new(vec![
+            session(1, "Session 1"),
+            session(2, "Deploy"),
+            session(12, "Session 12"),
+            SwitchEntry::shell("bash", Some("cargo test")),
+        ]);
+        assert_eq!(switcher.visible_titles(), vec!["Session 1", "Deploy", "Session 12", "Shell (bash)"]);
+        assert_eq!(switcher.selected_target(), Some(SwitchTarget::Session(1)));
+
+        switcher.move_selection(-1);
+        assert_eq!(switcher.selected_target(), Some(SwitchTarget::Shell));
+
+        "s12".chars().for_each(|c| switcher.push_char(c));
+        assert_eq!(switcher.visible_titles(), vec!["Session 12"]);
+        assert_eq!(switcher.selected_target(), Some(SwitchTarget::Session(12)));
+
+        switcher.push_char('x');
+        assert_eq!(switcher.selected_target(), None);
+        switcher.pop_char();
+        switcher.pop_char();
+        switcher.pop_char();
+        assert_eq!(switcher.query(), "s");
+        assert_eq!(switcher.visible_titles(), vec!["Session 1", "Session 12", "Shell (bash)"]);
+    }
+
+    #[test]
+    fn test_session_detail_shows_age() {
+        let now = Instant::now();
+        let tab = SessionTab {
+            id: 3,
+            name: "Logs".to_string(),
+            last_activity: now.checked_sub(Duration::from_secs(150)),
+            unread: true,
+            pending: true,
+            ..SessionTab::default()
+        };
+        let entry = SwitchEntry::session(&tab, now);
+        assert_eq!(entry.detail, "2m ago");
+        assert!(entry.unread && entry.pending);
+        assert_eq!(format_age(Duration::from_secs(7_200)), "2h ago");
+    }
+}
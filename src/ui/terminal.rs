@@ -4,6 +4,7 @@
 //! for proper resize handling (with scrollback preservation via reflow).
 
 use alacritty_terminal::term::{Term, Config, TermMode};
+use alacritty_terminal::term::cell::{Cell, Flags};
 use alacritty_terminal::event::{Event, EventListener};
 use alacritty_terminal::grid::Dimensions;
 use alacritty_terminal::index::{Column, Line as TermLine};
@@ -11,17 +12,34 @@ use alacritty_terminal::vte::ansi::{Color as AnsiColor, NamedColor, Processor};
 use ratatui::{
     prelude::{Buffer, Rect},
     style::{Color, Modifier, Style},
-    text::{Line, Span},
     widgets::Widget,
 };
+use std::borrow::Cow;
+use std::cell::Cell as StdCell;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::mpsc::{SyncSender, TrySendError};
 use tokio::sync::mpsc::{Receiver, UnboundedSender};
 use tracing::error;
 use unicode_width::UnicodeWidthChar;
 
 use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 
-use crate::event::AppEvent;
-use super::visual::{VisualState, SelectionMode, PaneStatus, KeyHandleResult, copy_to_clipboard, is_in_selection_with_mode};
+use crate::event::{AppEvent, EventOrigin, Stamped};
+use crate::context::{OscScanner, ScrollbackPosition};
+use crate::shell::{CastRecorder, ShellMark};
+use crate::utils::metrics::metrics;
+use crate::utils::persist;
+use crate::utils::utf8::Utf8Decoder;
+use super::command_marks::CommandMarks;
+use super::line_numbers::{self, LineNumbering};
+use super::long_lines::{self, LongLine, LongLines};
+use super::macros::KeyMacros;
+use super::repeats::RepeatedLines;
+use super::screen_dump;
+use super::scrollbar;
+use super::visual::{VisualState, SelectionMode, PaneStatus, StatusMemo, KeyHandleResult, copy_to_clipboard, is_in_selection_with_mode, CopyOutcome, COPY_UNAVAILABLE};
 
 /// Simple terminal size implementation that satisfies the alacritty Dimensions trait.
 #[derive(Debug, Copy, Clone)]
@@ -71,11 +89,11 @@ impl Dimensions for &TermSize {
 /// Minimal event listener that ignores all terminal events.
 #[derive(Clone)]
 struct TerminalEventListener {
-    app_event_sink: UnboundedSender<AppEvent>,
+    app_event_sink: UnboundedSender<Stamped<AppEvent>>,
 }
 
 impl TerminalEventListener {
-    fn new(app_event_sink: UnboundedSender<AppEvent>) -> Self {
+    fn new(app_event_sink: UnboundedSender<Stamped<AppEvent>>) -> Self {
         Self {
             app_event_sink,
         }
@@ -84,15 +102,12 @@ impl TerminalEventListener {
 
 impl EventListener for TerminalEventListener {
     fn send_event(&self, event: Event) {
-        match event {
-            Event::PtyWrite(s) => {
-                if let Err(e) = self.app_event_sink.send(AppEvent::PtyWrite(s.into_bytes())) {
-                    error!("Failed to send PtyWrite event: {:?}", e);
-                    // Note: This error is very unlikely to occur as it would mean
-                    // the event receiver has been dropped while the terminal is still active.
-                }
-            }
-            _ => {}
+        if let Event::PtyWrite(s) = event
+            && let Err(e) = self.app_event_sink.send(Stamped::new(EventOrigin::Terminal, AppEvent::PtyWrite(s.into_bytes())))
+        {
+            error!("Failed to send PtyWrite event: {:?}", e);
+            // Note: This error is very unlikely to occur as it would mean
+            // the event receiver has been dropped while the terminal is still active.
         }
     }
 }
@@ -101,21 +116,149 @@ impl EventListener for TerminalEventListener {
 pub struct TuiTerminal {
     term: Term<TerminalEventListener>,
     processor: Processor,
+    /// Start of a character cut off at the end of the last output, held
+    /// back until it is complete: the emulator drops the byte after a
+    /// character it had to join across two reads
+    cut_char: Vec<u8>,
     pty_output: Receiver<Vec<u8>>,
-    event_sink: UnboundedSender<AppEvent>,
+    /// Hands processed output buffers back to the PTY reader
+    spare_buffers: Option<SyncSender<Vec<u8>>>,
+    event_sink: UnboundedSender<Stamped<AppEvent>>,
     scroll_offset: usize,
     error_message: Option<String>,
 
     // Visual mode state
     visual_state: Option<VisualState>,
 
+    // Command boundaries for "select last command output"
+    command_marks: CommandMarks,
+    /// Maximum history size; once reached, new lines evict the oldest ones
+    scrollback_limit: usize,
+    /// Last selection made by `select_command_output`, to detect repeats
+    output_selection: Option<OutputSelection>,
+
+    /// Show the line-number gutter (also numbers copied text)
+    line_numbers: bool,
+    /// Absolute line numbers, kept stable across scrollback eviction
+    numbering: LineNumbering,
+
+    /// Shown in the title after a copy that no backend accepted
+    copy_error: Option<&'static str>,
+
+    /// Recording of the pane to a cast file, if one is running
+    recorder: Option<CastRecorder>,
+    /// Keys recorded into registers, and the one being replayed
+    macros: KeyMacros,
+    /// Shown in the title until the next key (e.g. where a recording went)
+    notice: Option<String>,
+    /// The pane status last worked out, reused while its inputs stay the same
+    status_memo: StatusMemo<StatusInputs>,
+
+    /// Working directory reports (OSC 7) and command marks (OSC 133) from
+    /// the shell
+    osc: OscScanner,
+    /// Command marks seen since the app last took them
+    shell_marks: Vec<ShellMark>,
+    /// Lines too long for the grid, kept out of it
+    long_lines: LongLines,
+    /// Runs of identical lines, collapsed (None = shown as they are)
+    repeats: Option<RepeatedLines>,
+    /// Drawn the cheap way: named colors, coalesced styles, no scrollbar
+    performance: bool,
+    /// Output that reached the grid, as text for the context snippet
+    snippet_decoder: Utf8Decoder,
+
+    /// Scratch row for rendering; keeps its capacity across frames
+    row_cells: StdCell<Vec<(char, Style)>>,
+    /// Rows captured before processing output, reused between reads
+    eviction_window: Vec<(usize, String)>,
+}
+
+/// What the terminal's pane status is worked out from.
+#[derive(Debug, Clone, PartialEq)]
+struct StatusInputs {
+    recording: bool,
+    /// Register a key macro is being recorded into
+    macro_register: Option<char>,
+    /// Selection mode name and repeat count, in visual mode
+    visual: Option<(&'static str, Option<usize>)>,
+    scrolled: Option<usize>,
+    copy_error: Option<&'static str>,
+    notice: Option<String>,
+}
+
+fn build_pane_status(inputs: &StatusInputs) -> PaneStatus {
+    let mut status_parts: Vec<String> = Vec::new();
+
+    if inputs.recording {
+        status_parts.push("● REC".to_string());
+    }
+
+    if let Some(register) = inputs.macro_register {
+        status_parts.push(format!("REC @{}", register));
+    }
+
+    if let Some((name, repeat)) = inputs.visual {
+        status_parts.push(name.to_string());
+        // Show repeat count if being accumulated
+        if let Some(count) = repeat {
+            status_parts.push(format!("{}×", count));
+        }
+    }
+
+    if let Some(offset) = inputs.scrolled {
+        status_parts.push(format!("Scrolled ↑{}", offset));
+    }
+
+    if let Some(error) = inputs.copy_error {
+        status_parts.push(error.to_string());
+    }
+
+    if let Some(notice) = &inputs.notice {
+        status_parts.push(notice.clone());
+    }
+
+    let title_status = if status_parts.is_empty() {
+        None
+    } else {
+        Some(status_parts.join(" "))
+    };
+
+    let hint_text = if inputs.visual.is_some() {
+        Some(" ESC: Exit | Space: Select | y: Copy | hjkl: Move ")
+    } else {
+        None
+    };
+
+    let border_color = if inputs.visual.is_some() {
+        Some(Color::Magenta)
+    } else {
+        None
+    };
+
+    PaneStatus {
+        title_status,
+        hint_text,
+        enter_hint: None,
+        key_hint: None,
+        border_color,
+    }
+}
+
+/// Selection produced by `select_command_output`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OutputSelection {
+    /// Index of the earliest command mark included
+    first_mark: usize,
+    anchor: (usize, usize),
+    cursor: (usize, usize),
 }
 
 impl TuiTerminal {
     /// Creates a new terminal display.
     pub fn new(
         pty_output: Receiver<Vec<u8>>,
-        event_sink: UnboundedSender<AppEvent>,
+        event_sink: UnboundedSender<Stamped<AppEvent>>,
     ) -> Self {
         // Start with reasonable default size (will be resized on first draw)
         let cols = 80;
@@ -123,47 +266,108 @@ impl TuiTerminal {
 
         let event_listener = TerminalEventListener::new(event_sink.clone());
         let config = Config::default();
+        let scrollback_limit = config.scrolling_history;
         let size = TermSize::new(cols, rows);
         let term = Term::new(config, &size, event_listener);
 
         Self {
             term,
             processor: Processor::new(),
+            cut_char: Vec::new(),
             pty_output,
+            spare_buffers: None,
             event_sink,
             scroll_offset: 0,
             error_message: None,
             visual_state: None,
+            command_marks: CommandMarks::new(),
+            scrollback_limit,
+            output_selection: None,
+            line_numbers: false,
+            numbering: LineNumbering::new(),
+            copy_error: None,
+            recorder: None,
+            macros: KeyMacros::new(),
+            notice: None,
+            status_memo: StatusMemo::default(),
+            osc: OscScanner::new(),
+            shell_marks: Vec::new(),
+            long_lines: LongLines::new(),
+            repeats: None,
+            performance: false,
+            snippet_decoder: Utf8Decoder::default(),
+            row_cells: StdCell::new(Vec::new()),
+            eviction_window: Vec::new(),
         }
     }
 
+    /// Send output buffers back to `spare` once processed, so the PTY reader
+    /// doesn't allocate a new one per read.
+    pub fn recycle_output_buffers(&mut self, spare: SyncSender<Vec<u8>>) {
+        self.spare_buffers = Some(spare);
+    }
+
     /// Receives and processes PTY output.
     /// Call this in tokio::select! to handle async PTY data.
     pub async fn recv_pty_output(&mut self) {
         let Some(first) = self.pty_output.recv().await else {
             return;
         };
+        self.process_pty_burst(first);
+    }
 
+    /// Process PTY output that already arrived, without waiting.
+    /// Returns false if there was none.
+    pub fn poll_pty_output(&mut self) -> bool {
+        match self.pty_output.try_recv() {
+            Ok(first) => {
+                self.process_pty_burst(first);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Process `first` and whatever output arrived right behind it.
+    fn process_pty_burst(&mut self, first: Vec<u8>) {
         // Drain bursts so heavy output doesn't force a redraw per chunk.
         // We always process all drained bytes for terminal display, but we coalesce the
         // AppEvent::ShellOutput snippet into a single message to reduce event traffic.
         let mut snippet_acc = String::new();
+        let mut cwd_report = None;
 
-        let process_one = |this: &mut Self, bytes: Vec<u8>, snippet_acc: &mut String| {
+        let mut process_one = |this: &mut Self, bytes: Vec<u8>, snippet_acc: &mut String| {
             // Always process PTY output for terminal display (including newlines, etc.)
-            this.process(&bytes);
+            let shown = this.process(&bytes);
+            if let Some(recorder) = &mut this.recorder {
+                recorder.output(&bytes);
+            }
+            this.osc.scan(&bytes, |payload| match payload.strip_prefix(b"7;") {
+                Some(location) => cwd_report = Some(String::from_utf8_lossy(location).into_owned()),
+                None => this.shell_marks.extend(ShellMark::parse(payload)),
+            });
 
             // TODO: the raw pty output is sometimes just GIBBERISH for AI.
             // TODO: Use rendered output instead.
-            // TODO: do shell integration to see the boundary of commands (OSC)
-            // Collect a small text snippet for context building (skip pure whitespace)
-            let snippet = String::from_utf8_lossy(&bytes);
-            let trimmed = snippet.trim();
-            if !trimmed.is_empty() && snippet_acc.len() < 2048 {
-                if !snippet_acc.is_empty() {
-                    snippet_acc.push('\n');
+            // Collect a small text snippet for context building (skip pure whitespace),
+            // from what reached the grid so collapsed lines stay collapsed
+            if snippet_acc.len() < 2048 {
+                let snippet = this.snippet_decoder.decode_clean(&shown);
+                let trimmed = snippet.trim();
+                if !trimmed.is_empty() {
+                    if !snippet_acc.is_empty() {
+                        snippet_acc.push('\n');
+                    }
+                    snippet_acc.push_str(trimmed);
                 }
-                snippet_acc.push_str(trimmed);
+            }
+            drop(shown);
+
+            // A full pool just drops the buffer
+            if let Some(spare) = &this.spare_buffers
+                && let Err(TrySendError::Disconnected(_)) = spare.try_send(bytes)
+            {
+                this.spare_buffers = None;
             }
         };
 
@@ -178,18 +382,106 @@ impl TuiTerminal {
             }
         }
 
+        // Rough size of the scrollback: every line holds a cell per column
+        let grid = self.term.grid();
+        let lines = grid.history_size() + grid.screen_lines();
+        metrics().set_scrollback(lines, lines * grid.columns() * std::mem::size_of::<Cell>());
+
         if !snippet_acc.trim().is_empty() {
             // Limit to avoid flooding the event channel
-            let truncated: String = snippet_acc.chars().take(400).collect();
-            if let Err(e) = self.event_sink.send(AppEvent::ShellOutput { data: truncated }) {
+            if let Some((end, _)) = snippet_acc.char_indices().nth(400) {
+                snippet_acc.truncate(end);
+            }
+            if let Err(e) = self.event_sink.send(Stamped::new(EventOrigin::Terminal, AppEvent::ShellOutput { data: snippet_acc })) {
                 error!("Failed to send shell output event: {:?}", e);
             }
         }
+        if let Some(payload) = cwd_report
+            && let Err(e) = self.event_sink.send(Stamped::new(EventOrigin::Terminal, AppEvent::ShellCwdChanged { payload }))
+        {
+            error!("Failed to send cwd event: {:?}", e);
+        }
+    }
+
+    /// Collapse runs of identical output lines with `repeats`, or show
+    /// every line with `None`.
+    pub fn set_repeated_lines(&mut self, repeats: Option<RepeatedLines>) {
+        self.repeats = repeats;
+    }
+
+    /// Draw the pane the cheap way (see [`crate::ui::performance`]).
+    pub fn set_performance(&mut self, on: bool) {
+        self.performance = on;
+    }
+
+    /// Process VT100 output data. Very long lines are kept out of the grid
+    /// and runs of identical lines collapsed. Returns what reached the grid.
+    fn process<'a>(&mut self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        let data = self.long_lines.filter(data);
+        let data = match (&mut self.repeats, data) {
+            (None, data) => data,
+            (Some(repeats), Cow::Borrowed(data)) => repeats.filter(data),
+            (Some(repeats), Cow::Owned(data)) => Cow::Owned(repeats.filter(&data).into_owned()),
+        };
+        if !data.is_empty() {
+            self.advance(&data);
+        }
+        data
+    }
+
+    /// Show the line being kept out of the grid as ended, and the copies of
+    /// a line held back, so what comes next (the echo of what the user
+    /// types) is not hidden with them or shown ahead of them.
+    pub fn end_held_output(&mut self) {
+        if let Some(marker) = self.long_lines.end_current() {
+            self.advance(&marker);
+        }
+        if let Some(held) = self.repeats.as_mut().and_then(RepeatedLines::end_held) {
+            self.advance(&held);
+        }
     }
 
-    /// Process VT100 output data.
-    fn process(&mut self, data: &[u8]) {
-        self.processor.advance(&mut self.term, data);
+    /// A line kept out of the grid, by the number in its marker row.
+    pub fn long_line(&self, id: usize) -> Option<&LongLine> {
+        self.long_lines.get(id)
+    }
+
+    /// Number of the lowest long line whose marker row is on screen, or
+    /// else of the most recent long line.
+    pub fn shown_long_line(&self) -> Option<usize> {
+        let mut text = String::new();
+        let on_screen = self.visible_rows().collect::<Vec<_>>().into_iter().rev().find_map(|cells| {
+            set_row_text(cells, &mut text);
+            long_lines::marker_id(&text)
+        });
+        on_screen.or(self.long_lines.latest().map(LongLine::id))
+    }
+
+    /// Feed output to the emulator.
+    fn advance(&mut self, data: &[u8]) {
+        let history_before = self.term.grid().history_size();
+        let newlines = data.iter().filter(|&&b| b == b'\n').count();
+        // Only capture an eviction window when lines may actually be evicted
+        let mut window = std::mem::take(&mut self.eviction_window);
+        if history_before + newlines >= self.scrollback_limit {
+            self.capture_eviction_window(history_before, &mut window);
+        } else {
+            window.clear();
+        }
+        let joined;
+        let whole = if self.cut_char.is_empty() {
+            data
+        } else {
+            self.cut_char.extend_from_slice(data);
+            joined = std::mem::take(&mut self.cut_char);
+            &joined
+        };
+        let cut = cut_char_len(whole);
+        self.processor.advance(&mut self.term, &whole[..whole.len() - cut]);
+        self.cut_char.extend_from_slice(&whole[whole.len() - cut..]);
+        self.update_numbering(history_before, newlines, data.len(), &window);
+        self.eviction_window = window;
+        self.update_command_marks(history_before, data);
 
         // Auto-scroll to bottom when new data arrives ONLY if not scrolled
         if self.scroll_offset == 0 {
@@ -214,6 +506,55 @@ impl TuiTerminal {
         self.scroll_offset = 0;
     }
 
+    /// Jump to a scroll offset (clamped to the available history).
+    pub fn set_scroll_offset(&mut self, offset: usize) {
+        self.scroll_offset = offset.min(self.term.grid().history_size());
+    }
+
+    // ========================================================================
+    // Scrollbar
+    // ========================================================================
+
+    /// The scrollbar is shown while browsing history or in visual mode,
+    /// except in performance mode.
+    pub fn scrollbar_visible(&self) -> bool {
+        !self.performance && (self.is_scrolled() || self.is_visual_mode())
+    }
+
+    fn scrollbar_thumb(&self, track: u16) -> Option<scrollbar::Thumb> {
+        let grid = self.term.grid();
+        let screen_lines = grid.screen_lines();
+        scrollbar::thumb(track, grid.history_size() + screen_lines, screen_lines, self.scroll_offset)
+    }
+
+    /// Draw the scrollbar over the rightmost column of `area` when it is shown.
+    pub fn render_scrollbar(&self, area: Rect, buf: &mut Buffer) {
+        if !self.scrollbar_visible() {
+            return;
+        }
+        if let Some(thumb) = self.scrollbar_thumb(area.height) {
+            scrollbar::render(area, buf, thumb);
+        }
+    }
+
+    /// Row within the scrollbar track if `(col, row)` hits the shown scrollbar.
+    pub fn scrollbar_hit(&self, area: Rect, col: u16, row: u16) -> Option<u16> {
+        if !self.scrollbar_visible() {
+            return None;
+        }
+        self.scrollbar_thumb(area.height)?;
+        scrollbar::hit(area, col, row)
+    }
+
+    /// Scroll so the thumb is centered on `row` of a track `track` rows tall.
+    pub fn scroll_to_scrollbar_row(&mut self, row: u16, track: u16) {
+        let grid = self.term.grid();
+        let screen_lines = grid.screen_lines();
+        if let Some(offset) = scrollbar::offset_at(track, grid.history_size() + screen_lines, screen_lines, row) {
+            self.set_scroll_offset(offset);
+        }
+    }
+
     /// Check if we're scrolled back in history.
     pub fn is_scrolled(&self) -> bool {
         self.scroll_offset > 0
@@ -232,10 +573,245 @@ impl TuiTerminal {
         (row, col)
     }
 
+    // ========================================================================
+    // Line Numbers
+    // ========================================================================
+
+    /// Show or hide the line-number gutter.
+    pub fn toggle_line_numbers(&mut self) {
+        self.line_numbers = !self.line_numbers;
+    }
+
+    pub fn line_numbers_enabled(&self) -> bool {
+        self.line_numbers
+    }
+
+    /// Width the gutter needs right now (0 when hidden).
+    ///
+    /// Sized for the last screen row, so it only grows when the numbers gain
+    /// a digit.
+    pub fn gutter_width(&self) -> u16 {
+        if !self.line_numbers {
+            return 0;
+        }
+        let grid = self.term.grid();
+        let last_row = grid.history_size() + grid.screen_lines().saturating_sub(1);
+        line_numbers::gutter_width(self.numbering.number(last_row))
+    }
+
+    /// Capture the last history rows (content row, text) into `window`, used
+    /// to measure eviction. The strings already in `window` are reused.
+    fn capture_eviction_window(&self, history_size: usize, window: &mut Vec<(usize, String)>) {
+        const WINDOW: usize = 8;
+        let rows = history_size.saturating_sub(WINDOW)..history_size;
+        window.resize_with(rows.len(), Default::default);
+        let mut kept = 0;
+        for row in rows {
+            let Some(cells) = row_cells(&self.term, row) else {
+                continue;
+            };
+            let (slot_row, text) = &mut window[kept];
+            *slot_row = row;
+            set_row_text(cells, text);
+            kept += 1;
+        }
+        window.truncate(kept);
+    }
+
+    /// Count lines evicted (or cleared) from the top of the scrollback.
+    fn update_numbering(&mut self, history_before: usize, newlines: usize, bytes: usize, window: &[(usize, String)]) {
+        let history_after = self.term.grid().history_size();
+        if history_after < history_before {
+            // Scrollback was cleared; the remaining lines keep their numbers
+            self.numbering.evict(history_before - history_after);
+            return;
+        }
+        if window.is_empty() || history_after < self.scrollback_limit {
+            return;
+        }
+
+        // Each newline or wrapped row evicts at most one line
+        let columns = self.term.grid().columns().max(1);
+        let max_shift = newlines + bytes / columns;
+        // A window of identical lines (blank, `yes` output) matches any shift
+        let ambiguous = window.iter().all(|(_, text)| *text == window[0].1);
+        let term = &self.term;
+        let shift = if ambiguous {
+            None
+        } else {
+            line_numbers::detect_shift(window, max_shift, |row| row_cells(term, row).map(RowText))
+        };
+        self.numbering.evict(shift.unwrap_or(newlines));
+    }
+
+    /// Draw the line-number gutter for the visible rows.
+    pub fn render_gutter(&self, area: Rect, buf: &mut Buffer) {
+        if !self.line_numbers || area.width == 0 {
+            return;
+        }
+        // Full-screen programs redraw in place; their rows have no line numbers
+        if self.term.mode().contains(TermMode::ALT_SCREEN) {
+            return;
+        }
+        let grid = self.term.grid();
+        let cursor_row = grid.history_size() + grid.cursor.point.line.0.max(0) as usize;
+        // The error banner takes the first two rows
+        let banner_rows = if self.error_message.is_some() { 2 } else { 0 };
+        let style = Style::default().fg(Color::DarkGray);
+
+        for y in 0..area.height {
+            let Some(screen_row) = usize::from(y).checked_sub(banner_rows) else {
+                continue;
+            };
+            let content_row = self.screen_row_to_content_row(screen_row);
+            if content_row > cursor_row {
+                break;
+            }
+            let label = line_numbers::gutter_label(self.numbering.number(content_row), area.width);
+            buf.set_stringn(area.x, area.y + y, label, usize::from(area.width), style);
+        }
+    }
+
+    /// Current size of the terminal display as (cols, rows).
+    pub fn size(&self) -> (u16, u16) {
+        let grid = self.term.grid();
+        let cols = u16::try_from(grid.columns()).unwrap_or(u16::MAX);
+        let rows = u16::try_from(grid.screen_lines()).unwrap_or(u16::MAX);
+        (cols, rows)
+    }
+
     /// Resize the terminal display.
     pub fn resize(&mut self, cols: u16, rows: u16) {
+        // The grid needs a cell to put the cursor in
+        if cols == 0 || rows == 0 {
+            return;
+        }
         let size = TermSize::new(cols, rows);
-        self.term.resize(&size);
+        self.term.resize(size);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.resize(cols, rows);
+        }
+        // Reflow moves lines around; the old rows no longer mean anything
+        self.command_marks.clear();
+    }
+
+    /// Bring view state back in bounds after the host terminal was resized.
+    ///
+    /// Reflow changes how many rows the content takes, so the scroll offset
+    /// and the visual-mode cursor may point past the end.
+    pub fn on_resize(&mut self) {
+        let grid = self.term.grid();
+        let history = grid.history_size();
+        let last_row = (history + grid.screen_lines()).saturating_sub(1);
+        let last_col = grid.columns().saturating_sub(1);
+        self.scroll_offset = self.scroll_offset.min(history);
+        if let Some(visual) = &mut self.visual_state {
+            visual.clamp(last_row, last_col);
+        }
+        self.output_selection = None;
+    }
+
+    // ========================================================================
+    // Command Marks
+    // ========================================================================
+
+    /// Record that `command` is being submitted on the cursor's line.
+    /// Call this right before the Enter key is forwarded to the shell.
+    ///
+    /// Returns where the command line sits, for numbering its output.
+    pub fn mark_command_start(&mut self, command: &str) -> Option<ScrollbackPosition> {
+        // Full-screen programs (vim, less, ...) don't run shell commands
+        if self.term.mode().contains(TermMode::ALT_SCREEN) {
+            return None;
+        }
+        let grid = self.term.grid();
+        let content_row = grid.history_size() + grid.cursor.point.line.0.max(0) as usize;
+        let columns = grid.columns();
+        let fingerprint = row_text(&self.term, content_row).unwrap_or_default();
+        self.command_marks.push(content_row, fingerprint, command.trim());
+        Some(ScrollbackPosition {
+            line: self.numbering.number(content_row),
+            columns,
+        })
+    }
+
+    /// Whether `command`, marked when it was sent, seems to still be
+    /// running: a full-screen program is up, or the shell has not printed
+    /// its prompt again. None if that can't be told (no mark of it).
+    pub fn command_running(&self, command: &str) -> Option<bool> {
+        if self.term.mode().contains(TermMode::ALT_SCREEN) {
+            return Some(true);
+        }
+        let grid = self.term.grid();
+        let cursor_row = grid.history_size() + grid.cursor.point.line.0.max(0) as usize;
+        self.command_marks.still_running(command, cursor_row, |row| row_text(&self.term, row))
+    }
+
+    /// Keep command marks aligned with the scrollback after processing output.
+    fn update_command_marks(&mut self, history_before: usize, data: &[u8]) {
+        if self.command_marks.is_empty() {
+            return;
+        }
+        let history_after = self.term.grid().history_size();
+        if history_after < history_before {
+            // Scrollback was cleared
+            self.command_marks.clear();
+        } else if history_after >= self.scrollback_limit {
+            // Each newline can evict at most one line from the top
+            let max_shift = data.iter().filter(|&&b| b == b'\n').count();
+            let term = &self.term;
+            self.command_marks.resync(max_shift, |row| row_cells(term, row).map(RowText));
+        }
+    }
+
+    /// Select the output of the most recent completed command (Line mode).
+    ///
+    /// Enters visual mode if needed. Calling it again while that selection is
+    /// unchanged extends it to include the previous command's output.
+    /// Returns false if no completed command output is known.
+    pub fn select_command_output(&mut self) -> bool {
+        let grid = self.term.grid();
+        let prompt_row = grid.history_size() + grid.cursor.point.line.0.max(0) as usize;
+
+        let repeated = self.output_selection.filter(|sel| {
+            self.visual_state
+                .as_ref()
+                .is_some_and(|v| v.anchor == Some(sel.anchor) && v.cursor == sel.cursor)
+        });
+
+        let (first_mark, end) = match repeated {
+            Some(sel) => {
+                let Some(first_mark) = sel.first_mark.checked_sub(1) else {
+                    return true;
+                };
+                (first_mark, sel.cursor)
+            }
+            None => {
+                let Some(index) = self.command_marks.last_with_output(prompt_row) else {
+                    return false;
+                };
+                let Some((_, end_row)) = self.command_marks.output_region(index, prompt_row) else {
+                    return false;
+                };
+                let end_col = self.get_line_effective_width(end_row).saturating_sub(1);
+                (index, (end_row, end_col))
+            }
+        };
+        let Some(mark_row) = self.command_marks.row(first_mark) else {
+            return false;
+        };
+        let anchor = (mark_row + 1, 0);
+
+        let visual = self.visual_state.get_or_insert_with(|| VisualState::new(end.0, end.1));
+        visual.anchor = Some(anchor);
+        visual.set_cursor(end.0, end.1);
+        visual.selection_mode = SelectionMode::Line;
+        visual.clear_repeat_count();
+
+        self.output_selection = Some(OutputSelection { first_mark, anchor, cursor: end });
+        // Show where the output starts
+        self.scroll_row_into_view(anchor.0);
+        true
     }
 
     /// Display an error message in the terminal.
@@ -243,6 +819,114 @@ impl TuiTerminal {
         self.error_message = Some(message.to_string());
     }
 
+    /// Show `message` in the title until the next key.
+    pub fn show_notice(&mut self, message: String) {
+        self.notice = Some(message);
+    }
+
+    pub fn clear_notice(&mut self) {
+        self.notice = None;
+    }
+
+    // ========================================================================
+    // Recording
+    // ========================================================================
+
+    /// Record the pane (output, resizes and, if enabled, input) from now on.
+    pub fn start_recording(&mut self, recorder: CastRecorder) {
+        self.notice = None;
+        self.recorder = Some(recorder);
+    }
+
+    /// Stop recording, handing back the recorder to finish its file.
+    pub fn stop_recording(&mut self) -> Option<CastRecorder> {
+        self.recorder.take()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// `bytes` are about to be written to the shell.
+    pub fn record_input(&mut self, bytes: &[u8]) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.input(bytes);
+        }
+    }
+
+    pub fn macros(&self) -> &KeyMacros {
+        &self.macros
+    }
+
+    pub fn macros_mut(&mut self) -> &mut KeyMacros {
+        &mut self.macros
+    }
+
+    // ========================================================================
+    // Screen Copy and Scrollback Dump
+    // ========================================================================
+
+    /// Text of the rows on screen (as scrolled), one line per row, without
+    /// the blank rows at the bottom. Returns the text and its line count.
+    pub fn visible_text(&self) -> (String, usize) {
+        let rows: Vec<String> = self
+            .visible_rows()
+            .map(|cells| {
+                let mut row = String::new();
+                screen_dump::push_row(&mut row, cells, false);
+                row.truncate(row.trim_end().len());
+                row
+            })
+            .collect();
+        let lines = rows.iter().rposition(|row| !row.is_empty()).map_or(0, |last| last + 1);
+        (rows[..lines].join("\n"), lines)
+    }
+
+    /// Write the whole scrollback and the screen to a new file in `dir`,
+    /// named after the current time. Wrapped rows are joined back into one
+    /// line and, with `colors`, attributes are kept as SGR sequences. The
+    /// file is written a line at a time. Returns its path and line count.
+    pub fn dump_scrollback(&self, dir: &Path, colors: bool) -> io::Result<(PathBuf, usize)> {
+        std::fs::create_dir_all(dir)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let extension = if colors { "ansi" } else { "txt" };
+        let (path, file) = persist::create_unique(dir, &format!("rusty-term-{}", timestamp), extension)?;
+        let mut out = BufWriter::new(file);
+        let lines = self.write_scrollback(&mut out, colors)?;
+        out.flush()?;
+        Ok((path, lines))
+    }
+
+    /// Write the scrollback and the screen to `out` (see [`Self::dump_scrollback`]),
+    /// up to the last row with text. Returns the number of lines written.
+    fn write_scrollback(&self, out: &mut impl Write, colors: bool) -> io::Result<usize> {
+        let grid = self.term.grid();
+        let rows = grid.history_size() + grid.screen_lines();
+        let is_empty = |cells: &[Cell]| cells.iter().all(|cell| matches!(cell.c, ' ' | '\0'));
+        let end = (0..rows)
+            .rev()
+            .find(|&row| row_cells(&self.term, row).is_some_and(|cells| !is_empty(cells)))
+            .map_or(0, |last| last + 1);
+
+        let mut lines = 0;
+        let mut line = String::new();
+        for row in 0..end {
+            let Some(cells) = row_cells(&self.term, row) else {
+                break;
+            };
+            line.clear();
+            screen_dump::push_row(&mut line, cells, colors);
+            // A wrapped row continues on the next one, unless it is the last
+            let wrapped = screen_dump::wraps(cells) && row + 1 < end;
+            if !wrapped {
+                line.push('\n');
+                lines += 1;
+            }
+            out.write_all(line.as_bytes())?;
+        }
+        Ok(lines)
+    }
+
     // ========================================================================
     // Mouse Mode Detection
     // ========================================================================
@@ -271,6 +955,25 @@ impl TuiTerminal {
         self.term.mode().contains(TermMode::SGR_MOUSE)
     }
 
+    /// Check if bracketed paste mode is enabled.
+    ///
+    /// Shells turn this on (ESC[?2004h) while reading a command line, so
+    /// text wrapped in paste markers is inserted rather than run key by key.
+    pub fn is_bracketed_paste(&self) -> bool {
+        self.term.mode().contains(TermMode::BRACKETED_PASTE)
+    }
+
+    /// Whether a full-screen program (an editor, a pager) has switched to
+    /// the alternate screen.
+    pub fn is_alt_screen(&self) -> bool {
+        self.term.mode().contains(TermMode::ALT_SCREEN)
+    }
+
+    /// The OSC 133 command marks the shell printed since the last call.
+    pub fn take_shell_marks(&mut self) -> Vec<ShellMark> {
+        std::mem::take(&mut self.shell_marks)
+    }
+
     // ========================================================================
     // Visual Mode
     // ========================================================================
@@ -282,7 +985,7 @@ impl TuiTerminal {
 
     /// Check if visual selection is active.
     pub fn is_visual_selecting(&self) -> bool {
-        self.visual_state.as_ref().map_or(false, |s| s.is_selecting())
+        self.visual_state.as_ref().is_some_and(|s| s.is_selecting())
     }
 
     /// Get visual selection mode.
@@ -306,7 +1009,7 @@ impl TuiTerminal {
         // cursor.line is in screen coordinates (0 = top of screen, can be negative for history)
         // Convert to content row (history_size + screen_row)
         let screen_row = cursor.line.0.max(0) as usize;
-        let col = cursor.column.0 as usize;
+        let col = cursor.column.0;
 
         // Content row = history_size + screen_row (when scroll_offset = 0)
         // If we're scrolled, the physical cursor is still at the same content position
@@ -321,6 +1024,7 @@ impl TuiTerminal {
     /// Exit visual mode.
     pub fn exit_visual_mode(&mut self) {
         self.visual_state = None;
+        self.copy_error = None;
     }
 
     /// Move visual cursor by delta.
@@ -352,27 +1056,30 @@ impl TuiTerminal {
         let Some(ref visual) = self.visual_state else {
             return;
         };
+        let (cursor_row, _) = visual.cursor;
+        self.scroll_row_into_view(cursor_row);
+    }
 
+    /// Scroll viewport so the given content row is visible.
+    fn scroll_row_into_view(&mut self, row: usize) {
         let grid = self.term.grid();
         let history_size = grid.history_size();
         let screen_lines = grid.screen_lines();
         let total_lines = history_size + screen_lines;
 
-        let (cursor_row, _) = visual.cursor;
-
         // Calculate the visible range in content coordinates
         // visible_top and visible_bottom are inclusive
         let visible_bottom = total_lines.saturating_sub(1).saturating_sub(self.scroll_offset);
         let visible_top = visible_bottom.saturating_sub(screen_lines.saturating_sub(1));
 
-        // Adjust scroll if cursor is out of visible range
-        if cursor_row < visible_top {
-            // Cursor is above visible area, scroll up
-            self.scroll_offset = total_lines.saturating_sub(1).saturating_sub(cursor_row).saturating_sub(screen_lines.saturating_sub(1));
+        // Adjust scroll if row is out of visible range
+        if row < visible_top {
+            // Row is above visible area, scroll up
+            self.scroll_offset = total_lines.saturating_sub(1).saturating_sub(row).saturating_sub(screen_lines.saturating_sub(1));
             self.scroll_offset = self.scroll_offset.min(history_size);
-        } else if cursor_row > visible_bottom {
-            // Cursor is below visible area, scroll down
-            self.scroll_offset = total_lines.saturating_sub(1).saturating_sub(cursor_row);
+        } else if row > visible_bottom {
+            // Row is below visible area, scroll down
+            self.scroll_offset = total_lines.saturating_sub(1).saturating_sub(row);
         }
     }
 
@@ -558,26 +1265,40 @@ impl TuiTerminal {
         }
     }
 
-    /// Copy selected text to clipboard and return true if successful.
+    /// Copy selected text to clipboard.
+    /// Returns None if there is nothing to copy.
     /// Line mode: trims trailing spaces from each line.
     /// Block mode: preserves all characters in the rectangle.
-    pub fn copy_visual_selection(&mut self) -> bool {
-        let Some(ref visual) = self.visual_state else {
-            return false;
-        };
+    pub fn copy_visual_selection(&mut self) -> Option<CopyOutcome> {
+        let mut text = self.selected_text()?;
+        if self.line_numbers {
+            let ((start_row, _), _) = self.visual_state.as_ref()?.selection_range()?;
+            let first = self.numbering.number(start_row);
+            text = line_numbers::numbered_excerpt(&text, first, self.command_marks.command_at(start_row));
+        }
 
-        let mode = visual.get_selection_mode();
-        let Some(((start_row, start_col), (end_row, end_col))) = visual.selection_range() else {
-            return false;
-        };
+        Some(copy_to_clipboard(&text))
+    }
 
-        // Extract text from the terminal grid
-        let text = self.get_text_range(start_row, start_col, end_row, end_col, mode);
-        if text.is_empty() {
-            return false;
-        }
+    /// Text of the visual selection, as copied without line numbers (None
+    /// without a selection or if it is blank).
+    pub fn selected_text(&self) -> Option<String> {
+        let visual = self.visual_state.as_ref()?;
+        let ((start_row, start_col), (end_row, end_col)) = visual.selection_range()?;
+        let text = self.get_text_range(start_row, start_col, end_row, end_col, visual.get_selection_mode());
+        (!text.is_empty()).then_some(text)
+    }
 
-        copy_to_clipboard(&text)
+    /// Clear the selection after a copy, or keep it and report the failure.
+    fn finish_copy(&mut self, outcome: Option<CopyOutcome>) {
+        if outcome == Some(CopyOutcome::Unavailable) {
+            self.copy_error = Some(COPY_UNAVAILABLE);
+            return;
+        }
+        self.copy_error = None;
+        if let Some(ref mut v) = self.visual_state {
+            v.clear_selection();
+        }
     }
 
     /// Get effective width of a line (position after last non-space character).
@@ -607,54 +1328,19 @@ impl TuiTerminal {
     /// Get pane status for rendering title bar and hints.
     /// This allows the component to control its appearance without exposing internal state.
     pub fn get_pane_status(&self) -> PaneStatus {
-        let mut status_parts: Vec<String> = Vec::new();
-
-        if self.is_visual_mode() {
-            if let Some(mode) = self.get_visual_selection_mode() {
-                if let Some(name) = mode.display_name() {
-                    status_parts.push(name.to_string());
-                } else {
-                    status_parts.push("VISUAL".to_string());
-                }
-            } else {
-                status_parts.push("VISUAL".to_string());
-            }
-
-            // Show repeat count if being accumulated
-            if let Some(ref visual) = self.visual_state {
-                if let Some(count) = visual.get_repeat_count() {
-                    status_parts.push(format!("{}×", count));
-                }
-            }
-        }
-
-        if self.is_scrolled() {
-            status_parts.push(format!("Scrolled ↑{}", self.scroll_offset));
-        }
-
-        let title_status = if status_parts.is_empty() {
-            None
-        } else {
-            Some(status_parts.join(" "))
-        };
-
-        let hint_text = if self.is_visual_mode() {
-            Some(" ESC: Exit | Space: Select | y: Copy | hjkl: Move ")
-        } else {
-            None
+        let visual = self.visual_state.as_ref().map(|visual| {
+            let name = self.get_visual_selection_mode().and_then(|mode| mode.display_name()).unwrap_or("VISUAL");
+            (name, visual.get_repeat_count())
+        });
+        let inputs = StatusInputs {
+            recording: self.is_recording(),
+            macro_register: self.macros.recording(),
+            visual,
+            scrolled: self.is_scrolled().then_some(self.scroll_offset),
+            copy_error: self.copy_error,
+            notice: self.notice.clone(),
         };
-
-        let border_color = if self.is_visual_mode() {
-            Some(Color::Magenta)
-        } else {
-            None
-        };
-
-        PaneStatus {
-            title_status,
-            hint_text,
-            border_color,
-        }
+        self.status_memo.get(inputs, build_pane_status)
     }
 
     /// Handle a key event when in visual mode.
@@ -663,6 +1349,8 @@ impl TuiTerminal {
         if !matches!(key.kind, KeyEventKind::Press) {
             return KeyHandleResult::NotConsumed;
         }
+        // A copy error is only reported until the next key
+        self.copy_error = None;
 
         // If not in visual mode, don't consume
         let Some(ref mut visual) = self.visual_state else {
@@ -670,13 +1358,6 @@ impl TuiTerminal {
         };
 
         let shift = key.modifiers.contains(KeyModifiers::SHIFT);
-        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
-
-        // Ctrl+B => Request command mode
-        if ctrl && matches!(key.code, KeyCode::Char('b') | KeyCode::Char('B')) {
-            visual.clear_repeat_count();
-            return KeyHandleResult::RequestCommandMode;
-        }
 
         match key.code {
             // Digit keys for repeat count
@@ -706,12 +1387,16 @@ impl TuiTerminal {
                 visual.cycle_selection_mode();
             }
 
-            // y => copy selected text and clear selection
+            // o => select last command output (repeat to extend to earlier commands)
+            KeyCode::Char('o') | KeyCode::Char('O') => {
+                visual.clear_repeat_count();
+                let _ = self.select_command_output();
+            }
+
+            // y => copy selected text and clear selection (kept if the copy failed)
             KeyCode::Char('y') | KeyCode::Char('Y') => {
-                let _ = self.copy_visual_selection();
-                if let Some(ref mut v) = self.visual_state {
-                    v.clear_selection();
-                }
+                let outcome = self.copy_visual_selection();
+                self.finish_copy(outcome);
             }
 
             // Scroll keys (Shift + arrows) - scroll without moving cursor
@@ -884,205 +1569,190 @@ impl TuiTerminal {
         visible_top + screen_row
     }
 
-    /// Get rendered lines for display.
-    fn get_lines(&self) -> Vec<Line<'_>> {
+    /// Visible grid rows as borrowed cells, top to bottom, honoring the
+    /// scroll offset.
+    fn visible_rows(&self) -> impl Iterator<Item = &[Cell]> + '_ {
         let grid = self.term.grid();
-        let mut lines = Vec::new();
-
-        // Show error message if present
-        if let Some(ref err) = self.error_message {
-            lines.push(Line::from(vec![
-                Span::styled(
-                    format!("[ERROR] {}", err),
-                    Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-                ),
-            ]));
-            lines.push(Line::from(""));
-        }
-
-        let screen_lines = grid.screen_lines();
-
-        // Iterate through visible lines, considering scroll offset
-        for line_idx in 0..screen_lines {
-            // When scrolled up, we want to show lines from history
-            // line_idx 0 at scroll_offset N should show line -N
-            let actual_line_idx = if self.scroll_offset > 0 {
-                line_idx as i32 - self.scroll_offset as i32
-            } else {
-                line_idx as i32
-            };
-
-            let line = TermLine(actual_line_idx);
-            let mut spans = Vec::new();
-            let mut current_text = String::new();
-            let mut current_style = Style::default();
-
-            // Iterate through columns in this line
-            for col in 0..grid.columns() {
-                let cell = &grid[line][Column(col)];
-
-                // Skip wide character spacers
-                if cell
-                    .flags
-                    .contains(alacritty_terminal::term::cell::Flags::WIDE_CHAR_SPACER)
-                {
-                    continue;
-                }
-
-                let c = cell.c;
-
-                // Build style for this cell
-                let mut style = Style::default();
-                style = style.fg(convert_ansi_color(cell.fg));
-                style = style.bg(convert_ansi_color(cell.bg));
-
-                if cell
-                    .flags
-                    .contains(alacritty_terminal::term::cell::Flags::BOLD)
-                {
-                    style = style.add_modifier(Modifier::BOLD);
-                }
-                if cell
-                    .flags
-                    .contains(alacritty_terminal::term::cell::Flags::ITALIC)
-                {
-                    style = style.add_modifier(Modifier::ITALIC);
-                }
-                if cell
-                    .flags
-                    .contains(alacritty_terminal::term::cell::Flags::UNDERLINE)
-                {
-                    style = style.add_modifier(Modifier::UNDERLINED);
-                }
-                if cell
-                    .flags
-                    .contains(alacritty_terminal::term::cell::Flags::INVERSE)
-                {
-                    style = style.add_modifier(Modifier::REVERSED);
-                }
-
-                // Group consecutive cells with the same style
-                if style == current_style {
-                    current_text.push(c);
-                } else {
-                    if !current_text.is_empty() {
-                        let text = std::mem::take(&mut current_text);
-                        spans.push(Span::styled(text, current_style));
-                    }
-                    current_style = style;
-                    current_text.push(c);
-                }
-            }
-
-            // Add the last span
-            if !current_text.is_empty() {
-                spans.push(Span::styled(current_text, current_style));
-            }
-
-            // If no spans, add empty line to maintain layout
-            if spans.is_empty() {
-                spans.push(Span::raw(""));
-            }
-
-            lines.push(Line::from(spans));
-        }
-
-        lines
+        (0..grid.screen_lines()).map(move |line_idx| {
+            // When scrolled up, line_idx 0 at scroll_offset N shows line -N
+            let line = TermLine(line_idx as i32 - self.scroll_offset as i32);
+            &grid[line][..]
+        })
     }
 }
 
 impl Widget for &TuiTerminal {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        // Resize terminal if needed
-        let grid = self.term.grid();
-        if grid.columns() != area.width as usize || grid.screen_lines() != area.height as usize {
-            // Note: We can't mutate self in render(), so resize should be done elsewhere
-            // This is just for display - actual resize happens in app event loop
-        }
-
-        let lines = self.get_lines();
+        // Rows to draw: the error banner (if any), then the visible grid
+        let error_line = self.error_message.as_ref().map(|err| format!("[ERROR] {}", err));
+        let mut grid_rows = self.visible_rows();
 
         // Get visual mode state for highlighting
         let visual_cursor_pos = self.get_visual_cursor_screen_pos();
         let selection_range = self.visual_state.as_ref().and_then(|v| v.selection_range());
         let selection_mode = self.visual_state.as_ref().map(|v| v.get_selection_mode()).unwrap_or(SelectionMode::None);
 
-        // Render lines directly to buffer
-        for (row, line) in lines.iter().enumerate() {
-            if row >= area.height as usize {
-                break;
+        // One row of styled characters at a time, reusing the allocation
+        let mut row_cells = self.row_cells.take();
+        for row in 0..area.height as usize {
+            row_cells.clear();
+            match (row, &error_line) {
+                (0, Some(text)) => {
+                    let style = Style::default().fg(Color::Red).add_modifier(Modifier::BOLD);
+                    row_cells.extend(text.chars().map(|c| (c, style)));
+                }
+                (1, Some(_)) => {}
+                _ => match grid_rows.next() {
+                    Some(cells) => styled_cells(cells, &mut row_cells),
+                    None => break,
+                },
             }
 
             let mut x = area.x;
             let mut col: usize = 0;
 
-            for span in &line.spans {
-                let content = &span.content;
-                for c in content.chars() {
-                    if x >= area.x + area.width {
-                        break;
-                    }
-
-                    // Render tab as a single space since alacritty_terminal has already
-                    // handled cursor movement. The tab character in the grid just marks
-                    // the position where tab was, subsequent characters are already at
-                    // correct positions.
-                    let render_char = if c == '\t' { ' ' } else { c };
-
-                    // Determine style with visual mode modifications
-                    let mut style = span.style;
-
-                    // Check if this cell is the visual cursor
-                    let is_cursor = visual_cursor_pos.map_or(false, |(cr, cc)| cr == row && cc == col);
-
-                    // Check if this cell is in the selection range
-                    let is_selected = selection_range.map_or(false, |(start, end)| {
-                        let content_row = self.screen_row_to_content_row(row);
-                        is_in_selection_with_mode(
-                            content_row,
-                            col,
-                            start,
-                            end,
-                            selection_mode,
-                            |r| self.get_line_effective_width(r),
-                        )
-                    });
-
-                    if is_cursor {
-                        // Visual cursor: blue background, white foreground
-                        style = Style::default().fg(Color::White).bg(Color::Blue);
-                    } else if is_selected {
-                        // Selection: blue background, white foreground
-                        style = Style::default().fg(Color::White).bg(Color::Blue);
-                    }
+            for &(c, cell_style) in row_cells.iter() {
+                if x >= area.x + area.width {
+                    break;
+                }
 
-                    // Get the display width of the character (CJK chars are typically 2)
-                    let char_width = UnicodeWidthChar::width(render_char).unwrap_or(1);
+                // Render tab as a single space since alacritty_terminal has already
+                // handled cursor movement. The tab character in the grid just marks
+                // the position where tab was, subsequent characters are already at
+                // correct positions.
+                let render_char = if c == '\t' { ' ' } else { c };
+
+                // Determine style with visual mode modifications
+                let mut style = cell_style;
+
+                // Check if this cell is the visual cursor
+                let is_cursor = visual_cursor_pos.is_some_and(|(cr, cc)| cr == row && cc == col);
+
+                // Check if this cell is in the selection range
+                let is_selected = selection_range.is_some_and(|(start, end)| {
+                    let content_row = self.screen_row_to_content_row(row);
+                    is_in_selection_with_mode(
+                        content_row,
+                        col,
+                        start,
+                        end,
+                        selection_mode,
+                        |r| self.get_line_effective_width(r),
+                    )
+                });
+
+                if is_cursor {
+                    // Visual cursor: blue background, white foreground
+                    style = Style::default().fg(Color::White).bg(Color::Blue);
+                } else if is_selected {
+                    // Selection: blue background, white foreground
+                    style = Style::default().fg(Color::White).bg(Color::Blue);
+                }
 
-                    if let Some(cell) = buf.cell_mut((x, area.y + row as u16)) {
-                        cell.set_char(render_char).set_style(style);
-                    }
+                // Get the display width of the character (CJK chars are typically 2)
+                let char_width = UnicodeWidthChar::width(render_char).unwrap_or(1);
 
-                    // Advance x and col by the character's display width
-                    // This accounts for wide characters (CJK) that occupy 2 columns
-                    x += char_width as u16;
-                    col += char_width;
+                if let Some(cell) = buf.cell_mut((x, area.y + row as u16)) {
+                    cell.set_char(render_char).set_style(style);
                 }
+
+                // Advance x and col by the character's display width
+                // This accounts for wide characters (CJK) that occupy 2 columns
+                x += char_width as u16;
+                col += char_width;
             }
 
             // If cursor is on this row but beyond the rendered content, render it
-            if let Some((cursor_row, cursor_col)) = visual_cursor_pos {
-                if cursor_row == row && cursor_col >= col {
-                    let cursor_x = area.x + cursor_col as u16;
-                    if cursor_x < area.x + area.width {
-                        let style = Style::default().fg(Color::White).bg(Color::Blue);
-                        if let Some(cell) = buf.cell_mut((cursor_x, area.y + row as u16)) {
-                            cell.set_char(' ').set_style(style);
-                        }
+            if let Some((cursor_row, cursor_col)) = visual_cursor_pos
+                && cursor_row == row && cursor_col >= col
+            {
+                let cursor_x = area.x + cursor_col as u16;
+                if cursor_x < area.x + area.width {
+                    let style = Style::default().fg(Color::White).bg(Color::Blue);
+                    if let Some(cell) = buf.cell_mut((cursor_x, area.y + row as u16)) {
+                        cell.set_char(' ').set_style(style);
                     }
                 }
             }
         }
+        self.row_cells.replace(row_cells);
+        if self.performance {
+            crate::ui::performance::simplify(buf, area);
+        }
+    }
+}
+
+/// Convert a grid row to (character, style) pairs, skipping the spacer
+/// cells behind wide characters. `out` is appended to.
+fn styled_cells(cells: &[Cell], out: &mut Vec<(char, Style)>) {
+    for cell in cells {
+        if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+            continue;
+        }
+        let mut style = Style::default().fg(convert_ansi_color(cell.fg)).bg(convert_ansi_color(cell.bg));
+        if cell.flags.contains(Flags::BOLD) {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        if cell.flags.contains(Flags::ITALIC) {
+            style = style.add_modifier(Modifier::ITALIC);
+        }
+        if cell.flags.contains(Flags::UNDERLINE) {
+            style = style.add_modifier(Modifier::UNDERLINED);
+        }
+        if cell.flags.contains(Flags::INVERSE) {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+        out.push((cell.c, style));
+    }
+}
+
+/// Length of the unfinished character `data` ends with, if any.
+fn cut_char_len(data: &[u8]) -> usize {
+    let from = data.len().saturating_sub(3);
+    let Some(lead) = data[from..].iter().rposition(|&b| b >= 0xc0).map(|at| from + at) else {
+        return 0;
+    };
+    match std::str::from_utf8(&data[lead..]) {
+        Err(error) if error.valid_up_to() == 0 && error.error_len().is_none() => data.len() - lead,
+        _ => 0,
+    }
+}
+
+/// Cells of a content row, if the row exists.
+fn row_cells(term: &Term<TerminalEventListener>, content_row: usize) -> Option<&[Cell]> {
+    let grid = term.grid();
+    let line = content_row as i32 - grid.history_size() as i32;
+    if line >= grid.screen_lines() as i32 {
+        return None;
+    }
+    Some(&grid[TermLine(line)][..])
+}
+
+/// Text of a content row with trailing blanks removed, if the row exists.
+fn row_text(term: &Term<TerminalEventListener>, content_row: usize) -> Option<String> {
+    let mut text = String::new();
+    set_row_text(row_cells(term, content_row)?, &mut text);
+    Some(text)
+}
+
+/// Replace `text` with the text of a row, trailing blanks removed.
+fn set_row_text(cells: &[Cell], text: &mut String) {
+    text.clear();
+    text.extend(cells.iter().map(|cell| cell.c));
+    text.truncate(text.trim_end_matches([' ', '\0']).len());
+}
+
+/// A content row that compares equal to its [`row_text`] without copying it.
+struct RowText<'a>(&'a [Cell]);
+
+impl PartialEq<str> for RowText<'_> {
+    fn eq(&self, text: &str) -> bool {
+        // Row text never ends in a blank: those are trimmed
+        if text.ends_with([' ', '\0']) {
+            return false;
+        }
+        let mut chars = self.0.iter().map(|cell| cell.c);
+        text.chars().all(|c| chars.next() == Some(c)) && chars.all(|c| c == ' ' || c == '\0')
     }
 }
 
@@ -1114,3 +1784,267 @@ fn convert_ansi_color(color: AnsiColor) -> Color {
         AnsiColor::Indexed(idx) => Color::Indexed(idx),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::long_lines::LongLineView;
+
+    fn test_terminal(scrollback: usize) -> TuiTerminal {
+        let (_pty_tx, pty_rx) = tokio::sync::mpsc::channel(1);
+        let (sink, _events) = tokio::sync::mpsc::unbounded_channel();
+        let mut terminal = TuiTerminal::new(pty_rx, sink);
+        let config = Config { scrolling_history: scrollback, ..Config::default() };
+        let listener = TerminalEventListener::new(terminal.event_sink.clone());
+        terminal.term = Term::new(config, &TermSize::new(20, 3), listener);
+        terminal.scrollback_limit = scrollback;
+        terminal
+    }
+
+    fn gutter_rows(terminal: &TuiTerminal, width: u16) -> Vec<String> {
+        let area = Rect::new(0, 0, width, 3);
+        let mut buf = Buffer::empty(area);
+        terminal.render_gutter(area, &mut buf);
+        (0..area.height)
+            .map(|y| (0..width).map(|x| buf[(x, y)].symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_gutter_renders_at_several_widths() {
+        let mut terminal = test_terminal(100);
+        terminal.process(b"one\r\ntwo");
+        assert_eq!(terminal.gutter_width(), 0);
+        terminal.toggle_line_numbers();
+        assert_eq!(terminal.gutter_width(), 4);
+
+        assert_eq!(gutter_rows(&terminal, 4), ["  1 ", "  2 ", "    "]);
+        assert_eq!(gutter_rows(&terminal, 5), ["   1 ", "   2 ", "     "]);
+        assert_eq!(gutter_rows(&terminal, 7), ["     1 ", "     2 ", "       "]);
+    }
+
+    #[test]
+    fn test_numbers_stay_attached_to_lines_after_eviction() {
+        let mut terminal = test_terminal(5);
+        terminal.toggle_line_numbers();
+        for i in 1..=20 {
+            terminal.process(format!("line {}\r\n", i).as_bytes());
+        }
+        // Screen shows lines 19, 20 and the empty cursor row 21
+        assert_eq!(gutter_rows(&terminal, 4), [" 19 ", " 20 ", " 21 "]);
+        let grid = terminal.term.grid();
+        let row = grid.history_size() + 1;
+        assert_eq!(row_text(&terminal.term, row).as_deref(), Some("line 20"));
+
+        let position = terminal.mark_command_start("ls");
+        assert_eq!(position.map(|p| p.line), Some(21));
+        assert_eq!(position.map(|p| p.columns), Some(20));
+    }
+
+    #[test]
+    fn test_failed_copy_keeps_selection() {
+        let mut terminal = test_terminal(100);
+        terminal.process(b"hello");
+        terminal.enter_visual_mode();
+        terminal.start_visual_selection();
+        assert!(terminal.is_visual_selecting());
+
+        terminal.finish_copy(Some(CopyOutcome::Unavailable));
+        assert!(terminal.is_visual_selecting());
+        let status = terminal.get_pane_status().title_status.unwrap_or_default();
+        assert!(status.contains(COPY_UNAVAILABLE));
+
+        terminal.finish_copy(Some(CopyOutcome::Osc52));
+        assert!(!terminal.is_visual_selecting());
+        let status = terminal.get_pane_status().title_status.unwrap_or_default();
+        assert!(!status.contains(COPY_UNAVAILABLE));
+    }
+
+    #[test]
+    fn test_gutter_width_grows_with_digits() {
+        let mut terminal = test_terminal(2000);
+        terminal.toggle_line_numbers();
+        terminal.process("x\r\n".repeat(999).as_bytes());
+        // Last screen row is line 1000
+        assert_eq!(terminal.gutter_width(), 5);
+    }
+
+    #[test]
+    fn test_dump_joins_wrapped_rows_and_copy_shows_the_screen() -> Result<(), Box<dyn std::error::Error>> {
+        let mut terminal = test_terminal(100);
+        terminal.process(b"one\r\n\x1b[32mgreen\x1b[0m\r\n");
+        terminal.process(format!("{}\r\nlast\r\n", "x".repeat(25)).as_bytes());
+
+        let dir = std::env::temp_dir().join(format!("rusty-term-dump-{}", std::process::id()));
+        let (path, lines) = terminal.dump_scrollback(&dir, false)?;
+        assert_eq!(lines, 4);
+        assert_eq!(std::fs::read_to_string(&path)?, format!("one\ngreen\n{}\nlast\n", "x".repeat(25)));
+        // A second dump in the same second gets its own file
+        let (colored, _) = terminal.dump_scrollback(&dir, true)?;
+        assert_ne!(colored, path);
+        assert!(std::fs::read_to_string(&colored)?.starts_with("one\n\x1b[0;32mgreen\x1b[0m\n"));
+        std::fs::remove_dir_all(&dir)?;
+
+        // The screen holds the wrapped tail, "last" and the empty cursor row
+        assert_eq!(terminal.visible_text(), ("xxxxx\nlast".to_string(), 2));
+        terminal.scroll_up(2);
+        assert_eq!(terminal.visible_text(), (format!("green\n{}\nxxxxx", "x".repeat(20)), 3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_visible_text_is_what_the_pane_draws() {
+        let mut terminal = test_terminal(100);
+        terminal.resize(30, 6);
+        terminal.process(b"$ cargo build\r\n\x1b[1;31merror\x1b[0m: expected `;`\r\n");
+        terminal.process("  --> src/main.rs:3:9 ünï\r\n\tindented\r\n$ ".as_bytes());
+
+        let area = Rect::new(0, 0, 30, 6);
+        let mut buf = Buffer::empty(area);
+        (&terminal).render(area, &mut buf);
+        let drawn: Vec<String> = (0..area.height)
+            .map(|y| {
+                let row: String = (0..area.width).filter_map(|x| buf.cell((x, y)).map(|cell| cell.symbol().to_string())).collect();
+                row.trim_end().to_string()
+            })
+            .collect();
+        let (text, lines) = terminal.visible_text();
+        assert_eq!(lines, 5);
+        assert_eq!(text, drawn[..lines].join("\n"));
+        assert_eq!(text, "$ cargo build\nerror: expected `;`\n  --> src/main.rs:3:9 ünï\n        indented\n$");
+
+        assert!(!terminal.is_alt_screen());
+        terminal.process(b"\x1b[?1049h\x1b[2J~\r\n~");
+        assert!(terminal.is_alt_screen());
+    }
+
+    #[test]
+    fn test_huge_line_stays_out_of_the_grid() -> Result<(), Box<dyn std::error::Error>> {
+        let mut terminal = test_terminal(10_000);
+        terminal.resize(80, 24);
+        let line: String = (0..5 * 1024 * 1024).map(|i| char::from(b'a' + (i % 26) as u8)).collect();
+        let output = format!("$ curl api\r\n{}\r\n$ ", line);
+        for chunk in output.as_bytes().chunks(64 * 1024) {
+            terminal.process(chunk);
+        }
+
+        // Only the head reached the grid, so a frame costs what it always does
+        let history = terminal.term.grid().history_size();
+        assert!(history < long_lines::LONG_LINE_COLUMNS / 80 + 24, "{} rows of history", history);
+        let area = Rect::new(0, 0, 80, 24);
+        let mut buf = Buffer::empty(area);
+        let start = std::time::Instant::now();
+        (&terminal).render(area, &mut buf);
+        assert!(start.elapsed() < std::time::Duration::from_millis(200), "frame took {:?}", start.elapsed());
+
+        // The screen ends with the head, the marker row and the prompt
+        let (text, _) = terminal.visible_text();
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows[rows.len() - 2], "⤶ line 1 continues (5.0 MiB) — command mode p opens it");
+        assert_eq!(rows[rows.len() - 1], "$");
+        assert_eq!(terminal.shown_long_line(), Some(1));
+
+        // Copying the viewer's rows gives the part of the line they show
+        let long_line = terminal.long_line(1).ok_or("line not kept")?;
+        assert_eq!(long_line.chars(), line.len());
+        let mut view = LongLineView::new(1);
+        let inner = Rect::new(0, 0, 70, 20);
+        view.scroll(long_line, inner, 1000);
+        let expected: Vec<&str> = (1000..1020).map(|row| &line[row * 70..(row + 1) * 70]).collect();
+        assert_eq!(view.visible_text(long_line, inner), expected.join("\n"));
+        view.scroll_to_end();
+        assert!(view.visible_text(long_line, inner).ends_with(&line[line.len() - 10..]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_repeated_lines_collapse_into_one_row() -> Result<(), Box<dyn std::error::Error>> {
+        let mut terminal = test_terminal(10_000);
+        terminal.resize(80, 24);
+        terminal.set_repeated_lines(Some(RepeatedLines::new(5, 1_000_000)));
+        terminal.process(b"$ tail -f app.log\r\n");
+        let chunk = "\x1b[2mpoll: no data\x1b[0m\r\n".repeat(1000);
+        for _ in 0..1000 {
+            terminal.process(chunk.as_bytes());
+        }
+        // The shell output event gets what reached the grid
+        let shown = String::from_utf8(terminal.process(b"error: gone\r\n$ ").into_owned())?;
+        assert_eq!(shown, "\r\nerror: gone\r\n$ ");
+
+        assert_eq!(terminal.term.grid().history_size(), 0);
+        let (text, _) = terminal.visible_text();
+        let rows: Vec<&str> = text.lines().take(5).collect();
+        assert_eq!(rows, [
+            "$ tail -f app.log",
+            "poll: no data",
+            "… last line repeated 999,999 times …",
+            "error: gone",
+            "$",
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cut_character_keeps_the_byte_after_it() -> Result<(), Box<dyn std::error::Error>> {
+        let mut terminal = test_terminal(100);
+        terminal.resize(40, 5);
+        let accent = "é".as_bytes();
+        terminal.process(&[b'c', b'a', b'f', accent[0]]);
+        terminal.process(&[accent[1], b' ', 0xff, b'o', b'k']);
+        let (text, _) = terminal.visible_text();
+        assert_eq!(text, "café \u{fffd}ok");
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary_output_leaves_the_terminal_usable() -> Result<(), Box<dyn std::error::Error>> {
+        let mut terminal = test_terminal(100);
+        terminal.resize(40, 5);
+        // A character cut across reads, then garbage in the middle of a line
+        let accent = "é".as_bytes();
+        terminal.process(&[b'c', b'a', b'f', accent[0]]);
+        terminal.process(&[accent[1], b' ', 0xff, 0xfe, b'o', b'k', 0xe6, 0x97]);
+        terminal.process(b"!\r\nnext\r\n");
+        let (text, _) = terminal.visible_text();
+        let rows: Vec<&str> = text.lines().collect();
+        let first = rows.first().ok_or("nothing shown")?;
+        assert!(first.starts_with("café \u{fffd}") && first.ends_with('!'), "{:?}", first);
+        assert!(first.contains("ok"), "{:?}", first);
+        assert_eq!(rows.get(1), Some(&"next"));
+
+        // Random bytes, escapes and all, in reads of an odd size
+        let mut state: u32 = 0x9e37_79b9;
+        for _ in 0..256 {
+            let chunk: Vec<u8> = (0..509)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect();
+            terminal.process(&chunk);
+        }
+        // Whatever modes the garbage left set, cancelling and resetting
+        // brings the terminal back, and valid output shows as it should
+        terminal.process(b"\x18\x1bc$ echo fine\r\nfine\r\n");
+        let (text, _) = terminal.visible_text();
+        assert_eq!(text, "$ echo fine\nfine");
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_compares_like_its_text() -> Result<(), Box<dyn std::error::Error>> {
+        let mut terminal = test_terminal(100);
+        terminal.process("漢字 ab\r\n\r\nxy  z".as_bytes());
+        let top = terminal.term.grid().history_size();
+        for row in top..top + 3 {
+            let cells = row_cells(&terminal.term, row).ok_or("row missing")?;
+            let text = row_text(&terminal.term, row).ok_or("row missing")?;
+            for candidate in [text.as_str(), "漢字 a", "xy  z ", "", " ", "xy  z", "xy"] {
+                assert_eq!(RowText(cells) == *candidate, text == candidate, "{:?} vs {:?}", text, candidate);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,489 @@
+//! Vi editing for the assistant input, with `input_editing = "vi"`.
+//!
+//! [`ViInput`] keeps the mode and any half-typed command, and turns a key
+//! typed in normal mode into a [`ViAction`]: where the cursor goes, or
+//! which range of the input to delete. The assistant pane applies it with
+//! its usual cursor and selection primitives, so undo, drafts and rendering
+//! work the same way in both editing modes.
+//!
+//! Offsets are bytes into the input, always on character boundaries. A line
+//! runs between line breaks; wrapped rows on screen are not lines here, and
+//! `j`/`k` move by rows, as the arrow keys do.
+
+use std::ops::Range;
+
+/// Which of the two vi modes the input is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViMode {
+    /// Keys type text
+    #[default]
+    Insert,
+    /// Keys move the cursor and edit
+    Normal,
+}
+
+/// What a key typed in normal mode asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViAction {
+    /// Put the cursor here
+    Move(usize),
+    /// Move the cursor a row up, as the Up arrow does
+    Up,
+    /// Move the cursor a row down, as the Down arrow does
+    Down,
+    /// Delete this range, then go to insert mode if `insert`
+    Delete { range: Range<usize>, insert: bool },
+    /// Delete whole lines; the cursor goes to the first non-blank of the
+    /// line that takes their place
+    DeleteLines(Range<usize>),
+    /// Go to insert mode with the cursor here
+    Insert(usize),
+    /// Take back the last change
+    Undo,
+    /// The key starts a command; wait for the rest
+    Pending,
+    /// The key means nothing here
+    Ignored,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+}
+
+/// The start of a command typed so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pending {
+    /// `d` or `c`, waiting for a motion
+    Operator(Operator),
+    /// `di` or `ci`, waiting for a text object
+    Inner(Operator),
+}
+
+/// Vi state of the input box.
+#[derive(Debug, Default)]
+pub struct ViInput {
+    mode: ViMode,
+    pending: Option<Pending>,
+    /// Input and cursor when insert mode was entered, so what was typed in
+    /// it can be undone as one change
+    insert_start: Option<(String, usize)>,
+}
+
+impl ViInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(&self) -> ViMode {
+        self.mode
+    }
+
+    /// Go to insert mode. `before` is the input to go back to on undo,
+    /// `None` if the command that got here saved it already.
+    pub fn insert(&mut self, before: Option<(String, usize)>) {
+        self.mode = ViMode::Insert;
+        self.pending = None;
+        self.insert_start = before;
+    }
+
+    /// Go to normal mode. Returns the input as it was when insert mode was
+    /// entered, if `text` differs from it.
+    pub fn normal(&mut self, text: &str) -> Option<(String, usize)> {
+        self.mode = ViMode::Normal;
+        self.pending = None;
+        self.insert_start.take().filter(|(before, _)| before != text)
+    }
+
+    /// Forget a half-typed command. Returns false if there was none.
+    pub fn cancel(&mut self) -> bool {
+        self.pending.take().is_some()
+    }
+
+    /// Back to insert mode with nothing to undo, for a new input.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// What `c`, typed in normal mode, asks of `text` with the cursor at
+    /// `cursor`.
+    pub fn key(&mut self, c: char, text: &str, cursor: usize) -> ViAction {
+        let pending = self.pending.take();
+        let Some(pending) = pending else {
+            return match c {
+                'i' => ViAction::Insert(cursor),
+                'a' => ViAction::Insert(after(text, cursor)),
+                'I' => ViAction::Insert(first_non_blank(text, cursor)),
+                'A' => ViAction::Insert(line_end(text, cursor)),
+                'x' => ViAction::Delete { range: cursor..after(text, cursor), insert: false },
+                'd' | 'c' => {
+                    let op = if c == 'd' { Operator::Delete } else { Operator::Change };
+                    self.pending = Some(Pending::Operator(op));
+                    ViAction::Pending
+                }
+                'j' => ViAction::Down,
+                'k' => ViAction::Up,
+                'u' => ViAction::Undo,
+                _ => motion(c, text, cursor).map_or(ViAction::Ignored, ViAction::Move),
+            };
+        };
+
+        let (op, inner) = match pending {
+            Pending::Operator(op) => (op, false),
+            Pending::Inner(op) => (op, true),
+        };
+        let insert = op == Operator::Change;
+        if inner {
+            return match c {
+                'w' => ViAction::Delete { range: inner_word(text, cursor), insert },
+                _ => ViAction::Ignored,
+            };
+        }
+        match (op, c) {
+            (_, 'i') => {
+                self.pending = Some(Pending::Inner(op));
+                ViAction::Pending
+            }
+            (Operator::Delete, 'd') => ViAction::DeleteLines(line_with_break(text, cursor)),
+            (Operator::Change, 'c') => {
+                ViAction::Delete { range: line_start(text, cursor)..line_end(text, cursor), insert }
+            }
+            // As in vi, `cw` on a word changes only the word, like `ce`
+            (Operator::Change, 'w') if text[cursor..].chars().next().is_some_and(|at| !at.is_whitespace()) => {
+                let end = word_end(text, cursor);
+                ViAction::Delete { range: cursor..after(text, end), insert }
+            }
+            _ => match motion(c, text, cursor) {
+                Some(target) => ViAction::Delete { range: motion_range(c, text, cursor, target), insert },
+                None => ViAction::Ignored,
+            },
+        }
+    }
+}
+
+/// Where the cursor may rest in normal mode: on a character, not after the
+/// last one of a line (unless the line is empty).
+pub fn normal_cursor(text: &str, cursor: usize) -> usize {
+    if cursor == line_end(text, cursor) && cursor > line_start(text, cursor) {
+        prev(text, cursor)
+    } else {
+        cursor
+    }
+}
+
+/// Where motion key `c` goes from `cursor`, or `None` if it is not one.
+fn motion(c: char, text: &str, cursor: usize) -> Option<usize> {
+    Some(match c {
+        'h' if cursor > line_start(text, cursor) => prev(text, cursor),
+        'h' => cursor,
+        'l' => after(text, cursor),
+        'w' => word_forward(text, cursor),
+        'b' => word_backward(text, cursor),
+        'e' => word_end(text, cursor),
+        '0' => line_start(text, cursor),
+        '^' => first_non_blank(text, cursor),
+        '$' => normal_cursor(text, line_end(text, cursor)),
+        _ => return None,
+    })
+}
+
+/// The range an operator acts on when combined with motion `c`. Backward
+/// motions stop short of the cursor; `e` and `$` include the character
+/// they land on; `w` stays within the line.
+fn motion_range(c: char, text: &str, cursor: usize, target: usize) -> Range<usize> {
+    match c {
+        _ if target < cursor => target..cursor,
+        'e' | '$' => cursor..after(text, target),
+        'w' if cursor < line_end(text, cursor) => cursor..target.min(line_end(text, cursor)),
+        _ => cursor..target,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> Class {
+    if c.is_whitespace() {
+        Class::Blank
+    } else if c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else {
+        Class::Punctuation
+    }
+}
+
+fn line_start(text: &str, cursor: usize) -> usize {
+    text[..cursor].rfind('\n').map_or(0, |i| i + 1)
+}
+
+fn line_end(text: &str, cursor: usize) -> usize {
+    text[cursor..].find('\n').map_or(text.len(), |i| cursor + i)
+}
+
+/// The first character of the line at `cursor` that is not blank, or the
+/// end of the line if there is none.
+pub fn first_non_blank(text: &str, cursor: usize) -> usize {
+    let (start, end) = (line_start(text, cursor), line_end(text, cursor));
+    text[start..end].find(|c: char| !c.is_whitespace()).map_or(end, |i| start + i)
+}
+
+/// The whole line at `cursor`, with one of the line breaks around it so
+/// deleting it leaves no empty line behind.
+fn line_with_break(text: &str, cursor: usize) -> Range<usize> {
+    let (start, end) = (line_start(text, cursor), line_end(text, cursor));
+    if end < text.len() {
+        start..end + 1
+    } else {
+        start.saturating_sub(1)..end
+    }
+}
+
+fn prev(text: &str, cursor: usize) -> usize {
+    text[..cursor].chars().next_back().map_or(cursor, |c| cursor - c.len_utf8())
+}
+
+/// The offset after the character at `cursor`, which stays put on a line
+/// break or the end of the text.
+fn after(text: &str, cursor: usize) -> usize {
+    match text[cursor..].chars().next() {
+        Some(c) if c != '\n' => cursor + c.len_utf8(),
+        _ => cursor,
+    }
+}
+
+fn is_empty_line(text: &str, i: usize) -> bool {
+    text[i..].starts_with('\n') && (i == 0 || text[..i].ends_with('\n'))
+}
+
+/// Start of the next word (`w`), or of the next empty line.
+fn word_forward(text: &str, cursor: usize) -> usize {
+    let mut chars = text[cursor..].char_indices().map(|(i, c)| (cursor + i, c));
+    let Some((_, first)) = chars.next() else {
+        return cursor;
+    };
+    let mut in_word = class(first) != Class::Blank;
+    for (i, c) in chars {
+        if in_word && class(c) == class(first) {
+            continue;
+        }
+        in_word = false;
+        if class(c) != Class::Blank || is_empty_line(text, i) {
+            return i;
+        }
+    }
+    text.len()
+}
+
+/// Start of the word before the cursor (`b`), or of an empty line.
+fn word_backward(text: &str, cursor: usize) -> usize {
+    let mut chars = text[..cursor].char_indices().rev().peekable();
+    while let Some(&(i, c)) = chars.peek()
+        && class(c) == Class::Blank
+    {
+        if is_empty_line(text, i) {
+            return i;
+        }
+        chars.next();
+    }
+    let Some((mut start, first)) = chars.next() else {
+        return 0;
+    };
+    for (i, c) in chars {
+        if class(c) != class(first) {
+            break;
+        }
+        start = i;
+    }
+    start
+}
+
+/// Last character of the word ending after the cursor (`e`).
+fn word_end(text: &str, cursor: usize) -> usize {
+    let mut chars = text[cursor..].char_indices().map(|(i, c)| (cursor + i, c)).skip(1).peekable();
+    while chars.next_if(|&(_, c)| class(c) == Class::Blank).is_some() {}
+    let Some((mut end, first)) = chars.next() else {
+        return cursor;
+    };
+    for (i, c) in chars {
+        if class(c) != class(first) {
+            break;
+        }
+        end = i;
+    }
+    end
+}
+
+/// The word, run of punctuation or run of blanks at the cursor (`iw`),
+/// never taking in a line break.
+fn inner_word(text: &str, cursor: usize) -> Range<usize> {
+    let Some(at) = text[cursor..].chars().next().filter(|&c| c != '\n') else {
+        return cursor..cursor;
+    };
+    let same = |c: char| c != '\n' && class(c) == class(at);
+    let start = text[..cursor]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| same(c))
+        .last()
+        .map_or(cursor, |(i, _)| i);
+    let end = text[cursor..].char_indices().find(|&(_, c)| !same(c)).map_or(text.len(), |(i, _)| cursor + i);
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `keys` in normal mode on `text`, with the cursor at the `|` in
+    /// it, applying moves and deletions the way the assistant pane does.
+    /// Returns the text with `|` at the cursor, and the mode.
+    fn run(text: &str, keys: &str) -> (String, ViMode) {
+        let mut cursor = text.find('|').unwrap_or(0);
+        let mut text = text.replacen('|', "", 1);
+        let mut vi = ViInput::new();
+        vi.normal(&text);
+        for c in keys.chars() {
+            match vi.key(c, &text, cursor) {
+                ViAction::Move(to) => cursor = normal_cursor(&text, to),
+                ViAction::Delete { range, insert } => {
+                    text.replace_range(range.clone(), "");
+                    cursor = range.start;
+                    match insert {
+                        true => vi.insert(None),
+                        false => cursor = normal_cursor(&text, cursor),
+                    }
+                }
+                ViAction::DeleteLines(range) => {
+                    text.replace_range(range.clone(), "");
+                    cursor = first_non_blank(&text, range.start.min(text.len()));
+                }
+                ViAction::Insert(at) => {
+                    cursor = at;
+                    vi.insert(None);
+                }
+                ViAction::Up | ViAction::Down | ViAction::Undo | ViAction::Pending | ViAction::Ignored => {}
+            }
+        }
+        text.insert(cursor, '|');
+        (text, vi.mode())
+    }
+
+    fn normal(text: &str, keys: &str) -> String {
+        let (text, mode) = run(text, keys);
+        assert_eq!(mode, ViMode::Normal, "{:?}", keys);
+        text
+    }
+
+    fn insert(text: &str, keys: &str) -> String {
+        let (text, mode) = run(text, keys);
+        assert_eq!(mode, ViMode::Insert, "{:?}", keys);
+        text
+    }
+
+    #[test]
+    fn test_motions() {
+        let text = "let 変数 = foo(ba_r);\n\n  next line";
+        for (keys, expected) in [
+            ("l", "l|et 変数 = foo(ba_r);\n\n  next line"),
+            ("w", "let |変数 = foo(ba_r);\n\n  next line"),
+            ("ww", "let 変数 |= foo(ba_r);\n\n  next line"),
+            ("wwww", "let 変数 = foo|(ba_r);\n\n  next line"),
+            ("wwwwwww", "let 変数 = foo(ba_r);\n|\n  next line"),
+            ("wwwwwwww", "let 変数 = foo(ba_r);\n\n  |next line"),
+            ("e", "le|t 変数 = foo(ba_r);\n\n  next line"),
+            ("ee", "let 変|数 = foo(ba_r);\n\n  next line"),
+            ("$", "let 変数 = foo(ba_r)|;\n\n  next line"),
+            ("$l", "let 変数 = foo(ba_r)|;\n\n  next line"),
+            ("$b", "let 変数 = foo(ba_r|);\n\n  next line"),
+            ("$bb", "let 変数 = foo(|ba_r);\n\n  next line"),
+            ("$0", "|let 変数 = foo(ba_r);\n\n  next line"),
+            ("h", "|let 変数 = foo(ba_r);\n\n  next line"),
+        ] {
+            assert_eq!(normal(&format!("|{}", text), keys), expected, "{:?}", keys);
+        }
+
+        // Backwards across the empty line, and within the last line
+        let end = "let 変数 = foo(ba_r);\n\n  next lin|e";
+        assert_eq!(normal(end, "b"), "let 変数 = foo(ba_r);\n\n  next |line");
+        assert_eq!(normal(end, "bbb"), "let 変数 = foo(ba_r);\n|\n  next line");
+        assert_eq!(normal(end, "bbbb"), "let 変数 = foo(ba_r|);\n\n  next line");
+        assert_eq!(normal(end, "0"), "let 変数 = foo(ba_r);\n\n|  next line");
+        assert_eq!(normal(end, "^"), "let 変数 = foo(ba_r);\n\n  |next line");
+        assert_eq!(normal(end, "hhhh"), "let 変数 = foo(ba_r);\n\n  next| line");
+        assert_eq!(normal(end, "w"), end);
+    }
+
+    #[test]
+    fn test_deletions() {
+        for (text, keys, expected) in [
+            ("ab|c", "x", "a|b"),
+            ("|変数x", "x", "|数x"),
+            ("|\nabc", "x", "|\nabc"),
+            ("one |two three", "dw", "one |three"),
+            ("one |two\nthree", "dw", "one| \nthree"),
+            ("one t|wo three", "db", "one |wo three"),
+            ("one t|wo three", "de", "one t| three"),
+            ("one t|wo three", "d$", "one |t"),
+            ("one t|wo three", "d0", "|wo three"),
+            ("one t|wo three", "dl", "one t|o three"),
+            ("one t|wo three", "dh", "one |wo three"),
+            ("one t|wo three", "diw", "one | three"),
+            ("one |  two", "diw", "one|two"),
+            ("foo(|)bar", "diw", "foo|bar"),
+            ("first\nsec|ond\n  third", "dd", "first\n  |third"),
+            ("first\n  sec|ond", "dd", "|first"),
+            ("on|ly", "dd", "|"),
+            ("first\n\n|", "dd", "first\n|"),
+        ] {
+            assert_eq!(normal(text, keys), expected, "{:?} {:?}", text, keys);
+        }
+    }
+
+    #[test]
+    fn test_changes_end_in_insert_mode() {
+        for (text, keys, expected) in [
+            ("one t|wo three", "cw", "one t| three"),
+            ("one |  two", "cw", "one |two"),
+            ("one t|wo three", "ciw", "one | three"),
+            ("one 変|数 three", "ciw", "one | three"),
+            ("first\n  sec|ond\nthird", "cc", "first\n|\nthird"),
+            ("one t|wo three", "c$", "one t|"),
+            ("one t|wo", "i", "one t|wo"),
+            ("one t|wo", "a", "one tw|o"),
+            ("one\nt|wo", "I", "one\n|two"),
+            ("one t|wo\nthree", "A", "one two|\nthree"),
+            ("|", "a", "|"),
+        ] {
+            assert_eq!(insert(text, keys), expected, "{:?} {:?}", text, keys);
+        }
+    }
+
+    #[test]
+    fn test_unknown_keys_drop_the_pending_command() {
+        // `dz` is nothing, so the `w` after it only moves
+        assert_eq!(normal("|one two", "dzw"), "one |two");
+        assert_eq!(normal("|one two", "dizw"), "one |two");
+        let mut vi = ViInput::new();
+        vi.normal("");
+        assert_eq!(vi.key('d', "x", 0), ViAction::Pending);
+        assert!(vi.cancel());
+        assert!(!vi.cancel());
+    }
+
+    #[test]
+    fn test_insert_is_undone_only_if_something_changed() {
+        let mut vi = ViInput::new();
+        vi.insert(Some(("before".to_string(), 2)));
+        assert_eq!(vi.normal("before"), None);
+        vi.insert(Some(("before".to_string(), 2)));
+        assert_eq!(vi.normal("before!"), Some(("before".to_string(), 2)));
+        // A change saved its own undo step already
+        vi.insert(None);
+        assert_eq!(vi.normal("changed"), None);
+    }
+}
@@ -3,9 +3,14 @@
 //! This module provides shared data structures for Visual mode functionality
 //! in both Terminal and Assistant panes.
 
+use std::cell::RefCell;
+use std::io::Write;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
 use arboard::Clipboard;
 use ratatui::style::Color;
-use tracing::error;
+use tracing::{error, warn};
 
 // ============================================================================
 // Pane Status API (for rendering title bar and hints)
@@ -14,12 +19,18 @@ use tracing::error;
 /// Status information that a pane provides for rendering.
 /// This allows components to control their title and hints without
 /// exposing internal state details to App.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct PaneStatus {
     /// Status text to show in title bar (e.g., "VISUAL | Scrolled ↑5")
     pub title_status: Option<String>,
     /// Hint text for the bottom bar
     pub hint_text: Option<&'static str>,
+    /// What Enter does with the input typed, shown first when `hint_text`
+    /// is None (e.g. "Enter: Send")
+    pub enter_hint: Option<&'static str>,
+    /// Shortcut that means something right now, shown in front of the
+    /// command-mode hint when `hint_text` is None (e.g. "Ctrl+C: Clear")
+    pub key_hint: Option<&'static str>,
     /// Override border color (None = use default based on active state)
     pub border_color: Option<Color>,
 }
@@ -45,6 +56,36 @@ impl PaneStatus {
     }
 }
 
+/// The last pane status a pane worked out, with the inputs it came from.
+/// The status is asked for on every frame and every status check, and
+/// mostly nothing it shows has changed since the last time.
+#[derive(Debug)]
+pub struct StatusMemo<K> {
+    last: RefCell<Option<(K, PaneStatus)>>,
+}
+
+impl<K> Default for StatusMemo<K> {
+    fn default() -> Self {
+        Self { last: RefCell::new(None) }
+    }
+}
+
+impl<K: PartialEq> StatusMemo<K> {
+    /// The status for `inputs`: the one from last time if they are the
+    /// same, otherwise `build(&inputs)`, kept for next time.
+    pub fn get(&self, inputs: K, build: impl FnOnce(&K) -> PaneStatus) -> PaneStatus {
+        let mut last = self.last.borrow_mut();
+        if let Some((cached, status)) = last.as_ref()
+            && *cached == inputs
+        {
+            return status.clone();
+        }
+        let status = build(&inputs);
+        *last = Some((inputs, status.clone()));
+        status
+    }
+}
+
 /// Result of handling a key event in a component.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyHandleResult {
@@ -52,8 +93,6 @@ pub enum KeyHandleResult {
     Consumed,
     /// Key was not handled, App should process it
     NotConsumed,
-    /// Request to enter command mode (Ctrl+B in visual mode)
-    RequestCommandMode,
 }
 
 /// Selection mode in visual mode.
@@ -229,6 +268,14 @@ impl VisualState {
         self.cursor = (row, col);
     }
 
+    /// Keep the cursor and anchor within `max_row` x `max_col` (after a resize).
+    pub fn clamp(&mut self, max_row: usize, max_col: usize) {
+        self.cursor = (self.cursor.0.min(max_row), self.cursor.1.min(max_col));
+        if let Some((row, col)) = self.anchor {
+            self.anchor = Some((row.min(max_row), col.min(max_col)));
+        }
+    }
+
     /// Clear selection (reset to None mode).
     pub fn clear_selection(&mut self) {
         self.anchor = None;
@@ -236,26 +283,139 @@ impl VisualState {
     }
 }
 
-/// Copy text to system clipboard.
-/// Returns true if successful.
-pub fn copy_to_clipboard(text: &str) -> bool {
-    match Clipboard::new() {
-        Ok(mut clipboard) => {
-            match clipboard.set_text(text) {
-                Ok(()) => true,
-                Err(e) => {
-                    error!("Failed to copy to clipboard: {}", e);
-                    false
-                }
+// ============================================================================
+// Clipboard
+// ============================================================================
+
+/// Largest OSC 52 payload (base64 bytes) sent to the host terminal.
+/// Many terminals silently drop longer sequences.
+const MAX_OSC52_BYTES: usize = 100_000;
+
+/// How long an unavailable system clipboard is trusted before probing again.
+const CLIPBOARD_RECHECK: Duration = Duration::from_secs(30);
+
+/// Status-line message when a selection could not be copied anywhere.
+pub const COPY_UNAVAILABLE: &str = "clipboard unavailable — selection kept";
+
+/// How a copy request was fulfilled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyOutcome {
+    /// Copied to the system clipboard
+    Clipboard,
+    /// Sent to the host terminal as an OSC 52 sequence (e.g. over SSH)
+    Osc52,
+    /// No backend accepted the text
+    Unavailable,
+}
+
+impl CopyOutcome {
+    /// Whether a backend reported success.
+    pub fn is_copied(self) -> bool {
+        !matches!(self, CopyOutcome::Unavailable)
+    }
+}
+
+/// Clipboard backends available to `copy_to_clipboard`.
+struct ClipboardState {
+    /// Fall back to OSC 52 when the system clipboard is unavailable
+    osc52: bool,
+    /// Last time the system clipboard could not be opened
+    unavailable_since: Option<Instant>,
+}
+
+static CLIPBOARD_STATE: Mutex<ClipboardState> = Mutex::new(ClipboardState {
+    osc52: true,
+    unavailable_since: None,
+});
+
+fn clipboard_state() -> MutexGuard<'static, ClipboardState> {
+    CLIPBOARD_STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Apply the clipboard config and probe the system clipboard once.
+/// Call at startup so the first copy does not pay for a failing probe.
+pub fn configure_clipboard(osc52: bool) {
+    let mut state = clipboard_state();
+    state.osc52 = osc52;
+    state.unavailable_since = match Clipboard::new() {
+        Ok(_) => None,
+        Err(e) => {
+            warn!("System clipboard unavailable: {}", e);
+            Some(Instant::now())
+        }
+    };
+}
+
+/// Copy text to the system clipboard, falling back to OSC 52.
+pub fn copy_to_clipboard(text: &str) -> CopyOutcome {
+    let mut state = clipboard_state();
+
+    // Skip the system clipboard while it is known to be missing, re-checking lazily
+    let skip_system = state
+        .unavailable_since
+        .is_some_and(|since| since.elapsed() < CLIPBOARD_RECHECK);
+    if !skip_system {
+        match Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text)) {
+            Ok(()) => {
+                state.unavailable_since = None;
+                return CopyOutcome::Clipboard;
+            }
+            Err(e) => {
+                error!("Failed to copy to clipboard: {}", e);
+                state.unavailable_since = Some(Instant::now());
             }
         }
+    }
+
+    if state.osc52 && emit_osc52(text) {
+        return CopyOutcome::Osc52;
+    }
+    CopyOutcome::Unavailable
+}
+
+/// Write an OSC 52 sequence to the host terminal.
+fn emit_osc52(text: &str) -> bool {
+    let Some(sequence) = osc52_sequence(text) else {
+        warn!("Selection too large for OSC 52 ({} bytes)", text.len());
+        return false;
+    };
+    let mut stdout = std::io::stdout();
+    match stdout.write_all(sequence.as_bytes()).and_then(|()| stdout.flush()) {
+        Ok(()) => true,
         Err(e) => {
-            error!("Failed to access clipboard: {}", e);
+            error!("Failed to write OSC 52 sequence: {}", e);
             false
         }
     }
 }
 
+/// OSC 52 "set clipboard" sequence for `text`, or None if it exceeds the size cap.
+fn osc52_sequence(text: &str) -> Option<String> {
+    let payload = base64_encode(text.as_bytes());
+    if payload.len() > MAX_OSC52_BYTES {
+        return None;
+    }
+    Some(format!("\x1b]52;c;{}\x07", payload))
+}
+
+/// Standard base64 with padding.
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
 /// Check if a position is within a LINE selection range.
 /// This is for text-flow selection where each line from start to end is included.
 pub fn is_in_line_selection(row: usize, col: usize, start: (usize, usize), end: (usize, usize)) -> bool {
@@ -344,3 +504,48 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode("ls -la ✓".as_bytes()), "bHMgLWxhIOKckw==");
+    }
+
+    #[test]
+    fn test_osc52_sequence_is_size_capped() {
+        assert_eq!(osc52_sequence("hi").as_deref(), Some("\x1b]52;c;aGk=\x07"));
+        // 3 bytes encode to 4, so this lands exactly on the cap
+        let at_cap = "x".repeat(MAX_OSC52_BYTES / 4 * 3);
+        assert!(osc52_sequence(&at_cap).is_some());
+        assert!(osc52_sequence(&format!("{}x", at_cap)).is_none());
+    }
+
+    #[test]
+    fn test_copy_outcome() {
+        assert!(CopyOutcome::Clipboard.is_copied());
+        assert!(CopyOutcome::Osc52.is_copied());
+        assert!(!CopyOutcome::Unavailable.is_copied());
+    }
+
+    #[test]
+    fn test_status_is_built_again_only_when_its_inputs_change() {
+        let memo = StatusMemo::default();
+        let builds = std::cell::Cell::new(0);
+        let build = |offset: &usize| {
+            builds.set(builds.get() + 1);
+            PaneStatus::normal().with_status(format!("Scrolled ↑{}", offset))
+        };
+        assert_eq!(memo.get(3, build).title_status.as_deref(), Some("Scrolled ↑3"));
+        assert_eq!(memo.get(3, build).title_status.as_deref(), Some("Scrolled ↑3"));
+        assert_eq!(builds.get(), 1);
+        assert_eq!(memo.get(4, build).title_status.as_deref(), Some("Scrolled ↑4"));
+        assert_eq!(builds.get(), 2);
+    }
+}
@@ -32,6 +32,10 @@ impl<F: FnOnce()> Context<F> {
     pub fn with(f: F) -> Self { Self { exit_cb: Some(f) }}
 }
 
+impl<F: FnOnce()> Default for Context<F> {
+    fn default() -> Self { Self::new() }
+}
+
 impl<F: FnOnce()> Drop for Context<F> {
     fn drop(&mut self) {
         if let Some(f) = self.exit_cb.take() {
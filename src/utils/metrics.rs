@@ -0,0 +1,590 @@
+//! Runtime metrics for instances left running for days.
+//!
+//! [`metrics()`] is a process-wide registry of relaxed atomics, cheap enough
+//! to update unconditionally: the main loop times every wake-up, the PTY
+//! reader thread counts what it reads, the input reader reports how far user
+//! events backed up, the AI session manager counts requests and times
+//! replies, and the terminal reports the size of its scrollback.
+//!
+//! The app takes a [`MetricsSnapshot`] every [`SAMPLE_INTERVAL`] and keeps the
+//! change since the previous one in a [`MetricsHistory`], which feeds the
+//! metrics overlay (current values and sparklines of the last
+//! [`HISTORY_LEN`] samples) and a summary line in the log every few minutes.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often the app samples the registry.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Samples kept for the sparklines (ten minutes).
+pub const HISTORY_LEN: usize = 120;
+
+/// Buckets of a [`Histogram`]: bucket 0 holds durations under 1µs, bucket
+/// `i` those from 2^(i-1) up to 2^i µs, and the last one everything longer.
+pub const BUCKETS: usize = 32;
+
+static METRICS: Metrics = Metrics::new();
+
+/// The process-wide registry.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// Durations in power-of-two buckets of microseconds. Quantiles come out as
+/// the upper bound of their bucket, so they are within a factor of two.
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS],
+    sum_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS],
+            sum_us: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, duration: Duration) {
+        let us = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        self.buckets[bucket_index(us)].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            buckets: std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+            sum_us: self.sum_us.load(Ordering::Relaxed),
+            max_us: self.max_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bucket of a duration in microseconds.
+fn bucket_index(us: u64) -> usize {
+    ((u64::BITS - us.leading_zeros()) as usize).min(BUCKETS - 1)
+}
+
+/// Upper bound of a bucket, in microseconds.
+fn bucket_bound(index: usize) -> u64 {
+    1 << index
+}
+
+/// Counts of a [`Histogram`] at one moment, or between two moments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    pub buckets: [u64; BUCKETS],
+    pub sum_us: u64,
+    /// Longest duration recorded
+    pub max_us: u64,
+}
+
+impl Default for HistogramSnapshot {
+    fn default() -> Self {
+        Self { buckets: [0; BUCKETS], sum_us: 0, max_us: 0 }
+    }
+}
+
+impl HistogramSnapshot {
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        (count > 0).then(|| Duration::from_micros(self.sum_us / count))
+    }
+
+    /// Duration that a `q` (0 to 1) share of the records stay under, as the
+    /// upper bound of its bucket but never above the longest one.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        let index = self.buckets.iter().position(|&n| {
+            seen += n;
+            seen >= rank
+        })?;
+        Some(Duration::from_micros(bucket_bound(index).min(self.max_us)))
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        (self.count() > 0).then(|| Duration::from_micros(self.max_us))
+    }
+
+    /// Records added since `earlier`. The longest duration cannot be told
+    /// apart, so it is the bound of the highest bucket that got records.
+    pub fn since(&self, earlier: &HistogramSnapshot) -> HistogramSnapshot {
+        let buckets: [u64; BUCKETS] = std::array::from_fn(|i| self.buckets[i].saturating_sub(earlier.buckets[i]));
+        let max_us = match buckets.iter().rposition(|&n| n > 0) {
+            Some(top) => bucket_bound(top).min(self.max_us),
+            None => 0,
+        };
+        HistogramSnapshot { buckets, sum_us: self.sum_us.saturating_sub(earlier.sum_us), max_us }
+    }
+}
+
+/// Why a frame was drawn. Ordered so that the stronger reason wins when
+/// both ask for the same frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DrawTrigger {
+    /// Only decorations changed (a countdown, a title notice, an overlay)
+    Decoration,
+    /// What the panes show changed, or the user did something
+    Content,
+}
+
+/// The registry: counters, gauges and histograms updated from all over.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    loop_busy: Histogram,
+    pty_bytes: AtomicU64,
+    ai_requests: AtomicU64,
+    ai_errors: AtomicU64,
+    ai_latency: Histogram,
+    ai_first_byte: Histogram,
+    reply_tokens: AtomicU64,
+    reasoning_tokens: AtomicU64,
+    summaries: AtomicU64,
+    summary_errors: AtomicU64,
+    summary_tokens: AtomicU64,
+    alternatives: AtomicU64,
+    alternative_tokens: AtomicU64,
+    frames: AtomicU64,
+    decoration_frames: AtomicU64,
+    dropped_frames: AtomicU64,
+    scrollback_lines: AtomicU64,
+    scrollback_bytes: AtomicU64,
+    input_queue_max: AtomicU64,
+    input_stalls: AtomicU64,
+    input_coalesced: AtomicU64,
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Self {
+            loop_busy: Histogram::new(),
+            pty_bytes: AtomicU64::new(0),
+            ai_requests: AtomicU64::new(0),
+            ai_errors: AtomicU64::new(0),
+            ai_latency: Histogram::new(),
+            ai_first_byte: Histogram::new(),
+            reply_tokens: AtomicU64::new(0),
+            reasoning_tokens: AtomicU64::new(0),
+            summaries: AtomicU64::new(0),
+            summary_errors: AtomicU64::new(0),
+            summary_tokens: AtomicU64::new(0),
+            alternatives: AtomicU64::new(0),
+            alternative_tokens: AtomicU64::new(0),
+            frames: AtomicU64::new(0),
+            decoration_frames: AtomicU64::new(0),
+            dropped_frames: AtomicU64::new(0),
+            scrollback_lines: AtomicU64::new(0),
+            scrollback_bytes: AtomicU64::new(0),
+            input_queue_max: AtomicU64::new(0),
+            input_stalls: AtomicU64::new(0),
+            input_coalesced: AtomicU64::new(0),
+        }
+    }
+
+    /// Time the main loop's handling of one wake-up: everything until the
+    /// guard drops, which is how long the next event waits behind it. A
+    /// stall anywhere in the loop shows up here.
+    pub fn loop_timer(&self) -> LoopTimer<'_> {
+        LoopTimer { histogram: &self.loop_busy, start: Instant::now() }
+    }
+
+    /// The PTY reader got `bytes` from the shell.
+    pub fn add_pty_read(&self, bytes: usize) {
+        self.pty_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// A question was sent to the AI.
+    pub fn note_ai_request(&self) {
+        self.ai_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A reply failed.
+    pub fn note_ai_error(&self) {
+        self.ai_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A reply finished `latency` after the question was sent.
+    pub fn record_ai_reply(&self, latency: Duration) {
+        self.ai_latency.record(latency);
+    }
+
+    /// The first part of a reply came `wait` after its request was sent.
+    pub fn record_ai_first_byte(&self, wait: Duration) {
+        self.ai_first_byte.record(wait);
+    }
+
+    /// A reply took `answer` tokens, and `reasoning` more before it.
+    pub fn add_reply_tokens(&self, answer: u64, reasoning: u64) {
+        self.reply_tokens.fetch_add(answer, Ordering::Relaxed);
+        self.reasoning_tokens.fetch_add(reasoning, Ordering::Relaxed);
+    }
+
+    /// Old messages were sent to be summarized. Counted apart from the
+    /// questions: the user did not ask for it.
+    pub fn note_summary_request(&self) {
+        self.summaries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A summary request failed.
+    pub fn note_summary_error(&self) {
+        self.summary_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A summary request used `tokens` (prompt and completion).
+    pub fn add_summary_tokens(&self, tokens: u64) {
+        self.summary_tokens.fetch_add(tokens, Ordering::Relaxed);
+    }
+
+    /// A safer command was asked for in place of a suggestion needing
+    /// confirmation. Counted apart from the questions, like summaries.
+    pub fn note_alternative_request(&self) {
+        self.alternatives.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A safer alternative request used `tokens` (prompt and completion).
+    pub fn add_alternative_tokens(&self, tokens: u64) {
+        self.alternative_tokens.fetch_add(tokens, Ordering::Relaxed);
+    }
+
+    /// A frame was drawn for `trigger`, `late` after it was due; more than
+    /// `budget` late counts as dropped.
+    pub fn record_frame(&self, trigger: DrawTrigger, late: Duration, budget: Duration) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        if trigger == DrawTrigger::Decoration {
+            self.decoration_frames.fetch_add(1, Ordering::Relaxed);
+        }
+        if late > budget {
+            self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The scrollback now holds `lines` lines taking about `bytes`.
+    pub fn set_scrollback(&self, lines: usize, bytes: usize) {
+        self.scrollback_lines.store(lines as u64, Ordering::Relaxed);
+        self.scrollback_bytes.store(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// `len` user events are waiting for the main loop.
+    pub fn note_input_queued(&self, len: usize) {
+        self.input_queue_max.fetch_max(len as u64, Ordering::Relaxed);
+    }
+
+    /// The main loop stopped taking user events while more came in.
+    pub fn note_input_stall(&self) {
+        self.input_stalls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A queued key repeat or pointer move was merged into the one before it.
+    pub fn note_input_coalesced(&self) {
+        self.input_coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            loop_busy: self.loop_busy.snapshot(),
+            pty_bytes: self.pty_bytes.load(Ordering::Relaxed),
+            ai_requests: self.ai_requests.load(Ordering::Relaxed),
+            ai_errors: self.ai_errors.load(Ordering::Relaxed),
+            ai_latency: self.ai_latency.snapshot(),
+            ai_first_byte: self.ai_first_byte.snapshot(),
+            reply_tokens: self.reply_tokens.load(Ordering::Relaxed),
+            reasoning_tokens: self.reasoning_tokens.load(Ordering::Relaxed),
+            summaries: self.summaries.load(Ordering::Relaxed),
+            summary_errors: self.summary_errors.load(Ordering::Relaxed),
+            summary_tokens: self.summary_tokens.load(Ordering::Relaxed),
+            alternatives: self.alternatives.load(Ordering::Relaxed),
+            alternative_tokens: self.alternative_tokens.load(Ordering::Relaxed),
+            frames: self.frames.load(Ordering::Relaxed),
+            decoration_frames: self.decoration_frames.load(Ordering::Relaxed),
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            scrollback_lines: self.scrollback_lines.load(Ordering::Relaxed),
+            scrollback_bytes: self.scrollback_bytes.load(Ordering::Relaxed),
+            input_queue_max: self.input_queue_max.load(Ordering::Relaxed),
+            input_stalls: self.input_stalls.load(Ordering::Relaxed),
+            input_coalesced: self.input_coalesced.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Records the time since it was created into the loop histogram when dropped.
+pub struct LoopTimer<'a> {
+    histogram: &'a Histogram,
+    start: Instant,
+}
+
+impl Drop for LoopTimer<'_> {
+    fn drop(&mut self) {
+        self.histogram.record(self.start.elapsed());
+    }
+}
+
+/// Values of the registry: totals since startup, or over an interval (see
+/// [`MetricsSnapshot::since`]). The scrollback is always the current size,
+/// and the input queue's high-water mark the deepest it got since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// Time the main loop spent on each wake-up
+    pub loop_busy: HistogramSnapshot,
+    pub pty_bytes: u64,
+    pub ai_requests: u64,
+    pub ai_errors: u64,
+    /// Time from a question to the end of its reply
+    pub ai_latency: HistogramSnapshot,
+    /// Time from sending a request to the first part of its reply
+    pub ai_first_byte: HistogramSnapshot,
+    /// Tokens of the replies: the answers, and the reasoning before them
+    pub reply_tokens: u64,
+    pub reasoning_tokens: u64,
+    /// Requests summarizing old messages, and what they used
+    pub summaries: u64,
+    pub summary_errors: u64,
+    pub summary_tokens: u64,
+    /// Requests for a safer command than a suggestion, and what they used
+    pub alternatives: u64,
+    pub alternative_tokens: u64,
+    pub frames: u64,
+    /// Frames drawn only because decorations changed
+    pub decoration_frames: u64,
+    pub dropped_frames: u64,
+    pub scrollback_lines: u64,
+    pub scrollback_bytes: u64,
+    /// Most user events ever waiting for the main loop at once
+    pub input_queue_max: u64,
+    /// Times the main loop stopped taking user events while more came in
+    pub input_stalls: u64,
+    /// Key repeats and pointer moves merged while they waited
+    pub input_coalesced: u64,
+}
+
+impl MetricsSnapshot {
+    /// What happened since `earlier`.
+    pub fn since(&self, earlier: &MetricsSnapshot) -> MetricsSnapshot {
+        MetricsSnapshot {
+            loop_busy: self.loop_busy.since(&earlier.loop_busy),
+            pty_bytes: self.pty_bytes.saturating_sub(earlier.pty_bytes),
+            ai_requests: self.ai_requests.saturating_sub(earlier.ai_requests),
+            ai_errors: self.ai_errors.saturating_sub(earlier.ai_errors),
+            ai_latency: self.ai_latency.since(&earlier.ai_latency),
+            ai_first_byte: self.ai_first_byte.since(&earlier.ai_first_byte),
+            reply_tokens: self.reply_tokens.saturating_sub(earlier.reply_tokens),
+            reasoning_tokens: self.reasoning_tokens.saturating_sub(earlier.reasoning_tokens),
+            summaries: self.summaries.saturating_sub(earlier.summaries),
+            summary_errors: self.summary_errors.saturating_sub(earlier.summary_errors),
+            summary_tokens: self.summary_tokens.saturating_sub(earlier.summary_tokens),
+            alternatives: self.alternatives.saturating_sub(earlier.alternatives),
+            alternative_tokens: self.alternative_tokens.saturating_sub(earlier.alternative_tokens),
+            frames: self.frames.saturating_sub(earlier.frames),
+            decoration_frames: self.decoration_frames.saturating_sub(earlier.decoration_frames),
+            dropped_frames: self.dropped_frames.saturating_sub(earlier.dropped_frames),
+            scrollback_lines: self.scrollback_lines,
+            scrollback_bytes: self.scrollback_bytes,
+            input_queue_max: self.input_queue_max,
+            input_stalls: self.input_stalls.saturating_sub(earlier.input_stalls),
+            input_coalesced: self.input_coalesced.saturating_sub(earlier.input_coalesced),
+        }
+    }
+
+    /// One line for the log, over an interval of `elapsed`.
+    pub fn summary(&self, elapsed: Duration) -> String {
+        let ms = |d: Option<Duration>| d.map_or("-".to_string(), format_duration);
+        format!(
+            "scrollback={} ({} lines) loop_p50={} loop_p99={} loop_max={} pty={}/s ai_requests={} ai_errors={} ai_p50={} ai_max={} ai_ttfb_p50={} reply_tokens={} reasoning_tokens={} summaries={} summary_errors={} summary_tokens={} alternatives={} alternative_tokens={} frames={} decoration_frames={} dropped_frames={} input_queue_max={} input_stalls={} input_coalesced={}",
+            format_bytes(self.scrollback_bytes),
+            self.scrollback_lines,
+            ms(self.loop_busy.quantile(0.5)),
+            ms(self.loop_busy.quantile(0.99)),
+            ms(self.loop_busy.max()),
+            format_bytes(per_second(self.pty_bytes, elapsed)),
+            self.ai_requests,
+            self.ai_errors,
+            ms(self.ai_latency.quantile(0.5)),
+            ms(self.ai_latency.max()),
+            ms(self.ai_first_byte.quantile(0.5)),
+            self.reply_tokens,
+            self.reasoning_tokens,
+            self.summaries,
+            self.summary_errors,
+            self.summary_tokens,
+            self.alternatives,
+            self.alternative_tokens,
+            self.frames,
+            self.decoration_frames,
+            self.dropped_frames,
+            self.input_queue_max,
+            self.input_stalls,
+            self.input_coalesced,
+        )
+    }
+}
+
+/// `count` over `elapsed`, per second.
+pub fn per_second(count: u64, elapsed: Duration) -> u64 {
+    match elapsed.as_millis() {
+        0 => 0,
+        ms => u64::try_from(u128::from(count) * 1000 / ms).unwrap_or(u64::MAX),
+    }
+}
+
+/// Snapshots taken at a steady pace, kept as the change over each interval.
+#[derive(Debug, Clone)]
+pub struct MetricsHistory {
+    /// Totals at the last sample
+    total: MetricsSnapshot,
+    /// Change over each interval, oldest first
+    samples: VecDeque<MetricsSnapshot>,
+    /// Write a summary to the log this often (None = never)
+    log_every: Option<Duration>,
+    /// Totals when the last summary was written, and when
+    logged: (MetricsSnapshot, Instant),
+}
+
+impl MetricsHistory {
+    pub fn new(total: MetricsSnapshot, log_every: Option<Duration>, now: Instant) -> Self {
+        Self { total, samples: VecDeque::with_capacity(HISTORY_LEN), log_every, logged: (total, now) }
+    }
+
+    /// Add a sample from the current totals. Returns a summary for the log
+    /// when one is due.
+    pub fn sample(&mut self, total: MetricsSnapshot, now: Instant) -> Option<String> {
+        if self.samples.len() == HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(total.since(&self.total));
+        self.total = total;
+
+        let elapsed = now.saturating_duration_since(self.logged.1);
+        if self.log_every.is_some_and(|every| elapsed >= every) {
+            let summary = total.since(&self.logged.0).summary(elapsed);
+            self.logged = (total, now);
+            return Some(summary);
+        }
+        None
+    }
+
+    /// Totals at the last sample.
+    pub fn total(&self) -> &MetricsSnapshot {
+        &self.total
+    }
+
+    /// Change over each sample interval, oldest first.
+    pub fn samples(&self) -> impl ExactSizeIterator<Item = &MetricsSnapshot> + DoubleEndedIterator {
+        self.samples.iter()
+    }
+}
+
+/// Bytes in B, KiB, MiB or GiB.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+/// A duration in µs, ms or s, whichever reads best.
+pub fn format_duration(duration: Duration) -> String {
+    let us = duration.as_micros();
+    if us < 1000 {
+        format!("{}µs", us)
+    } else if us < 1_000_000 {
+        format!("{}ms", us / 1000)
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_and_quantiles() -> Result<(), Box<dyn std::error::Error>> {
+        assert_eq!(bucket_index(0), 0);
+        assert_eq!(bucket_index(1), 1);
+        assert_eq!(bucket_index(1023), 10);
+        assert_eq!(bucket_index(1024), 11);
+        assert_eq!(bucket_index(u64::MAX), BUCKETS - 1);
+
+        let histogram = Histogram::new();
+        assert_eq!(histogram.snapshot().quantile(0.5), None);
+        // 98 quick wake-ups and two stalls
+        for _ in 0..98 {
+            histogram.record(Duration::from_micros(300));
+        }
+        histogram.record(Duration::from_millis(40));
+        histogram.record(Duration::from_secs(2));
+        let snapshot = histogram.snapshot();
+
+        assert_eq!(snapshot.count(), 100);
+        assert_eq!(snapshot.mean(), Some(Duration::from_micros((98 * 300 + 40_000 + 2_000_000) / 100)));
+        // 300µs is in the bucket up to 512µs
+        assert_eq!(snapshot.quantile(0.5), Some(Duration::from_micros(512)));
+        assert_eq!(snapshot.quantile(0.98), Some(Duration::from_micros(512)));
+        assert_eq!(snapshot.quantile(0.99), Some(Duration::from_micros(65_536)));
+        // The top bucket's bound is past the longest record, which caps it
+        assert_eq!(snapshot.quantile(1.0), Some(Duration::from_secs(2)));
+        assert_eq!(snapshot.max(), Some(Duration::from_secs(2)));
+
+        // Only what came after the earlier snapshot
+        histogram.record(Duration::from_millis(3));
+        let delta = histogram.snapshot().since(&snapshot);
+        assert_eq!(delta.count(), 1);
+        assert_eq!(delta.sum_us, 3000);
+        assert_eq!(delta.max(), Some(Duration::from_micros(4096)));
+        assert_eq!(delta.quantile(0.5).ok_or("no records")?, Duration::from_micros(4096));
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_keeps_intervals_and_logs_when_due() {
+        let start = Instant::now();
+        let mut total = MetricsSnapshot::default();
+        let mut history = MetricsHistory::new(total, Some(Duration::from_secs(60)), start);
+
+        let mut logged = Vec::new();
+        for i in 1..=HISTORY_LEN as u64 + 10 {
+            total.pty_bytes += 1000 * i;
+            total.scrollback_lines = i;
+            if let Some(summary) = history.sample(total, start + SAMPLE_INTERVAL * i as u32) {
+                logged.push(summary);
+            }
+        }
+
+        assert_eq!(history.samples().len(), HISTORY_LEN);
+        // Intervals, not totals; the gauge is the value at the time
+        let last = history.samples().next_back().copied().unwrap_or_default();
+        assert_eq!(last.pty_bytes, 1000 * (HISTORY_LEN as u64 + 10));
+        assert_eq!(last.scrollback_lines, HISTORY_LEN as u64 + 10);
+        // Every 12 samples of 5s
+        assert_eq!(logged.len(), (HISTORY_LEN + 10) / 12);
+        assert!(logged[0].contains("scrollback=0 B (12 lines)"), "{}", logged[0]);
+        assert!(logged[0].contains("pty=1.3 KiB/s"), "{}", logged[0]);
+    }
+}
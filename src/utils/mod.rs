@@ -1,8 +1,15 @@
 //! Utility modules for common functionality.
 //!
 //! This module contains utility functions and helpers used throughout
-//! the application, including logging configuration and other shared tools.
+//! the application, including logging configuration, crash-safe and
+//! versioned persistence, runtime metrics, job control, PTY output decoding,
+//! and other shared tools.
 
 pub mod logger;
+pub mod metrics;
+pub mod persist;
+pub mod state_dir;
 pub mod context;
 pub mod shell2;
+pub mod suspend;
+pub mod utf8;
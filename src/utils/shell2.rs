@@ -2,31 +2,35 @@
 //!
 //! This is intentionally isolated from the interactive PTY shell:
 //! - read-only commands only (best-effort)
-//! - time-bounded
-//! - output-bounded
+//! - time-bounded: the whole collection runs under one budget, and whatever
+//!   was printed before it ran out is kept
+//! - output-bounded: the injected text is capped with head+tail truncation
 
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use futures::future::BoxFuture;
+use tokio::io::AsyncReadExt;
 use tokio::process::Command;
 use tokio::time::timeout;
 use std::process::Stdio;
 
+/// Stop reading a probe that prints far more than could ever be injected.
+const MAX_RAW_OUTPUT_BYTES: usize = 256 * 1024;
+
 #[derive(Clone, Debug)]
 pub struct Shell2Config {
-    /// Per-command timeout.
-    pub total_timeout: Duration,
-    /// Max bytes captured per command (stdout + stderr combined).
-    pub max_output_bytes: usize,
-    /// Max lines kept per command output.
-    pub max_lines: usize,
+    /// Budget for the whole collection; partial output is kept when it runs out.
+    pub timeout: Duration,
+    /// Max bytes of context injected into the request.
+    pub max_context_bytes: usize,
 }
 
 impl Default for Shell2Config {
     fn default() -> Self {
         Self {
-            total_timeout: Duration::from_millis(600),
-            max_output_bytes: 8 * 1024,
-            max_lines: 60,
+            timeout: Duration::from_millis(1500),
+            max_context_bytes: 8 * 1024,
         }
     }
 }
@@ -36,34 +40,32 @@ fn default_shell() -> String {
     std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string())
 }
 
-fn truncate_lines(s: &str, max_lines: usize) -> String {
-    let mut out = String::new();
-    for (i, line) in s.lines().enumerate() {
-        if i >= max_lines {
-            break;
-        }
-        out.push_str(line);
-        out.push('\n');
-    }
-    out
+/// Largest char boundary <= `index`.
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    (0..=index.min(s.len())).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0)
 }
 
-fn truncate_bytes_utf8(s: &str, max_bytes: usize) -> String {
+/// Smallest char boundary >= `index`.
+fn ceil_char_boundary(s: &str, index: usize) -> usize {
+    (index..=s.len()).find(|&i| s.is_char_boundary(i)).unwrap_or(s.len())
+}
+
+/// Cap `s` at roughly `max_bytes`, keeping its beginning and end.
+///
+/// The header lines (uname, whoami) and the end of the git status are the
+/// most useful parts, so the middle is dropped and replaced by a note.
+pub fn truncate_head_tail(s: &str, max_bytes: usize) -> String {
     if s.len() <= max_bytes {
         return s.to_string();
     }
-
-    // Find a valid UTF-8 boundary <= max_bytes.
-    let mut cut = 0usize;
-    for (idx, _) in s.char_indices() {
-        if idx > max_bytes {
-            break;
-        }
-        cut = idx;
-    }
-    let mut out = s[..cut].to_string();
-    out.push_str("\n...(truncated)...\n");
-    out
+    let head_end = floor_char_boundary(s, max_bytes / 2);
+    let tail_start = ceil_char_boundary(s, s.len() - max_bytes / 2).max(head_end);
+    format!(
+        "{}\n...({} bytes of context truncated)...\n{}",
+        &s[..head_end],
+        tail_start - head_end,
+        &s[tail_start..]
+    )
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -73,47 +75,111 @@ pub struct Shell2Intent {
     pub want_tools: bool,
 }
 
-async fn run_shell_script(cfg: &Shell2Config, cwd: &str, script: &str) -> Option<String> {
+/// Output written so far by a collector, shared so a timeout can keep it.
+#[derive(Clone, Debug, Default)]
+pub struct PartialOutput(Arc<Mutex<Vec<u8>>>);
+
+impl PartialOutput {
+    pub fn push(&self, bytes: &[u8]) {
+        if let Ok(mut buf) = self.0.lock() {
+            buf.extend_from_slice(bytes);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().map_or(0, |buf| buf.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Everything written so far, decoded lossily.
+    pub fn text(&self) -> String {
+        self.0
+            .lock()
+            .map(|buf| String::from_utf8_lossy(&buf).into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// Something that writes system context for `(cwd, intent)` into a
+/// [`PartialOutput`]. Injectable so tests can simulate a hung probe.
+pub type Shell2Collector =
+    Arc<dyn Fn(String, Shell2Intent, PartialOutput) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// The collector that runs the probe script in a separate shell.
+pub fn shell_collector() -> Shell2Collector {
+    Arc::new(|cwd, intent, out| {
+        Box::pin(async move { stream_shell2_system_context(&cwd, intent, &out).await })
+    })
+}
+
+/// Context collected within the budget.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Shell2Context {
+    pub text: String,
+    /// The budget ran out; `text` is whatever was printed before that
+    pub timed_out: bool,
+}
+
+/// Run `collector` under `cfg.timeout`, keeping partial output, and cap the result.
+pub async fn collect_with_budget(
+    collector: &Shell2Collector,
+    cfg: &Shell2Config,
+    cwd: &str,
+    intent: Shell2Intent,
+) -> Shell2Context {
+    let out = PartialOutput::default();
+    let timed_out = timeout(cfg.timeout, collector(cwd.to_string(), intent, out.clone()))
+        .await
+        .is_err();
+    let text = out.text();
+    Shell2Context {
+        text: truncate_head_tail(text.trim(), cfg.max_context_bytes),
+        timed_out,
+    }
+}
+
+/// Run `script` and copy its output (stderr merged into stdout) into `out`
+/// as it arrives. The process is killed when the future is dropped.
+async fn stream_shell_script(cwd: &str, script: &str, out: &PartialOutput) {
     let shell = default_shell();
     let mut c = Command::new(shell);
     // Use non-login shell to avoid slow profile scripts and reduce variability.
-    c.arg("-c").arg(script);
+    // Stderr is merged (it often contains useful signals like "not a git repo").
+    c.arg("-c").arg(format!("exec 2>&1; {}", script));
     c.current_dir(cwd);
     c.kill_on_drop(true);
     c.stdin(Stdio::null());
+    c.stdout(Stdio::piped());
+    c.stderr(Stdio::null());
 
-    let res = timeout(cfg.total_timeout, c.output()).await.ok()?;
-    let res = res.ok()?;
-
-    // Merge stdout+stderr (stderr often contains useful signals like "not a git repo")
-    let mut merged = Vec::new();
-    merged.extend_from_slice(&res.stdout);
-    if !res.stderr.is_empty() {
-        if !merged.is_empty() && *merged.last().unwrap_or(&b'\n') != b'\n' {
-            merged.push(b'\n');
+    let Ok(mut child) = c.spawn() else {
+        return;
+    };
+    let Some(mut stdout) = child.stdout.take() else {
+        return;
+    };
+    let mut buf = [0u8; 4096];
+    while let Ok(n) = stdout.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        out.push(&buf[..n]);
+        if out.len() >= MAX_RAW_OUTPUT_BYTES {
+            return;
         }
-        merged.extend_from_slice(&res.stderr);
-    }
-
-    if merged.is_empty() {
-        return None;
     }
-
-    let s = String::from_utf8_lossy(&merged);
-    let s = truncate_bytes_utf8(&s, cfg.max_output_bytes);
-    let s = truncate_lines(&s, cfg.max_lines);
-    let trimmed = s.trim();
-    if trimmed.is_empty() {
-        None
-    } else {
-        Some(trimmed.to_string())
+    if let Err(e) = child.wait().await {
+        tracing::debug!("shell2 probe did not exit cleanly: {}", e);
     }
 }
 
 /// Collect best-effort system context from a separate shell process.
 ///
 /// This is designed to be fast and safe:
-/// - short timeouts
+/// - one time budget for the whole collection
 /// - bounded output
 /// - read-only commands (best-effort)
 pub async fn collect_shell2_system_context(cwd: &str) -> String {
@@ -122,12 +188,13 @@ pub async fn collect_shell2_system_context(cwd: &str) -> String {
 
 /// Same as `collect_shell2_system_context`, but allows choosing which checks to run.
 pub async fn collect_shell2_system_context_with_intent(cwd: &str, intent: Shell2Intent) -> String {
-    let mut cfg = Shell2Config::default();
-    // Tool version probes can be slightly slower than uname/whoami; allow a bit more time when requested.
-    if intent.want_tools {
-        cfg.total_timeout = Duration::from_millis(1000);
-    }
+    collect_with_budget(&shell_collector(), &Shell2Config::default(), cwd, intent)
+        .await
+        .text
+}
 
+/// Write system context for `intent` into `out` as the probe script prints it.
+async fn stream_shell2_system_context(cwd: &str, intent: Shell2Intent, out: &PartialOutput) {
     // Build a single script to minimize subprocess overhead.
     // Each block is best-effort and bounded (via head).
     let mut script = String::new();
@@ -163,5 +230,64 @@ pub async fn collect_shell2_system_context_with_intent(cwd: &str, intent: Shell2
         script.push_str("printf '\\n'; ");
     }
 
-    run_shell_script(&cfg, cwd, &script).await.unwrap_or_default()
+    stream_shell_script(cwd, &script, out).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collector(f: fn(PartialOutput) -> BoxFuture<'static, ()>) -> Shell2Collector {
+        Arc::new(move |_cwd, _intent, out| f(out))
+    }
+
+    #[test]
+    fn test_truncate_head_tail() {
+        assert_eq!(truncate_head_tail("short", 10), "short");
+
+        let text = format!("{}{}", "a".repeat(100), "z".repeat(100));
+        let truncated = truncate_head_tail(&text, 20);
+        assert!(truncated.starts_with(&"a".repeat(10)));
+        assert!(truncated.ends_with(&"z".repeat(10)));
+        assert!(truncated.contains("(180 bytes of context truncated)"));
+    }
+
+    #[test]
+    fn test_truncate_head_tail_keeps_char_boundaries() {
+        let text = "é".repeat(50);
+        let truncated = truncate_head_tail(&text, 21);
+        assert!(truncated.starts_with("ééééé\n"));
+        assert!(truncated.ends_with("\nééééé"));
+    }
+
+    #[tokio::test]
+    async fn test_hung_collector_keeps_partial_output() {
+        let hung = collector(|out| {
+            Box::pin(async move {
+                out.push(b"uname:\nLinux\n");
+                std::future::pending::<()>().await;
+            })
+        });
+        let cfg = Shell2Config { timeout: Duration::from_millis(50), ..Shell2Config::default() };
+
+        let started = std::time::Instant::now();
+        let context = collect_with_budget(&hung, &cfg, "/", Shell2Intent::default()).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(context.timed_out);
+        assert_eq!(context.text, "uname:\nLinux");
+    }
+
+    #[tokio::test]
+    async fn test_collected_context_is_capped() {
+        let chatty = collector(|out| {
+            Box::pin(async move {
+                out.push("x".repeat(64 * 1024).as_bytes());
+            })
+        });
+        let cfg = Shell2Config { max_context_bytes: 1024, ..Shell2Config::default() };
+        let context = collect_with_budget(&chatty, &cfg, "/", Shell2Intent::default()).await;
+        assert!(!context.timed_out);
+        assert!(context.text.len() < 1100);
+        assert!(context.text.contains("bytes of context truncated"));
+    }
 }
@@ -0,0 +1,336 @@
+//! Schema version handshake of the data directory.
+//!
+//! Every file RustyTerm keeps in [`persist::data_dir`] is written in the
+//! format of one schema version. `meta.json` next to them records that
+//! version and the RustyTerm that wrote it, and [`handshake`] checks it on
+//! startup:
+//!
+//! - Same schema: carry on. A directory without `meta.json` was written
+//!   before there was one, in schema 1.
+//! - Older schema: the [`MIGRATIONS`] bring each file up to date, after
+//!   copying the original to `backups/schema-<n>/`.
+//! - Newer schema: a newer RustyTerm wrote the files (it may still be
+//!   running, or share the directory over NFS). This one can't tell what
+//!   its saves would break, so the directory is [frozen](persist::freeze):
+//!   everything works, nothing is saved.
+//!
+//! A `meta.json` that can't be read, or a migration that fails, freezes
+//! the directory too.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::persist::{self, FileLock, LOCK_TIMEOUT, PersistError};
+
+/// Schema of the files this build writes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// File name in the data directory.
+pub const META_FILE: &str = "meta.json";
+
+/// Version of this build, recorded as the writer.
+const WRITER: &str = env!("CARGO_PKG_VERSION");
+
+/// Contents of `meta.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateMeta {
+    pub schema: u32,
+    /// Version of the RustyTerm that last wrote the directory
+    #[serde(default)]
+    pub writer: String,
+}
+
+impl StateMeta {
+    fn written_by_us(schema: u32) -> Self {
+        Self { schema, writer: WRITER.to_string() }
+    }
+}
+
+/// Brings one file from schema `from` to `from + 1`.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub from: u32,
+    /// File name in the data directory
+    pub file: &'static str,
+    pub migrate: fn(&[u8]) -> Result<Vec<u8>, String>,
+}
+
+/// Every migration, oldest schema first.
+///
+/// Changing the format of a file means bumping [`SCHEMA_VERSION`] and
+/// adding its migration here, with the file as the old schema wrote it in
+/// `tests/fixtures/state/<from>/` and as the migration must leave it in
+/// `tests/fixtures/state/<from + 1>/`.
+pub const MIGRATIONS: &[Migration] = &[];
+
+/// What the handshake found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Handshake {
+    /// Written in this schema, or nothing written yet
+    Current,
+    /// Brought up from schema `from`; the originals are in `backups`
+    Migrated { from: u32, backups: PathBuf },
+    /// Written in a newer schema; the directory is frozen
+    Newer(StateMeta),
+}
+
+/// Check the schema of `dir` and act on it: migrate it if it is older,
+/// freeze it if it is newer, or if it can't be checked or migrated.
+pub fn handshake(dir: &Path) -> Result<Handshake, PersistError> {
+    let result = handshake_with(dir, SCHEMA_VERSION, MIGRATIONS);
+    if !matches!(result, Ok(Handshake::Current | Handshake::Migrated { .. })) {
+        persist::freeze(dir);
+    }
+    result
+}
+
+/// Why nothing will be saved this run, and what to do about it: the text of
+/// the banner shown on startup (None if the handshake went fine).
+pub fn read_only_reason(dir: &Path, handshake: &Result<Handshake, PersistError>) -> Option<String> {
+    let why = match handshake {
+        Ok(Handshake::Current | Handshake::Migrated { .. }) => return None,
+        Ok(Handshake::Newer(meta)) => format!(
+            "{} was written by RustyTerm {} (schema {}); this is RustyTerm {} (schema {}).",
+            dir.display(),
+            if meta.writer.is_empty() { "?" } else { meta.writer.as_str() },
+            meta.schema,
+            WRITER,
+            SCHEMA_VERSION
+        ),
+        Err(e) => format!("{} could not be checked: {}.", dir.display(), e),
+    };
+    Some(format!(
+        "{} Sessions, drafts and other state are not saved this run. To resolve it, \
+         upgrade this RustyTerm, or point XDG_DATA_HOME at a separate directory for it.",
+        why
+    ))
+}
+
+fn handshake_with(dir: &Path, current: u32, migrations: &[Migration]) -> Result<Handshake, PersistError> {
+    let path = dir.join(META_FILE);
+    // Two instances starting at once must not both migrate
+    let _lock = FileLock::acquire(&path, LOCK_TIMEOUT)?;
+    let meta = match fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice::<StateMeta>(&bytes)
+            .map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => StateMeta { schema: 1, writer: String::new() },
+        Err(e) => return Err(e.into()),
+    };
+    if meta.schema > current {
+        return Ok(Handshake::Newer(meta));
+    }
+
+    let from = meta.schema;
+    let mut backups = None;
+    for schema in from..current {
+        backups = Some(migrate_step(dir, from, schema, migrations)?);
+        // Recorded after each step, so a failed run resumes where it stopped
+        write_meta(&path, schema + 1)?;
+        info!("Migrated {} from schema {} to {}", dir.display(), schema, schema + 1);
+    }
+    if meta != StateMeta::written_by_us(current) {
+        write_meta(&path, current)?;
+    }
+    Ok(match backups {
+        Some(backups) => Handshake::Migrated { from, backups },
+        None => Handshake::Current,
+    })
+}
+
+/// Run the migrations from `schema` on the files that exist, backing up
+/// each one to the backups of a migration started at `from` first (unless
+/// an earlier step already did). Returns the backup directory.
+fn migrate_step(dir: &Path, from: u32, schema: u32, migrations: &[Migration]) -> Result<PathBuf, PersistError> {
+    let backups = dir.join("backups").join(format!("schema-{}", from));
+    for migration in migrations.iter().filter(|m| m.from == schema) {
+        let path = dir.join(migration.file);
+        let old = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let backup = backups.join(migration.file);
+        if !backup.exists() {
+            persist::write_atomic(&backup, &old)?;
+        }
+        let new = (migration.migrate)(&old).map_err(|e| {
+            invalid_data(format!(
+                "migrating {} from schema {} failed ({}); the original is in {}",
+                migration.file,
+                schema,
+                e,
+                backups.display()
+            ))
+        })?;
+        persist::write_atomic(&path, &new)?;
+    }
+    Ok(backups)
+}
+
+fn write_meta(path: &Path, schema: u32) -> Result<(), PersistError> {
+    let bytes = serde_json::to_vec_pretty(&StateMeta::written_by_us(schema))
+        .map_err(|e| invalid_data(e.to_string()))?;
+    persist::write_atomic(path, &bytes)?;
+    Ok(())
+}
+
+fn invalid_data(message: String) -> PersistError {
+    io::Error::new(io::ErrorKind::InvalidData, message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::persist::{AppendLog, StateFile};
+
+    /// Fresh empty directory under the system temp dir.
+    fn scratch_dir(name: &str) -> io::Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("rusty-term-state-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    fn read_meta(dir: &Path) -> Result<StateMeta, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_slice(&fs::read(dir.join(META_FILE))?)?)
+    }
+
+    /// Path of a fixture file written by `schema`.
+    fn fixture(schema: u32, file: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/state")
+            .join(schema.to_string())
+            .join(file)
+    }
+
+    #[test]
+    fn test_every_migration_turns_its_fixture_into_the_next() -> Result<(), Box<dyn std::error::Error>> {
+        for migration in MIGRATIONS {
+            let old = fs::read(fixture(migration.from, migration.file))?;
+            let expected = fs::read(fixture(migration.from + 1, migration.file))?;
+            let migrated = (migration.migrate)(&old)?;
+            assert_eq!(
+                String::from_utf8_lossy(&migrated),
+                String::from_utf8_lossy(&expected),
+                "{} from schema {}",
+                migration.file,
+                migration.from
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_schema_proceeds_and_records_the_writer() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = scratch_dir("same")?;
+        // Written before there was a meta file
+        fs::write(dir.join("layout.json"), br#"{"split_ratio": 60}"#)?;
+        assert_eq!(handshake_with(&dir, 1, &[])?, Handshake::Current);
+        assert_eq!(read_meta(&dir)?, StateMeta { schema: 1, writer: WRITER.to_string() });
+        assert_eq!(fs::read(dir.join("layout.json"))?, br#"{"split_ratio": 60}"#);
+
+        fs::write(dir.join(META_FILE), br#"{"schema": 1, "writer": "0.0.1"}"#)?;
+        assert_eq!(handshake_with(&dir, 1, &[])?, Handshake::Current);
+        assert_eq!(read_meta(&dir)?.writer, WRITER);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_newer_schema_freezes_the_directory() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = scratch_dir("newer")?;
+        let meta = br#"{"schema": 99, "writer": "9.0.0"}"#;
+        fs::write(dir.join(META_FILE), meta)?;
+        fs::write(dir.join("drafts.json"), b"{}")?;
+
+        let result = handshake(&dir);
+        assert_eq!(result.as_ref().ok(), Some(&Handshake::Newer(StateMeta { schema: 99, writer: "9.0.0".into() })));
+        let reason = read_only_reason(&dir, &result).ok_or("no reason")?;
+        assert!(reason.contains("RustyTerm 9.0.0 (schema 99)"), "{}", reason);
+        assert!(reason.contains("not saved this run"), "{}", reason);
+
+        // Everything still loads, nothing is written
+        let drafts = StateFile::open(dir.join("drafts.json"), "draft")?;
+        assert!(drafts.is_read_only());
+        assert_eq!(drafts.load()?.as_deref(), Some(&b"{}"[..]));
+        assert!(matches!(drafts.save(b"[]"), Err(PersistError::Frozen { .. })));
+        assert!(persist::write_atomic(&dir.join("response_cache.json"), b"{}").is_err());
+        let audit = AppendLog::new(dir.join("audit.jsonl"));
+        assert!(matches!(audit.append(b"record"), Err(PersistError::Frozen { .. })));
+        assert_eq!(fs::read(dir.join(META_FILE))?, meta);
+        let mut names: Vec<_> = fs::read_dir(&dir)?.map(|e| e.map(|e| e.file_name())).collect::<Result<_, _>>()?;
+        names.sort();
+        assert_eq!(names, ["drafts.json", "meta.json", "meta.json.lock"]);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unreadable_meta_is_an_error() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = scratch_dir("unreadable")?;
+        fs::write(dir.join(META_FILE), b"schema = 2")?;
+        let result = handshake_with(&dir, 1, &[]);
+        assert!(result.is_err());
+        let reason = read_only_reason(&dir, &result).ok_or("no reason")?;
+        assert!(reason.contains("could not be checked"), "{}", reason);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    /// Schema 1 stored the split as a fraction, schema 2 as a percentage.
+    fn split_to_percent(bytes: &[u8]) -> Result<Vec<u8>, String> {
+        let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+        let split: f64 = text.trim().parse().map_err(|e| format!("{}", e))?;
+        Ok(format!("{}", (split * 100.0).round()).into_bytes())
+    }
+
+    /// Schema 2 to 3 wraps the percentage in an object.
+    fn percent_to_json(bytes: &[u8]) -> Result<Vec<u8>, String> {
+        let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+        Ok(format!("{{\"split_ratio\":{}}}", text).into_bytes())
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration { from: 1, file: "split", migrate: split_to_percent },
+        Migration { from: 2, file: "split", migrate: percent_to_json },
+    ];
+
+    #[test]
+    fn test_older_schema_is_migrated_with_backups() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = scratch_dir("older")?;
+        fs::write(dir.join("split"), b"0.6")?;
+
+        let result = handshake_with(&dir, 3, TEST_MIGRATIONS)?;
+        let backups = dir.join("backups/schema-1");
+        assert_eq!(result, Handshake::Migrated { from: 1, backups: backups.clone() });
+        assert_eq!(fs::read(dir.join("split"))?, br#"{"split_ratio":60}"#);
+        // The original, not the file as the first step left it
+        assert_eq!(fs::read(backups.join("split"))?, b"0.6");
+        assert_eq!(read_meta(&dir)?.schema, 3);
+
+        // Nothing left to do on the next start
+        assert_eq!(handshake_with(&dir, 3, TEST_MIGRATIONS)?, Handshake::Current);
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_migration_stops_at_the_last_good_schema() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = scratch_dir("failed")?;
+        fs::write(dir.join("split"), b"most of it")?;
+
+        let error = handshake_with(&dir, 3, TEST_MIGRATIONS).err().ok_or("migrated")?;
+        assert!(error.to_string().contains("migrating split from schema 1 failed"), "{}", error);
+        assert_eq!(fs::read(dir.join("split"))?, b"most of it");
+        assert_eq!(fs::read(dir.join("backups/schema-1/split"))?, b"most of it");
+        assert!(!dir.join(META_FILE).exists());
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
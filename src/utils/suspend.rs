@@ -0,0 +1,222 @@
+//! Job control: suspending RustyTerm (SIGTSTP) and picking up again (SIGCONT).
+//!
+//! Stopped as it is, RustyTerm would leave the user's terminal in raw mode on
+//! the alternate screen. Instead SIGTSTP is caught: the modes RustyTerm turned
+//! on are turned off, then the process stops itself with SIGSTOP. When it is
+//! continued the modes are turned back on, and the caller resizes the PTY (the
+//! window may have changed meanwhile) and redraws everything.
+//!
+//! [`Suspension`] keeps track of which modes were turned off, so a second
+//! SIGTSTP or a SIGCONT that follows an outside SIGSTOP does not toggle
+//! modes twice.
+
+use std::io::{self, Write};
+
+use crossterm::event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+
+/// Host terminal modes RustyTerm turns on while it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TerminalModes {
+    pub raw: bool,
+    pub alternate_screen: bool,
+    pub mouse_capture: bool,
+    pub bracketed_paste: bool,
+}
+
+impl TerminalModes {
+    /// What `main` turns on: raw mode and the alternate screen (`ratatui::init`)
+    /// and mouse capture.
+    pub const APP: Self = Self { raw: true, alternate_screen: true, mouse_capture: true, bracketed_paste: false };
+
+    /// Turn the modes off, in the reverse of the order they are turned on.
+    pub fn disable(self, out: &mut impl Write) -> io::Result<()> {
+        if self.bracketed_paste {
+            execute!(out, DisableBracketedPaste)?;
+        }
+        if self.mouse_capture {
+            execute!(out, DisableMouseCapture)?;
+        }
+        if self.alternate_screen {
+            execute!(out, LeaveAlternateScreen)?;
+        }
+        if self.raw {
+            disable_raw_mode()?;
+        }
+        Ok(())
+    }
+
+    /// Turn the modes on.
+    pub fn enable(self, out: &mut impl Write) -> io::Result<()> {
+        if self.raw {
+            enable_raw_mode()?;
+        }
+        if self.alternate_screen {
+            execute!(out, EnterAlternateScreen)?;
+        }
+        if self.mouse_capture {
+            execute!(out, EnableMouseCapture)?;
+        }
+        if self.bracketed_paste {
+            execute!(out, EnableBracketedPaste)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether RustyTerm is suspended, and what to turn back on when it is not.
+#[derive(Debug, Default)]
+pub struct Suspension {
+    /// Modes turned off for the stop (None = running)
+    released: Option<TerminalModes>,
+}
+
+impl Suspension {
+    /// Start a suspension of a terminal in `modes`. Returns the modes to turn
+    /// off, or None if already suspended.
+    pub fn suspend(&mut self, modes: TerminalModes) -> Option<TerminalModes> {
+        if self.released.is_some() {
+            return None;
+        }
+        self.released = Some(modes);
+        Some(modes)
+    }
+
+    /// End the suspension. Returns the modes to turn back on, or None if
+    /// RustyTerm was not suspended (for example after an outside SIGSTOP,
+    /// which leaves the modes as they were).
+    pub fn resume(&mut self) -> Option<TerminalModes> {
+        self.released.take()
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.released.is_some()
+    }
+}
+
+/// A job control signal sent to RustyTerm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobSignal {
+    /// SIGTSTP: asked to stop (Ctrl+Z in a shell, `kill -TSTP`)
+    Stop,
+    /// SIGCONT: continued after a stop
+    Continue,
+}
+
+/// Receives SIGTSTP and SIGCONT. Once installed, SIGTSTP no longer stops
+/// the process by itself: [`stop_self`] does, after the terminal is restored.
+#[cfg(unix)]
+pub struct JobSignals {
+    stop: tokio::signal::unix::Signal,
+    cont: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl JobSignals {
+    /// Catch the signals. Must be called within the Tokio runtime.
+    pub fn install() -> io::Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+        Ok(Self {
+            stop: signal(SignalKind::from_raw(libc::SIGTSTP))?,
+            cont: signal(SignalKind::from_raw(libc::SIGCONT))?,
+        })
+    }
+
+    /// Wait for the next signal.
+    pub async fn recv(&mut self) -> Option<JobSignal> {
+        tokio::select! {
+            received = self.stop.recv() => received.map(|()| JobSignal::Stop),
+            received = self.cont.recv() => received.map(|()| JobSignal::Continue),
+        }
+    }
+}
+
+/// Without job control there is nothing to receive.
+#[cfg(not(unix))]
+pub struct JobSignals;
+
+#[cfg(not(unix))]
+impl JobSignals {
+    pub fn install() -> io::Result<Self> {
+        Ok(Self)
+    }
+
+    pub async fn recv(&mut self) -> Option<JobSignal> {
+        std::future::pending().await
+    }
+}
+
+/// Stop the process as SIGTSTP would by default. Returns once it is
+/// continued.
+#[cfg(unix)]
+pub fn stop_self() -> io::Result<()> {
+    // SAFETY: raise only sends a signal to the calling thread; SIGSTOP
+    // cannot be caught, so no handler runs
+    if unsafe { libc::raise(libc::SIGSTOP) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn stop_self() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "job control needs a Unix system"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suspend_then_resume_restores_the_same_modes() {
+        let mut suspension = Suspension::default();
+        assert!(!suspension.is_suspended());
+
+        assert_eq!(suspension.suspend(TerminalModes::APP), Some(TerminalModes::APP));
+        assert!(suspension.is_suspended());
+        assert_eq!(suspension.resume(), Some(TerminalModes::APP));
+        assert!(!suspension.is_suspended());
+    }
+
+    #[test]
+    fn test_repeated_signals_do_not_toggle_modes_twice() {
+        let mut suspension = Suspension::default();
+        // SIGCONT without a suspension of ours (outside SIGSTOP): nothing to turn on
+        assert_eq!(suspension.resume(), None);
+
+        let modes = TerminalModes { bracketed_paste: true, ..TerminalModes::APP };
+        assert_eq!(suspension.suspend(modes), Some(modes));
+        // A second SIGTSTP while stopping leaves the modes recorded first
+        assert_eq!(suspension.suspend(TerminalModes::default()), None);
+        assert_eq!(suspension.resume(), Some(modes));
+        // The SIGCONT that follows the resume finds nothing left to do
+        assert_eq!(suspension.resume(), None);
+    }
+
+    #[test]
+    fn test_no_modes_writes_nothing() -> io::Result<()> {
+        let mut out = Vec::new();
+        TerminalModes::default().disable(&mut out)?;
+        TerminalModes::default().enable(&mut out)?;
+        assert!(out.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_modes_are_turned_off_in_reverse_order() -> Result<(), Box<dyn std::error::Error>> {
+        let modes = TerminalModes { raw: false, alternate_screen: true, mouse_capture: true, bracketed_paste: true };
+        let mut off = Vec::new();
+        modes.disable(&mut off)?;
+        let off = String::from_utf8_lossy(&off);
+        let paste = off.find("\x1b[?2004l").ok_or("no paste")?;
+        let screen = off.find("\x1b[?1049l").ok_or("no screen")?;
+        assert!(paste < screen);
+
+        let mut on = Vec::new();
+        modes.enable(&mut on)?;
+        let on = String::from_utf8_lossy(&on);
+        assert!(on.find("\x1b[?1049h").ok_or("no screen")? < on.find("\x1b[?2004h").ok_or("no paste")?);
+        Ok(())
+    }
+}
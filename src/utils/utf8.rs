@@ -0,0 +1,163 @@
+//! PTY output as text.
+//!
+//! The PTY hands out bytes in reads of whatever size it likes, so a
+//! character can be cut across two reads, and a `cat` of a tarball is not
+//! text at all. A [`Utf8Decoder`] holds a cut-off character back until the
+//! next read completes it, and turns bytes that can never be UTF-8 into
+//! U+FFFD right away, so garbage never holds up the valid output behind it.
+//!
+//! What is kept for the AI (recent output, command records) also goes
+//! through [`sanitize`], which takes out NUL and the other control
+//! characters that have no business in a prompt or a saved record.
+
+use std::borrow::Cow;
+
+/// Turns a stream of bytes, read in any pieces, into text.
+#[derive(Debug, Clone, Default)]
+pub struct Utf8Decoder {
+    /// Start of a character cut off at the end of the last read
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    /// Text of the next read. Invalid sequences become U+FFFD; an
+    /// unfinished character at the end waits for the next read.
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        let input: Cow<[u8]> = if self.pending.is_empty() {
+            Cow::Borrowed(bytes)
+        } else {
+            let mut joined = std::mem::take(&mut self.pending);
+            joined.extend_from_slice(bytes);
+            Cow::Owned(joined)
+        };
+
+        let mut text = String::with_capacity(input.len());
+        let mut chunks = input.utf8_chunks().peekable();
+        while let Some(chunk) = chunks.next() {
+            text.push_str(chunk.valid());
+            let invalid = chunk.invalid();
+            if invalid.is_empty() {
+                continue;
+            }
+            let last = chunks.peek().is_none();
+            if last && std::str::from_utf8(invalid).is_err_and(|e| e.error_len().is_none()) {
+                self.pending.extend_from_slice(invalid);
+            } else {
+                text.push(char::REPLACEMENT_CHARACTER);
+            }
+        }
+        text
+    }
+
+    /// Text of the next read, ready to be kept: decoded and sanitized.
+    pub fn decode_clean(&mut self, bytes: &[u8]) -> String {
+        let text = self.decode(bytes);
+        match sanitize(&text) {
+            Cow::Borrowed(_) => text,
+            Cow::Owned(clean) => clean,
+        }
+    }
+
+    /// The stream ended: a character still waiting for its bytes never
+    /// comes, so it becomes U+FFFD.
+    pub fn finish(&mut self) -> String {
+        if self.pending.is_empty() {
+            return String::new();
+        }
+        self.pending.clear();
+        char::REPLACEMENT_CHARACTER.to_string()
+    }
+}
+
+/// `text` without NUL, the other control characters and the bidi overrides
+/// that would make it read differently than it prints. Tabs, line ends and
+/// ESC (which starts the escape codes stripped later) stay.
+pub fn sanitize(text: &str) -> Cow<'_, str> {
+    if text.chars().any(is_hazard) {
+        Cow::Owned(text.chars().filter(|&c| !is_hazard(c)).collect())
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+fn is_hazard(c: char) -> bool {
+    match c {
+        '\t' | '\n' | '\r' | '\x1b' => false,
+        '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => true,
+        c => c.is_control(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode `bytes` cut into the given pieces, and what's left at the end.
+    fn decode_in_pieces(pieces: &[&[u8]]) -> String {
+        let mut decoder = Utf8Decoder::default();
+        let mut text: String = pieces.iter().map(|piece| decoder.decode(piece)).collect();
+        text.push_str(&decoder.finish());
+        text
+    }
+
+    #[test]
+    fn test_characters_cut_across_reads_come_out_whole() {
+        let text = "é → 日本 🦀\n";
+        let bytes = text.as_bytes();
+        for cut in 0..=bytes.len() {
+            assert_eq!(decode_in_pieces(&[&bytes[..cut], &bytes[cut..]]), text, "cut at {}", cut);
+        }
+        let singles: Vec<&[u8]> = bytes.chunks(1).collect();
+        assert_eq!(decode_in_pieces(&singles), text);
+
+        // Nothing comes out until the character is complete
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(&[0xf0, 0x9f]), "");
+        assert_eq!(decoder.decode(&[0xa6]), "");
+        assert_eq!(decoder.decode(&[0x80, b'!']), "🦀!");
+    }
+
+    #[test]
+    fn test_garbage_is_replaced_and_valid_output_after_it_is_kept() {
+        let mut decoder = Utf8Decoder::default();
+        assert_eq!(decoder.decode(b"ok \xff\xfe then"), "ok \u{fffd}\u{fffd} then");
+        // A sequence broken off by a byte that can't continue it
+        assert_eq!(decoder.decode(&[0xe6, 0x97]), "");
+        assert_eq!(decoder.decode(b"x\n"), "\u{fffd}x\n");
+        // The end of the stream doesn't wait
+        assert_eq!(decoder.decode(&[0xc3]), "");
+        assert_eq!(decoder.finish(), "\u{fffd}");
+        assert_eq!(decoder.decode("é".as_bytes()), "é");
+    }
+
+    #[test]
+    fn test_random_bytes_never_hold_up_the_stream() {
+        // A cheap generator, so the bytes are the same on every run
+        let mut state: u32 = 0x1234_5678;
+        let bytes: Vec<u8> = (0..64 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let mut decoder = Utf8Decoder::default();
+        for piece in bytes.chunks(7) {
+            let text = decoder.decode(piece);
+            assert!(sanitize(&text).chars().all(|c| !is_hazard(c)));
+        }
+        // At most one unfinished character is held back
+        assert!(decoder.pending.len() < 4);
+        decoder.finish();
+        assert_eq!(decoder.decode(b"fine\n"), "fine\n");
+    }
+
+    #[test]
+    fn test_sanitize_takes_out_control_characters_only() {
+        assert!(matches!(sanitize("plain\ttext\r\n\x1b[0m"), Cow::Borrowed(_)));
+        assert_eq!(sanitize("a\0b\x07c\x7fd\u{85}e"), "abcde");
+        assert_eq!(sanitize("abc\u{202e}fed"), "abcfed");
+        assert_eq!(sanitize("\u{fffd}日本"), "\u{fffd}日本");
+    }
+}
@@ -0,0 +1,94 @@
+//! End-to-end harness: the whole app on a scripted shell and scripted AI
+//! replies, rendered to ratatui's `TestBackend`.
+//!
+//! Scenarios type keys with the [`script`] DSL, push shell output and
+//! stream data, then compare the rendered screen against a golden
+//! [`snapshot`].
+
+pub mod script;
+pub mod snapshot;
+
+use anyhow::{anyhow, Result};
+use crossterm::event::{KeyModifiers, MouseEvent, MouseEventKind};
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+use rusty_term::ai::session::ScriptedReplies;
+use rusty_term::config::Config;
+use rusty_term::shell::ScriptedShell;
+use rusty_term::{App, UserEvent};
+
+pub struct Harness {
+    pub app: App,
+    pub shell: ScriptedShell,
+    pub ai: ScriptedReplies,
+    terminal: Terminal<TestBackend>,
+}
+
+impl Harness {
+    /// An app with the default config on a `cols`x`rows` screen.
+    pub fn new(cols: u16, rows: u16) -> Result<Self> {
+        Self::with_config(Config::default(), cols, rows)
+    }
+
+    /// An app with `config` on a `cols`x`rows` screen.
+    pub fn with_config(config: Config, cols: u16, rows: u16) -> Result<Self> {
+        let (app, shell, ai) = App::scripted(config, cols, rows)?;
+        let terminal = Terminal::new(TestBackend::new(cols, rows))?;
+        let mut harness = Self { app, shell, ai, terminal };
+        // Lay out once so the shell gets its size before any output
+        harness.render()?;
+        Ok(harness)
+    }
+
+    /// Type `script` (see [`script::parse_keys`]), handling queued output
+    /// after every key.
+    pub fn keys(&mut self, script: &str) -> Result<&mut Self> {
+        for key in script::parse_keys(script)? {
+            self.event(UserEvent::Key(key))?;
+        }
+        Ok(self)
+    }
+
+    /// Feed one user event.
+    pub fn event(&mut self, event: UserEvent) -> Result<&mut Self> {
+        self.app.on_user_event(event)?;
+        self.settle()?;
+        Ok(self)
+    }
+
+    /// Feed a mouse event at `col`, `row`.
+    pub fn mouse(&mut self, kind: MouseEventKind, col: u16, row: u16) -> Result<&mut Self> {
+        self.event(UserEvent::Mouse(MouseEvent { kind, column: col, row, modifiers: KeyModifiers::NONE }))
+    }
+
+    /// Let the shell print `text` and handle it.
+    pub fn print(&mut self, text: &str) -> Result<&mut Self> {
+        self.shell.print(text).map_err(|e| anyhow!("shell output not queued: {}", e))?;
+        self.settle()
+    }
+
+    /// Handle everything queued so far.
+    pub fn settle(&mut self) -> Result<&mut Self> {
+        self.app.process_pending()?;
+        Ok(self)
+    }
+
+    /// Resize the host screen.
+    pub fn resize(&mut self, cols: u16, rows: u16) -> Result<&mut Self> {
+        self.terminal.backend_mut().resize(cols, rows);
+        self.event(UserEvent::Resize(cols, rows))
+    }
+
+    /// Settle, draw a frame and return its snapshot.
+    pub fn render(&mut self) -> Result<String> {
+        self.settle()?;
+        self.app.draw(&mut self.terminal)?;
+        Ok(snapshot::normalize(self.terminal.backend().buffer()))
+    }
+
+    /// Render and compare with the golden file `name`.
+    pub fn assert_golden(&mut self, name: &str) -> Result<()> {
+        let actual = self.render()?;
+        snapshot::assert_golden(name, &actual)
+    }
+}
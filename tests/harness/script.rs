@@ -0,0 +1,87 @@
+//! A small key-script language for typing into the app.
+//!
+//! Plain characters are typed as-is; `<...>` names one key, optionally with
+//! modifiers: `<Enter>`, `<Esc>`, `<C-b>`, `<S-PageUp>`, `<A-x>`, `<C-S-Up>`.
+//! `<lt>` types a literal `<`.
+
+use anyhow::{bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Parse `script` into key presses.
+pub fn parse_keys(script: &str) -> Result<Vec<KeyEvent>> {
+    let mut keys = Vec::new();
+    let mut chars = script.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(char_key(c, KeyModifiers::NONE));
+            continue;
+        }
+        let name: String = chars.by_ref().take_while(|&c| c != '>').collect();
+        keys.push(named_key(&name)?);
+    }
+    Ok(keys)
+}
+
+/// A typed character; uppercase letters carry Shift like a real terminal sends.
+fn char_key(c: char, modifiers: KeyModifiers) -> KeyEvent {
+    let shift = if c.is_ascii_uppercase() { KeyModifiers::SHIFT } else { KeyModifiers::NONE };
+    KeyEvent::new(KeyCode::Char(c), modifiers | shift)
+}
+
+fn named_key(spec: &str) -> Result<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = spec;
+    while let Some((prefix, tail)) = rest.split_once('-').filter(|(_, tail)| !tail.is_empty()) {
+        modifiers |= match prefix {
+            "C" => KeyModifiers::CONTROL,
+            "S" => KeyModifiers::SHIFT,
+            "A" => KeyModifiers::ALT,
+            _ => break,
+        };
+        rest = tail;
+    }
+
+    let code = match rest {
+        "Enter" => KeyCode::Enter,
+        "Esc" => KeyCode::Esc,
+        "Tab" => KeyCode::Tab,
+        "BackTab" => KeyCode::BackTab,
+        "BS" | "Backspace" => KeyCode::Backspace,
+        "Space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "Up" => KeyCode::Up,
+        "Down" => KeyCode::Down,
+        "Left" => KeyCode::Left,
+        "Right" => KeyCode::Right,
+        "Home" => KeyCode::Home,
+        "End" => KeyCode::End,
+        "PageUp" => KeyCode::PageUp,
+        "PageDown" => KeyCode::PageDown,
+        "Delete" => KeyCode::Delete,
+        _ => {
+            let mut chars = rest.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => return Ok(char_key(c, modifiers)),
+                _ => bail!("unknown key <{}> in script", spec),
+            }
+        }
+    };
+    Ok(KeyEvent::new(code, modifiers))
+}
+
+#[test]
+fn test_parse_keys() -> Result<()> {
+    let keys = parse_keys("a<C-b>N<S-PageUp><lt><Enter>")?;
+    let expected = [
+        KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE),
+        KeyEvent::new(KeyCode::Char('b'), KeyModifiers::CONTROL),
+        KeyEvent::new(KeyCode::Char('N'), KeyModifiers::SHIFT),
+        KeyEvent::new(KeyCode::PageUp, KeyModifiers::SHIFT),
+        KeyEvent::new(KeyCode::Char('<'), KeyModifiers::NONE),
+        KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+    ];
+    assert_eq!(keys, expected);
+    assert!(parse_keys("<Hyper-x>").is_err());
+    assert!(parse_keys("<C->").is_err());
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Plain-text snapshots of a rendered buffer and golden-file comparison.
+//!
+//! A snapshot is the buffer's text, one line per row with trailing blanks
+//! trimmed, followed by a mask of highlighted cells (a background colour or
+//! reversed video: selections, the visual cursor, buttons) for the rows that
+//! have any. Golden files live in `tests/golden/`; run with
+//! `UPDATE_GOLDEN=1` to rewrite them after an intended change.
+
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use ratatui::buffer::{Buffer, Cell};
+use ratatui::style::{Color, Modifier};
+
+/// Text and highlight mask of `buffer`.
+pub fn normalize(buffer: &Buffer) -> String {
+    let area = buffer.area;
+    let mut out = format!("{}x{}\n", area.width, area.height);
+    let mut highlights = String::new();
+    for y in area.top()..area.bottom() {
+        let cells: Vec<&Cell> = (area.left()..area.right()).filter_map(|x| buffer.cell((x, y))).collect();
+        let text: String = cells.iter().map(|cell| cell.symbol()).collect();
+        out.push_str(text.trim_end());
+        out.push('\n');
+
+        let mask: String = cells.iter().map(|cell| if is_highlighted(cell) { '#' } else { ' ' }).collect();
+        if !mask.trim().is_empty() {
+            highlights.push_str(&format!("{:>3} {}\n", y, mask.trim_end()));
+        }
+    }
+    if !highlights.is_empty() {
+        out.push_str("--- highlights ---\n");
+        out.push_str(&highlights);
+    }
+    out
+}
+
+fn is_highlighted(cell: &Cell) -> bool {
+    cell.bg != Color::Reset || cell.modifier.contains(Modifier::REVERSED)
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.txt", name))
+}
+
+/// Compare `actual` with the golden file `name`, or rewrite it when
+/// `UPDATE_GOLDEN` is set.
+pub fn assert_golden(name: &str, actual: &str) -> Result<()> {
+    let path = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).with_context(|| format!("writing {}", path.display()))?;
+        return Ok(());
+    }
+    let expected = std::fs::read_to_string(&path)
+        .with_context(|| format!("missing golden {} (run with UPDATE_GOLDEN=1)", path.display()))?;
+    if expected == actual {
+        return Ok(());
+    }
+    let line = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+    bail!(
+        "snapshot {} differs from golden at line {}\n--- expected\n{}\n--- actual\n{}",
+        name,
+        line + 1,
+        expected,
+        actual
+    )
+}
@@ -0,0 +1,756 @@
+//! End-to-end scenarios: keys and scripted output in, rendered screen out.
+//!
+//! Each scenario drives the whole app through [`harness::Harness`] and
+//! compares the screen with `tests/golden/<name>.txt`. After an intended UI
+//! change, regenerate with `UPDATE_GOLDEN=1 cargo test --test tui` and
+//! review the diff.
+
+mod harness;
+
+use anyhow::{anyhow, Result};
+use crossterm::event::{MouseButton, MouseEventKind};
+use harness::Harness;
+use ratatui::layout::Rect;
+use rusty_term::ai::session::SessionId;
+use rusty_term::app::ActivePane;
+use rusty_term::{AiStreamData, UserEvent};
+
+/// Ask the assistant `question` and return the session it went to.
+fn ask(h: &mut Harness, question: &str) -> Result<SessionId> {
+    h.keys("<C-b>n")?.keys(question)?.keys("<Enter>")?;
+    let asked = h.ai.asked();
+    let (session_id, text) = asked.last().ok_or_else(|| anyhow!("question was not sent"))?;
+    assert_eq!(text, question);
+    Ok(*session_id)
+}
+
+/// Stream `data` into the app as if the API had sent it.
+fn stream(h: &mut Harness, data: AiStreamData) -> Result<()> {
+    h.ai.send(data).map_err(|e| anyhow!("stream data not queued: {}", e))?;
+    h.settle()?;
+    Ok(())
+}
+
+fn suggest(command: &str, explanation: &str) -> (String, String, String) {
+    let args = serde_json::json!({"command": command, "explanation": explanation, "risk_level": "medium"});
+    ("call_1".to_string(), "suggest_command".to_string(), args.to_string())
+}
+
+fn numbered_lines(count: usize) -> String {
+    (1..=count).map(|i| format!("line {}\r\n", i)).collect()
+}
+
+#[test]
+fn initial_layout_80x24() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.print("$ ")?;
+    h.assert_golden("initial_80x24")
+}
+
+#[test]
+fn initial_layout_120x40() -> Result<()> {
+    let mut h = Harness::new(120, 40)?;
+    h.print("$ ")?;
+    h.assert_golden("initial_120x40")
+}
+
+#[test]
+fn resize_relays_out_both_panes() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.print("$ echo hello\r\nhello\r\n$ ")?;
+    h.resize(100, 30)?;
+    assert!(h.shell.size().is_some());
+    h.assert_golden("resized_100x30")
+}
+
+#[test]
+fn command_mode_popup() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.print("$ ")?;
+    h.keys("<C-b>")?;
+    h.assert_golden("command_mode_popup")
+}
+
+#[test]
+fn keys_after_command_mode_reach_the_shell() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.keys("<C-b><Esc>ls<Enter>")?;
+    assert_eq!(h.shell.input(), b"ls\r");
+    Ok(())
+}
+
+#[test]
+fn regenerated_reply_keeps_the_old_one_greyed_out() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    h.print("$ ")?;
+    let session_id = ask(&mut h, "free disk space")?;
+    stream(&mut h, AiStreamData::Chunk { session_id, text: "Clear the apt cache.".to_string() })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+    assert!(h.render()?.contains("[↻ Regenerate]"));
+
+    // "/regen" opens the menu; 2 picks "Shorter"
+    h.keys("/regen<Enter>")?;
+    assert!(h.render()?.contains("Different approach"));
+    h.keys("2")?;
+    let asked = h.ai.asked();
+    let (_, question) = asked.last().ok_or_else(|| anyhow!("question was not sent again"))?;
+    assert_eq!(question, "free disk space\n\n(Answer again, more briefly.)");
+    stream(&mut h, AiStreamData::Chunk { session_id, text: "apt clean".to_string() })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+    let screen = h.render()?;
+    assert!(screen.contains("AI (superseded): Clear the apt cache."));
+    assert!(screen.contains("AI: apt clean"));
+    Ok(())
+}
+
+#[test]
+fn accepted_suggestion_is_pasted_and_run_once() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    // The shell turns on bracketed paste at its prompt
+    h.print("\x1b[?2004h$ ")?;
+    let session_id = ask(&mut h, "show tabs")?;
+    stream(&mut h, AiStreamData::ToolCalls {
+        session_id,
+        tool_calls: vec![suggest("printf 'a\tb'\n\n", "Print a tab")],
+    })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+    h.keys("<C-y>")?;
+    assert_eq!(h.shell.input(), b"\x1b[200~printf 'a\tb'\x1b[201~\r");
+    Ok(())
+}
+
+#[test]
+fn confirm_keys_stay_in_the_active_session() -> Result<()> {
+    let mut h = Harness::new(160, 30)?;
+    h.print("$ ")?;
+    let session_id = ask(&mut h, "list files")?;
+    stream(&mut h, AiStreamData::ToolCalls { session_id, tool_calls: vec![suggest("ls -la", "List files")] })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+
+    // In a new session the keys don't reach the first session's card
+    h.keys("<C-b>t")?.keys("<C-y>")?;
+    assert!(h.shell.input().is_empty());
+    let screen = h.render()?;
+    assert!(screen.contains("pending suggestion in list files — Ctrl+B ] to view"), "{screen}");
+    assert!(screen.contains("[no card here, see list files]"), "{screen}");
+
+    h.keys("<C-b>[")?.keys("<C-y>")?;
+    assert_eq!(h.shell.input(), b"ls -la\r");
+    assert!(!h.render()?.contains("pending suggestion in"));
+    Ok(())
+}
+
+/// Accept `command` in a new question, then let the shell echo it and keep
+/// running (no prompt comes back).
+fn start_long_running(h: &mut Harness, command: &str) -> Result<SessionId> {
+    h.print("$ ")?;
+    let session_id = ask(h, "start the dev server")?;
+    stream(h, AiStreamData::ToolCalls { session_id, tool_calls: vec![suggest(command, "Start it")] })?;
+    stream(h, AiStreamData::End { session_id, cached: false })?;
+    h.keys("<C-y>")?;
+    h.print(&format!("{}\r\nready on http://localhost:3000\r\n", command))?;
+    Ok(session_id)
+}
+
+/// Ask `question` in the open assistant and get `command` suggested.
+fn ask_again(h: &mut Harness, session_id: SessionId, question: &str, command: &str) -> Result<()> {
+    h.keys(question)?.keys("<Enter>")?;
+    let (_, id, args) = suggest(command, "Next step");
+    stream(h, AiStreamData::ToolCalls { session_id, tool_calls: vec![("call_2".to_string(), id, args)] })?;
+    stream(h, AiStreamData::End { session_id, cached: false })
+}
+
+#[test]
+fn suggestion_while_the_previous_one_runs_asks_twice() -> Result<()> {
+    let mut h = Harness::new(250, 30)?;
+    let session_id = start_long_running(&mut h, "npm run dev")?;
+    assert_eq!(h.shell.input(), b"npm run dev\r");
+
+    ask_again(&mut h, session_id, "lint the project", "npm run lint")?;
+    let screen = h.render()?;
+    assert!(screen.contains("⚠ previous AI command `npm run dev` appears to still be running"), "{screen}");
+
+    // The first press would type into the dev server: it only asks
+    h.keys("<C-y>")?;
+    assert_eq!(h.shell.input(), b"npm run dev\r");
+    assert!(h.render()?.contains("press again to send anyway, or Ctrl+N to cancel"));
+    h.keys("<C-y>")?;
+    assert_eq!(h.shell.input(), b"npm run dev\rnpm run lint\r");
+    Ok(())
+}
+
+#[test]
+fn interrupt_then_run_and_prompt_clears_the_warning() -> Result<()> {
+    let mut h = Harness::new(250, 30)?;
+    let session_id = start_long_running(&mut h, "npm run dev")?;
+    ask_again(&mut h, session_id, "build instead", "npm run build")?;
+    h.keys("<C-k>")?;
+    assert_eq!(h.shell.input(), b"npm run dev\r\x03npm run build\r");
+
+    // Once the shell is back at its prompt there is nothing to warn about
+    let mut h = Harness::new(250, 30)?;
+    let session_id = start_long_running(&mut h, "npm run dev")?;
+    h.print("^C\r\n$ ")?;
+    ask_again(&mut h, session_id, "build instead", "npm run build")?;
+    assert!(!h.render()?.contains("appears to still be running"));
+    h.keys("<C-y>")?;
+    assert_eq!(h.shell.input(), b"npm run dev\rnpm run build\r");
+    Ok(())
+}
+
+#[test]
+fn pending_card_with_confirm_verdict() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    h.print("$ ")?;
+    let session_id = ask(&mut h, "stage my changes")?;
+    stream(&mut h, AiStreamData::Chunk { session_id, text: "Here you go:".to_string() })?;
+    let tool_calls = vec![suggest("git add .", "Stage every change in the working tree")];
+    stream(&mut h, AiStreamData::ToolCalls { session_id, tool_calls })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+    h.assert_golden("pending_card_confirm")
+}
+
+#[test]
+fn draft_card_while_arguments_stream() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    h.print("$ ")?;
+    let session_id = ask(&mut h, "find todo markers")?;
+    let draft = rusty_term::ai::draft::SuggestionDraft::scan(r#"{"command": "grep -rn TODO src", "expla"#);
+    stream(&mut h, AiStreamData::SuggestionDraft { session_id, draft })?;
+    h.assert_golden("draft_card")
+}
+
+#[test]
+fn assistant_streaming_prompt() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.print("$ ")?;
+    let session_id = ask(&mut h, "what is my shell")?;
+    stream(&mut h, AiStreamData::Chunk { session_id, text: "You are running a POSIX ".to_string() })?;
+    h.assert_golden("assistant_streaming")
+}
+
+#[test]
+fn visual_mode_character_selection() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.print(&numbered_lines(5))?.print("$ ")?;
+    h.keys("<C-b>vkkk<Space>j")?;
+    h.assert_golden("visual_selection")
+}
+
+#[test]
+fn visual_mode_block_selection() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.print(&numbered_lines(5))?.print("$ ")?;
+    h.keys("<C-b>vkkk<Space><Space>jlll")?;
+    h.assert_golden("visual_block_selection")
+}
+
+#[test]
+fn browsing_mode_title() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.print(&numbered_lines(60))?.print("$ ")?;
+    h.keys("<S-PageUp>")?;
+    h.assert_golden("browsing_title")
+}
+
+#[test]
+fn line_numbers_toggle() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.print(&numbered_lines(3))?.print("$ ")?;
+    h.keys("<C-b>N")?;
+    h.assert_golden("line_numbers")
+}
+
+#[test]
+fn performance_mode_toggles_per_pane() -> Result<()> {
+    let mut h = Harness::new(100, 24)?;
+    h.print("$ ")?;
+    h.keys("<C-b>z")?;
+    assert!(h.render()?.contains("RustyTerm · perf: terminal─"));
+    h.keys("<C-b>n<C-b>z")?;
+    assert!(h.render()?.contains("RustyTerm · perf─"));
+    h.keys("<C-b>z")?;
+    assert!(h.render()?.contains("RustyTerm · perf: terminal─"));
+    Ok(())
+}
+
+#[test]
+fn small_screen_shows_only_active_pane() -> Result<()> {
+    let mut h = Harness::new(60, 20)?;
+    h.print("$ echo hello\r\nhello\r\n$ ")?;
+    h.assert_golden("single_pane_terminal")?;
+    h.keys("<C-b>n")?;
+    h.assert_golden("single_pane_assistant")
+}
+
+#[test]
+fn tiny_screen_shows_placeholder() -> Result<()> {
+    let mut h = Harness::new(45, 7)?;
+    h.print("$ ")?;
+    h.assert_golden("too_small")
+}
+
+#[test]
+fn growing_back_restores_split_and_shell_size() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    h.print("$ echo hello\r\nhello\r\n$ ")?;
+    let before = h.render()?;
+    let shell_size = h.shell.size();
+
+    // Hiding the terminal leaves the shell at its last size
+    h.keys("<C-b>n")?.resize(50, 12)?;
+    h.render()?;
+    h.resize(20, 5)?;
+    h.render()?;
+    h.keys("<C-b>n")?.resize(100, 30)?;
+    assert_eq!(h.shell.size(), shell_size);
+    assert_eq!(h.render()?, before);
+    Ok(())
+}
+
+/// Press on the separator and drag it to `col`, without releasing.
+fn drag_separator(h: &mut Harness, col: u16) -> Result<()> {
+    let separator = h.app.layout().separator_area;
+    let row = separator.y + separator.height / 2;
+    h.mouse(MouseEventKind::Down(MouseButton::Left), separator.x, row)?;
+    h.mouse(MouseEventKind::Drag(MouseButton::Left), col - 5, row)?;
+    h.mouse(MouseEventKind::Drag(MouseButton::Left), col, row)?;
+    Ok(())
+}
+
+#[test]
+fn separator_drag_follows_the_pointer_and_resizes_the_shell_once() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    h.print("$ echo hello\r\nhello\r\n$ ")?;
+    let (shell_size, resizes) = (h.shell.size(), h.shell.resize_count());
+    let old_width = h.app.layout().terminal_inner.width;
+
+    // The panes follow the pointer; the shell keeps its size meanwhile
+    drag_separator(&mut h, 75)?;
+    assert_eq!(h.app.separator_drag_ratio(), Some(75));
+    assert!(h.app.layout().terminal_inner.width > old_width);
+    assert!(h.render()?.contains("75% │ 25%"));
+    assert_eq!(h.shell.size(), shell_size);
+    assert_eq!(h.shell.resize_count(), resizes);
+
+    // Released: the split is committed and the shell resized, once
+    let row = h.app.layout().separator_area.y;
+    h.mouse(MouseEventKind::Up(MouseButton::Left), 75, row)?;
+    assert_eq!(h.app.separator_drag_ratio(), None);
+    assert_eq!(h.app.split_ratio(), 75);
+    assert_eq!(h.shell.resize_count(), resizes + 1);
+    let inner = h.app.layout().terminal_inner;
+    assert_eq!(h.shell.size(), Some((inner.width, inner.height)));
+    assert!(!h.render()?.contains("75% │ 25%"));
+    Ok(())
+}
+
+#[test]
+fn esc_puts_a_dragged_separator_back() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    h.print("$ ")?;
+    let (ratio, layout, resizes) = (h.app.split_ratio(), h.app.layout().terminal_area, h.shell.resize_count());
+
+    drag_separator(&mut h, 30)?;
+    assert_ne!(h.app.layout().terminal_area, layout);
+    h.keys("<Esc>")?;
+    assert_eq!(h.app.separator_drag_ratio(), None);
+    assert_eq!(h.app.split_ratio(), ratio);
+    assert_eq!(h.app.layout().terminal_area, layout);
+    assert_eq!(h.shell.resize_count(), resizes);
+    // Neither the Esc nor the release that follows reach the shell
+    let row = h.app.layout().separator_area.y;
+    h.mouse(MouseEventKind::Up(MouseButton::Left), 30, row)?;
+    assert_eq!(h.shell.input(), b"");
+    assert_eq!(h.app.split_ratio(), ratio);
+    Ok(())
+}
+
+#[test]
+fn any_screen_size_renders() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    h.print(&numbered_lines(40))?.print("$ ")?;
+    h.keys("<C-b>N")?;
+    let session_id = ask(&mut h, "explain")?;
+    stream(&mut h, AiStreamData::Chunk { session_id, text: "Partial answer ".repeat(20) })?;
+    for width in (0..=130).step_by(3) {
+        for height in (0..=45).step_by(2) {
+            h.resize(width, height)?;
+            h.render()?;
+        }
+    }
+    Ok(())
+}
+
+/// A terminal with history and a session with a suggestion card.
+fn populated(h: &mut Harness) -> Result<()> {
+    h.print(&numbered_lines(40))?.print("$ ")?;
+    let session_id = ask(h, "list files")?;
+    let (call_id, name, args) = suggest("ls -la", "Show every file");
+    stream(h, AiStreamData::ToolCalls { session_id, tool_calls: vec![(call_id, name, args)] })?;
+    stream(h, AiStreamData::End { session_id, cached: false })
+}
+
+#[test]
+fn degenerate_sizes_leave_no_trace() -> Result<()> {
+    let mut h = Harness::new(80, 24)?;
+    populated(&mut h)?;
+    for width in 0..=5 {
+        for height in 0..=5 {
+            h.resize(width, height)?;
+            h.render()?;
+        }
+    }
+    // A bogus report is ignored outright
+    h.event(UserEvent::Resize(u16::MAX, u16::MAX))?;
+    assert_eq!(h.app.layout().full_area, Rect::new(0, 0, 5, 5));
+    h.resize(80, 24)?;
+
+    let mut fresh = Harness::new(80, 24)?;
+    populated(&mut fresh)?;
+    assert_eq!(h.shell.size(), fresh.shell.size());
+    assert_eq!(h.render()?, fresh.render()?);
+    Ok(())
+}
+
+#[test]
+fn project_config_asks_for_trust_then_applies() -> Result<()> {
+    let root = std::env::temp_dir().join(format!("rusty-term-tui-project-{}", std::process::id()));
+    let repo = root.join("infra");
+    std::fs::create_dir_all(repo.join(".git"))?;
+    std::fs::create_dir_all(repo.join("charts"))?;
+    std::fs::write(repo.join(".rustyterm.toml"), "[security]\ndeny = [\"ls\"]\n")?;
+    let osc7 = |dir: &std::path::Path| format!("\x1b]7;file://host{}\x07$ ", dir.display());
+
+    let mut h = Harness::new(100, 30)?;
+    h.print(&osc7(&repo.join("charts")))?;
+    let screen = h.render()?;
+    assert!(screen.contains("TRUST PROJECT CONFIG?"));
+    assert!(screen.contains("deny: ls"));
+    assert!(!screen.contains("(project: infra)"));
+
+    // Typing doesn't reach the shell while the prompt is open
+    h.keys("ls")?;
+    assert_eq!(h.shell.input(), b"");
+    h.keys("y")?;
+    let screen = h.render()?;
+    assert!(!screen.contains("TRUST PROJECT CONFIG?"));
+    assert!(screen.contains("RustyTerm (project: infra)"));
+
+    // The project's rules tighten verdicts of suggested commands
+    let session_id = ask(&mut h, "list files")?;
+    stream(&mut h, AiStreamData::ToolCalls { session_id, tool_calls: vec![suggest("ls -la", "List files")] })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+    assert!(h.render()?.contains("✗ Deny"));
+
+    // Leaving the tree removes the overlay; coming back doesn't ask again
+    h.print(&osc7(&root))?;
+    assert!(!h.render()?.contains("(project: infra)"));
+    h.print(&osc7(&repo))?;
+    let screen = h.render()?;
+    assert!(!screen.contains("TRUST PROJECT CONFIG?"));
+    assert!(screen.contains("(project: infra)"));
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn switcher_filters_and_jumps_to_sessions_and_shell() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    h.print("$ ")?;
+    // A second session, then back to the shell
+    h.keys("<C-b>n<C-b>T<C-b>n")?;
+    assert_eq!(h.app.active_session(), 2);
+
+    h.keys("<C-b>s")?;
+    let screen = h.render()?;
+    assert!(screen.contains("Switch to"));
+    assert!(screen.contains("Session 1") && screen.contains("Session 2"));
+
+    // Typing filters the list instead of reaching the shell
+    h.keys("s1<Enter>")?;
+    assert_eq!(h.shell.input(), b"");
+    assert!(h.app.switcher().is_none());
+    assert_eq!(h.app.get_active_pane(), ActivePane::Assistant);
+    assert_eq!(h.app.active_session(), 1);
+
+    h.keys("<C-b>sshell<Enter>")?;
+    assert_eq!(h.app.get_active_pane(), ActivePane::Terminal);
+
+    // Esc leaves everything as it was
+    h.keys("<C-b>s<C-n><Esc>")?;
+    assert!(h.app.switcher().is_none());
+    assert_eq!(h.app.get_active_pane(), ActivePane::Terminal);
+    assert_eq!(h.shell.input(), b"");
+    Ok(())
+}
+
+#[test]
+fn help_lists_the_keys_of_the_pane_and_filters() -> Result<()> {
+    let mut h = Harness::new(120, 40)?;
+    h.print("$ ")?;
+    h.keys("<C-b>?")?;
+    let screen = h.render()?;
+    assert!(screen.contains("Command mode (current)"));
+    assert!(screen.contains("Ctrl+B Shift+N") && screen.contains("select-output"));
+    // Assistant-only keys are left out in the terminal pane
+    assert!(!screen.contains("run-suggestion"));
+
+    // Typing filters instead of reaching the shell
+    h.keys("scrollback")?;
+    assert_eq!(h.shell.input(), b"");
+    let names = h.app.help().map(|help| help.visible_names()).unwrap_or_default();
+    assert_eq!(names, vec!["dump-scrollback", "scroll-line", "scroll-page"]);
+
+    h.keys("?")?;
+    assert!(h.app.help().is_none());
+    h.keys("<C-b>?<Esc>")?;
+    assert!(h.app.help().is_none());
+    assert_eq!(h.shell.input(), b"");
+    Ok(())
+}
+
+#[test]
+fn recording_writes_a_cast_file() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("rusty-term-tui-casts-{}", std::process::id()));
+    let mut config = rusty_term::config::Config::default();
+    config.recording.dir = Some(dir.clone());
+    config.recording.record_input = true;
+
+    let mut h = Harness::with_config(config, 80, 24)?;
+    h.print("$ ")?;
+    h.keys("<C-b>r")?;
+    assert!(h.render()?.contains("● REC"));
+    h.keys("l")?;
+    h.print("l")?;
+    h.keys("<C-b>r")?;
+    let screen = h.render()?;
+    assert!(!screen.contains("● REC"));
+    assert!(screen.contains("Saved "));
+
+    let cast = std::fs::read_dir(&dir)?.next().ok_or_else(|| anyhow!("no cast file"))??.path();
+    let text = std::fs::read_to_string(&cast)?;
+    std::fs::remove_dir_all(&dir)?;
+    let mut lines = text.lines();
+    let header: serde_json::Value = serde_json::from_str(lines.next().unwrap_or_default())?;
+    assert_eq!(header["version"], 2);
+    let events: Vec<serde_json::Value> = lines.map(serde_json::from_str).collect::<Result<_, _>>()?;
+    let codes: Vec<(&str, &str)> = events.iter().filter_map(|e| Some((e[1].as_str()?, e[2].as_str()?))).collect();
+    assert_eq!(codes, vec![("i", "l"), ("o", "l")]);
+    Ok(())
+}
+
+#[test]
+fn keyboard_macro_replays_the_keys_sent_to_the_shell() -> Result<()> {
+    let config = rusty_term::config::Config::from_toml_str("[keymap]\nmacro_delay_ms = 0\n")?;
+    let mut h = Harness::with_config(config, 80, 24)?;
+    h.print("$ ")?;
+
+    // Command mode keys and scrolling are left out
+    h.keys("<C-b>qa")?;
+    assert!(h.render()?.contains("REC @a"));
+    h.keys("<Up><Up><C-w>7<S-Up><Enter>")?;
+    h.keys("<C-b>q")?;
+    let screen = h.render()?;
+    assert!(!screen.contains("REC @a") && screen.contains("Recorded 5 keys in @a"), "{screen}");
+    let recorded = b"\x1b[A\x1b[A\x177\r".to_vec();
+    assert_eq!(h.shell.input(), recorded);
+
+    h.keys("<C-b>@a")?;
+    assert_eq!(h.shell.input(), recorded.repeat(2));
+    h.keys("<C-b>12@a")?;
+    assert_eq!(h.shell.input(), recorded.repeat(14));
+    h.keys("<C-b>@b")?;
+    assert!(h.render()?.contains("Nothing recorded in @b"));
+
+    // Not over a full-screen program, unless forced
+    h.print("\x1b[?1049h")?;
+    h.keys("<C-b>@a")?;
+    let screen = h.render()?;
+    assert!(screen.contains("Full screen: @A to replay anyway"), "{screen}");
+    assert_eq!(h.shell.input(), recorded.repeat(14));
+    h.keys("<C-b>@A")?;
+    assert_eq!(h.shell.input(), recorded.repeat(15));
+    Ok(())
+}
+
+#[test]
+fn quick_actions_run_after_the_gate() -> Result<()> {
+    let config = rusty_term::config::Config::from_toml_str(
+        r#"
+[[quick_actions]]
+name = "status"
+key = "g"
+run_shell = { command = "git status", confirm = false }
+
+[[quick_actions]]
+name = "stage all"
+key = "a"
+run_shell = { command = "git add .", confirm = false }
+
+[[quick_actions]]
+name = "log"
+key = "k"
+run_shell = { command = "git log --oneline" }
+
+[[quick_actions]]
+name = "where"
+key = "f"
+ask_ai = "What is in {cwd}?"
+"#,
+    )?;
+    let mut h = Harness::with_config(config, 120, 30)?;
+    h.print("$ ")?;
+
+    // Listed in the command mode popup
+    h.keys("<C-b>")?;
+    let screen = h.render()?;
+    assert!(screen.contains("Custom") && screen.contains("stage all"), "{screen}");
+    h.keys("<Esc>")?;
+
+    // Allowed and not confirmed: typed right away
+    h.keys("<C-b>g")?;
+    assert_eq!(h.shell.input(), b"git status\r");
+
+    // The gate asks even when the action doesn't
+    h.keys("<C-b>a")?;
+    assert!(h.render()?.contains("RUN STAGE ALL?"));
+    h.keys("n")?;
+    assert_eq!(h.shell.input(), b"git status\r");
+    h.keys("<C-b>a")?.keys("y")?;
+    assert_eq!(h.shell.input(), b"git status\rgit add .\r");
+
+    // Confirmed by default
+    h.keys("<C-b>k")?;
+    assert!(h.render()?.contains("$ git log --oneline"));
+    h.keys("<Esc>")?;
+    assert_eq!(h.shell.input(), b"git status\rgit add .\r");
+
+    h.keys("<C-b>f")?;
+    let asked = h.ai.asked();
+    let (_, question) = asked.last().ok_or_else(|| anyhow!("question was not sent"))?;
+    assert!(question.starts_with("What is in ") && !question.contains("{cwd}"), "{question}");
+    assert_eq!(h.app.get_active_pane(), ActivePane::Assistant);
+    Ok(())
+}
+
+/// A session named after its question, answered, and an empty Session 2
+/// shown.
+fn two_sessions() -> Result<Harness> {
+    let mut h = Harness::new(160, 30)?;
+    h.print("$ ")?;
+    let session_id = ask(&mut h, "list files")?;
+    stream(&mut h, AiStreamData::Chunk { session_id, text: "Use ls.".to_string() })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+    h.keys("<C-b>t")?;
+    assert!(!h.render()?.contains("AI: Use ls."));
+    Ok(h)
+}
+
+#[test]
+fn every_way_of_switching_sessions_shows_the_same() -> Result<()> {
+    let mut by_leader = two_sessions()?;
+    by_leader.keys("<C-b>[")?;
+    let expected = by_leader.render()?;
+    assert!(expected.contains("AI: Use ls."), "{expected}");
+
+    let mut by_tab = two_sessions()?;
+    by_tab.keys("<Tab>")?;
+    assert_eq!(by_tab.render()?, expected);
+
+    let mut by_switcher = two_sessions()?;
+    by_switcher.keys("<C-b>s")?.keys("li")?.keys("<Enter>")?;
+    assert_eq!(by_switcher.render()?, expected);
+
+    let mut by_mouse = two_sessions()?;
+    let screen = by_mouse.render()?;
+    // The first line of a snapshot is its size
+    let (row, line) = screen
+        .lines()
+        .skip(1)
+        .enumerate()
+        .find(|(_, line)| line.contains("list files") && line.contains("Session 2"))
+        .ok_or_else(|| anyhow!("no tab bar\n{screen}"))?;
+    let col = line.split("list files").next().map_or(0, |before| before.chars().count()) as u16;
+    by_mouse.mouse(MouseEventKind::Down(MouseButton::Left), col + 1, row as u16)?;
+    by_mouse.mouse(MouseEventKind::Up(MouseButton::Left), col + 1, row as u16)?;
+    by_mouse.mouse(MouseEventKind::Moved, 0, 0)?;
+    assert_eq!(by_mouse.render()?, expected);
+    Ok(())
+}
+
+#[test]
+fn suggestion_in_a_background_session_shows_right_away() -> Result<()> {
+    let mut h = Harness::new(160, 30)?;
+    h.print("$ ")?;
+    let session_id = ask(&mut h, "list files")?;
+    // Session 2 is shown while the first session's reply comes in
+    h.keys("<C-b>t")?;
+    stream(&mut h, AiStreamData::ToolCalls { session_id, tool_calls: vec![suggest("ls -la", "List files")] })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+    let screen = h.render()?;
+    assert!(screen.contains("pending suggestion in list files — Ctrl+B ] to view"), "{screen}");
+
+    // Closing the session it is in takes the notice with it
+    h.keys("<C-b>[")?.keys("<C-b>w")?;
+    let screen = h.render()?;
+    assert!(!screen.contains("list files") && !screen.contains("pending suggestion in"), "{screen}");
+    Ok(())
+}
+
+/// Ask `question` in the mini assistant and return its session.
+fn ask_mini(h: &mut Harness, question: &str) -> Result<SessionId> {
+    h.keys("<C-b><Space>")?.keys(question)?.keys("<Enter>")?;
+    let asked = h.ai.asked();
+    let (session_id, text) = asked.last().ok_or_else(|| anyhow!("question was not sent"))?;
+    assert_eq!(text, question);
+    Ok(*session_id)
+}
+
+#[test]
+fn mini_assistant_runs_a_suggestion_through_the_gate() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    h.print("$ ")?;
+    let session_id = ask_mini(&mut h, "list files")?;
+    stream(&mut h, AiStreamData::Chunk { session_id, text: "Use `ls`:".to_string() })?;
+    stream(&mut h, AiStreamData::ToolCalls { session_id, tool_calls: vec![suggest("ls -la", "Show every file")] })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+
+    // The popup has the reply and the card; the session has no tab
+    let screen = h.render()?;
+    assert!(screen.contains("Ask AI") && screen.contains("Use ls:") && screen.contains("> ls -la"), "{screen}");
+    assert!(!screen.contains(&format!("Session {}", session_id)), "{screen}");
+    assert_eq!(h.app.get_active_pane(), ActivePane::Terminal);
+
+    h.keys("<C-y>")?;
+    assert_eq!(h.shell.input(), b"ls -la\r");
+    assert!(h.render()?.contains("✓ Executed"));
+
+    // Esc drops the popup and its session
+    h.keys("<Esc>")?;
+    assert!(h.app.mini_assistant().is_none());
+    assert!(!h.render()?.contains("Ask AI"));
+    assert_eq!(h.app.active_session(), 1);
+    Ok(())
+}
+
+#[test]
+fn mini_assistant_promotes_to_a_tab() -> Result<()> {
+    let mut h = Harness::new(100, 30)?;
+    h.print("$ ")?;
+    let session_id = ask_mini(&mut h, "what is my shell")?;
+    stream(&mut h, AiStreamData::Chunk { session_id, text: "You are running a POSIX ".to_string() })?;
+
+    // Mid-reply it becomes a tab, shown in the assistant pane
+    h.keys("<C-t>")?;
+    assert!(h.app.mini_assistant().is_none());
+    assert_eq!(h.app.get_active_pane(), ActivePane::Assistant);
+    assert_eq!(h.app.active_session(), session_id);
+    stream(&mut h, AiStreamData::Chunk { session_id, text: "shell.".to_string() })?;
+    stream(&mut h, AiStreamData::End { session_id, cached: false })?;
+    let screen = h.render()?;
+    assert!(screen.contains(&format!("Session {}", session_id)), "{screen}");
+    assert!(screen.contains("You: what is my shell") && screen.contains("POSIX shell."), "{screen}");
+    Ok(())
+}